@@ -0,0 +1,67 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Instruction throughput on tight loops. Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mos6502::cpu::CPU;
+use mos6502::instruction::Nmos6502;
+use mos6502::memory::{Bus, Memory};
+
+/// Fills $1000-$7fff with $55 through `STA ($00),Y`, then stops on a JAM.
+const FILL: [u8; 22] = [
+    0xa9, 0x00, // LDA #$00
+    0x85, 0x00, // STA $00
+    0xa9, 0x10, // LDA #$10
+    0x85, 0x01, // STA $01
+    0xa9, 0x55, // LDA #$55
+    0xa0, 0x00, // LDY #$00
+    0x91, 0x00, // loop: STA ($00),Y
+    0xc8, // INY
+    0xd0, 0xfb, // BNE loop
+    0xe6, 0x01, // INC $01
+    0x10, 0xf7, // BPL loop
+    0x02, // JAM
+];
+
+fn memory_fill(c: &mut Criterion) {
+    let mut cpu = CPU::new(Memory::new(), Nmos6502);
+    cpu.memory.set_bytes(0x0400, &FILL);
+    // Three instructions per byte filled.
+    let mut group = c.benchmark_group("memory_fill");
+    group.throughput(Throughput::Elements(3 * 0x7000));
+    group.bench_function("nmos6502", |b| {
+        b.iter(|| {
+            cpu.registers.program_counter = 0x0400;
+            cpu.run();
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, memory_fill);
+criterion_main!(benches);
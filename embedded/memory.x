@@ -0,0 +1,6 @@
+/* The MPS2 AN386 board as QEMU models it. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 4M
+  RAM : ORIGIN = 0x20000000, LENGTH = 4M
+}
@@ -0,0 +1,98 @@
+//! Runs a short 6502 program on the emulated core and checks its results,
+//! exiting QEMU with a success status only if they are right.
+
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+
+use cortex_m_rt::entry;
+use mos6502::cpu::CPU;
+use mos6502::instruction::Nmos6502;
+use mos6502::memory::Bus;
+
+/// Address the program is loaded at.
+const START: u16 = 0x0200;
+
+#[rustfmt::skip]
+const PROGRAM: [u8; 24] = [
+    0xf8,             // SED
+    0x18,             // CLC
+    0xa9, 0x19,       // LDA #$19
+    0x69, 0x28,       // ADC #$28
+    0xd8,             // CLD
+    0x85, 0x10,       // STA $10
+    0xa2, 0x0a,       // LDX #10
+    0xa9, 0x00,       // LDA #0
+    0x18,             // loop: CLC
+    0x69, 0x05,       // ADC #5
+    0xca,             // DEX
+    0xd0, 0xfa,       // BNE loop
+    0x85, 0x11,       // STA $11
+    0x4c, 0x15, 0x02, // done: JMP done
+];
+
+/// Address of the final `JMP`, which loops on itself.
+const DONE: u16 = 0x0215;
+
+#[entry]
+fn main() -> ! {
+    let mut ram = [0; 0x1_0000];
+    let mut cpu = CPU::new(&mut ram, Nmos6502);
+    cpu.memory.set_bytes(START, &PROGRAM);
+    cpu.registers.program_counter = START;
+
+    let mut steps = 0;
+    while cpu.registers.program_counter != DONE && steps < 1000 {
+        cpu.single_step();
+        steps += 1;
+    }
+
+    check(
+        cpu.registers.program_counter == DONE,
+        "program didn't finish\n\0",
+    );
+    // 19 + 28 in BCD.
+    check(
+        cpu.memory.get_byte(0x10) == 0x47,
+        "decimal ADC is wrong\n\0",
+    );
+    check(cpu.memory.get_byte(0x11) == 50, "binary loop is wrong\n\0");
+    write("mos6502 runs on Cortex-M\n\0");
+    exit(true)
+}
+
+fn check(condition: bool, message: &str) {
+    if !condition {
+        write(message);
+        exit(false);
+    }
+}
+
+/// Semihosting `SYS_WRITE0`: writes a NUL-terminated string to the host.
+fn write(message: &str) {
+    // SAFETY: the debugger (QEMU here) handles the breakpoint and only reads
+    // the string, which every caller terminates.
+    unsafe {
+        asm!("bkpt #0xab", inout("r0") 0x04 => _, in("r1") message.as_ptr(), options(nostack));
+    }
+}
+
+/// Semihosting `SYS_EXIT`, which QEMU turns into its exit status.
+fn exit(success: bool) -> ! {
+    // ADP_Stopped_ApplicationExit or ADP_Stopped_RunTimeErrorUnknown.
+    let reason: u32 = if success { 0x2_0026 } else { 0x2_0023 };
+    loop {
+        // SAFETY: as for `write`; the host stops the program here.
+        unsafe {
+            asm!("bkpt #0xab", inout("r0") 0x18 => _, in("r1") reason, options(nostack));
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    write("panicked\n\0");
+    exit(false)
+}
@@ -0,0 +1,87 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Runs a ROM for Ben Eater's 6502 breadboard computer, with its serial
+//! port on the terminal.
+//!
+//! Assemble the ROM for `$8000-$FFFF`, as burned to the EEPROM, and run
+//!
+//! ```sh
+//! cargo run --example ben_eater -- rom.bin
+//! ```
+//!
+//! The ACIA at `$5000` reads standard input and writes standard output,
+//! and the VIA at `$6000` has nothing connected to its ports. The example
+//! exits when standard input is closed.
+
+use std::process::ExitCode;
+
+use mos6502::acia::Acia;
+use mos6502::console::{ConsoleInput, KeyEncoding};
+use mos6502::cpu::CPU;
+use mos6502::instruction::Cmos6502;
+use mos6502::memory::{Bus, Memory};
+use mos6502::via::Via;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: ben_eater <rom>");
+        return ExitCode::FAILURE;
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(start) = 0x1_0000_usize
+        .checked_sub(rom.len())
+        .and_then(|start| u16::try_from(start).ok())
+        .filter(|&start| start >= 0x8000)
+    else {
+        eprintln!("{path}: the ROM must fit in 32 KiB");
+        return ExitCode::FAILURE;
+    };
+    let mut memory = Memory::new();
+    memory.set_bytes(start, &rom);
+
+    let via = Via::new(memory, 0x6000);
+    let input = ConsoleInput::stdin(KeyEncoding::Ascii);
+    let mut cpu = CPU::new(Acia::new(via, 0x5000, input, std::io::stdout()), Cmos6502);
+    cpu.reset();
+    while !cpu.memory.is_closed() {
+        if cpu.single_step().is_none() {
+            let pc = cpu.registers.program_counter;
+            eprintln!("\nstopped: illegal opcode at ${pc:04X}");
+            return ExitCode::FAILURE;
+        }
+        let irq = cpu.memory.irq_pending() || cpu.memory.inner().irq_pending();
+        cpu.set_irq(irq);
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,77 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Boots Enhanced BASIC on the terminal.
+//!
+//! Build the EhBASIC ROM (`basic.asm` with `min_mon.asm`) and run
+//!
+//! ```sh
+//! cargo run --example ehbasic -- ehbasic.bin
+//! ```
+//!
+//! then answer the cold/warm start prompt with `C` and type away. The
+//! example exits when standard input is closed.
+
+use std::process::ExitCode;
+
+use mos6502::console::{ConsoleInput, KeyEncoding};
+use mos6502::ehbasic;
+use mos6502::machine::StopReason;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: ehbasic <rom>");
+        return ExitCode::FAILURE;
+    };
+    let rom = match std::fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let input = ConsoleInput::stdin(KeyEncoding::Ascii);
+    let mut machine = match ehbasic::machine(&rom, input, std::io::stdout()) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    while !machine.cpu.memory.is_closed() {
+        let report = machine.run(Some(100_000));
+        match report.stop {
+            StopReason::LimitReached { .. } => {}
+            _ => {
+                eprintln!("\nstopped: {report}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -83,7 +83,7 @@ fn main() {
         0x71, // ADC IndirectIndexedY
         0x0F, //     IndirectIndexedY operand
         0xEA, // NOP :)
-        0xFF, // Something invalid -- the end!
+        0x02, // Jams the CPU -- the end!
     ];
 
     let data = [
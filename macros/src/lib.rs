@@ -0,0 +1,185 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Compile-time assembly for tests of 6502 programs.
+//!
+//! [`asm6502!`] runs the [`mos6502::asm`] assembler while the crate using
+//! it is compiled, so a test program can live next to the test that runs
+//! it and a typo in it fails the build rather than the test.
+
+#![warn(clippy::all, clippy::pedantic)]
+#![warn(
+    absolute_paths_not_starting_with_crate,
+    missing_debug_implementations,
+    unreachable_pub,
+    unused_extern_crates
+)]
+
+use std::fmt::Write as _;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Assembles 6502 source into a module of constants.
+///
+/// The macro takes the name of the module, optionally preceded by its
+/// visibility, and the source as a string literal:
+///
+/// ```
+/// mos6502_macros::asm6502!(pub countdown, r"
+///         .org $0200
+///         LDX #8
+/// loop:   DEX
+///         BNE loop
+///         BRK
+/// ");
+///
+/// assert_eq!(countdown::ORIGIN, 0x0200);
+/// assert_eq!(countdown::BYTES, [0xa2, 0x08, 0xca, 0xd0, 0xfd, 0x00]);
+/// assert_eq!(countdown::r#loop, 0x0202);
+/// ```
+///
+/// `ORIGIN` is the address of the first byte of `BYTES`, and every symbol
+/// the source defines becomes a `u16` constant of the same name. Names
+/// that are Rust keywords are written as raw identifiers, such as `r#loop`.
+///
+/// The source is assembled for the NMOS 6502 in the generic syntax described
+/// in [`mos6502::asm`]; errors are reported as compile errors.
+#[proc_macro]
+// Only a bug in `expand` can make the expansion fail to parse.
+#[allow(clippy::missing_panics_doc)]
+pub fn asm6502(input: TokenStream) -> TokenStream {
+    let output = match expand(input) {
+        Ok(output) => output,
+        Err(message) => format!("::core::compile_error!({message:?});"),
+    };
+    output.parse().expect("the expansion is valid Rust")
+}
+
+fn expand(input: TokenStream) -> Result<String, String> {
+    let mut head = Vec::new();
+    let mut tokens = input.into_iter();
+    for token in tokens.by_ref() {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => break,
+            _ => head.push(token),
+        }
+    }
+    let Some(TokenTree::Ident(name)) = head.pop() else {
+        return Err("expected `name, \"source\"`".to_owned());
+    };
+    let source = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => string(&literal.to_string())?,
+        // Literals passed through `macro_rules!` arrive in a group.
+        (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::None => {
+            string(&group.stream().to_string())?
+        }
+        _ => return Err("expected the source as a string literal".to_owned()),
+    };
+
+    let assembly = mos6502::asm::assemble(&source)
+        .map_err(|err| format!("line {}: {}", err.line, err.message))?;
+
+    let visibility: Vec<String> = head.iter().map(ToString::to_string).collect();
+    let mut output = String::new();
+    writeln!(
+        output,
+        "#[allow(non_upper_case_globals, dead_code)] {} mod {name} {{",
+        visibility.join(" ")
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "    /// Address of the first byte of `BYTES`.\n    pub const ORIGIN: u16 = {};",
+        assembly.origin
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "    pub const BYTES: &[u8] = &{:?};",
+        assembly.bytes
+    )
+    .unwrap();
+    for (symbol, address) in assembly.symbols.iter() {
+        if matches!(symbol, "crate" | "self" | "super" | "Self" | "_") {
+            return Err(format!("`{symbol}` can't be the name of a constant"));
+        }
+        writeln!(output, "    pub const r#{symbol}: u16 = {address};").unwrap();
+    }
+    output.push('}');
+    Ok(output)
+}
+
+/// The value of a string literal, as written in the source.
+fn string(literal: &str) -> Result<String, String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return raw[hashes..raw.len() - hashes]
+            .strip_prefix('"')
+            .and_then(|text| text.strip_suffix('"'))
+            .map(str::to_owned)
+            .ok_or_else(|| "expected a string literal".to_owned());
+    }
+    let text = literal
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or("expected a string literal")?;
+
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some('0') => value.push('\0'),
+            Some(c @ ('\\' | '"' | '\'')) => value.push(c),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16).map_err(|_| "malformed escape")?;
+                value.push(char::from(byte));
+            }
+            Some('u') => {
+                let hex: String = chars.by_ref().take_while(|c| *c != '}').skip(1).collect();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or("malformed escape")?;
+                value.push(c);
+            }
+            // A backslash at the end of a line joins it to the next.
+            Some('\n') => {
+                chars = chars.as_str().trim_start().chars();
+            }
+            _ => return Err("malformed escape".to_owned()),
+        }
+    }
+    Ok(value)
+}
@@ -0,0 +1,79 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+use mos6502::cpu::CPU;
+use mos6502::instruction::Nmos6502;
+use mos6502::memory::{Bus, Memory};
+use mos6502_macros::asm6502;
+
+asm6502!(
+    sum,
+    "
+    count = 5
+            .org $0300
+    start:  LDA #0
+            LDX #count
+    add:    CLC
+            ADC table-1,X
+            DEX
+            BNE add
+            STA result
+            BRK
+    table:  .byte 1, 2, 3, 4, 5
+    result: .byte 0
+    "
+);
+
+#[test]
+fn exports_the_bytes_and_symbols() {
+    assert_eq!(sum::ORIGIN, 0x0300);
+    assert_eq!(sum::start, 0x0300);
+    assert_eq!(sum::add, 0x0304);
+    assert_eq!(sum::count, 5);
+    assert_eq!(usize::from(sum::result - sum::ORIGIN), sum::BYTES.len() - 1);
+}
+
+#[test]
+fn runs_on_the_emulator() {
+    let mut cpu = CPU::new(Memory::new(), Nmos6502);
+    cpu.memory.set_bytes(sum::ORIGIN, sum::BYTES);
+    cpu.registers.program_counter = sum::start;
+    while cpu.memory.get_byte(cpu.registers.program_counter) != 0x00 {
+        cpu.single_step();
+    }
+    assert_eq!(cpu.memory.get_byte(sum::result), 15);
+}
+
+mod escapes {
+    mos6502_macros::asm6502!(pub(crate) text, "\t.org $10\n\t.byte \"\x41\", '\u{42}'\n");
+}
+
+#[test]
+fn reads_escaped_literals() {
+    assert_eq!(escapes::text::ORIGIN, 0x10);
+    assert_eq!(escapes::text::BYTES, b"AB");
+}
@@ -0,0 +1,412 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A 6551 Asynchronous Communications Interface Adapter.
+//!
+//! [`Acia`] maps the four registers of a 6551 (or W65C51) over another bus
+//! and connects its serial lines to a host reader and writer:
+//!
+//! | Offset | Register  | Read                         | Write                       |
+//! |--------|-----------|------------------------------|-----------------------------|
+//! | 0      | `DATA`    | the received byte            | sends a byte                |
+//! | 1      | `STATUS`  | status, clears the IRQ       | programmed reset            |
+//! | 2      | `COMMAND` | parity, echo, TX/RX control  | the same                    |
+//! | 3      | `CONTROL` | baud rate, word length, stop | the same                    |
+//!
+//! Characters move at the rate set in `CONTROL`, paced by [`Bus::phi2`]
+//! against the CPU clock given to [`Acia::set_clock`], 1 MHz by default.
+//! Baud rate 0, the external clock, moves them as fast as the program
+//! takes them. A byte written to `DATA` goes to the writer at once, and
+//! `STATUS` shows the transmitter busy for a character time. The reader
+//! is only polled while the receive register is empty, so input is never
+//! lost to an overrun; a reader that would block should return
+//! [`ErrorKind::WouldBlock`], as a [`ConsoleInput`] does, and end of file
+//! stops the receiver.
+//!
+//! With bit 0 of `COMMAND` set, received bytes and, in transmitter mode
+//! `01`, an empty transmit register assert IRQ until `STATUS` is read.
+//! Wired with a [`Via`](crate::via::Via), this is the serial setup of Ben
+//! Eater's breadboard computer: RAM from `$0000`, the VIA at `$6000`, the
+//! ACIA at `$5000` and ROM from `$8000`.
+//!
+//! ```
+//! use mos6502::acia::{Acia, COMMAND, DATA, RDRF, STATUS};
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::via::Via;
+//!
+//! let bus = Via::new(Memory::new(), 0x6000);
+//! let mut output = Vec::new();
+//! let mut acia = Acia::new(bus, 0x5000, &b"hi"[..], &mut output);
+//! acia.set_byte(0x5000 + COMMAND, 0x0b);
+//! for cycle in 0..10 {
+//!     acia.phi2(cycle);
+//! }
+//! assert_eq!(acia.get_byte(0x5000 + STATUS) & RDRF, RDRF);
+//! let byte = acia.get_byte(0x5000 + DATA);
+//! acia.set_byte(0x5000 + DATA, byte.to_ascii_uppercase());
+//! drop(acia);
+//! assert_eq!(output, b"H");
+//! ```
+//!
+//! [`ErrorKind::WouldBlock`]: std::io::ErrorKind::WouldBlock
+//! [`ConsoleInput`]: crate::console::ConsoleInput
+
+use core::cell::Cell;
+use core::ops::Range;
+use std::io::{ErrorKind, Read, Write};
+
+use crate::interrupt::IrqSource;
+use crate::memory::{Bus, BusError};
+
+pub const DATA: u16 = 0;
+pub const STATUS: u16 = 1;
+pub const COMMAND: u16 = 2;
+pub const CONTROL: u16 = 3;
+
+/// Number of addresses the registers take up.
+pub const SIZE: u16 = 4;
+
+/// The IRQ bit of [`STATUS`].
+pub const IRQ: u8 = 0x80;
+/// Transmit data register empty.
+pub const TDRE: u8 = 0x10;
+/// Receive data register full.
+pub const RDRF: u8 = 0x08;
+
+/// Baud rates of the low four bits of [`CONTROL`], in hundredths. Zero is
+/// the external clock, which isn't paced.
+const BAUD_RATES: [u32; 16] = [
+    0, 5_000, 7_500, 10_992, 13_458, 15_000, 30_000, 60_000, 120_000, 180_000, 240_000, 360_000,
+    480_000, 720_000, 960_000, 1_920_000,
+];
+
+/// A bus with the registers of a 6551 ACIA at `base` to `base + 3`,
+/// receiving from `R` and sending to `W`.
+#[derive(Debug)]
+pub struct Acia<B: Bus, R: Read, W: Write> {
+    inner: B,
+    base: u16,
+    input: R,
+    output: W,
+    /// The CPU clock in Hz.
+    clock: u32,
+    command: u8,
+    control: u8,
+    data: u8,
+    /// Whether a received byte waits in `DATA`.
+    received: Cell<bool>,
+    irq: Cell<bool>,
+    /// Cycles until the transmitter is ready for another byte.
+    sending: u64,
+    /// Cycles until the receiver polls the input again.
+    receiving: u64,
+    /// Whether the input has ended.
+    closed: bool,
+}
+
+impl<B: Bus, R: Read, W: Write> Acia<B, R, W> {
+    /// Maps the ACIA registers at `base` over `inner`, in the state a
+    /// hardware reset leaves them.
+    pub const fn new(inner: B, base: u16, input: R, output: W) -> Self {
+        Acia {
+            inner,
+            base,
+            input,
+            output,
+            clock: 1_000_000,
+            command: 0x02,
+            control: 0,
+            data: 0,
+            received: Cell::new(false),
+            irq: Cell::new(false),
+            sending: 0,
+            receiving: 0,
+            closed: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    /// Sets the CPU clock in Hz that characters are paced against.
+    pub const fn set_clock(&mut self, hz: u32) {
+        self.clock = hz;
+    }
+
+    /// Returns `true` while the ACIA asserts IRQ.
+    #[must_use]
+    pub const fn irq_pending(&self) -> bool {
+        self.irq.get()
+    }
+
+    /// Returns `true` once the input has ended and its last byte has been
+    /// read.
+    #[must_use]
+    pub const fn is_closed(&self) -> bool {
+        self.closed && !self.received.get()
+    }
+
+    /// The host reader.
+    #[must_use]
+    pub const fn input(&self) -> &R {
+        &self.input
+    }
+
+    /// The host writer, with what the program has sent so far if it was
+    /// collected in memory.
+    #[must_use]
+    pub const fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the ACIA, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset < SIZE).then_some(offset)
+    }
+
+    /// Whether DTR is asserted, which enables the receiver and interrupts.
+    const fn ready(&self) -> bool {
+        self.command & 0x01 != 0
+    }
+
+    const fn transmitter(&self) -> u8 {
+        (self.command >> 2) & 0x03
+    }
+
+    /// CPU cycles a character takes on the line: a start bit, the data
+    /// bits, parity and the stop bits.
+    fn character_time(&self) -> u64 {
+        let baud = BAUD_RATES[usize::from(self.control & 0x0f)];
+        if baud == 0 {
+            return 0;
+        }
+        let data = 8 - u64::from((self.control >> 5) & 0x03);
+        let parity = u64::from((self.command >> 5) & 0x01);
+        let stop = if self.control & 0x80 == 0 { 1 } else { 2 };
+        (1 + data + parity + stop) * u64::from(self.clock) * 100 / u64::from(baud)
+    }
+
+    fn send(&mut self, byte: u8) {
+        // Errors on the host side are the host's business; the line
+        // doesn't report them.
+        let _ = self
+            .output
+            .write_all(&[byte])
+            .and_then(|()| self.output.flush());
+        self.sending = self.character_time();
+    }
+
+    fn receive(&mut self) {
+        let mut byte = [0];
+        match self.input.read(&mut byte) {
+            Ok(0) => self.closed = true,
+            Ok(_) => {
+                self.data = byte[0];
+                self.received.set(true);
+                if self.command & 0x02 == 0 {
+                    self.irq.set(true);
+                }
+                // Echo mode sends what comes in straight back.
+                if self.command & 0x10 != 0 && self.transmitter() == 0 {
+                    self.send(byte[0]);
+                }
+                self.receiving = self.character_time();
+            }
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                self.receiving = self.character_time();
+            }
+            Err(_) => self.closed = true,
+        }
+    }
+}
+
+impl<B: Bus, R: Read, W: Write> IrqSource for Acia<B, R, W> {
+    fn irq_pending(&self) -> bool {
+        Acia::irq_pending(self)
+    }
+}
+
+impl<B: Bus, R: Read, W: Write> Bus for Acia<B, R, W> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.register(address) {
+            Some(DATA) => {
+                self.received.set(false);
+                self.data
+            }
+            Some(STATUS) => {
+                let mut status = 0;
+                if self.irq.replace(false) {
+                    status |= IRQ;
+                }
+                if self.sending == 0 {
+                    status |= TDRE;
+                }
+                if self.received.get() {
+                    status |= RDRF;
+                }
+                status
+            }
+            Some(COMMAND) => self.command,
+            Some(_) => self.control,
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            // Transmitter mode 00 holds the transmitter off.
+            Some(DATA) if self.transmitter() != 0 => self.send(value),
+            Some(DATA) => {}
+            Some(STATUS) => self.command &= 0xe0,
+            Some(COMMAND) => self.command = value,
+            Some(_) => self.control = value,
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+        if self.sending > 0 {
+            self.sending -= 1;
+            if self.sending == 0 && self.ready() && self.transmitter() == 1 {
+                self.irq.set(true);
+            }
+        }
+        self.receiving = self.receiving.saturating_sub(1);
+        if self.receiving == 0 && self.ready() && !self.received.get() && !self.closed {
+            self.receive();
+        }
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    /// Puts the registers back in their power-on state, which disables the
+    /// receiver and interrupts. A byte waiting in `DATA` is dropped.
+    fn reset(&mut self) {
+        self.command = 0x02;
+        self.control = 0;
+        self.received.set(false);
+        self.irq.set(false);
+        self.sending = 0;
+        self.receiving = 0;
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::memory::Memory;
+
+    fn run<B: Bus>(bus: &mut B, cycles: u64) {
+        for cycle in 0..cycles {
+            bus.phi2(cycle);
+        }
+    }
+
+    #[test]
+    fn receiver_waits_for_dtr_and_paces_input() {
+        let mut acia = Acia::new(Memory::new(), 0x5000, &b"ab"[..], Vec::new());
+        run(&mut acia, 10);
+        assert_eq!(acia.get_byte(0x5000 + STATUS), TDRE);
+        // 8N1 at 9600 baud, 1041 cycles a character.
+        acia.set_byte(0x5000 + CONTROL, 0x1e);
+        acia.set_byte(0x5000 + COMMAND, 0x09);
+        run(&mut acia, 1);
+        assert_eq!(acia.get_byte(0x5000 + STATUS), IRQ | TDRE | RDRF);
+        assert_eq!(acia.get_byte(0x5000 + DATA), b'a');
+        run(&mut acia, 1040);
+        assert_eq!(acia.get_byte(0x5000 + STATUS), TDRE);
+        run(&mut acia, 1);
+        assert_eq!(acia.get_byte(0x5000 + DATA), b'b');
+        run(&mut acia, 2000);
+        assert!(acia.is_closed());
+    }
+
+    #[test]
+    fn transmitter_is_busy_for_a_character_time() {
+        let mut acia = Acia::new(Memory::new(), 0x5000, io::empty(), Vec::new());
+        acia.set_byte(0x5000 + CONTROL, 0x1f);
+        // Transmitter off: nothing is sent.
+        acia.set_byte(0x5000 + DATA, b'x');
+        // Transmit interrupts on, receiver interrupts off.
+        acia.set_byte(0x5000 + COMMAND, 0x07);
+        acia.set_byte(0x5000 + DATA, b'o');
+        acia.set_byte(0x5000 + DATA, b'k');
+        assert_eq!(acia.output(), b"ok");
+        assert_eq!(acia.get_byte(0x5000 + STATUS), 0);
+        run(&mut acia, 520);
+        assert!(acia.irq_pending());
+        assert_eq!(acia.get_byte(0x5000 + STATUS), IRQ | TDRE);
+        assert!(!acia.irq_pending());
+    }
+
+    #[test]
+    fn programmed_reset_clears_the_low_command_bits() {
+        let mut acia = Acia::new(Memory::new(), 0x5000, io::empty(), Vec::new());
+        assert_eq!(acia.get_byte(0x5000 + COMMAND), 0x02);
+        acia.set_byte(0x5000 + COMMAND, 0xff);
+        acia.set_byte(0x5000 + CONTROL, 0x1f);
+        acia.set_byte(0x5000 + STATUS, 0);
+        assert_eq!(acia.get_byte(0x5000 + COMMAND), 0xe0);
+        assert_eq!(acia.get_byte(0x5000 + CONTROL), 0x1f);
+        acia.reset();
+        assert_eq!(acia.get_byte(0x5000 + COMMAND), 0x02);
+        assert_eq!(acia.get_byte(0x5000 + CONTROL), 0);
+    }
+}
@@ -0,0 +1,635 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! The arithmetic and logic unit, as pure functions.
+//!
+//! Each function takes its operands and the incoming carry, and returns the
+//! result with the flags it sets. The instruction handlers apply those
+//! flags through the matching mask, [`NVZC`] or [`NZC`]; everything else in
+//! the status register is left alone. Keeping the flag logic here lets it
+//! be tested exhaustively without building a CPU: [`verify`] runs every
+//! input of [`adc`] and [`sbc`] against reference models of the NMOS chip
+//! and reports the combinations that disagree.
+//!
+//! # Examples
+//!
+//! ```
+//! use mos6502::alu;
+//! use mos6502::registers::Status;
+//!
+//! // 0x50 + 0x50 overflows into the sign bit.
+//! let (result, flags) = alu::adc(0x50, 0x50, false, false);
+//! assert_eq!(result, 0xa0);
+//! assert_eq!(flags, Status::PS_NEGATIVE | Status::PS_OVERFLOW);
+//!
+//! // In decimal mode, 0x19 + 0x01 is 20.
+//! assert_eq!(alu::adc(0x19, 0x01, false, true).0, 0x20);
+//! ```
+
+use core::fmt;
+
+use crate::registers::Status;
+
+/// The flags set by [`adc`] and [`sbc`].
+pub const NVZC: Status = Status::PS_NEGATIVE
+    .union(Status::PS_OVERFLOW)
+    .union(Status::PS_ZERO)
+    .union(Status::PS_CARRY);
+
+/// The flags set by [`compare`] and the shifts and rotates.
+pub const NZC: Status = Status::PS_NEGATIVE
+    .union(Status::PS_ZERO)
+    .union(Status::PS_CARRY);
+
+/// The negative and zero flags for `value`, plus carry and overflow as
+/// given.
+const fn flags(value: u8, overflow: bool, carry: bool) -> Status {
+    let mut bits = value & Status::PS_NEGATIVE.bits();
+    if value == 0 {
+        bits |= Status::PS_ZERO.bits();
+    }
+    if overflow {
+        bits |= Status::PS_OVERFLOW.bits();
+    }
+    if carry {
+        bits |= Status::PS_CARRY.bits();
+    }
+    Status::from_bits_truncate(bits)
+}
+
+/// The binary adder every arithmetic instruction is built on: `a + b +
+/// carry` wrapped to a byte, with the carry out and whether the sum
+/// overflowed as two's complement. Subtracting adds the complement of the
+/// subtrahend, as the chip does, so the carry out is "no borrow".
+const fn add(a: u8, b: u8, carry: bool) -> (u8, bool, bool) {
+    let (sum, carried) = a.overflowing_add(b);
+    let (sum, carried_in) = sum.overflowing_add(carry as u8);
+    let overflow = (a ^ sum) & (b ^ sum) & 0x80 != 0;
+    (sum, carried || carried_in, overflow)
+}
+
+/// `a + b + carry`, as `ADC` on the NMOS 6502, with BCD correction if
+/// `decimal` is set.
+///
+/// In decimal mode the flags are the NMOS chip's: Z comes from the binary
+/// sum, N and V from the sum after only the low digit is corrected, and C
+/// from the corrected result. Operands that aren't valid BCD give the same
+/// results as the real chip.
+#[must_use]
+// The high byte of `sum` holds the carry out.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn adc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (binary, carry_out, overflow) = add(a, b, carry);
+    if !decimal {
+        return (binary, flags(binary, overflow, carry_out));
+    }
+
+    let (a16, b16, c) = (a as u16, b as u16, carry as u16);
+
+    let mut sum = (a16 & 0x0f) + (b16 & 0x0f) + c;
+    if sum > 0x09 {
+        sum += 0x06;
+    }
+    sum = if sum > 0x0f { 0x10 } else { 0x00 } + (sum & 0x0f) + (a16 & 0xf0) + (b16 & 0xf0);
+
+    let mut bits = (sum as u8) & Status::PS_NEGATIVE.bits();
+    if binary == 0 {
+        bits |= Status::PS_ZERO.bits();
+    }
+    if (a16 ^ sum) & !(a16 ^ b16) & 0x80 != 0 {
+        bits |= Status::PS_OVERFLOW.bits();
+    }
+    if sum & 0x1f0 > 0x90 {
+        sum += 0x60;
+    }
+    if sum > 0xff {
+        bits |= Status::PS_CARRY.bits();
+    }
+    (sum as u8, Status::from_bits_truncate(bits))
+}
+
+/// `a - b - (1 - carry)`, as `SBC` on the NMOS 6502, with BCD correction
+/// if `decimal` is set. The carry out is set unless the subtraction
+/// borrowed.
+///
+/// In decimal mode all four flags come from the binary subtraction, as on
+/// the NMOS chip; only the result is corrected.
+#[must_use]
+// Borrows show up in the high byte of the wrapping `u16` differences.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn sbc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (difference, no_borrow, overflow) = add(a, !b, carry);
+    let status = flags(difference, overflow, no_borrow);
+    if !decimal {
+        return (difference, status);
+    }
+
+    // nc -- 'not carry'
+    let nc = !carry as u8;
+    let (a16, b16) = (a as u16, b as u16);
+    let low = (a16 & 0x0f)
+        .wrapping_sub(b16 & 0x0f)
+        .wrapping_sub(nc as u16);
+    let high = (a16 & 0xf0).wrapping_sub(b16 & 0xf0);
+    let mut result = if low & 0x10 != 0 {
+        (low.wrapping_sub(0x06) & 0x0f) | high.wrapping_sub(0x10)
+    } else {
+        (low & 0x0f) | high
+    };
+    if result & 0x100 != 0 {
+        result = result.wrapping_sub(0x60);
+    }
+    (result as u8, status)
+}
+
+/// `ADC` as the 65C02 computes it. The result, carry and overflow are those
+/// of [`adc`]; in decimal mode N and Z follow the corrected result.
+#[must_use]
+pub const fn adc_cmos(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (result, nmos) = adc(a, b, carry, decimal);
+    (
+        result,
+        flags(
+            result,
+            nmos.contains(Status::PS_OVERFLOW),
+            nmos.contains(Status::PS_CARRY),
+        ),
+    )
+}
+
+/// `SBC` as the 65C02 computes it. In decimal mode the digits are
+/// corrected after a full binary subtraction, which differs from [`sbc`]
+/// for invalid BCD, and N and Z follow the corrected result.
+#[must_use]
+pub const fn sbc_cmos(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (difference, nmos) = sbc(a, b, carry, false);
+    if !decimal {
+        return (difference, nmos);
+    }
+
+    let mut result = difference;
+    if !nmos.contains(Status::PS_CARRY) {
+        result = result.wrapping_sub(0x60);
+    }
+    if (a & 0x0f) < (b & 0x0f) + !carry as u8 {
+        result = result.wrapping_sub(0x06);
+    }
+    (
+        result,
+        flags(
+            result,
+            nmos.contains(Status::PS_OVERFLOW),
+            nmos.contains(Status::PS_CARRY),
+        ),
+    )
+}
+
+/// The flags `CMP`, `CPX` and `CPY` set for `register - value`.
+///
+/// From <http://www.6502.org/tutorials/compare_beyond.html>: Z is set if
+/// the two are equal, C if `register >= value` unsigned, and N holds bit 7
+/// of the difference. It is a binary [`sbc`] with the carry set that
+/// leaves V alone.
+#[must_use]
+pub const fn compare(register: u8, value: u8) -> Status {
+    let (difference, no_borrow, _) = add(register, !value, true);
+    flags(difference, false, no_borrow)
+}
+
+/// `value` shifted left, as `ASL`, with bit 7 going to the carry.
+#[must_use]
+pub const fn asl(value: u8) -> (u8, Status) {
+    let result = value << 1;
+    (result, flags(result, false, value & 0x80 != 0))
+}
+
+/// `value` shifted right, as `LSR`, with bit 0 going to the carry.
+#[must_use]
+pub const fn lsr(value: u8) -> (u8, Status) {
+    let result = value >> 1;
+    (result, flags(result, false, value & 0x01 != 0))
+}
+
+/// `value` rotated left through the carry, as `ROL`.
+#[must_use]
+pub const fn rol(value: u8, carry: bool) -> (u8, Status) {
+    let result = (value << 1) | carry as u8;
+    (result, flags(result, false, value & 0x80 != 0))
+}
+
+/// `value` rotated right through the carry, as `ROR`.
+#[must_use]
+pub const fn ror(value: u8, carry: bool) -> (u8, Status) {
+    let result = (value >> 1) | ((carry as u8) << 7);
+    (result, flags(result, false, value & 0x01 != 0))
+}
+
+/// `a & value` rotated right through the carry, as the undocumented `ARR`.
+///
+/// In binary mode C is bit 6 of the result and V is bit 6 xor bit 5. In
+/// decimal mode N is the incoming carry, Z and V come from the rotated
+/// value, and each digit of it is corrected as `ADC` would correct the
+/// digits of the `AND`, the high one setting the carry.
+#[must_use]
+pub const fn arr(a: u8, value: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let and = a & value;
+    let rotated = (and >> 1) | ((carry as u8) << 7);
+    if !decimal {
+        let overflow = (rotated ^ (rotated << 1)) & 0x40 != 0;
+        return (rotated, flags(rotated, overflow, rotated & 0x40 != 0));
+    }
+    let mut result = rotated;
+    if (and & 0x0f) + (and & 0x01) > 0x05 {
+        result = (result & 0xf0) | (result.wrapping_add(0x06) & 0x0f);
+    }
+    let high = (and >> 4) + ((and >> 4) & 0x01) > 0x05;
+    if high {
+        result = result.wrapping_add(0x60);
+    }
+    (result, flags(rotated, (rotated ^ and) & 0x40 != 0, high))
+}
+
+/// `ADC` as the NMOS 6502 computes it, following the algorithm in Bruce
+/// Clark's "Decimal Mode" tutorial, for checking [`adc`] against.
+///
+/// This models decimal mode down to the flags: Z comes from the binary sum,
+/// and N and V from the sum after correcting the low digit only. Invalid
+/// BCD operands give the same results as the real chip.
+#[must_use]
+// The algorithm is specified in signed arithmetic; results are the low
+// eight bits.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+pub const fn reference_adc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let c = carry as i16;
+    let binary = a as i16 + b as i16 + c;
+    if !decimal {
+        let result = binary as u8;
+        let overflow = (a ^ result) & (b ^ result) & 0x80 != 0;
+        return (result, flags(result, overflow, binary > 0xff));
+    }
+
+    let mut low = (a & 0x0f) as i16 + (b & 0x0f) as i16 + c;
+    if low >= 0x0a {
+        low = ((low + 0x06) & 0x0f) + 0x10;
+    }
+    let mut sum = (a & 0xf0) as i16 + (b & 0xf0) as i16 + low;
+    // N and V see the sum before the high digit is corrected, with the high
+    // digits taken as signed.
+    let signed = (a & 0xf0) as i8 as i16 + (b & 0xf0) as i8 as i16 + low;
+    let mut bits = (sum as u8) & Status::PS_NEGATIVE.bits();
+    if sum >= 0xa0 {
+        sum += 0x60;
+    }
+
+    if binary as u8 == 0 {
+        bits |= Status::PS_ZERO.bits();
+    }
+    if signed < -128 || signed > 127 {
+        bits |= Status::PS_OVERFLOW.bits();
+    }
+    if sum > 0xff {
+        bits |= Status::PS_CARRY.bits();
+    }
+    (sum as u8, Status::from_bits_truncate(bits))
+}
+
+/// `SBC` as the NMOS 6502 computes it, following the algorithm in Bruce
+/// Clark's "Decimal Mode" tutorial, for checking [`sbc`] against.
+///
+/// In decimal mode, all four flags come from the binary subtraction.
+#[must_use]
+// The algorithm is specified in signed arithmetic; results are the low
+// eight bits.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+pub const fn reference_sbc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let borrow = !carry as i16;
+    let (a, b) = (a as i16, b as i16);
+    let binary = a - b - borrow;
+    let overflow = (a ^ binary) & (a ^ b) & 0x80 != 0;
+    let binary_flags = flags((binary & 0xff) as u8, overflow, binary >= 0);
+    if !decimal {
+        return ((binary & 0xff) as u8, binary_flags);
+    }
+
+    let mut low = (a & 0x0f) - (b & 0x0f) - borrow;
+    if low < 0 {
+        low = ((low - 0x06) & 0x0f) - 0x10;
+    }
+    let mut difference = (a & 0xf0) - (b & 0xf0) + low;
+    if difference < 0 {
+        difference -= 0x60;
+    }
+    ((difference & 0xff) as u8, binary_flags)
+}
+
+/// `ADC` as the 65C02 computes it. The result, carry and overflow are those
+/// of the NMOS chip; N and Z are fixed to follow the result.
+#[must_use]
+pub const fn reference_adc_cmos(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (result, nmos) = reference_adc(a, b, carry, decimal);
+    let overflow = nmos.contains(Status::PS_OVERFLOW);
+    (
+        result,
+        flags(result, overflow, nmos.contains(Status::PS_CARRY)),
+    )
+}
+
+/// `SBC` as the 65C02 computes it: in decimal mode the digits are corrected
+/// after a binary subtraction, which differs from the NMOS chip for invalid
+/// BCD, and N and Z follow the result.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub const fn reference_sbc_cmos(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, Status) {
+    let (binary, nmos) = reference_sbc(a, b, carry, false);
+    if !decimal {
+        return (binary, nmos);
+    }
+
+    let borrow = !carry as i16;
+    let (a, b) = (a as i16, b as i16);
+    let low = (a & 0x0f) - (b & 0x0f) - borrow;
+    let mut difference = a - b - borrow;
+    if difference < 0 {
+        difference -= 0x60;
+    }
+    if low < 0 {
+        difference -= 0x06;
+    }
+    let result = (difference & 0xff) as u8;
+    let overflow = nmos.contains(Status::PS_OVERFLOW);
+    (
+        result,
+        flags(result, overflow, nmos.contains(Status::PS_CARRY)),
+    )
+}
+
+/// An input on which [`adc`] or [`sbc`] disagrees with its reference.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// `true` for `SBC`, `false` for `ADC`.
+    pub subtract: bool,
+    pub a: u8,
+    pub b: u8,
+    pub carry: bool,
+    pub decimal: bool,
+    /// The result and flags from [`adc`] or [`sbc`].
+    pub actual: (u8, Status),
+    /// The result and flags from [`reference_adc`] or [`reference_sbc`].
+    pub expected: (u8, Status),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ${:02X}, ${:02X} with C={} D={}: got ${:02X} {:?}, expected ${:02X} {:?}",
+            if self.subtract { "SBC" } else { "ADC" },
+            self.a,
+            self.b,
+            u8::from(self.carry),
+            u8::from(self.decimal),
+            self.actual.0,
+            self.actual.1,
+            self.expected.0,
+            self.expected.1,
+        )
+    }
+}
+
+/// Runs all 256 x 256 x 2 x 2 inputs of [`adc`] and [`sbc`] against
+/// [`reference_adc`] and [`reference_sbc`], and returns those where the
+/// results or any of the flags in `checked` differ.
+///
+/// In decimal mode the flags checked are narrowed to `decimal_flags`, for
+/// callers that only care about the parts the 6502 documentation defines.
+/// Operands that aren't valid BCD are checked too.
+pub fn verify(checked: Status, decimal_flags: Status) -> impl Iterator<Item = Mismatch> {
+    (0..0x8_0000_u32).filter_map(move |index| {
+        let [a, b, mode, _] = index.to_le_bytes();
+        let carry = mode & 1 != 0;
+        let decimal = mode & 2 != 0;
+        let subtract = mode & 4 != 0;
+        let (actual, expected) = if subtract {
+            (
+                sbc(a, b, carry, decimal),
+                reference_sbc(a, b, carry, decimal),
+            )
+        } else {
+            (
+                adc(a, b, carry, decimal),
+                reference_adc(a, b, carry, decimal),
+            )
+        };
+        let mask = if decimal { decimal_flags } else { checked };
+        (actual.0 != expected.0 || actual.1 & mask != expected.1 & mask).then_some(Mismatch {
+            subtract,
+            a,
+            b,
+            carry,
+            decimal,
+            actual,
+            expected,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adc_sets_carry_and_overflow() {
+        assert_eq!(
+            adc(0xff, 0x01, false, false),
+            (0x00, Status::PS_ZERO | Status::PS_CARRY)
+        );
+        assert_eq!(
+            adc(0x7f, 0x00, true, false),
+            (0x80, Status::PS_NEGATIVE | Status::PS_OVERFLOW)
+        );
+        assert_eq!(
+            adc(0x80, 0xff, false, false),
+            (0x7f, Status::PS_OVERFLOW | Status::PS_CARRY)
+        );
+    }
+
+    #[test]
+    fn sbc_borrows_when_carry_is_clear() {
+        let without_carry = |(result, flags): (u8, Status)| (result, flags - Status::PS_CARRY);
+        assert_eq!(
+            without_carry(sbc(0x05, 0x03, true, false)),
+            (0x02, Status::empty())
+        );
+        assert_eq!(
+            without_carry(sbc(0x05, 0x05, false, false)),
+            (0xff, Status::PS_NEGATIVE)
+        );
+        assert_eq!(
+            without_carry(sbc(0x80, 0x01, true, false)),
+            (0x7f, Status::PS_OVERFLOW)
+        );
+    }
+
+    #[test]
+    fn sbc_carries_unless_it_borrows() {
+        assert_eq!(sbc(0x05, 0x03, true, false), (0x02, Status::PS_CARRY));
+        assert_eq!(sbc(0x05, 0x05, false, false).1, Status::PS_NEGATIVE);
+        assert_eq!(
+            sbc(0x05, 0x04, false, false),
+            (0x00, Status::PS_ZERO | Status::PS_CARRY)
+        );
+    }
+
+    #[test]
+    fn decimal_arithmetic_carries_past_99() {
+        // The NMOS chip takes N from the uncorrected 0x9a and Z from the
+        // binary sum.
+        assert_eq!(
+            adc(0x99, 0x01, false, true),
+            (0x00, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+        assert_eq!(
+            adc_cmos(0x99, 0x01, false, true),
+            (0x00, Status::PS_ZERO | Status::PS_CARRY)
+        );
+        assert_eq!(sbc(0x00, 0x01, true, true), (0x99, Status::PS_NEGATIVE));
+        assert_eq!(sbc(0x42, 0x13, true, true), (0x29, Status::PS_CARRY));
+    }
+
+    #[test]
+    fn decimal_arithmetic_on_invalid_bcd() {
+        assert_eq!(adc(0x0f, 0x0f, false, true).0, 0x14);
+        assert_eq!(adc(0x0a, 0x00, false, true).0, 0x10);
+        assert_eq!(
+            adc(0xa0, 0x00, false, true),
+            (0x00, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+        assert_eq!(sbc(0x20, 0x0f, true, true).0, 0x1b);
+        assert_eq!(sbc_cmos(0x20, 0x0f, true, true).0, 0x0b);
+    }
+
+    #[test]
+    fn reference_handles_decimal_edge_cases() {
+        assert_eq!(
+            reference_adc(0x99, 0x01, false, true),
+            (0x00, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+        assert_eq!(reference_adc(0x09, 0x07, false, true).0, 0x16);
+        // Invalid BCD, as measured on an NMOS 6502.
+        assert_eq!(reference_adc(0x0f, 0x0f, false, true).0, 0x14);
+        assert_eq!(
+            reference_sbc(0x00, 0x01, true, true),
+            (0x99, Status::PS_NEGATIVE)
+        );
+        assert_eq!(
+            reference_sbc(0x00, 0x01, true, false),
+            (0xff, Status::PS_NEGATIVE)
+        );
+    }
+
+    #[test]
+    fn cmos_reference_fixes_the_flags() {
+        assert_eq!(
+            reference_adc_cmos(0x99, 0x01, false, true),
+            (0x00, Status::PS_ZERO | Status::PS_CARRY)
+        );
+        assert_eq!(reference_sbc_cmos(0x00, 0x01, true, true).0, 0x99);
+        // Invalid BCD, where the two chips part ways.
+        assert_eq!(reference_sbc(0x20, 0x0f, true, true).0, 0x1b);
+        assert_eq!(reference_sbc_cmos(0x20, 0x0f, true, true).0, 0x0b);
+    }
+
+    #[test]
+    fn every_input_matches_the_reference() {
+        assert_eq!(verify(NVZC, NVZC).next(), None);
+    }
+
+    #[test]
+    fn cmos_matches_its_reference() {
+        for index in 0..0x4_0000_u32 {
+            let [a, b, mode, _] = index.to_le_bytes();
+            let (carry, decimal) = (mode & 1 != 0, mode & 2 != 0);
+            assert_eq!(
+                adc_cmos(a, b, carry, decimal),
+                reference_adc_cmos(a, b, carry, decimal)
+            );
+            assert_eq!(
+                sbc_cmos(a, b, carry, decimal),
+                reference_sbc_cmos(a, b, carry, decimal)
+            );
+        }
+    }
+
+    #[test]
+    fn arr_corrects_digits_in_decimal_mode() {
+        assert_eq!(
+            arr(0xff, 0xc0, true, false),
+            (0xe0, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+        assert_eq!(
+            arr(0xff, 0x55, false, true),
+            (0x80, Status::PS_OVERFLOW | Status::PS_CARRY)
+        );
+        assert_eq!(arr(0x12, 0xff, true, true), (0x89, Status::PS_NEGATIVE));
+    }
+
+    #[test]
+    fn compare_is_sbc_without_overflow() {
+        for register in 0..=u8::MAX {
+            for value in 0..=u8::MAX {
+                let (_, flags) = sbc(register, value, true, false);
+                assert_eq!(compare(register, value), flags - Status::PS_OVERFLOW);
+            }
+        }
+    }
+
+    #[test]
+    fn compare_orders_unsigned() {
+        assert_eq!(compare(0x10, 0x10), Status::PS_ZERO | Status::PS_CARRY);
+        assert_eq!(compare(0x10, 0x20), Status::PS_NEGATIVE);
+        assert_eq!(compare(0xf0, 0x10), Status::PS_NEGATIVE | Status::PS_CARRY);
+    }
+
+    #[test]
+    fn shifts_move_bits_through_the_carry() {
+        assert_eq!(asl(0x81), (0x02, Status::PS_CARRY));
+        assert_eq!(lsr(0x01), (0x00, Status::PS_ZERO | Status::PS_CARRY));
+        assert_eq!(rol(0x40, true), (0x81, Status::PS_NEGATIVE));
+        assert_eq!(
+            ror(0x01, true),
+            (0x80, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+    }
+}
@@ -0,0 +1,1340 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A small two-pass assembler for 6502 source.
+//!
+//! The syntax is the conventional one:
+//!
+//! ```text
+//! ; comments run to the end of the line
+//! count = 8             ; constants
+//!         .org $C000    ; set the program counter (also `*= $C000`)
+//! reset:  LDX #count    ; labels end with a colon
+//! loop:   DEX
+//!         BNE loop      ; forward and backward references both work
+//!         JMP (vector)
+//! vector: .word reset   ; little-endian words
+//! text:   .byte "hi", 0 ; bytes and strings
+//! ```
+//!
+//! Numbers are decimal, `$` hexadecimal, `%` binary or a quoted character.
+//! Expressions combine numbers, symbols and `*` (the current address) with
+//! `+` and `-`, and `<` and `>` select the low and high byte of a value.
+//! Operands whose value is known to fit in a byte when the instruction is
+//! first seen use zero-page addressing; forward references use absolute
+//! addressing.
+//!
+//! [`assemble_with`] reads sources written for ca65, ACME or 64tass
+//! instead, with their pseudo-ops and local labels; see [`Dialect`]. A
+//! local label goes into the symbol table qualified by its scope, as in
+//! `loop.next`. Anonymous labels aren't supported.
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+
+use crate::coverage::SourceLine;
+use crate::dialect::Dialect;
+use crate::instruction::{AddressingMode, Nmos6502};
+use crate::symbols::SymbolTable;
+use crate::Variant;
+
+/// Error raised for a line that cannot be assembled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// One-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AsmError {}
+
+/// The output of the assembler.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assembly {
+    /// Address of the first byte of [`Assembly::bytes`].
+    pub origin: u16,
+    /// Everything emitted, from the lowest to the highest address written.
+    /// Gaps between `.org` sections are filled with zeroes.
+    pub bytes: Vec<u8>,
+    /// Labels and constants defined by the source.
+    pub symbols: SymbolTable,
+    /// One entry per source line.
+    pub listing: Vec<ListingLine>,
+}
+
+/// A source line and the bytes it assembled to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListingLine {
+    /// One-based line number.
+    pub line: usize,
+    /// Value of the program counter at the start of the line.
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Whether the bytes are an instruction rather than data.
+    pub code: bool,
+    /// How long the instruction takes, if the line is one.
+    pub cycles: Option<Timing>,
+    pub source: String,
+}
+
+/// The cycle count of an assembled instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    /// Cycles taken without any penalty.
+    pub base: u8,
+    /// Whether a page crossing or a taken branch can add cycles.
+    pub variable: bool,
+}
+
+impl Timing {
+    fn of<V: Variant>(opcode: u8) -> Timing {
+        let variable = V::decode(opcode).is_some_and(|(instruction, mode)| match mode {
+            AddressingMode::Relative => true,
+            AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::IndirectIndexedY => V::page_cross_penalty(instruction, mode),
+            _ => false,
+        });
+        Timing {
+            base: V::cycles(opcode),
+            variable,
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.base)?;
+        if self.variable {
+            f.write_str("+")?;
+        }
+        Ok(())
+    }
+}
+
+impl Assembly {
+    /// The address of every line that assembled to an instruction, for
+    /// mapping coverage back to `file`.
+    pub fn source_lines<'a>(&'a self, file: &'a str) -> impl Iterator<Item = SourceLine<'a>> {
+        self.listing
+            .iter()
+            .filter(|entry| entry.code)
+            .map(move |entry| SourceLine {
+                file,
+                line: entry.line,
+                address: entry.address,
+            })
+    }
+
+    /// Writes a listing with the line number, address, emitted bytes, cycle
+    /// count and source of every line. Cycle counts marked `+` grow by one
+    /// when the instruction crosses a page or takes a branch.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by `out`.
+    pub fn write_listing(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        const BYTES_PER_ROW: usize = 3;
+
+        for entry in &self.listing {
+            let mut rows = entry.bytes.chunks(BYTES_PER_ROW);
+            let first = rows.next().unwrap_or_default();
+            if first.is_empty() {
+                write!(out, "{:>5}  {:4}  {:8}", entry.line, "", "")?;
+            } else {
+                write!(
+                    out,
+                    "{:>5}  {:04X}  {:8}",
+                    entry.line,
+                    entry.address,
+                    hex(first)
+                )?;
+            }
+            let cycles = entry.cycles.map(|timing| format!("{timing}"));
+            write!(out, "  {:>3}", cycles.as_deref().unwrap_or_default())?;
+            writeln!(out, "  {}", entry.source.trim_end())?;
+
+            let mut address = entry.address;
+            for row in rows {
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    address = address.wrapping_add(BYTES_PER_ROW as u16);
+                }
+                writeln!(out, "{:>5}  {address:04X}  {}", "", hex(row))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        let separator = if i > 0 { " " } else { "" };
+        let _ = write!(text, "{separator}{byte:02X}");
+    }
+    text
+}
+
+/// Assembles NMOS 6502 source.
+///
+/// # Errors
+///
+/// Returns the first line that cannot be assembled.
+///
+/// # Examples
+///
+/// ```
+/// let program = mos6502::asm::assemble("LDX #$08\nloop: DEX\nBNE loop").unwrap();
+/// assert_eq!(program.bytes, [0xa2, 0x08, 0xca, 0xd0, 0xfd]);
+/// ```
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    assemble_for::<Nmos6502>(source)
+}
+
+/// Assembles source for the variant `V`, which decides the available
+/// instructions and addressing modes.
+///
+/// # Errors
+///
+/// Returns the first line that cannot be assembled.
+pub fn assemble_for<V: Variant>(source: &str) -> Result<Assembly, AsmError> {
+    assemble_with::<V>(source, Dialect::Generic)
+}
+
+/// Assembles source written for `dialect`, for the variant `V`.
+///
+/// # Errors
+///
+/// Returns the first line that cannot be assembled.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::asm::assemble_with;
+/// use mos6502::dialect::Dialect;
+/// use mos6502::instruction::Nmos6502;
+///
+/// let source = "
+/// * = $C000
+/// clear   ldx #8
+/// .loop   dex
+///         bne .loop
+///         !byte $ea
+/// ";
+/// let program = assemble_with::<Nmos6502>(source, Dialect::Acme).unwrap();
+/// assert_eq!(program.bytes, [0xa2, 0x08, 0xca, 0xd0, 0xfd, 0xea]);
+/// assert_eq!(program.symbols.address_of(".loop"), Some(0xc002));
+/// ```
+pub fn assemble_with<V: Variant>(source: &str, dialect: Dialect) -> Result<Assembly, AsmError> {
+    let statements = source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| {
+            parse_line(text, dialect, Assembler::<V>::is_mnemonic).map_err(|message| AsmError {
+                line: index + 1,
+                message,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut assembler = Assembler::<V> {
+        symbols: SymbolTable::new(),
+        encodings: vec![None; statements.len()],
+        scopes: scopes(&statements, dialect),
+        dialect,
+        variant: core::marker::PhantomData,
+    };
+    assembler.first_pass(&statements)?;
+    assembler.second_pass(source, &statements)
+}
+
+/// The scope that local labels on each statement belong to.
+fn scopes(statements: &[Statement], dialect: Dialect) -> Vec<String> {
+    let mut scope = String::new();
+    let mut scopes = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        match (dialect, statement) {
+            (
+                Dialect::Acme,
+                Statement {
+                    directive: Directive::Zone(name),
+                    ..
+                },
+            ) => scope = name.map_or_else(|| format!("zone{}", index + 1), ToOwned::to_owned),
+            (
+                Dialect::Ca65 | Dialect::Tass64,
+                Statement {
+                    label: Some(label), ..
+                },
+            ) if !is_local(label, dialect) => (*label).clone_into(&mut scope),
+            _ => {}
+        }
+        scopes.push(scope.clone());
+    }
+    scopes
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Index {
+    X,
+    Y,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operand<'s> {
+    None,
+    Accumulator,
+    Immediate(&'s str),
+    Address(&'s str, Option<Index>),
+    Indirect(&'s str),
+    IndirectX(&'s str),
+    IndirectY(&'s str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum DataItem<'s> {
+    Expr(&'s str),
+    Text(&'s str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Directive<'s> {
+    None,
+    Org(&'s str),
+    /// Starts a new scope for local labels, optionally named.
+    Zone(Option<&'s str>),
+    Data(usize, Vec<DataItem<'s>>),
+    Constant(&'s str, &'s str),
+    Instruction(String, Operand<'s>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Statement<'s> {
+    label: Option<&'s str>,
+    directive: Directive<'s>,
+}
+
+struct Assembler<V> {
+    symbols: SymbolTable,
+    /// Opcode and addressing mode chosen for each instruction in the first
+    /// pass, so that both passes agree on instruction sizes.
+    encodings: Vec<Option<(u8, AddressingMode)>>,
+    /// Scope of the local labels on each statement.
+    scopes: Vec<String>,
+    dialect: Dialect,
+    variant: core::marker::PhantomData<V>,
+}
+
+impl<V: Variant> Assembler<V> {
+    fn first_pass(&mut self, statements: &[Statement]) -> Result<(), AsmError> {
+        let mut pc: usize = 0;
+        let mut deferred = Vec::new();
+
+        for (index, statement) in statements.iter().enumerate() {
+            let error = |message: String| AsmError {
+                line: index + 1,
+                message,
+            };
+            let address = address_of(pc);
+            if let Some(label) = statement.label {
+                let name = qualify(label, &self.scopes[index], self.dialect);
+                self.define(&name, address).map_err(error)?;
+            }
+            let scope = &self.scopes[index];
+
+            match &statement.directive {
+                Directive::None | Directive::Zone(_) => {}
+                Directive::Org(expr) => {
+                    let value = self.evaluate(expr, address, scope, true).map_err(error)?;
+                    pc = usize::from(to_word(value.unwrap_or_default()).map_err(error)?);
+                }
+                Directive::Data(width, items) => {
+                    for item in items {
+                        pc += match item {
+                            DataItem::Expr(_) => *width,
+                            DataItem::Text(text) => text.len() * width,
+                        };
+                    }
+                }
+                Directive::Constant(name, expr) => {
+                    match self.evaluate(expr, address, scope, false).map_err(error)? {
+                        Some(value) => self
+                            .define(name, to_word(value).map_err(error)?)
+                            .map_err(error)?,
+                        None => deferred.push((index, *name, *expr, address)),
+                    }
+                }
+                Directive::Instruction(mnemonic, operand) => {
+                    let encoding = self
+                        .choose_encoding(mnemonic, *operand, address, scope)
+                        .map_err(error)?;
+                    pc += 1 + usize::from(encoding.1.extra_bytes());
+                    self.encodings[index] = Some(encoding);
+                }
+            }
+            if pc > END_OF_MEMORY {
+                return Err(error("program runs past the end of memory".to_owned()));
+            }
+        }
+
+        // Constants that refer to later labels or constants.
+        while !deferred.is_empty() {
+            let before = deferred.len();
+            let mut unresolved = Vec::new();
+            for (index, name, expr, address) in deferred {
+                let error = |message: String| AsmError {
+                    line: index + 1,
+                    message,
+                };
+                match self
+                    .evaluate(expr, address, &self.scopes[index], false)
+                    .map_err(error)?
+                {
+                    Some(value) => self
+                        .define(name, to_word(value).map_err(error)?)
+                        .map_err(error)?,
+                    None => unresolved.push((index, name, expr, address)),
+                }
+            }
+            if unresolved.len() == before {
+                let (index, _, expr, address) = unresolved[0];
+                // Evaluate strictly to report the missing symbol.
+                let message = self
+                    .evaluate(expr, address, &self.scopes[index], true)
+                    .unwrap_err();
+                return Err(AsmError {
+                    line: index + 1,
+                    message,
+                });
+            }
+            deferred = unresolved;
+        }
+        Ok(())
+    }
+
+    fn second_pass(&self, source: &str, statements: &[Statement]) -> Result<Assembly, AsmError> {
+        let mut image = vec![0; 0x10000];
+        let mut written = vec![false; 0x10000];
+        let mut listing = Vec::with_capacity(statements.len());
+        let mut pc: u16 = 0;
+
+        for ((index, statement), text) in statements.iter().enumerate().zip(source.lines()) {
+            let error = |message: String| AsmError {
+                line: index + 1,
+                message,
+            };
+            let address = pc;
+            let scope = &self.scopes[index];
+            let mut bytes = Vec::new();
+
+            match &statement.directive {
+                Directive::None | Directive::Zone(_) | Directive::Constant(..) => {}
+                Directive::Org(expr) => {
+                    pc = to_word(self.strict(expr, pc, scope).map_err(error)?).map_err(error)?;
+                }
+                Directive::Data(width, items) => {
+                    for item in items {
+                        match item {
+                            DataItem::Expr(expr) => {
+                                let value = self.strict(expr, pc, scope).map_err(error)?;
+                                if *width == 1 {
+                                    bytes.push(to_byte(value).map_err(error)?);
+                                } else {
+                                    bytes.extend(to_word(value).map_err(error)?.to_le_bytes());
+                                }
+                            }
+                            DataItem::Text(text) => {
+                                for byte in text.bytes() {
+                                    bytes.push(byte);
+                                    bytes.resize(bytes.len() + width - 1, 0);
+                                }
+                            }
+                        }
+                    }
+                }
+                Directive::Instruction(_, operand) => {
+                    let (opcode, mode) = self.encodings[index].expect("encoded in the first pass");
+                    bytes.push(opcode);
+                    self.encode_operand(*operand, mode, pc, scope, &mut bytes)
+                        .map_err(error)?;
+                }
+            }
+
+            for (offset, byte) in bytes.iter().enumerate() {
+                let at = usize::from(address) + offset;
+                if written[at] {
+                    return Err(error(format!("overwrites code at ${at:04X}")));
+                }
+                written[at] = true;
+                image[at] = *byte;
+            }
+            if !bytes.is_empty() {
+                // The first pass has checked that the program fits in memory.
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    pc = address.wrapping_add(bytes.len() as u16);
+                }
+            }
+
+            let cycles = match statement.directive {
+                Directive::Instruction(..) => Some(Timing::of::<V>(bytes[0])),
+                _ => None,
+            };
+            listing.push(ListingLine {
+                line: index + 1,
+                address,
+                bytes,
+                code: cycles.is_some(),
+                cycles,
+                source: text.to_owned(),
+            });
+        }
+
+        let start = written.iter().position(|w| *w);
+        let end = written.iter().rposition(|w| *w);
+        let (origin, bytes) = match (start, end) {
+            (Some(start), Some(end)) => (
+                u16::try_from(start).expect("addresses fit in 16 bits"),
+                image[start..=end].to_vec(),
+            ),
+            _ => (0, Vec::new()),
+        };
+
+        Ok(Assembly {
+            origin,
+            bytes,
+            symbols: self.symbols.clone(),
+            listing,
+        })
+    }
+
+    fn define(&mut self, name: &str, value: u16) -> Result<(), String> {
+        if self.symbols.address_of(name).is_some() {
+            return Err(format!("symbol `{name}` is defined twice"));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    fn evaluate(
+        &self,
+        expr: &str,
+        pc: u16,
+        scope: &str,
+        strict: bool,
+    ) -> Result<Option<i32>, String> {
+        let mut parser = Expression {
+            text: expr.as_bytes(),
+            position: 0,
+            symbols: &self.symbols,
+            pc,
+            scope,
+            dialect: self.dialect,
+            strict,
+        };
+        let value = parser.expression()?;
+        parser.skip_spaces();
+        if parser.position < parser.text.len() {
+            return Err(format!("malformed expression `{}`", expr.trim()));
+        }
+        Ok(value)
+    }
+
+    fn strict(&self, expr: &str, pc: u16, scope: &str) -> Result<i32, String> {
+        Ok(self
+            .evaluate(expr, pc, scope, true)?
+            .expect("strict evaluation resolves every symbol"))
+    }
+
+    fn choose_encoding(
+        &self,
+        mnemonic: &str,
+        operand: Operand,
+        pc: u16,
+        scope: &str,
+    ) -> Result<(u8, AddressingMode), String> {
+        let candidates: &[AddressingMode] = match operand {
+            Operand::None => &[AddressingMode::Implied, AddressingMode::Accumulator],
+            Operand::Accumulator => &[AddressingMode::Accumulator],
+            Operand::Immediate(_) => &[AddressingMode::Immediate],
+            Operand::Indirect(_) => &[
+                AddressingMode::Indirect,
+                AddressingMode::BuggyIndirect,
+                AddressingMode::ZeroPageIndirect,
+            ],
+            Operand::IndirectX(_) => &[
+                AddressingMode::IndexedIndirectX,
+                AddressingMode::AbsoluteIndexedIndirect,
+            ],
+            Operand::IndirectY(_) => &[AddressingMode::IndirectIndexedY],
+            Operand::Address(expr, index) => {
+                let zero_page = self
+                    .evaluate(expr, pc, scope, false)?
+                    .is_some_and(|value| (0..=0xff).contains(&value));
+                match index {
+                    None if zero_page => &[
+                        AddressingMode::Relative,
+                        AddressingMode::ZeroPage,
+                        AddressingMode::Absolute,
+                    ],
+                    None => &[
+                        AddressingMode::Relative,
+                        AddressingMode::Absolute,
+                        AddressingMode::ZeroPage,
+                    ],
+                    Some(Index::X) if zero_page => {
+                        &[AddressingMode::ZeroPageX, AddressingMode::AbsoluteX]
+                    }
+                    Some(Index::X) => &[AddressingMode::AbsoluteX, AddressingMode::ZeroPageX],
+                    Some(Index::Y) if zero_page => {
+                        &[AddressingMode::ZeroPageY, AddressingMode::AbsoluteY]
+                    }
+                    Some(Index::Y) => &[AddressingMode::AbsoluteY, AddressingMode::ZeroPageY],
+                }
+            }
+        };
+
+        candidates
+            .iter()
+            .find_map(|mode| opcode::<V>(mnemonic, *mode).map(|opcode| (opcode, *mode)))
+            .ok_or_else(|| {
+                if Self::is_mnemonic(mnemonic) {
+                    format!("addressing mode not available for {mnemonic}")
+                } else {
+                    format!("unknown instruction `{mnemonic}`")
+                }
+            })
+    }
+
+    fn is_mnemonic(mnemonic: &str) -> bool {
+        (0..=u8::MAX).any(|opcode| {
+            V::decode(opcode).is_some_and(|(instruction, _)| instruction.mnemonic() == mnemonic)
+        })
+    }
+
+    fn encode_operand(
+        &self,
+        operand: Operand,
+        mode: AddressingMode,
+        pc: u16,
+        scope: &str,
+        bytes: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let expr = match operand {
+            Operand::None | Operand::Accumulator => return Ok(()),
+            Operand::Immediate(expr)
+            | Operand::Address(expr, _)
+            | Operand::Indirect(expr)
+            | Operand::IndirectX(expr)
+            | Operand::IndirectY(expr) => expr,
+        };
+        let value = self.strict(expr, pc, scope)?;
+
+        match mode {
+            AddressingMode::Immediate => bytes.push(to_byte(value)?),
+            AddressingMode::Relative => {
+                let offset = value - (i32::from(pc) + 2);
+                let offset = i8::try_from(offset)
+                    .map_err(|_| format!("branch target is {offset} bytes away"))?;
+                bytes.push(offset.to_le_bytes()[0]);
+            }
+            _ if mode.extra_bytes() == 1 => {
+                let address = u8::try_from(value)
+                    .map_err(|_| format!("${value:X} is not a zero-page address"))?;
+                bytes.push(address);
+            }
+            _ => bytes.extend(to_word(value)?.to_le_bytes()),
+        }
+        Ok(())
+    }
+}
+
+/// The position just past the last byte of memory, which is where `pc`
+/// ends up once a byte has been placed at $FFFF.
+const END_OF_MEMORY: usize = 0x10000;
+
+/// The address of `pc`, with [`END_OF_MEMORY`] wrapping round to $0000 as
+/// the 6502's own address arithmetic does, so that a label after the last
+/// byte still gets a value.
+// `pc` is at most `END_OF_MEMORY`, the only value that truncates.
+#[allow(clippy::cast_possible_truncation)]
+const fn address_of(pc: usize) -> u16 {
+    pc as u16
+}
+
+/// The opcode that `V` decodes to `mnemonic` in `mode`, preferring a
+/// documented one where an undocumented opcode duplicates it.
+pub(crate) fn opcode<V: Variant>(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    (0..=u8::MAX)
+        .filter(|opcode| {
+            V::decode(*opcode)
+                .is_some_and(|(instruction, m)| m == mode && instruction.mnemonic() == mnemonic)
+        })
+        .min_by_key(|opcode| V::is_undocumented(*opcode))
+}
+
+fn to_byte(value: i32) -> Result<u8, String> {
+    // Negative bytes are accepted as two's complement.
+    i8::try_from(value)
+        .map(|value| value.to_le_bytes()[0])
+        .or_else(|_| u8::try_from(value))
+        .map_err(|_| format!("{value} does not fit in a byte"))
+}
+
+fn to_word(value: i32) -> Result<u16, String> {
+    u16::try_from(value).map_err(|_| format!("{value} does not fit in a word"))
+}
+
+/// Recursive-descent evaluator for operand expressions.
+struct Expression<'a> {
+    text: &'a [u8],
+    position: usize,
+    symbols: &'a SymbolTable,
+    pc: u16,
+    /// Scope that local labels are looked up in.
+    scope: &'a str,
+    dialect: Dialect,
+    /// Whether an undefined symbol is an error, rather than an unknown value.
+    strict: bool,
+}
+
+impl<'a> Expression<'a> {
+    fn skip_spaces(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_spaces();
+        self.text.get(self.position).copied()
+    }
+
+    fn expression(&mut self) -> Result<Option<i32>, String> {
+        let mut value = self.unary()?;
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.position += 1;
+            let rhs = self.unary()?;
+            value = match (value, rhs) {
+                (Some(lhs), Some(rhs)) if op == b'+' => Some(lhs.wrapping_add(rhs)),
+                (Some(lhs), Some(rhs)) => Some(lhs.wrapping_sub(rhs)),
+                _ => None,
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Option<i32>, String> {
+        match self.peek() {
+            Some(b'<') => {
+                self.position += 1;
+                Ok(self.unary()?.map(|value| value & 0xff))
+            }
+            Some(b'>') => {
+                self.position += 1;
+                Ok(self.unary()?.map(|value| (value >> 8) & 0xff))
+            }
+            Some(b'-') => {
+                self.position += 1;
+                Ok(self.unary()?.map(i32::wrapping_neg))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Option<i32>, String> {
+        let start = self.position;
+        match self.peek() {
+            Some(b'*') => {
+                self.position += 1;
+                Ok(Some(i32::from(self.pc)))
+            }
+            Some(b'(') => {
+                self.position += 1;
+                let value = self.expression()?;
+                if self.peek() != Some(b')') {
+                    return Err("missing `)`".to_owned());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(b'\'') => match self.text.get(self.position + 1..self.position + 3) {
+                Some([c, b'\'']) => {
+                    self.position += 3;
+                    Ok(Some(i32::from(*c)))
+                }
+                _ => Err("malformed character constant".to_owned()),
+            },
+            Some(b'$') => self.number(16, 1),
+            Some(b'%') => self.number(2, 1),
+            Some(b'0')
+                if self.dialect.c_hex()
+                    && matches!(self.text.get(self.position + 1), Some(b'x' | b'X')) =>
+            {
+                self.number(16, 2)
+            }
+            Some(c) if c.is_ascii_digit() => self.number(10, 0),
+            Some(c)
+                if self.dialect.local_prefix() == Some(char::from(c))
+                    && self
+                        .text
+                        .get(self.position + 1)
+                        .copied()
+                        .is_some_and(is_identifier_char) =>
+            {
+                self.position += 1;
+                let name = self.take_while(is_identifier_char);
+                self.symbol(&format!("{}.{name}", self.scope))
+            }
+            Some(c) if is_identifier_start(c) => {
+                let name = self.take_while(is_identifier_char);
+                self.symbol(name)
+            }
+            _ => Err(format!(
+                "expected a value at `{}`",
+                core::str::from_utf8(&self.text[start..])
+                    .unwrap_or_default()
+                    .trim()
+            )),
+        }
+    }
+
+    fn symbol(&self, name: &str) -> Result<Option<i32>, String> {
+        match self.symbols.address_of(name) {
+            Some(value) => Ok(Some(i32::from(value))),
+            None if self.strict => Err(format!("undefined symbol `{name}`")),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&mut self, radix: u32, prefix: usize) -> Result<Option<i32>, String> {
+        self.position += prefix;
+        let digits = self.take_while(|c| c.is_ascii_alphanumeric());
+        i32::from_str_radix(digits, radix)
+            .ok()
+            .filter(|value| *value <= 0xffff)
+            .map(Some)
+            .ok_or_else(|| format!("malformed number `{digits}`"))
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(u8) -> bool) -> &'a str {
+        let text = self.text;
+        let start = self.position;
+        while self.text.get(self.position).is_some_and(|c| predicate(*c)) {
+            self.position += 1;
+        }
+        core::str::from_utf8(&text[start..self.position]).unwrap_or_default()
+    }
+}
+
+const fn is_identifier_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+const fn is_identifier_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut bytes = text.bytes();
+    bytes.next().is_some_and(is_identifier_start) && bytes.all(is_identifier_char)
+}
+
+/// Whether `text` names a local label in `dialect`.
+fn is_local(text: &str, dialect: Dialect) -> bool {
+    dialect
+        .local_prefix()
+        .and_then(|prefix| text.strip_prefix(prefix))
+        .is_some_and(|name| !name.is_empty() && name.bytes().all(is_identifier_char))
+}
+
+fn is_label(text: &str, dialect: Dialect) -> bool {
+    is_identifier(text) || is_local(text, dialect)
+}
+
+/// The symbol-table name of the label `name` in `scope`.
+fn qualify(name: &str, scope: &str, dialect: Dialect) -> String {
+    if is_local(name, dialect) {
+        format!("{scope}.{}", &name[1..])
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Removes a trailing comment, ignoring semicolons inside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits on commas outside quotes.
+fn split_items(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items
+}
+
+fn parse_line(
+    text: &str,
+    dialect: Dialect,
+    is_mnemonic: impl Fn(&str) -> bool,
+) -> Result<Statement<'_>, String> {
+    let indented = text.starts_with(char::is_whitespace);
+    let mut rest = strip_comment(text).trim();
+
+    let mut label = None;
+    if let Some((name, after)) = rest.split_once(':') {
+        // `:=` is an assignment, not a label.
+        if is_label(name.trim(), dialect) && !after.starts_with('=') {
+            label = Some(name.trim());
+            rest = after.trim();
+        }
+    }
+    if label.is_none() && !indented && !dialect.labels_need_colon() {
+        let (name, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if is_label(name, dialect)
+            && !after.trim_start().starts_with('=')
+            && !is_mnemonic(&name.to_ascii_uppercase())
+        {
+            label = Some(name);
+            rest = after.trim();
+        }
+    }
+
+    let directive = if rest.is_empty() {
+        Directive::None
+    } else if let Some(expr) = rest
+        .strip_prefix('*')
+        .and_then(|rest| rest.trim_start().strip_prefix('='))
+    {
+        Directive::Org(expr)
+    } else if let Some((name, expr)) = rest.split_once(":=").or_else(|| rest.split_once('=')) {
+        let name = name.trim();
+        if !is_identifier(name) {
+            return Err(format!("malformed symbol name `{name}`"));
+        }
+        Directive::Constant(name, expr)
+    } else if let Some(directive) = rest.strip_prefix(dialect.directive_prefix()) {
+        let (name, args) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        match pseudo_op(name.to_ascii_lowercase().as_str(), dialect) {
+            Some(PseudoOp::Org) => Directive::Org(args),
+            Some(PseudoOp::Data(width)) => Directive::Data(width, parse_data(args)?),
+            Some(PseudoOp::Zone) => Directive::Zone(Some(args.trim()).filter(|a| !a.is_empty())),
+            None => {
+                let prefix = dialect.directive_prefix();
+                return Err(format!("unknown directive `{prefix}{name}`"));
+            }
+        }
+    } else {
+        let (mnemonic, operand) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        Directive::Instruction(
+            mnemonic.to_ascii_uppercase(),
+            parse_operand(operand.trim())?,
+        )
+    };
+
+    Ok(Statement { label, directive })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PseudoOp {
+    Org,
+    Data(usize),
+    Zone,
+}
+
+/// The pseudo-op called `name` in `dialect`, without its prefix.
+const fn pseudo_op(name: &str, dialect: Dialect) -> Option<PseudoOp> {
+    let name = name.as_bytes();
+    Some(match (dialect, name) {
+        (Dialect::Generic | Dialect::Ca65, b"org") => PseudoOp::Org,
+        (Dialect::Generic, b"byte" | b"db")
+        | (Dialect::Ca65, b"byte")
+        | (Dialect::Acme, b"byte" | b"by" | b"8" | b"08" | b"text" | b"tx")
+        | (Dialect::Tass64, b"byte" | b"text") => PseudoOp::Data(1),
+        (Dialect::Generic, b"word" | b"dw")
+        | (Dialect::Ca65 | Dialect::Tass64, b"word" | b"addr")
+        | (Dialect::Acme, b"word" | b"wo" | b"16") => PseudoOp::Data(2),
+        (Dialect::Acme, b"zone" | b"zn") => PseudoOp::Zone,
+        _ => return None,
+    })
+}
+
+fn parse_data(args: &str) -> Result<Vec<DataItem<'_>>, String> {
+    if args.trim().is_empty() {
+        return Err("expected at least one value".to_owned());
+    }
+    split_items(args)
+        .into_iter()
+        .map(|item| {
+            if let Some(text) = item.strip_prefix('"') {
+                text.strip_suffix('"')
+                    .map(DataItem::Text)
+                    .ok_or_else(|| "unterminated string".to_owned())
+            } else if item.is_empty() {
+                Err("empty value".to_owned())
+            } else {
+                Ok(DataItem::Expr(item))
+            }
+        })
+        .collect()
+}
+
+fn parse_operand(text: &str) -> Result<Operand<'_>, String> {
+    if text.is_empty() {
+        return Ok(Operand::None);
+    }
+    if text.eq_ignore_ascii_case("a") {
+        return Ok(Operand::Accumulator);
+    }
+    if let Some(expr) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(expr));
+    }
+
+    // `(zp,X)` puts the index inside the parentheses.
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        if let Some((base, index)) = inner.rsplit_once(',') {
+            if index.trim().eq_ignore_ascii_case("x") {
+                return Ok(Operand::IndirectX(base.trim()));
+            }
+            return Err(format!("malformed operand `{text}`"));
+        }
+    }
+
+    let (base, index) = match text.rsplit_once(',') {
+        Some((base, index)) if index.trim().eq_ignore_ascii_case("x") => {
+            (base.trim(), Some(Index::X))
+        }
+        Some((base, index)) if index.trim().eq_ignore_ascii_case("y") => {
+            (base.trim(), Some(Index::Y))
+        }
+        Some(_) => return Err(format!("malformed operand `{text}`")),
+        None => (text, None),
+    };
+
+    // `(ptr),Y` puts it outside.
+    if let Some(inner) = base.strip_prefix('(').and_then(|b| b.strip_suffix(')')) {
+        return match index {
+            None => Ok(Operand::Indirect(inner)),
+            Some(Index::Y) => Ok(Operand::IndirectY(inner)),
+            Some(Index::X) => Err(format!("malformed operand `{text}`")),
+        };
+    }
+
+    Ok(Operand::Address(base, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Cmos6502;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    #[test]
+    fn every_addressing_mode() {
+        let source = "
+            ASL
+            ASL A
+            LDA #$2A
+            LDA $10
+            LDA $10,X
+            LDX $10,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $1234,Y
+            JMP ($1234)
+            LDA ($10,X)
+            LDA ($10),Y
+        ";
+        #[rustfmt::skip]
+        let expected = [
+            0x0a,
+            0x0a,
+            0xa9, 0x2a,
+            0xa5, 0x10,
+            0xb5, 0x10,
+            0xb6, 0x10,
+            0xad, 0x34, 0x12,
+            0xbd, 0x34, 0x12,
+            0xb9, 0x34, 0x12,
+            0x6c, 0x34, 0x12,
+            0xa1, 0x10,
+            0xb1, 0x10,
+        ];
+        assert_eq!(bytes(source), expected);
+    }
+
+    #[test]
+    fn labels_constants_and_forward_references() {
+        let program = assemble(
+            "
+            count = 3
+                    .org $C000
+            start:  LDX #count
+            loop:   DEX
+                    BNE loop
+                    JSR sub
+                    LDA data+1
+                    BRK
+            sub:    RTS
+            data:   .byte 1, \"ab\", <start, >start
+                    .word start
+            ",
+        )
+        .unwrap();
+        assert_eq!(program.origin, 0xc000);
+        #[rustfmt::skip]
+        assert_eq!(
+            program.bytes,
+            [
+                0xa2, 0x03,
+                0xca,
+                0xd0, 0xfd,
+                0x20, 0x0c, 0xc0,
+                0xad, 0x0e, 0xc0,
+                0x00,
+                0x60,
+                0x01, b'a', b'b', 0x00, 0xc0,
+                0x00, 0xc0,
+            ]
+        );
+        assert_eq!(program.symbols.address_of("sub"), Some(0xc00c));
+    }
+
+    #[test]
+    fn vector_table_fills_the_end_of_memory() {
+        let program = assemble(
+            "
+                    .org $FF00
+            nmi:    RTI
+            reset:  JMP reset
+            irq:    RTI
+                    .org $FFFA
+                    .word nmi, reset, irq
+            end:
+            ",
+        )
+        .unwrap();
+        assert_eq!(program.origin, 0xff00);
+        assert_eq!(program.bytes.len(), 0x100);
+        assert_eq!(program.bytes[0xfa..], [0x00, 0xff, 0x01, 0xff, 0x04, 0xff]);
+        assert_eq!(program.symbols.address_of("end"), Some(0x0000));
+
+        assert_eq!(
+            assemble(".org $FFFA\n.word 1, 2, 3, 4"),
+            Err(AsmError {
+                line: 2,
+                message: "program runs past the end of memory".to_owned(),
+            })
+        );
+        assert_eq!(assemble(".org $FFFF\nNOP\nNOP").unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn forward_references_use_absolute_addressing() {
+        assert_eq!(bytes("LDA zp\nzp = $10"), [0xad, 0x10, 0x00]);
+        assert_eq!(bytes("zp = $10\nLDA zp"), [0xa5, 0x10]);
+    }
+
+    #[test]
+    fn variant_decides_instructions() {
+        assert_eq!(
+            assemble("STZ $10").unwrap_err().message,
+            "unknown instruction `STZ`"
+        );
+        assert_eq!(
+            assemble_for::<Cmos6502>("STZ $10").unwrap().bytes,
+            [0x64, 0x10]
+        );
+        assert_eq!(
+            assemble_for::<Cmos6502>("LDA ($10)").unwrap().bytes,
+            [0xb2, 0x10]
+        );
+        assert_eq!(
+            assemble_for::<Cmos6502>("JMP ($1234,X)").unwrap().bytes,
+            [0x7c, 0x34, 0x12]
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        let error = assemble("NOP\n\nLDA missing\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(error.message, "undefined symbol `missing`");
+
+        assert_eq!(assemble("x: NOP\nx: NOP").unwrap_err().line, 2);
+        assert_eq!(
+            assemble("BNE far\n.org $200\nfar: NOP").unwrap_err().line,
+            1
+        );
+        assert_eq!(
+            assemble("LDX ($10),Y").unwrap_err().message,
+            "addressing mode not available for LDX"
+        );
+        assert!(assemble(".org $FFFF\nNOP\nNOP").is_err());
+    }
+
+    #[test]
+    fn dialect_pseudo_ops_and_local_labels() {
+        let ca65 = "
+                .org $0300
+        first:  ldx #2
+        @loop:  dex
+                bne @loop
+        second: ldy #2
+        @loop:  dey
+                bne @loop
+                .addr first, second
+        ";
+        let program = assemble_with::<Nmos6502>(ca65, Dialect::Ca65).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            program.bytes,
+            [
+                0xa2, 0x02, 0xca, 0xd0, 0xfd,
+                0xa0, 0x02, 0x88, 0xd0, 0xfd,
+                0x00, 0x03, 0x05, 0x03,
+            ]
+        );
+        assert_eq!(program.symbols.address_of("second.loop"), Some(0x0307));
+
+        let acme = "\
+*=$1000
+        !zone one
+.skip   lda #0x10
+        beq .skip
+        !zn two
+.skip   !tx \"ok\"
+        !16 .skip
+";
+        let program = assemble_with::<Nmos6502>(acme, Dialect::Acme).unwrap();
+        assert_eq!(
+            program.bytes,
+            [0xa9, 0x10, 0xf0, 0xfc, b'o', b'k', 0x04, 0x10]
+        );
+
+        let tass = "\
+* = $2000
+start   ldx #1
+_next   dex
+        bpl _next
+        .text \"a\"
+";
+        let program = assemble_with::<Nmos6502>(tass, Dialect::Tass64).unwrap();
+        assert_eq!(program.bytes, [0xa2, 0x01, 0xca, 0x10, 0xfd, b'a']);
+        assert_eq!(program.symbols.address_of("start.next"), Some(0x2002));
+
+        assert_eq!(
+            assemble_with::<Nmos6502>("  .byte 1", Dialect::Acme)
+                .unwrap_err()
+                .message,
+            "unknown instruction `.BYTE`"
+        );
+        assert_eq!(
+            assemble_with::<Nmos6502>("  !zone", Dialect::Ca65)
+                .unwrap_err()
+                .message,
+            "unknown instruction `!ZONE`"
+        );
+        assert!(assemble("@loop: NOP").is_err());
+    }
+
+    #[test]
+    fn listing_shows_addresses_and_bytes() {
+        let program = assemble(".org $0200\nstart: LDA #1 ; one\n.byte 1,2,3,4").unwrap();
+        let mut listing = String::new();
+        program.write_listing(&mut listing).unwrap();
+        assert_eq!(
+            listing,
+            "    1                       .org $0200\n\
+             \x20   2  0200  A9 01       2  start: LDA #1 ; one\n\
+             \x20   3  0202  01 02 03       .byte 1,2,3,4\n\
+             \x20      0205  04\n"
+        );
+    }
+
+    #[test]
+    fn listing_marks_variable_timings() {
+        let program = assemble(".org $0200\nLDA $1000,X\nSTA $1000,X\nBNE *\nLDA ($10),Y").unwrap();
+        let cycles: Vec<_> = program.listing.iter().map(|line| line.cycles).collect();
+        let timing = |base, variable| Some(Timing { base, variable });
+        assert_eq!(
+            cycles,
+            [
+                None,
+                timing(4, true),
+                timing(5, false),
+                timing(2, true),
+                timing(5, true)
+            ]
+        );
+        let mut listing = String::new();
+        program.write_listing(&mut listing).unwrap();
+        assert!(listing.contains("  4+  LDA $1000,X"));
+    }
+}
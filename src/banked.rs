@@ -0,0 +1,442 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Bank-switched memory.
+//!
+//! Many 6502 systems have more ROM and RAM than fits in 64 KiB and page it
+//! in through windows: the C64 swaps its BASIC and KERNAL ROMs for the RAM
+//! underneath, NES mappers page PRG ROM, and cartridges bring 128 KiB or
+//! more. [`BankedMemory`] splits the address space into windows of a fixed
+//! power-of-two size, usually 4 or 8 KiB, each of which shows one bank of
+//! a region or nothing.
+//!
+//! Regions are ROM slices or RAM that [`BankedMemory`] owns, cut into
+//! window-sized banks. Windows with nothing mapped fall through to the bus
+//! underneath, as do writes to ROM, which is how the C64 writes to the RAM
+//! under its ROMs. Bank-select registers are callbacks on addresses that
+//! remap the [`Windows`] when the CPU writes to them.
+//!
+//! ```
+//! use mos6502::banked::BankedMemory;
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! static CARTRIDGE: [u8; 0x8000] = [0; 0x8000];
+//! let mut memory = BankedMemory::new(0x2000, Memory::new());
+//! let rom = memory.add_rom(&CARTRIDGE);
+//! memory.windows_mut().map(0x8000, rom.bank(0));
+//! // Writing to $8000 selects the bank at $8000-$9FFF.
+//! memory.on_write(0x8000, move |windows, value| {
+//!     windows.map(0x8000, rom.bank(usize::from(value)));
+//! });
+//! memory.set_byte(0x8000, 3);
+//! assert_eq!(memory.windows().bank_at(0x9FFF), Some(rom.bank(3)));
+//! ```
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::fmt;
+use core::ops::Range;
+
+use crate::memory::{Bus, BusError};
+
+/// A region added to a [`BankedMemory`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Region(usize);
+
+impl Region {
+    /// The `index`th window-sized bank of the region.
+    #[must_use]
+    pub const fn bank(self, index: usize) -> Bank {
+        Bank {
+            region: self,
+            index,
+        }
+    }
+}
+
+/// One window-sized bank of a region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Bank {
+    pub region: Region,
+    pub index: usize,
+}
+
+/// Which bank each window of a [`BankedMemory`] shows, which is what
+/// bank-select callbacks change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Windows {
+    shift: u32,
+    table: Vec<Option<Bank>>,
+    /// Number of banks in each region.
+    banks: Vec<usize>,
+}
+
+impl Windows {
+    /// Size of a window in bytes.
+    #[must_use]
+    pub const fn size(&self) -> usize {
+        1 << self.shift
+    }
+
+    /// Shows `bank` in the window containing `address`. Bank indices wrap
+    /// around the size of the region, as the high bits of a bank-select
+    /// register do on hardware with less memory than the register can
+    /// address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bank's region was not added to this memory.
+    pub fn map(&mut self, address: u16, bank: Bank) {
+        let banks = self.banks[bank.region.0];
+        let window = self.window(address);
+        self.table[window] = Some(bank.region.bank(bank.index % banks));
+    }
+
+    /// Leaves the window containing `address` to the bus underneath.
+    pub fn unmap(&mut self, address: u16) {
+        let window = self.window(address);
+        self.table[window] = None;
+    }
+
+    /// The bank shown in the window containing `address`, if any.
+    #[must_use]
+    pub fn bank_at(&self, address: u16) -> Option<Bank> {
+        self.table[self.window(address)]
+    }
+
+    fn window(&self, address: u16) -> usize {
+        usize::from(address) >> self.shift
+    }
+
+    fn offset(&self, address: u16) -> usize {
+        usize::from(address) & (self.size() - 1)
+    }
+}
+
+enum Backing<'a> {
+    Rom(&'a [u8]),
+    Ram(Vec<u8>),
+}
+
+type Select<'a> = Box<dyn FnMut(&mut Windows, u8) + 'a>;
+
+/// A bus whose address space is paged in from ROM and RAM regions through
+/// fixed-size windows, on top of another bus.
+pub struct BankedMemory<'a, B: Bus> {
+    inner: B,
+    windows: Windows,
+    regions: Vec<Backing<'a>>,
+    selects: Vec<(u16, Select<'a>)>,
+}
+
+impl<'a, B: Bus> BankedMemory<'a, B> {
+    /// Splits the address space into windows of `window_size` bytes, all
+    /// showing `inner` until banks are mapped into them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_size` is not a power of two between 256 bytes and
+    /// 64 KiB.
+    #[must_use]
+    pub fn new(window_size: usize, inner: B) -> BankedMemory<'a, B> {
+        assert!(
+            window_size.is_power_of_two() && (0x100..=0x1_0000).contains(&window_size),
+            "windows must be a power of two between 256 bytes and 64 KiB"
+        );
+        let shift = window_size.trailing_zeros();
+        BankedMemory {
+            inner,
+            windows: Windows {
+                shift,
+                table: vec![None; 0x1_0000 >> shift],
+                banks: Vec::new(),
+            },
+            regions: Vec::new(),
+            selects: Vec::new(),
+        }
+    }
+
+    /// Adds a read-only region. Writes to its banks go to the bus
+    /// underneath.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rom` is empty or not a whole number of windows.
+    pub fn add_rom(&mut self, rom: &'a [u8]) -> Region {
+        let size = self.windows.size();
+        assert!(
+            !rom.is_empty() && rom.len().is_multiple_of(size),
+            "a ROM region must be a whole number of {size}-byte windows"
+        );
+        self.add(rom.len() / size, Backing::Rom(rom))
+    }
+
+    /// Adds a region of `banks` windows of RAM, cleared to zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `banks` is zero.
+    pub fn add_ram(&mut self, banks: usize) -> Region {
+        assert!(banks > 0, "a RAM region needs at least one bank");
+        self.add(banks, Backing::Ram(vec![0; banks * self.windows.size()]))
+    }
+
+    fn add(&mut self, banks: usize, backing: Backing<'a>) -> Region {
+        self.regions.push(backing);
+        self.windows.banks.push(banks);
+        Region(self.regions.len() - 1)
+    }
+
+    /// Calls `select` with the value whenever the CPU writes to `address`,
+    /// so it can remap the windows. The write still reaches memory as
+    /// usual. Several callbacks on one address run in the order they were
+    /// added.
+    pub fn on_write(&mut self, address: u16, select: impl FnMut(&mut Windows, u8) + 'a) {
+        self.selects.push((address, Box::new(select)));
+    }
+
+    #[must_use]
+    pub const fn windows(&self) -> &Windows {
+        &self.windows
+    }
+
+    pub const fn windows_mut(&mut self) -> &mut Windows {
+        &mut self.windows
+    }
+
+    /// The contents of a RAM region, for saving battery-backed RAM, or
+    /// `None` if `region` is ROM or was not added to this memory.
+    #[must_use]
+    pub fn ram(&self, region: Region) -> Option<&[u8]> {
+        match self.regions.get(region.0)? {
+            Backing::Ram(bytes) => Some(bytes),
+            Backing::Rom(_) => None,
+        }
+    }
+
+    /// The contents of a RAM region, for restoring battery-backed RAM, or
+    /// `None` if `region` is ROM or was not added to this memory.
+    pub fn ram_mut(&mut self, region: Region) -> Option<&mut [u8]> {
+        match self.regions.get_mut(region.0)? {
+            Backing::Ram(bytes) => Some(bytes),
+            Backing::Rom(_) => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// The bytes of `bank`, which must come from this memory.
+    fn bank(&self, bank: Bank) -> &[u8] {
+        let size = self.windows.size();
+        let bytes = match &self.regions[bank.region.0] {
+            Backing::Rom(bytes) => bytes,
+            Backing::Ram(bytes) => bytes.as_slice(),
+        };
+        &bytes[bank.index * size..(bank.index + 1) * size]
+    }
+}
+
+impl<B: Bus> fmt::Debug for BankedMemory<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BankedMemory")
+            .field("windows", &self.windows)
+            .field(
+                "selects",
+                &self
+                    .selects
+                    .iter()
+                    .map(|(address, _)| address)
+                    .collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: Bus> Bus for BankedMemory<'_, B> {
+    /// Lends out the bytes in `range`, which must lie within one window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` spans windows that aren't both left to the bus
+    /// underneath.
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        let size = self.windows.size();
+        let window = range.start / size;
+        let bank = self.windows.table.get(window).copied().flatten();
+        match bank {
+            Some(bank) if range.end <= (window + 1) * size => {
+                &self.bank(bank)[range.start % size..range.end - window * size]
+            }
+            Some(_) => panic!("banked memory can only lend out bytes within a window"),
+            None if range.end <= (window + 1) * size => self.inner.get_bytes(range),
+            None => {
+                let last = (range.end - 1) / size;
+                assert!(
+                    self.windows.table[window..=last]
+                        .iter()
+                        .all(Option::is_none),
+                    "banked memory can only lend out bytes within a window"
+                );
+                self.inner.get_bytes(range)
+            }
+        }
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.windows.bank_at(address) {
+            Some(bank) => self.bank(bank)[self.windows.offset(address)],
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        let offset = self.windows.offset(address);
+        let size = self.windows.size();
+        match self.windows.bank_at(address) {
+            Some(bank) => match &mut self.regions[bank.region.0] {
+                Backing::Ram(bytes) => bytes[bank.index * size + offset] = value,
+                Backing::Rom(_) => self.inner.set_byte(address, value),
+            },
+            None => self.inner.set_byte(address, value),
+        }
+        for (_, select) in self.selects.iter_mut().filter(|(at, _)| *at == address) {
+            select(&mut self.windows, value);
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    /// Resets the bus underneath. The windows keep their banks; bank-select
+    /// hardware that resets is up to whoever registered it.
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn numbered_banks<const N: usize>(size: usize) -> [u8; N] {
+        let mut rom = [0; N];
+        for (index, byte) in rom.iter_mut().enumerate() {
+            *byte = u8::try_from(index / size).unwrap();
+        }
+        rom
+    }
+
+    #[test]
+    fn select_register_pages_rom() {
+        let rom = numbered_banks::<0x20000>(0x2000);
+        let mut memory = BankedMemory::new(0x2000, Memory::new());
+        let prg = memory.add_rom(&rom);
+        memory.windows_mut().map(0x8000, prg.bank(0));
+        memory.windows_mut().map(0xE000, prg.bank(15));
+        memory.on_write(0x8000, move |windows, value| {
+            windows.map(0x8000, prg.bank(usize::from(value)));
+        });
+        assert_eq!(memory.get_byte(0x9FFF), 0);
+        memory.set_byte(0x8000, 5);
+        assert_eq!(memory.get_byte(0x8000), 5);
+        assert_eq!(memory.get_byte(0xFFFF), 15);
+        // Selecting past the end wraps around.
+        memory.set_byte(0x8000, 17);
+        assert_eq!(memory.get_byte(0x8000), 1);
+        assert_eq!(memory.get_bytes(0x8010..0x8012), [1, 1]);
+    }
+
+    #[test]
+    fn rom_can_be_banked_out_for_the_ram_underneath() {
+        let basic = [0xAA; 0x2000];
+        let mut memory = BankedMemory::new(0x1000, Memory::new());
+        let rom = memory.add_rom(&basic);
+        let map = move |windows: &mut Windows, value: u8| {
+            if value & 0x01 == 0 {
+                windows.unmap(0xA000);
+                windows.unmap(0xB000);
+            } else {
+                windows.map(0xA000, rom.bank(0));
+                windows.map(0xB000, rom.bank(1));
+            }
+        };
+        memory.on_write(0x0001, map);
+        memory.set_byte(0x0001, 0x37);
+        // Writes to ROM go to the RAM underneath.
+        memory.set_byte(0xA000, 0x55);
+        assert_eq!(memory.get_byte(0xA000), 0xAA);
+        memory.set_byte(0x0001, 0x36);
+        assert_eq!(memory.get_byte(0xA000), 0x55);
+        assert_eq!(memory.get_byte(0x0001), 0x36);
+    }
+
+    #[test]
+    fn ram_banks_keep_their_contents() {
+        let mut memory = BankedMemory::new(0x1000, Memory::new());
+        let ram = memory.add_ram(4);
+        memory.on_write(0xDF00, move |windows, value| {
+            windows.map(0x4000, ram.bank(usize::from(value)));
+        });
+        for bank in 0..4 {
+            memory.set_byte(0xDF00, bank);
+            memory.set_byte(0x4123, bank + 0x10);
+        }
+        memory.set_byte(0xDF00, 2);
+        assert_eq!(memory.get_byte(0x4123), 0x12);
+        assert_eq!(memory.ram(ram).unwrap()[0x1123], 0x11);
+        assert_eq!(memory.inner().get_byte(0x4123), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "within a window")]
+    fn lending_across_windows_panics() {
+        let mut memory = BankedMemory::new(0x1000, Memory::new());
+        let ram = memory.add_ram(1);
+        memory.windows_mut().map(0x1000, ram.bank(0));
+        let _ = memory.get_bytes(0x0FFF..0x1001);
+    }
+}
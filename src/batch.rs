@@ -0,0 +1,279 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Running many independent machines in parallel.
+//!
+//! Fuzzers, superoptimizers and genetic programming all run the same code
+//! thousands of times with different inputs. [`Batch`] shares one memory
+//! image between every run: each gets an [`Overlay`] that reads the image
+//! and keeps its own writes on copy-on-write pages, so a run that only
+//! touches the zero page and stack costs a few hundred bytes rather than
+//! 64 KiB. Runs are spread over a pool of threads and their results
+//! returned in input order.
+//!
+//! # Examples
+//!
+//! ```
+//! use mos6502::batch::Batch;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::Bus;
+//!
+//! // ASL A; BRK
+//! let mut image = vec![0; 0x10000];
+//! image[0x0200..0x0202].copy_from_slice(&[0x0a, 0x00]);
+//!
+//! let inputs: Vec<u8> = (0..=255).collect();
+//! let doubled = Batch::new(&image).run(Nmos6502, &inputs, |&a, mut cpu| {
+//!     cpu.registers.accumulator = a;
+//!     cpu.registers.program_counter = 0x0200;
+//!     cpu.single_step();
+//!     cpu.registers.accumulator
+//! });
+//! assert_eq!(doubled[100], 200);
+//! assert_eq!(doubled[200], 144);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::cpu::CPU;
+use crate::memory::Bus;
+use crate::Variant;
+
+const PAGE_SIZE: usize = 0x100;
+
+/// A copy-on-write view of a shared memory image.
+///
+/// Reads come from the image until the page they fall in is first written,
+/// after which the page is a private copy. The image itself is never
+/// changed.
+#[derive(Clone, Debug)]
+pub struct Overlay<'a> {
+    image: &'a [u8],
+    pages: [Option<Box<[u8; PAGE_SIZE]>>; 256],
+}
+
+impl<'a> Overlay<'a> {
+    /// A view of `image` with nothing written yet. Addresses past the end
+    /// of `image` read as zero.
+    #[must_use]
+    pub const fn new(image: &'a [u8]) -> Overlay<'a> {
+        Overlay {
+            image,
+            pages: [const { None }; 256],
+        }
+    }
+
+    /// The bytes that now differ from the image, in address order.
+    pub fn changes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        (0..=u8::MAX)
+            .filter_map(|page| Some((page, self.pages[usize::from(page)].as_deref()?)))
+            .flat_map(move |(page, bytes)| {
+                (0..=u8::MAX).filter_map(move |offset| {
+                    let address = u16::from_le_bytes([offset, page]);
+                    let value = bytes[usize::from(offset)];
+                    (value != self.original(address)).then_some((address, value))
+                })
+            })
+    }
+
+    /// Number of pages copied so far, for measuring how much memory runs
+    /// use.
+    #[must_use]
+    pub fn pages_copied(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+
+    fn original(&self, address: u16) -> u8 {
+        self.image.get(usize::from(address)).copied().unwrap_or(0)
+    }
+
+    fn page_mut(&mut self, page: u8) -> &mut [u8; PAGE_SIZE] {
+        let image = self.image;
+        self.pages[usize::from(page)].get_or_insert_with(|| {
+            let mut bytes = Box::new([0; PAGE_SIZE]);
+            let start = usize::from(page) * PAGE_SIZE;
+            if let Some(original) = image.get(start..image.len().min(start + PAGE_SIZE)) {
+                bytes[..original.len()].copy_from_slice(original);
+            }
+            bytes
+        })
+    }
+}
+
+impl Bus for Overlay<'_> {
+    /// Lends out the bytes in `range`, which must lie within one page.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` spans pages or runs past the end of the image.
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        let page = range.start / PAGE_SIZE;
+        match self.pages.get(page).and_then(Option::as_deref) {
+            Some(bytes) if range.end <= (page + 1) * PAGE_SIZE => {
+                &bytes[range.start % PAGE_SIZE..range.end - page * PAGE_SIZE]
+            }
+            Some(_) => panic!("an overlay can only lend out bytes within a page"),
+            None => &self.image[range],
+        }
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        let [offset, page] = address.to_le_bytes();
+        match &self.pages[usize::from(page)] {
+            Some(bytes) => bytes[usize::from(offset)],
+            None => self.original(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        let [offset, page] = address.to_le_bytes();
+        self.page_mut(page)[usize::from(offset)] = value;
+    }
+}
+
+/// Runs jobs on CPUs over a shared memory image, in parallel.
+#[derive(Clone, Debug)]
+pub struct Batch<'a> {
+    image: &'a [u8],
+    threads: usize,
+}
+
+impl<'a> Batch<'a> {
+    /// Runs over `image` with a thread for every available core.
+    #[must_use]
+    pub fn new(image: &'a [u8]) -> Batch<'a> {
+        Batch {
+            image,
+            threads: thread::available_parallelism().map_or(1, usize::from),
+        }
+    }
+
+    /// Uses `threads` worker threads, or one if `threads` is zero.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    #[must_use]
+    pub const fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Calls `job` once for each of `inputs`, with a fresh CPU of `variant`
+    /// over its own [`Overlay`] of the image, and returns what each call
+    /// returned in the order of `inputs`. The job decides how far to run,
+    /// e.g. by wrapping the CPU in a [`Machine`](crate::machine::Machine)
+    /// with an instruction limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a job panics.
+    pub fn run<S, R, V, F>(&self, variant: V, inputs: &[S], job: F) -> Vec<R>
+    where
+        S: Sync,
+        R: Send,
+        V: Variant + Copy + Sync,
+        F: Fn(&S, CPU<Overlay<'a>, V>) -> R + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let workers = self.threads.min(inputs.len()).max(1);
+        let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(input) = inputs.get(index) else {
+                                break done;
+                            };
+                            let cpu = CPU::new(Overlay::new(self.image), variant);
+                            done.push((index, job(input, cpu)));
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("a batch job panicked"))
+                .collect()
+        });
+        results.sort_unstable_by_key(|&(index, _)| index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::machine::Machine;
+
+    #[test]
+    fn writes_stay_private_to_each_overlay() {
+        let mut image = alloc::vec![0; 0x10000];
+        image[0x1234] = 0x55;
+        let mut overlay = Overlay::new(&image);
+        overlay.set_byte(0x1235, 0x66);
+        overlay.set_byte(0x1236, 0x00);
+
+        assert_eq!(overlay.get_byte(0x1234), 0x55);
+        assert_eq!(overlay.get_byte(0x1235), 0x66);
+        assert_eq!(overlay.get_bytes(0x1234..0x1236), [0x55, 0x66]);
+        assert_eq!(overlay.changes().collect::<Vec<_>>(), [(0x1235, 0x66)]);
+        assert_eq!(overlay.pages_copied(), 1);
+        assert_eq!(image[0x1235], 0x00);
+    }
+
+    #[test]
+    fn runs_every_input_and_keeps_their_order() {
+        // Count $00 down to zero, storing each value: LDX $00; loop: TXA;
+        // STA $10,X; DEX; BNE loop; BRK
+        let mut image = alloc::vec![0; 0x10000];
+        image[0x0200..0x0209]
+            .copy_from_slice(&[0xa6, 0x00, 0x8a, 0x95, 0x10, 0xca, 0xd0, 0xfa, 0x00]);
+        let mut batch = Batch::new(&image);
+        batch.set_threads(4);
+
+        let inputs: Vec<u8> = (1..=100).collect();
+        let results = batch.run(Nmos6502, &inputs, |&count, mut cpu| {
+            cpu.memory.set_byte(0x0000, count);
+            cpu.registers.program_counter = 0x0200;
+            let mut machine = Machine::new(cpu);
+            machine.add_breakpoint(0x0208);
+            machine.run(Some(1000));
+            machine.cpu.memory.changes().count()
+        });
+
+        // The count itself plus one store per value.
+        let expected: Vec<usize> = inputs.iter().map(|&count| 1 + usize::from(count)).collect();
+        assert_eq!(results, expected);
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Battery-backed RAM that survives across emulator runs.
+//!
+//! Cartridges and single-board computers often keep a small amount of SRAM
+//! alive with a battery so that save games or configuration survive a power
+//! cycle. [`BatteryBacked`] wraps any [`Bus`] and associates one address
+//! range with a file on the host: the file is loaded into the range when the
+//! wrapper is constructed and written back on [`BatteryBacked::flush`] or when
+//! the wrapper is dropped.
+
+use core::ops::{Range, RangeInclusive};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::memory::{Bus, BusError};
+
+/// A bus with one battery-backed address range persisted to a host file.
+///
+/// Several regions can be modelled by nesting wrappers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mos6502::battery::BatteryBacked;
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut memory = BatteryBacked::new(Memory::new(), 0x6000..=0x7FFF, "save.sav")?;
+/// memory.set_byte(0x6000, 0x42);
+/// memory.flush()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct BatteryBacked<B: Bus> {
+    inner: B,
+    region: RangeInclusive<u16>,
+    path: PathBuf,
+    dirty: bool,
+}
+
+impl<B: Bus> BatteryBacked<B> {
+    /// Wraps `inner`, marking `region` as battery-backed and persisted to
+    /// `path`.
+    ///
+    /// If the file exists, its contents are copied into the region. A file
+    /// shorter than the region only initializes the start of it; any bytes
+    /// past the end of the region are ignored. A missing file is not an
+    /// error: the region keeps whatever `inner` holds and the file is created
+    /// on the first flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error other than [`io::ErrorKind::NotFound`] raised
+    /// while reading the file.
+    pub fn new(
+        mut inner: B,
+        region: RangeInclusive<u16>,
+        path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        match fs::read(&path) {
+            Ok(data) => {
+                let len = data.len().min(region_len(&region));
+                inner.set_bytes(*region.start(), &data[..len]);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        Ok(BatteryBacked {
+            inner,
+            region,
+            path,
+            dirty: false,
+        })
+    }
+
+    /// Writes the battery-backed region to its file, regardless of whether
+    /// it has been modified since the last flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error raised while writing the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let data: std::vec::Vec<u8> = self
+            .region
+            .clone()
+            .map(|address| self.inner.get_byte(address))
+            .collect();
+        fs::write(&self.path, data)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Returns `true` if the region has been written since it was loaded or
+    /// last flushed.
+    #[must_use]
+    pub const fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The battery-backed address range.
+    #[must_use]
+    pub const fn region(&self) -> &RangeInclusive<u16> {
+        &self.region
+    }
+
+    /// The file the region is persisted to.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    ///
+    /// Writes made through this reference bypass dirty tracking; call
+    /// [`BatteryBacked::flush`] explicitly if they touch the region.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+}
+
+fn region_len(region: &RangeInclusive<u16>) -> usize {
+    usize::from(*region.end()) + 1 - usize::from(*region.start())
+}
+
+impl<B: Bus> Bus for BatteryBacked<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if self.region.contains(&address) {
+            self.dirty = true;
+        }
+        self.inner.set_byte(address, value);
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+impl<B: Bus> Drop for BatteryBacked<B> {
+    fn drop(&mut self) {
+        if self.dirty {
+            if let Err(err) = self.flush() {
+                log::warn!(
+                    "failed to persist battery-backed RAM to {}: {err}",
+                    self.path.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(std::format!(
+            "mos6502-battery-{}-{name}.sav",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn contents_survive_drop_and_reload() {
+        let path = temp_path("reload");
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut memory = BatteryBacked::new(Memory::new(), 0x6000..=0x600F, &path).unwrap();
+            memory.set_byte(0x6000, 0xAA);
+            memory.set_byte(0x600F, 0x55);
+            memory.set_byte(0x7000, 0x11);
+            assert!(memory.is_dirty());
+        }
+
+        let memory = BatteryBacked::new(Memory::new(), 0x6000..=0x600F, &path).unwrap();
+        assert_eq!(memory.get_byte(0x6000), 0xAA);
+        assert_eq!(memory.get_byte(0x600F), 0x55);
+        assert_eq!(memory.get_byte(0x7000), 0x00);
+        assert!(!memory.is_dirty());
+        assert_eq!(fs::read(&path).unwrap().len(), 16);
+
+        drop(memory);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_outside_region_do_not_dirty() {
+        let path = temp_path("clean");
+        let _ = fs::remove_file(&path);
+
+        let mut memory = BatteryBacked::new(Memory::new(), 0x6000..=0x600F, &path).unwrap();
+        memory.set_byte(0x5FFF, 0x01);
+        memory.set_byte(0x6010, 0x01);
+        assert!(!memory.is_dirty());
+
+        drop(memory);
+        assert!(!path.exists());
+    }
+}
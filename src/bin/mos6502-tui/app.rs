@@ -0,0 +1,247 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Debugger state and input handling.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use mos6502::cpu::CPU;
+use mos6502::dbginfo::DebugInfo;
+use mos6502::machine::{Machine, StopReason};
+use mos6502::memory::Memory;
+#[cfg(feature = "scripting")]
+use mos6502::script::ScriptEngine;
+use mos6502::stack::CallTracker;
+use mos6502::Variant;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::args;
+
+/// Instructions executed between redraws while running.
+const INSTRUCTIONS_PER_TICK: u64 = 50_000;
+
+pub const HELP: &str = "s step  c continue  p pause  b breakpoint at PC  : command  q quit";
+
+#[cfg(not(feature = "scripting"))]
+pub const COMMANDS: &str =
+    "b <addr|file:line> toggle breakpoint  m <addr> memory view  pc <addr> set PC  s <n> step n  clear";
+#[cfg(feature = "scripting")]
+pub const COMMANDS: &str =
+    "b <addr|file:line> toggle breakpoint  bif <addr> <cond> conditional breakpoint  \
+     m <addr> memory view  pc <addr> set PC  s <n> step n  eval <expr>  clear";
+
+pub struct App<V: Variant> {
+    pub machine: Machine<Memory, V>,
+    /// Labels and source lines of the program, if it came with any.
+    pub debug: DebugInfo,
+    /// What pushed each stack byte, for the stack pane.
+    pub calls: Rc<RefCell<CallTracker>>,
+    /// First address of the memory dump.
+    pub memory_view: u16,
+    pub running: bool,
+    pub status: String,
+    /// The command being typed after `:`, if any.
+    pub input: Option<String>,
+    pub quit: bool,
+    #[cfg(feature = "scripting")]
+    scripts: ScriptEngine,
+}
+
+impl<V: Variant> App<V> {
+    pub fn new(cpu: CPU<Memory, V>, debug: DebugInfo) -> App<V> {
+        let mut machine = Machine::new(cpu);
+        let calls = Rc::new(RefCell::new(CallTracker::new()));
+        machine.add_observer(Rc::clone(&calls));
+        App {
+            machine,
+            debug,
+            calls,
+            memory_view: 0x0000,
+            running: false,
+            status: HELP.to_owned(),
+            input: None,
+            quit: false,
+            #[cfg(feature = "scripting")]
+            scripts: ScriptEngine::new(),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let line = std::mem::take(input);
+                    self.input = None;
+                    self.command(&line);
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit = true;
+            }
+            KeyCode::Char('s') | KeyCode::F(7) if !self.running => {
+                self.status = match self.machine.step() {
+                    Some(reason) => reason.to_string(),
+                    None => HELP.to_owned(),
+                };
+            }
+            KeyCode::Char('c') | KeyCode::F(5) => {
+                self.running = true;
+                self.status = "running".to_owned();
+            }
+            KeyCode::Char('p') if self.running => {
+                self.running = false;
+                self.status = "paused".to_owned();
+            }
+            KeyCode::Char('b') => {
+                let pc = self.machine.cpu.registers.program_counter;
+                self.toggle_breakpoint(pc);
+            }
+            KeyCode::Char(':') => {
+                self.input = Some(String::new());
+                self.status = COMMANDS.to_owned();
+            }
+            KeyCode::PageDown => self.memory_view = self.memory_view.wrapping_add(0x80),
+            KeyCode::PageUp => self.memory_view = self.memory_view.wrapping_sub(0x80),
+            _ => {}
+        }
+    }
+
+    /// Runs a batch of instructions while the program is running.
+    pub fn tick(&mut self) {
+        if !self.running {
+            return;
+        }
+        match self.machine.run(Some(INSTRUCTIONS_PER_TICK)).stop {
+            StopReason::LimitReached { .. } => {}
+            reason => {
+                self.running = false;
+                self.status = reason.to_string();
+            }
+        }
+    }
+
+    fn toggle_breakpoint(&mut self, address: u16) {
+        self.status = if self.machine.toggle_breakpoint(address) {
+            format!("breakpoint set at ${address:04X}")
+        } else {
+            format!("breakpoint removed at ${address:04X}")
+        };
+    }
+
+    fn command(&mut self, line: &str) {
+        #[cfg(feature = "scripting")]
+        if self.script_command(line) {
+            return;
+        }
+
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        if command == "s" {
+            self.status = match words.next().map_or(Ok(1), args::number) {
+                Ok(count) => {
+                    let steps = self.machine.step_instructions(count);
+                    match steps.stop {
+                        Some(reason) => format!("{reason} after {} instructions", steps.executed),
+                        None => format!("stepped {} instructions", steps.executed),
+                    }
+                }
+                Err(message) => message,
+            };
+            return;
+        }
+        let address = words.next().map(|text| self.location(text));
+
+        match (command, address) {
+            ("", None) => self.status = HELP.to_owned(),
+            ("clear", None) => {
+                self.machine.clear_breakpoints();
+                self.status = "breakpoints cleared".to_owned();
+            }
+            ("b", Some(Ok(address))) => self.toggle_breakpoint(address),
+            ("m", Some(Ok(address))) => self.memory_view = address,
+            ("pc", Some(Ok(address))) => self.machine.cpu.registers.program_counter = address,
+            (_, Some(Err(message))) => self.status = message,
+            _ => self.status = format!("unknown command `{line}`; {COMMANDS}"),
+        }
+    }
+
+    /// Parses an address, or a source line written as `file:line`.
+    fn location(&self, text: &str) -> Result<u16, String> {
+        let Some((file, line)) = text.rsplit_once(':') else {
+            return args::address(text);
+        };
+        let line = line
+            .parse()
+            .map_err(|_| format!("`{line}` is not a line number"))?;
+        self.debug
+            .address_of(file, line)
+            .ok_or_else(|| format!("no code for {file}:{line}"))
+    }
+
+    /// Handles the commands that take a script. Returns whether `line` was
+    /// one of them.
+    #[cfg(feature = "scripting")]
+    fn script_command(&mut self, line: &str) -> bool {
+        let (command, rest) = line.trim().split_once(' ').unwrap_or((line, ""));
+        self.status = match command {
+            "bif" => {
+                let (address, condition) = rest.trim().split_once(' ').unwrap_or((rest, ""));
+                match self.location(address) {
+                    Ok(address) => match self.scripts.compile(condition) {
+                        Ok(script) => {
+                            self.machine.add_conditional_breakpoint(address, script);
+                            format!("breakpoint set at ${address:04X} if {condition}")
+                        }
+                        Err(error) => error.to_string(),
+                    },
+                    Err(message) => message,
+                }
+            }
+            "eval" => match self.scripts.compile(rest) {
+                Ok(script) => match script.eval(&self.machine.cpu) {
+                    Ok(value) => value,
+                    Err(error) => error.to_string(),
+                },
+                Err(error) => error.to_string(),
+            },
+            _ => return false,
+        };
+        true
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A terminal debugger for 6502 programs.
+//!
+//! Shows the disassembly at PC, the registers, the stack, a memory dump and
+//! the breakpoints, and lets the program be stepped or run until it hits a
+//! breakpoint.
+
+mod app;
+// Shared with the `mos6502` binary, which uses more of the helpers.
+#[allow(dead_code)]
+#[path = "../mos6502/args.rs"]
+mod args;
+mod ui;
+
+use std::io;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use mos6502::cpu::CPU;
+use mos6502::dbginfo::DebugInfo;
+use mos6502::instruction::{Cmos6502, Nmos6502, RevisionA, Ricoh2a03};
+use mos6502::memory::{Bus, Memory};
+use mos6502::Variant;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+
+use crate::app::App;
+use crate::args::VariantName;
+
+const USAGE: &str = "\
+Usage: mos6502-tui <image> [options]
+
+Options:
+  --load <addr>        Address to load the image at (default 0)
+  --pc <addr>          Start address (default: the load address)
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+  --debug-info <file>  Read labels and source lines from an ld65 --dbgfile;
+                       breakpoints can then be set on `file:line`
+";
+
+#[derive(Debug, Default)]
+struct Options {
+    image: Option<String>,
+    load: u16,
+    pc: Option<u16>,
+    variant: VariantName,
+    debug_info: DebugInfo,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--load" => options.load = args::address(&args::value(&mut args, &arg)?)?,
+            "--pc" => options.pc = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--variant" => options.variant = VariantName::parse(&args::value(&mut args, &arg)?)?,
+            "--debug-info" => {
+                options.debug_info = args::debug_info(&args::value(&mut args, &arg)?)?
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if options.image.is_none() => options.image = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+    Ok(Some(options))
+}
+
+fn main() -> ExitCode {
+    let options = match parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("mos6502-tui: {message}");
+            return ExitCode::from(args::USAGE_ERROR);
+        }
+    };
+
+    let memory = match load(&options) {
+        Ok(memory) => memory,
+        Err(message) => {
+            eprintln!("mos6502-tui: {message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match options.variant {
+        VariantName::Nmos => debug(memory, Nmos6502, options),
+        VariantName::Cmos => debug(memory, Cmos6502, options),
+        VariantName::Ricoh => debug(memory, Ricoh2a03, options),
+        VariantName::RevisionA => debug(memory, RevisionA, options),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("mos6502-tui: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn load(options: &Options) -> Result<Memory, String> {
+    let path = options.image.as_deref().ok_or("no image given")?;
+    let image = std::fs::read(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+    if usize::from(options.load) + image.len() > 0x10000 {
+        return Err(format!(
+            "image of {} bytes does not fit at ${:04X}",
+            image.len(),
+            options.load
+        ));
+    }
+    let mut memory = Memory::new();
+    memory.set_bytes(options.load, &image);
+    Ok(memory)
+}
+
+fn debug<V: Variant>(memory: Memory, variant: V, options: Options) -> io::Result<()> {
+    let mut cpu = CPU::new(memory, variant);
+    cpu.registers.program_counter = options.pc.unwrap_or(options.load);
+    let mut app = App::new(cpu, options.debug_info);
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn event_loop<V: Variant>(terminal: &mut DefaultTerminal, app: &mut App<V>) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+
+        // Don't wait for input while the program is running.
+        let timeout = if app.running {
+            Duration::ZERO
+        } else {
+            Duration::from_millis(250)
+        };
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+        app.tick();
+    }
+    Ok(())
+}
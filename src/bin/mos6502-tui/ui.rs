@@ -0,0 +1,215 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Drawing the debugger panes.
+
+use mos6502::disasm::Disassembler;
+use mos6502::memory::Bus;
+use mos6502::registers::Status;
+use mos6502::Variant;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use crate::app::App;
+
+const BYTES_PER_ROW: u16 = 16;
+
+pub fn draw<V: Variant>(frame: &mut Frame, app: &App<V>) {
+    let [main, memory, status] = Layout::vertical([
+        Constraint::Min(12),
+        Constraint::Length(10),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [code, side] =
+        Layout::horizontal([Constraint::Min(40), Constraint::Length(28)]).areas(main);
+    let [registers, stack, breakpoints] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Min(4),
+        Constraint::Length(6),
+    ])
+    .areas(side);
+
+    draw_disassembly(frame, code, app);
+    draw_registers(frame, registers, app);
+    draw_stack(frame, stack, app);
+    draw_breakpoints(frame, breakpoints, app);
+    draw_memory(frame, memory, app);
+
+    let status_line = match &app.input {
+        Some(input) => format!(":{input}"),
+        None => app.status.clone(),
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+}
+
+fn draw_disassembly<V: Variant>(frame: &mut Frame, area: Rect, app: &App<V>) {
+    let cpu = &app.machine.cpu;
+    let pc = cpu.registers.program_counter;
+    let rows = usize::from(area.height.saturating_sub(2));
+    // Three bytes per row is always enough.
+    let bytes: Vec<u8> = (0..rows * 3)
+        .map(|i| {
+            cpu.memory
+                .get_byte(pc.wrapping_add(u16::try_from(i).unwrap_or(0)))
+        })
+        .collect();
+
+    let lines: Vec<Line> = Disassembler::<V>::for_variant(&bytes, pc)
+        .take(rows)
+        .map(|line| {
+            let marker = if app.machine.has_breakpoint(line.address) {
+                '*'
+            } else {
+                ' '
+            };
+            let hex: Vec<String> = line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+            let text = format!(
+                "{marker} {:04X}  {:<8}  {}",
+                line.address,
+                hex.join(" "),
+                line.with_labels(app.debug.symbols())
+            );
+            if line.address == pc {
+                Line::styled(text, Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                Line::raw(text)
+            }
+        })
+        .collect();
+    let title = match app.debug.source_at(pc) {
+        Some(source) => format!(" Code: {source} "),
+        None => " Code ".to_owned(),
+    };
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn draw_registers<V: Variant>(frame: &mut Frame, area: Rect, app: &App<V>) {
+    let cpu = &app.machine.cpu;
+    let registers = &cpu.registers;
+    let flags: String = [
+        (Status::PS_NEGATIVE, 'N'),
+        (Status::PS_OVERFLOW, 'V'),
+        (Status::PS_UNUSED, '-'),
+        (Status::PS_BRK, 'B'),
+        (Status::PS_DECIMAL_MODE, 'D'),
+        (Status::PS_DISABLE_INTERRUPTS, 'I'),
+        (Status::PS_ZERO, 'Z'),
+        (Status::PS_CARRY, 'C'),
+    ]
+    .iter()
+    .map(|(flag, name)| {
+        if registers.status.to_byte() & flag.bits() != 0 {
+            *name
+        } else {
+            '.'
+        }
+    })
+    .collect();
+
+    let lines = vec![
+        Line::raw(format!(
+            "A:{:02X}  X:{:02X}  Y:{:02X}",
+            registers.accumulator, registers.index_x, registers.index_y
+        )),
+        Line::raw(format!(
+            "PC:{:04X}  SP:{:02X}",
+            registers.program_counter, registers.stack_pointer.0
+        )),
+        Line::from(vec![Span::raw("P: "), Span::raw(flags)]),
+        Line::raw(format!("cycles: {}", cpu.cycles)),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Registers ")),
+        area,
+    );
+}
+
+fn draw_stack<V: Variant>(frame: &mut Frame, area: Rect, app: &App<V>) {
+    let rows = usize::from(area.height.saturating_sub(2));
+    // The stack grows down from $01FF; show the most recently pushed first.
+    let lines: Vec<Line> = app
+        .calls
+        .borrow()
+        .stack(&app.machine.cpu)
+        .entries
+        .iter()
+        .take(rows)
+        .map(|entry| Line::raw(format!("{:04X}: {}", entry.address, entry.kind)))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Stack ")),
+        area,
+    );
+}
+
+fn draw_breakpoints<V: Variant>(frame: &mut Frame, area: Rect, app: &App<V>) {
+    let lines: Vec<Line> = app
+        .machine
+        .breakpoints()
+        .map(|address| Line::raw(format!("${address:04X}")))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Breakpoints ")),
+        area,
+    );
+}
+
+fn draw_memory<V: Variant>(frame: &mut Frame, area: Rect, app: &App<V>) {
+    let memory = &app.machine.cpu.memory;
+    let rows = area.height.saturating_sub(2);
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let start = app.memory_view.wrapping_add(row * BYTES_PER_ROW);
+            let bytes: Vec<u8> = (0..BYTES_PER_ROW)
+                .map(|i| memory.get_byte(start.wrapping_add(i)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            let text: String = bytes
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() {
+                        char::from(*b)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            Line::raw(format!("{start:04X}  {}  {text}", hex.join(" ")))
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Memory (PgUp/PgDn) ")),
+        area,
+    );
+}
@@ -0,0 +1,134 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Helpers shared by the subcommands for parsing their arguments.
+
+use std::ops::RangeInclusive;
+
+use mos6502::dbginfo::DebugInfo;
+use mos6502::dialect::Dialect;
+
+/// Exit status for malformed command lines.
+pub const USAGE_ERROR: u8 = 2;
+
+/// Returns the value following the option `name`.
+pub fn value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option `{name}` needs a value"))
+}
+
+/// Parses a number written in decimal, or in hexadecimal with a `0x` or `$`
+/// prefix.
+pub fn number(text: &str) -> Result<u64, String> {
+    let parsed = if let Some(hex) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        u64::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+    parsed.map_err(|_| format!("`{text}` is not a number"))
+}
+
+/// Parses a 16-bit address, written like [`number`].
+pub fn address(text: &str) -> Result<u16, String> {
+    u16::try_from(number(text)?).map_err(|_| format!("`{text}` is not a 16-bit address"))
+}
+
+/// Parses an inclusive address range written as `<first>-<last>`, each
+/// written like [`number`].
+pub fn address_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (first, last) = text
+        .split_once('-')
+        .ok_or_else(|| format!("`{text}` is not an address range like 0xC000-0xCFFF"))?;
+    let (first, last) = (address(first)?, address(last)?);
+    if first > last {
+        return Err(format!("address range `{text}` is empty"));
+    }
+    Ok(first..=last)
+}
+
+/// The CPU variants selectable with `--variant`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum VariantName {
+    #[default]
+    Nmos,
+    Cmos,
+    Ricoh,
+    RevisionA,
+}
+
+impl VariantName {
+    pub const HELP: &'static str = "nmos (default), cmos, ricoh or reva";
+
+    pub fn parse(text: &str) -> Result<VariantName, String> {
+        match text {
+            "nmos" | "6502" => Ok(VariantName::Nmos),
+            "cmos" | "65c02" => Ok(VariantName::Cmos),
+            "ricoh" | "2a03" => Ok(VariantName::Ricoh),
+            "reva" => Ok(VariantName::RevisionA),
+            _ => Err(format!("unknown variant `{text}`; expected {}", Self::HELP)),
+        }
+    }
+}
+
+/// Parses the name of an assembler dialect.
+pub fn dialect(text: &str) -> Result<Dialect, String> {
+    match text {
+        "generic" => Ok(Dialect::Generic),
+        "ca65" => Ok(Dialect::Ca65),
+        "acme" => Ok(Dialect::Acme),
+        "64tass" => Ok(Dialect::Tass64),
+        _ => Err(format!(
+            "unknown dialect `{text}`; expected generic (default), ca65, acme or 64tass"
+        )),
+    }
+}
+
+/// Reads an ld65 debug information file.
+pub fn debug_info(path: &str) -> Result<DebugInfo, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+    DebugInfo::parse(&text).map_err(|err| format!("{path}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_in_all_bases() {
+        assert_eq!(number("49152"), Ok(49152));
+        assert_eq!(number("0xC000"), Ok(0xC000));
+        assert_eq!(number("$c000"), Ok(0xC000));
+        assert!(number("C000").is_err());
+        assert!(address("0x10000").is_err());
+        assert_eq!(address_range("$C000-0xCFFF"), Ok(0xC000..=0xCFFF));
+        assert!(address_range("0xD000-0xC000").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 asm`: assemble a source file into a binary image.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use mos6502::asm::{assemble_with, AsmError, Assembly};
+use mos6502::dialect::Dialect;
+use mos6502::instruction::{Cmos6502, Nmos6502, RevisionA, Ricoh2a03};
+
+use crate::args::{self, VariantName};
+
+const HELP: &str = "\
+Usage: mos6502 asm <source> [options]
+
+Assembles a source file into a raw binary image. The image starts at the
+lowest address the program writes to.
+
+Options:
+  -o, --output <file>  Output file (default: the source with a .bin extension)
+  --listing <file>     Also write a listing with addresses, bytes and cycles
+  --symbols <file>     Also write the labels as a VICE label file, as read by
+                       `mos6502 dasm --labels`
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+  --dialect <name>     Source syntax: generic (default), ca65, acme or 64tass
+";
+
+#[derive(Debug, Default)]
+struct Options {
+    source: Option<String>,
+    output: Option<String>,
+    listing: Option<String>,
+    symbols: Option<String>,
+    variant: VariantName,
+    dialect: Dialect,
+}
+
+pub fn main(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "-o" | "--output" => options.output = Some(args::value(&mut args, &arg)?),
+            "--listing" => options.listing = Some(args::value(&mut args, &arg)?),
+            "--symbols" => options.symbols = Some(args::value(&mut args, &arg)?),
+            "--variant" => options.variant = VariantName::parse(&args::value(&mut args, &arg)?)?,
+            "--dialect" => options.dialect = args::dialect(&args::value(&mut args, &arg)?)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if options.source.is_none() => options.source = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let path = options.source.as_deref().ok_or("no source file given")?;
+    let source =
+        std::fs::read_to_string(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+
+    let assembly = match assemble(&source, options.variant, options.dialect) {
+        Ok(assembly) => assembly,
+        Err(err) => {
+            eprintln!("{path}:{}: {}", err.line, err.message);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    let output = options.output.unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension("bin")
+            .to_string_lossy()
+            .into_owned()
+    });
+    std::fs::write(&output, &assembly.bytes)
+        .map_err(|err| format!("cannot write {output}: {err}"))?;
+
+    if let Some(listing) = options.listing {
+        let mut text = String::new();
+        assembly
+            .write_listing(&mut text)
+            .expect("writing to a String cannot fail");
+        std::fs::write(&listing, text).map_err(|err| format!("cannot write {listing}: {err}"))?;
+    }
+
+    if let Some(symbols) = options.symbols {
+        let mut text = String::new();
+        assembly
+            .symbols
+            .write(&mut text)
+            .expect("writing to a String cannot fail");
+        std::fs::write(&symbols, text).map_err(|err| format!("cannot write {symbols}: {err}"))?;
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn assemble(source: &str, variant: VariantName, dialect: Dialect) -> Result<Assembly, AsmError> {
+    match variant {
+        VariantName::Nmos => assemble_with::<Nmos6502>(source, dialect),
+        VariantName::Cmos => assemble_with::<Cmos6502>(source, dialect),
+        VariantName::Ricoh => assemble_with::<Ricoh2a03>(source, dialect),
+        VariantName::RevisionA => assemble_with::<RevisionA>(source, dialect),
+    }
+}
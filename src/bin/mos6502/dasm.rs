@@ -0,0 +1,140 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 dasm`: print a disassembly listing of a binary image.
+
+use std::fmt::Write as _;
+use std::process::ExitCode;
+
+use mos6502::dialect::Dialect;
+use mos6502::disasm::{Disassembler, Line};
+use mos6502::instruction::{Cmos6502, Nmos6502, RevisionA, Ricoh2a03};
+use mos6502::symbols::SymbolTable;
+use mos6502::Variant;
+
+use crate::args::{self, VariantName};
+
+const HELP: &str = "\
+Usage: mos6502 dasm <image> [options]
+
+Prints a disassembly listing of a raw binary image to stdout.
+
+Options:
+  --org <addr>         Address the image is loaded at (default 0)
+  --labels <file>      Name addresses using a symbol file: VICE labels
+                       (`al C:C000 .reset`) or assignments (`reset = $C000`)
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+  --dialect <name>     Output syntax: generic (default), ca65, acme or 64tass
+";
+
+#[derive(Debug, Default)]
+struct Options {
+    image: Option<String>,
+    org: u16,
+    labels: Option<String>,
+    variant: VariantName,
+    dialect: Dialect,
+}
+
+pub fn main(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--org" => options.org = args::address(&args::value(&mut args, &arg)?)?,
+            "--labels" => options.labels = Some(args::value(&mut args, &arg)?),
+            "--variant" => options.variant = VariantName::parse(&args::value(&mut args, &arg)?)?,
+            "--dialect" => options.dialect = args::dialect(&args::value(&mut args, &arg)?)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if options.image.is_none() => options.image = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let path = options.image.as_deref().ok_or("no image given")?;
+    let image = std::fs::read(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+    let symbols = match &options.labels {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("cannot read {path}: {err}"))?;
+            SymbolTable::parse(&text).map_err(|err| format!("{path}: {err}"))?
+        }
+        None => SymbolTable::new(),
+    };
+
+    let (org, dialect) = (options.org, options.dialect);
+    let listing = match options.variant {
+        VariantName::Nmos => listing::<Nmos6502>(&image, org, &symbols, dialect),
+        VariantName::Cmos => listing::<Cmos6502>(&image, org, &symbols, dialect),
+        VariantName::Ricoh => listing::<Ricoh2a03>(&image, org, &symbols, dialect),
+        VariantName::RevisionA => listing::<RevisionA>(&image, org, &symbols, dialect),
+    };
+    print!("{listing}");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Formats `image` as a listing, with a label line before every address
+/// that has a symbol.
+fn listing<V: Variant>(image: &[u8], org: u16, symbols: &SymbolTable, dialect: Dialect) -> String {
+    let mut out = String::new();
+    for line in Disassembler::<V>::for_variant(image, org) {
+        if let Some(name) = symbols.name_of(line.address) {
+            let _ = writeln!(out, "{name}:");
+        }
+        let _ = writeln!(out, "{}", format_line(&line, symbols, dialect));
+    }
+    out
+}
+
+/// Formats one line as `ADDR  BYTES     INSTRUCTION`.
+pub fn format_line(line: &Line, symbols: &SymbolTable, dialect: Dialect) -> String {
+    let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{b:02X}")).collect();
+    format!(
+        "{:04X}  {:<8}  {}",
+        line.address,
+        bytes.join(" "),
+        line.with_labels(symbols).dialect(dialect)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_with_labels() {
+        let symbols = SymbolTable::parse("loop = $C002").unwrap();
+        let code = [0xa2, 0x08, 0xca, 0xd0, 0xfd];
+        assert_eq!(
+            listing::<Nmos6502>(&code, 0xc000, &symbols, Dialect::Generic),
+            "C000  A2 08     LDX #$08\nloop:\nC002  CA        DEX\nC003  D0 FD     BNE loop\n"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 golden`: generate single-instruction test vectors.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use mos6502::golden::{self, Generator};
+use mos6502::instruction::{Cmos6502, Nmos6502, RevisionA, Ricoh2a03};
+use mos6502::Variant;
+
+use crate::args::{self, VariantName};
+
+const HELP: &str = "\
+Usage: mos6502 golden <dir> [options]
+
+Executes each opcode from random initial states and writes the states
+before and after, with the bus accesses, to <dir>/<opcode>.json in the
+SingleStepTests JSON schema. Opcodes the variant doesn't implement are
+skipped.
+
+Options:
+  --opcode <op>        Only generate tests for this opcode
+  --count <n>          Tests per opcode (default 100)
+  --seed <n>           Seed for the random states (default 0)
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+";
+
+#[derive(Debug)]
+struct Options {
+    dir: Option<String>,
+    opcode: Option<u8>,
+    count: usize,
+    seed: u64,
+    variant: VariantName,
+}
+
+pub fn main(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut options = Options {
+        dir: None,
+        opcode: None,
+        count: 100,
+        seed: 0,
+        variant: VariantName::default(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--opcode" => {
+                let text = args::value(&mut args, &arg)?;
+                let opcode = args::number(&text)?;
+                options.opcode =
+                    Some(u8::try_from(opcode).map_err(|_| format!("`{text}` is not an opcode"))?);
+            }
+            "--count" => {
+                let text = args::value(&mut args, &arg)?;
+                options.count = usize::try_from(args::number(&text)?)
+                    .map_err(|_| format!("`{text}` is too many tests"))?;
+            }
+            "--seed" => options.seed = args::number(&args::value(&mut args, &arg)?)?,
+            "--variant" => options.variant = VariantName::parse(&args::value(&mut args, &arg)?)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if options.dir.is_none() => options.dir = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    match options.variant {
+        VariantName::Nmos => generate(&options, Nmos6502),
+        VariantName::Cmos => generate(&options, Cmos6502),
+        VariantName::Ricoh => generate(&options, Ricoh2a03),
+        VariantName::RevisionA => generate(&options, RevisionA),
+    }
+}
+
+fn generate<V: Variant + Copy>(options: &Options, variant: V) -> Result<ExitCode, String> {
+    let dir = Path::new(options.dir.as_deref().ok_or("no output directory given")?);
+    std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+
+    let mut generator = Generator::new(variant, options.seed);
+    let opcodes = options.opcode.map_or(0..=u8::MAX, |opcode| opcode..=opcode);
+    let mut files = 0;
+    for opcode in opcodes {
+        let tests = generator.generate_many(opcode, options.count);
+        if tests.is_empty() {
+            continue;
+        }
+        let path = dir.join(format!("{opcode:02x}.json"));
+        std::fs::write(&path, golden::to_json_array(&tests))
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        files += 1;
+    }
+    eprintln!("wrote {files} files to {}", dir.display());
+    Ok(ExitCode::SUCCESS)
+}
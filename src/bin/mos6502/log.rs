@@ -0,0 +1,78 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 log`: convert a binary execution log to text or JSON.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use mos6502::execlog::LogReader;
+
+const HELP: &str = "\
+Usage: mos6502 log <file> [--json]
+
+Prints the records of an execution log written by `mos6502 run --exec-log`,
+one per line, as text or as JSON objects.
+";
+
+pub fn main(args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut path = None;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let path = path.ok_or("no log file given")?;
+    let error = |err: std::io::Error| format!("{path}: {err}");
+    let reader =
+        LogReader::new(BufReader::new(File::open(&path).map_err(error)?)).map_err(error)?;
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for record in reader {
+        let record = record.map_err(error)?;
+        let written = if json {
+            writeln!(out, "{}", record.to_json())
+        } else {
+            writeln!(out, "{record}")
+        };
+        if written.is_err() {
+            // Most likely a closed pipe, e.g. when piping into `head`.
+            break;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
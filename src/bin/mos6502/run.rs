@@ -0,0 +1,531 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 run`: load a binary image and execute it until it stops.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::process::ExitCode;
+
+use mos6502::console::{ConsoleInput, KeyEncoding, Terminal};
+use mos6502::cpu::CPU;
+use mos6502::dbginfo::DebugInfo;
+use mos6502::dialect::Dialect;
+use mos6502::disasm::Disassembler;
+use mos6502::execlog::{LogWriter, Record};
+use mos6502::explain;
+#[cfg(feature = "gdbstub")]
+use mos6502::gdbstub::{Disconnect, GdbStub};
+use mos6502::instruction::{Cmos6502, Instruction, Nmos6502, RevisionA, Ricoh2a03};
+#[cfg(feature = "gdbstub")]
+use mos6502::machine::Machine;
+use mos6502::memory::{Bus, Memory};
+use mos6502::pacing::Pacer;
+use mos6502::profile::{Hotspot, Metric, Profile};
+use mos6502::semihost::Semihost;
+use mos6502::tracefilter::TraceFilter;
+use mos6502::tracelog::{TraceFormat, TraceLine};
+use mos6502::Variant;
+
+use crate::args::{self, VariantName};
+use crate::dasm::format_line;
+
+const HELP: &str = "\
+Usage: mos6502 run <image> [options]
+
+Loads a raw binary image into memory and executes it until the program
+stops. Images with a sim65 header are loaded at the address it specifies.
+
+Options:
+  --load <addr>        Address to load the image at (default 0)
+  --pc <addr>          Start address (default: the load address)
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+  --max-cycles <n>     Stop after executing <n> cycles
+  --clock <hz>         Run at the speed of a <hz> clock rather than flat out;
+                       also accepts apple2, c64pal, nes or nespal
+  --success <addr>     Address of the trap loop that signals success
+  --trace              Print every instruction to stderr before it executes
+  --trace-format <fmt> Trace in another emulator's format, to diff against
+                       its logs: nintendulator (as in nestest.log) or
+                       compact (the same without memory contents); implies
+                       --trace
+  --explain            Describe what every instruction did to stderr, after
+                       it executes
+  --debug-info <file>  Read labels and source lines from an ld65 --dbgfile,
+                       to show in the trace and the stop message
+  --trace-range <a-b>  Only trace or explain instructions in this range, e.g.
+                       0xC000-0xCFFF; may be repeated
+  --trace-exclude <a-b>
+                       Don't trace or explain instructions in this range;
+                       may be repeated
+  --exec-log <file>    Record every instruction to a binary execution log,
+                       readable with `mos6502 log`
+  --console <addr>     Map a terminal on stdin/stdout at <addr>, using the
+                       Apple-1 PIA layout (KBD, KBDCR, DSP, DSPCR)
+  --keys <encoding>    Console key encoding: ascii (default) or apple1
+  --semihost <addr>    Map the semihosting registers at <addr>, giving the
+                       program stdin, stdout, the time and an exit call
+  --files <dir>        Let a semihosted program open files in <dir>
+  --profile            Print execution statistics to stderr when done
+  --gdb <addr>         Wait for GDB to connect on <addr>, e.g.
+                       localhost:1234, and run the program under its
+                       control until it detaches; needs the gdbstub feature
+  --quiet              Don't print why execution stopped
+
+Exit status:
+  The value of A when the program executes BRK, calls the sim65 exit hook
+  (JSR $FFF9) or enters a trap loop (a jump or branch to itself), or the
+  code stored to the semihosting EXIT register. With
+  --success, a trap loop exits with 0 at that address and 1 elsewhere.
+  124 if the cycle limit is reached, 125 on an undecodable opcode.
+";
+
+/// Address of the sim65 paravirtualization `exit` hook. Programs end by
+/// calling it with the exit code in A.
+const SIM65_EXIT: u16 = 0xFFF9;
+
+const SIM65_MAGIC: &[u8] = b"sim65";
+const SIM65_HEADER_LEN: usize = 12;
+
+const EXIT_CYCLE_LIMIT: u8 = 124;
+const EXIT_ILLEGAL_OPCODE: u8 = 125;
+
+#[derive(Debug, Default)]
+struct Options {
+    image: Option<String>,
+    load: Option<u16>,
+    pc: Option<u16>,
+    variant: VariantName,
+    max_cycles: Option<u64>,
+    clock: Option<u64>,
+    success: Option<u16>,
+    trace: bool,
+    trace_format: Option<TraceFormat>,
+    explain: bool,
+    trace_filter: TraceFilter,
+    debug_info: DebugInfo,
+    exec_log: Option<String>,
+    console: Option<u16>,
+    keys: KeyEncoding,
+    semihost: Option<u16>,
+    files: Option<String>,
+    profile: bool,
+    quiet: bool,
+    #[cfg(feature = "gdbstub")]
+    gdb: Option<String>,
+}
+
+type ExecLog = LogWriter<BufWriter<File>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Stop {
+    Brk(u16),
+    Trap(u16),
+    Exit,
+    HostExit(u8),
+    CycleLimit,
+    IllegalOpcode(u16, u8),
+}
+
+pub fn main(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--load" => options.load = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--pc" => options.pc = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--variant" => options.variant = VariantName::parse(&args::value(&mut args, &arg)?)?,
+            "--max-cycles" => {
+                options.max_cycles = Some(args::number(&args::value(&mut args, &arg)?)?);
+            }
+            "--clock" => options.clock = Some(clock(&args::value(&mut args, &arg)?)?),
+            "--success" => options.success = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--trace" => options.trace = true,
+            "--trace-format" => {
+                options.trace = true;
+                options.trace_format = Some(trace_format(&args::value(&mut args, &arg)?)?);
+            }
+            "--explain" => options.explain = true,
+            "--trace-range" => options
+                .trace_filter
+                .include(args::address_range(&args::value(&mut args, &arg)?)?),
+            "--trace-exclude" => options
+                .trace_filter
+                .exclude(args::address_range(&args::value(&mut args, &arg)?)?),
+            "--debug-info" => {
+                options.debug_info = args::debug_info(&args::value(&mut args, &arg)?)?
+            }
+            "--exec-log" => options.exec_log = Some(args::value(&mut args, &arg)?),
+            "--console" => options.console = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--keys" => options.keys = key_encoding(&args::value(&mut args, &arg)?)?,
+            "--semihost" => {
+                options.semihost = Some(args::address(&args::value(&mut args, &arg)?)?);
+            }
+            "--files" => options.files = Some(args::value(&mut args, &arg)?),
+            "--profile" => options.profile = true,
+            #[cfg(feature = "gdbstub")]
+            "--gdb" => options.gdb = Some(args::value(&mut args, &arg)?),
+            "--quiet" => options.quiet = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ if options.image.is_none() => options.image = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let path = options.image.as_deref().ok_or("no image given")?;
+    let image = std::fs::read(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+    let (memory, start) = load(&image, &options)?;
+
+    let mut log = match &options.exec_log {
+        Some(path) => Some(
+            File::create(path)
+                .and_then(|file| LogWriter::new(BufWriter::new(file)))
+                .map_err(|err| format!("cannot create {path}: {err}"))?,
+        ),
+        None => None,
+    };
+
+    if options.files.is_some() && options.semihost.is_none() {
+        return Err("--files needs --semihost".to_owned());
+    }
+    let code = match (options.console, options.semihost) {
+        (Some(_), Some(_)) => Err("--console and --semihost can't be combined".to_owned()),
+        (Some(base), None) => {
+            let input = ConsoleInput::stdin(options.keys);
+            let bus = Terminal::new(memory, base, input, io::stdout());
+            execute_variant(bus, start, &options, &mut log, |_| None)
+        }
+        (None, Some(base)) => {
+            let mut bus = Semihost::new(memory, base, io::stdin(), io::stdout());
+            if let Some(dir) = &options.files {
+                bus.allow_files(dir);
+            }
+            execute_variant(bus, start, &options, &mut log, Semihost::exit_code)
+        }
+        (None, None) => execute_variant(memory, start, &options, &mut log, |_| None),
+    };
+
+    if let (Some(log), Some(path)) = (&mut log, &options.exec_log) {
+        log.flush()
+            .map_err(|err| format!("cannot write {path}: {err}"))?;
+    }
+    code
+}
+
+/// Loads `image` into a fresh memory, returning it and the start address.
+fn load(image: &[u8], options: &Options) -> Result<(Memory, u16), String> {
+    let (mut load, mut start, data) = if image.starts_with(SIM65_MAGIC) {
+        if image.len() < SIM65_HEADER_LEN {
+            return Err("truncated sim65 header".to_owned());
+        }
+        let load = u16::from_le_bytes([image[8], image[9]]);
+        let reset = u16::from_le_bytes([image[10], image[11]]);
+        (load, Some(reset), &image[SIM65_HEADER_LEN..])
+    } else {
+        (0, None, image)
+    };
+    if let Some(address) = options.load {
+        load = address;
+    }
+    if let Some(address) = options.pc {
+        start = Some(address);
+    }
+
+    if usize::from(load) + data.len() > 0x10000 {
+        return Err(format!(
+            "image of {} bytes does not fit at ${load:04X}",
+            data.len()
+        ));
+    }
+
+    let mut memory = Memory::new();
+    memory.set_bytes(load, data);
+    Ok((memory, start.unwrap_or(load)))
+}
+
+fn clock(text: &str) -> Result<u64, String> {
+    let hz = match text {
+        "apple2" => Pacer::APPLE_II,
+        "c64pal" => Pacer::C64_PAL,
+        "nes" => Pacer::NES_NTSC,
+        "nespal" => Pacer::NES_PAL,
+        _ => args::number(text)?,
+    };
+    if hz == 0 {
+        return Err("the clock frequency must not be zero".to_owned());
+    }
+    Ok(hz)
+}
+
+fn key_encoding(name: &str) -> Result<KeyEncoding, String> {
+    match name {
+        "ascii" => Ok(KeyEncoding::Ascii),
+        "apple1" => Ok(KeyEncoding::Apple1),
+        _ => Err(format!(
+            "unknown key encoding `{name}` (expected ascii or apple1)"
+        )),
+    }
+}
+
+fn trace_format(name: &str) -> Result<TraceFormat, String> {
+    match name {
+        "nintendulator" => Ok(TraceFormat::Nintendulator),
+        "compact" => Ok(TraceFormat::Compact),
+        _ => Err(format!(
+            "unknown trace format `{name}` (expected nintendulator or compact)"
+        )),
+    }
+}
+
+/// `exit` returns the exit code once the program has asked to exit through
+/// the bus.
+fn execute_variant<B: Bus>(
+    bus: B,
+    start: u16,
+    options: &Options,
+    log: &mut Option<ExecLog>,
+    exit: fn(&B) -> Option<u8>,
+) -> Result<ExitCode, String> {
+    match options.variant {
+        VariantName::Nmos => execute(CPU::new(bus, Nmos6502), start, options, log, exit),
+        VariantName::Cmos => execute(CPU::new(bus, Cmos6502), start, options, log, exit),
+        VariantName::Ricoh => execute(CPU::new(bus, Ricoh2a03), start, options, log, exit),
+        VariantName::RevisionA => execute(CPU::new(bus, RevisionA), start, options, log, exit),
+    }
+}
+
+fn execute<B: Bus, V: Variant>(
+    mut cpu: CPU<B, V>,
+    start: u16,
+    options: &Options,
+    log: &mut Option<ExecLog>,
+    exit: fn(&B) -> Option<u8>,
+) -> Result<ExitCode, String> {
+    cpu.registers.program_counter = start;
+    #[cfg(feature = "gdbstub")]
+    if let Some(address) = &options.gdb {
+        return debug(cpu, address);
+    }
+    let mut profile = options.profile.then(Profile::new);
+    let mut pacer = options.clock.map(Pacer::new);
+
+    let stop = loop {
+        let pc = cpu.registers.program_counter;
+        if pc == SIM65_EXIT {
+            break Stop::Exit;
+        }
+        if let Some(code) = exit(&cpu.memory) {
+            break Stop::HostExit(code);
+        }
+        if options.max_cycles.is_some_and(|max| cpu.cycles >= max) {
+            break Stop::CycleLimit;
+        }
+        if options.trace && options.trace_filter.matches(pc) {
+            match options.trace_format {
+                Some(format) => eprintln!("{}", TraceLine::new(&cpu, format)),
+                None => eprintln!("{}", trace_line(&cpu, &options.debug_info)),
+            }
+        }
+        if let Some(log) = log {
+            log.write(&Record::instruction(&cpu))
+                .map_err(|err| format!("cannot write execution log: {err}"))?;
+        }
+
+        if let Some(pacer) = &mut pacer {
+            pacer.pace(cpu.cycles);
+        }
+        let opcode = cpu.memory.get_byte(pc);
+        let cycles = cpu.cycles;
+        let decoded = if options.explain && options.trace_filter.matches(pc) {
+            explain::step(&mut cpu).map(|(decoded, text)| {
+                eprintln!("{text}");
+                decoded
+            })
+        } else {
+            cpu.single_step()
+        };
+        if let (Some(profile), Some((instruction, _))) = (&mut profile, decoded) {
+            let next_pc = cpu.registers.program_counter;
+            profile.record(pc, opcode, instruction, cpu.cycles - cycles, next_pc);
+        }
+        match decoded {
+            None => break Stop::IllegalOpcode(pc, opcode),
+            Some((Instruction::BRK | Instruction::BRKcld, _)) => break Stop::Brk(pc),
+            Some(_) if cpu.registers.program_counter == pc => break Stop::Trap(pc),
+            Some(_) => {}
+        }
+    };
+
+    let a = cpu.registers.accumulator;
+    let (status, reason) = match stop {
+        Stop::Brk(pc) => (a, format!("BRK at ${pc:04X}")),
+        Stop::Exit => (a, "exit hook called".to_owned()),
+        Stop::HostExit(code) => (code, "semihosting exit".to_owned()),
+        Stop::Trap(pc) => match options.success {
+            Some(success) => (u8::from(pc != success), format!("trap loop at ${pc:04X}")),
+            None => (a, format!("trap loop at ${pc:04X}")),
+        },
+        Stop::CycleLimit => (EXIT_CYCLE_LIMIT, "cycle limit reached".to_owned()),
+        Stop::IllegalOpcode(pc, opcode) => (
+            EXIT_ILLEGAL_OPCODE,
+            format!("undecodable opcode ${opcode:02X} at ${pc:04X}"),
+        ),
+    };
+
+    if !options.quiet {
+        let pc = match stop {
+            Stop::Brk(pc) | Stop::Trap(pc) | Stop::IllegalOpcode(pc, _) => Some(pc),
+            _ => None,
+        };
+        let source = match pc.and_then(|pc| options.debug_info.source_at(pc)) {
+            Some(line) => format!(" in {line}"),
+            None => String::new(),
+        };
+        eprintln!(
+            "stopped: {reason}{source} after {} cycles (exit status {status})",
+            cpu.cycles
+        );
+    }
+    if let Some(profile) = &profile {
+        eprint!("{}", profile_report::<V>(profile));
+    }
+    Ok(ExitCode::from(status))
+}
+
+/// Hands the program to a debugger connecting on `address`.
+#[cfg(feature = "gdbstub")]
+fn debug<B: Bus, V: Variant>(cpu: CPU<B, V>, address: &str) -> Result<ExitCode, String> {
+    let listener = std::net::TcpListener::bind(address)
+        .map_err(|err| format!("cannot listen on {address}: {err}"))?;
+    eprintln!("waiting for GDB on {address}");
+    let (stream, _) = listener
+        .accept()
+        .map_err(|err| format!("cannot accept a connection: {err}"))?;
+    let mut machine = Machine::new(cpu);
+    let disconnect = GdbStub::new(stream)
+        .serve(&mut machine)
+        .map_err(|err| format!("lost the debugger: {err}"))?;
+    let how = match disconnect {
+        Disconnect::Detached => "detached",
+        Disconnect::Killed => "killed the program",
+        Disconnect::Closed => "closed the connection",
+    };
+    eprintln!("debugger {how}");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Number of entries in each hotspot table of the profile report.
+const HOTSPOTS: usize = 10;
+
+/// Formats the instruction mix and hotspots recorded in `profile`.
+fn profile_report<V: Variant>(profile: &Profile) -> String {
+    let total = profile.instructions();
+    let mut report = format!("instruction mix ({total} instructions):\n");
+    for (mnemonic, count) in profile.instruction_mix::<V>() {
+        report += &format!(
+            "  {mnemonic}  {count:>12}  {:5.1}%\n",
+            percent(count, total)
+        );
+    }
+
+    let cycles = profile.cycles();
+    let tables = [
+        (
+            "hottest addresses by cycles",
+            profile.hottest_addresses(HOTSPOTS, Metric::Cycles),
+        ),
+        (
+            "hottest addresses by count",
+            profile.hottest_addresses(HOTSPOTS, Metric::Count),
+        ),
+        (
+            "hottest routines by cycles",
+            profile.hottest_routines(HOTSPOTS, Metric::Cycles),
+        ),
+        (
+            "hottest routines by calls",
+            profile.hottest_routines(HOTSPOTS, Metric::Count),
+        ),
+    ];
+    for (title, spots) in tables {
+        if spots.is_empty() {
+            continue;
+        }
+        report += &format!("{title}:\n");
+        for Hotspot {
+            address,
+            count,
+            cycles: spent,
+        } in spots
+        {
+            report += &format!(
+                "  ${address:04X}  {count:>12}  {spent:>14} cycles  {:5.1}%\n",
+                percent(spent, cycles)
+            );
+        }
+    }
+    report
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+/// Formats the instruction at PC and the register state before it executes,
+/// followed by the source line of the instruction if `debug` knows it.
+fn trace_line<B: Bus, V: Variant>(cpu: &CPU<B, V>, debug: &DebugInfo) -> String {
+    let pc = cpu.registers.program_counter;
+    // Fetch enough bytes for the longest instruction; the disassembler
+    // only consumes what the opcode needs.
+    let bytes = [0, 1, 2].map(|i| cpu.memory.get_byte(pc.wrapping_add(i)));
+    let line = Disassembler::<V>::for_variant(&bytes, pc)
+        .next()
+        .expect("three bytes always hold an instruction");
+
+    let mut text = format!(
+        "{:<36}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+        format_line(&line, debug.symbols(), Dialect::Generic),
+        cpu.registers.accumulator,
+        cpu.registers.index_x,
+        cpu.registers.index_y,
+        cpu.registers.status.to_byte(),
+        cpu.registers.stack_pointer.0,
+        cpu.cycles,
+    );
+    if let Some(source) = debug.source_at(pc) {
+        text += &format!("  {source}");
+    }
+    text
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! `mos6502 verify`: run a conformance test image and report the result.
+
+use std::process::ExitCode;
+
+use mos6502::instruction::{Cmos6502, Nmos6502, RevisionA, Ricoh2a03};
+use mos6502::testsuite::{DecimalTest, FunctionalTest, ImageError, Nestest, Report};
+use mos6502::Variant;
+
+use crate::args::{self, VariantName};
+
+const HELP: &str = "\
+Usage: mos6502 verify --suite <suite> --rom <file> [options]
+
+Runs a well-known test image and prints a pass/fail report.
+
+Suites:
+  klaus      Klaus Dormann's 6502_functional_test.bin
+  decimal    Klaus Dormann's 6502_decimal_test.bin
+  nestest    Kevin Horton's nestest.nes (runs on the ricoh variant by default)
+
+Options:
+  --suite <suite>      The test suite the image belongs to
+  --rom <file>         The test image
+  --variant <name>     CPU variant: nmos (default), cmos, ricoh or reva
+  --success <addr>     klaus: address of the success trap (default $3469)
+  --max-cycles <n>     Give up after <n> cycles
+
+The exit status is 0 if the test passed and 1 if it failed.
+";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Suite {
+    Klaus,
+    Decimal,
+    Nestest,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    suite: Option<Suite>,
+    rom: Option<String>,
+    variant: Option<VariantName>,
+    success: Option<u16>,
+    max_cycles: Option<u64>,
+}
+
+pub fn main(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{HELP}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--suite" => {
+                options.suite = Some(match args::value(&mut args, &arg)?.as_str() {
+                    "klaus" => Suite::Klaus,
+                    "decimal" => Suite::Decimal,
+                    "nestest" => Suite::Nestest,
+                    other => {
+                        return Err(format!(
+                            "unknown suite `{other}`; expected klaus, decimal or nestest"
+                        ))
+                    }
+                });
+            }
+            "--rom" => options.rom = Some(args::value(&mut args, &arg)?),
+            "--variant" => {
+                options.variant = Some(VariantName::parse(&args::value(&mut args, &arg)?)?);
+            }
+            "--success" => options.success = Some(args::address(&args::value(&mut args, &arg)?)?),
+            "--max-cycles" => {
+                options.max_cycles = Some(args::number(&args::value(&mut args, &arg)?)?);
+            }
+            _ => return Err(format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let suite = options.suite.ok_or("no suite given; use --suite")?;
+    let path = options
+        .rom
+        .as_deref()
+        .ok_or("no test image given; use --rom")?;
+    let image = std::fs::read(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+
+    let default_variant = match suite {
+        Suite::Nestest => VariantName::Ricoh,
+        Suite::Klaus | Suite::Decimal => VariantName::Nmos,
+    };
+    let report = match options.variant.unwrap_or(default_variant) {
+        VariantName::Nmos => verify(suite, &options, &image, Nmos6502),
+        VariantName::Cmos => verify(suite, &options, &image, Cmos6502),
+        VariantName::Ricoh => verify(suite, &options, &image, Ricoh2a03),
+        VariantName::RevisionA => verify(suite, &options, &image, RevisionA),
+    }
+    .map_err(|err| format!("{path}: {err}"))?;
+
+    print!("{report}");
+    Ok(if report.passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn verify<V: Variant>(
+    suite: Suite,
+    options: &Options,
+    image: &[u8],
+    variant: V,
+) -> Result<Report, ImageError> {
+    match suite {
+        Suite::Klaus => {
+            let mut test = FunctionalTest::default();
+            test.success = options.success.unwrap_or(test.success);
+            test.max_cycles = options.max_cycles.unwrap_or(test.max_cycles);
+            test.run(image, variant)
+        }
+        Suite::Decimal => {
+            let mut test = DecimalTest::default();
+            test.max_cycles = options.max_cycles.unwrap_or(test.max_cycles);
+            test.run(image, variant)
+        }
+        Suite::Nestest => {
+            let mut test = Nestest::default();
+            test.max_cycles = options.max_cycles.unwrap_or(test.max_cycles);
+            test.run(image, variant)
+        }
+    }
+}
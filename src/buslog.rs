@@ -0,0 +1,321 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Logging of individual bus accesses.
+//!
+//! [`BusLogger`] wraps any [`Bus`] and reports every read and write that
+//! passes its [`BusFilter`], tagged with the name of the region it falls in
+//! (`"ram"`, `"rom"`, `"via"`...). Accesses are logged at `trace` level
+//! under the `mos6502::bus` target and can also be recorded for inspection
+//! with [`BusLogger::take_events`].
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::{Range, RangeInclusive};
+
+pub use crate::memory::Access;
+use crate::memory::{Bus, BusError};
+
+/// A single bus access.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusEvent {
+    pub access: Access,
+    pub address: u16,
+    pub value: u8,
+    /// Name of the region containing the address, if any.
+    pub region: Option<&'static str>,
+}
+
+impl fmt::Display for BusEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => 'R',
+            Access::Write => 'W',
+        };
+        write!(f, "{access} ${:04X} = ${:02X}", self.address, self.value)?;
+        if let Some(region) = self.region {
+            write!(f, " [{region}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Selects the accesses a [`BusLogger`] reports.
+///
+/// An access is reported if its direction is enabled, and it falls in one
+/// of `ranges` and in one of `regions`. An empty list matches everything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusFilter {
+    pub reads: bool,
+    pub writes: bool,
+    pub ranges: Vec<RangeInclusive<u16>>,
+    pub regions: Vec<&'static str>,
+}
+
+impl Default for BusFilter {
+    fn default() -> Self {
+        BusFilter {
+            reads: true,
+            writes: true,
+            ranges: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+}
+
+impl BusFilter {
+    #[must_use]
+    pub fn matches(&self, event: &BusEvent) -> bool {
+        let direction = match event.access {
+            Access::Read => self.reads,
+            Access::Write => self.writes,
+        };
+        direction
+            && (self.ranges.is_empty()
+                || self
+                    .ranges
+                    .iter()
+                    .any(|range| range.contains(&event.address)))
+            && (self.regions.is_empty()
+                || event
+                    .region
+                    .is_some_and(|region| self.regions.contains(&region)))
+    }
+}
+
+/// A bus that reports the accesses made through it.
+#[derive(Debug)]
+pub struct BusLogger<B: Bus> {
+    inner: B,
+    regions: Vec<(RangeInclusive<u16>, &'static str)>,
+    filter: BusFilter,
+    recording: bool,
+    // Reads only get `&self`.
+    events: RefCell<Vec<BusEvent>>,
+}
+
+impl<B: Bus> BusLogger<B> {
+    /// Wraps `inner`, reporting every access until a filter is set.
+    pub fn new(inner: B) -> BusLogger<B> {
+        BusLogger {
+            inner,
+            regions: Vec::new(),
+            filter: BusFilter::default(),
+            recording: false,
+            events: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Names the addresses in `range`. Where regions overlap, the one added
+    /// first wins.
+    pub fn add_region(&mut self, range: RangeInclusive<u16>, name: &'static str) {
+        self.regions.push((range, name));
+    }
+
+    /// The name of the region containing `address`.
+    #[must_use]
+    pub fn region_of(&self, address: u16) -> Option<&'static str> {
+        self.regions
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(_, name)| *name)
+    }
+
+    pub fn set_filter(&mut self, filter: BusFilter) {
+        self.filter = filter;
+    }
+
+    #[must_use]
+    pub const fn filter(&self) -> &BusFilter {
+        &self.filter
+    }
+
+    /// Keeps the reported accesses in memory, in addition to logging them,
+    /// until they are taken with [`BusLogger::take_events`].
+    pub const fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    /// Returns and forgets the recorded accesses.
+    pub fn take_events(&self) -> Vec<BusEvent> {
+        self.events.take()
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus. Accesses made through
+    /// it are not reported.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Unwraps the logger, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn report(&self, access: Access, address: u16, value: u8) {
+        let event = BusEvent {
+            access,
+            address,
+            value,
+            region: self.region_of(address),
+        };
+        if !self.filter.matches(&event) {
+            return;
+        }
+        log::trace!(target: "mos6502::bus", "{event}");
+        if self.recording {
+            self.events.borrow_mut().push(event);
+        }
+    }
+}
+
+impl<B: Bus> Bus for BusLogger<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        let bytes = self.inner.get_bytes(range.clone());
+        for (address, value) in range.zip(bytes) {
+            // Ranges come from 16-bit addresses.
+            #[allow(clippy::cast_possible_truncation)]
+            self.report(Access::Read, address as u16, *value);
+        }
+        bytes
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        let value = self.inner.get_byte(address);
+        self.report(Access::Read, address, value);
+        value
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self.report(Access::Write, address, value);
+        self.inner.set_byte(address, value);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn logger() -> BusLogger<Memory> {
+        let mut bus = BusLogger::new(Memory::new());
+        bus.add_region(0x0000..=0x7fff, "ram");
+        bus.add_region(0xd000..=0xd3ff, "vic");
+        bus.set_recording(true);
+        bus
+    }
+
+    #[test]
+    fn records_instruction_traffic() {
+        let mut cpu = CPU::new(logger(), Nmos6502);
+        // LDA $D012; STA $10
+        cpu.memory
+            .inner_mut()
+            .set_bytes(0x0200, &[0xad, 0x12, 0xd0, 0x85, 0x10]);
+        cpu.memory.inner_mut().set_byte(0xd012, 0x3f);
+        cpu.registers.program_counter = 0x0200;
+        cpu.single_step();
+        cpu.single_step();
+
+        let events = cpu.memory.take_events();
+        assert!(events.contains(&BusEvent {
+            access: Access::Read,
+            address: 0xd012,
+            value: 0x3f,
+            region: Some("vic"),
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&BusEvent {
+                access: Access::Write,
+                address: 0x0010,
+                value: 0x3f,
+                region: Some("ram"),
+            })
+        );
+        assert!(cpu.memory.take_events().is_empty());
+    }
+
+    #[test]
+    fn filters_by_region_and_direction() {
+        let mut bus = logger();
+        bus.set_filter(BusFilter {
+            reads: false,
+            regions: vec!["vic"],
+            ..BusFilter::default()
+        });
+        bus.set_byte(0x0010, 1);
+        bus.set_byte(0xd020, 2);
+        bus.get_byte(0xd020);
+        bus.set_byte(0xe000, 3);
+
+        let events = bus.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].to_string(), "W $D020 = $02 [vic]");
+    }
+
+    #[test]
+    fn filters_by_address_range() {
+        let mut bus = logger();
+        bus.set_filter(BusFilter {
+            ranges: vec![0xe000..=0xffff],
+            ..BusFilter::default()
+        });
+        bus.set_byte(0x0010, 1);
+        bus.set_byte(0xfffe, 2);
+
+        let events = bus.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].region, None);
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Calling functions compiled by cc65 from the host.
+//!
+//! cc65 passes the rightmost argument of a function in A and X, with the
+//! high word of a `long` in the zero page variable `sreg`, and pushes the
+//! others left to right onto a software stack addressed by the zero page
+//! pointer `sp`, which grows down. A function returns its result the same
+//! way as the rightmost argument, after removing its arguments from the
+//! stack. Variadic functions take every argument on the stack, with Y
+//! holding the number of bytes pushed.
+//!
+//! [`Cc65`] implements that convention, so tests can call the functions of
+//! a program linked with ld65 directly:
+//!
+//! ```
+//! use mos6502::cc65::Cc65;
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! // unsigned char next(unsigned char n) { return n + 1; }
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! cpu.memory.set_bytes(0x0200, &[0x18, 0x69, 0x01, 0x60]);
+//! let cc65 = Cc65::default();
+//! cc65.set_stack_pointer(&mut cpu, 0x1000);
+//! let result = cc65.call(&mut cpu, 0x0200, &[41u8.into()]).unwrap();
+//! assert_eq!(result.as_u8(), 42);
+//! ```
+
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::memory::Bus;
+#[cfg(feature = "alloc")]
+use crate::symbols::SymbolTable;
+use crate::Variant;
+
+/// An argument of a C function.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Arg {
+    /// A `char`. Variadic functions take it as an `int`, zero-extended;
+    /// pass a signed `char` to them as an `i16`.
+    Char(u8),
+    /// An `int`, `short` or pointer.
+    Int(u16),
+    Long(u32),
+}
+
+impl Arg {
+    /// The little-endian bytes of the argument.
+    fn bytes(self) -> ([u8; 4], usize) {
+        match self {
+            Arg::Char(value) => ([value, 0, 0, 0], 1),
+            Arg::Int(value) => (u32::from(value).to_le_bytes(), 2),
+            Arg::Long(value) => (value.to_le_bytes(), 4),
+        }
+    }
+
+    /// The argument after the default promotions of a variadic call.
+    const fn promoted(self) -> Arg {
+        match self {
+            Arg::Char(value) => Arg::Int(value as u16),
+            other => other,
+        }
+    }
+}
+
+impl From<u8> for Arg {
+    fn from(value: u8) -> Arg {
+        Arg::Char(value)
+    }
+}
+
+impl From<i8> for Arg {
+    fn from(value: i8) -> Arg {
+        Arg::Char(value.to_le_bytes()[0])
+    }
+}
+
+impl From<bool> for Arg {
+    fn from(value: bool) -> Arg {
+        Arg::Char(u8::from(value))
+    }
+}
+
+impl From<u16> for Arg {
+    fn from(value: u16) -> Arg {
+        Arg::Int(value)
+    }
+}
+
+impl From<i16> for Arg {
+    fn from(value: i16) -> Arg {
+        Arg::Int(u16::from_le_bytes(value.to_le_bytes()))
+    }
+}
+
+impl From<u32> for Arg {
+    fn from(value: u32) -> Arg {
+        Arg::Long(value)
+    }
+}
+
+impl From<i32> for Arg {
+    fn from(value: i32) -> Arg {
+        Arg::Long(u32::from_le_bytes(value.to_le_bytes()))
+    }
+}
+
+/// The registers a function returned its result in. Which of them hold
+/// the result depends on its return type.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Value {
+    pub a: u8,
+    pub x: u8,
+    /// The high word of a `long`.
+    pub sreg: u16,
+}
+
+impl Value {
+    #[must_use]
+    pub const fn as_u8(self) -> u8 {
+        self.a
+    }
+
+    #[must_use]
+    pub const fn as_i8(self) -> i8 {
+        i8::from_le_bytes([self.a])
+    }
+
+    #[must_use]
+    pub const fn as_bool(self) -> bool {
+        self.a != 0 || self.x != 0
+    }
+
+    #[must_use]
+    pub const fn as_u16(self) -> u16 {
+        u16::from_le_bytes([self.a, self.x])
+    }
+
+    #[must_use]
+    pub const fn as_i16(self) -> i16 {
+        i16::from_le_bytes([self.a, self.x])
+    }
+
+    #[must_use]
+    pub const fn as_u32(self) -> u32 {
+        let [b2, b3] = self.sreg.to_le_bytes();
+        u32::from_le_bytes([self.a, self.x, b2, b3])
+    }
+
+    #[must_use]
+    pub const fn as_i32(self) -> i32 {
+        let [b2, b3] = self.sreg.to_le_bytes();
+        i32::from_le_bytes([self.a, self.x, b2, b3])
+    }
+}
+
+/// Why a call did not return.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// The opcode at this address is not valid for the variant.
+    IllegalOpcode(u16, u8),
+    /// The function ran for [`Cc65::max_cycles`] without returning.
+    CycleLimit,
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::IllegalOpcode(pc, opcode) => {
+                write!(f, "illegal opcode ${opcode:02X} at ${pc:04X}")
+            }
+            CallError::CycleLimit => f.write_str("function did not return"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CallError {}
+
+/// The cc65 runtime of a program, for calling its functions.
+///
+/// The defaults match the `sim6502` target, whose zero page starts at $00.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cc65 {
+    /// Zero page address of the C stack pointer `sp`, called `c_sp` since
+    /// cc65 2.19.
+    pub sp: u8,
+    /// Zero page address of `sreg`.
+    pub sreg: u8,
+    /// Where called functions return to. Nothing there is executed; the
+    /// call ends when the function returns to it.
+    pub return_address: u16,
+    /// Cycles a call may run for before it is abandoned.
+    pub max_cycles: u64,
+}
+
+impl Default for Cc65 {
+    fn default() -> Self {
+        Cc65::at(0x00)
+    }
+}
+
+impl Cc65 {
+    /// The runtime of a program whose zero page area starts at `zero_page`,
+    /// where the runtime puts `sp` and `sreg` first.
+    #[must_use]
+    pub const fn at(zero_page: u8) -> Cc65 {
+        Cc65 {
+            sp: zero_page,
+            sreg: zero_page.wrapping_add(2),
+            return_address: 0xfff0,
+            max_cycles: 10_000_000,
+        }
+    }
+
+    /// Finds `sp` and `sreg` in the labels of a linked program, such as
+    /// those written by `ld65 -Ln`.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn from_symbols(symbols: &SymbolTable) -> Option<Cc65> {
+        let zero_page = |name| symbols.address_of(name).and_then(|a| u8::try_from(a).ok());
+        Some(Cc65 {
+            sp: zero_page("c_sp").or_else(|| zero_page("sp"))?,
+            sreg: zero_page("sreg")?,
+            ..Cc65::default()
+        })
+    }
+
+    /// The C stack pointer.
+    pub fn stack_pointer<M: Bus, V: Variant>(&self, cpu: &CPU<M, V>) -> u16 {
+        read_word(&cpu.memory, self.sp)
+    }
+
+    /// Points the C stack at `address`. The startup code does this before
+    /// `main`; a test that calls functions without running it must too.
+    pub fn set_stack_pointer<M: Bus, V: Variant>(&self, cpu: &mut CPU<M, V>, address: u16) {
+        write_word(&mut cpu.memory, self.sp, address);
+    }
+
+    /// Calls the function at `function` with `args`, the rightmost passed
+    /// in registers, and returns once it has returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the function did not return.
+    pub fn call<M: Bus, V: Variant>(
+        &self,
+        cpu: &mut CPU<M, V>,
+        function: u16,
+        args: &[Arg],
+    ) -> Result<Value, CallError> {
+        let (last, stacked) = match args.split_last() {
+            Some((last, stacked)) => (Some(*last), stacked),
+            None => (None, args),
+        };
+        for arg in stacked {
+            self.push(cpu, *arg);
+        }
+        let ([a, x, b2, b3], _) = last.map_or(([0; 4], 0), Arg::bytes);
+        cpu.registers.accumulator = a;
+        cpu.registers.index_x = x;
+        write_word(&mut cpu.memory, self.sreg, u16::from_le_bytes([b2, b3]));
+        self.invoke(cpu, function)
+    }
+
+    /// Calls the variadic function at `function` with `args`, all of them
+    /// passed on the stack.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the function did not return.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arguments take more than 255 bytes, which cc65 can't
+    /// pass either.
+    pub fn call_variadic<M: Bus, V: Variant>(
+        &self,
+        cpu: &mut CPU<M, V>,
+        function: u16,
+        args: &[Arg],
+    ) -> Result<Value, CallError> {
+        let mut size = 0;
+        for arg in args {
+            size += self.push(cpu, arg.promoted());
+        }
+        cpu.registers.index_y = u8::try_from(size).expect("too many arguments");
+        self.invoke(cpu, function)
+    }
+
+    /// Pushes `arg` onto the C stack, returning its size.
+    fn push<M: Bus, V: Variant>(&self, cpu: &mut CPU<M, V>, arg: Arg) -> usize {
+        let (bytes, size) = arg.bytes();
+        #[allow(clippy::cast_possible_truncation)]
+        let sp = self.stack_pointer(cpu).wrapping_sub(size as u16);
+        self.set_stack_pointer(cpu, sp);
+        let mut address = sp;
+        for byte in &bytes[..size] {
+            cpu.memory.set_byte(address, *byte);
+            address = address.wrapping_add(1);
+        }
+        size
+    }
+
+    fn invoke<M: Bus, V: Variant>(
+        &self,
+        cpu: &mut CPU<M, V>,
+        function: u16,
+    ) -> Result<Value, CallError> {
+        // JSR pushes the address of its last byte, one before the return.
+        let stack = cpu.registers.stack_pointer;
+        for byte in self.return_address.wrapping_sub(1).to_be_bytes() {
+            let address = cpu.registers.stack_pointer.to_u16();
+            cpu.memory.set_byte(address, byte);
+            cpu.registers.stack_pointer.decrement();
+        }
+        cpu.registers.program_counter = function;
+
+        let limit = cpu.cycles.saturating_add(self.max_cycles);
+        loop {
+            let pc = cpu.registers.program_counter;
+            if pc == self.return_address && cpu.registers.stack_pointer == stack {
+                return Ok(Value {
+                    a: cpu.registers.accumulator,
+                    x: cpu.registers.index_x,
+                    sreg: read_word(&cpu.memory, self.sreg),
+                });
+            }
+            if cpu.cycles >= limit {
+                return Err(CallError::CycleLimit);
+            }
+            let opcode = cpu.memory.get_byte(pc);
+            if cpu.single_step().is_none() {
+                return Err(CallError::IllegalOpcode(pc, opcode));
+            }
+        }
+    }
+}
+
+/// Reads the zero page word at `address`.
+fn read_word(memory: &impl Bus, address: u8) -> u16 {
+    let lo = memory.get_byte(u16::from(address));
+    let hi = memory.get_byte(u16::from(address.wrapping_add(1)));
+    u16::from_le_bytes([lo, hi])
+}
+
+fn write_word(memory: &mut impl Bus, address: u8, value: u16) {
+    let [lo, hi] = value.to_le_bytes();
+    memory.set_byte(u16::from(address), lo);
+    memory.set_byte(u16::from(address.wrapping_add(1)), hi);
+}
+
+// The tests assemble their programs, which needs an allocator.
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    const STACK: u16 = 0x1000;
+
+    fn load(source: &str) -> (CPU<Memory, Nmos6502>, Cc65) {
+        let program = assemble(source).unwrap();
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(program.origin, &program.bytes);
+        let cc65 = Cc65::default();
+        cc65.set_stack_pointer(&mut cpu, STACK);
+        (cpu, cc65)
+    }
+
+    #[test]
+    fn calls_a_fastcall_function() {
+        // int add(int a, int b) { return a + b; }
+        let (mut cpu, cc65) = load(
+            "
+            sp = $00
+            tmp = $10
+                    .org $0200
+            add:    CLC
+                    LDY #0
+                    ADC (sp),Y
+                    STA tmp
+                    TXA
+                    INY
+                    ADC (sp),Y
+                    TAX
+                    LDA sp
+                    CLC
+                    ADC #2
+                    STA sp
+                    BCC done
+                    INC sp+1
+            done:   LDA tmp
+                    RTS
+            ",
+        );
+        let result = cc65
+            .call(&mut cpu, 0x0200, &[1000i16.into(), (-3i16).into()])
+            .unwrap();
+        assert_eq!(result.as_i16(), 997);
+        assert_eq!(cc65.stack_pointer(&cpu), STACK);
+    }
+
+    #[test]
+    fn passes_arguments_by_size() {
+        let (mut cpu, cc65) = load(".org $0200\nRTS");
+        let args = [7u8.into(), 0x1234u16.into(), 0x89AB_CDEFu32.into()];
+        let result = cc65.call(&mut cpu, 0x0200, &args).unwrap();
+        assert_eq!(result.as_u32(), 0x89AB_CDEF);
+        assert_eq!(cc65.stack_pointer(&cpu), STACK - 3);
+        assert_eq!(cpu.memory.get_bytes(0x0ffd..0x1000), [0x34, 0x12, 0x07]);
+
+        cc65.set_stack_pointer(&mut cpu, STACK);
+        cc65.call_variadic(&mut cpu, 0x0200, &[1u8.into(), (-2i16).into()])
+            .unwrap();
+        assert_eq!(cpu.registers.index_y, 4);
+        assert_eq!(
+            cpu.memory.get_bytes(0x0ffc..0x1000),
+            [0xfe, 0xff, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn gives_up_on_functions_that_never_return() {
+        let (mut cpu, mut cc65) = load(".org $0200\nloop: JMP loop");
+        cc65.max_cycles = 100;
+        assert_eq!(cc65.call(&mut cpu, 0x0200, &[]), Err(CallError::CycleLimit));
+    }
+
+    #[test]
+    fn finds_the_runtime_in_symbols() {
+        let symbols = SymbolTable::parse("al C:0002 .c_sp\nal C:0004 .sreg\n").unwrap();
+        let cc65 = Cc65::from_symbols(&symbols).unwrap();
+        assert_eq!((cc65.sp, cc65.sreg), (0x02, 0x04));
+        assert_eq!(cc65, Cc65::at(0x02));
+        assert!(Cc65::from_symbols(&SymbolTable::new()).is_none());
+    }
+}
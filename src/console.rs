@@ -0,0 +1,361 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Host console input and output for emulated terminals.
+//!
+//! Reading the host's stdin blocks, which would freeze the emulation loop
+//! while a program waits for a key. [`ConsoleInput`] moves the blocking read
+//! onto a background thread that feeds a channel, so the emulated device can
+//! [`poll`](ConsoleInput::poll) for a key on every access and carry on when
+//! none is waiting. Keys are translated with a [`KeyEncoding`] into the codes
+//! the emulated machine expects.
+//!
+//! The host terminal keeps its usual line discipline: input typically
+//! arrives when Enter is pressed. Front-ends that want every key as it is
+//! typed can switch the terminal to raw mode before creating the console.
+//!
+//! [`Terminal`] maps a console onto the bus using the register layout of the
+//! Apple-1 keyboard and display PIA, so monitors such as Wozmon run
+//! unmodified.
+
+use core::cell::{Cell, RefCell};
+use core::ops::Range;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::memory::{Bus, BusError};
+
+/// How host key codes are translated for the emulated machine.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyEncoding {
+    /// Plain ASCII, with newlines sent as carriage returns and delete sent
+    /// as backspace.
+    #[default]
+    Ascii,
+    /// Apple-1 keyboard codes: upper case with bit 7 set, carriage return
+    /// for newline and `_` for backspace.
+    Apple1,
+}
+
+impl KeyEncoding {
+    /// Translates one byte read from the host, or returns `None` if the
+    /// machine has no equivalent and the byte should be dropped.
+    #[must_use]
+    pub const fn translate(self, byte: u8) -> Option<u8> {
+        // Terminals send LF for Enter, but carriage return is what 6502
+        // monitors look for. A CR of a CRLF pair would otherwise be doubled.
+        let byte = match byte {
+            b'\r' => return None,
+            b'\n' => b'\r',
+            0x7F => 0x08,
+            byte if byte.is_ascii() => byte,
+            _ => return None,
+        };
+        match self {
+            KeyEncoding::Ascii => Some(byte),
+            KeyEncoding::Apple1 => {
+                let byte = if byte == 0x08 {
+                    b'_'
+                } else {
+                    byte.to_ascii_uppercase()
+                };
+                Some(byte | 0x80)
+            }
+        }
+    }
+}
+
+/// Keys read from the host without blocking.
+#[derive(Debug)]
+pub struct ConsoleInput {
+    keys: Receiver<u8>,
+    encoding: KeyEncoding,
+    closed: bool,
+}
+
+impl ConsoleInput {
+    /// Reads keys from the host's standard input.
+    #[must_use]
+    pub fn stdin(encoding: KeyEncoding) -> ConsoleInput {
+        ConsoleInput::from_reader(io::stdin(), encoding)
+    }
+
+    /// Reads keys from `reader` on a background thread.
+    ///
+    /// The thread exits once `reader` reaches end of file or fails, or when
+    /// the console is dropped and the next key has been read.
+    #[must_use]
+    pub fn from_reader<R: Read + Send + 'static>(
+        mut reader: R,
+        encoding: KeyEncoding,
+    ) -> ConsoleInput {
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            while let Ok(len @ 1..) = reader.read(&mut buffer) {
+                if buffer[..len].iter().any(|&byte| sender.send(byte).is_err()) {
+                    break;
+                }
+            }
+        });
+        ConsoleInput {
+            keys,
+            encoding,
+            closed: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn encoding(&self) -> KeyEncoding {
+        self.encoding
+    }
+
+    /// Returns the next translated key if one is waiting.
+    pub fn poll(&mut self) -> Option<u8> {
+        loop {
+            match self.keys.try_recv() {
+                Ok(byte) => {
+                    if let Some(key) = self.encoding.translate(byte) {
+                        return Some(key);
+                    }
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` once the host input has ended and every key read
+    /// before that has been polled.
+    #[must_use]
+    pub const fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Reads one translated key per call, failing with
+/// [`io::ErrorKind::WouldBlock`] when none is waiting, so the console can
+/// feed devices that take any reader, such as an
+/// [`Acia`](crate::acia::Acia).
+impl Read for ConsoleInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let Some(first) = buffer.first_mut() else {
+            return Ok(0);
+        };
+        match self.poll() {
+            Some(key) => {
+                *first = key;
+                Ok(1)
+            }
+            None if self.closed => Ok(0),
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+/// Offset of the keyboard data register.
+pub const KBD: u16 = 0;
+/// Offset of the keyboard control register; bit 7 is set when a key is
+/// waiting.
+pub const KBDCR: u16 = 1;
+/// Offset of the display data register.
+pub const DSP: u16 = 2;
+/// Offset of the display control register.
+pub const DSPCR: u16 = 3;
+
+/// A console mapped onto four registers of the bus, laid out like the
+/// Apple-1 keyboard and display PIA (at `$D010` on that machine).
+///
+/// Reading `KBDCR` polls the host for a key and latches it; reading `KBD`
+/// returns the latched key and clears the flag. Characters written to `DSP`
+/// go to the output with bit 7 stripped and carriage returns turned into
+/// newlines. The display is always ready, so `DSP` reads as zero.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mos6502::console::{ConsoleInput, KeyEncoding, Terminal};
+/// use mos6502::memory::Memory;
+///
+/// let input = ConsoleInput::stdin(KeyEncoding::Apple1);
+/// let bus = Terminal::new(Memory::new(), 0xD010, input, std::io::stdout());
+/// ```
+#[derive(Debug)]
+pub struct Terminal<B: Bus, W: Write> {
+    inner: B,
+    base: u16,
+    input: RefCell<ConsoleInput>,
+    key: Cell<Option<u8>>,
+    output: W,
+}
+
+impl<B: Bus, W: Write> Terminal<B, W> {
+    /// Maps the console registers at `base`..`base + 4` over `inner`.
+    pub const fn new(inner: B, base: u16, input: ConsoleInput, output: W) -> Self {
+        Terminal {
+            inner,
+            base,
+            input: RefCell::new(input),
+            key: Cell::new(None),
+            output,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    /// Returns `true` once the host input has ended and no key is waiting.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.key.get().is_none() && self.input.borrow().is_closed()
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the terminal, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset <= DSPCR).then_some(offset)
+    }
+
+    fn key_waiting(&self) -> bool {
+        if self.key.get().is_none() {
+            self.key.set(self.input.borrow_mut().poll());
+        }
+        self.key.get().is_some()
+    }
+}
+
+impl<B: Bus, W: Write> Bus for Terminal<B, W> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.register(address) {
+            Some(KBD) => {
+                self.key_waiting();
+                self.key.take().unwrap_or(0)
+            }
+            Some(KBDCR) => {
+                if self.key_waiting() {
+                    0x80
+                } else {
+                    0
+                }
+            }
+            Some(_) => 0,
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(DSP) => {
+                let byte = match value & 0x7F {
+                    b'\r' => b'\n',
+                    byte => byte,
+                };
+                // A closed or broken output must not stop the emulation.
+                let _ = self
+                    .output
+                    .write_all(&[byte])
+                    .and_then(|()| self.output.flush());
+            }
+            Some(_) => {}
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::time::{Duration, Instant};
+    use std::vec::Vec;
+
+    fn wait_for_key(terminal: &Terminal<Memory, Vec<u8>>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while terminal.get_byte(0xD011) & 0x80 == 0 {
+            assert!(Instant::now() < deadline, "no key arrived");
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn apple1_encoding() {
+        let encoding = KeyEncoding::Apple1;
+        assert_eq!(encoding.translate(b'a'), Some(0xC1));
+        assert_eq!(encoding.translate(b'\n'), Some(0x8D));
+        assert_eq!(encoding.translate(0x7F), Some(0xDF));
+        assert_eq!(encoding.translate(b'\r'), None);
+        assert_eq!(KeyEncoding::Ascii.translate(b'a'), Some(b'a'));
+    }
+
+    #[test]
+    fn terminal_registers() {
+        let input = ConsoleInput::from_reader(&b"a\n"[..], KeyEncoding::Apple1);
+        let mut terminal = Terminal::new(Memory::new(), 0xD010, input, Vec::new());
+
+        wait_for_key(&terminal);
+        assert_eq!(terminal.get_byte(0xD010), 0xC1);
+        wait_for_key(&terminal);
+        assert_eq!(terminal.get_byte(0xD010), 0x8D);
+
+        for &byte in b"HI\x8D" {
+            terminal.set_byte(0xD012, byte | 0x80);
+        }
+        assert_eq!(terminal.output, b"HI\n");
+
+        terminal.set_byte(0x0200, 0x42);
+        assert_eq!(terminal.inner().get_byte(0x0200), 0x42);
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Running the CPU piecewise from an event loop.
+//!
+//! A [`Coroutine`] owns a CPU and runs it one instruction or one cycle per
+//! [`Coroutine::resume`], handing control back in between, so it can be
+//! interleaved with the other chips of a machine without threads. It is
+//! also an [`Iterator`] over what it yields, and [`Coroutine::run_until`]
+//! returns a [`Future`] that does one step per poll, for use inside an
+//! async executor next to other tasks.
+//!
+//! ```
+//! use mos6502::coroutine::{Coroutine, Granularity, Yielded};
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! // INX; INX
+//! cpu.memory.set_bytes(0, &[0xe8, 0xe8]);
+//! let mut coroutine = Coroutine::new(cpu, Granularity::Cycle);
+//! for _ in 0..4 {
+//!     assert!(matches!(coroutine.resume(), Yielded::Cycle(_)));
+//! }
+//! assert_eq!(coroutine.clock(), 4);
+//! assert_eq!(coroutine.cpu().registers.index_x, 2);
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::Bus;
+use crate::tstate::BusCycle;
+use crate::Variant;
+
+/// How far a [`Coroutine`] runs before yielding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Granularity {
+    /// One instruction, and any interrupt sequence taken after it.
+    Instruction,
+    /// One clock cycle, as [`CPU::step_cycle`].
+    Cycle,
+}
+
+/// What a [`Coroutine`] did before yielding.
+#[derive(Copy, Clone, Debug)]
+pub enum Yielded {
+    Instruction(DecodedInstr),
+    Cycle(BusCycle),
+    /// The next opcode could not be decoded, so the CPU did not move.
+    Halted,
+}
+
+/// A CPU that runs a step at a time.
+#[derive(Debug)]
+pub struct Coroutine<M: Bus, V: Variant> {
+    cpu: CPU<M, V>,
+    granularity: Granularity,
+    clock: u64,
+}
+
+impl<M: Bus, V: Variant> Coroutine<M, V> {
+    pub const fn new(cpu: CPU<M, V>, granularity: Granularity) -> Coroutine<M, V> {
+        Coroutine {
+            clock: cpu.cycles,
+            cpu,
+            granularity,
+        }
+    }
+
+    /// Runs one step and yields.
+    pub fn resume(&mut self) -> Yielded {
+        match self.granularity {
+            Granularity::Instruction => {
+                let yielded = self
+                    .cpu
+                    .single_step()
+                    .map_or(Yielded::Halted, Yielded::Instruction);
+                self.clock = self.cpu.cycles;
+                yielded
+            }
+            Granularity::Cycle => match self.cpu.step_cycle() {
+                Some(cycle) => {
+                    self.clock += 1;
+                    Yielded::Cycle(cycle)
+                }
+                None => Yielded::Halted,
+            },
+        }
+    }
+
+    /// Returns a future that resumes the coroutine once per poll until the
+    /// clock reaches `deadline` or the CPU halts, and resolves to the last
+    /// thing yielded. It wakes itself between steps, so other tasks of the
+    /// executor get a turn after every one.
+    pub const fn run_until(&mut self, deadline: u64) -> RunUntil<'_, M, V> {
+        RunUntil {
+            coroutine: self,
+            deadline,
+        }
+    }
+
+    /// The number of cycles run so far. With [`Granularity::Cycle`], this
+    /// trails `cpu().cycles`, which counts the whole of the current
+    /// instruction as soon as it starts.
+    #[must_use]
+    pub const fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    #[must_use]
+    pub const fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// Changes the size of the steps. A switch to whole instructions waits
+    /// for the current one to finish first.
+    pub const fn set_granularity(&mut self, granularity: Granularity) {
+        self.granularity = granularity;
+    }
+
+    #[must_use]
+    pub const fn cpu(&self) -> &CPU<M, V> {
+        &self.cpu
+    }
+
+    pub const fn cpu_mut(&mut self) -> &mut CPU<M, V> {
+        &mut self.cpu
+    }
+
+    /// Consumes the coroutine, returning the CPU.
+    pub fn into_inner(self) -> CPU<M, V> {
+        self.cpu
+    }
+}
+
+/// Yields until the CPU halts.
+impl<M: Bus, V: Variant> Iterator for Coroutine<M, V> {
+    type Item = Yielded;
+
+    fn next(&mut self) -> Option<Yielded> {
+        match self.resume() {
+            Yielded::Halted => None,
+            yielded => Some(yielded),
+        }
+    }
+}
+
+/// The future returned by [`Coroutine::run_until`].
+#[derive(Debug)]
+pub struct RunUntil<'a, M: Bus, V: Variant> {
+    coroutine: &'a mut Coroutine<M, V>,
+    deadline: u64,
+}
+
+impl<M: Bus, V: Variant> Future for RunUntil<'_, M, V> {
+    type Output = Yielded;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Yielded> {
+        let yielded = self.coroutine.resume();
+        if matches!(yielded, Yielded::Halted) || self.coroutine.clock >= self.deadline {
+            Poll::Ready(yielded)
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use core::task::Waker;
+
+    fn coroutine(granularity: Granularity) -> Coroutine<Memory, Nmos6502> {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        // LDA #1; STA $10; INX; .byte $02
+        cpu.memory
+            .set_bytes(0, &[0xa9, 0x01, 0x85, 0x10, 0xe8, 0x02]);
+        Coroutine::new(cpu, granularity)
+    }
+
+    #[test]
+    fn yields_each_step_until_halted() {
+        assert_eq!(coroutine(Granularity::Instruction).count(), 3);
+
+        let mut cycles = coroutine(Granularity::Cycle);
+        let writes = cycles
+            .by_ref()
+            .filter(|yielded| {
+                matches!(yielded, Yielded::Cycle(cycle) if cycle.access == crate::memory::Access::Write)
+            })
+            .count();
+        assert_eq!(writes, 1);
+        assert_eq!(cycles.clock(), 7);
+    }
+
+    #[test]
+    fn future_interleaves_with_the_event_loop() {
+        let mut coroutine = coroutine(Granularity::Cycle);
+        let mut context = Context::from_waker(Waker::noop());
+        let mut polls = 0;
+        let yielded = {
+            let mut future = coroutine.run_until(5);
+            loop {
+                polls += 1;
+                if let Poll::Ready(yielded) = Pin::new(&mut future).poll(&mut context) {
+                    break yielded;
+                }
+            }
+        };
+        assert_eq!(polls, 5);
+        assert!(matches!(yielded, Yielded::Cycle(_)));
+        assert_eq!(coroutine.clock(), 5);
+        assert_eq!(coroutine.cpu().memory.get_byte(0x10), 1);
+    }
+}
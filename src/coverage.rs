@@ -0,0 +1,199 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Recording which instructions a program executed.
+//!
+//! [`Coverage`] counts how often each address was fetched as an opcode.
+//! Combined with a mapping from addresses to source lines, such as the one
+//! produced by the [assembler](crate::asm), the counts can be written in the
+//! lcov tracefile format understood by `genhtml` and most coverage tools.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::Bus;
+use crate::Variant;
+
+/// A source line that assembled to an instruction at `address`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine<'a> {
+    pub file: &'a str,
+    /// One-based line number.
+    pub line: usize,
+    pub address: u16,
+}
+
+impl fmt::Display for SourceLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Execution counts for every address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    counts: Vec<u32>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage {
+            counts: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Coverage {
+    #[must_use]
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Records an instruction fetched from `address`.
+    pub fn record(&mut self, address: u16) {
+        let count = &mut self.counts[usize::from(address)];
+        *count = count.saturating_add(1);
+    }
+
+    /// Executes one instruction on `cpu`, recording its address.
+    pub fn step<M: Bus, V: Variant>(&mut self, cpu: &mut CPU<M, V>) -> Option<DecodedInstr> {
+        let pc = cpu.registers.program_counter;
+        let decoded = cpu.single_step();
+        if decoded.is_some() {
+            self.record(pc);
+        }
+        decoded
+    }
+
+    /// How many times an instruction was executed at `address`.
+    #[must_use]
+    pub fn count(&self, address: u16) -> u32 {
+        self.counts[usize::from(address)]
+    }
+
+    #[must_use]
+    pub fn is_covered(&self, address: u16) -> bool {
+        self.count(address) > 0
+    }
+
+    /// Number of distinct addresses executed.
+    #[must_use]
+    pub fn covered(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+
+    /// Forgets every recorded execution.
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// Writes the counts of `lines` as an lcov tracefile, one record per
+    /// source file.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by `out`.
+    pub fn write_lcov<'a>(
+        &self,
+        out: &mut impl fmt::Write,
+        test_name: &str,
+        lines: impl IntoIterator<Item = SourceLine<'a>>,
+    ) -> fmt::Result {
+        // Several instructions can share a line, e.g. in macros; the line
+        // counts as executed as often as its busiest instruction.
+        let mut files: BTreeMap<&str, BTreeMap<usize, u32>> = BTreeMap::new();
+        for line in lines {
+            let hits = files
+                .entry(line.file)
+                .or_default()
+                .entry(line.line)
+                .or_default();
+            *hits = (*hits).max(self.count(line.address));
+        }
+
+        for (file, lines) in files {
+            writeln!(out, "TN:{test_name}")?;
+            writeln!(out, "SF:{file}")?;
+            for (line, hits) in &lines {
+                writeln!(out, "DA:{line},{hits}")?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(
+                out,
+                "LH:{}",
+                lines.values().filter(|hits| **hits > 0).count()
+            )?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use alloc::string::String;
+
+    #[test]
+    fn lcov_from_assembler_listing() {
+        let program = assemble(
+            "
+                    LDX #2
+            loop:   DEX
+                    BNE loop
+                    BRK
+                    LDA #1
+            ",
+        )
+        .unwrap();
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(program.origin, &program.bytes);
+
+        let mut coverage = Coverage::new();
+        for _ in 0..6 {
+            coverage.step(&mut cpu);
+        }
+        assert_eq!(coverage.count(0x0002), 2);
+        assert_eq!(coverage.covered(), 4);
+
+        let mut lcov = String::new();
+        coverage
+            .write_lcov(&mut lcov, "loop", program.source_lines("loop.s"))
+            .unwrap();
+        assert_eq!(
+            lcov,
+            "TN:loop\nSF:loop.s\nDA:2,1\nDA:3,2\nDA:4,2\nDA:5,1\nDA:6,0\nLF:5\nLH:4\nend_of_record\n"
+        );
+    }
+}
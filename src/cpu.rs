@@ -25,16 +25,64 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
+use crate::alu;
 use crate::instruction::{AddressingMode, DecodedInstr, Instruction, OpInput};
-use crate::memory::Bus;
+use crate::interrupt::{InterruptController, Request};
+use crate::memory::{Access, Bus, IRQ_INTERRUPT_VECTOR_LO, RESET_VECTOR_HI, RESET_VECTOR_LO};
+use crate::tstate::{self, BusCycle, Context, Sequencer};
 use crate::Variant;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
-use crate::registers::{Registers, StackPointer, Status, StatusArgs};
+use crate::registers::{Flags, Registers, StackPointer, Status, StatusArgs};
 
 fn address_from_bytes(lo: u8, hi: u8) -> u16 {
     u16::from(lo) + (u16::from(hi) << 8usize)
 }
 
+/// A request from an external device to halt the CPU for `count` cycles
+/// starting at cycle `start`, like the VIC-II does on badlines.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct CycleSteal {
+    start: u64,
+    count: u32,
+}
+
+/// Computes the operand of an instruction from the bytes that follow its
+/// opcode, making any reads its addressing mode needs.
+type Fetch<M, V> = fn(&mut CPU<M, V>, Instruction, [u8; 2]) -> OpInput;
+
+/// Carries out an instruction on its operand.
+type Execute<M, V> = fn(&mut CPU<M, V>, OpInput);
+
+/// What the CPU does for one opcode. The CPU looks these up in a 256-entry
+/// table built at compile time for each bus and variant, so that decoding
+/// and executing an instruction takes an index and two calls.
+struct Opcode<M: Bus, V: Variant> {
+    instruction: Instruction,
+    mode: AddressingMode,
+    fetch: Fetch<M, V>,
+    execute: Execute<M, V>,
+}
+
+// Derived impls would require `M` and `V` to be `Clone` too.
+impl<M: Bus, V: Variant> Clone for Opcode<M, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: Bus, V: Variant> Copy for Opcode<M, V> {}
+
+/// Progress through the cycles of an instruction run by
+/// [`CPU::step_cycle`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Replay {
+    start: u64,
+    cycles: u64,
+    position: u64,
+}
+
 #[derive(Clone)]
 pub struct CPU<M, V>
 where
@@ -43,6 +91,38 @@ where
 {
     pub registers: Registers,
     pub memory: M,
+    /// Total number of cycles executed since the CPU was created.
+    pub cycles: u64,
+    /// Enables timing details that only matter to cycle-exact emulations,
+    /// such as cycle stealing honoring only read cycles and the dummy
+    /// accesses listed in [`tstate`].
+    pub cycle_accurate: bool,
+    stall: Option<CycleSteal>,
+    sequencer: Sequencer,
+    replay: Option<Replay>,
+    /// Cycles added by the instruction being executed on top of its base
+    /// count, such as for a taken branch.
+    penalty_cycles: u8,
+    /// Wait states of the accesses made so far by the instruction being
+    /// executed, see [`Bus::wait_states`].
+    wait_cycles: u32,
+    /// The interrupt lines and the requests pending on them.
+    interrupts: InterruptController,
+    /// The interrupt that followed the last instruction, if any.
+    taken: Option<Request>,
+    /// The vector the last `BRK` fetched its handler through.
+    brk_vector: u16,
+    /// The cycle the IRQ line was asserted on, until an IRQ is taken.
+    irq_since: Option<u64>,
+    /// The cycle the pending NMI was requested on.
+    nmi_since: Option<u64>,
+    /// Cycles from the request of the interrupt in `taken` to its handler.
+    latency: Option<u64>,
+    /// Bus accesses made by instructions, while recording.
+    #[cfg(feature = "alloc")]
+    accesses: Option<Vec<(Access, u16, u8)>>,
+    #[cfg(feature = "tracing")]
+    spans: crate::instrument::Spans,
     variant: core::marker::PhantomData<V>,
 }
 
@@ -55,699 +135,1113 @@ impl<M: Bus, V: Variant> CPU<M, V> {
         CPU {
             registers: Registers::new(),
             memory,
+            cycles: 0,
+            cycle_accurate: false,
+            stall: None,
+            sequencer: Sequencer::new(),
+            replay: None,
+            penalty_cycles: 0,
+            wait_cycles: 0,
+            interrupts: InterruptController::new(),
+            taken: None,
+            brk_vector: IRQ_INTERRUPT_VECTOR_LO,
+            irq_since: None,
+            nmi_since: None,
+            latency: None,
+            #[cfg(feature = "alloc")]
+            accesses: None,
+            #[cfg(feature = "tracing")]
+            spans: crate::instrument::Spans::default(),
             variant: core::marker::PhantomData::<V>,
         }
     }
 
-    pub fn reset(&mut self) {
-        //TODO: should read some bytes from the stack and also get the PC from the reset vector
+    /// Requests that the CPU be halted for `count` cycles starting at cycle
+    /// `start`, as a device sharing the bus would do to steal cycles.
+    ///
+    /// A real 6502 only stops on read cycles, so with `cycle_accurate` set,
+    /// write cycles falling inside the window (the tail of a store,
+    /// read-modify-write or push) complete before the CPU halts. Otherwise
+    /// the CPU simply stalls from `start` until the window ends.
+    ///
+    /// Only one stall can be pending at a time; a new request replaces one
+    /// that has not been honored yet.
+    pub const fn steal_cycles(&mut self, start: u64, count: u32) {
+        self.stall = Some(CycleSteal { start, count });
     }
 
-    /// Get the next byte from memory and decode it into an instruction and addressing mode.
+    /// Drives the IRQ input.
     ///
-    /// # Panics
+    /// The line is level-triggered: while it is asserted and interrupts are
+    /// enabled, the CPU takes an interrupt at the end of each instruction. The
+    /// device that raised it is expected to release it once serviced.
     ///
-    /// This function will panic if the instruction is not recognized
-    /// (i.e. the opcode is invalid or has not been implemented).
-    pub fn fetch_next_and_decode(&mut self) -> Option<DecodedInstr> {
-        // Helper function to read a 16-bit address from memory
-        fn read_address<M: Bus>(mem: &mut M, addr: u16) -> [u8; 2] {
-            let lo = mem.get_byte(addr);
-            let hi = mem.get_byte(addr.wrapping_add(1));
-            [lo, hi]
+    /// With `cycle_accurate` set, `CLI`, `SEI` and `PLP` change the interrupt
+    /// mask one instruction late, as on a real 6502: the interrupt check at
+    /// the end of these instructions still sees the old I flag. An IRQ
+    /// pending across `CLI` is taken after the following instruction, and one
+    /// arriving before `SEI` is still taken straight after it. `RTI` restores
+    /// the flag without delay.
+    pub const fn set_irq(&mut self, asserted: bool) {
+        if !asserted {
+            self.irq_since = None;
+        } else if !self.interrupts.irq_asserted() {
+            self.irq_since = Some(self.cycles);
         }
+        self.interrupts.set_irq(asserted);
+    }
 
-        let x: u8 = self.memory.get_byte(self.registers.program_counter);
+    /// Returns `true` while the IRQ input is asserted.
+    #[must_use]
+    pub const fn irq_asserted(&self) -> bool {
+        self.interrupts.irq_asserted()
+    }
 
-        match V::decode(x) {
-            Some((instr, am)) => {
-                let extra_bytes = am.extra_bytes();
-                let num_bytes = extra_bytes + 1;
+    /// Drives the NMI input.
+    ///
+    /// The line is edge-triggered: asserting it requests one interrupt,
+    /// which is taken at the end of the next instruction whatever the I
+    /// flag, ahead of any IRQ. If that instruction is `BRK`, it continues at
+    /// the NMI handler instead. See [`InterruptController`].
+    pub const fn set_nmi(&mut self, asserted: bool) {
+        if asserted && !self.interrupts.nmi_asserted() {
+            self.nmi_since = Some(self.cycles);
+        }
+        self.interrupts.set_nmi(asserted);
+    }
 
-                let data_start = self.registers.program_counter.wrapping_add(1);
+    /// Pushes `value` on the stack, as `PHA` does, but without clocking the
+    /// bus. The stack pointer wraps around within page 1, so a push with it
+    /// at $00 writes $0100 and leaves it at $FF.
+    pub fn push_byte(&mut self, value: u8) {
+        self.memory
+            .set_byte(self.registers.stack_pointer.to_u16(), value);
+        self.registers.stack_pointer.decrement();
+    }
 
-                let slice = if extra_bytes == 0 {
-                    [0, 0]
-                } else if extra_bytes == 1 {
-                    [self.memory.get_byte(data_start), 0]
-                } else if extra_bytes == 2 {
-                    [
-                        self.memory.get_byte(data_start),
-                        self.memory.get_byte(data_start.wrapping_add(1)),
-                    ]
-                } else {
-                    panic!()
-                };
+    /// Pulls a byte off the stack, as `PLA` does, but without clocking the
+    /// bus or changing the flags. Wraps around within page 1 like
+    /// [`CPU::push_byte`].
+    pub fn pull_byte(&mut self) -> u8 {
+        self.registers.stack_pointer.increment();
+        self.memory.get_byte(self.registers.stack_pointer.to_u16())
+    }
+
+    /// The state of the interrupt lines.
+    #[must_use]
+    pub const fn interrupts(&self) -> &InterruptController {
+        &self.interrupts
+    }
+
+    /// Puts back registers, cycle count and interrupt state saved between
+    /// instructions, dropping whatever was in flight: a partly replayed
+    /// instruction, a pending cycle steal and the record of the last
+    /// interrupt taken. Execution then continues exactly as it did from the
+    /// saved state.
+    #[cfg(feature = "alloc")]
+    pub(crate) const fn restore_state(
+        &mut self,
+        registers: Registers,
+        cycles: u64,
+        interrupts: InterruptController,
+    ) {
+        self.registers = registers;
+        self.cycles = cycles;
+        self.replay = None;
+        self.stall = None;
+        self.taken = None;
+        self.latency = None;
+        self.irq_since = if interrupts.irq_asserted() {
+            Some(cycles)
+        } else {
+            None
+        };
+        self.nmi_since = if interrupts.nmi_pending() {
+            Some(cycles)
+        } else {
+            None
+        };
+        self.interrupts = interrupts;
+    }
 
-                let x = self.registers.index_x;
-                let y = self.registers.index_y;
-
-                let memory = &mut self.memory;
-
-                let am_out = match am {
-                    AddressingMode::Accumulator | AddressingMode::Implied => {
-                        // Always the same -- no input
-                        OpInput::UseImplied
-                    }
-                    AddressingMode::Immediate => {
-                        // Use [u8, ..1] specified in instruction as input
-                        OpInput::UseImmediate(slice[0])
-                    }
-                    AddressingMode::ZeroPage => {
-                        // Use [u8, ..1] from instruction
-                        // Interpret as zero page address
-                        // (Output: an 8-bit zero-page address)
-                        OpInput::UseAddress(u16::from(slice[0]))
-                    }
-                    AddressingMode::ZeroPageX => {
-                        // Use [u8, ..1] from instruction
-                        // Add to X register (as u8 -- the final address is in 0-page)
-                        // (Output: an 8-bit zero-page address)
-                        OpInput::UseAddress(u16::from(slice[0].wrapping_add(x)))
-                    }
-                    AddressingMode::ZeroPageY => {
-                        // Use [u8, ..1] from instruction
-                        // Add to Y register (as u8 -- the final address is in 0-page)
-                        // (Output: an 8-bit zero-page address)
-                        OpInput::UseAddress(u16::from(slice[0].wrapping_add(y)))
-                    }
-
-                    AddressingMode::Relative => {
-                        // Use [u8, ..1] from instruction
-                        // (interpret as relative...)
-                        // (This is sign extended to a 16-but data type, but an unsigned one: u16. It's a
-                        // little weird, but it's so we can add the PC and the offset easily)
-                        let offset = slice[0];
-                        let sign_extend = if offset & 0x80 == 0x80 { 0xffu8 } else { 0x0 };
-                        let rel = u16::from_le_bytes([offset, sign_extend]);
-                        OpInput::UseRelative(rel)
-                    }
-                    AddressingMode::Absolute => {
-                        // Use [u8, ..2] from instruction as address
-                        // (Output: a 16-bit address)
-                        OpInput::UseAddress(address_from_bytes(slice[0], slice[1]))
-                    }
-                    AddressingMode::AbsoluteX => {
-                        // Use [u8, ..2] from instruction as address, add X
-                        // (Output: a 16-bit address)
-                        OpInput::UseAddress(
-                            address_from_bytes(slice[0], slice[1]).wrapping_add(x.into()),
-                        )
-                    }
-                    AddressingMode::AbsoluteY => {
-                        // Use [u8, ..2] from instruction as address, add Y
-                        // (Output: a 16-bit address)
-                        OpInput::UseAddress(
-                            address_from_bytes(slice[0], slice[1]).wrapping_add(y.into()),
-                        )
-                    }
-                    AddressingMode::Indirect => {
-                        // Use [u8, ..2] from instruction as an address. Interpret the
-                        // two bytes starting at that address as an address.
-                        // (Output: a 16-bit address)
-                        // TODO: If the pointer ends in 0xff, then incrementing it would propagate
-                        // the carry to the high byte of the pointer. This incurs a cost of one
-                        // machine cycle on the real 65C02, which is not implemented here.
-                        let slice = read_address(memory, address_from_bytes(slice[0], slice[1]));
-                        OpInput::UseAddress(address_from_bytes(slice[0], slice[1]))
-                    }
-                    AddressingMode::BuggyIndirect => {
-                        // Use [u8, ..2] from instruction as an address. Interpret the
-                        // two bytes starting at that address as an address.
-                        // (Output: a 16-bit address)
-                        let pointer = address_from_bytes(slice[0], slice[1]);
-
-                        let low_byte_of_target = memory.get_byte(pointer);
-
-                        let low_byte_of_incremented_pointer =
-                            pointer.to_le_bytes()[0].wrapping_add(1);
-                        let incremented_pointer = u16::from_le_bytes([
-                            low_byte_of_incremented_pointer,
-                            pointer.to_le_bytes()[1],
-                        ]);
-
-                        let high_byte_of_target = memory.get_byte(incremented_pointer);
-                        OpInput::UseAddress(address_from_bytes(
-                            low_byte_of_target,
-                            high_byte_of_target,
-                        ))
-                    }
-                    AddressingMode::IndexedIndirectX => {
-                        // Use [u8, ..1] from instruction
-                        // Add to X register with 0-page wraparound, like ZeroPageX.
-                        // This is where the absolute (16-bit) target address is stored.
-                        // (Output: a 16-bit address)
-                        let start = slice[0].wrapping_add(x);
-                        let slice = read_address(memory, u16::from(start));
-                        OpInput::UseAddress(address_from_bytes(slice[0], slice[1]))
-                    }
-                    AddressingMode::IndirectIndexedY => {
-                        // Use [u8, ..1] from instruction
-                        // This is where the absolute (16-bit) target address is stored.
-                        // Add Y register to this address to get the final address
-                        // (Output: a 16-bit address)
-                        let start = slice[0];
-                        let slice = read_address(memory, u16::from(start));
-                        OpInput::UseAddress(
-                            address_from_bytes(slice[0], slice[1]).wrapping_add(y.into()),
-                        )
-                    }
-                    AddressingMode::ZeroPageIndirect => {
-                        // Use [u8, ..1] from instruction
-                        // This is where the absolute (16-bit) target address is stored.
-                        // (Output: a 16-bit address)
-                        let start = slice[0];
-                        let slice = read_address(memory, u16::from(start));
-                        OpInput::UseAddress(address_from_bytes(slice[0], slice[1]))
-                    }
-                };
+    /// Returns `true` if an IRQ was taken straight after the instruction
+    /// executed by the last [`CPU::single_step`].
+    #[must_use]
+    pub const fn irq_taken(&self) -> bool {
+        matches!(self.taken, Some(Request::Irq))
+    }
 
-                // Increment program counter
-                self.registers.program_counter =
-                    self.registers.program_counter.wrapping_add(num_bytes);
+    /// The interrupt taken straight after the instruction executed by the
+    /// last [`CPU::single_step`], if any.
+    #[must_use]
+    pub const fn interrupt_taken(&self) -> Option<Request> {
+        self.taken
+    }
 
-                Some((instr, am_out))
-            }
-            _ => None,
-        }
+    /// The vector the last `BRK` fetched its handler through: the IRQ/BRK
+    /// vector, or the NMI vector when an NMI hijacked it.
+    #[must_use]
+    pub const fn brk_vector(&self) -> u16 {
+        self.brk_vector
     }
 
-    #[allow(clippy::too_many_lines)]
-    pub fn execute_instruction(&mut self, decoded_instr: DecodedInstr) {
-        match decoded_instr {
-            (Instruction::ADC, OpInput::UseImmediate(val)) => {
-                log::debug!("add with carry immediate: {}", val);
-                self.add_with_carry(val);
-            }
-            (Instruction::ADC, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("add with carry. address: {:?}. value: {}", addr, val);
-                self.add_with_carry(val);
-            }
-            (Instruction::ADCnd, OpInput::UseImmediate(val)) => {
-                log::debug!("add with carry immediate: {}", val);
-                self.add_with_no_decimal(val);
-            }
-            (Instruction::ADCnd, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("add with carry. address: {:?}. value: {}", addr, val);
-                self.add_with_no_decimal(val);
-            }
+    /// Cycles from the request of the interrupt taken after the last
+    /// instruction, when its line was asserted, to the first cycle of its
+    /// handler.
+    ///
+    /// `None` if no interrupt was taken, or for an IRQ taken again because
+    /// the line was never released after the last one.
+    #[must_use]
+    pub const fn interrupt_latency(&self) -> Option<u64> {
+        self.latency
+    }
 
-            (Instruction::AND, OpInput::UseImmediate(val)) => {
-                self.and(val);
-            }
-            (Instruction::AND, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.and(val);
-            }
+    /// Starts or stops recording the bus accesses made by instructions,
+    /// discarding any recorded so far.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn record_accesses(&mut self, enabled: bool) {
+        self.accesses = enabled.then(Vec::new);
+    }
 
-            (Instruction::ASL, OpInput::UseImplied) => {
-                // Accumulator mode
-                let mut val = self.registers.accumulator;
-                CPU::<M, V>::shift_left_with_flags(&mut val, &mut self.registers.status);
-                self.registers.accumulator = val;
-            }
-            (Instruction::ASL, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::shift_left_with_flags(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
+    /// Whether bus accesses are being recorded.
+    #[cfg(feature = "alloc")]
+    pub(crate) const fn is_recording_accesses(&self) -> bool {
+        self.accesses.is_some()
+    }
 
-            (Instruction::BCC, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_carry_clear(addr);
-            }
+    /// The recorded bus accesses not drained yet, oldest first.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn recorded_accesses(&self) -> &[(Access, u16, u8)] {
+        self.accesses.as_deref().unwrap_or_default()
+    }
 
-            (Instruction::BCS, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_carry_set(addr);
-            }
+    /// Removes the recorded bus accesses, oldest first.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn drain_accesses(&mut self) -> impl Iterator<Item = (Access, u16, u8)> + '_ {
+        self.accesses
+            .iter_mut()
+            .flat_map(|accesses| accesses.drain(..))
+    }
 
-            (Instruction::BEQ, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_equal(addr);
-            }
+    /// A stable 64-bit hash of the registers, flags and all 64K of memory,
+    /// for regression tests that check one value per checkpoint instead of
+    /// a whole snapshot.
+    ///
+    /// The hash is FNV-1a and does not change between releases or hosts. The
+    /// cycle counter is not included. Memory is read with [`Bus::get_byte`],
+    /// so devices with side effects on read should be hashed with care.
+    #[must_use]
+    pub fn state_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let r = &self.registers;
+        let [pcl, pch] = r.program_counter.to_le_bytes();
+        let registers = [
+            pcl,
+            pch,
+            r.accumulator,
+            r.index_x,
+            r.index_y,
+            r.stack_pointer.0,
+            r.status.to_byte(),
+        ];
+        let memory = (0..=u16::MAX).map(|address| self.memory.get_byte(address));
+        registers
+            .into_iter()
+            .chain(memory)
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Runs the reset sequence: the stack pointer is left at `$FD`, where
+    /// the three pushes the chip makes without writing leave it from
+    /// power-on, interrupts are disabled and the program counter is loaded
+    /// from the vector at `$FFFC`, taking seven cycles. The decimal flag is cleared only on
+    /// variants whose [`Variant::clears_decimal_on_reset`] says so; A, X and
+    /// Y keep their values. A latched NMI is dropped.
+    ///
+    /// Assign [`Registers::zeroed`] to `registers` afterwards for a fixed
+    /// starting state instead.
+    pub fn reset(&mut self) {
+        self.interrupts.reset();
+        self.taken = None;
+        self.replay = None;
+        self.stall = None;
+        self.registers.program_counter = u16::from_le_bytes([
+            self.memory.get_byte(RESET_VECTOR_LO),
+            self.memory.get_byte(RESET_VECTOR_HI),
+        ]);
+        self.cycles += 7;
+        self.registers.stack_pointer = StackPointer(0xfd);
+        self.registers.status.insert(Status::PS_DISABLE_INTERRUPTS);
+        if V::clears_decimal_on_reset() {
+            self.registers.status.remove(Status::PS_DECIMAL_MODE);
+        }
+    }
 
-            (Instruction::BNE, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_not_equal(addr);
-            }
+    /// Every opcode the variant decodes, paired with the routines that fetch
+    /// its operand and execute it.
+    const OPCODES: [Option<Opcode<M, V>>; 256] = {
+        let mut opcodes = [None; 256];
+        let mut index = 0;
+        while index < opcodes.len() {
+            if let Some((instruction, mode)) = V::OPCODES[index] {
+                opcodes[index] = Some(Opcode {
+                    instruction,
+                    mode,
+                    fetch: Self::fetcher(mode),
+                    execute: Self::executor(instruction),
+                });
+            }
+            index += 1;
+        }
+        opcodes
+    };
+
+    /// The entry for `opcode` in [`CPU::OPCODES`], looked up in place rather
+    /// than copying the table.
+    fn opcode(opcode: u8) -> Option<Opcode<M, V>> {
+        let opcodes: &[Option<Opcode<M, V>>; 256] = const { &Self::OPCODES };
+        opcodes[usize::from(opcode)]
+    }
+
+    /// Adds `index` to `base`, charging the extra cycle the variant takes
+    /// for `instruction` when the sum lands on another page.
+    fn index(
+        &mut self,
+        base: u16,
+        index: u8,
+        instruction: Instruction,
+        mode: AddressingMode,
+    ) -> u16 {
+        let address = base.wrapping_add(index.into());
+        if (base ^ address) & 0xff00 != 0 && V::page_cross_penalty(instruction, mode) {
+            self.penalty_cycles += 1;
+        }
+        address
+    }
 
-            (Instruction::BIT, OpInput::UseImmediate(val)) => {
-                self.registers.status.set_with_mask(
-                    Status::PS_ZERO,
-                    Status::new(StatusArgs {
-                        zero: 0 == (self.registers.accumulator & val),
-                        ..StatusArgs::none()
-                    }),
-                );
+    /// Get the next byte from memory and decode it into an instruction and addressing mode.
+    ///
+    /// Returns `None` if the opcode is not one the variant decodes.
+    pub fn fetch_next_and_decode(&mut self) -> Option<DecodedInstr> {
+        self.fetch()
+            .map(|(opcode, input)| (opcode.instruction, input))
+    }
+
+    /// Reads the opcode at PC and its operand, leaving PC on the next
+    /// instruction.
+    fn fetch(&mut self) -> Option<(Opcode<M, V>, OpInput)> {
+        let opcode = self.read(self.registers.program_counter);
+        self.fetch_operand(opcode)
+    }
+
+    /// Reads the operand of `opcode`, just fetched from PC, leaving PC on
+    /// the next instruction.
+    fn fetch_operand(&mut self, opcode: u8) -> Option<(Opcode<M, V>, OpInput)> {
+        let opcode = Self::opcode(opcode)?;
+
+        let extra_bytes = opcode.mode.extra_bytes();
+        let data_start = self.registers.program_counter.wrapping_add(1);
+        let bytes = match extra_bytes {
+            0 => [0, 0],
+            1 => [self.read(data_start), 0],
+            // JSR reads the high byte of its target only after pushing the
+            // return address; that read is made by the sequencer.
+            _ if opcode.instruction == Instruction::JSR && self.cycle_accurate => {
+                let lo = self.read(data_start);
+                [lo, self.memory.get_byte(data_start.wrapping_add(1))]
+            }
+            _ => [self.read(data_start), self.read(data_start.wrapping_add(1))],
+        };
+        let input = (opcode.fetch)(self, opcode.instruction, bytes);
+
+        // Increment program counter
+        self.registers.program_counter = data_start.wrapping_add(extra_bytes);
+        Some((opcode, input))
+    }
+
+    /// Decodes and executes an instruction already fetched, such as one
+    /// returned by [`CPU::fetch_next_and_decode`].
+    pub fn execute_instruction(&mut self, (instruction, input): DecodedInstr) {
+        Self::executor(instruction)(self, input);
+    }
+
+    /// The routine computing the operand of instructions in `mode`, from the
+    /// bytes that follow the opcode.
+    const fn fetcher(mode: AddressingMode) -> Fetch<M, V> {
+        match mode {
+            // Always the same -- no input
+            AddressingMode::Accumulator | AddressingMode::Implied => |_, _, _| OpInput::UseImplied,
+            // Use [u8, ..1] specified in instruction as input
+            AddressingMode::Immediate => |_, _, [value, _]| OpInput::UseImmediate(value),
+            // Interpret [u8, ..1] as a zero page address
+            AddressingMode::ZeroPage => |_, _, [zp, _]| OpInput::UseAddress(u16::from(zp)),
+            // Add X or Y as u8 -- the final address is in 0-page
+            AddressingMode::ZeroPageX => |cpu, _, [zp, _]| {
+                OpInput::UseAddress(u16::from(zp.wrapping_add(cpu.registers.index_x)))
+            },
+            AddressingMode::ZeroPageY => |cpu, _, [zp, _]| {
+                OpInput::UseAddress(u16::from(zp.wrapping_add(cpu.registers.index_y)))
+            },
+            // Sign extended to a u16, so that the PC and the offset can be
+            // added with a wrapping add
+            AddressingMode::Relative => |_, _, [offset, _]| {
+                let sign_extend = if offset & 0x80 == 0x80 { 0xffu8 } else { 0x0 };
+                OpInput::UseRelative(u16::from_le_bytes([offset, sign_extend]))
+            },
+            AddressingMode::Absolute => {
+                |_, _, [lo, hi]| OpInput::UseAddress(address_from_bytes(lo, hi))
             }
+            AddressingMode::AbsoluteX => |cpu, instruction, [lo, hi]| {
+                let base = address_from_bytes(lo, hi);
+                let x = cpu.registers.index_x;
+                OpInput::UseAddress(cpu.index(base, x, instruction, AddressingMode::AbsoluteX))
+            },
+            AddressingMode::AbsoluteY => |cpu, instruction, [lo, hi]| {
+                let base = address_from_bytes(lo, hi);
+                let y = cpu.registers.index_y;
+                OpInput::UseAddress(cpu.index(base, y, instruction, AddressingMode::AbsoluteY))
+            },
+            // TODO: If the pointer ends in 0xff, then incrementing it would
+            // propagate the carry to the high byte of the pointer. This incurs
+            // a cost of one machine cycle on the real 65C02, which is not
+            // implemented here.
+            AddressingMode::Indirect => |cpu, _, [lo, hi]| {
+                let [lo, hi] = cpu.read_address(address_from_bytes(lo, hi));
+                OpInput::UseAddress(address_from_bytes(lo, hi))
+            },
+            // The NMOS chips don't carry into the high byte of the pointer:
+            // JMP ($10ff) reads its target from $10ff and $1000.
+            AddressingMode::BuggyIndirect => |cpu, _, [lo, hi]| {
+                let low_byte_of_target = cpu.read(address_from_bytes(lo, hi));
+                let high_byte_of_target = cpu.read(address_from_bytes(lo.wrapping_add(1), hi));
+                OpInput::UseAddress(address_from_bytes(low_byte_of_target, high_byte_of_target))
+            },
+            // Add X with 0-page wraparound, like ZeroPageX. This is where the
+            // absolute (16-bit) target address is stored.
+            AddressingMode::IndexedIndirectX => |cpu, _, [zp, _]| {
+                let start = zp.wrapping_add(cpu.registers.index_x);
+                let [lo, hi] = cpu.read_address(u16::from(start));
+                OpInput::UseAddress(address_from_bytes(lo, hi))
+            },
+            // The pointer is in the zero page; Y is added to its target.
+            AddressingMode::IndirectIndexedY => |cpu, instruction, [zp, _]| {
+                let [lo, hi] = cpu.read_address(u16::from(zp));
+                let base = address_from_bytes(lo, hi);
+                let y = cpu.registers.index_y;
+                OpInput::UseAddress(cpu.index(
+                    base,
+                    y,
+                    instruction,
+                    AddressingMode::IndirectIndexedY,
+                ))
+            },
+            AddressingMode::ZeroPageIndirect => |cpu, _, [zp, _]| {
+                let [lo, hi] = cpu.read_address(u16::from(zp));
+                OpInput::UseAddress(address_from_bytes(lo, hi))
+            },
+            // X is added with a carry into the high byte, unlike the zero
+            // page, and the target is read from there.
+            AddressingMode::AbsoluteIndexedIndirect => |cpu, _, [lo, hi]| {
+                let start =
+                    address_from_bytes(lo, hi).wrapping_add(u16::from(cpu.registers.index_x));
+                let [lo, hi] = cpu.read_address(start);
+                OpInput::UseAddress(address_from_bytes(lo, hi))
+            },
+        }
+    }
 
-            (Instruction::BIT, OpInput::UseAddress(addr)) => {
-                let a: u8 = self.registers.accumulator;
-                let m: u8 = self.memory.get_byte(addr);
-                let res = a & m;
-
-                // The zero flag is set based on the result of the 'and'.
-                let is_zero = 0 == res;
-
-                // The N flag is set to bit 7 of the byte from memory.
-                let bit7 = 0 != (0x80 & m);
-
-                // The V flag is set to bit 6 of the byte from memory.
-                let bit6 = 0 != (0x40 & m);
+    /// The routine carrying out `instruction` on its operand.
+    const fn executor(instruction: Instruction) -> Execute<M, V> {
+        match instruction {
+            Instruction::ADC => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.add_with_carry(val);
+            },
+            Instruction::ADCnd => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.add_with_no_decimal(val);
+            },
+            Instruction::AND => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.and(val);
+            },
+            Instruction::ASL => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::shift_left_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
 
-                self.registers.status.set_with_mask(
-                    Status::PS_ZERO | Status::PS_NEGATIVE | Status::PS_OVERFLOW,
+            Instruction::BCC => |cpu, input| cpu.branch_if_carry_clear(cpu.target(input)),
+            Instruction::BCS => |cpu, input| cpu.branch_if_carry_set(cpu.target(input)),
+            Instruction::BEQ => |cpu, input| cpu.branch_if_equal(cpu.target(input)),
+            Instruction::BNE => |cpu, input| cpu.branch_if_not_equal(cpu.target(input)),
+            Instruction::BMI => |cpu, input| cpu.branch_if_minus(cpu.target(input)),
+            Instruction::BPL => |cpu, input| cpu.branch_if_positive(cpu.target(input)),
+            Instruction::BRA => |cpu, input| cpu.branch(cpu.target(input)),
+            Instruction::BVC => |cpu, input| cpu.branch_if_overflow_clear(cpu.target(input)),
+            Instruction::BVS => |cpu, input| cpu.branch_if_overflow_set(cpu.target(input)),
+
+            Instruction::BIT => |cpu, input| {
+                let m = cpu.load(input);
+                // The zero flag is set based on the result of the 'and'. With
+                // a memory operand, N and V are set to bits 7 and 6 of the
+                // byte from memory.
+                let mask = match input {
+                    OpInput::UseImmediate(_) => Status::PS_ZERO,
+                    _ => Status::PS_ZERO | Status::PS_NEGATIVE | Status::PS_OVERFLOW,
+                };
+                cpu.registers.status.set_with_mask(
+                    mask,
                     Status::new(StatusArgs {
-                        zero: is_zero,
-                        negative: bit7,
-                        overflow: bit6,
+                        zero: 0 == (cpu.registers.accumulator & m),
+                        negative: 0 != (0x80 & m),
+                        overflow: 0 != (0x40 & m),
                         ..StatusArgs::none()
                     }),
                 );
-            }
-
-            (Instruction::BMI, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                log::debug!("branch if minus relative. address: {:?}", addr);
-                self.branch_if_minus(addr);
-            }
-
-            (Instruction::BPL, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_positive(addr);
-            }
-
-            (Instruction::BRA, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch(addr);
-            }
-
-            (Instruction::BRK, OpInput::UseImplied) => {
-                for b in self.registers.program_counter.wrapping_sub(1).to_be_bytes() {
-                    self.push_on_stack(b);
-                }
-                self.push_on_stack(self.registers.status.bits());
-                let pcl = self.memory.get_byte(0xfffe);
-                let pch = self.memory.get_byte(0xffff);
-                self.jump((u16::from(pch) << 8) | u16::from(pcl));
-                self.registers.status.or(Status::PS_DISABLE_INTERRUPTS);
-            }
-
-            (Instruction::BRKcld, OpInput::UseImplied) => {
-                for b in self.registers.program_counter.wrapping_sub(1).to_be_bytes() {
-                    self.push_on_stack(b);
-                }
-                self.push_on_stack(self.registers.status.bits());
-                let pcl = self.memory.get_byte(0xfffe);
-                let pch = self.memory.get_byte(0xffff);
-                self.jump((u16::from(pch) << 8) | u16::from(pcl));
-                self.registers.status.or(Status::PS_DISABLE_INTERRUPTS);
-                self.registers.status.and(!Status::PS_DECIMAL_MODE);
-            }
-
-            (Instruction::BVC, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_overflow_clear(addr);
-            }
-
-            (Instruction::BVS, OpInput::UseRelative(rel)) => {
-                let addr = self.registers.program_counter.wrapping_add(rel);
-                self.branch_if_overflow_set(addr);
-            }
-
-            (Instruction::CLC, OpInput::UseImplied) => {
-                self.registers.status.and(!Status::PS_CARRY);
-            }
-            (Instruction::CLD, OpInput::UseImplied) => {
-                self.registers.status.and(!Status::PS_DECIMAL_MODE);
-            }
-            (Instruction::CLI, OpInput::UseImplied) => {
-                self.registers.status.and(!Status::PS_DISABLE_INTERRUPTS);
-            }
-            (Instruction::CLV, OpInput::UseImplied) => {
-                self.registers.status.and(!Status::PS_OVERFLOW);
-            }
-
-            (Instruction::CMP, OpInput::UseImmediate(val)) => {
-                self.compare_with_a_register(val);
-            }
-            (Instruction::CMP, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.compare_with_a_register(val);
-            }
-
-            (Instruction::CPX, OpInput::UseImmediate(val)) => {
-                self.compare_with_x_register(val);
-            }
-            (Instruction::CPX, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.compare_with_x_register(val);
-            }
+            },
 
-            (Instruction::CPY, OpInput::UseImmediate(val)) => {
-                self.compare_with_y_register(val);
-            }
-            (Instruction::CPY, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.compare_with_y_register(val);
-            }
+            Instruction::BRK => |cpu, _| cpu.brk(),
+            Instruction::BRKcld => |cpu, _| {
+                cpu.brk();
+                cpu.registers.status.and(!Status::PS_DECIMAL_MODE);
+            },
 
-            (Instruction::DEC, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::decrement(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
+            Instruction::CLC => |cpu, _| cpu.registers.status.and(!Status::PS_CARRY),
+            Instruction::CLD => |cpu, _| cpu.registers.status.and(!Status::PS_DECIMAL_MODE),
+            Instruction::CLI => |cpu, _| cpu.registers.status.and(!Status::PS_DISABLE_INTERRUPTS),
+            Instruction::CLV => |cpu, _| cpu.registers.status.and(!Status::PS_OVERFLOW),
 
-            (Instruction::DEY, OpInput::UseImplied) => {
-                CPU::<M, V>::decrement(&mut self.registers.index_y, &mut self.registers.status);
-            }
-
-            (Instruction::DEX, OpInput::UseImplied) => {
-                CPU::<M, V>::decrement(&mut self.registers.index_x, &mut self.registers.status);
-            }
+            Instruction::CMP => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.compare_with_a_register(val);
+            },
+            Instruction::CPX => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.compare_with_x_register(val);
+            },
+            Instruction::CPY => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.compare_with_y_register(val);
+            },
 
-            (Instruction::EOR, OpInput::UseImmediate(val)) => {
-                self.exclusive_or(val);
-            }
-            (Instruction::EOR, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.exclusive_or(val);
-            }
+            Instruction::DEC => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::decrement(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
+            Instruction::DEX => |cpu, _| {
+                CPU::<M, V>::decrement(&mut cpu.registers.index_x, &mut cpu.registers.status);
+            },
+            Instruction::DEY => |cpu, _| {
+                CPU::<M, V>::decrement(&mut cpu.registers.index_y, &mut cpu.registers.status);
+            },
 
-            (Instruction::INC, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::increment(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
-            (Instruction::INX, OpInput::UseImplied) => {
-                CPU::<M, V>::increment(&mut self.registers.index_x, &mut self.registers.status);
-            }
-            (Instruction::INY, OpInput::UseImplied) => {
-                CPU::<M, V>::increment(&mut self.registers.index_y, &mut self.registers.status);
-            }
+            Instruction::EOR => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.exclusive_or(val);
+            },
 
-            (Instruction::JMP, OpInput::UseAddress(addr)) => self.jump(addr),
+            Instruction::INC => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::increment(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
+            Instruction::INX => |cpu, _| {
+                CPU::<M, V>::increment(&mut cpu.registers.index_x, &mut cpu.registers.status);
+            },
+            Instruction::INY => |cpu, _| {
+                CPU::<M, V>::increment(&mut cpu.registers.index_y, &mut cpu.registers.status);
+            },
 
-            (Instruction::JSR, OpInput::UseAddress(addr)) => {
-                for b in self.registers.program_counter.wrapping_sub(1).to_be_bytes() {
-                    self.push_on_stack(b);
+            Instruction::JMP => |cpu, input| cpu.jump(cpu.target(input)),
+            Instruction::JSR => |cpu, input| {
+                for b in cpu.registers.program_counter.wrapping_sub(1).to_be_bytes() {
+                    cpu.push_on_stack(b);
                 }
-                self.jump(addr);
-            }
-
-            (Instruction::LDA, OpInput::UseImmediate(val)) => {
-                log::debug!("load A immediate: {}", val);
-                self.load_accumulator(val);
-            }
-            (Instruction::LDA, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("load A. address: {:?}. value: {}", addr, val);
-                self.load_accumulator(val);
-            }
+                cpu.jump(cpu.target(input));
+            },
 
-            (Instruction::LDX, OpInput::UseImmediate(val)) => {
-                log::debug!("load X immediate: {}", val);
-                self.load_x_register(val);
-            }
-            (Instruction::LDX, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("load X. address: {:?}. value: {}", addr, val);
-                self.load_x_register(val);
-            }
+            Instruction::LDA => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.load_accumulator(val);
+            },
+            Instruction::LDX => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.load_x_register(val);
+            },
+            Instruction::LDY => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.load_y_register(val);
+            },
 
-            (Instruction::LDY, OpInput::UseImmediate(val)) => {
-                log::debug!("load Y immediate: {}", val);
-                self.load_y_register(val);
-            }
-            (Instruction::LDY, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("load Y. address: {:?}. value: {}", addr, val);
-                self.load_y_register(val);
-            }
+            Instruction::LSR => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::shift_right_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
 
-            (Instruction::LSR, OpInput::UseImplied) => {
-                // Accumulator mode
-                let mut val = self.registers.accumulator;
-                CPU::<M, V>::shift_right_with_flags(&mut val, &mut self.registers.status);
-                self.registers.accumulator = val;
-            }
-            (Instruction::LSR, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::shift_right_with_flags(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
+            Instruction::ORA => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.inclusive_or(val);
+            },
 
-            (Instruction::ORA, OpInput::UseImmediate(val)) => {
-                self.inclusive_or(val);
-            }
-            (Instruction::ORA, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                self.inclusive_or(val);
-            }
+            Instruction::PHA => |cpu, _| cpu.push_on_stack(cpu.registers.accumulator),
+            Instruction::PHX => |cpu, _| cpu.push_on_stack(cpu.registers.index_x),
+            Instruction::PHY => |cpu, _| cpu.push_on_stack(cpu.registers.index_y),
+            Instruction::PHP => |cpu, _| {
+                let val = cpu.registers.status.to_byte() | Status::PS_BRK.bits();
+                cpu.push_on_stack(val);
+            },
+            Instruction::PLA => |cpu, _| {
+                let val = cpu.pull();
+                cpu.registers.accumulator = val;
+                cpu.set_pulled_flags(val);
+            },
+            Instruction::PLX => |cpu, _| {
+                let val = cpu.pull();
+                cpu.registers.index_x = val;
+                cpu.set_pulled_flags(val);
+            },
+            Instruction::PLY => |cpu, _| {
+                let val = cpu.pull();
+                cpu.registers.index_y = val;
+                cpu.set_pulled_flags(val);
+            },
+            Instruction::PLP => |cpu, _| {
+                let val = cpu.pull();
+                cpu.registers.status = Status::from_byte(val);
+            },
 
-            (Instruction::PHA, OpInput::UseImplied) => {
-                // Push accumulator
-                let val = self.registers.accumulator;
-                self.push_on_stack(val);
-            }
-            (Instruction::PHX, OpInput::UseImplied) => {
-                // Push X
-                self.push_on_stack(self.registers.index_x);
-            }
-            (Instruction::PHY, OpInput::UseImplied) => {
-                // Push Y
-                self.push_on_stack(self.registers.index_y);
-            }
-            (Instruction::PHP, OpInput::UseImplied) => {
-                // Push status
-                let val = self.registers.status.bits() | 0x30;
-                self.push_on_stack(val);
-            }
-            (Instruction::PLX, OpInput::UseImplied) => {
-                // Pull accumulator
-                self.pull_from_stack();
-                let val: u8 = self.fetch_from_stack();
-                self.registers.index_x = val;
-                self.registers.status.set_with_mask(
-                    Status::PS_ZERO | Status::PS_NEGATIVE,
-                    Status::new(StatusArgs {
-                        zero: val == 0,
-                        negative: self.registers.accumulator > 127,
-                        ..StatusArgs::none()
-                    }),
-                );
-            }
-            (Instruction::PLY, OpInput::UseImplied) => {
-                // Pull accumulator
-                self.pull_from_stack();
-                let val: u8 = self.fetch_from_stack();
-                self.registers.index_y = val;
-                self.registers.status.set_with_mask(
-                    Status::PS_ZERO | Status::PS_NEGATIVE,
-                    Status::new(StatusArgs {
-                        zero: val == 0,
-                        negative: self.registers.accumulator > 127,
-                        ..StatusArgs::none()
-                    }),
-                );
-            }
-            (Instruction::PLA, OpInput::UseImplied) => {
-                // Pull accumulator
-                self.pull_from_stack();
-                let val: u8 = self.fetch_from_stack();
-                self.registers.accumulator = val;
-                self.registers.status.set_with_mask(
-                    Status::PS_ZERO | Status::PS_NEGATIVE,
-                    Status::new(StatusArgs {
-                        zero: val == 0,
-                        negative: self.registers.accumulator > 127,
-                        ..StatusArgs::none()
-                    }),
-                );
-            }
-            (Instruction::PLP, OpInput::UseImplied) => {
-                // Pull status
-                self.pull_from_stack();
-                let val: u8 = self.fetch_from_stack();
-                // The `truncate` here won't do anything because we have a
-                // constant for the single unused flags bit. This probably
-                // corresponds to the behavior of the 6502...? FIXME: verify
-                self.registers.status = Status::from_bits_truncate(val);
-            }
+            Instruction::ROL => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::rotate_left_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
+            Instruction::ROR => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::rotate_right_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+            },
 
-            (Instruction::ROL, OpInput::UseImplied) => {
-                // Accumulator mode
-                let mut val = self.registers.accumulator;
-                CPU::<M, V>::rotate_left_with_flags(&mut val, &mut self.registers.status);
-                self.registers.accumulator = val;
-            }
-            (Instruction::ROL, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::rotate_left_with_flags(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
-            (Instruction::ROR, OpInput::UseImplied) => {
-                // Accumulator mode
-                let mut val = self.registers.accumulator;
-                CPU::<M, V>::rotate_right_with_flags(&mut val, &mut self.registers.status);
-                self.registers.accumulator = val;
-            }
-            (Instruction::ROR, OpInput::UseAddress(addr)) => {
-                let mut operand: u8 = self.memory.get_byte(addr);
-                CPU::<M, V>::rotate_right_with_flags(&mut operand, &mut self.registers.status);
-                self.memory.set_byte(addr, operand);
-            }
-            (Instruction::RTI, OpInput::UseImplied) => {
+            Instruction::RTI => |cpu, _| {
                 // Pull status
-                self.pull_from_stack();
-                let val: u8 = self.pull_from_stack();
-                // The `truncate` here won't do anything because we have a
-                // constant for the single unused flags bit. This probably
-                // corresponds to the behavior of the 6502...? FIXME: verify
-                self.registers.status = Status::from_bits_truncate(val);
-                let pcl: u8 = self.pull_from_stack();
-                let pch: u8 = self.fetch_from_stack();
-                self.registers.program_counter = (u16::from(pch) << 8) | u16::from(pcl);
-            }
-            (Instruction::RTS, OpInput::UseImplied) => {
-                self.pull_from_stack();
-                let pcl: u8 = self.pull_from_stack();
-                let pch: u8 = self.fetch_from_stack();
-                self.registers.program_counter =
+                cpu.registers.stack_pointer.increment();
+                let val: u8 = cpu.pull_from_stack();
+                cpu.registers.status = Status::from_byte(val);
+                let pcl: u8 = cpu.pull_from_stack();
+                let pch: u8 = cpu.fetch_from_stack();
+                cpu.registers.program_counter = (u16::from(pch) << 8) | u16::from(pcl);
+            },
+            Instruction::RTS => |cpu, _| {
+                cpu.registers.stack_pointer.increment();
+                let pcl: u8 = cpu.pull_from_stack();
+                let pch: u8 = cpu.fetch_from_stack();
+                cpu.registers.program_counter =
                     ((u16::from(pch) << 8) | u16::from(pcl)).wrapping_add(1);
-            }
-
-            (Instruction::SBC, OpInput::UseImmediate(val)) => {
-                log::debug!("subtract with carry immediate: {}", val);
-                self.subtract_with_carry(val);
-            }
-            (Instruction::SBC, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("subtract with carry. address: {:?}. value: {}", addr, val);
-                self.subtract_with_carry(val);
-            }
+            },
 
-            (Instruction::SBCnd, OpInput::UseImmediate(val)) => {
-                log::debug!("subtract with carry immediate: {}", val);
-                self.subtract_with_no_decimal(val);
-            }
-            (Instruction::SBCnd, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
-                log::debug!("subtract with carry. address: {:?}. value: {}", addr, val);
-                self.subtract_with_no_decimal(val);
-            }
+            Instruction::SBC => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.subtract_with_carry(val);
+            },
+            Instruction::SBCnd => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.subtract_with_no_decimal(val);
+            },
 
-            (Instruction::SEC, OpInput::UseImplied) => {
-                self.registers.status.or(Status::PS_CARRY);
-            }
-            (Instruction::SED, OpInput::UseImplied) => {
-                self.registers.status.or(Status::PS_DECIMAL_MODE);
-            }
-            (Instruction::SEI, OpInput::UseImplied) => {
-                self.registers.status.or(Status::PS_DISABLE_INTERRUPTS);
-            }
+            Instruction::SEC => |cpu, _| cpu.registers.status.or(Status::PS_CARRY),
+            Instruction::SED => |cpu, _| cpu.registers.status.or(Status::PS_DECIMAL_MODE),
+            Instruction::SEI => |cpu, _| cpu.registers.status.or(Status::PS_DISABLE_INTERRUPTS),
 
-            (Instruction::STA, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.accumulator);
-            }
-            (Instruction::STX, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.index_x);
-            }
-            (Instruction::STY, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, self.registers.index_y);
-            }
-            (Instruction::STZ, OpInput::UseAddress(addr)) => {
-                self.memory.set_byte(addr, 0);
-            }
+            Instruction::STA => |cpu, input| cpu.store(input, cpu.registers.accumulator),
+            Instruction::STX => |cpu, input| cpu.store(input, cpu.registers.index_x),
+            Instruction::STY => |cpu, input| cpu.store(input, cpu.registers.index_y),
+            Instruction::STZ => |cpu, input| cpu.store(input, 0),
 
-            (Instruction::TAX, OpInput::UseImplied) => {
-                let val = self.registers.accumulator;
-                self.load_x_register(val);
-            }
-            (Instruction::TAY, OpInput::UseImplied) => {
-                let val = self.registers.accumulator;
-                self.load_y_register(val);
-            }
-            (Instruction::TRB, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
+            Instruction::TAX => |cpu, _| cpu.load_x_register(cpu.registers.accumulator),
+            Instruction::TAY => |cpu, _| cpu.load_y_register(cpu.registers.accumulator),
+            Instruction::TRB => |cpu, input| {
+                let val = cpu.load(input);
 
                 // The zero flag is set based on the result of the 'and'.
-                self.registers.status.set_with_mask(
+                cpu.registers.status.set_with_mask(
                     Status::PS_ZERO,
                     Status::new(StatusArgs {
-                        zero: 0 == (self.registers.accumulator & val),
+                        zero: 0 == (cpu.registers.accumulator & val),
                         ..StatusArgs::none()
                     }),
                 );
 
                 // The 1's in the accumulator set the corresponding bits in the operand
-                let res = self.registers.accumulator | val;
-                self.memory.set_byte(addr, res);
-            }
-            (Instruction::TSB, OpInput::UseAddress(addr)) => {
-                let val = self.memory.get_byte(addr);
+                let res = cpu.registers.accumulator | val;
+                cpu.store(input, res);
+            },
+            Instruction::TSB => |cpu, input| {
+                let val = cpu.load(input);
 
                 // The zero flag is set based on the result of the 'and'.
-                self.registers.status.set_with_mask(
+                cpu.registers.status.set_with_mask(
                     Status::PS_ZERO,
                     Status::new(StatusArgs {
-                        zero: 0 == (self.registers.accumulator & val),
+                        zero: 0 == (cpu.registers.accumulator & val),
                         ..StatusArgs::none()
                     }),
                 );
 
                 // The 1's in the accumulator clear the corresponding bits in the operand
-                let res = (self.registers.accumulator ^ 0xff) & val;
-                self.memory.set_byte(addr, res);
-            }
-            (Instruction::TSX, OpInput::UseImplied) => {
-                let StackPointer(val) = self.registers.stack_pointer;
-                self.load_x_register(val);
-            }
-            (Instruction::TXA, OpInput::UseImplied) => {
-                let val = self.registers.index_x;
-                self.load_accumulator(val);
-            }
-            (Instruction::TXS, OpInput::UseImplied) => {
-                // Note that this is the only 'transfer' instruction that does
-                // NOT set the zero and negative flags. (Because the target
-                // is the stack pointer)
-                let val = self.registers.index_x;
-                self.registers.stack_pointer = StackPointer(val);
-            }
-            (Instruction::TYA, OpInput::UseImplied) => {
-                let val = self.registers.index_y;
-                self.load_accumulator(val);
-            }
+                let res = (cpu.registers.accumulator ^ 0xff) & val;
+                cpu.store(input, res);
+            },
+            Instruction::TSX => |cpu, _| {
+                let StackPointer(val) = cpu.registers.stack_pointer;
+                cpu.load_x_register(val);
+            },
+            Instruction::TXA => |cpu, _| cpu.load_accumulator(cpu.registers.index_x),
+            // Note that this is the only 'transfer' instruction that does NOT
+            // set the zero and negative flags. (Because the target is the
+            // stack pointer)
+            Instruction::TXS => {
+                |cpu, _| cpu.registers.stack_pointer = StackPointer(cpu.registers.index_x)
+            }
+            Instruction::TYA => |cpu, _| cpu.load_accumulator(cpu.registers.index_y),
+
+            // The stable undocumented NMOS instructions. Most combine a
+            // read-modify-write with an accumulator operation on the result.
+            Instruction::ALR => |cpu, input| {
+                let mut val = cpu.registers.accumulator & cpu.load(input);
+                CPU::<M, V>::shift_right_with_flags(&mut val, &mut cpu.registers.status);
+                cpu.registers.accumulator = val;
+            },
+            Instruction::ANC => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.and(val);
+                let negative = cpu.registers.status.contains(Status::PS_NEGATIVE);
+                cpu.registers.status.set(Status::PS_CARRY, negative);
+            },
+            Instruction::ARR => |cpu, input| {
+                let val = cpu.load(input);
+                let decimal = cpu.decimal_mode();
+                cpu.arithmetic(alu::arr, val, decimal);
+            },
+            Instruction::ARRnd => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.arithmetic(alu::arr, val, false);
+            },
+            Instruction::DCP => |cpu, input| {
+                let operand = cpu.load(input).wrapping_sub(1);
+                cpu.store(input, operand);
+                cpu.compare_with_a_register(operand);
+            },
+            Instruction::ISC => |cpu, input| {
+                let operand = cpu.load(input).wrapping_add(1);
+                cpu.store(input, operand);
+                cpu.subtract_with_carry(operand);
+            },
+            Instruction::ISCnd => |cpu, input| {
+                let operand = cpu.load(input).wrapping_add(1);
+                cpu.store(input, operand);
+                cpu.subtract_with_no_decimal(operand);
+            },
+            Instruction::LAS => |cpu, input| {
+                let val = cpu.load(input) & cpu.registers.stack_pointer.0;
+                cpu.load_accumulator(val);
+                cpu.registers.index_x = val;
+                cpu.registers.stack_pointer.0 = val;
+            },
+            Instruction::LAX => |cpu, input| {
+                let val = cpu.load(input);
+                cpu.load_accumulator(val);
+                cpu.registers.index_x = val;
+            },
+            Instruction::RLA => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::rotate_left_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+                cpu.and(operand);
+            },
+            Instruction::RRA => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::rotate_right_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+                cpu.add_with_carry(operand);
+            },
+            Instruction::RRAnd => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::rotate_right_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+                cpu.add_with_no_decimal(operand);
+            },
+            Instruction::SAX => |cpu, input| {
+                cpu.store(input, cpu.registers.accumulator & cpu.registers.index_x);
+            },
+            Instruction::SBX => |cpu, input| {
+                let val = cpu.load(input);
+                let and = cpu.registers.accumulator & cpu.registers.index_x;
+                cpu.compare(and, val);
+                cpu.registers.index_x = and.wrapping_sub(val);
+            },
+            Instruction::SLO => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::shift_left_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+                cpu.inclusive_or(operand);
+            },
+            Instruction::SRE => |cpu, input| {
+                let mut operand = cpu.load(input);
+                CPU::<M, V>::shift_right_with_flags(&mut operand, &mut cpu.registers.status);
+                cpu.store(input, operand);
+                cpu.exclusive_or(operand);
+            },
 
-            (Instruction::NOP, OpInput::UseImplied) => {
-                log::debug!("NOP instruction");
-            }
-            (_, _) => {
-                log::debug!(
-                    "attempting to execute unimplemented or invalid \
-                     instruction"
-                );
+            // The 65C02's undefined opcodes are NOPs, some skipping an
+            // operand, as are some of the NMOS chip's.
+            Instruction::NOP => |_, _| {},
+        }
+    }
+
+    /// The value an instruction works on: its immediate operand, the byte at
+    /// its address, or the accumulator for those in accumulator mode.
+    fn load(&mut self, input: OpInput) -> u8 {
+        match input {
+            OpInput::UseImmediate(val) => val,
+            OpInput::UseAddress(addr) => self.read(addr),
+            OpInput::UseImplied | OpInput::UseRelative(_) => self.registers.accumulator,
+        }
+    }
+
+    /// Stores the result of an instruction where [`CPU::load`] found its
+    /// operand.
+    fn store(&mut self, input: OpInput, value: u8) {
+        match input {
+            OpInput::UseAddress(addr) => self.write(addr, value),
+            _ => self.registers.accumulator = value,
+        }
+    }
+
+    /// Where a jump or a taken branch goes.
+    const fn target(&self, input: OpInput) -> u16 {
+        match input {
+            OpInput::UseAddress(addr) => addr,
+            OpInput::UseRelative(rel) => self.registers.program_counter.wrapping_add(rel),
+            OpInput::UseImplied | OpInput::UseImmediate(_) => self.registers.program_counter,
+        }
+    }
+
+    /// `BRK` skips the byte after it, and pushes the status with B set to
+    /// tell the handler it wasn't an IRQ.
+    fn brk(&mut self) {
+        for b in self.registers.program_counter.wrapping_add(1).to_be_bytes() {
+            self.push_on_stack(b);
+        }
+        self.push_on_stack(self.registers.status.to_byte() | Status::PS_BRK.bits());
+        let vector = self.interrupts.brk_vector();
+        self.brk_vector = vector;
+        let pcl = self.read(vector);
+        let pch = self.read(vector.wrapping_add(1));
+        self.jump((u16::from(pch) << 8) | u16::from(pcl));
+        self.registers.status.or(Status::PS_DISABLE_INTERRUPTS);
+    }
+
+    /// Pulls a byte from the stack for `PLA`, `PLX` or `PLY`.
+    fn pull(&mut self) -> u8 {
+        self.registers.stack_pointer.increment();
+        self.fetch_from_stack()
+    }
+
+    /// Sets Z and N after `PLA`, `PLX` or `PLY` pulled `val`.
+    fn set_pulled_flags(&mut self, val: u8) {
+        self.registers.status.set_with_mask(
+            Status::PS_ZERO | Status::PS_NEGATIVE,
+            Status::new(StatusArgs {
+                zero: val == 0,
+                negative: val & 0x80 != 0,
+                ..StatusArgs::none()
+            }),
+        );
+    }
+
+    pub fn single_step(&mut self) -> Option<DecodedInstr> {
+        self.replay = None;
+        let start = self.cycles;
+        let decoded = self.execute_next()?;
+        for cycle in start..self.cycles {
+            self.memory.phi2(cycle);
+        }
+        Some(decoded)
+    }
+
+    /// Runs the CPU for one clock cycle and returns its bus activity.
+    ///
+    /// The instruction is executed in full on its first cycle; the following
+    /// calls return the rest of its cycles, and those of an interrupt taken
+    /// after it, calling [`Bus::phi2`] once per cycle. Without
+    /// `cycle_accurate`, while halted by [`CPU::steal_cycles`], and during
+    /// [`Bus::wait_states`], there are more cycles than accesses and the last
+    /// access is repeated.
+    ///
+    /// Returns `None`, without using a cycle, if the next opcode cannot be
+    /// decoded.
+    pub fn step_cycle(&mut self) -> Option<BusCycle> {
+        let mut replay = match self.replay {
+            Some(replay) if replay.position < replay.cycles => replay,
+            _ => {
+                let start = self.cycles;
+                self.execute_next()?;
+                Replay {
+                    start,
+                    cycles: self.cycles - start,
+                    position: 0,
+                }
             }
         };
+        let log = self.sequencer.log();
+        let index = usize::try_from(replay.position).unwrap_or(usize::MAX);
+        let cycle = log.get(index).or_else(|| log.last()).copied()?;
+        self.memory.phi2(replay.start + replay.position);
+        replay.position += 1;
+        self.replay = Some(replay);
+        Some(cycle)
     }
 
-    pub fn single_step(&mut self) -> Option<DecodedInstr> {
-        if let Some(decoded_instr) = self.fetch_next_and_decode() {
-            self.execute_instruction(decoded_instr);
-            Some(decoded_instr)
+    /// The bus activity of the last instruction, followed by that of the
+    /// interrupt sequence if one was taken after it, one entry per cycle.
+    ///
+    /// Dummy accesses are only made, and listed, with `cycle_accurate` set.
+    #[must_use]
+    pub fn bus_cycles(&self) -> &[BusCycle] {
+        self.sequencer.log()
+    }
+
+    /// Pin state for the cycle just executed: the one last returned by
+    /// [`CPU::step_cycle`], or the last cycle of an instruction run by
+    /// [`CPU::single_step`].
+    ///
+    /// Returns `None` before anything has run.
+    #[must_use]
+    pub fn pins(&self) -> Option<BusCycle> {
+        let log = self.sequencer.log();
+        match self.replay {
+            Some(replay) => {
+                let index = usize::try_from(replay.position - 1).unwrap_or(usize::MAX);
+                log.get(index).or_else(|| log.last()).copied()
+            }
+            None => log.last().copied(),
+        }
+    }
+
+    /// Executes one instruction, and the interrupt sequence if an IRQ is
+    /// taken after it, without clocking the bus.
+    fn execute_next(&mut self) -> Option<DecodedInstr> {
+        let start = self.cycles;
+        let pc = self.registers.program_counter;
+        self.taken = None;
+        self.latency = None;
+        let masked_before = self
+            .registers
+            .status
+            .contains(Status::PS_DISABLE_INTERRUPTS);
+        // Every sequence starts with the opcode fetch, so it is followed as
+        // part of a NOP's until the opcode is known. Buses whose reads have
+        // side effects see the opcode read only once.
+        let fetch = tstate::sequence(Instruction::NOP, AddressingMode::Implied);
+        self.sequencer.begin(
+            fetch,
+            AddressingMode::Implied,
+            pc,
+            self.registers.stack_pointer.0,
+            false,
+        );
+        let opcode = self.read(pc);
+        let (instruction, mode) = Self::opcode(opcode)
+            .map_or((Instruction::NOP, AddressingMode::Implied), |opcode| {
+                (opcode.instruction, opcode.mode)
+            });
+        let sequence = tstate::sequence(instruction, mode);
+        self.sequencer.decoded(sequence, mode);
+        let (entry, input) = self.fetch_operand(opcode)?;
+        let decoded_instr = (entry.instruction, input);
+        #[cfg(feature = "tracing")]
+        self.trace_instruction(pc, opcode, decoded_instr);
+        (entry.execute)(self, input);
+        self.dummy_cycles(true);
+        #[cfg(feature = "tracing")]
+        self.trace_control_flow(pc, decoded_instr.0);
+        self.cycles += u64::from(V::cycles(opcode));
+        self.cycles += u64::from(core::mem::take(&mut self.penalty_cycles));
+        let irq_masked = self.irq_masked(masked_before);
+        self.taken = self.interrupts.poll(irq_masked);
+        if let Some(request) = self.taken {
+            #[cfg(feature = "tracing")]
+            let from = self.registers.program_counter;
+            self.interrupt(request.vector());
+            #[cfg(feature = "tracing")]
+            self.spans.enter(tracing::debug_span!(
+                target: "mos6502::cpu",
+                "interrupt",
+                kind = match request {
+                    Request::Nmi => "nmi",
+                    Request::Irq => "irq",
+                },
+                vector = request.vector(),
+                handler = self.registers.program_counter,
+                from
+            ));
+        }
+        self.cycles += u64::from(core::mem::take(&mut self.wait_cycles));
+        let since = match self.taken {
+            Some(Request::Nmi) => self.nmi_since.take(),
+            Some(Request::Irq) => self.irq_since.take(),
+            None => None,
+        };
+        self.latency = since.map(|since| self.cycles - since);
+        self.honor_stall(start, sequence.trailing_writes());
+        Some(decoded_instr)
+    }
+
+    /// Makes the dummy accesses due before the next real one, or at the end
+    /// of the instruction with `finish` set, when `cycle_accurate` is set.
+    fn dummy_cycles(&mut self, finish: bool) {
+        if !self.cycle_accurate {
+            return;
+        }
+        let context = Context {
+            index_x: self.registers.index_x,
+            index_y: self.registers.index_y,
+            program_counter: self.registers.program_counter,
+            branch_taken: self.penalty_cycles > 0,
+        };
+        while let Some((access, address, value, sync)) = self.sequencer.next_dummy(context, finish)
+        {
+            self.wait_cycles += u32::from(self.memory.wait_states(address));
+            let data = match access {
+                Access::Read => self.memory.get_byte(address),
+                Access::Write => {
+                    self.memory.set_byte(address, value);
+                    value
+                }
+            };
+            #[cfg(feature = "alloc")]
+            if let Some(accesses) = &mut self.accesses {
+                accesses.push((access, address, data));
+            }
+            self.sequencer.dummy(access, address, data, sync);
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn trace_instruction(&self, pc: u16, opcode: u8, (instruction, operand): DecodedInstr) {
+        tracing::trace!(
+            target: "mos6502::cpu",
+            pc,
+            opcode,
+            ?instruction,
+            ?operand,
+            a = self.registers.accumulator,
+            x = self.registers.index_x,
+            y = self.registers.index_y,
+            p = self.registers.status.to_byte(),
+            sp = self.registers.stack_pointer.0,
+            cycles = self.cycles,
+            "instruction"
+        );
+    }
+
+    /// Opens a span when the instruction at `pc` entered a subroutine or
+    /// interrupt handler, and closes one when it returned.
+    #[cfg(feature = "tracing")]
+    fn trace_control_flow(&mut self, pc: u16, instruction: Instruction) {
+        let target = self.registers.program_counter;
+        match instruction {
+            Instruction::JSR => self.spans.enter(tracing::debug_span!(
+                target: "mos6502::cpu",
+                "subroutine",
+                address = target,
+                caller = pc
+            )),
+            Instruction::BRK | Instruction::BRKcld => self.spans.enter(tracing::debug_span!(
+                target: "mos6502::cpu",
+                "interrupt",
+                kind = "brk",
+                vector = self.brk_vector,
+                handler = target,
+                from = pc
+            )),
+            Instruction::RTS | Instruction::RTI => self.spans.exit(),
+            _ => {}
+        }
+    }
+
+    /// Whether IRQs are masked when the interrupt lines are polled at the
+    /// end of the instruction just executed, which started with the I flag
+    /// at `masked_before`.
+    const fn irq_masked(&self, masked_before: bool) -> bool {
+        // Instructions that change the status on their last cycle do so
+        // after the interrupt lines have been polled.
+        if self.cycle_accurate && self.sequencer.sequence().late_status() {
+            masked_before
         } else {
-            None
+            self.registers
+                .status
+                .contains(Status::PS_DISABLE_INTERRUPTS)
+        }
+    }
+
+    /// Runs the hardware interrupt sequence through the vector at `vector`:
+    /// pushes PC and the status with B clear, masks further IRQs and jumps to
+    /// the handler. Takes 7 cycles.
+    fn interrupt(&mut self, vector: u16) {
+        self.sequencer.begin(
+            tstate::INTERRUPT,
+            AddressingMode::Implied,
+            self.registers.program_counter,
+            self.registers.stack_pointer.0,
+            true,
+        );
+        for b in self.registers.program_counter.to_be_bytes() {
+            self.push_on_stack(b);
         }
+        let status = self.registers.status - Status::PS_BRK;
+        self.push_on_stack(status.to_byte());
+        self.registers.status.or(Status::PS_DISABLE_INTERRUPTS);
+        let pcl = self.read(vector);
+        let pch = self.read(vector.wrapping_add(1));
+        self.jump((u16::from(pch) << 8) | u16::from(pcl));
+        self.cycles += 7;
     }
 
     pub fn run(&mut self) {
-        while let Some(decoded_instr) = self.fetch_next_and_decode() {
-            self.execute_instruction(decoded_instr);
+        while self.single_step().is_some() {}
+    }
+
+    /// Charges the part of a pending cycle steal that overlaps the instruction
+    /// which started at cycle `start` and ends at `self.cycles` with
+    /// `trailing_writes` write cycles.
+    fn honor_stall(&mut self, start: u64, trailing_writes: u8) {
+        let Some(stall) = self.stall else {
+            return;
+        };
+        let end = self.cycles;
+        if stall.start >= end {
+            return;
+        }
+
+        let mut halt_at = stall.start.max(start);
+        if self.cycle_accurate {
+            // Trailing write cycles run to completion; the CPU halts on the
+            // opcode fetch of the next instruction instead.
+            if halt_at >= end - u64::from(trailing_writes) {
+                halt_at = end;
+            }
         }
+
+        let stall_end = stall.start + u64::from(stall.count);
+        self.cycles += stall_end.saturating_sub(halt_at);
+        self.stall = None;
     }
 
     /// Checks if a given `u8` value should be interpreted as negative when
@@ -779,64 +1273,27 @@ impl<M: Bus, V: Variant> CPU<M, V> {
     }
 
     fn shift_left_with_flags(p_val: &mut u8, status: &mut Status) {
-        let mask = 1 << 7;
-        let is_bit_7_set = (*p_val & mask) == mask;
-        let shifted = (*p_val & !(1 << 7)) << 1;
-        *p_val = shifted;
-        status.set_with_mask(
-            Status::PS_CARRY,
-            Status::new(StatusArgs {
-                carry: is_bit_7_set,
-                ..StatusArgs::none()
-            }),
-        );
-        CPU::<M, V>::set_flags_from_u8(status, *p_val);
+        let (result, flags) = alu::asl(*p_val);
+        *p_val = result;
+        status.set_with_mask(alu::NZC, flags);
     }
 
     fn shift_right_with_flags(p_val: &mut u8, status: &mut Status) {
-        let mask = 1;
-        let is_bit_0_set = (*p_val & mask) == mask;
-        *p_val >>= 1;
-        status.set_with_mask(
-            Status::PS_CARRY,
-            Status::new(StatusArgs {
-                carry: is_bit_0_set,
-                ..StatusArgs::none()
-            }),
-        );
-        CPU::<M, V>::set_flags_from_u8(status, *p_val);
+        let (result, flags) = alu::lsr(*p_val);
+        *p_val = result;
+        status.set_with_mask(alu::NZC, flags);
     }
 
     fn rotate_left_with_flags(p_val: &mut u8, status: &mut Status) {
-        let is_carry_set = status.contains(Status::PS_CARRY);
-        let mask = 1 << 7;
-        let is_bit_7_set = (*p_val & mask) == mask;
-        let shifted = (*p_val & !(1 << 7)) << 1;
-        *p_val = shifted + u8::from(is_carry_set);
-        status.set_with_mask(
-            Status::PS_CARRY,
-            Status::new(StatusArgs {
-                carry: is_bit_7_set,
-                ..StatusArgs::none()
-            }),
-        );
-        CPU::<M, V>::set_flags_from_u8(status, *p_val);
+        let (result, flags) = alu::rol(*p_val, status.contains(Status::PS_CARRY));
+        *p_val = result;
+        status.set_with_mask(alu::NZC, flags);
     }
 
     fn rotate_right_with_flags(p_val: &mut u8, status: &mut Status) {
-        let is_carry_set = status.contains(Status::PS_CARRY);
-        let mask = 1;
-        let is_bit_0_set = (*p_val & mask) == mask;
-        let shifted = *p_val >> 1;
-        *p_val = shifted + if is_carry_set { 1 << 7 } else { 0 };
-        status.set_with_mask(
-            Status::PS_CARRY,
-            Status::new(StatusArgs {
-                carry: is_bit_0_set,
-                ..StatusArgs::none()
-            }),
-        );
-        CPU::<M, V>::set_flags_from_u8(status, *p_val);
+        let (result, flags) = alu::ror(*p_val, status.contains(Status::PS_CARRY));
+        *p_val = result;
+        status.set_with_mask(alu::NZC, flags);
     }
 
     fn set_u8_with_flags(mem: &mut u8, status: &mut Status, value: u8) {
@@ -869,188 +1326,61 @@ impl<M: Bus, V: Variant> CPU<M, V> {
     }
 
     fn add_with_carry(&mut self, value: u8) {
-        const fn decimal_adjust(result: u8) -> u8 {
-            let bcd1: u8 = if (result & 0x0f) > 0x09 { 0x06 } else { 0x00 };
-
-            let bcd2: u8 = if (result.wrapping_add(bcd1) & 0xf0) > 0x90 {
-                0x60
-            } else {
-                0x00
-            };
-
-            result.wrapping_add(bcd1).wrapping_add(bcd2)
-        }
-
-        let a_before: u8 = self.registers.accumulator;
-        let c_before: u8 = u8::from(self.registers.status.contains(Status::PS_CARRY));
-        let a_after: u8 = a_before.wrapping_add(c_before).wrapping_add(value);
-
-        debug_assert_eq!(a_after, a_before.wrapping_add(c_before).wrapping_add(value));
-
-        let result: u8 = if self.registers.status.contains(Status::PS_DECIMAL_MODE) {
-            decimal_adjust(a_after)
-        } else {
-            a_after
-        };
-
-        let did_carry = (result) < (a_before)
-            || (a_after == 0 && c_before == 0x01)
-            || (value == 0xff && c_before == 0x01);
-
-        let did_overflow = (a_before > 127 && value > 127 && a_after < 128)
-            || (a_before < 128 && value < 128 && a_after > 127);
-
-        let mask = Status::PS_CARRY | Status::PS_OVERFLOW;
-
-        self.registers.status.set_with_mask(
-            mask,
-            Status::new(StatusArgs {
-                carry: did_carry,
-                overflow: did_overflow,
-                ..StatusArgs::none()
-            }),
-        );
-
-        self.load_accumulator(result);
+        let decimal = self.decimal_mode();
+        self.arithmetic(V::adc, value, decimal);
+        self.decimal_penalty(decimal);
 
         log::debug!("accumulator: {}", self.registers.accumulator);
     }
 
     fn add_with_no_decimal(&mut self, value: u8) {
-        let a_before: u8 = self.registers.accumulator;
-        let c_before: u8 = u8::from(self.registers.status.contains(Status::PS_CARRY));
-        let a_after: u8 = a_before.wrapping_add(c_before).wrapping_add(value);
-
-        debug_assert_eq!(a_after, a_before.wrapping_add(c_before).wrapping_add(value));
-
-        let result = a_after;
-
-        let did_carry = (result) < (a_before)
-            || (a_after == 0 && c_before == 0x01)
-            || (value == 0xff && c_before == 0x01);
-
-        let did_overflow = (a_before > 127 && value > 127 && a_after < 128)
-            || (a_before < 128 && value < 128 && a_after > 127);
-
-        let mask = Status::PS_CARRY | Status::PS_OVERFLOW;
-
-        self.registers.status.set_with_mask(
-            mask,
-            Status::new(StatusArgs {
-                carry: did_carry,
-                overflow: did_overflow,
-                ..StatusArgs::none()
-            }),
-        );
-
-        self.load_accumulator(result);
+        self.arithmetic(V::adc, value, false);
 
         log::debug!("accumulator: {}", self.registers.accumulator);
     }
 
+    /// Applies `operation`, [`Variant::adc`] or [`Variant::sbc`], to the
+    /// accumulator and `value`.
+    fn arithmetic(
+        &mut self,
+        operation: fn(u8, u8, bool, bool) -> (u8, Status),
+        value: u8,
+        decimal: bool,
+    ) {
+        let carry = self.registers.status.contains(Status::PS_CARRY);
+        let (result, flags) = operation(self.registers.accumulator, value, carry, decimal);
+        self.registers.accumulator = result;
+        self.registers.status.set_with_mask(alu::NVZC, flags);
+    }
+
     fn and(&mut self, value: u8) {
         let a_after = self.registers.accumulator & value;
         self.load_accumulator(a_after);
     }
 
     fn subtract_with_no_decimal(&mut self, value: u8) {
-        // A - M - (1 - C)
-
-        // nc -- 'not carry'
-        let nc: u8 = u8::from(!self.registers.status.contains(Status::PS_CARRY));
-
-        let a_before = self.registers.accumulator;
-
-        let a_after = a_before.wrapping_sub(value).wrapping_sub(nc);
-
-        // The overflow flag is set on two's-complement overflow.
-        //
-        // range of A              is  -128 to 127
-        // range of - M - (1 - C)  is  -128 to 128
-        //                             -(127 + 1) to -(-128 + 0)
-        //
-        let over = (nc == 0 && value > 127) && a_before < 128 && a_after > 127;
-
-        let under =
-            (a_before > 127) && (0u8.wrapping_sub(value).wrapping_sub(nc) > 127) && a_after < 128;
-
-        let did_overflow = over || under;
-
-        let mask = Status::PS_CARRY | Status::PS_OVERFLOW;
-
-        let result = a_after;
-
-        // The carry flag is set on unsigned overflow.
-        let did_carry = (result) > (a_before);
-
-        self.registers.status.set_with_mask(
-            mask,
-            Status::new(StatusArgs {
-                carry: did_carry,
-                overflow: did_overflow,
-                ..StatusArgs::none()
-            }),
-        );
-
-        self.load_accumulator(result);
+        self.arithmetic(V::sbc, value, false);
     }
 
     fn subtract_with_carry(&mut self, value: u8) {
-        // A - M - (1 - C)
-
-        // nc -- 'not carry'
-        let nc: u8 = u8::from(!self.registers.status.contains(Status::PS_CARRY));
-
-        let a_before = self.registers.accumulator;
-
-        let a_after = a_before.wrapping_sub(value).wrapping_sub(nc);
-
-        // The overflow flag is set on two's-complement overflow.
-        //
-        // range of A              is  -128 to 127
-        // range of - M - (1 - C)  is  -128 to 128
-        //                             -(127 + 1) to -(-128 + 0)
-        //
-        let over = (nc == 0 && value > 127) && a_before < 128 && a_after > 127;
-
-        let under =
-            (a_before > 127) && (0u8.wrapping_sub(value).wrapping_sub(nc) > 127) && a_after < 128;
-
-        let did_overflow = over || under;
-
-        let mask = Status::PS_CARRY | Status::PS_OVERFLOW;
-
-        let bcd1: u8 = if (a_before & 0x0f).wrapping_sub(nc) < (value & 0x0f) {
-            0x06
-        } else {
-            0x00
-        };
-
-        let bcd2: u8 = if (a_after.wrapping_sub(bcd1) & 0xf0) > 0x90 {
-            0x60
-        } else {
-            0x00
-        };
-
-        let result: u8 = if self.registers.status.contains(Status::PS_DECIMAL_MODE) {
-            a_after.wrapping_sub(bcd1).wrapping_sub(bcd2)
-        } else {
-            a_after
-        };
-
-        // The carry flag is set on unsigned overflow.
-        let did_carry = (result) > (a_before);
+        let decimal = self.decimal_mode();
+        self.arithmetic(V::sbc, value, decimal);
+        self.decimal_penalty(decimal);
+    }
 
-        self.registers.status.set_with_mask(
-            mask,
-            Status::new(StatusArgs {
-                carry: did_carry,
-                overflow: did_overflow,
-                ..StatusArgs::none()
-            }),
-        );
+    /// Whether `ADC` and `SBC` work in BCD: the decimal flag is set and the
+    /// `decimal_mode` feature is enabled. Variants without decimal mode,
+    /// such as [`Ricoh2a03`](crate::instruction::Ricoh2a03), decode them
+    /// to instructions that ignore the flag instead.
+    const fn decimal_mode(&self) -> bool {
+        cfg!(feature = "decimal_mode") && self.registers.status.contains(Status::PS_DECIMAL_MODE)
+    }
 
-        self.load_accumulator(result);
+    /// Charges the extra cycle decimal arithmetic takes on some variants.
+    fn decimal_penalty(&mut self, decimal: bool) {
+        if decimal && V::decimal_extra_cycle() {
+            self.penalty_cycles += 1;
+        }
     }
 
     fn increment(val: &mut u8, flags: &mut Status) {
@@ -1086,92 +1416,75 @@ impl<M: Bus, V: Variant> CPU<M, V> {
         );
     }
 
-    fn jump(&mut self, addr: u16) {
+    const fn jump(&mut self, addr: u16) {
+        self.registers.program_counter = addr;
+    }
+
+    /// Moves PC to a branch target, charging the extra cycle of a taken
+    /// branch and one more if the target is on a different page than the
+    /// next instruction.
+    const fn take_branch(&mut self, addr: u16) {
+        let next = self.registers.program_counter;
+        self.penalty_cycles += if next & 0xFF00 == addr & 0xFF00 { 1 } else { 2 };
         self.registers.program_counter = addr;
     }
 
-    fn branch_if_carry_clear(&mut self, addr: u16) {
+    const fn branch_if_carry_clear(&mut self, addr: u16) {
         if !self.registers.status.contains(Status::PS_CARRY) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_carry_set(&mut self, addr: u16) {
+    const fn branch_if_carry_set(&mut self, addr: u16) {
         if self.registers.status.contains(Status::PS_CARRY) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_equal(&mut self, addr: u16) {
+    const fn branch_if_equal(&mut self, addr: u16) {
         if self.registers.status.contains(Status::PS_ZERO) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_not_equal(&mut self, addr: u16) {
+    const fn branch_if_not_equal(&mut self, addr: u16) {
         if !self.registers.status.contains(Status::PS_ZERO) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_minus(&mut self, addr: u16) {
+    const fn branch_if_minus(&mut self, addr: u16) {
         if self.registers.status.contains(Status::PS_NEGATIVE) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch(&mut self, addr: u16) {
-        self.registers.program_counter = addr;
+    const fn branch(&mut self, addr: u16) {
+        self.take_branch(addr);
     }
 
-    fn branch_if_positive(&mut self, addr: u16) {
+    const fn branch_if_positive(&mut self, addr: u16) {
         if !self.registers.status.contains(Status::PS_NEGATIVE) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_overflow_clear(&mut self, addr: u16) {
+    const fn branch_if_overflow_clear(&mut self, addr: u16) {
         if !self.registers.status.contains(Status::PS_OVERFLOW) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    fn branch_if_overflow_set(&mut self, addr: u16) {
+    const fn branch_if_overflow_set(&mut self, addr: u16) {
         if self.registers.status.contains(Status::PS_OVERFLOW) {
-            self.registers.program_counter = addr;
+            self.take_branch(addr);
         }
     }
 
-    // From http://www.6502.org/tutorials/compare_beyond.html:
-    //   If the Z flag is 0, then A <> NUM and BNE will branch
-    //   If the Z flag is 1, then A = NUM and BEQ will branch
-    //   If the C flag is 0, then A (unsigned) < NUM (unsigned) and BCC will branch
-    //   If the C flag is 1, then A (unsigned) >= NUM (unsigned) and BCS will branch
-    //   ...
-    //   The N flag contains most significant bit of the subtraction result.
     fn compare(&mut self, r: u8, val: u8) {
-        // Setting the CARRY flag: A (unsigned) >= NUM (unsigned)
-        if r >= val {
-            self.registers.status.insert(Status::PS_CARRY);
-        } else {
-            self.registers.status.remove(Status::PS_CARRY);
-        }
-
-        // Setting the ZERO flag: A = NUM
-        if r == val {
-            self.registers.status.insert(Status::PS_ZERO);
-        } else {
-            self.registers.status.remove(Status::PS_ZERO);
-        }
-
-        // Set the NEGATIVE flag based on the MSB of the result of subtraction
-        // This checks if the 8th bit is set (0x80 in hex is 128 in decimal, which is the 8th bit in a byte)
-        let diff = r.wrapping_sub(val);
-        if Self::value_is_negative(diff) {
-            self.registers.status.insert(Status::PS_NEGATIVE);
-        } else {
-            self.registers.status.remove(Status::PS_NEGATIVE);
-        }
+        self.registers
+            .status
+            .set_with_mask(alu::NZC, alu::compare(r, val));
     }
 
     fn compare_with_a_register(&mut self, val: u8) {
@@ -1201,15 +1514,47 @@ impl<M: Bus, V: Variant> CPU<M, V> {
         self.load_accumulator(a_after);
     }
 
+    /// Reads a byte from the bus, recording the access if requested.
+    fn read(&mut self, address: u16) -> u8 {
+        self.dummy_cycles(false);
+        self.wait_cycles += u32::from(self.memory.wait_states(address));
+        let value = self.memory.get_byte(address);
+        #[cfg(feature = "alloc")]
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push((Access::Read, address, value));
+        }
+        self.sequencer.real(Access::Read, address, value);
+        value
+    }
+
+    /// Writes a byte to the bus, recording the access if requested.
+    fn write(&mut self, address: u16, value: u8) {
+        self.dummy_cycles(false);
+        self.wait_cycles += u32::from(self.memory.wait_states(address));
+        self.memory.set_byte(address, value);
+        #[cfg(feature = "alloc")]
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push((Access::Write, address, value));
+        }
+        self.sequencer.real(Access::Write, address, value);
+    }
+
+    /// Reads a 16-bit address from memory.
+    fn read_address(&mut self, address: u16) -> [u8; 2] {
+        let lo = self.read(address);
+        let hi = self.read(address.wrapping_add(1));
+        [lo, hi]
+    }
+
     fn push_on_stack(&mut self, val: u8) {
         let addr = self.registers.stack_pointer.to_u16();
-        self.memory.set_byte(addr, val);
+        self.write(addr, val);
         self.registers.stack_pointer.decrement();
     }
 
     fn pull_from_stack(&mut self) -> u8 {
         let addr = self.registers.stack_pointer.to_u16();
-        let out = self.memory.get_byte(addr);
+        let out = self.read(addr);
         self.registers.stack_pointer.increment();
         out
     }
@@ -1217,17 +1562,116 @@ impl<M: Bus, V: Variant> CPU<M, V> {
     fn fetch_from_stack(&mut self) -> u8 {
         // gets the next value on the stack but does not update the stack pointer
         let addr = self.registers.stack_pointer.to_u16();
-        self.memory.get_byte(addr)
+        self.read(addr)
     }
 }
 
+/// A 6502 core attached to a bus: the interpreter in [`CPU`], the
+/// transistor-level `netlist::GateLevel` with the `netlist` feature, or
+/// another backend. Code written against this trait, such as
+/// [`Scheduler::step`](crate::system::Scheduler::step), runs on any of them.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::{Cpu, CPU};
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+///
+/// fn run<C: Cpu>(cpu: &mut C, instructions: usize) -> u64 {
+///     for _ in 0..instructions {
+///         cpu.step();
+///     }
+///     cpu.cycles()
+/// }
+///
+/// let mut cpu = CPU::new(Memory::new(), Nmos6502);
+/// // INX; INX
+/// cpu.memory.set_bytes(0, &[0xe8, 0xe8]);
+/// assert_eq!(run(&mut cpu, 2), 4);
+/// assert_eq!(Cpu::registers(&cpu).index_x, 2);
+/// ```
+pub trait Cpu {
+    type Bus: Bus;
+    /// What [`Cpu::step`] returns about the instruction it ran.
+    type Step;
+
+    /// Executes the next instruction, and the interrupt sequence if one is
+    /// taken after it. Returns `None` if the core could not execute it.
+    fn step(&mut self) -> Option<Self::Step>;
+
+    /// Clock cycles run so far.
+    fn cycles(&self) -> u64;
+
+    /// Drives the IRQ line; `true` asserts it.
+    fn set_irq(&mut self, asserted: bool);
+
+    /// Drives the NMI line; `true` asserts it.
+    fn set_nmi(&mut self, asserted: bool);
+
+    fn registers(&self) -> Registers;
+
+    fn memory(&self) -> &Self::Bus;
+
+    fn memory_mut(&mut self) -> &mut Self::Bus;
+
+    fn reset(&mut self);
+}
+
+impl<M: Bus, V: Variant> Cpu for CPU<M, V> {
+    type Bus = M;
+    type Step = DecodedInstr;
+
+    fn step(&mut self) -> Option<DecodedInstr> {
+        self.single_step()
+    }
+
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn set_irq(&mut self, asserted: bool) {
+        CPU::set_irq(self, asserted);
+    }
+
+    fn set_nmi(&mut self, asserted: bool) {
+        CPU::set_nmi(self, asserted);
+    }
+
+    fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        CPU::reset(self);
+    }
+}
+
+/// The registers, decoded flags and cycle count of a [`CpuState`], leaving
+/// out the bus.
+///
+/// [`CpuState`]: crate::state::CpuState
 impl<M: Bus, V: Variant> core::fmt::Debug for CPU<M, V> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(
-            f,
-            "CPU Dump:\n\nAccumulator: {}",
-            self.registers.accumulator
-        )
+        let state = crate::state::CpuState::capture(self);
+        let registers = state.registers;
+        f.debug_struct("CPU")
+            .field("pc", &format_args!("${:04X}", registers.program_counter))
+            .field("a", &format_args!("${:02X}", registers.accumulator))
+            .field("x", &format_args!("${:02X}", registers.index_x))
+            .field("y", &format_args!("${:02X}", registers.index_y))
+            .field("sp", &format_args!("${:02X}", registers.stack_pointer.0))
+            .field("flags", &format_args!("{}", Flags(registers.status)))
+            .field("cycles", &state.cycles)
+            .finish_non_exhaustive()
     }
 }
 
@@ -1253,11 +1697,13 @@ mod tests {
 
         cpu.subtract_with_carry(0x80);
         assert_eq!(cpu.registers.accumulator, 0x80);
+        // That borrowed, so this takes one more off.
         cpu.subtract_with_carry(0x80);
-        assert_eq!(cpu.registers.accumulator, 0);
+        assert_eq!(cpu.registers.accumulator, 0xff);
     }
 
-    #[cfg_attr(feature = "decimal_mode", test)]
+    #[cfg(feature = "decimal_mode")]
+    #[test]
     fn decimal_add_test() {
         let mut cpu = CPU::new(Ram::new(), Nmos6502);
         cpu.registers.status.or(Status::PS_DECIMAL_MODE);
@@ -1276,15 +1722,54 @@ mod tests {
         assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
 
+        // The NMOS chip takes N and V from 0xa0, before the high digit is
+        // corrected, and Z from the binary sum 0x9a.
         cpu.add_with_carry(0x48);
         assert_eq!(cpu.registers.accumulator, 0x00);
         assert!(cpu.registers.status.contains(Status::PS_CARRY));
-        assert!(cpu.registers.status.contains(Status::PS_ZERO));
-        assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
+        assert!(!cpu.registers.status.contains(Status::PS_ZERO));
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(cpu.registers.status.contains(Status::PS_OVERFLOW));
     }
 
-    #[cfg_attr(feature = "decimal_mode", test)]
+    #[cfg(feature = "decimal_mode")]
+    #[test]
+    fn decimal_edge_cases_test() {
+        let decimal = |a: u8, b: u8| {
+            let mut cpu = CPU::new(Ram::new(), Nmos6502);
+            cpu.registers.status.or(Status::PS_DECIMAL_MODE);
+            cpu.registers.accumulator = a;
+            cpu.add_with_carry(b);
+            (cpu.registers.accumulator, cpu.registers.status & alu::NVZC)
+        };
+        assert_eq!(
+            decimal(0x99, 0x01),
+            (0x00, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+        assert_eq!(decimal(0x0f, 0x0f), (0x14, Status::empty()));
+        assert_eq!(
+            decimal(0x9a, 0x00),
+            (0x00, Status::PS_NEGATIVE | Status::PS_CARRY)
+        );
+
+        let mut cmos = CPU::new(Ram::new(), crate::instruction::Cmos6502);
+        cmos.registers.status.or(Status::PS_DECIMAL_MODE);
+        cmos.registers.accumulator = 0x99;
+        cmos.add_with_carry(0x01);
+        assert_eq!(cmos.registers.accumulator, 0x00);
+        assert!(cmos.registers.status.contains(Status::PS_ZERO));
+        assert!(!cmos.registers.status.contains(Status::PS_NEGATIVE));
+
+        // The 2A03 ignores the decimal flag.
+        let mut nes = CPU::new(Ram::new(), crate::instruction::Ricoh2a03);
+        nes.registers.status.or(Status::PS_DECIMAL_MODE);
+        nes.registers.accumulator = 0x99;
+        nes.add_with_no_decimal(0x01);
+        assert_eq!(nes.registers.accumulator, 0x9a);
+    }
+
+    #[cfg(feature = "decimal_mode")]
+    #[test]
     fn decimal_subtract_test() {
         let mut cpu = CPU::new(Ram::new(), Nmos6502);
         cpu.registers
@@ -1292,15 +1777,17 @@ mod tests {
             .or(Status::PS_DECIMAL_MODE | Status::PS_CARRY);
         cpu.registers.accumulator = 0;
 
+        // The flags come from the binary difference, 0xb8, which borrowed.
         cpu.subtract_with_carry(0x48);
         assert_eq!(cpu.registers.accumulator, 0x52);
-        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
-        assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
 
         cpu.subtract_with_carry(0x43);
-        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
+        assert_eq!(cpu.registers.accumulator, 0x08);
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1412,6 +1899,42 @@ mod tests {
         assert_eq!(cpu.registers.accumulator, 0x30);
     }
 
+    #[test]
+    fn plx_and_ply_set_flags_from_the_pulled_value() {
+        use crate::instruction::Cmos6502;
+
+        let mut cpu = CPU::new(Ram::new(), Cmos6502);
+        cpu.registers.accumulator = 0x80;
+        cpu.registers.index_y = 0x01;
+        cpu.execute_instruction((Instruction::PHY, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::PLX, OpInput::UseImplied));
+        assert_eq!(cpu.registers.index_x, 0x01);
+        assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
+
+        cpu.registers.accumulator = 0x01;
+        cpu.registers.index_x = 0x80;
+        cpu.execute_instruction((Instruction::PHX, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::PLY, OpInput::UseImplied));
+        assert_eq!(cpu.registers.index_y, 0x80);
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
+        assert!(!cpu.registers.status.contains(Status::PS_ZERO));
+    }
+
+    #[test]
+    fn plp_and_brk_keep_bit_5_set() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.registers.accumulator = 0x00;
+        cpu.execute_instruction((Instruction::PHA, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::PLP, OpInput::UseImplied));
+        assert_eq!(cpu.registers.status.to_byte(), 0x20);
+
+        // Even a status that lost bit 5 internally pushes it set.
+        cpu.registers.status = Status::empty();
+        cpu.execute_instruction((Instruction::BRK, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::PLA, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator & 0x20, 0x20);
+    }
+
     #[test]
     fn and_test() {
         let mut cpu = CPU::new(Ram::new(), Nmos6502);
@@ -1450,7 +1973,7 @@ mod tests {
 
         cpu.subtract_with_carry(1);
         assert_eq!(cpu.registers.accumulator, 0xff);
-        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1459,7 +1982,7 @@ mod tests {
         cpu.registers.accumulator = 0x80;
         cpu.subtract_with_carry(1);
         assert_eq!(cpu.registers.accumulator, 127);
-        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1468,7 +1991,7 @@ mod tests {
         cpu.registers.accumulator = 127;
         cpu.subtract_with_carry(0xff);
         assert_eq!(cpu.registers.accumulator, 0x80);
-        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1477,7 +2000,7 @@ mod tests {
         cpu.registers.accumulator = -64i8 as u8;
         cpu.subtract_with_carry(64);
         assert_eq!(cpu.registers.accumulator, 127);
-        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1486,7 +2009,7 @@ mod tests {
         cpu.registers.accumulator = 0;
         cpu.subtract_with_carry(0x80);
         assert_eq!(cpu.registers.accumulator, 0x80);
-        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1495,7 +2018,7 @@ mod tests {
         cpu.registers.accumulator = 0;
         cpu.subtract_with_carry(127);
         assert_eq!(cpu.registers.accumulator, 0x80);
-        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
@@ -1526,7 +2049,7 @@ mod tests {
         assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
 
         cpu.execute_instruction((Instruction::DEC, OpInput::UseAddress(addr)));
-        assert_eq!(cpu.memory.get_byte(addr) as i8, -1);
+        assert_eq!(cpu.memory.get_byte(addr), 0xff);
         assert!(!cpu.registers.status.contains(Status::PS_ZERO));
         assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
 
@@ -1596,6 +2119,134 @@ mod tests {
         assert!(!cpu.registers.status.contains(Status::PS_OVERFLOW));
     }
 
+    #[test]
+    fn shifts_and_rotates_test() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.execute_instruction((Instruction::LDA, OpInput::UseImmediate(0x81)));
+        cpu.execute_instruction((Instruction::ASL, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator, 0x02);
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+
+        cpu.execute_instruction((Instruction::ROL, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator, 0x05);
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
+
+        cpu.execute_instruction((Instruction::ROR, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator, 0x02);
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+
+        cpu.memory.set_byte(0x10, 0x40);
+        cpu.execute_instruction((Instruction::ROR, OpInput::UseAddress(0x10)));
+        assert_eq!(cpu.memory.get_byte(0x10), 0xa0);
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
+        cpu.execute_instruction((Instruction::ASL, OpInput::UseAddress(0x10)));
+        assert_eq!(cpu.memory.get_byte(0x10), 0x40);
+        assert!(cpu.registers.status.contains(Status::PS_CARRY));
+        cpu.execute_instruction((Instruction::LSR, OpInput::UseAddress(0x10)));
+        assert_eq!(cpu.memory.get_byte(0x10), 0x20);
+        assert!(!cpu.registers.status.contains(Status::PS_CARRY));
+    }
+
+    #[test]
+    fn stores_and_transfers_test() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.execute_instruction((Instruction::LDA, OpInput::UseImmediate(0x80)));
+        cpu.execute_instruction((Instruction::TAX, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::TAY, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::STA, OpInput::UseAddress(0x10)));
+        cpu.execute_instruction((Instruction::STX, OpInput::UseAddress(0x11)));
+        cpu.execute_instruction((Instruction::STY, OpInput::UseAddress(0x12)));
+        assert_eq!(cpu.memory.get_bytes(0x10..0x13), [0x80, 0x80, 0x80]);
+
+        cpu.execute_instruction((Instruction::LDX, OpInput::UseImmediate(0)));
+        cpu.execute_instruction((Instruction::TXA, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator, 0);
+        assert!(cpu.registers.status.contains(Status::PS_ZERO));
+        cpu.execute_instruction((Instruction::TYA, OpInput::UseImplied));
+        assert_eq!(cpu.registers.accumulator, 0x80);
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
+
+        // TXS leaves the flags alone; TSX sets them.
+        cpu.execute_instruction((Instruction::TXS, OpInput::UseImplied));
+        assert_eq!(cpu.registers.stack_pointer.0, 0);
+        assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
+        cpu.execute_instruction((Instruction::TSX, OpInput::UseImplied));
+        assert!(cpu.registers.status.contains(Status::PS_ZERO));
+    }
+
+    #[test]
+    fn bit_and_increment_test() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory.set_byte(0x10, 0xc0);
+        cpu.execute_instruction((Instruction::LDA, OpInput::UseImmediate(0x01)));
+        cpu.execute_instruction((Instruction::BIT, OpInput::UseAddress(0x10)));
+        assert!(cpu
+            .registers
+            .status
+            .contains(Status::PS_NEGATIVE | Status::PS_OVERFLOW | Status::PS_ZERO));
+        assert_eq!(cpu.registers.accumulator, 0x01);
+
+        cpu.memory.set_byte(0x11, 0xff);
+        cpu.execute_instruction((Instruction::INC, OpInput::UseAddress(0x11)));
+        assert_eq!(cpu.memory.get_byte(0x11), 0);
+        assert!(cpu.registers.status.contains(Status::PS_ZERO));
+        cpu.execute_instruction((Instruction::INX, OpInput::UseImplied));
+        cpu.execute_instruction((Instruction::INY, OpInput::UseImplied));
+        assert_eq!((cpu.registers.index_x, cpu.registers.index_y), (1, 1));
+    }
+
+    #[test]
+    fn flag_instructions_test() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.registers.status = Status::empty();
+        for instruction in [Instruction::SEC, Instruction::SED, Instruction::SEI] {
+            cpu.execute_instruction((instruction, OpInput::UseImplied));
+        }
+        assert_eq!(
+            cpu.registers.status,
+            Status::PS_CARRY | Status::PS_DECIMAL_MODE | Status::PS_DISABLE_INTERRUPTS
+        );
+        cpu.registers.status.insert(Status::PS_OVERFLOW);
+        for instruction in [
+            Instruction::CLC,
+            Instruction::CLD,
+            Instruction::CLI,
+            Instruction::CLV,
+        ] {
+            cpu.execute_instruction((instruction, OpInput::UseImplied));
+        }
+        assert_eq!(cpu.registers.status, Status::empty());
+    }
+
+    #[test]
+    fn subroutines_and_interrupts_return_test() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // JSR $0300; BRK; .byte $ff; INX
+        cpu.memory
+            .set_bytes(0x0200, &[0x20, 0x00, 0x03, 0x00, 0xff, 0xe8]);
+        // $0300: RTS; $0400: RTI
+        cpu.memory.set_byte(0x0300, 0x60);
+        cpu.memory.set_byte(0x0400, 0x40);
+        cpu.memory.set_bytes(0xfffe, &[0x00, 0x04]);
+        cpu.registers.program_counter = 0x0200;
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+        assert_eq!(cpu.memory.get_bytes(0x01fc..0x01fe), [0x02, 0x02]);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0203);
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0400);
+        assert!(cpu.registers.status.contains(Status::PS_DISABLE_INTERRUPTS));
+        // BRK pushes the address two past itself and the status with B set.
+        assert_eq!(cpu.memory.get_bytes(0x01fb..0x01fe), [0x34, 0x05, 0x02]);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0205);
+        cpu.single_step();
+        assert_eq!(cpu.registers.index_x, 1);
+    }
+
     #[test]
     fn dec_x_test() {
         let mut cpu = CPU::new(Ram::new(), Nmos6502);
@@ -1858,7 +2509,7 @@ mod tests {
                     assert!(!cpu.registers.status.contains(Status::PS_ZERO));
                 }
 
-                if (a_after as i8) < 0 {
+                if a_after & 0x80 != 0 {
                     assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
                 } else {
                     assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
@@ -1886,7 +2537,7 @@ mod tests {
                     assert!(!cpu.registers.status.contains(Status::PS_ZERO));
                 }
 
-                if (a_after as i8) < 0 {
+                if a_after & 0x80 != 0 {
                     assert!(cpu.registers.status.contains(Status::PS_NEGATIVE));
                 } else {
                     assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
@@ -1900,4 +2551,829 @@ mod tests {
         let mut cpu = CPU::new(Ram::new(), Nmos6502);
         let _val: u8 = cpu.pull_from_stack();
     }
+
+    #[test]
+    fn stack_wraps_within_page_one() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.registers.stack_pointer = StackPointer(0x00);
+        cpu.push_byte(0x12);
+        cpu.push_byte(0x34);
+        assert_eq!(cpu.memory.get_byte(0x0100), 0x12);
+        assert_eq!(cpu.memory.get_byte(0x01ff), 0x34);
+        assert_eq!(cpu.memory.get_byte(0x0000), 0x00);
+        assert_eq!(cpu.registers.stack_pointer, StackPointer(0xfe));
+        assert_eq!(cpu.pull_byte(), 0x34);
+        assert_eq!(cpu.pull_byte(), 0x12);
+        assert_eq!(cpu.registers.stack_pointer, StackPointer(0x00));
+
+        // LDX #$00; TXS; LDA #$56; PHA; PHP; PLA; PLP; TSX
+        cpu.memory.set_bytes(
+            0x0200,
+            &[0xa2, 0x00, 0x9a, 0xa9, 0x56, 0x48, 0x08, 0x68, 0x28, 0xba],
+        );
+        cpu.registers.program_counter = 0x0200;
+        for _ in 0..6 {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.memory.get_byte(0x0100), 0x56);
+        assert_eq!(cpu.registers.stack_pointer, StackPointer(0xff));
+        // PLA pulled the status PHP pushed, with B set.
+        assert_eq!(
+            cpu.registers.accumulator,
+            cpu.registers.status.to_byte() | Status::PS_BRK.bits()
+        );
+        // PLP pulls what PHA pushed, back across the wrap.
+        cpu.single_step();
+        assert_eq!(cpu.registers.status.to_byte(), 0x56 | 0x20);
+        cpu.single_step();
+        assert_eq!(cpu.registers.index_x, 0x00);
+    }
+
+    #[test]
+    fn jsr_pushes_the_address_of_its_last_byte() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // JSR $0400; ...; $0400: RTS
+        cpu.memory.set_bytes(0x02fe, &[0x20, 0x00, 0x04]);
+        cpu.memory.set_byte(0x0400, 0x60);
+        cpu.registers.program_counter = 0x02fe;
+        cpu.registers.stack_pointer = StackPointer(0x01);
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0400);
+        // The return address is $0300, the last byte of the JSR rather than
+        // the next instruction, high byte first, wrapping from $0100 to
+        // $01FF.
+        assert_eq!(cpu.memory.get_byte(0x0101), 0x03);
+        assert_eq!(cpu.memory.get_byte(0x0100), 0x00);
+        assert_eq!(cpu.registers.stack_pointer, StackPointer(0xff));
+
+        // RTS adds the missing one.
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0301);
+        assert_eq!(cpu.registers.stack_pointer, StackPointer(0x01));
+    }
+
+    #[test]
+    fn single_step_counts_base_cycles() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // LDA #$01; STA $0200; INC $10; NOP
+        cpu.memory
+            .set_bytes(0x0000, &[0xa9, 0x01, 0x8d, 0x00, 0x02, 0xe6, 0x10, 0xea]);
+
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 2);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 6);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 11);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 13);
+    }
+
+    #[test]
+    fn branch_penalties() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // BEQ +$10 at $02FD: the next instruction is at $02FF, so the taken
+        // branch to $030F crosses a page.
+        cpu.memory.set_bytes(0x02FD, &[0xf0, 0x10]);
+
+        cpu.registers.program_counter = 0x02FD;
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 2, "not taken");
+
+        cpu.registers.status.insert(Status::PS_ZERO);
+        cpu.registers.program_counter = 0x02FD;
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x030F);
+        assert_eq!(cpu.cycles, 2 + 4, "taken across a page");
+
+        // BNE -2 at $0310 loops to itself on the same page.
+        cpu.memory.set_bytes(0x0310, &[0xd0, 0xfe]);
+        cpu.registers.status.remove(Status::PS_ZERO);
+        cpu.registers.program_counter = 0x0310;
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 6 + 3, "taken within the page");
+    }
+
+    #[test]
+    fn page_crossing_penalties() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // LDA $10FF,X; STA $10FF,X; LDA ($10),Y; LDA $1000,Y
+        cpu.memory.set_bytes(
+            0,
+            &[
+                0xbd, 0xff, 0x10, 0x9d, 0xff, 0x10, 0xb1, 0x10, 0xb9, 0x00, 0x10,
+            ],
+        );
+        cpu.memory.set_bytes(0x10, &[0xff, 0x20]);
+        cpu.registers.index_x = 1;
+        cpu.registers.index_y = 1;
+
+        let mut step = || {
+            let start = cpu.cycles;
+            cpu.single_step();
+            cpu.cycles - start
+        };
+        assert_eq!(step(), 5, "read across a page");
+        assert_eq!(step(), 5, "writes always take the extra cycle");
+        assert_eq!(step(), 6, "indirect read across a page");
+        assert_eq!(step(), 4, "read within the page");
+    }
+
+    #[test]
+    fn delay_loop_timing() {
+        // The classic delay loop:
+        //         LDX #$05
+        // loop:   DEX
+        //         BNE loop
+        //
+        // LDX takes 2 cycles, each DEX 2, each taken BNE 3 and the final
+        // one 2: 2 + 5 * 2 + 4 * 3 + 2 = 26.
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory
+            .set_bytes(0x0200, &[0xa2, 0x05, 0xca, 0xd0, 0xfd]);
+        cpu.registers.program_counter = 0x0200;
+        while cpu.registers.program_counter != 0x0205 {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.cycles, 26);
+
+        // The same loop straddling a page boundary pays an extra cycle on
+        // every taken branch: 26 + 4 = 30.
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory
+            .set_bytes(0x02FD, &[0xa2, 0x05, 0xca, 0xd0, 0xfd]);
+        cpu.registers.program_counter = 0x02FD;
+        while cpu.registers.program_counter != 0x0302 {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.cycles, 30);
+    }
+
+    /// Sets up a CPU with `I` set, `program` at $0200 and an IRQ handler
+    /// starting with a NOP at $0300.
+    fn irq_cpu(program: &[u8]) -> CPU<Ram, Nmos6502> {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        cpu.memory.set_bytes(0x0200, program);
+        cpu.memory.set_byte(0x0300, 0xea);
+        cpu.registers.program_counter = 0x0200;
+        cpu.registers.stack_pointer = StackPointer(0xff);
+        cpu
+    }
+
+    #[test]
+    fn nmi_is_taken_ahead_of_irq_and_hijacks_brk() {
+        // NOP; NOP, with the NMI handler at $0400.
+        let mut cpu = irq_cpu(&[0xea, 0xea]);
+        cpu.memory.set_bytes(0xfffa, &[0x00, 0x04]);
+        cpu.memory.set_byte(0x0400, 0xea);
+        cpu.registers.status.remove(Status::PS_DISABLE_INTERRUPTS);
+        cpu.set_irq(true);
+        cpu.set_nmi(true);
+        cpu.single_step();
+        assert_eq!(cpu.interrupt_taken(), Some(Request::Nmi));
+        assert_eq!(cpu.registers.program_counter, 0x0400);
+
+        // The NMI handler masks IRQs; unmasked, the IRQ is still there.
+        cpu.registers.status.remove(Status::PS_DISABLE_INTERRUPTS);
+        cpu.single_step();
+        assert!(cpu.irq_taken());
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+
+        // BRK, with an NMI arriving as it starts.
+        let mut cpu = irq_cpu(&[0x00, 0x00]);
+        cpu.memory.set_bytes(0xfffa, &[0x00, 0x04]);
+        cpu.set_nmi(true);
+        cpu.single_step();
+        assert_eq!(cpu.interrupt_taken(), None);
+        assert_eq!(cpu.registers.program_counter, 0x0400);
+        assert_eq!(cpu.brk_vector(), 0xfffa);
+        assert_eq!(cpu.cycles, 7);
+        assert!(!cpu.interrupts().nmi_pending());
+    }
+
+    #[test]
+    fn irq_is_taken_after_the_instruction() {
+        // NOP; NOP
+        let mut cpu = irq_cpu(&[0xea, 0xea]);
+        cpu.registers.status.remove(Status::PS_DISABLE_INTERRUPTS);
+        cpu.registers.status.insert(Status::PS_BRK);
+        cpu.set_irq(true);
+        cpu.single_step();
+
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+        assert_eq!(cpu.cycles, 2 + 7);
+        assert!(cpu.registers.status.contains(Status::PS_DISABLE_INTERRUPTS));
+        assert_eq!(cpu.memory.get_bytes(0x01fe..0x0200), [0x01, 0x02]);
+        // Pushed with B clear.
+        assert_eq!(cpu.memory.get_byte(0x01fd) & Status::PS_BRK.bits(), 0);
+
+        // Masked now, so the handler runs.
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0301);
+    }
+
+    #[test]
+    fn cli_enables_irqs_one_instruction_late() {
+        // CLI; NOP; NOP
+        let program = [0x58, 0xea, 0xea];
+
+        let mut cpu = irq_cpu(&program);
+        cpu.set_irq(true);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+
+        let mut cpu = irq_cpu(&program);
+        cpu.cycle_accurate = true;
+        cpu.set_irq(true);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0201);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+        // The interrupt returns to the second NOP.
+        assert_eq!(cpu.memory.get_bytes(0x01fe..0x0200), [0x02, 0x02]);
+    }
+
+    #[test]
+    fn sei_masks_irqs_one_instruction_late() {
+        // SEI; NOP
+        let program = [0x78, 0xea];
+
+        let mut cpu = irq_cpu(&program);
+        cpu.registers.status.remove(Status::PS_DISABLE_INTERRUPTS);
+        cpu.set_irq(true);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0201);
+
+        let mut cpu = irq_cpu(&program);
+        cpu.cycle_accurate = true;
+        cpu.registers.status.remove(Status::PS_DISABLE_INTERRUPTS);
+        cpu.set_irq(true);
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0300);
+        // The pushed status already has I set.
+        let pushed = cpu.memory.get_byte(0x01fd);
+        assert_ne!(pushed & Status::PS_DISABLE_INTERRUPTS.bits(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    std::thread_local! {
+        static ALLOCATIONS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    /// Counts the allocations made on each thread, so a test can check that
+    /// it made none.
+    #[cfg(feature = "std")]
+    struct CountingAllocator;
+
+    #[cfg(feature = "std")]
+    unsafe impl core::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn executing_never_allocates() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // loop: JSR sub; INC $10; BNE loop; BRK; sub: PHA; LDA ($10),Y; PLA; RTS
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0x20, 0x08, 0x02, 0xe6, 0x10, 0xd0, 0xf9, 0x00, 0x48, 0xb1, 0x10, 0x68, 0x60,
+            ],
+        );
+        cpu.memory.set_bytes(0xfffe, &[0x00, 0x02]);
+        cpu.registers.program_counter = 0x0200;
+        cpu.set_irq(true);
+        cpu.cycle_accurate = true;
+
+        let before = ALLOCATIONS.with(core::cell::Cell::get);
+        for _ in 0..2000 {
+            cpu.single_step();
+        }
+        for _ in 0..2000 {
+            cpu.step_cycle();
+        }
+        assert_eq!(ALLOCATIONS.with(core::cell::Cell::get), before);
+    }
+
+    #[test]
+    fn cmos_instructions_test() {
+        use crate::instruction::Cmos6502;
+
+        // BIT $0F,X; BIT $10FF,X; NOP #$EA; NOP $EAEA; .byte $03; JMP ($0400,X)
+        let program = [
+            0x34, 0x0f, 0x3c, 0xff, 0x10, 0x02, 0xea, 0xdc, 0xea, 0xea, 0x03, 0x7c, 0x00, 0x04,
+        ];
+        let mut cpu = CPU::new(Ram::new(), Cmos6502);
+        cpu.memory.set_bytes(0, &program);
+        cpu.memory.set_bytes(0x0010, &[0xc0]);
+        cpu.memory.set_bytes(0x1100, &[0x00]);
+        cpu.memory.set_bytes(0x0401, &[0x34, 0x12]);
+        cpu.registers.index_x = 1;
+        cpu.registers.accumulator = 0x01;
+
+        cpu.single_step();
+        assert!(cpu
+            .registers
+            .status
+            .contains(Status::PS_NEGATIVE | Status::PS_OVERFLOW | Status::PS_ZERO));
+        cpu.single_step();
+        assert!(!cpu.registers.status.contains(Status::PS_NEGATIVE));
+        assert_eq!(cpu.cycles, 9);
+
+        // The undefined opcodes skip their operands and do nothing else.
+        let before = cpu.registers;
+        for (pc, cycles) in [(0x0007, 11), (0x000a, 15), (0x000b, 16)] {
+            cpu.single_step();
+            assert_eq!((cpu.registers.program_counter, cpu.cycles), (pc, cycles));
+        }
+        assert_eq!(cpu.registers.accumulator, before.accumulator);
+        assert_eq!(cpu.registers.status, before.status);
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x1234);
+        assert_eq!(cpu.cycles, 22);
+        assert!((0..=u8::MAX).all(|opcode| Cmos6502::decode(opcode).is_some()));
+    }
+
+    #[test]
+    fn undocumented_instructions_test() {
+        use crate::instruction::{Cmos6502, Ricoh2a03};
+
+        // LAX $80; SAX $81; DCP $82; ISC $83; SLO $84; RLA $85; SRE $86;
+        // RRA $87; ANC #$80; ALR #$03; ARR #$C0; SBX #$01; LAS $0200,Y;
+        // SBC #$01 ($EB); NOP $10FF,X
+        let program = [
+            0xa7, 0x80, 0x87, 0x81, 0xc7, 0x82, 0xe7, 0x83, 0x07, 0x84, 0x27, 0x85, 0x47, 0x86,
+            0x67, 0x87, 0x0b, 0x80, 0x4b, 0x03, 0x6b, 0xc0, 0xcb, 0x01, 0xbb, 0x00, 0x02, 0xeb,
+            0x01, 0x1c, 0xff, 0x10,
+        ];
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory.set_bytes(0, &program);
+        cpu.memory
+            .set_bytes(0x0080, &[0x8f, 0x00, 0x90, 0x0f, 0x81, 0x81, 0x03, 0x02]);
+        cpu.memory.set_bytes(0x0200, &[0xf7]);
+        cpu.registers.status.remove(alu::NVZC);
+        let flags = |cpu: &CPU<Ram, Nmos6502>| cpu.registers.status & alu::NVZC;
+
+        cpu.single_step();
+        assert_eq!(
+            (cpu.registers.accumulator, cpu.registers.index_x),
+            (0x8f, 0x8f)
+        );
+        assert_eq!(flags(&cpu), Status::PS_NEGATIVE);
+        cpu.registers.index_x = 0x0f;
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x81), 0x0f);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x82), 0x8f);
+        assert_eq!(flags(&cpu), Status::PS_ZERO | Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x83), 0x10);
+        assert_eq!(cpu.registers.accumulator, 0x7f);
+        assert_eq!(flags(&cpu), Status::PS_OVERFLOW | Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x84), 0x02);
+        assert_eq!(cpu.registers.accumulator, 0x7f);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x85), 0x03);
+        assert_eq!(cpu.registers.accumulator, 0x03);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x86), 0x01);
+        assert_eq!(cpu.registers.accumulator, 0x02);
+        assert_eq!(flags(&cpu), Status::PS_OVERFLOW | Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.memory.get_byte(0x87), 0x81);
+        assert_eq!(cpu.registers.accumulator, 0x83);
+        assert_eq!(flags(&cpu), Status::PS_NEGATIVE);
+
+        // ANC copies N to C; ALR shifts the AND.
+        cpu.single_step();
+        assert_eq!(cpu.registers.accumulator, 0x80);
+        assert_eq!(flags(&cpu), Status::PS_NEGATIVE | Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.registers.accumulator, 0x00);
+        assert_eq!(flags(&cpu), Status::PS_ZERO);
+
+        // ARR takes C from bit 6 and V from bit 6 xor bit 5.
+        cpu.registers.accumulator = 0xff;
+        cpu.registers.status.insert(Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.registers.accumulator, 0xe0);
+        assert_eq!(flags(&cpu), Status::PS_NEGATIVE | Status::PS_CARRY);
+
+        // SBX compares A & X with the operand, leaving the difference in X.
+        cpu.registers.index_x = 0x0f;
+        cpu.single_step();
+        assert_eq!(cpu.registers.index_x, 0xff);
+        assert_eq!(flags(&cpu), Status::PS_NEGATIVE);
+
+        cpu.registers.index_y = 0;
+        cpu.registers.stack_pointer = StackPointer(0x3f);
+        cpu.single_step();
+        assert_eq!(cpu.registers.accumulator, 0x37);
+        assert_eq!(cpu.registers.index_x, 0x37);
+        assert_eq!(cpu.registers.stack_pointer.0, 0x37);
+
+        cpu.registers.status.insert(Status::PS_CARRY);
+        cpu.single_step();
+        assert_eq!(cpu.registers.accumulator, 0x36);
+
+        // The NOPs read their operand, paying for a page crossing.
+        let cycles = cpu.cycles;
+        cpu.registers.index_x = 1;
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0020);
+        assert_eq!(cpu.cycles - cycles, 5);
+
+        // The 2A03's RRA ignores the decimal flag, as its ADC does.
+        let mut ricoh = CPU::new(Ram::new(), Ricoh2a03);
+        ricoh.memory.set_bytes(0, &[0xf8, 0x67, 0x10]);
+        ricoh.memory.set_bytes(0x0010, &[0x12]);
+        ricoh.registers.accumulator = 0x09;
+        ricoh.single_step();
+        ricoh.single_step();
+        assert_eq!(ricoh.registers.accumulator, 0x12);
+
+        // The jams and unstable opcodes aren't decoded, and the 65C02
+        // doesn't have any of these.
+        assert!([0x02, 0x12, 0x8b, 0x93, 0x9b, 0x9c, 0x9e, 0x9f, 0xab]
+            .iter()
+            .all(|&opcode| Nmos6502::decode(opcode).is_none()));
+        assert_eq!(
+            Cmos6502::decode(0xa7),
+            Some((Instruction::NOP, AddressingMode::Implied))
+        );
+    }
+
+    #[cfg(feature = "decimal_mode")]
+    #[test]
+    fn cmos_timing_differs_from_nmos() {
+        // SED; ADC #$01; ROL $10F0,X; ROL $10FF,X; JMP ($0300)
+        let program = [
+            0xf8, 0x69, 0x01, 0x3e, 0xf0, 0x10, 0x3e, 0xff, 0x10, 0x6c, 0x00, 0x03,
+        ];
+        let mut nmos = CPU::new(Ram::new(), Nmos6502);
+        let mut cmos = CPU::new(Ram::new(), crate::instruction::Cmos6502);
+        nmos.memory.set_bytes(0, &program);
+        cmos.memory.set_bytes(0, &program);
+        nmos.registers.index_x = 1;
+        cmos.registers.index_x = 1;
+
+        let cycles = |nmos: &mut CPU<Ram, Nmos6502>, cmos: &mut CPU<Ram, _>| {
+            nmos.single_step();
+            cmos.single_step();
+            (nmos.cycles, cmos.cycles)
+        };
+        assert_eq!(cycles(&mut nmos, &mut cmos), (2, 2));
+        assert_eq!(cycles(&mut nmos, &mut cmos), (4, 5));
+        assert_eq!(cycles(&mut nmos, &mut cmos), (11, 11));
+        assert_eq!(cycles(&mut nmos, &mut cmos), (18, 18));
+        assert_eq!(cycles(&mut nmos, &mut cmos), (23, 24));
+    }
+
+    #[test]
+    fn reset_matches_the_hardware() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        assert_eq!(cpu.registers.stack_pointer.0, 0xfd);
+        assert!(cpu.registers.status.contains(Status::PS_DISABLE_INTERRUPTS));
+
+        cpu.registers.accumulator = 0x42;
+        cpu.registers.status = Status::PS_DECIMAL_MODE;
+        cpu.reset();
+        assert_eq!(cpu.registers.stack_pointer.0, 0xfd);
+        // However deep the stack was, a reset starts it over.
+        cpu.registers.stack_pointer.0 = 0x42;
+        cpu.reset();
+        assert_eq!(cpu.registers.stack_pointer.0, 0xfd);
+        assert_eq!(cpu.registers.accumulator, 0x42);
+        assert_eq!(
+            cpu.registers.status,
+            Status::PS_DECIMAL_MODE | Status::PS_DISABLE_INTERRUPTS
+        );
+
+        let mut cpu = CPU::new(Ram::new(), crate::instruction::Cmos6502);
+        cpu.registers.status = Status::PS_DECIMAL_MODE;
+        cpu.reset();
+        assert_eq!(cpu.registers.status, Status::PS_DISABLE_INTERRUPTS);
+        assert_eq!(Registers::zeroed().stack_pointer.0, 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn debug_shows_the_registers_flags_and_cycles() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.registers.program_counter = 0xc000;
+        cpu.registers.accumulator = 0x80;
+        cpu.registers.index_y = 0x12;
+        cpu.registers.status.insert(Status::PS_NEGATIVE);
+        cpu.cycles = 7;
+        assert_eq!(
+            alloc::format!("{cpu:?}"),
+            "CPU { pc: $C000, a: $80, x: $00, y: $12, sp: $FD, flags: N.-..I.., cycles: 7, .. }"
+        );
+    }
+
+    #[test]
+    fn state_hash_is_stable() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        let hash = cpu.state_hash();
+        assert_eq!(hash, 0x5fa8_fbc0_46fd_0656);
+
+        cpu.cycles += 10;
+        assert_eq!(cpu.state_hash(), hash);
+        cpu.memory.set_byte(0xffff, 0x01);
+        assert_ne!(cpu.state_hash(), hash);
+        cpu.memory.set_byte(0xffff, 0x00);
+        cpu.registers.index_y = 1;
+        assert_ne!(cpu.state_hash(), hash);
+    }
+
+    #[test]
+    fn stolen_cycles_are_added_once() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        // NOP; NOP; NOP
+        cpu.memory.set_bytes(0x0000, &[0xea, 0xea, 0xea]);
+        cpu.steal_cycles(3, 40);
+
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 2);
+        // Halted on the second cycle of this NOP, which finishes once the
+        // window is over.
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 44);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 46);
+    }
+
+    #[test]
+    fn accurate_stall_lets_trailing_writes_finish() {
+        // STA $0200 takes 4 cycles, the last of which is a write.
+        let program = [0x8d, 0x00, 0x02, 0xea];
+
+        // The stall begins on the write cycle: the CPU halts on the next
+        // opcode fetch, so only the remainder of the window is charged.
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.cycle_accurate = true;
+        cpu.memory.set_bytes(0x0000, &program);
+        cpu.steal_cycles(3, 10);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 13);
+
+        // Without cycle accuracy the whole window is charged.
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0000, &program);
+        cpu.steal_cycles(3, 10);
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 14);
+    }
+
+    #[cfg(feature = "alloc")]
+    fn bus(cycles: &[BusCycle]) -> Vec<(Access, u16, u8)> {
+        cycles
+            .iter()
+            .map(|cycle| (cycle.access, cycle.address, cycle.data))
+            .collect()
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn dummy_accesses_follow_the_t_states() {
+        use Access::{Read, Write};
+
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.cycle_accurate = true;
+        cpu.registers.index_x = 0x01;
+        cpu.registers.stack_pointer = StackPointer(0xfd);
+        // INC $10; LDA $02FF,X; PHA; PLA
+        cpu.memory
+            .set_bytes(0x0000, &[0xe6, 0x10, 0xbd, 0xff, 0x02, 0x48, 0x68]);
+        cpu.memory.set_byte(0x0010, 0x41);
+        cpu.memory.set_byte(0x0300, 0x99);
+
+        assert_eq!(cpu.pins(), None);
+        cpu.single_step();
+        let pins = cpu.pins().unwrap();
+        assert_eq!((pins.address, pins.data), (0x0010, 0x42));
+        assert!(!pins.read_write());
+        assert_eq!(
+            bus(cpu.bus_cycles()),
+            [
+                (Read, 0x0000, 0xe6),
+                (Read, 0x0001, 0x10),
+                (Read, 0x0010, 0x41),
+                (Write, 0x0010, 0x41),
+                (Write, 0x0010, 0x42),
+            ]
+        );
+        assert!(cpu.bus_cycles()[0].sync);
+        assert!(!cpu.bus_cycles()[1].sync);
+
+        // Indexing into the next page reads from the wrong page first.
+        cpu.single_step();
+        assert_eq!(
+            bus(cpu.bus_cycles()),
+            [
+                (Read, 0x0002, 0xbd),
+                (Read, 0x0003, 0xff),
+                (Read, 0x0004, 0x02),
+                (Read, 0x0200, 0x00),
+                (Read, 0x0300, 0x99),
+            ]
+        );
+
+        cpu.single_step();
+        cpu.single_step();
+        assert_eq!(
+            bus(cpu.bus_cycles()),
+            [
+                (Read, 0x0006, 0x68),
+                (Read, 0x0007, 0x00),
+                (Read, 0x01fc, 0x00),
+                (Read, 0x01fd, 0x99),
+            ]
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn jsr_reads_the_target_high_byte_last() {
+        use Access::{Read, Write};
+
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.cycle_accurate = true;
+        cpu.registers.stack_pointer = StackPointer(0xff);
+        // JSR $0305 at $0200; RTS at $0305
+        cpu.memory.set_bytes(0x0200, &[0x20, 0x05, 0x03]);
+        cpu.memory.set_byte(0x0305, 0x60);
+        cpu.registers.program_counter = 0x0200;
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0305);
+        assert_eq!(
+            bus(cpu.bus_cycles()),
+            [
+                (Read, 0x0200, 0x20),
+                (Read, 0x0201, 0x05),
+                (Read, 0x01ff, 0x00),
+                (Write, 0x01ff, 0x02),
+                (Write, 0x01fe, 0x02),
+                (Read, 0x0202, 0x03),
+            ]
+        );
+
+        cpu.single_step();
+        assert_eq!(cpu.registers.program_counter, 0x0203);
+        assert_eq!(
+            bus(cpu.bus_cycles()),
+            [
+                (Read, 0x0305, 0x60),
+                (Read, 0x0306, 0x00),
+                (Read, 0x01fd, 0x00),
+                (Read, 0x01fe, 0x02),
+                (Read, 0x01ff, 0x02),
+                (Read, 0x0202, 0x03),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_cycle_uses_the_bus() {
+        for opcode in 0..=u8::MAX {
+            if Nmos6502::decode(opcode).is_none() {
+                continue;
+            }
+            // Page-crossing indexed reads aren't charged their extra cycle
+            // yet, so stay on the page.
+            for carry in [false, true] {
+                let mut cpu = CPU::new(Ram::new(), Nmos6502);
+                cpu.cycle_accurate = true;
+                cpu.registers.status.set(Status::PS_CARRY, carry);
+                cpu.memory.set_bytes(0x0280, &[opcode, 0x90, 0x02]);
+                cpu.memory.set_bytes(0x0090, &[0xf0, 0x02]);
+                cpu.registers.program_counter = 0x0280;
+                cpu.single_step();
+                assert_eq!(
+                    cpu.bus_cycles().len() as u64,
+                    cpu.cycles,
+                    "opcode {opcode:02x} with carry {carry}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cycle_stepping_replays_the_bus() {
+        let mut cpu = CPU::new(Ram::new(), Nmos6502);
+        cpu.cycle_accurate = true;
+        // LDA #$01; DEX
+        cpu.memory.set_bytes(0x0000, &[0xa9, 0x01, 0xca]);
+
+        let cycle = cpu.step_cycle().unwrap();
+        assert_eq!((cycle.address, cycle.sync), (0x0000, true));
+        // The instruction has already run.
+        assert_eq!(cpu.registers.accumulator, 1);
+        assert_eq!(cpu.step_cycle().unwrap().address, 0x0001);
+
+        let cycle = cpu.step_cycle().unwrap();
+        assert_eq!((cycle.address, cycle.sync), (0x0002, true));
+        assert_eq!(cpu.step_cycle().unwrap().address, 0x0003);
+        assert_eq!(cpu.pins().map(|pins| pins.address), Some(0x0003));
+        assert_eq!(cpu.registers.index_x, 0xff);
+        assert_eq!(cpu.cycles, 4);
+    }
+
+    #[test]
+    fn phi2_is_called_once_per_cycle() {
+        struct Phi2Counter {
+            memory: Ram,
+            cycles: [u64; 8],
+            calls: usize,
+        }
+
+        impl Bus for Phi2Counter {
+            fn get_bytes(&self, range: core::ops::Range<usize>) -> &[u8] {
+                self.memory.get_bytes(range)
+            }
+
+            fn get_byte(&self, address: u16) -> u8 {
+                self.memory.get_byte(address)
+            }
+
+            fn set_byte(&mut self, address: u16, value: u8) {
+                self.memory.set_byte(address, value);
+            }
+
+            fn phi2(&mut self, cycle: u64) {
+                self.cycles[self.calls] = cycle;
+                self.calls += 1;
+            }
+        }
+
+        let memory = Phi2Counter {
+            memory: Ram::new(),
+            cycles: [0; 8],
+            calls: 0,
+        };
+        let mut cpu = CPU::new(memory, Nmos6502);
+        // LDA #$01; INC $10
+        cpu.memory.set_bytes(0x0000, &[0xa9, 0x01, 0xe6, 0x10]);
+        cpu.single_step();
+        cpu.single_step();
+
+        assert_eq!(cpu.memory.calls, 7);
+        assert_eq!(cpu.memory.cycles[..7], [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn opcode_is_read_once_per_instruction() {
+        struct ReadCounter {
+            memory: Ram,
+            reads: core::cell::Cell<[u8; 4]>,
+        }
+
+        impl Bus for ReadCounter {
+            fn get_bytes(&self, range: core::ops::Range<usize>) -> &[u8] {
+                self.memory.get_bytes(range)
+            }
+
+            fn get_byte(&self, address: u16) -> u8 {
+                let mut reads = self.reads.get();
+                if let Some(count) = reads.get_mut(usize::from(address)) {
+                    *count += 1;
+                }
+                self.reads.set(reads);
+                self.memory.get_byte(address)
+            }
+
+            fn set_byte(&mut self, address: u16, value: u8) {
+                self.memory.set_byte(address, value);
+            }
+        }
+
+        let memory = ReadCounter {
+            memory: Ram::new(),
+            reads: core::cell::Cell::new([0; 4]),
+        };
+        let mut cpu = CPU::new(memory, Nmos6502);
+        // LDA #$01; NOP
+        cpu.memory.set_bytes(0x0000, &[0xa9, 0x01, 0xea]);
+        cpu.single_step();
+        cpu.single_step();
+
+        assert_eq!(cpu.memory.reads.get(), [1, 1, 1, 0]);
+    }
 }
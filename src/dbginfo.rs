@@ -0,0 +1,425 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Source-level debug information written by the cc65 linker.
+//!
+//! `ld65 --dbgfile program.dbg` records which source lines and symbols
+//! produced each byte of a linked program. [`DebugInfo`] reads that file so
+//! addresses can be reported as `file:line` and breakpoints set on source
+//! lines, for assembly as well as C compiled with `cc65 -g`.
+//!
+//! Only the `file`, `line`, `seg`, `span`, `sym` and `csym` records are used.
+//! Labels are read into the symbols, but cheap locals (`@loop`), equates
+//! and C variables on the stack are not, since none of them name a fixed
+//! address in the program.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::coverage::SourceLine;
+use crate::symbols::{ParseError, SymbolTable};
+
+/// The language of a source line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineKind {
+    /// Assembly, written by hand or generated by the C compiler.
+    Assembly,
+    /// The C source that the assembly was compiled from.
+    C,
+    /// The body of an assembler macro.
+    Macro,
+}
+
+/// Source locations and symbols of a program linked by ld65.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    files: BTreeMap<u32, String>,
+    ranges: Vec<LineRange>,
+    symbols: SymbolTable,
+}
+
+/// Bytes generated by one source line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct LineRange {
+    file: u32,
+    line: usize,
+    kind: LineKind,
+    start: u16,
+    /// One past the last address, which may be $10000.
+    end: u32,
+}
+
+impl DebugInfo {
+    /// Parses the debug information file written by `ld65 --dbgfile`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that is not a well-formed record, or whose
+    /// record refers to a file, segment or span that doesn't exist.
+    pub fn parse(text: &str) -> Result<DebugInfo, ParseError> {
+        let mut files = BTreeMap::new();
+        let mut lines = Vec::new();
+        let mut segments = BTreeMap::new();
+        let mut spans = BTreeMap::new();
+        let mut labels = BTreeMap::new();
+        let mut c_symbols = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message| ParseError {
+                line: index + 1,
+                message,
+            };
+            let Some((kind, fields)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let record = Record::parse(fields).map_err(error)?;
+            match kind {
+                "file" => {
+                    files.insert(
+                        record.id().map_err(error)?,
+                        record.string("name").map_err(error)?,
+                    );
+                }
+                "line" => {
+                    let kind = match record.optional_number("type").map_err(error)? {
+                        None | Some(0) => LineKind::Assembly,
+                        Some(1) => LineKind::C,
+                        Some(_) => LineKind::Macro,
+                    };
+                    let span = record.optional_list("span").map_err(error)?;
+                    lines.push((
+                        index + 1,
+                        record.number("file").map_err(error)?,
+                        record.number("line").map_err(error)?,
+                        kind,
+                        span,
+                    ));
+                }
+                "seg" => {
+                    segments.insert(
+                        record.id().map_err(error)?,
+                        record.number("start").map_err(error)?,
+                    );
+                }
+                "span" => {
+                    let span = (
+                        index + 1,
+                        record.number("seg").map_err(error)?,
+                        record.number("start").map_err(error)?,
+                        record.number("size").map_err(error)?,
+                    );
+                    spans.insert(record.id().map_err(error)?, span);
+                }
+                "sym" => {
+                    let label =
+                        record.field("type") == Some("lab") && record.field("parent").is_none();
+                    if let (true, Some(value)) =
+                        (label, record.optional_number("val").map_err(error)?)
+                    {
+                        let name = record.string("name").map_err(error)?;
+                        labels.insert(record.id().map_err(error)?, (name, value));
+                    }
+                }
+                "csym" => {
+                    let fixed = matches!(record.field("sc"), Some("ext" | "static"));
+                    if let (true, Some(symbol)) =
+                        (fixed, record.optional_number("sym").map_err(error)?)
+                    {
+                        c_symbols.push((record.string("name").map_err(error)?, symbol));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut ranges = Vec::new();
+        for (line, file, number, kind, span_ids) in lines {
+            let error = |message| ParseError { line, message };
+            if !files.contains_key(&file) {
+                return Err(error("unknown file"));
+            }
+            for id in span_ids {
+                let &(span_line, segment, start, size) =
+                    spans.get(&id).ok_or(error("unknown span"))?;
+                let base = segments.get(&segment).ok_or(ParseError {
+                    line: span_line,
+                    message: "unknown segment",
+                })?;
+                let start = base + start;
+                // Spans outside the 64K address space belong to 65816
+                // programs and can't be executed here.
+                let (Ok(address), true) = (u16::try_from(start), size > 0) else {
+                    continue;
+                };
+                ranges.push(LineRange {
+                    file,
+                    line: usize::try_from(number).unwrap_or(usize::MAX),
+                    kind,
+                    start: address,
+                    end: start + size,
+                });
+            }
+        }
+
+        // C names go in first so they label the addresses rather than the
+        // underscored assembler names the compiler gives them.
+        let mut symbols = SymbolTable::new();
+        for (name, symbol) in &c_symbols {
+            if let Some(address) = labels
+                .get(symbol)
+                .and_then(|(_, value)| u16::try_from(*value).ok())
+            {
+                symbols.insert(name, address);
+            }
+        }
+        for (name, value) in labels.values() {
+            if let Ok(address) = u16::try_from(*value) {
+                symbols.insert(name, address);
+            }
+        }
+
+        Ok(DebugInfo {
+            files,
+            ranges,
+            symbols,
+        })
+    }
+
+    /// The source line that generated the byte at `address`, with the
+    /// address of the first byte of that line. A line of C is preferred
+    /// over the assembly compiled from it, and the line invoking a macro
+    /// over the lines of its body.
+    #[must_use]
+    pub fn source_at(&self, address: u16) -> Option<SourceLine<'_>> {
+        self.ranges
+            .iter()
+            .filter(|range| range.kind != LineKind::Macro)
+            .filter(|range| range.start <= address && u32::from(address) < range.end)
+            .min_by_key(|range| {
+                (
+                    range.kind != LineKind::C,
+                    range.end - u32::from(range.start),
+                )
+            })
+            .map(|range| self.source_line(range))
+    }
+
+    /// Every line that generated the byte at `address`.
+    pub fn lines_at(&self, address: u16) -> impl Iterator<Item = (LineKind, SourceLine<'_>)> {
+        self.ranges
+            .iter()
+            .filter(move |range| range.start <= address && u32::from(address) < range.end)
+            .map(|range| (range.kind, self.source_line(range)))
+    }
+
+    /// The first address generated for `line` of `file`, for setting a
+    /// breakpoint on it. `file` may be the name recorded by the linker or
+    /// its last path components, such as `main.c` for `src/main.c`.
+    #[must_use]
+    pub fn address_of(&self, file: &str, line: usize) -> Option<u16> {
+        self.ranges
+            .iter()
+            .filter(|range| range.line == line && range.kind != LineKind::Macro)
+            .filter(|range| same_file(&self.files[&range.file], file))
+            .map(|range| range.start)
+            .min()
+    }
+
+    /// The labels and C functions and globals of the program.
+    #[must_use]
+    pub const fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    fn source_line(&self, range: &LineRange) -> SourceLine<'_> {
+        SourceLine {
+            file: &self.files[&range.file],
+            line: range.line,
+            address: range.start,
+        }
+    }
+}
+
+fn same_file(recorded: &str, wanted: &str) -> bool {
+    recorded
+        .strip_suffix(wanted)
+        .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with(['/', '\\']))
+}
+
+/// The `key=value` fields of a record.
+struct Record<'a>(BTreeMap<&'a str, &'a str>);
+
+impl<'a> Record<'a> {
+    fn parse(mut text: &'a str) -> Result<Record<'a>, &'static str> {
+        let mut fields = BTreeMap::new();
+        while !text.is_empty() {
+            let (key, rest) = text.split_once('=').ok_or("expected `key=value`")?;
+            let end = match rest.strip_prefix('"') {
+                Some(string) => string.find('"').ok_or("unterminated string")? + 2,
+                None => rest.find(',').unwrap_or(rest.len()),
+            };
+            let (value, rest) = rest.split_at(end);
+            fields.insert(key, value);
+            text = match rest.strip_prefix(',') {
+                Some(rest) => rest,
+                None if rest.is_empty() => rest,
+                None => return Err("expected `,` between fields"),
+            };
+        }
+        Ok(Record(fields))
+    }
+
+    fn field(&self, key: &str) -> Option<&'a str> {
+        self.0.get(key).copied()
+    }
+
+    fn id(&self) -> Result<u32, &'static str> {
+        self.number("id")
+    }
+
+    fn number(&self, key: &str) -> Result<u32, &'static str> {
+        self.optional_number(key)?.ok_or("missing field")
+    }
+
+    fn optional_number(&self, key: &str) -> Result<Option<u32>, &'static str> {
+        self.field(key).map(number).transpose()
+    }
+
+    fn optional_list(&self, key: &str) -> Result<Vec<u32>, &'static str> {
+        match self.field(key) {
+            Some(list) => list.split('+').map(number).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<String, &'static str> {
+        let value = self.field(key).ok_or("missing field")?;
+        value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .map(String::from)
+            .ok_or("expected a quoted string")
+    }
+}
+
+fn number(text: &str) -> Result<u32, &'static str> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| "malformed number")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    /// A C function `main` at $0200 whose one statement compiled to two
+    /// lines of assembly.
+    const PROGRAM: &str = "\
+version\tmajor=2,minor=0
+info\tcsym=2,file=2,lib=0,line=4,mod=1,scope=2,seg=1,span=3,sym=3,type=1
+file\tid=0,name=\"main.c\",size=120,mtime=0x65000000,mod=0
+file\tid=1,name=\"src/main.s\",size=400,mtime=0x65000000,mod=0
+line\tid=0,file=0,line=5,type=1,count=1,span=2
+line\tid=1,file=1,line=20,span=0
+line\tid=2,file=1,line=21,span=1
+line\tid=3,file=1,line=30
+mod\tid=0,name=\"main.o\",file=1
+seg\tid=0,name=\"CODE\",start=0x000200,size=0x0005,addrsize=absolute,type=ro,oname=\"main.bin\",ooffs=0
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=3
+span\tid=2,seg=0,start=0,size=5,type=0
+scope\tid=0,name=\"\",mod=0,size=5
+scope\tid=1,name=\"_main\",parent=0,mod=0,sym=0,size=5,span=2
+sym\tid=0,name=\"_main\",addrsize=absolute,size=5,scope=0,def=0,val=0x200,seg=0,type=lab
+sym\tid=1,name=\"@loop\",addrsize=absolute,scope=1,parent=0,def=1,val=0x202,seg=0,type=lab
+sym\tid=2,name=\"SCREEN\",addrsize=absolute,scope=0,def=1,val=0x400,type=equ
+csym\tid=0,name=\"main\",scope=1,type=0,sc=ext,sym=0
+csym\tid=1,name=\"count\",scope=1,type=0,sc=auto,offs=0
+type\tid=0,val=\"800420\"
+";
+
+    #[test]
+    fn maps_addresses_to_source() {
+        let info = DebugInfo::parse(PROGRAM).unwrap();
+        let at = |address| {
+            info.source_at(address)
+                .map(|line| (line.file, line.line, line.address))
+        };
+        assert_eq!(at(0x0203), Some(("main.c", 5, 0x0200)));
+        assert_eq!(at(0x0205), None);
+        let lines: Vec<_> = info.lines_at(0x0203).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().any(|(kind, line)| {
+            *kind == LineKind::Assembly && line.to_string() == "src/main.s:21"
+        }));
+    }
+
+    #[test]
+    fn maps_source_to_addresses() {
+        let info = DebugInfo::parse(PROGRAM).unwrap();
+        assert_eq!(info.address_of("main.c", 5), Some(0x0200));
+        assert_eq!(info.address_of("main.s", 21), Some(0x0202));
+        assert_eq!(info.address_of("src/main.s", 20), Some(0x0200));
+        assert_eq!(info.address_of("ain.s", 20), None);
+        assert_eq!(info.address_of("main.s", 30), None);
+    }
+
+    #[test]
+    fn reads_labels_and_c_symbols() {
+        let info = DebugInfo::parse(PROGRAM).unwrap();
+        let symbols = info.symbols();
+        assert_eq!(symbols.name_of(0x0200), Some("main"));
+        assert_eq!(symbols.address_of("_main"), Some(0x0200));
+        assert_eq!(symbols.len(), 2);
+    }
+
+    #[test]
+    fn reports_bad_records() {
+        let err = DebugInfo::parse("version\tmajor=2,minor=0\nfile\tid=0,name=\"a.s\nline\tid=0")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 2,
+                message: "unterminated string"
+            }
+        );
+        let err = DebugInfo::parse("line\tid=0,file=0,line=1,span=0\nfile\tid=0,name=\"a.s\"")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 1,
+                message: "unknown span"
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Syntax dialects of 6502 assemblers.
+//!
+//! Assemblers agree on the instructions but not much else. A [`Dialect`]
+//! captures the differences that matter when reading their sources or
+//! writing disassembly for them:
+//!
+//! - **Generic**: `.org`, `.byte`/`.db` and `.word`/`.dw`. Labels end with
+//!   a colon and are all global.
+//! - **ca65**: `.org`, `.byte` and `.word`/`.addr`. Labels end with a colon;
+//!   `@name` is local to the label before it.
+//! - **ACME**: `!byte`/`!by`/`!8`, `!word`/`!wo`/`!16`, `!text`/`!tx` and
+//!   `!zone`/`!zn`. The colon is optional on labels in the first column;
+//!   `.name` is local to the zone started by the `!zone` before it.
+//! - **64tass**: `.byte`, `.text` and `.word`/`.addr`. The colon is
+//!   optional on labels in the first column; `_name` is local to the label
+//!   before it.
+//!
+//! Every dialect sets the program counter with `* = address`, writes
+//! hexadecimal with `$` and binary with `%`; ACME also takes `0x`. ACME
+//! writes accumulator operands as a bare `ASL`, the others as `ASL A`.
+
+/// The syntax of a particular assembler.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    /// The conventional syntax of the built-in assembler.
+    #[default]
+    Generic,
+    /// ca65, from the cc65 suite.
+    Ca65,
+    /// The ACME cross-assembler.
+    Acme,
+    /// 64tass.
+    Tass64,
+}
+
+impl Dialect {
+    /// The character pseudo-ops start with, as in `.byte` or `!byte`.
+    #[must_use]
+    pub const fn directive_prefix(self) -> char {
+        match self {
+            Dialect::Acme => '!',
+            _ => '.',
+        }
+    }
+
+    /// The character that marks a label as local, if the dialect has local
+    /// labels.
+    #[must_use]
+    pub const fn local_prefix(self) -> Option<char> {
+        match self {
+            Dialect::Generic => None,
+            Dialect::Ca65 => Some('@'),
+            Dialect::Acme => Some('.'),
+            Dialect::Tass64 => Some('_'),
+        }
+    }
+
+    /// Whether labels must end with a colon. Where they needn't, a name in
+    /// the first column that isn't a mnemonic is a label.
+    #[must_use]
+    pub const fn labels_need_colon(self) -> bool {
+        matches!(self, Dialect::Generic | Dialect::Ca65)
+    }
+
+    /// Whether accumulator operands are written out, as in `ASL A`.
+    #[must_use]
+    pub const fn explicit_accumulator(self) -> bool {
+        !matches!(self, Dialect::Acme)
+    }
+
+    /// Whether `0x` introduces a hexadecimal number, besides `$`.
+    #[must_use]
+    pub const fn c_hex(self) -> bool {
+        matches!(self, Dialect::Acme)
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Differences between two machine states.
+//!
+//! A [`StateDiff`] lists the registers, flags and bytes of memory that
+//! differ between two states, for debugger "what changed?" views and for
+//! test failures that say more than "not equal". Build one with
+//! [`Machine::diff`](crate::machine::Machine::diff),
+//! [`Machine::changes_since`](crate::machine::Machine::changes_since),
+//! [`StateDiff::between`] for snapshots or [`StateDiff::between_states`]
+//! for the registers alone.
+//!
+//! ```
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::machine::Machine;
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+//! // LDA #$80; STA $10
+//! machine.cpu.memory.set_bytes(0x0000, &[0xa9, 0x80, 0x85, 0x10]);
+//! let before = machine.snapshot();
+//! machine.step();
+//! machine.step();
+//!
+//! let diff = machine.changes_since(&before);
+//! assert_eq!(diff.memory, [(0x0010, 0x00, 0x80)]);
+//! assert_eq!(
+//!     diff.to_string(),
+//!     "A: $00 -> $80\nPC: $0000 -> $0004\nflags: +N\n$0010: $00 -> $80\ncycles: 0 -> 5\n"
+//! );
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::registers::{Registers, Status};
+use crate::snapshot::Snapshot;
+use crate::state::CpuState;
+
+/// A register, as named in a [`StateDiff`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    SP,
+    PC,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Register::A => "A",
+            Register::X => "X",
+            Register::Y => "Y",
+            Register::SP => "SP",
+            Register::PC => "PC",
+        })
+    }
+}
+
+/// Flags in the order the status register holds them, with their names.
+const FLAGS: [(char, Status); 7] = [
+    ('N', Status::PS_NEGATIVE),
+    ('V', Status::PS_OVERFLOW),
+    ('B', Status::PS_BRK),
+    ('D', Status::PS_DECIMAL_MODE),
+    ('I', Status::PS_DISABLE_INTERRUPTS),
+    ('Z', Status::PS_ZERO),
+    ('C', Status::PS_CARRY),
+];
+
+/// What differs between an old and a new state.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// Changed registers as `(register, old, new)`, in the order of
+    /// [`Register`].
+    pub registers: Vec<(Register, u16, u16)>,
+    /// Flags that are set in the new state but not the old.
+    pub set_flags: Status,
+    /// Flags that are set in the old state but not the new.
+    pub cleared_flags: Status,
+    /// Changed bytes as `(address, old, new)`, in address order.
+    pub memory: Vec<(u16, u8, u8)>,
+    /// The old and new cycle counts, if they differ.
+    pub cycles: Option<(u64, u64)>,
+}
+
+impl StateDiff {
+    /// The differences between two snapshots.
+    #[must_use]
+    pub fn between(old: &Snapshot, new: &Snapshot) -> StateDiff {
+        StateDiff::compute(
+            (&old.registers, old.cycles, |address| {
+                old.memory()[usize::from(address)]
+            }),
+            (&new.registers, new.cycles, |address| {
+                new.memory()[usize::from(address)]
+            }),
+        )
+    }
+
+    /// The registers, flags and cycle counts that differ between two CPU
+    /// states. Memory isn't part of a [`CpuState`], so none is listed.
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::diff::{Register, StateDiff};
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::Machine;
+    /// use mos6502::memory::{Bus, Memory};
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// // LDX #$00
+    /// machine.cpu.memory.set_bytes(0x0000, &[0xa2, 0x00]);
+    /// let before = machine.cpu_state();
+    /// machine.step();
+    /// let diff = StateDiff::between_states(&before, &machine.cpu_state());
+    /// assert_eq!(diff.registers, [(Register::PC, 0x0000, 0x0002)]);
+    /// assert_eq!(diff.to_string(), "PC: $0000 -> $0002\nflags: +Z\ncycles: 0 -> 2\n");
+    /// ```
+    #[must_use]
+    pub fn between_states(old: &CpuState, new: &CpuState) -> StateDiff {
+        StateDiff::registers((&old.registers, old.cycles), (&new.registers, new.cycles))
+    }
+
+    /// The differences between two states, each given as its registers,
+    /// cycle count and a way to read its memory.
+    pub(crate) fn compute(
+        (old, old_cycles, old_memory): (&Registers, u64, impl Fn(u16) -> u8),
+        (new, new_cycles, new_memory): (&Registers, u64, impl Fn(u16) -> u8),
+    ) -> StateDiff {
+        let memory = (0..=u16::MAX)
+            .filter_map(|address| {
+                let (old, new) = (old_memory(address), new_memory(address));
+                (old != new).then_some((address, old, new))
+            })
+            .collect();
+        StateDiff {
+            memory,
+            ..StateDiff::registers((old, old_cycles), (new, new_cycles))
+        }
+    }
+
+    /// The differences in registers, flags and cycles alone.
+    fn registers(
+        (old, old_cycles): (&Registers, u64),
+        (new, new_cycles): (&Registers, u64),
+    ) -> StateDiff {
+        let registers = [
+            (Register::A, old.accumulator.into(), new.accumulator.into()),
+            (Register::X, old.index_x.into(), new.index_x.into()),
+            (Register::Y, old.index_y.into(), new.index_y.into()),
+            (
+                Register::SP,
+                old.stack_pointer.0.into(),
+                new.stack_pointer.0.into(),
+            ),
+            (Register::PC, old.program_counter, new.program_counter),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .collect();
+        StateDiff {
+            registers,
+            set_flags: new.status.difference(old.status) - Status::PS_UNUSED,
+            cleared_flags: old.status.difference(new.status) - Status::PS_UNUSED,
+            memory: Vec::new(),
+            cycles: (old_cycles != new_cycles).then_some((old_cycles, new_cycles)),
+        }
+    }
+
+    /// Whether the states are the same.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.set_flags.is_empty()
+            && self.cleared_flags.is_empty()
+            && self.memory.is_empty()
+            && self.cycles.is_none()
+    }
+}
+
+/// One change per line: registers, then flags, memory and cycles.
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(register, old, new) in &self.registers {
+            if register == Register::PC {
+                writeln!(f, "{register}: ${old:04X} -> ${new:04X}")?;
+            } else {
+                writeln!(f, "{register}: ${old:02X} -> ${new:02X}")?;
+            }
+        }
+        if !(self.set_flags | self.cleared_flags).is_empty() {
+            f.write_str("flags:")?;
+            for (name, flag) in FLAGS {
+                if self.set_flags.contains(flag) {
+                    write!(f, " +{name}")?;
+                } else if self.cleared_flags.contains(flag) {
+                    write!(f, " -{name}")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        for (address, old, new) in &self.memory {
+            writeln!(f, "${address:04X}: ${old:02X} -> ${new:02X}")?;
+        }
+        if let Some((old, new)) = self.cycles {
+            writeln!(f, "cycles: {old} -> {new}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::{Bus, Memory};
+    use alloc::string::ToString;
+
+    #[test]
+    fn identical_states_have_no_differences() {
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let snapshot = Snapshot::capture(&cpu);
+        let diff = StateDiff::between(&snapshot, &snapshot);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[test]
+    fn lists_every_kind_of_change() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.registers.status = Status::PS_CARRY | Status::PS_UNUSED;
+        let old = Snapshot::capture(&cpu);
+        cpu.registers.index_y = 0x7f;
+        cpu.registers.stack_pointer.0 = 0xfa;
+        cpu.registers.status = Status::PS_ZERO | Status::PS_OVERFLOW;
+        cpu.memory.set_byte(0xc000, 0xea);
+        cpu.memory.set_byte(0x0002, 0x01);
+        let new = Snapshot::capture(&cpu);
+
+        let diff = StateDiff::between(&old, &new);
+        assert_eq!(
+            diff.registers,
+            [(Register::Y, 0x00, 0x7f), (Register::SP, 0xfd, 0xfa)]
+        );
+        assert_eq!(diff.set_flags, Status::PS_ZERO | Status::PS_OVERFLOW);
+        assert_eq!(diff.cleared_flags, Status::PS_CARRY);
+        assert_eq!(diff.memory, [(0x0002, 0x00, 0x01), (0xc000, 0x00, 0xea)]);
+        assert_eq!(diff.cycles, None);
+        assert_eq!(
+            diff.to_string(),
+            "Y: $00 -> $7F\nSP: $FD -> $FA\nflags: +V +Z -C\n$0002: $00 -> $01\n$C000: $00 -> $EA\n"
+        );
+    }
+}
@@ -0,0 +1,835 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Turning machine code back into assembly.
+//!
+//! A [`Disassembler`] walks a byte slice and yields one [`Line`] per
+//! instruction. Lines format as conventional 6502 assembly, e. g.
+//! `LDA ($10),Y`, and can name addresses through any [`Labels`]
+//! implementation.
+//!
+//! [`WithLabels::dialect`] writes lines in the syntax of a particular
+//! assembler, so that a disassembly can be fed back to it.
+//!
+//! Memory that isn't a slice, such as a running machine's bus, goes
+//! through [`OwnedLine`]s instead: `Machine::disassemble_at` returns those
+//! for any address range.
+//!
+//! Tools that analyze code rather than print it can take each line apart
+//! instead: [`Line::decoded_operand`] gives the operand as an [`Operand`],
+//! and [`Line::class`] says whether the instruction reads, writes or
+//! transfers control.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::dialect::Dialect;
+use crate::instruction::{AddressingMode, Instruction, Nmos6502};
+use crate::memory::Bus;
+use crate::registers::Registers;
+use crate::Variant;
+
+/// Names addresses in a disassembly.
+pub trait Labels {
+    /// The label for `address`, if it has one.
+    fn label(&self, address: u16) -> Option<&str>;
+}
+
+/// Leaves every address as a number.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoLabels;
+
+impl Labels for NoLabels {
+    fn label(&self, _address: u16) -> Option<&str> {
+        None
+    }
+}
+
+/// An index register added to an operand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Index {
+    X,
+    Y,
+}
+
+/// The operand of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// The instruction takes no operand.
+    Implied,
+    Accumulator,
+    Immediate(u8),
+    /// The memory at `address`, plus the index register if there is one.
+    Direct {
+        address: u16,
+        index: Option<Index>,
+    },
+    /// The memory at the address held in the pointer at `pointer`. An X
+    /// index is added to the pointer, a Y index to the address read from
+    /// it.
+    Indirect {
+        pointer: u16,
+        index: Option<Index>,
+    },
+    /// The destination of a relative branch.
+    Relative(u16),
+}
+
+/// What an instruction does with its operand, as far as analysis tools
+/// are concerned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Class {
+    /// Reads the memory its operand refers to.
+    Read,
+    /// Writes the memory its operand refers to.
+    Write,
+    /// Reads, modifies and writes back the memory its operand refers to.
+    ReadModifyWrite,
+    /// Branches to its operand if a flag is set or clear.
+    Branch,
+    /// Always continues at its operand.
+    Jump,
+    /// `JSR`.
+    Call,
+    /// `RTS` or `RTI`.
+    Return,
+    /// `BRK`.
+    Interrupt,
+    /// Works on registers, flags or the stack only.
+    Other,
+}
+
+/// Classifies `instruction` with operands in `mode`.
+#[must_use]
+pub const fn classify(instruction: Instruction, mode: AddressingMode) -> Class {
+    let memory = !matches!(
+        mode,
+        AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Immediate
+    );
+    match instruction {
+        Instruction::BCC
+        | Instruction::BCS
+        | Instruction::BEQ
+        | Instruction::BMI
+        | Instruction::BNE
+        | Instruction::BPL
+        | Instruction::BVC
+        | Instruction::BVS => Class::Branch,
+        Instruction::BRA | Instruction::JMP => Class::Jump,
+        Instruction::JSR => Class::Call,
+        Instruction::RTS | Instruction::RTI => Class::Return,
+        Instruction::BRK | Instruction::BRKcld => Class::Interrupt,
+        Instruction::STA
+        | Instruction::STX
+        | Instruction::STY
+        | Instruction::STZ
+        | Instruction::SAX => Class::Write,
+        Instruction::ASL
+        | Instruction::LSR
+        | Instruction::ROL
+        | Instruction::ROR
+        | Instruction::INC
+        | Instruction::DEC
+        | Instruction::TSB
+        | Instruction::TRB
+        | Instruction::SLO
+        | Instruction::RLA
+        | Instruction::SRE
+        | Instruction::RRA
+        | Instruction::RRAnd
+        | Instruction::DCP
+        | Instruction::ISC
+        | Instruction::ISCnd
+            if memory =>
+        {
+            Class::ReadModifyWrite
+        }
+        _ if memory => Class::Read,
+        _ => Class::Other,
+    }
+}
+
+/// Where an instruction's operand lands once index registers and pointers
+/// are applied.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EffectiveAddress {
+    pub address: u16,
+    /// Whether indexing carried into the high byte, or a branch lands on a
+    /// different page than the following instruction. Either costs a cycle
+    /// on the real chip, though stores and read-modify-write instructions
+    /// always take it.
+    pub page_crossed: bool,
+}
+
+/// A single disassembled instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// Address of the opcode.
+    pub address: u16,
+    /// The opcode followed by its operand bytes.
+    pub bytes: &'a [u8],
+    /// `None` if the opcode is not valid for the variant, or if the input
+    /// ends in the middle of the instruction. Such lines hold a single byte.
+    pub decoded: Option<(Instruction, AddressingMode)>,
+}
+
+impl Line<'_> {
+    /// The raw operand, without applying any indexing.
+    #[must_use]
+    pub fn operand(&self) -> Option<u16> {
+        match self.bytes {
+            [_, lo] => Some(u16::from(*lo)),
+            [_, lo, hi] => Some(u16::from_le_bytes([*lo, *hi])),
+            _ => None,
+        }
+    }
+
+    /// The address the operand refers to, before indexing. Branch offsets are
+    /// resolved to their destination. `None` for instructions that don't
+    /// reference memory through their operand.
+    #[must_use]
+    pub fn target(&self) -> Option<u16> {
+        let (_, mode) = self.decoded?;
+        let operand = self.operand()?;
+        match mode {
+            AddressingMode::Accumulator | AddressingMode::Implied | AddressingMode::Immediate => {
+                None
+            }
+            AddressingMode::Relative => Some(branch_target(self.address, operand)),
+            _ => Some(operand),
+        }
+    }
+
+    /// The operand taken apart, with branch offsets resolved to their
+    /// destination. `None` for lines that didn't decode.
+    #[must_use]
+    pub fn decoded_operand(&self) -> Option<Operand> {
+        let (_, mode) = self.decoded?;
+        let operand = self.operand().unwrap_or_default();
+        let direct = |index| Operand::Direct {
+            address: operand,
+            index,
+        };
+        let indirect = |index| Operand::Indirect {
+            pointer: operand,
+            index,
+        };
+        Some(match mode {
+            AddressingMode::Implied => Operand::Implied,
+            AddressingMode::Accumulator => Operand::Accumulator,
+            AddressingMode::Immediate => Operand::Immediate(self.bytes[1]),
+            AddressingMode::Relative => Operand::Relative(branch_target(self.address, operand)),
+            AddressingMode::ZeroPage | AddressingMode::Absolute => direct(None),
+            AddressingMode::ZeroPageX | AddressingMode::AbsoluteX => direct(Some(Index::X)),
+            AddressingMode::ZeroPageY | AddressingMode::AbsoluteY => direct(Some(Index::Y)),
+            AddressingMode::Indirect
+            | AddressingMode::BuggyIndirect
+            | AddressingMode::ZeroPageIndirect => indirect(None),
+            AddressingMode::IndexedIndirectX | AddressingMode::AbsoluteIndexedIndirect => {
+                indirect(Some(Index::X))
+            }
+            AddressingMode::IndirectIndexedY => indirect(Some(Index::Y)),
+        })
+    }
+
+    /// Resolves the operand against `registers` and `memory` without
+    /// executing anything, e.g. to show where a store is about to write.
+    /// Pointers are read with [`Bus::get_byte`]. Branches resolve to their
+    /// destination whether or not they would be taken. `None` for lines that
+    /// didn't decode and for instructions with no memory operand.
+    #[must_use]
+    pub fn effective_address<M: Bus + ?Sized>(
+        &self,
+        registers: &Registers,
+        memory: &M,
+    ) -> Option<EffectiveAddress> {
+        let (_, mode) = self.decoded?;
+        let operand = self.operand()?;
+        let x = u16::from(registers.index_x);
+        let y = u16::from(registers.index_y);
+        let [lo, hi] = operand.to_le_bytes();
+        let pointer = |lo: u8, hi: u8| {
+            u16::from_le_bytes([
+                memory.get_byte(u16::from_le_bytes([lo, hi])),
+                memory.get_byte(u16::from_le_bytes([lo.wrapping_add(1), hi])),
+            ])
+        };
+        let indexed = |base: u16, index: u16| {
+            let address = base.wrapping_add(index);
+            EffectiveAddress {
+                address,
+                page_crossed: address >> 8 != base >> 8,
+            }
+        };
+        let plain = |address| EffectiveAddress {
+            address,
+            page_crossed: false,
+        };
+        Some(match mode {
+            AddressingMode::Accumulator | AddressingMode::Implied | AddressingMode::Immediate => {
+                return None;
+            }
+            AddressingMode::Relative => {
+                let address = branch_target(self.address, operand);
+                EffectiveAddress {
+                    address,
+                    page_crossed: address >> 8 != self.address.wrapping_add(2) >> 8,
+                }
+            }
+            AddressingMode::ZeroPage | AddressingMode::Absolute => plain(operand),
+            AddressingMode::ZeroPageX => plain(u16::from(lo.wrapping_add(registers.index_x))),
+            AddressingMode::ZeroPageY => plain(u16::from(lo.wrapping_add(registers.index_y))),
+            AddressingMode::AbsoluteX => indexed(operand, x),
+            AddressingMode::AbsoluteY => indexed(operand, y),
+            AddressingMode::Indirect => plain(u16::from_le_bytes([
+                memory.get_byte(operand),
+                memory.get_byte(operand.wrapping_add(1)),
+            ])),
+            AddressingMode::BuggyIndirect => plain(pointer(lo, hi)),
+            AddressingMode::IndexedIndirectX => {
+                plain(pointer(lo.wrapping_add(registers.index_x), 0))
+            }
+            AddressingMode::IndirectIndexedY => indexed(pointer(lo, 0), y),
+            AddressingMode::ZeroPageIndirect => plain(pointer(lo, 0)),
+            AddressingMode::AbsoluteIndexedIndirect => {
+                let pointer = operand.wrapping_add(x);
+                plain(u16::from_le_bytes([
+                    memory.get_byte(pointer),
+                    memory.get_byte(pointer.wrapping_add(1)),
+                ]))
+            }
+        })
+    }
+
+    /// What the instruction does with its operand. `None` for lines that
+    /// didn't decode.
+    #[must_use]
+    pub fn class(&self) -> Option<Class> {
+        let (instruction, mode) = self.decoded?;
+        Some(classify(instruction, mode))
+    }
+
+    /// Formats the line, naming addresses with `labels`.
+    #[must_use]
+    pub const fn with_labels<'l, L: Labels + ?Sized>(&'l self, labels: &'l L) -> WithLabels<'l, L> {
+        WithLabels {
+            line: self,
+            labels,
+            dialect: Dialect::Generic,
+        }
+    }
+
+    fn format(
+        &self,
+        f: &mut fmt::Formatter,
+        labels: &(impl Labels + ?Sized),
+        dialect: Dialect,
+    ) -> fmt::Result {
+        let Some((instruction, mode)) = self.decoded else {
+            let prefix = dialect.directive_prefix();
+            return write!(f, "{prefix}byte ${:02X}", self.bytes[0]);
+        };
+        f.write_str(instruction.mnemonic())?;
+
+        let byte = |f: &mut fmt::Formatter, value: u16| match labels.label(value) {
+            Some(label) => f.write_str(label),
+            None => write!(f, "${value:02X}"),
+        };
+        let word = |f: &mut fmt::Formatter, value: u16| match labels.label(value) {
+            Some(label) => f.write_str(label),
+            None => write!(f, "${value:04X}"),
+        };
+
+        let operand = self.operand().unwrap_or_default();
+        match mode {
+            AddressingMode::Implied => Ok(()),
+            AddressingMode::Accumulator if dialect.explicit_accumulator() => f.write_str(" A"),
+            AddressingMode::Accumulator => Ok(()),
+            AddressingMode::Immediate => write!(f, " #${operand:02X}"),
+            AddressingMode::ZeroPage => {
+                f.write_str(" ")?;
+                byte(f, operand)
+            }
+            AddressingMode::ZeroPageX => {
+                f.write_str(" ")?;
+                byte(f, operand)?;
+                f.write_str(",X")
+            }
+            AddressingMode::ZeroPageY => {
+                f.write_str(" ")?;
+                byte(f, operand)?;
+                f.write_str(",Y")
+            }
+            AddressingMode::Relative => {
+                f.write_str(" ")?;
+                word(f, branch_target(self.address, operand))
+            }
+            AddressingMode::Absolute => {
+                f.write_str(" ")?;
+                word(f, operand)
+            }
+            AddressingMode::AbsoluteX => {
+                f.write_str(" ")?;
+                word(f, operand)?;
+                f.write_str(",X")
+            }
+            AddressingMode::AbsoluteY => {
+                f.write_str(" ")?;
+                word(f, operand)?;
+                f.write_str(",Y")
+            }
+            AddressingMode::Indirect | AddressingMode::BuggyIndirect => {
+                f.write_str(" (")?;
+                word(f, operand)?;
+                f.write_str(")")
+            }
+            AddressingMode::IndexedIndirectX => {
+                f.write_str(" (")?;
+                byte(f, operand)?;
+                f.write_str(",X)")
+            }
+            AddressingMode::IndirectIndexedY => {
+                f.write_str(" (")?;
+                byte(f, operand)?;
+                f.write_str("),Y")
+            }
+            AddressingMode::ZeroPageIndirect => {
+                f.write_str(" (")?;
+                byte(f, operand)?;
+                f.write_str(")")
+            }
+            AddressingMode::AbsoluteIndexedIndirect => {
+                f.write_str(" (")?;
+                word(f, operand)?;
+                f.write_str(",X)")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.format(f, &NoLabels, Dialect::Generic)
+    }
+}
+
+/// A [`Line`] that holds its own bytes, for disassembling memory that
+/// can't be borrowed as a slice, such as a [`Bus`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OwnedLine {
+    /// Address of the opcode.
+    pub address: u16,
+    bytes: [u8; 3],
+    len: u8,
+    /// As [`Line::decoded`].
+    pub decoded: Option<(Instruction, AddressingMode)>,
+}
+
+impl OwnedLine {
+    /// The opcode followed by its operand bytes.
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+
+    /// Borrows this as a [`Line`], for its operand decoding and formatting.
+    #[must_use]
+    pub fn line(&self) -> Line<'_> {
+        Line {
+            address: self.address,
+            bytes: self.bytes(),
+            decoded: self.decoded,
+        }
+    }
+}
+
+impl From<Line<'_>> for OwnedLine {
+    fn from(line: Line<'_>) -> Self {
+        let mut bytes = [0; 3];
+        bytes[..line.bytes.len()].copy_from_slice(line.bytes);
+        OwnedLine {
+            address: line.address,
+            bytes,
+            // A line is at most three bytes long.
+            #[allow(clippy::cast_possible_truncation)]
+            len: line.bytes.len() as u8,
+            decoded: line.decoded,
+        }
+    }
+}
+
+impl fmt::Display for OwnedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.line().fmt(f)
+    }
+}
+
+/// A [`Line`] formatted with labels; see [`Line::with_labels`].
+#[derive(Debug)]
+pub struct WithLabels<'l, L: ?Sized> {
+    line: &'l Line<'l>,
+    labels: &'l L,
+    dialect: Dialect,
+}
+
+impl<L: ?Sized> WithLabels<'_, L> {
+    /// Formats in the syntax of `dialect` rather than the generic one.
+    #[must_use]
+    pub const fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+}
+
+impl<L: Labels + ?Sized> fmt::Display for WithLabels<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.line.format(f, self.labels, self.dialect)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn branch_target(address: u16, offset: u16) -> u16 {
+    // The offset is relative to the instruction following the branch.
+    address
+        .wrapping_add(2)
+        .wrapping_add_signed((offset as u8).cast_signed() as i16)
+}
+
+/// Iterates over the instructions in a byte slice.
+///
+/// The variant decides which opcodes are valid; it defaults to the NMOS 6502.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::disasm::Disassembler;
+///
+/// let code = [0xa2, 0x08, 0xca, 0xd0, 0xfd];
+/// let lines: Vec<String> = Disassembler::new(&code, 0xc000)
+///     .map(|line| line.to_string())
+///     .collect();
+/// assert_eq!(lines, ["LDX #$08", "DEX", "BNE $C002"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Disassembler<'a, V = Nmos6502> {
+    bytes: &'a [u8],
+    org: u16,
+    offset: usize,
+    variant: PhantomData<V>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Disassembles NMOS 6502 code loaded at `org`.
+    #[must_use]
+    pub const fn new(bytes: &'a [u8], org: u16) -> Self {
+        Disassembler::for_variant(bytes, org)
+    }
+}
+
+impl<'a, V> Disassembler<'a, V> {
+    /// Disassembles code for the variant `V` loaded at `org`.
+    #[must_use]
+    pub const fn for_variant(bytes: &'a [u8], org: u16) -> Self {
+        Disassembler {
+            bytes,
+            org,
+            offset: 0,
+            variant: PhantomData,
+        }
+    }
+}
+
+impl<'a, V: Variant> Iterator for Disassembler<'a, V> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Line<'a>> {
+        let rest = self
+            .bytes
+            .get(self.offset..)
+            .filter(|rest| !rest.is_empty())?;
+        // Addresses wrap around the top of memory, like the program counter.
+        #[allow(clippy::cast_possible_truncation)]
+        let address = self.org.wrapping_add(self.offset as u16);
+
+        let decoded =
+            V::decode(rest[0]).filter(|(_, mode)| usize::from(mode.extra_bytes()) < rest.len());
+        let len = decoded.map_or(1, |(_, mode)| 1 + usize::from(mode.extra_bytes()));
+        self.offset += len;
+
+        Some(Line {
+            address,
+            bytes: &rest[..len],
+            decoded,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::instruction::Cmos6502;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    struct Reset;
+
+    impl Labels for Reset {
+        fn label(&self, address: u16) -> Option<&str> {
+            (address == 0xc000).then_some("reset")
+        }
+    }
+
+    fn listing<V: Variant>(code: &[u8], org: u16) -> Vec<String> {
+        Disassembler::<V>::for_variant(code, org)
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn owned_lines_keep_their_bytes() {
+        let code = [0xb1, 0x10, 0xea];
+        let owned: Vec<OwnedLine> = Disassembler::new(&code, 0x0200)
+            .map(OwnedLine::from)
+            .collect();
+        assert_eq!(owned[0].bytes(), [0xb1, 0x10]);
+        assert_eq!(owned[0].to_string(), "LDA ($10),Y");
+        assert_eq!(
+            owned[1].line().class(),
+            Disassembler::new(&code[2..], 0x0202)
+                .next()
+                .unwrap()
+                .class()
+        );
+    }
+
+    #[test]
+    fn resolves_effective_addresses() {
+        use crate::memory::Memory;
+
+        let mut memory = Memory::new();
+        memory.set_bytes(0x0010, &[0xf0, 0x12]);
+        memory.set_bytes(0x00ff, &[0x34]);
+        memory.set_bytes(0x0000, &[0x56]);
+        let mut registers = Registers::new();
+        registers.index_x = 0x20;
+        registers.index_y = 0x20;
+        let resolve = |code: &[u8], address| {
+            Disassembler::<Cmos6502>::for_variant(code, address)
+                .next()
+                .unwrap()
+                .effective_address(&registers, &memory)
+        };
+        let at = |address, page_crossed| {
+            Some(EffectiveAddress {
+                address,
+                page_crossed,
+            })
+        };
+
+        // STA $C0F0,X; LDA $C000,Y; LDA $F0,X
+        assert_eq!(resolve(&[0x9d, 0xf0, 0xc0], 0), at(0xc110, true));
+        assert_eq!(resolve(&[0xb9, 0x00, 0xc0], 0), at(0xc020, false));
+        assert_eq!(resolve(&[0xb5, 0xf0], 0), at(0x0010, false));
+        // LDA ($10),Y; LDA ($F0,X); LDA ($FF)
+        assert_eq!(resolve(&[0xb1, 0x10], 0), at(0x1310, true));
+        assert_eq!(resolve(&[0xa1, 0xf0], 0), at(0x12f0, false));
+        assert_eq!(resolve(&[0xb2, 0xff], 0), at(0x5634, false));
+        // BNE -4 from the start of a page; LDA #$01 and NOP have no address.
+        assert_eq!(resolve(&[0xd0, 0xfc], 0x0200), at(0x01fe, true));
+        assert_eq!(resolve(&[0xa9, 0x01], 0), None);
+        assert_eq!(resolve(&[0xea], 0), None);
+    }
+
+    #[test]
+    fn formats_every_addressing_mode() {
+        #[rustfmt::skip]
+        let code = [
+            0x0a,             // ASL A
+            0xa9, 0x2a,       // LDA #$2A
+            0xa5, 0x10,       // LDA $10
+            0xb5, 0x10,       // LDA $10,X
+            0xb6, 0x10,       // LDX $10,Y
+            0xad, 0x34, 0x12, // LDA $1234
+            0xbd, 0x34, 0x12, // LDA $1234,X
+            0xb9, 0x34, 0x12, // LDA $1234,Y
+            0x6c, 0x34, 0x12, // JMP ($1234)
+            0xa1, 0x10,       // LDA ($10,X)
+            0xb1, 0x10,       // LDA ($10),Y
+            0xf0, 0xfe,       // BEQ to itself
+        ];
+        assert_eq!(
+            listing::<Nmos6502>(&code, 0x0200),
+            [
+                "ASL A",
+                "LDA #$2A",
+                "LDA $10",
+                "LDA $10,X",
+                "LDX $10,Y",
+                "LDA $1234",
+                "LDA $1234,X",
+                "LDA $1234,Y",
+                "JMP ($1234)",
+                "LDA ($10,X)",
+                "LDA ($10),Y",
+                "BEQ $0219",
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_come_apart_into_operands_and_classes() {
+        #[rustfmt::skip]
+        let code = [
+            0xa9, 0x2a,       // LDA #$2A
+            0x9d, 0x34, 0x12, // STA $1234,X
+            0xe6, 0x10,       // INC $10
+            0x0a,             // ASL A
+            0xb1, 0x10,       // LDA ($10),Y
+            0x6c, 0x34, 0x12, // JMP ($1234)
+            0xd0, 0xfe,       // BNE to itself
+            0x20, 0x00, 0x03, // JSR $0300
+            0x60,             // RTS
+        ];
+        let lines: Vec<_> = Disassembler::new(&code, 0x0200)
+            .map(|line| (line.decoded_operand().unwrap(), line.class().unwrap()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (Operand::Immediate(0x2a), Class::Other),
+                (
+                    Operand::Direct {
+                        address: 0x1234,
+                        index: Some(Index::X)
+                    },
+                    Class::Write
+                ),
+                (
+                    Operand::Direct {
+                        address: 0x0010,
+                        index: None
+                    },
+                    Class::ReadModifyWrite
+                ),
+                (Operand::Accumulator, Class::Other),
+                (
+                    Operand::Indirect {
+                        pointer: 0x0010,
+                        index: Some(Index::Y)
+                    },
+                    Class::Read
+                ),
+                (
+                    Operand::Indirect {
+                        pointer: 0x1234,
+                        index: None
+                    },
+                    Class::Jump
+                ),
+                (Operand::Relative(0x020d), Class::Branch),
+                (
+                    Operand::Direct {
+                        address: 0x0300,
+                        index: None
+                    },
+                    Class::Call
+                ),
+                (Operand::Implied, Class::Return),
+            ]
+        );
+        let data = Disassembler::new(&[0x02], 0).next().unwrap();
+        assert_eq!((data.decoded_operand(), data.class()), (None, None));
+    }
+
+    #[test]
+    fn dialects_round_trip_through_the_assembler() {
+        use crate::asm::assemble_with;
+        use core::fmt::Write as _;
+
+        let code = [0x0a, 0xa9, 0x2a, 0x8d, 0x34, 0x12, 0x02];
+        for dialect in [
+            Dialect::Generic,
+            Dialect::Ca65,
+            Dialect::Acme,
+            Dialect::Tass64,
+        ] {
+            let mut source = String::new();
+            for line in Disassembler::new(&code, 0x0200) {
+                let _ = writeln!(source, "  {}", line.with_labels(&NoLabels).dialect(dialect));
+            }
+            let program = assemble_with::<Nmos6502>(&source, dialect).unwrap();
+            assert_eq!(program.bytes, code, "{dialect:?}:\n{source}");
+        }
+        let line = Disassembler::new(&code, 0).next().unwrap();
+        assert_eq!(
+            line.with_labels(&NoLabels)
+                .dialect(Dialect::Acme)
+                .to_string(),
+            "ASL"
+        );
+    }
+
+    #[test]
+    fn variant_decides_valid_opcodes() {
+        // STZ $0202 only exists on the 65C02; the NMOS chip's $9C is
+        // unstable and $02 jams it.
+        assert_eq!(
+            listing::<Nmos6502>(&[0x9c, 0x02, 0x02], 0),
+            [".byte $9C", ".byte $02", ".byte $02"]
+        );
+        assert_eq!(listing::<Cmos6502>(&[0x9c, 0x02, 0x02], 0), ["STZ $0202"]);
+        assert_eq!(
+            listing::<Nmos6502>(&[0x64, 0x10, 0xa7, 0x10], 0),
+            ["NOP $10", "LAX $10"]
+        );
+        assert_eq!(
+            listing::<Cmos6502>(&[0x7c, 0x34, 0x12, 0x02, 0x10], 0),
+            ["JMP ($1234,X)", "NOP #$10"]
+        );
+    }
+
+    #[test]
+    fn truncated_instruction_is_data() {
+        let lines: Vec<Line> = Disassembler::new(&[0xea, 0x4c, 0x00], 0x8000).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].address, 0x8001);
+        assert_eq!(lines[1].decoded, None);
+    }
+
+    #[test]
+    fn labels_replace_addresses() {
+        let code = [0x4c, 0x00, 0xc0, 0xd0, 0xfb];
+        let lines: Vec<_> = Disassembler::new(&code, 0xc000)
+            .map(|line| line.with_labels(&Reset).to_string())
+            .collect();
+        assert_eq!(lines, ["JMP reset", "BNE reset"]);
+        assert_eq!(
+            Disassembler::new(&code, 0xc000).nth(1).unwrap().target(),
+            Some(0xc000)
+        );
+    }
+}
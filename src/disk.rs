@@ -0,0 +1,306 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A block device backed by a host file.
+//!
+//! Firmware that loads programs and data from "disk" usually only needs to
+//! move fixed-size sectors between the medium and memory. [`BlockDevice`]
+//! provides that without modelling a real disk controller: the program sets
+//! a sector number and a buffer address, then stores a command, and the
+//! sector is copied at once.
+//!
+//! | Offset | Register  | Access                                           |
+//! |--------|-----------|--------------------------------------------------|
+//! | 0      | `COMMAND` | store `READ` or `WRITE` to transfer a sector     |
+//! | 1      | `STATUS`  | read only: `OK`, or `ERROR` if the command failed |
+//! | 2-3    | `SECTOR`  | the sector number, LE                            |
+//! | 4-5    | `BUFFER`  | the address of the `SECTOR_SIZE`-byte buffer, LE |
+//!
+//! Reading beyond the end of the image gives zeroes, and writing there
+//! grows it, so an empty file works as a blank disk.
+
+use core::ops::Range;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::memory::{Bus, BusError};
+
+pub const COMMAND: u16 = 0;
+pub const STATUS: u16 = 1;
+pub const SECTOR: u16 = 2;
+pub const BUFFER: u16 = 4;
+
+/// Number of addresses the registers take up.
+pub const SIZE: u16 = 6;
+
+/// Bytes in a sector.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Commands, stored to [`COMMAND`].
+pub const READ: u8 = 1;
+pub const WRITE: u8 = 2;
+
+/// Values of [`STATUS`].
+pub const OK: u8 = 0;
+pub const ERROR: u8 = 0xFF;
+
+/// A bus with the block device registers at `base`..`base + SIZE`,
+/// transferring sectors of `image`.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use mos6502::disk::{BlockDevice, BUFFER, COMMAND, OK, READ, SECTOR, STATUS};
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut image = vec![0; 1024];
+/// image[512] = 0x42;
+/// let mut bus = BlockDevice::new(Memory::new(), 0xFE00, Cursor::new(image));
+/// bus.set_byte(0xFE00 + SECTOR, 1);
+/// bus.set_byte(0xFE00 + BUFFER + 1, 0x40);
+/// bus.set_byte(0xFE00 + COMMAND, READ);
+/// assert_eq!(bus.get_byte(0xFE00 + STATUS), OK);
+/// assert_eq!(bus.get_byte(0x4000), 0x42);
+/// ```
+#[derive(Debug)]
+pub struct BlockDevice<B: Bus, F: Read + Write + Seek> {
+    inner: B,
+    base: u16,
+    image: F,
+    sector: u16,
+    buffer: u16,
+    status: u8,
+}
+
+impl<B: Bus> BlockDevice<B, File> {
+    /// Maps the registers at `base` over `inner`, using the file at `path`
+    /// as the disk. The file is created if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from opening the file.
+    pub fn open(inner: B, base: u16, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(BlockDevice::new(inner, base, file))
+    }
+}
+
+impl<B: Bus, F: Read + Write + Seek> BlockDevice<B, F> {
+    /// Maps the registers at `base` over `inner`, using `image` as the disk.
+    pub const fn new(inner: B, base: u16, image: F) -> Self {
+        BlockDevice {
+            inner,
+            base,
+            image,
+            sector: 0,
+            buffer: 0,
+            status: OK,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[must_use]
+    pub const fn image(&self) -> &F {
+        &self.image
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> (B, F) {
+        (self.inner, self.image)
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset < SIZE).then_some(offset)
+    }
+
+    fn seek(&mut self) -> io::Result<()> {
+        let offset = u64::from(self.sector) * SECTOR_SIZE as u64;
+        self.image.seek(SeekFrom::Start(offset)).map(drop)
+    }
+
+    fn read_sector(&mut self) -> io::Result<()> {
+        self.seek()?;
+        let mut data = [0; SECTOR_SIZE];
+        let mut filled = 0;
+        while filled < SECTOR_SIZE {
+            match self.image.read(&mut data[filled..])? {
+                0 => break,
+                count => filled += count,
+            }
+        }
+        for (address, &byte) in (self.buffer..=u16::MAX).zip(&data) {
+            self.inner.set_byte(address, byte);
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self) -> io::Result<()> {
+        let mut data = [0; SECTOR_SIZE];
+        for (byte, address) in data.iter_mut().zip(self.buffer..=u16::MAX) {
+            *byte = self.inner.get_byte(address);
+        }
+        self.seek()?;
+        self.image.write_all(&data)?;
+        self.image.flush()
+    }
+}
+
+impl<B: Bus, F: Read + Write + Seek> Bus for BlockDevice<B, F> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.register(address) {
+            Some(STATUS) => self.status,
+            Some(offset @ (SECTOR | 3)) => self.sector.to_le_bytes()[usize::from(offset - SECTOR)],
+            Some(offset @ (BUFFER | 5)) => self.buffer.to_le_bytes()[usize::from(offset - BUFFER)],
+            Some(_) => 0,
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(COMMAND) => {
+                let result = match value {
+                    READ => self.read_sector(),
+                    WRITE => self.write_sector(),
+                    _ => Err(io::ErrorKind::InvalidInput.into()),
+                };
+                self.status = if result.is_ok() { OK } else { ERROR };
+            }
+            Some(offset @ (SECTOR | 3)) => {
+                let mut bytes = self.sector.to_le_bytes();
+                bytes[usize::from(offset - SECTOR)] = value;
+                self.sector = u16::from_le_bytes(bytes);
+            }
+            Some(offset @ (BUFFER | 5)) => {
+                let mut bytes = self.buffer.to_le_bytes();
+                bytes[usize::from(offset - BUFFER)] = value;
+                self.buffer = u16::from_le_bytes(bytes);
+            }
+            Some(_) => {}
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    /// Clears the registers. The image is left alone.
+    fn reset(&mut self) {
+        self.sector = 0;
+        self.buffer = 0;
+        self.status = OK;
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::io::Cursor;
+    use std::vec::Vec;
+
+    const BASE: u16 = 0xFE00;
+
+    fn select(disk: &mut BlockDevice<Memory, Cursor<Vec<u8>>>, sector: u16, buffer: u16) {
+        for (offset, byte) in (SECTOR..).zip(sector.to_le_bytes()) {
+            disk.set_byte(BASE + offset, byte);
+        }
+        for (offset, byte) in (BUFFER..).zip(buffer.to_le_bytes()) {
+            disk.set_byte(BASE + offset, byte);
+        }
+    }
+
+    #[test]
+    fn writes_grow_a_blank_image() {
+        let mut disk = BlockDevice::new(Memory::new(), BASE, Cursor::new(Vec::new()));
+        disk.inner_mut().set_bytes(0x2000, b"boot");
+        select(&mut disk, 2, 0x2000);
+        disk.set_byte(BASE + COMMAND, WRITE);
+        assert_eq!(disk.get_byte(BASE + STATUS), OK);
+        assert_eq!(disk.get_byte(BASE + SECTOR), 2);
+        assert_eq!(disk.get_byte(BASE + BUFFER + 1), 0x20);
+
+        let image = disk.image().get_ref();
+        assert_eq!(image.len(), 3 * SECTOR_SIZE);
+        assert_eq!(&image[2 * SECTOR_SIZE..][..4], b"boot");
+
+        // Sectors past the end of the image read as zeroes.
+        disk.inner_mut().set_byte(0x3000, 0xAA);
+        select(&mut disk, 9, 0x3000);
+        disk.set_byte(BASE + COMMAND, READ);
+        assert_eq!(disk.get_byte(BASE + STATUS), OK);
+        assert_eq!(disk.get_byte(0x3000), 0);
+
+        select(&mut disk, 2, 0x3000);
+        disk.set_byte(BASE + COMMAND, READ);
+        assert_eq!(disk.inner().get_bytes(0x3000..0x3004), b"boot");
+    }
+
+    #[test]
+    fn unknown_commands_fail() {
+        let mut disk = BlockDevice::new(Memory::new(), BASE, Cursor::new(Vec::new()));
+        disk.set_byte(BASE + COMMAND, 0x33);
+        assert_eq!(disk.get_byte(BASE + STATUS), ERROR);
+        disk.set_byte(BASE + COMMAND, READ);
+        assert_eq!(disk.get_byte(BASE + STATUS), OK);
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A machine ready to run Lee Davison's Enhanced BASIC.
+//!
+//! Enhanced BASIC comes with a minimal monitor, `min_mon.asm`, written for the
+//! Kowalski simulator. Built together, the two make a ROM image that ends at
+//! `$FFFF`, starts from the reset vector and does all of its I/O through two
+//! registers: a byte written to [`OUTPUT`] is printed, and reading
+//! [`INPUT`] returns the next key or zero if none is waiting. RAM fills the
+//! rest of the address space, which is what BASIC reports as free memory
+//! at start-up.
+//!
+//! The ROM is not distributed with this crate; build it from the BASIC
+//! sources and pass the image to [`machine`]. `ON IRQ` handlers run when
+//! the IRQ line is asserted with [`CPU::set_irq`].
+//!
+//! [`CPU::set_irq`]: crate::cpu::CPU::set_irq
+
+use core::cell::RefCell;
+use core::ops::Range;
+use std::io::Write;
+
+use crate::console::ConsoleInput;
+use crate::cpu::CPU;
+use crate::instruction::Nmos6502;
+use crate::machine::Machine;
+use crate::memory::{Bus, Memory};
+use crate::testsuite::ImageError;
+
+/// Writing a byte here prints it.
+pub const OUTPUT: u16 = 0xF001;
+
+/// Reading here returns the next key, or zero if none is waiting.
+pub const INPUT: u16 = 0xF004;
+
+/// The largest ROM image accepted, which leaves RAM up to `$BFFF`.
+pub const MAX_ROM_SIZE: usize = 0x4000;
+
+/// RAM, a write-protected ROM at the top of memory, and the Enhanced BASIC
+/// character I/O registers.
+#[derive(Debug)]
+pub struct EhBasicBus<W: Write> {
+    memory: Memory,
+    rom_start: u16,
+    input: RefCell<ConsoleInput>,
+    output: W,
+}
+
+impl<W: Write> EhBasicBus<W> {
+    /// Returns `true` once the host input has ended.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.input.borrow().is_closed()
+    }
+
+    /// Returns the output written so far, if it was collected in memory.
+    #[must_use]
+    pub const fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// The first address of the ROM.
+    #[must_use]
+    pub const fn rom_start(&self) -> u16 {
+        self.rom_start
+    }
+}
+
+impl<W: Write> Bus for EhBasicBus<W> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.memory.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        if address == INPUT {
+            self.input.borrow_mut().poll().unwrap_or(0)
+        } else {
+            self.memory.get_byte(address)
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if address == OUTPUT {
+            // A closed or broken output must not stop the emulation.
+            let _ = self
+                .output
+                .write_all(&[value])
+                .and_then(|()| self.output.flush());
+        } else if address < self.rom_start {
+            self.memory.set_byte(address, value);
+        }
+    }
+}
+
+/// Builds a machine with `rom` at the top of memory, reading keys from
+/// `input` and printing to `output`, and points it at the reset vector.
+///
+/// # Errors
+///
+/// Returns [`ImageError::TooLarge`] if `rom` is bigger than
+/// [`MAX_ROM_SIZE`], and [`ImageError::Malformed`] if it is empty.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mos6502::console::{ConsoleInput, KeyEncoding};
+/// use mos6502::ehbasic;
+///
+/// let rom = std::fs::read("ehbasic.bin").unwrap();
+/// let input = ConsoleInput::stdin(KeyEncoding::Ascii);
+/// let mut machine = ehbasic::machine(&rom, input, std::io::stdout()).unwrap();
+/// while !machine.cpu.memory.is_closed() {
+///     machine.run(Some(100_000));
+/// }
+/// ```
+pub fn machine<W: Write>(
+    rom: &[u8],
+    input: ConsoleInput,
+    output: W,
+) -> Result<Machine<EhBasicBus<W>, Nmos6502>, ImageError> {
+    if rom.is_empty() {
+        return Err(ImageError::Malformed("the ROM is empty"));
+    }
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(ImageError::TooLarge);
+    }
+    let rom_start = u16::try_from(0x1_0000 - rom.len()).map_err(|_| ImageError::TooLarge)?;
+    let mut memory = Memory::new();
+    memory.set_bytes(rom_start, rom);
+
+    let mut cpu = CPU::new(
+        EhBasicBus {
+            memory,
+            rom_start,
+            input: RefCell::new(input),
+            output,
+        },
+        Nmos6502,
+    );
+    cpu.registers.program_counter =
+        u16::from_le_bytes([cpu.memory.get_byte(0xFFFC), cpu.memory.get_byte(0xFFFD)]);
+    Ok(Machine::new(cpu))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::KeyEncoding;
+    use std::vec::Vec;
+
+    /// A ROM at `$FF00` that echoes every key in upper case.
+    fn echo_rom() -> Vec<u8> {
+        let mut rom = std::vec![0; 0x100];
+        rom[..13].copy_from_slice(&[
+            0xad, 0x04, 0xf0, // loop: LDA INPUT
+            0xf0, 0xfb, //       BEQ loop
+            0x29, 0xdf, //       AND #$DF
+            0x8d, 0x01, 0xf0, // STA OUTPUT
+            0x4c, 0x00, 0xff, // JMP loop
+        ]);
+        rom[0xfc..0xfe].copy_from_slice(&[0x00, 0xff]);
+        rom
+    }
+
+    #[test]
+    fn runs_from_the_reset_vector() {
+        let input = ConsoleInput::from_reader(&b"run"[..], KeyEncoding::Ascii);
+        let mut machine = machine(&echo_rom(), input, Vec::new()).unwrap();
+        assert_eq!(machine.cpu.registers.program_counter, 0xff00);
+        for _ in 0..1000 {
+            machine.run(Some(100));
+            if machine.cpu.memory.output().len() == 3 {
+                break;
+            }
+            std::thread::yield_now();
+        }
+        assert_eq!(machine.cpu.memory.output(), b"RUN");
+    }
+
+    #[test]
+    fn rom_is_write_protected() {
+        let input = ConsoleInput::from_reader(&b""[..], KeyEncoding::Ascii);
+        let mut machine = machine(&echo_rom(), input, Vec::new()).unwrap();
+        machine.cpu.memory.set_byte(0xff00, 0xea);
+        machine.cpu.memory.set_byte(0xbfff, 0xea);
+        assert_eq!(machine.cpu.memory.get_byte(0xff00), 0xad);
+        assert_eq!(machine.cpu.memory.get_byte(0xbfff), 0xea);
+        assert!(matches!(
+            super::machine(
+                &std::vec![0; MAX_ROM_SIZE + 1],
+                ConsoleInput::from_reader(&b""[..], KeyEncoding::Ascii),
+                Vec::new()
+            ),
+            Err(ImageError::TooLarge)
+        ));
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A compact binary format for execution logs.
+//!
+//! Text traces of long runs quickly reach gigabytes. An execution log
+//! instead stores each executed instruction in about ten bytes, optionally
+//! followed by the bus accesses it made, and can be turned back into text
+//! or JSON when needed.
+//!
+//! The stream starts with the magic bytes `M65L` and a version byte. Each
+//! record then starts with a tag byte:
+//!
+//! | Tag | Record      | Payload                                              |
+//! |-----|-------------|------------------------------------------------------|
+//! | 1   | instruction | PC (LE u16), opcode, A, X, Y, P, SP, cycle delta     |
+//! | 2   | bus read    | address (LE u16), value                              |
+//! | 3   | bus write   | address (LE u16), value                              |
+//!
+//! The cycle delta is the number of cycles since the previous instruction
+//! record, as an unsigned LEB128 varint.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::string::String;
+
+use crate::buslog::{Access, BusEvent};
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::Bus;
+use crate::Variant;
+
+const MAGIC: &[u8; 4] = b"M65L";
+const VERSION: u8 = 1;
+
+const TAG_INSTRUCTION: u8 = 1;
+const TAG_READ: u8 = 2;
+const TAG_WRITE: u8 = 3;
+
+/// One entry of an execution log.
+// Both variants are small enough to copy around; boxing would only add
+// allocations to a hot path.
+#[allow(variant_size_differences)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    /// An instruction about to execute, with the CPU state before it.
+    Instruction {
+        pc: u16,
+        opcode: u8,
+        a: u8,
+        x: u8,
+        y: u8,
+        p: u8,
+        sp: u8,
+        cycles: u64,
+    },
+    /// A bus access made by the preceding instruction.
+    Bus {
+        access: Access,
+        address: u16,
+        value: u8,
+    },
+}
+
+impl Record {
+    /// Captures the state of `cpu` before it executes its next instruction.
+    #[must_use]
+    pub fn instruction<M: Bus, V: Variant>(cpu: &CPU<M, V>) -> Record {
+        let pc = cpu.registers.program_counter;
+        Record::Instruction {
+            pc,
+            opcode: cpu.memory.get_byte(pc),
+            a: cpu.registers.accumulator,
+            x: cpu.registers.index_x,
+            y: cpu.registers.index_y,
+            p: cpu.registers.status.to_byte(),
+            sp: cpu.registers.stack_pointer.0,
+            cycles: cpu.cycles,
+        }
+    }
+
+    /// Formats the record as a single-line JSON object.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        match *self {
+            Record::Instruction {
+                pc,
+                opcode,
+                a,
+                x,
+                y,
+                p,
+                sp,
+                cycles,
+            } => std::format!(
+                r#"{{"type":"instruction","pc":{pc},"opcode":{opcode},"a":{a},"x":{x},"y":{y},"p":{p},"sp":{sp},"cycles":{cycles}}}"#
+            ),
+            Record::Bus {
+                access,
+                address,
+                value,
+            } => {
+                let kind = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                };
+                std::format!(r#"{{"type":"{kind}","address":{address},"value":{value}}}"#)
+            }
+        }
+    }
+}
+
+impl From<BusEvent> for Record {
+    fn from(event: BusEvent) -> Self {
+        Record::Bus {
+            access: event.access,
+            address: event.address,
+            value: event.value,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Record::Instruction {
+                pc,
+                opcode,
+                a,
+                x,
+                y,
+                p,
+                sp,
+                cycles,
+            } => write!(
+                f,
+                "{pc:04X}  {opcode:02X}  A:{a:02X} X:{x:02X} Y:{y:02X} P:{p:02X} SP:{sp:02X} CYC:{cycles}"
+            ),
+            Record::Bus {
+                access,
+                address,
+                value,
+            } => {
+                let access = match access {
+                    Access::Read => 'R',
+                    Access::Write => 'W',
+                };
+                write!(f, "      {access} ${address:04X} = ${value:02X}")
+            }
+        }
+    }
+}
+
+/// Streams records to a writer.
+///
+/// The writer is not buffered; wrap files in a [`std::io::BufWriter`].
+#[derive(Debug)]
+pub struct LogWriter<W: Write> {
+    out: W,
+    last_cycles: u64,
+}
+
+impl<W: Write> LogWriter<W> {
+    /// Writes the stream header.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by `out`.
+    pub fn new(mut out: W) -> io::Result<LogWriter<W>> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(LogWriter {
+            out,
+            last_cycles: 0,
+        })
+    }
+
+    /// Appends a record.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        match *record {
+            Record::Instruction {
+                pc,
+                opcode,
+                a,
+                x,
+                y,
+                p,
+                sp,
+                cycles,
+            } => {
+                let [lo, hi] = pc.to_le_bytes();
+                let mut buf = [
+                    TAG_INSTRUCTION,
+                    lo,
+                    hi,
+                    opcode,
+                    a,
+                    x,
+                    y,
+                    p,
+                    sp,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                ];
+                let delta = cycles.wrapping_sub(self.last_cycles);
+                let len = 9 + encode_varint(delta, &mut buf[9..]);
+                self.last_cycles = cycles;
+                self.out.write_all(&buf[..len])
+            }
+            Record::Bus {
+                access,
+                address,
+                value,
+            } => {
+                let tag = match access {
+                    Access::Read => TAG_READ,
+                    Access::Write => TAG_WRITE,
+                };
+                let [lo, hi] = address.to_le_bytes();
+                self.out.write_all(&[tag, lo, hi, value])
+            }
+        }
+    }
+
+    /// Records the state of `cpu` and then executes one instruction.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn step<M: Bus, V: Variant>(
+        &mut self,
+        cpu: &mut CPU<M, V>,
+    ) -> io::Result<Option<DecodedInstr>> {
+        self.write(&Record::instruction(cpu))?;
+        Ok(cpu.single_step())
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads the records of an execution log, in order.
+#[derive(Debug)]
+pub struct LogReader<R: Read> {
+    input: R,
+    cycles: u64,
+}
+
+impl<R: Read> LogReader<R> {
+    /// Reads and checks the stream header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::InvalidData`] if the stream is not an
+    /// execution log of a supported version, or any error raised by `input`.
+    pub fn new(mut input: R) -> io::Result<LogReader<R>> {
+        let mut header = [0; 5];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an execution log"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported execution log version"));
+        }
+        Ok(LogReader { input, cycles: 0 })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut tag = [0];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        match tag[0] {
+            TAG_INSTRUCTION => {
+                let mut buf = [0; 8];
+                self.input.read_exact(&mut buf)?;
+                self.cycles = self.cycles.wrapping_add(self.read_varint()?);
+                Ok(Some(Record::Instruction {
+                    pc: u16::from_le_bytes([buf[0], buf[1]]),
+                    opcode: buf[2],
+                    a: buf[3],
+                    x: buf[4],
+                    y: buf[5],
+                    p: buf[6],
+                    sp: buf[7],
+                    cycles: self.cycles,
+                }))
+            }
+            TAG_READ | TAG_WRITE => {
+                let mut buf = [0; 3];
+                self.input.read_exact(&mut buf)?;
+                Ok(Some(Record::Bus {
+                    access: if tag[0] == TAG_READ {
+                        Access::Read
+                    } else {
+                        Access::Write
+                    },
+                    address: u16::from_le_bytes([buf[0], buf[1]]),
+                    value: buf[2],
+                }))
+            }
+            _ => Err(invalid("unknown record tag")),
+        }
+    }
+
+    fn read_varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            self.input.read_exact(&mut byte)?;
+            value |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("malformed cycle count"))
+    }
+}
+
+impl<R: Read> Iterator for LogReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes `value` as an unsigned LEB128 varint, returning its length.
+#[allow(clippy::cast_possible_truncation)]
+fn encode_varint(mut value: u64, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buslog::BusLogger;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    #[test]
+    fn round_trips_a_run() {
+        let mut bus = BusLogger::new(Memory::new());
+        bus.set_recording(true);
+        let mut cpu = CPU::new(bus, Nmos6502);
+        // LDA #$2A; STA $10; NOP
+        cpu.memory
+            .inner_mut()
+            .set_bytes(0x0000, &[0xa9, 0x2a, 0x85, 0x10, 0xea]);
+        cpu.cycles = 1_000_000;
+
+        let mut log = LogWriter::new(Vec::new()).unwrap();
+        let mut written = Vec::new();
+        for _ in 0..3 {
+            written.push(Record::instruction(&cpu));
+            log.step(&mut cpu).unwrap();
+            for event in cpu.memory.take_events() {
+                log.write(&event.into()).unwrap();
+                written.push(event.into());
+            }
+        }
+        let bytes = log.into_inner();
+
+        let read: Vec<Record> = LogReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, written);
+        assert_eq!(
+            read.iter()
+                .filter(|r| matches!(
+                    r,
+                    Record::Bus {
+                        access: Access::Write,
+                        ..
+                    }
+                ))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn formats_as_text_and_json() {
+        let record = Record::Instruction {
+            pc: 0xc000,
+            opcode: 0xa9,
+            a: 0,
+            x: 1,
+            y: 2,
+            p: 0x24,
+            sp: 0xfd,
+            cycles: 7,
+        };
+        assert_eq!(
+            record.to_string(),
+            "C000  A9  A:00 X:01 Y:02 P:24 SP:FD CYC:7"
+        );
+        assert_eq!(
+            record.to_json(),
+            r#"{"type":"instruction","pc":49152,"opcode":169,"a":0,"x":1,"y":2,"p":36,"sp":253,"cycles":7}"#
+        );
+        let write = Record::Bus {
+            access: Access::Write,
+            address: 0x10,
+            value: 0x2a,
+        };
+        assert_eq!(
+            write.to_json(),
+            r#"{"type":"write","address":16,"value":42}"#
+        );
+    }
+
+    #[test]
+    fn rejects_other_streams() {
+        let err = LogReader::new(&b"nope!"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf = [0; 10];
+        assert_eq!(encode_varint(0, &mut buf), 1);
+        assert_eq!(encode_varint(300, &mut buf), 2);
+        assert_eq!(buf[..2], [0xac, 0x02]);
+        assert_eq!(encode_varint(u64::MAX, &mut buf), 10);
+    }
+}
@@ -0,0 +1,489 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Plain-language descriptions of executed instructions, for teaching.
+//!
+//! [`explain`] describes an instruction from its disassembly, the bus
+//! accesses it made and the registers before and after it, such as
+//! `LDA $44,X: read $0047 (=$12) into A; N=0 Z=0`. [`step`] executes the
+//! next instruction of a CPU and describes it, for use in a hook or next to
+//! a trace.
+//!
+//! ```
+//! use mos6502::cpu::CPU;
+//! use mos6502::explain;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! cpu.memory.set_bytes(0x0200, &[0xa2, 0x03, 0xb5, 0x44]);
+//! cpu.memory.set_byte(0x0047, 0x12);
+//! cpu.registers.program_counter = 0x0200;
+//!
+//! let (_, text) = explain::step(&mut cpu).unwrap();
+//! assert_eq!(text, "LDX #$03: took $03 into X; N=0 Z=0");
+//! let (_, text) = explain::step(&mut cpu).unwrap();
+//! assert_eq!(text, "LDA $44,X: read $0047 (=$12) into A; N=0 Z=0");
+//! ```
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::cpu::CPU;
+use crate::disasm::{classify, Class, Disassembler, Line};
+use crate::instruction::{AddressingMode, DecodedInstr, Instruction};
+use crate::memory::{Access, Bus};
+use crate::registers::{Registers, Status};
+use crate::Variant;
+
+/// Executes the next instruction of `cpu` and describes what it did.
+///
+/// Bus accesses are recorded for the instruction if they aren't already,
+/// and left in place if they are. `None` if the opcode can't be decoded.
+pub fn step<M: Bus, V: Variant>(cpu: &mut CPU<M, V>) -> Option<(DecodedInstr, String)> {
+    let pc = cpu.registers.program_counter;
+    let bytes = [0, 1, 2].map(|offset| cpu.memory.get_byte(pc.wrapping_add(offset)));
+    let before = cpu.registers;
+    let recording = cpu.is_recording_accesses();
+    if !recording {
+        cpu.record_accesses(true);
+    }
+    let start = cpu.recorded_accesses().len();
+    let decoded = cpu.single_step();
+    let accesses = cpu
+        .recorded_accesses()
+        .get(start..)
+        .unwrap_or_default()
+        .to_vec();
+    if !recording {
+        cpu.record_accesses(false);
+    }
+    let decoded = decoded?;
+    let line = Disassembler::<V>::for_variant(&bytes, pc).next()?;
+    Some((decoded, explain(&line, &accesses, &before, &cpu.registers)))
+}
+
+/// Describes the instruction on `line`, given the bus `accesses` it made
+/// and the registers `before` and `after` it executed.
+///
+/// Fetches of the instruction's own bytes are ignored, so `accesses` may
+/// include them or not.
+#[must_use]
+pub fn explain(
+    line: &Line,
+    accesses: &[(Access, u16, u8)],
+    before: &Registers,
+    after: &Registers,
+) -> String {
+    let mut text = format!("{line}: ");
+    let Some((instruction, mode)) = line.decoded else {
+        text.push_str("not a valid instruction");
+        return text;
+    };
+    let (reads, writes) = data_accesses(line, accesses);
+    let last_read = reads.last().copied();
+    let last_write = writes.last().copied();
+    let destination = destination(instruction, mode);
+    let value = |register| match register {
+        "X" => after.index_x,
+        "Y" => after.index_y,
+        "SP" => after.stack_pointer.0,
+        "P" => after.status.bits(),
+        _ => after.accumulator,
+    };
+
+    // Immediate operands are read like memory ones, just from the operand.
+    let class = match classify(instruction, mode) {
+        Class::Other if mode == AddressingMode::Immediate => Class::Read,
+        class => class,
+    };
+    let _ = match class {
+        Class::Read => {
+            let _ = match (mode, last_read) {
+                (AddressingMode::Immediate, _) => write!(text, "took ${:02X}", line.bytes[1]),
+                (_, Some((address, value))) => write!(text, "read ${address:04X} (=${value:02X})"),
+                (_, None) => write!(text, "read memory"),
+            };
+            let a = after.accumulator;
+            match instruction {
+                Instruction::LDA | Instruction::LDX | Instruction::LDY => {
+                    write!(text, " into {}", destination.unwrap_or("A"))
+                }
+                Instruction::ADC | Instruction::ADCnd => {
+                    write!(text, " and added it to A with carry, giving ${a:02X}")
+                }
+                Instruction::SBC | Instruction::SBCnd => {
+                    write!(
+                        text,
+                        " and subtracted it from A with borrow, giving ${a:02X}"
+                    )
+                }
+                Instruction::AND => write!(text, " and ANDed it with A, giving ${a:02X}"),
+                Instruction::ORA => write!(text, " and ORed it into A, giving ${a:02X}"),
+                Instruction::EOR => write!(text, " and EORed it with A, giving ${a:02X}"),
+                Instruction::CMP => write!(
+                    text,
+                    " and compared it with A (=${:02X})",
+                    before.accumulator
+                ),
+                Instruction::CPX => {
+                    write!(text, " and compared it with X (=${:02X})", before.index_x)
+                }
+                Instruction::CPY => {
+                    write!(text, " and compared it with Y (=${:02X})", before.index_y)
+                }
+                Instruction::BIT => write!(
+                    text,
+                    " and tested it against A (=${:02X})",
+                    before.accumulator
+                ),
+                _ => Ok(()),
+            }
+        }
+        Class::Write => {
+            let register = match instruction {
+                Instruction::STX => "X",
+                Instruction::STY => "Y",
+                Instruction::STZ => "zero",
+                _ => "A",
+            };
+            match last_write {
+                Some((address, _)) if register == "zero" => {
+                    write!(text, "wrote zero to ${address:04X}")
+                }
+                Some((address, value)) => {
+                    write!(text, "wrote {register} (=${value:02X}) to ${address:04X}")
+                }
+                None => write!(text, "wrote {register}"),
+            }
+        }
+        Class::ReadModifyWrite => match (reads.first(), last_write) {
+            (Some((address, old)), Some((_, new))) => {
+                write!(
+                    text,
+                    "read ${address:04X} (=${old:02X}) and wrote back ${new:02X}"
+                )
+            }
+            _ => write!(text, "modified memory"),
+        },
+        Class::Branch => {
+            let (name, flag) = branch_flag(instruction);
+            let set = u8::from(before.status.contains(flag));
+            if after.program_counter == next(line) {
+                write!(text, "{name}={set}, did not branch")
+            } else {
+                write!(
+                    text,
+                    "{name}={set}, branched to ${:04X}",
+                    after.program_counter
+                )
+            }
+        }
+        Class::Jump => match (mode, reads.first()) {
+            (AddressingMode::Relative, _) => {
+                write!(text, "branched to ${:04X}", after.program_counter)
+            }
+            (AddressingMode::Absolute, _) | (_, None) => {
+                write!(text, "jumped to ${:04X}", after.program_counter)
+            }
+            (_, Some((pointer, _))) => write!(
+                text,
+                "jumped through ${pointer:04X} to ${:04X}",
+                after.program_counter
+            ),
+        },
+        Class::Call => write!(
+            text,
+            "pushed ${:04X} and jumped to ${:04X}",
+            next(line).wrapping_sub(1),
+            after.program_counter
+        ),
+        Class::Return if instruction == Instruction::RTI => {
+            write!(
+                text,
+                "pulled P and returned to ${:04X}",
+                after.program_counter
+            )
+        }
+        Class::Return => write!(text, "returned to ${:04X}", after.program_counter),
+        Class::Interrupt => write!(
+            text,
+            "pushed PC and P and jumped to the handler at ${:04X}",
+            after.program_counter
+        ),
+        Class::Other => match instruction {
+            Instruction::PHA | Instruction::PHX | Instruction::PHY | Instruction::PHP => {
+                let register = match instruction {
+                    Instruction::PHX => "X",
+                    Instruction::PHY => "Y",
+                    Instruction::PHP => "P",
+                    _ => "A",
+                };
+                let value = last_write.map_or_else(|| value(register), |(_, value)| value);
+                write!(text, "pushed {register} (=${value:02X})")
+            }
+            Instruction::PLP => write!(text, "pulled ${:02X} into P", after.status.bits()),
+            Instruction::NOP => write!(text, "did nothing"),
+            _ => match destination {
+                Some(register)
+                    if matches!(
+                        instruction,
+                        Instruction::PLA | Instruction::PLX | Instruction::PLY
+                    ) =>
+                {
+                    write!(text, "pulled ${:02X} into {register}", value(register))
+                }
+                Some(register) => write!(text, "{register} is now ${:02X}", value(register)),
+                None => Ok(()),
+            },
+        },
+    };
+
+    let mut separator = if text.ends_with(": ") { "" } else { "; " };
+    for (name, flag) in affected_flags(instruction) {
+        let _ = write!(
+            text,
+            "{separator}{name}={}",
+            u8::from(after.status.contains(*flag))
+        );
+        separator = " ";
+    }
+    text
+}
+
+/// Addresses and values accessed on the bus, oldest first.
+type Accesses = Vec<(u16, u8)>;
+
+/// Splits `accesses` into the data reads and the writes, leaving out the
+/// fetches of the bytes on `line`.
+fn data_accesses(line: &Line, accesses: &[(Access, u16, u8)]) -> (Accesses, Accesses) {
+    let mut fetched = [false; 3];
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for &(access, address, value) in accesses {
+        match access {
+            Access::Read => {
+                let offset = usize::from(address.wrapping_sub(line.address));
+                match (line.bytes.get(offset), fetched.get_mut(offset)) {
+                    (Some(&byte), Some(seen)) if byte == value && !*seen => *seen = true,
+                    _ => reads.push((address, value)),
+                }
+            }
+            Access::Write => writes.push((address, value)),
+        }
+    }
+    (reads, writes)
+}
+
+/// The address of the instruction after `line`.
+const fn next(line: &Line) -> u16 {
+    // Instructions are at most three bytes long.
+    #[allow(clippy::cast_possible_truncation)]
+    line.address.wrapping_add(line.bytes.len() as u16)
+}
+
+/// The register an instruction that doesn't access memory leaves its
+/// result in, or a load puts its value in.
+const fn destination(instruction: Instruction, mode: AddressingMode) -> Option<&'static str> {
+    match instruction {
+        Instruction::LDX
+        | Instruction::TAX
+        | Instruction::TSX
+        | Instruction::INX
+        | Instruction::DEX
+        | Instruction::PLX => Some("X"),
+        Instruction::LDY
+        | Instruction::TAY
+        | Instruction::INY
+        | Instruction::DEY
+        | Instruction::PLY => Some("Y"),
+        Instruction::TXS => Some("SP"),
+        Instruction::LDA | Instruction::TXA | Instruction::TYA | Instruction::PLA => Some("A"),
+        Instruction::ASL
+        | Instruction::LSR
+        | Instruction::ROL
+        | Instruction::ROR
+        | Instruction::INC
+        | Instruction::DEC
+            if matches!(mode, AddressingMode::Accumulator) =>
+        {
+            Some("A")
+        }
+        _ => None,
+    }
+}
+
+/// The flag a branch tests.
+const fn branch_flag(instruction: Instruction) -> (&'static str, Status) {
+    match instruction {
+        Instruction::BCC | Instruction::BCS => ("C", Status::PS_CARRY),
+        Instruction::BEQ | Instruction::BNE => ("Z", Status::PS_ZERO),
+        Instruction::BVC | Instruction::BVS => ("V", Status::PS_OVERFLOW),
+        _ => ("N", Status::PS_NEGATIVE),
+    }
+}
+
+const N: (&str, Status) = ("N", Status::PS_NEGATIVE);
+const V: (&str, Status) = ("V", Status::PS_OVERFLOW);
+const D: (&str, Status) = ("D", Status::PS_DECIMAL_MODE);
+const I: (&str, Status) = ("I", Status::PS_DISABLE_INTERRUPTS);
+const Z: (&str, Status) = ("Z", Status::PS_ZERO);
+const C: (&str, Status) = ("C", Status::PS_CARRY);
+
+/// The flags `instruction` may change, in the order the status register
+/// holds them.
+const fn affected_flags(instruction: Instruction) -> &'static [(&'static str, Status)] {
+    match instruction {
+        Instruction::LDA
+        | Instruction::LDX
+        | Instruction::LDY
+        | Instruction::TAX
+        | Instruction::TAY
+        | Instruction::TSX
+        | Instruction::TXA
+        | Instruction::TYA
+        | Instruction::PLA
+        | Instruction::PLX
+        | Instruction::PLY
+        | Instruction::INC
+        | Instruction::INX
+        | Instruction::INY
+        | Instruction::DEC
+        | Instruction::DEX
+        | Instruction::DEY
+        | Instruction::AND
+        | Instruction::ORA
+        | Instruction::EOR
+        | Instruction::LAS
+        | Instruction::LAX => &[N, Z],
+        Instruction::ADC
+        | Instruction::ADCnd
+        | Instruction::SBC
+        | Instruction::SBCnd
+        | Instruction::ARR
+        | Instruction::ARRnd
+        | Instruction::ISC
+        | Instruction::ISCnd
+        | Instruction::RRA
+        | Instruction::RRAnd => &[N, V, Z, C],
+        Instruction::CMP
+        | Instruction::CPX
+        | Instruction::CPY
+        | Instruction::ASL
+        | Instruction::LSR
+        | Instruction::ROL
+        | Instruction::ROR
+        | Instruction::ALR
+        | Instruction::ANC
+        | Instruction::DCP
+        | Instruction::RLA
+        | Instruction::SBX
+        | Instruction::SLO
+        | Instruction::SRE => &[N, Z, C],
+        Instruction::BIT => &[N, V, Z],
+        Instruction::TRB | Instruction::TSB => &[Z],
+        Instruction::CLC | Instruction::SEC => &[C],
+        Instruction::CLD | Instruction::SED => &[D],
+        Instruction::CLI | Instruction::SEI => &[I],
+        Instruction::CLV => &[V],
+        Instruction::PLP | Instruction::RTI => &[N, V, D, I, Z, C],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    fn explained(source: &str, setup: impl FnOnce(&mut CPU<Memory, Nmos6502>)) -> Vec<String> {
+        let assembly = assemble(source).unwrap();
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(assembly.origin, &assembly.bytes);
+        cpu.registers.program_counter = assembly.origin;
+        setup(&mut cpu);
+        let end = assembly
+            .origin
+            .wrapping_add(u16::try_from(assembly.bytes.len()).unwrap());
+        let mut lines = Vec::new();
+        while cpu.registers.program_counter != end {
+            lines.push(step(&mut cpu).unwrap().1);
+        }
+        lines
+    }
+
+    #[test]
+    fn loads_stores_and_arithmetic() {
+        let lines = explained(
+            "* = $0200\nLDX #$03\nLDA $44,X\nCLC\nADC #$F0\nSTA $10\nINC $10\nTAY\n",
+            |cpu| cpu.memory.set_byte(0x0047, 0x12),
+        );
+        assert_eq!(
+            lines,
+            [
+                "LDX #$03: took $03 into X; N=0 Z=0",
+                "LDA $44,X: read $0047 (=$12) into A; N=0 Z=0",
+                "CLC: C=0",
+                "ADC #$F0: took $F0 and added it to A with carry, giving $02; N=0 V=0 Z=0 C=1",
+                "STA $10: wrote A (=$02) to $0010",
+                "INC $10: read $0010 (=$02) and wrote back $03; N=0 Z=0",
+                "TAY: Y is now $02; N=0 Z=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow_and_the_stack() {
+        let lines = explained(
+            "* = $0200\nLDA #$80\nBMI skip\nNOP\nskip: BPL skip\nPHA\nPLA\nJSR sub\nJMP end\nsub: RTS\nend:\n",
+            |_| {},
+        );
+        assert_eq!(
+            lines[1..],
+            [
+                "BMI $0205: N=1, branched to $0205",
+                "BPL $0205: N=1, did not branch",
+                "PHA: pushed A (=$80)",
+                "PLA: pulled $80 into A; N=1 Z=0",
+                "JSR $020F: pushed $020B and jumped to $020F",
+                "RTS: returned to $020C",
+                "JMP $0210: jumped to $0210",
+            ]
+        );
+    }
+
+    #[test]
+    fn explains_undecodable_lines() {
+        let line = Disassembler::new(&[0x02], 0x0300).next().unwrap();
+        let registers = Registers::new();
+        assert!(explain(&line, &[], &registers, &registers).ends_with(": not a valid instruction"));
+    }
+}
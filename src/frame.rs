@@ -0,0 +1,275 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Driving a display-oriented front-end one frame at a time.
+//!
+//! A windowing front-end, whether it uses minifb, SDL or a browser canvas,
+//! wants the same thing from an emulator: run for a frame's worth of
+//! cycles, hand over the picture, and take back whatever the user pressed.
+//! A [`FrameLoop`] does this on top of the [`Scheduler`], so the front-end
+//! only has to supply a callback that puts a [`Frame`] on screen and
+//! returns [`InputEvent`]s.
+//!
+//! # Examples
+//!
+//! ```
+//! use mos6502::cpu::CPU;
+//! use mos6502::frame::{FrameLoop, InputEvent};
+//! use mos6502::framebuffer::Framebuffer;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::keyboard::Keyboard;
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::system::Scheduler;
+//!
+//! let mut memory = Keyboard::new(Memory::new(), 0xff00);
+//! // loop: JMP loop
+//! memory.set_bytes(0x0600, &[0x4c, 0x00, 0x06]);
+//! let mut cpu = CPU::new(memory, Nmos6502);
+//! cpu.registers.program_counter = 0x0600;
+//!
+//! let mut frames = FrameLoop::new(Framebuffer::easy6502(), 1000, |frame| {
+//!     // A real front-end would draw `frame.rgba` and poll its window here.
+//!     assert_eq!(frame.rgba.len(), 32 * 32 * 4);
+//!     if frame.number < 2 {
+//!         vec![InputEvent::Key(b'A')]
+//!     } else {
+//!         vec![InputEvent::Quit]
+//!     }
+//! });
+//! frames.run(&mut cpu, &mut Scheduler::new());
+//! assert_eq!(frames.frames(), 3);
+//! assert_eq!(cpu.memory.queued(), 1);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::cpu::CPU;
+use crate::framebuffer::Framebuffer;
+use crate::keyboard::Keyboard;
+use crate::memory::Bus;
+use crate::system::Scheduler;
+use crate::Variant;
+
+/// A rendered frame, as handed to a front-end.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// Frames completed before this one.
+    pub number: u64,
+    pub width: u16,
+    pub height: u16,
+    /// The display as RGBA bytes, row by row.
+    pub rgba: &'a [u8],
+}
+
+/// Input gathered by a front-end during a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    /// A key code for the emulated keyboard.
+    Key(u8),
+    /// The user closed the window; stop running.
+    Quit,
+}
+
+/// A bus that can take input events from a front-end.
+pub trait InputSink {
+    /// Delivers an event other than [`InputEvent::Quit`].
+    fn input(&mut self, event: InputEvent);
+}
+
+impl<B: Bus> InputSink for Keyboard<B> {
+    fn input(&mut self, event: InputEvent) {
+        if let InputEvent::Key(key) = event {
+            self.press(key);
+        }
+    }
+}
+
+/// Runs a CPU in frames of a fixed number of cycles, calling back after
+/// each one with the rendered display.
+pub struct FrameLoop<F> {
+    framebuffer: Framebuffer,
+    cycles_per_frame: u64,
+    frames: u64,
+    halted: bool,
+    callback: F,
+}
+
+impl<F: FnMut(&Frame) -> Vec<InputEvent>> FrameLoop<F> {
+    /// A loop drawing `framebuffer` every `cycles_per_frame` CPU cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cycles_per_frame` is zero.
+    pub fn new(framebuffer: Framebuffer, cycles_per_frame: u64, callback: F) -> Self {
+        assert!(cycles_per_frame > 0, "a frame must last at least one cycle");
+        FrameLoop {
+            framebuffer,
+            cycles_per_frame,
+            frames: 0,
+            halted: false,
+            callback,
+        }
+    }
+
+    #[must_use]
+    pub const fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    #[must_use]
+    pub const fn cycles_per_frame(&self) -> u64 {
+        self.cycles_per_frame
+    }
+
+    /// Frames completed so far.
+    #[must_use]
+    pub const fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Whether the CPU stopped on an opcode it could not decode.
+    #[must_use]
+    pub const fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Executes instructions through `scheduler` until the CPU reaches the
+    /// end of the current frame, then renders the display and passes it to
+    /// the callback, returning the events it produced.
+    ///
+    /// Frames are aligned to multiples of `cycles_per_frame` on the CPU's
+    /// cycle counter, so an instruction that overruns a frame boundary
+    /// shortens the next frame rather than delaying every later one. If the
+    /// CPU halts, the frame is cut short and still delivered.
+    pub fn run_frame<M: Bus, V: Variant>(
+        &mut self,
+        cpu: &mut CPU<M, V>,
+        scheduler: &mut Scheduler,
+    ) -> Vec<InputEvent> {
+        let end = (cpu.cycles / self.cycles_per_frame + 1) * self.cycles_per_frame;
+        while !self.halted && cpu.cycles < end {
+            self.halted = scheduler.step(cpu).is_none();
+        }
+        let rgba = self.framebuffer.render_to_rgba(&cpu.memory);
+        let frame = Frame {
+            number: self.frames,
+            width: self.framebuffer.width(),
+            height: self.framebuffer.height(),
+            rgba: &rgba,
+        };
+        self.frames += 1;
+        (self.callback)(&frame)
+    }
+
+    /// Runs frame after frame, delivering the events from each to the bus,
+    /// until the callback asks to quit or the CPU halts.
+    pub fn run<M: Bus + InputSink, V: Variant>(
+        &mut self,
+        cpu: &mut CPU<M, V>,
+        scheduler: &mut Scheduler,
+    ) {
+        while !self.halted {
+            for event in self.run_frame(cpu, scheduler) {
+                if event == InputEvent::Quit {
+                    return;
+                }
+                cpu.memory.input(event);
+            }
+        }
+    }
+}
+
+impl<F> core::fmt::Debug for FrameLoop<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("FrameLoop")
+            .field("framebuffer", &self.framebuffer)
+            .field("cycles_per_frame", &self.cycles_per_frame)
+            .field("frames", &self.frames)
+            .field("halted", &self.halted)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use crate::system::{ClockDivider, Tickable};
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    struct Counter(u64);
+
+    impl Tickable for Counter {
+        fn tick(&mut self, ticks: u64) {
+            self.0 += ticks;
+        }
+    }
+
+    #[test]
+    fn frames_follow_the_cycle_counter() {
+        let mut memory = Memory::new();
+        // loop: INC $0200; JMP loop
+        memory.set_bytes(0x0600, &[0xee, 0x00, 0x02, 0x4c, 0x00, 0x06]);
+        let mut cpu = CPU::new(memory, Nmos6502);
+        cpu.registers.program_counter = 0x0600;
+        let timer = Rc::new(RefCell::new(Counter(0)));
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ClockDivider::divide(1), Rc::clone(&timer));
+
+        let mut seen = Vec::new();
+        let mut frames = FrameLoop::new(Framebuffer::new(0x0200, 32, 32), 90, |frame: &Frame| {
+            seen.push((frame.number, frame.rgba[0]));
+            Vec::new()
+        });
+        assert!(frames.run_frame(&mut cpu, &mut scheduler).is_empty());
+        assert!(frames.run_frame(&mut cpu, &mut scheduler).is_empty());
+        // Each pass through the loop takes 9 cycles.
+        assert_eq!(cpu.cycles, 180);
+        assert_eq!(timer.borrow().0, 180);
+        assert_eq!(frames.frames(), 2);
+        drop(frames);
+        // The greyscale palette shows the counter as brightness.
+        assert_eq!(seen, [(0, 10), (1, 20)]);
+    }
+
+    #[test]
+    fn halting_ends_the_loop() {
+        let mut memory = Keyboard::new(Memory::new(), 0xff00);
+        // NOP; then an illegal opcode
+        memory.set_bytes(0x0000, &[0xea, 0x02]);
+        let mut cpu = CPU::new(memory, Nmos6502);
+        let mut frames = FrameLoop::new(Framebuffer::easy6502(), 1000, |_: &Frame| {
+            alloc::vec![InputEvent::Key(b'X')]
+        });
+        frames.run(&mut cpu, &mut Scheduler::new());
+        assert!(frames.halted());
+        assert_eq!(frames.frames(), 1);
+        assert_eq!(cpu.memory.get_byte(0xff00), b'X');
+    }
+}
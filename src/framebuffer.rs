@@ -0,0 +1,285 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Rendering a region of memory as a display.
+//!
+//! Many simple systems, the Easy6502 simulator among them, draw by storing
+//! one byte per pixel into a block of memory. A [`Framebuffer`] describes
+//! such a block and turns it into RGBA pixels through a palette, or into a
+//! PNG image, so graphical programs can be checked headlessly in tests or
+//! handed to a windowing front-end.
+//!
+//! The PNG encoder stores the pixels uncompressed, which keeps it free of
+//! dependencies; the files are bigger than they need to be but any viewer
+//! reads them.
+
+use alloc::vec::Vec;
+
+use crate::memory::Bus;
+
+/// The 16 colours of the Easy6502 display, in the order of the low nibble
+/// of a pixel.
+pub const EASY6502_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff],
+    [0x88, 0x00, 0x00],
+    [0xaa, 0xff, 0xee],
+    [0xcc, 0x44, 0xcc],
+    [0x00, 0xcc, 0x55],
+    [0x00, 0x00, 0xaa],
+    [0xee, 0xee, 0x77],
+    [0xdd, 0x88, 0x55],
+    [0x66, 0x44, 0x00],
+    [0xff, 0x77, 0x77],
+    [0x33, 0x33, 0x33],
+    [0x77, 0x77, 0x77],
+    [0xaa, 0xff, 0x66],
+    [0x00, 0x88, 0xff],
+    [0xbb, 0xbb, 0xbb],
+];
+
+/// A `width` by `height` display with one byte per pixel, row by row from
+/// `base`.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::framebuffer::Framebuffer;
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut memory = Memory::new();
+/// let display = Framebuffer::easy6502();
+/// // A white pixel at (1, 0).
+/// memory.set_byte(0x0201, 1);
+/// let rgba = display.render_to_rgba(&memory);
+/// assert_eq!(rgba.len(), 32 * 32 * 4);
+/// assert_eq!(rgba[4..8], [0xff, 0xff, 0xff, 0xff]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    base: u16,
+    width: u16,
+    height: u16,
+    palette: Vec<[u8; 3]>,
+}
+
+impl Framebuffer {
+    /// A display with a greyscale palette, where each byte is a brightness.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the display is empty or doesn't fit in memory from `base`.
+    #[must_use]
+    pub fn new(base: u16, width: u16, height: u16) -> Framebuffer {
+        let size = usize::from(width) * usize::from(height);
+        assert!(size > 0, "the display must have pixels");
+        assert!(
+            usize::from(base) + size <= 0x1_0000,
+            "the display must fit in memory"
+        );
+        Framebuffer {
+            base,
+            width,
+            height,
+            palette: (0..=u8::MAX).map(|level| [level; 3]).collect(),
+        }
+    }
+
+    /// The Easy6502 display: 32 by 32 pixels at `$0200`, coloured by the
+    /// low nibble of each byte.
+    #[must_use]
+    pub fn easy6502() -> Framebuffer {
+        let mut display = Framebuffer::new(0x0200, 32, 32);
+        display.set_palette(&EASY6502_PALETTE);
+        display
+    }
+
+    /// Sets the colours pixels are drawn in. A pixel value `v` is drawn in
+    /// colour `v % colors.len()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `colors` is empty.
+    pub fn set_palette(&mut self, colors: &[[u8; 3]]) {
+        assert!(!colors.is_empty(), "the palette must have colours");
+        self.palette = colors.to_vec();
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> u16 {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The byte stored for the pixel at (`x`, `y`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is outside the display.
+    #[must_use]
+    pub fn pixel(&self, bus: &impl Bus, x: u16, y: u16) -> u8 {
+        assert!(x < self.width && y < self.height, "pixel out of range");
+        bus.get_byte(self.base + y * self.width + x)
+    }
+
+    /// The colour of a pixel value.
+    #[must_use]
+    pub fn color(&self, value: u8) -> [u8; 3] {
+        self.palette[usize::from(value) % self.palette.len()]
+    }
+
+    /// The display as RGBA bytes, row by row, fully opaque.
+    #[must_use]
+    pub fn render_to_rgba(&self, bus: &impl Bus) -> Vec<u8> {
+        let size = usize::from(self.width) * usize::from(self.height);
+        let mut rgba = Vec::with_capacity(size * 4);
+        for address in (self.base..).take(size) {
+            let [r, g, b] = self.color(bus.get_byte(address));
+            rgba.extend_from_slice(&[r, g, b, 0xff]);
+        }
+        rgba
+    }
+
+    /// The display as a PNG image.
+    #[must_use]
+    pub fn to_png(&self, bus: &impl Bus) -> Vec<u8> {
+        let rgba = self.render_to_rgba(bus);
+        encode_png(u32::from(self.width), u32::from(self.height), &rgba)
+    }
+}
+
+/// Encodes RGBA pixels as a PNG with stored (uncompressed) deflate blocks.
+pub(crate) fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    /// The most a stored deflate block can hold.
+    const BLOCK: usize = 0xffff;
+
+    // Each row starts with its filter type, 0 for none.
+    let row = width as usize * 4;
+    let mut raw = Vec::with_capacity(rgba.len() + height as usize);
+    for line in rgba.chunks(row) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    let mut zlib = Vec::with_capacity(raw.len() + raw.len() / BLOCK * 5 + 11);
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    let blocks = raw.chunks(BLOCK).count();
+    for (i, block) in raw.chunks(BLOCK).enumerate() {
+        let len = u16::try_from(block.len()).expect("blocks are at most 64K");
+        zlib.push(u8::from(i + 1 == blocks));
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, no filtering, not interlaced.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = Vec::from(*b"\x89PNG\r\n\x1a\n");
+    write_chunk(&mut png, *b"IHDR", &header);
+    write_chunk(&mut png, *b"IDAT", &zlib);
+    write_chunk(&mut png, *b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+    let len = u32::try_from(data.len()).expect("chunks are under 4 GiB");
+    png.extend_from_slice(&len.to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(&kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn palette_wraps() {
+        let mut display = Framebuffer::new(0x1000, 2, 1);
+        display.set_palette(&[[1, 2, 3], [4, 5, 6]]);
+        let mut memory = Memory::new();
+        memory.set_bytes(0x1000, &[0, 3]);
+        assert_eq!(
+            display.render_to_rgba(&memory),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+        assert_eq!(display.pixel(&memory, 1, 0), 3);
+    }
+
+    #[test]
+    fn png_structure() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        let png = Framebuffer::easy6502().to_png(&Memory::new());
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 32, 0, 0, 0, 32]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+}
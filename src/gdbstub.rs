@@ -0,0 +1,598 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A GDB remote serial protocol stub.
+//!
+//! [`GdbStub`] lets GDB, or an editor driving GDB such as VS Code, debug a
+//! program running in a [`Machine`] over TCP. With the ELF and DWARF that
+//! llvm-mos or cc65 produce, that means stepping through the C source:
+//!
+//! ```text
+//! (gdb) target remote localhost:1234
+//! ```
+//!
+//! The stub reads and writes registers and memory, single-steps,
+//! continues, and sets breakpoints and watchpoints, which are the
+//! machine's own. Ctrl-C in the debugger interrupts a running program. GDB
+//! has no 6502 architecture built in, so the stub describes the registers
+//! itself:
+//!
+//! | Number | Register | Size                                        |
+//! |--------|----------|---------------------------------------------|
+//! | 0      | `a`      | 8 bits                                      |
+//! | 1      | `x`      | 8 bits                                      |
+//! | 2      | `y`      | 8 bits                                      |
+//! | 3      | `p`      | 8 bits                                      |
+//! | 4      | `sp`     | 16 bits, the address in page 1 it points to |
+//! | 5      | `pc`     | 16 bits                                     |
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//!
+//! use mos6502::cpu::CPU;
+//! use mos6502::gdbstub::GdbStub;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::machine::Machine;
+//! use mos6502::memory::Memory;
+//!
+//! let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+//! let listener = TcpListener::bind("127.0.0.1:1234").unwrap();
+//! let (stream, _) = listener.accept().unwrap();
+//! GdbStub::new(stream).serve(&mut machine).unwrap();
+//! ```
+
+use std::borrow::ToOwned;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::machine::{Machine, StopReason, Watch};
+use crate::memory::{Access, Bus};
+use crate::registers::{StackPointer, Status};
+use crate::Variant;
+
+/// Instructions run between checks for Ctrl-C while continuing.
+const SLICE: u64 = 10_000;
+
+/// The interrupt byte GDB sends for Ctrl-C.
+const INTERRUPT: u8 = 0x03;
+
+/// The register layout, sent to GDB when it asks for `target.xml`.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.mos6502.core">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="p" bitsize="8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// How a debugging session ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Disconnect {
+    /// The debugger detached, leaving the program to run on without it.
+    Detached,
+    /// The debugger asked for the program to be killed.
+    Killed,
+    /// The connection was closed.
+    Closed,
+}
+
+/// One debugger connection.
+#[derive(Debug)]
+pub struct GdbStub {
+    stream: TcpStream,
+    /// Bytes received but not handled yet.
+    input: VecDeque<u8>,
+    /// Whether packets are acknowledged, until GDB turns that off.
+    ack: bool,
+    /// Whether the debugger closed the connection while the program ran.
+    closed: bool,
+}
+
+impl GdbStub {
+    #[must_use]
+    pub const fn new(stream: TcpStream) -> GdbStub {
+        GdbStub {
+            stream,
+            input: VecDeque::new(),
+            ack: true,
+            closed: false,
+        }
+    }
+
+    /// Answers the debugger's requests on `machine` until it detaches, asks
+    /// for the program to be killed or closes the connection.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error if reading from or writing to the connection
+    /// fails.
+    pub fn serve<M: Bus, V: Variant>(
+        &mut self,
+        machine: &mut Machine<M, V>,
+    ) -> io::Result<Disconnect> {
+        while let Some(packet) = self.packet()? {
+            let reply = match packet.as_slice() {
+                [b'k', ..] | b"vKill" => return Ok(Disconnect::Killed),
+                [b'D', ..] => {
+                    self.send("OK")?;
+                    return Ok(Disconnect::Detached);
+                }
+                [b'c', address @ ..] => self.resume(machine, address, false)?,
+                [b's', address @ ..] => self.resume(machine, address, true)?,
+                b"QStartNoAckMode" => {
+                    self.send("OK")?;
+                    self.ack = false;
+                    continue;
+                }
+                _ => request(machine, &packet).unwrap_or_else(|| "E01".to_owned()),
+            };
+            if self.closed {
+                break;
+            }
+            self.send(&reply)?;
+        }
+        Ok(Disconnect::Closed)
+    }
+
+    /// Continues or single-steps, from `address` if it is given, and
+    /// returns the stop reply.
+    fn resume<M: Bus, V: Variant>(
+        &mut self,
+        machine: &mut Machine<M, V>,
+        address: &[u8],
+        step: bool,
+    ) -> io::Result<String> {
+        if let Some(address) = number(address).and_then(|n| u16::try_from(n).ok()) {
+            machine.cpu.registers.program_counter = address;
+        }
+        if step {
+            return Ok(stop_reply(machine.step()));
+        }
+        loop {
+            let report = machine.run(Some(SLICE));
+            if !matches!(report.stop, StopReason::LimitReached { .. }) {
+                return Ok(stop_reply(Some(report.stop)));
+            }
+            if self.interrupted()? {
+                return Ok(stop_reply(Some(StopReason::Paused)));
+            }
+        }
+    }
+
+    /// Checks, without blocking, whether the debugger has sent Ctrl-C or
+    /// closed the connection.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 64];
+        self.stream.set_nonblocking(true)?;
+        let read = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(0) => {
+                self.closed = true;
+                Ok(true)
+            }
+            Ok(len) => {
+                let bytes = &buffer[..len];
+                self.input
+                    .extend(bytes.iter().filter(|&&byte| byte != INTERRUPT));
+                Ok(bytes.contains(&INTERRUPT))
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.input.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads the next packet with a good checksum, skipping acknowledgements
+    /// and stray interrupts. Returns `None` once the connection is closed.
+    fn packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            loop {
+                match self.byte()? {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let (Some(high), Some(low)) = (self.byte()?, self.byte()?) else {
+                return Ok(None);
+            };
+            let good = number(&[high, low]) == Some(u32::from(checksum(&data)));
+            if self.ack {
+                self.stream.write_all(if good { b"+" } else { b"-" })?;
+            }
+            if good {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    /// Sends a packet, resending it until it is acknowledged.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = std::format!("${data}#{:02x}", checksum(data.as_bytes()));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            loop {
+                match self.byte()? {
+                    Some(b'+') | None => return Ok(()),
+                    Some(b'-') => break,
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Answers a request that doesn't resume execution. Unsupported requests
+/// get the empty reply; `None` means a malformed one.
+fn request<M: Bus, V: Variant>(machine: &mut Machine<M, V>, packet: &[u8]) -> Option<String> {
+    let registers = &mut machine.cpu.registers;
+    let reply = match packet {
+        b"?" => "S05".to_owned(),
+        b"g" => hex(&register_bytes(machine)),
+        [b'G', values @ ..] => {
+            let bytes = bytes(values)?;
+            let [a, x, y, p, sp, _, pc_low, pc_high] = bytes[..] else {
+                return None;
+            };
+            registers.accumulator = a;
+            registers.index_x = x;
+            registers.index_y = y;
+            registers.status = Status::from_byte(p);
+            registers.stack_pointer = StackPointer(sp);
+            registers.program_counter = u16::from_le_bytes([pc_low, pc_high]);
+            "OK".to_owned()
+        }
+        [b'p', number @ ..] => {
+            let bytes = register_bytes(machine);
+            match self::number(number)? {
+                n @ 0..=3 => hex(&bytes[n as usize..=n as usize]),
+                4 => hex(&bytes[4..6]),
+                5 => hex(&bytes[6..8]),
+                _ => return None,
+            }
+        }
+        [b'P', assignment @ ..] => {
+            let (number, value) = split(assignment, b'=')?;
+            let value = bytes(value)?;
+            let low = *value.first()?;
+            match self::number(number)? {
+                0 => registers.accumulator = low,
+                1 => registers.index_x = low,
+                2 => registers.index_y = low,
+                3 => registers.status = Status::from_byte(low),
+                4 => registers.stack_pointer = StackPointer(low),
+                5 => {
+                    registers.program_counter = u16::from_le_bytes([low, *value.get(1)?]);
+                }
+                _ => return None,
+            }
+            "OK".to_owned()
+        }
+        [b'm', range @ ..] => {
+            let (address, len) = address_range(range)?;
+            let memory = &machine.cpu.memory;
+            let bytes: Vec<u8> = (address..=u16::MAX)
+                .take(len)
+                .map(|address| memory.get_byte(address))
+                .collect();
+            hex(&bytes)
+        }
+        [b'M', write @ ..] => {
+            let (range, values) = split(write, b':')?;
+            let (address, len) = address_range(range)?;
+            let values = bytes(values)?;
+            if values.len() != len {
+                return None;
+            }
+            for (address, value) in (address..).zip(values) {
+                machine.cpu.memory.set_byte(address, value);
+            }
+            "OK".to_owned()
+        }
+        [insert @ (b'Z' | b'z'), kind, b',', point @ ..] => {
+            let (address, len) = address_range(point)?;
+            let insert = *insert == b'Z';
+            let watch = match kind {
+                b'0' | b'1' => {
+                    if insert {
+                        machine.add_breakpoint(address);
+                    } else {
+                        machine.remove_breakpoint(address);
+                    }
+                    return Some("OK".to_owned());
+                }
+                b'2' => Watch::Write,
+                b'3' => Watch::Read,
+                b'4' => Watch::ReadWrite,
+                _ => return Some(String::new()),
+            };
+            for address in (address..=u16::MAX).take(len.max(1)) {
+                if insert {
+                    machine.add_watchpoint(address, watch);
+                } else {
+                    machine.remove_watchpoint(address);
+                }
+            }
+            "OK".to_owned()
+        }
+        [b'H', ..] => "OK".to_owned(),
+        b"qAttached" => "1".to_owned(),
+        _ if packet.starts_with(b"qSupported") => {
+            "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+".to_owned()
+        }
+        [b'q', b'X', b'f', b'e', b'r', b':', query @ ..] => {
+            let window = query.strip_prefix(b"features:read:target.xml:")?;
+            let (offset, len) = split(window, b',')?;
+            let offset = (number(offset)? as usize).min(TARGET_XML.len());
+            let end = offset.saturating_add(number(len)? as usize);
+            let chunk = TARGET_XML.get(offset..end.min(TARGET_XML.len()))?;
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            std::format!("{more}{chunk}")
+        }
+        _ => String::new(),
+    };
+    Some(reply)
+}
+
+/// The registers in GDB's order, little-endian.
+const fn register_bytes<M: Bus, V: Variant>(machine: &Machine<M, V>) -> [u8; 8] {
+    let registers = &machine.cpu.registers;
+    let [pc_low, pc_high] = registers.program_counter.to_le_bytes();
+    [
+        registers.accumulator,
+        registers.index_x,
+        registers.index_y,
+        registers.status.to_byte(),
+        registers.stack_pointer.0,
+        0x01,
+        pc_low,
+        pc_high,
+    ]
+}
+
+fn stop_reply(stop: Option<StopReason>) -> String {
+    match stop {
+        Some(StopReason::Watchpoint {
+            access, address, ..
+        }) => {
+            let kind = match access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            std::format!("T05{kind}:{address:04x};")
+        }
+        // SIGILL, SIGSEGV and SIGINT, as a native debugger would see them.
+        Some(StopReason::IllegalOpcode { .. }) => "S04".to_owned(),
+        Some(StopReason::BusError { .. }) => "S0b".to_owned(),
+        Some(StopReason::Paused) => "S02".to_owned(),
+        _ => "S05".to_owned(),
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{byte:02x}");
+        text
+    })
+}
+
+fn number(text: &[u8]) -> Option<u32> {
+    u32::from_str_radix(core::str::from_utf8(text).ok()?, 16).ok()
+}
+
+fn bytes(text: &[u8]) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| number(pair).and_then(|n| u8::try_from(n).ok()))
+        .collect()
+}
+
+fn split(text: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = text.iter().position(|&byte| byte == separator)?;
+    Some((&text[..at], &text[at + 1..]))
+}
+
+/// Parses `address,length`, clipping the length to the end of memory.
+fn address_range(text: &[u8]) -> Option<(u16, usize)> {
+    let (address, len) = split(text, b',')?;
+    let address = u16::try_from(number(address)?).ok()?;
+    let len = usize::try_from(number(len)?).ok()?;
+    Some((address, len.min(0x1_0000 - usize::from(address))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    /// The debugger's end of the connection.
+    struct Client(TcpStream);
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let packet = std::format!("${data}#{:02x}", checksum(data.as_bytes()));
+            self.0.write_all(packet.as_bytes()).unwrap();
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let mut text = Vec::new();
+            let mut byte = [0];
+            while text.len() < 3 || text[text.len() - 3] != b'#' {
+                self.0.read_exact(&mut byte).unwrap();
+                if !(text.is_empty() && byte[0] == b'+') {
+                    text.push(byte[0]);
+                }
+            }
+            self.0.write_all(b"+").unwrap();
+            String::from_utf8(text[1..text.len() - 3].to_vec()).unwrap()
+        }
+    }
+
+    /// Serves `machine` to a client running `session`, returning how the
+    /// session ended and the client's result.
+    fn debug<T: Send + 'static>(
+        machine: &mut Machine<Memory, Nmos6502>,
+        session: impl FnOnce(&mut Client) -> T + Send + 'static,
+    ) -> (Disconnect, T) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client =
+            thread::spawn(move || session(&mut Client(TcpStream::connect(address).unwrap())));
+        let (stream, _) = listener.accept().unwrap();
+        let disconnect = GdbStub::new(stream).serve(machine).unwrap();
+        (disconnect, client.join().unwrap())
+    }
+
+    fn machine(program: &[u8]) -> Machine<Memory, Nmos6502> {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0200, program);
+        cpu.registers.program_counter = 0x0200;
+        Machine::new(cpu)
+    }
+
+    #[test]
+    fn registers_memory_and_breakpoints() {
+        // INX; INX; INX; JMP $0200
+        let mut machine = machine(&[0xe8, 0xe8, 0xe8, 0x4c, 0x00, 0x02]);
+        let (disconnect, replies) = debug(&mut machine, |client| {
+            let supported = client.request("qSupported:swbreak+");
+            let xml = client.request("qXfer:features:read:target.xml:0,14");
+            let replies = [
+                client.request("g"),
+                client.request("Z0,202,1"),
+                client.request("c"),
+                client.request("p5"),
+                client.request("s"),
+                client.request("P0=42"),
+                client.request("M10,2:abcd"),
+                client.request("m0f,4"),
+                client.request("p4"),
+            ];
+            client.request("D");
+            (supported, xml, replies)
+        });
+        let (supported, xml, replies) = replies;
+        assert_eq!(disconnect, Disconnect::Detached);
+        assert!(supported.contains("qXfer:features:read+"));
+        assert_eq!(xml, "m<?xml version=\"1.0\"?");
+        assert_eq!(
+            replies,
+            [
+                "00000024fd010002",
+                "OK",
+                "S05",
+                "0202",
+                "S05",
+                "OK",
+                "OK",
+                "00abcd00",
+                "fd01"
+            ]
+        );
+        assert_eq!(machine.cpu.registers.accumulator, 0x42);
+        assert_eq!(machine.cpu.registers.index_x, 3);
+    }
+
+    #[test]
+    fn ctrl_c_interrupts_a_running_program() {
+        // JMP $0200
+        let mut machine = machine(&[0x4c, 0x00, 0x02]);
+        let (disconnect, reply) = debug(&mut machine, |client| {
+            client.send("c");
+            thread::sleep(std::time::Duration::from_millis(50));
+            client.0.write_all(&[INTERRUPT]).unwrap();
+            let reply = client.reply();
+            client.send("k");
+            reply
+        });
+        assert_eq!(reply, "S02");
+        assert_eq!(disconnect, Disconnect::Killed);
+    }
+
+    #[test]
+    fn watchpoints_report_the_address() {
+        // LDA #1; STA $10; BRK
+        let mut machine = machine(&[0xa9, 0x01, 0x85, 0x10, 0x00]);
+        let (_, reply) = debug(&mut machine, |client| {
+            client.request("Z2,10,1");
+            client.request("c")
+        });
+        assert_eq!(reply, "T05watch:0010;");
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Generating single-instruction test vectors.
+//!
+//! A [`Generator`] puts the CPU in a random state with random memory,
+//! executes one instruction and records the state before and after along
+//! with the bus accesses made, in the JSON schema of Tom Harte's
+//! [SingleStepTests](https://github.com/SingleStepTests/65x02). The vectors
+//! pin this crate's behavior in regression tests and can be fed to other
+//! emulators.
+//!
+//! The CPU runs with `cycle_accurate` set, so `cycles` includes the dummy
+//! reads and writes of the real chip and has one entry per clock cycle.
+//!
+//! # Examples
+//!
+//! ```
+//! use mos6502::golden::Generator;
+//! use mos6502::instruction::Nmos6502;
+//!
+//! let mut generator = Generator::new(Nmos6502, 1);
+//! // LDA #imm
+//! let test = generator.generate(0xa9).unwrap();
+//! assert_eq!(test.final_state.a, test.name_bytes()[1]);
+//! assert!(test.to_json().starts_with(r#"{"name": "a9 "#));
+//! ```
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::Range;
+
+use crate::cpu::CPU;
+use crate::memory::{Access, Bus};
+use crate::random::{mix, GAMMA};
+use crate::registers::{StackPointer, Status};
+use crate::Variant;
+
+/// The registers and the memory an instruction touched, before or after it
+/// executed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestState {
+    pub pc: u16,
+    pub s: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    /// The addresses accessed and their values, by address.
+    pub ram: Vec<(u16, u8)>,
+}
+
+impl TestState {
+    fn write_json(&self, out: &mut String) {
+        let _ = write!(
+            out,
+            r#"{{"pc": {}, "s": {}, "a": {}, "x": {}, "y": {}, "p": {}, "ram": ["#,
+            self.pc, self.s, self.a, self.x, self.y, self.p
+        );
+        for (i, (address, value)) in self.ram.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            let _ = write!(out, "{separator}[{address}, {value}]");
+        }
+        out.push_str("]}");
+    }
+}
+
+/// One test vector: a single instruction executed from a random state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoldenTest {
+    /// The bytes of the instruction in hex, e.g. `a9 3c`.
+    pub name: String,
+    pub initial: TestState,
+    pub final_state: TestState,
+    /// The bus accesses made, in order.
+    pub cycles: Vec<(u16, u8, Access)>,
+}
+
+impl GoldenTest {
+    /// The bytes of the instruction, parsed back from [`GoldenTest::name`].
+    #[must_use]
+    pub fn name_bytes(&self) -> Vec<u8> {
+        self.name
+            .split(' ')
+            .filter_map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect()
+    }
+
+    /// Formats the test as a JSON object in the `SingleStepTests` schema.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(out, r#"{{"name": "{}", "initial": "#, self.name);
+        self.initial.write_json(&mut out);
+        out.push_str(r#", "final": "#);
+        self.final_state.write_json(&mut out);
+        out.push_str(r#", "cycles": ["#);
+        for (i, &(address, value, access)) in self.cycles.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            let access = match access {
+                Access::Read => "read",
+                Access::Write => "write",
+            };
+            let _ = write!(out, r#"{separator}[{address}, {value}, "{access}"]"#);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Formats tests as a JSON array, one test per line, as in the files of
+/// `SingleStepTests`.
+#[must_use]
+pub fn to_json_array(tests: &[GoldenTest]) -> String {
+    let mut out = String::from("[\n");
+    for (i, test) in tests.iter().enumerate() {
+        out.push_str(&test.to_json());
+        out.push_str(if i + 1 == tests.len() { "\n" } else { ",\n" });
+    }
+    out.push_str("]\n");
+    out
+}
+
+/// Memory that holds a fixed random byte at every address until written.
+/// The values are computed from the seed, so 64K of them needn't be
+/// stored for every test.
+struct RandomMemory {
+    seed: u64,
+    /// Bytes placed before the instruction executes.
+    initial: BTreeMap<u16, u8>,
+    written: BTreeMap<u16, u8>,
+}
+
+impl RandomMemory {
+    fn initial_byte(&self, address: u16) -> u8 {
+        self.initial
+            .get(&address)
+            .copied()
+            .unwrap_or_else(|| mix(self.seed ^ u64::from(address)).to_le_bytes()[0])
+    }
+}
+
+impl Bus for RandomMemory {
+    /// # Panics
+    ///
+    /// Always panics: the bytes are computed, not stored.
+    fn get_bytes(&self, _range: Range<usize>) -> &[u8] {
+        panic!("random memory can't lend out slices")
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.written.get(&address) {
+            Some(&value) => value,
+            None => self.initial_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self.written.insert(address, value);
+    }
+}
+
+/// Generates test vectors for a variant from a seed. The same seed always
+/// gives the same tests.
+#[derive(Copy, Clone, Debug)]
+pub struct Generator<V: Variant> {
+    variant: V,
+    state: u64,
+}
+
+impl<V: Variant + Copy> Generator<V> {
+    #[must_use]
+    pub const fn new(variant: V, seed: u64) -> Generator<V> {
+        Generator {
+            variant,
+            state: seed,
+        }
+    }
+
+    const fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GAMMA);
+        mix(self.state)
+    }
+
+    /// Executes `opcode` once from a random state, or returns `None` if the
+    /// variant doesn't implement it.
+    pub fn generate(&mut self, opcode: u8) -> Option<GoldenTest> {
+        let (_, mode) = V::decode(opcode)?;
+        let [pc_lo, pc_hi, sp, accumulator, index_x, index_y, status, _] =
+            self.next().to_le_bytes();
+        let pc = u16::from_le_bytes([pc_lo, pc_hi]);
+
+        let mut memory = RandomMemory {
+            seed: self.next(),
+            initial: BTreeMap::new(),
+            written: BTreeMap::new(),
+        };
+        memory.initial.insert(pc, opcode);
+        let mut cpu = CPU::new(memory, self.variant);
+        cpu.cycle_accurate = true;
+        cpu.registers.program_counter = pc;
+        cpu.registers.stack_pointer = StackPointer(sp);
+        cpu.registers.accumulator = accumulator;
+        cpu.registers.index_x = index_x;
+        cpu.registers.index_y = index_y;
+        cpu.registers.status = Status::from_byte(status);
+        let initial_p = cpu.registers.status.to_byte();
+
+        let name = (0..=mode.extra_bytes())
+            .map(|offset| alloc::format!("{:02x}", cpu.memory.get_byte(pc.wrapping_add(offset))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        cpu.record_accesses(true);
+        cpu.single_step()?;
+        let cycles: Vec<_> = cpu.drain_accesses().collect();
+
+        let touched: BTreeSet<u16> = cycles.iter().map(|&(_, address, _)| address).collect();
+        let initial = TestState {
+            pc,
+            s: sp,
+            a: accumulator,
+            x: index_x,
+            y: index_y,
+            p: initial_p,
+            ram: touched
+                .iter()
+                .map(|&address| (address, cpu.memory.initial_byte(address)))
+                .collect(),
+        };
+        let final_state = TestState {
+            pc: cpu.registers.program_counter,
+            s: cpu.registers.stack_pointer.0,
+            a: cpu.registers.accumulator,
+            x: cpu.registers.index_x,
+            y: cpu.registers.index_y,
+            p: cpu.registers.status.to_byte(),
+            ram: touched
+                .iter()
+                .map(|&address| (address, cpu.memory.get_byte(address)))
+                .collect(),
+        };
+        Some(GoldenTest {
+            name,
+            initial,
+            final_state,
+            cycles: cycles
+                .into_iter()
+                .map(|(access, address, value)| (address, value, access))
+                .collect(),
+        })
+    }
+
+    /// Generates `count` tests for `opcode`, or none if the variant doesn't
+    /// implement it.
+    pub fn generate_many(&mut self, opcode: u8, count: usize) -> Vec<GoldenTest> {
+        (0..count).map_while(|_| self.generate(opcode)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+
+    #[test]
+    fn same_seed_same_tests() {
+        let first = Generator::new(Nmos6502, 7).generate_many(0x6d, 3);
+        let second = Generator::new(Nmos6502, 7).generate_many(0x6d, 3);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, second);
+        assert_ne!(first[0], first[1]);
+    }
+
+    #[test]
+    fn records_state_and_accesses() {
+        // STA abs
+        let test = Generator::new(Nmos6502, 3).generate(0x8d).unwrap();
+        let [_, lo, hi] = test.name_bytes()[..] else {
+            panic!("{}", test.name);
+        };
+        let target = u16::from_le_bytes([lo, hi]);
+        let pc = test.initial.pc;
+        assert_eq!(test.final_state.pc, pc.wrapping_add(3));
+        assert_eq!(test.cycles.len(), 4);
+        assert_eq!(test.cycles[3], (target, test.initial.a, Access::Write));
+        assert!(test.final_state.ram.contains(&(target, test.initial.a)));
+        assert!(test.initial.ram.contains(&(pc, 0x8d)));
+    }
+
+    #[test]
+    fn unimplemented_opcodes_give_nothing() {
+        assert_eq!(Generator::new(Nmos6502, 0).generate(0x02), None);
+    }
+}
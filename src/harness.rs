@@ -0,0 +1,511 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Unit testing 6502 code.
+//!
+//! A [`Harness`] loads a program, runs it until it executes `BRK`, traps in
+//! a loop or runs out of cycles, then checks the registers, flags, memory
+//! and cycle count. The checks panic like `assert_eq!`, showing what was
+//! expected and the state the program ended in, so they can be used
+//! directly in tests:
+//!
+//! ```
+//! use mos6502::harness::{End, Harness};
+//! use mos6502::registers::Status;
+//!
+//! let mut test = Harness::new();
+//! // LDA #5; ASL A; STA $0300; BRK
+//! test.load(0x0200, &[0xa9, 0x05, 0x0a, 0x8d, 0x00, 0x03, 0x00]);
+//! test.run_from(0x0200);
+//! test.assert_end(End::Brk(0x0206))
+//!     .assert_a(10)
+//!     .assert_flags_clear(Status::PS_CARRY | Status::PS_ZERO)
+//!     .assert_memory(0x0300, &[10])
+//!     .assert_cycles(8);
+//! ```
+
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use crate::asm::Assembly;
+use crate::cpu::CPU;
+use crate::instruction::Nmos6502;
+use crate::memory::{Bus, Memory};
+use crate::registers::{Flags, Status};
+use crate::Variant;
+
+/// Why a run stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum End {
+    /// The program reached a `BRK` at this address, which was not executed.
+    Brk(u16),
+    /// The program jumped or branched to itself at this address.
+    Trap(u16),
+    /// The opcode at this address is not valid for the variant.
+    IllegalOpcode(u16, u8),
+    /// The run took [`Harness::max_cycles`].
+    CycleLimit,
+}
+
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            End::Brk(pc) => write!(f, "BRK at ${pc:04X}"),
+            End::Trap(pc) => write!(f, "trap loop at ${pc:04X}"),
+            End::IllegalOpcode(pc, opcode) => {
+                write!(f, "illegal opcode ${opcode:02X} at ${pc:04X}")
+            }
+            End::CycleLimit => f.write_str("cycle limit reached"),
+        }
+    }
+}
+
+/// A CPU with a flat memory, for running a program under test.
+#[derive(Debug)]
+pub struct Harness<V: Variant = Nmos6502> {
+    pub cpu: CPU<Memory, V>,
+    /// Cycles a run may take before it ends with [`End::CycleLimit`].
+    pub max_cycles: u64,
+    end: Option<End>,
+    cycles: u64,
+    instructions: u64,
+}
+
+impl Harness {
+    /// A harness for the NMOS 6502.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_variant(Nmos6502)
+    }
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The assertions return the harness only so they can be chained.
+#[allow(clippy::must_use_candidate)]
+impl<V: Variant> Harness<V> {
+    #[must_use]
+    pub fn with_variant(variant: V) -> Self {
+        Harness {
+            cpu: CPU::new(Memory::new(), variant),
+            max_cycles: 10_000_000,
+            end: None,
+            cycles: 0,
+            instructions: 0,
+        }
+    }
+
+    /// Copies `bytes` into memory at `address`.
+    pub fn load(&mut self, address: u16, bytes: &[u8]) -> &mut Self {
+        self.cpu.memory.set_bytes(address, bytes);
+        self
+    }
+
+    /// Copies an assembled program into memory.
+    #[cfg(feature = "alloc")]
+    pub fn load_assembly(&mut self, assembly: &Assembly) -> &mut Self {
+        self.load(assembly.origin, &assembly.bytes)
+    }
+
+    /// Runs from `pc` until the program ends.
+    pub fn run_from(&mut self, pc: u16) -> End {
+        self.cpu.registers.program_counter = pc;
+        self.run()
+    }
+
+    /// Runs from the current PC until the program ends.
+    pub fn run(&mut self) -> End {
+        let start = self.cpu.cycles;
+        self.instructions = 0;
+        let end = loop {
+            let pc = self.cpu.registers.program_counter;
+            let opcode = self.cpu.memory.get_byte(pc);
+            if opcode == 0x00 {
+                break End::Brk(pc);
+            }
+            if self.cpu.cycles - start >= self.max_cycles {
+                break End::CycleLimit;
+            }
+            if self.cpu.single_step().is_none() {
+                break End::IllegalOpcode(pc, opcode);
+            }
+            self.instructions += 1;
+            if self.cpu.registers.program_counter == pc {
+                break End::Trap(pc);
+            }
+        };
+        self.cycles = self.cpu.cycles - start;
+        self.end = Some(end);
+        end
+    }
+
+    /// How the last run ended, if there was one.
+    #[must_use]
+    pub const fn end(&self) -> Option<End> {
+        self.end
+    }
+
+    /// Cycles taken by the last run.
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Instructions executed by the last run.
+    #[must_use]
+    pub const fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Asserts that the last run ended as `expected`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it didn't, or if nothing has run.
+    #[track_caller]
+    pub fn assert_end(&self, expected: End) -> &Self {
+        match self.end {
+            Some(end) if end == expected => self,
+            Some(end) => self.fail(format_args!(
+                "program ended with {end}, expected {expected}"
+            )),
+            None => self.fail(format_args!("program has not run, expected {expected}")),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if A doesn't hold `expected`.
+    #[track_caller]
+    pub fn assert_a(&self, expected: u8) -> &Self {
+        self.check_register("A", self.cpu.registers.accumulator, expected)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if X doesn't hold `expected`.
+    #[track_caller]
+    pub fn assert_x(&self, expected: u8) -> &Self {
+        self.check_register("X", self.cpu.registers.index_x, expected)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if Y doesn't hold `expected`.
+    #[track_caller]
+    pub fn assert_y(&self, expected: u8) -> &Self {
+        self.check_register("Y", self.cpu.registers.index_y, expected)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the stack pointer isn't `expected`.
+    #[track_caller]
+    pub fn assert_sp(&self, expected: u8) -> &Self {
+        self.check_register("SP", self.cpu.registers.stack_pointer.0, expected)
+    }
+
+    /// Asserts that every flag in `flags` is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of them is clear.
+    #[track_caller]
+    pub fn assert_flags_set(&self, flags: Status) -> &Self {
+        let status = self.cpu.registers.status;
+        if !status.contains(flags) {
+            self.fail(format_args!(
+                "flags {} are clear, expected them set",
+                Flags(flags - status)
+            ));
+        }
+        self
+    }
+
+    /// Asserts that every flag in `flags` is clear.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of them is set.
+    #[track_caller]
+    pub fn assert_flags_clear(&self, flags: Status) -> &Self {
+        let status = self.cpu.registers.status;
+        if status.intersects(flags) {
+            self.fail(format_args!(
+                "flags {} are set, expected them clear",
+                Flags(flags & status)
+            ));
+        }
+        self
+    }
+
+    /// Asserts that memory from `address` holds `expected`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any byte differs, listing the rows that do.
+    #[track_caller]
+    pub fn assert_memory(&self, address: u16, expected: &[u8]) -> &Self {
+        let diff = MemoryDiff {
+            address,
+            expected,
+            memory: &self.cpu.memory,
+        };
+        if diff.count() > 0 {
+            self.fail(format_args!("{diff}"));
+        }
+        self
+    }
+
+    /// Asserts that the last run took exactly `expected` cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it took any other number.
+    #[track_caller]
+    pub fn assert_cycles(&self, expected: u64) -> &Self {
+        if self.cycles != expected {
+            self.fail(format_args!(
+                "program took {} cycles, expected {expected}",
+                self.cycles
+            ));
+        }
+        self
+    }
+
+    /// Asserts that the last run took at most `limit` cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it took longer.
+    #[track_caller]
+    pub fn assert_cycles_at_most(&self, limit: u64) -> &Self {
+        if self.cycles > limit {
+            self.fail(format_args!(
+                "program took {} cycles, expected at most {limit}",
+                self.cycles
+            ));
+        }
+        self
+    }
+
+    #[track_caller]
+    fn check_register(&self, name: &str, actual: u8, expected: u8) -> &Self {
+        if actual != expected {
+            self.fail(format_args!(
+                "{name} is ${actual:02X}, expected ${expected:02X}"
+            ));
+        }
+        self
+    }
+
+    #[track_caller]
+    fn fail(&self, message: fmt::Arguments) -> ! {
+        let registers = &self.cpu.registers;
+        let Some(end) = self.end else {
+            panic!("{message}");
+        };
+        panic!(
+            "{message}\n\
+             state: {registers}\n\
+             ended: {end} after {} instructions and {} cycles",
+            self.instructions, self.cycles,
+        )
+    }
+}
+
+/// The rows of memory that differ from the expected bytes.
+struct MemoryDiff<'a> {
+    address: u16,
+    expected: &'a [u8],
+    memory: &'a Memory,
+}
+
+impl MemoryDiff<'_> {
+    const BYTES_PER_ROW: usize = 8;
+
+    fn actual(&self, offset: usize) -> u8 {
+        #[allow(clippy::cast_possible_truncation)]
+        self.memory
+            .get_byte(self.address.wrapping_add(offset as u16))
+    }
+
+    fn count(&self) -> usize {
+        (0..self.expected.len())
+            .filter(|offset| self.actual(*offset) != self.expected[*offset])
+            .count()
+    }
+}
+
+impl fmt::Display for MemoryDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const WIDTH: usize = MemoryDiff::BYTES_PER_ROW * 3 - 1;
+
+        writeln!(
+            f,
+            "{} of {} bytes differ from ${:04X}",
+            self.count(),
+            self.expected.len(),
+            self.address
+        )?;
+        write!(f, "       {:WIDTH$}  actual", "expected")?;
+        for (row, expected) in self.expected.chunks(Self::BYTES_PER_ROW).enumerate() {
+            let start = row * Self::BYTES_PER_ROW;
+            let differs = |i: usize| self.actual(start + i) != expected[i];
+            if !(0..expected.len()).any(differs) {
+                continue;
+            }
+
+            #[allow(clippy::cast_possible_truncation)]
+            let address = self.address.wrapping_add(start as u16);
+            write!(f, "\n${address:04X}  ")?;
+            for (i, byte) in expected.iter().enumerate() {
+                write!(f, "{}{byte:02X}", if i == 0 { "" } else { " " })?;
+            }
+            write!(f, "{:1$}", "", WIDTH - (expected.len() * 3 - 1) + 2)?;
+            for i in 0..expected.len() {
+                write!(
+                    f,
+                    "{}{:02X}",
+                    if i == 0 { "" } else { " " },
+                    self.actual(start + i)
+                )?;
+            }
+            write!(f, "\n       {:WIDTH$}  ", "")?;
+            let last = (0..expected.len()).rev().find(|i| differs(*i)).unwrap_or(0);
+            for i in 0..=last {
+                write!(
+                    f,
+                    "{}{}",
+                    if i == 0 { "" } else { " " },
+                    if differs(i) { "^^" } else { "  " }
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The tests catch the panics of failed assertions, which needs `std`.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::instruction::Cmos6502;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    /// The panic message of `check`.
+    fn failure(check: impl FnOnce() + std::panic::UnwindSafe) -> String {
+        let payload = std::panic::catch_unwind(check).unwrap_err();
+        payload.downcast_ref::<String>().unwrap().clone()
+    }
+
+    fn sum() -> Harness {
+        let mut test = Harness::new();
+        // Sums the table at $0300 into $0310: LDX #4; LDA #0; CLC;
+        // loop: ADC $02FF,X; DEX; BNE loop; STA $0310; BRK
+        // The ADC crosses a page every time round.
+        test.load(
+            0x0200,
+            &[
+                0xa2, 0x04, 0xa9, 0x00, 0x18, 0x7d, 0xff, 0x02, 0xca, 0xd0, 0xfa, 0x8d, 0x10, 0x03,
+                0x00,
+            ],
+        )
+        .load(0x0300, &[1, 2, 3, 4]);
+        test
+    }
+
+    #[test]
+    fn runs_until_brk() {
+        let mut test = sum();
+        assert_eq!(test.run_from(0x0200), End::Brk(0x020e));
+        test.assert_a(10)
+            .assert_x(0)
+            .assert_flags_set(Status::PS_ZERO)
+            .assert_flags_clear(Status::PS_CARRY | Status::PS_NEGATIVE)
+            .assert_memory(0x0310, &[10]);
+        assert_eq!(test.instructions(), 16);
+        test.assert_cycles(49).assert_cycles_at_most(60);
+    }
+
+    #[test]
+    fn stops_at_traps_and_limits() {
+        let mut test = Harness::with_variant(Cmos6502);
+        test.load(0x0200, &[0xe8, 0x80, 0xfe]);
+        assert_eq!(test.run_from(0x0200), End::Trap(0x0201));
+        test.assert_x(1);
+
+        let mut test = Harness::new();
+        test.max_cycles = 100;
+        test.load(0x0200, &[0xe8, 0x4c, 0x00, 0x02]);
+        assert_eq!(test.run_from(0x0200), End::CycleLimit);
+        test.assert_cycles_at_most(102);
+    }
+
+    #[test]
+    fn failures_show_the_state() {
+        let message = failure(|| {
+            let mut test = sum();
+            test.run_from(0x0200);
+            test.assert_a(11);
+        });
+        assert_eq!(
+            message,
+            "A is $0A, expected $0B\n\
+             state: PC=020E A=0A X=00 Y=00 SP=FD P=nv-bdIZc\n\
+             ended: BRK at $020E after 16 instructions and 49 cycles"
+        );
+
+        let message = failure(|| {
+            sum().assert_end(End::Brk(0x020e));
+        });
+        assert_eq!(message, "program has not run, expected BRK at $020E");
+    }
+
+    #[test]
+    fn memory_failures_mark_the_differences() {
+        let message = failure(|| {
+            let mut test = sum();
+            test.run_from(0x0200);
+            test.assert_memory(0x02f8, &[0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 9, 4, 5]);
+        });
+        let diff = message.lines().take(4).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            diff,
+            "2 of 13 bytes differ from $02F8\n\
+             \x20      expected                 actual\n\
+             $0300  01 02 09 04 05           01 02 03 04 00\n\
+             \x20                                     ^^    ^^"
+        );
+        assert!(message.contains("ended: BRK"));
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Counting the accesses made to every address.
+//!
+//! [`Heatmap`] wraps a bus and counts the reads and writes of each of the
+//! 65536 addresses. The counts can be written as CSV for a spreadsheet, or
+//! rendered as a 256 by 256 grayscale PNG with one pixel per address, a row
+//! per page, where hot buffers stand out in white and untouched memory
+//! stays black.
+//!
+//! ```
+//! use mos6502::heatmap::Heatmap;
+//! use mos6502::memory::{Access, Bus, Memory};
+//!
+//! let mut bus = Heatmap::new(Memory::new());
+//! bus.set_byte(0x0200, 1);
+//! bus.get_byte(0x0200);
+//! bus.get_byte(0x0200);
+//! assert_eq!(bus.count(0x0200, Access::Read), 2);
+//! assert_eq!(bus.count(0x0200, Access::Write), 1);
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+use core::ops::Range;
+
+use crate::framebuffer::encode_png;
+use crate::memory::{Access, Bus, BusError};
+
+/// A bus that counts the reads and writes of every address.
+///
+/// Counts saturate rather than wrap. Loading a program with
+/// [`Bus::set_bytes`] is not counted, and neither are the reads of
+/// [`Bus::get_bytes`]; tools reading through [`Bus::get_byte`] are counted
+/// like the CPU.
+#[derive(Debug)]
+pub struct Heatmap<B: Bus> {
+    inner: B,
+    reads: Vec<Cell<u32>>,
+    writes: Vec<u32>,
+}
+
+impl<B: Bus> Heatmap<B> {
+    /// Wraps `inner` with every count at zero.
+    pub fn new(inner: B) -> Heatmap<B> {
+        Heatmap {
+            inner,
+            reads: vec![Cell::new(0); 0x10000],
+            writes: vec![0; 0x10000],
+        }
+    }
+
+    /// How many times `address` was accessed in the given way.
+    #[must_use]
+    pub fn count(&self, address: u16, access: Access) -> u32 {
+        match access {
+            Access::Read => self.reads[usize::from(address)].get(),
+            Access::Write => self.writes[usize::from(address)],
+        }
+    }
+
+    /// Forgets every count.
+    pub fn clear(&mut self) {
+        self.reads.iter().for_each(|count| count.set(0));
+        self.writes.fill(0);
+    }
+
+    /// Writes `address,reads,writes` for every address accessed at least
+    /// once, under a header line.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by `out`.
+    pub fn write_csv(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out, "address,reads,writes")?;
+        for address in 0..=u16::MAX {
+            let reads = self.count(address, Access::Read);
+            let writes = self.count(address, Access::Write);
+            if reads > 0 || writes > 0 {
+                writeln!(out, "${address:04X},{reads},{writes}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The counts of `access` as a 256 by 256 grayscale PNG, page `$00` at
+    /// the top. Brightness follows the logarithm of the count, relative to
+    /// the busiest address, so a single access is still visible next to a
+    /// loop counter; addresses never accessed are black.
+    #[must_use]
+    pub fn to_png(&self, access: Access) -> Vec<u8> {
+        let magnitude = |count: u32| u32::BITS - count.leading_zeros();
+        let brightest = (0..=u16::MAX)
+            .map(|address| magnitude(self.count(address, access)))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        let mut rgba = Vec::with_capacity(0x10000 * 4);
+        for address in 0..=u16::MAX {
+            let level = magnitude(self.count(address, access)) * 255 / brightest;
+            let level = u8::try_from(level).unwrap_or(u8::MAX);
+            rgba.extend_from_slice(&[level, level, level, 0xff]);
+        }
+        encode_png(256, 256, &rgba)
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus. Accesses made through
+    /// it are not counted.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus> Bus for Heatmap<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        let count = &self.reads[usize::from(address)];
+        count.set(count.get().saturating_add(1));
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        let count = &mut self.writes[usize::from(address)];
+        *count = count.saturating_add(1);
+        self.inner.set_byte(address, value);
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        self.inner.set_bytes(start, values);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use alloc::string::String;
+
+    #[test]
+    fn counts_a_program_run() {
+        // LDX #2; loop: STX $10; DEX; BNE loop
+        let mut cpu = CPU::new(Heatmap::new(Memory::new()), Nmos6502);
+        cpu.memory
+            .set_bytes(0x0200, &[0xa2, 0x02, 0x86, 0x10, 0xca, 0xd0, 0xfb]);
+        cpu.registers.program_counter = 0x0200;
+        for _ in 0..7 {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.memory.count(0x0010, Access::Write), 2);
+        assert_eq!(cpu.memory.count(0x0203, Access::Read), 2);
+
+        let mut csv = String::new();
+        cpu.memory.write_csv(&mut csv).unwrap();
+        assert!(csv.starts_with("address,reads,writes\n$0010,0,2\n$0200,"));
+        assert!(csv.contains("\n$0201,1,0\n"));
+
+        let png = cpu.memory.to_png(Access::Write);
+        assert_eq!(&png[1..4], b"PNG");
+        // The pixels of the first row follow the signature, the header
+        // chunk, the data chunk's length and type, the zlib header, the
+        // stored block header and the row's filter byte.
+        let row = &png[8 + 25 + 8 + 2 + 5 + 1..];
+        assert_eq!(row[..4], [0, 0, 0, 0xff]);
+        assert_eq!(row[0x10 * 4..0x11 * 4], [0xff, 0xff, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A bus backed by real hardware on the other end of a serial link.
+//!
+//! [`HilBus`] forwards every access the CPU makes to a board attached over
+//! a serial port or USB CDC link: a memory board, or an adapter sitting in a
+//! real machine's 65C02 socket. Run it at a few kilohertz and the emulated
+//! core can drive real chips, or be checked against them, one cycle at a
+//! time. The protocol is small enough to implement on any microcontroller:
+//!
+//! | Request                   | Reply     | Meaning                          |
+//! |---------------------------|-----------|----------------------------------|
+//! | `READ`, addr lo, addr hi  | the byte  | read the byte at the address     |
+//! | `WRITE`, lo, hi, value    | `ACK`     | write the value to the address   |
+//! | `CLOCK`                   | `ACK`     | pulse phi2 once                  |
+//!
+//! `CLOCK` is only sent when clocking is on (see [`HilBus::set_clocked`]),
+//! for boards that take their clock from the emulator rather than their own
+//! oscillator.
+//!
+//! Any [`Read`] + [`Write`] stream will do as the link, such as a port opened
+//! by the `serialport` crate. Give it a read timeout so an unplugged board
+//! stops the CPU with [`BusFault::Timeout`] instead of hanging it.
+
+use core::cell::RefCell;
+use std::io::{self, Read, Write};
+
+use crate::memory::{Access, BusError, BusFault, FallibleBus};
+
+/// Requests, sent as the first byte of a message.
+pub const READ: u8 = b'R';
+pub const WRITE: u8 = b'W';
+pub const CLOCK: u8 = b'C';
+
+/// The reply to a completed `WRITE` or `CLOCK`.
+pub const ACK: u8 = b'K';
+
+/// A bus whose accesses are carried out by hardware on `link`. Wrap it in
+/// [`Fallible`](crate::memory::Fallible) to run a CPU on it, so a board that
+/// stops answering stops the CPU.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::OpenOptions;
+///
+/// use mos6502::cpu::CPU;
+/// use mos6502::hil::HilBus;
+/// use mos6502::instruction::Cmos6502;
+/// use mos6502::machine::Machine;
+/// use mos6502::memory::Fallible;
+///
+/// let port = OpenOptions::new().read(true).write(true).open("/dev/ttyACM0")?;
+/// let mut bus = HilBus::new(port);
+/// bus.set_clocked(true);
+/// let mut machine = Machine::new(CPU::new(Fallible::new(bus), Cmos6502));
+/// machine.reset();
+/// machine.run(Some(1_000));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct HilBus<L: Read + Write> {
+    link: RefCell<L>,
+    clocked: bool,
+    /// A failed `CLOCK`, reported by the next access.
+    clock_error: Option<BusFault>,
+    last_error: RefCell<Option<io::Error>>,
+}
+
+impl<L: Read + Write> HilBus<L> {
+    /// Talks to the board over `link`, with clocking off.
+    pub const fn new(link: L) -> HilBus<L> {
+        HilBus {
+            link: RefCell::new(link),
+            clocked: false,
+            clock_error: None,
+            last_error: RefCell::new(None),
+        }
+    }
+
+    /// Sends a `CLOCK` for every CPU cycle.
+    pub const fn set_clocked(&mut self, clocked: bool) {
+        self.clocked = clocked;
+    }
+
+    #[must_use]
+    pub const fn is_clocked(&self) -> bool {
+        self.clocked
+    }
+
+    /// Returns and forgets the I/O error behind the last failed access, for
+    /// more detail than its [`BusFault`] gives.
+    pub fn take_io_error(&self) -> Option<io::Error> {
+        self.last_error.take()
+    }
+
+    /// Consumes the bus, returning the link.
+    pub fn into_inner(self) -> L {
+        self.link.into_inner()
+    }
+
+    /// Sends `request` and reads the one-byte reply.
+    fn transact(&self, request: &[u8]) -> Result<u8, BusFault> {
+        let mut link = self.link.borrow_mut();
+        let mut reply = [0];
+        link.write_all(request)
+            .and_then(|()| link.flush())
+            .and_then(|()| link.read_exact(&mut reply))
+            .map_err(|error| {
+                let fault = match error.kind() {
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => BusFault::Timeout,
+                    _ => BusFault::Device,
+                };
+                *self.last_error.borrow_mut() = Some(error);
+                fault
+            })?;
+        Ok(reply[0])
+    }
+
+    /// Sends `request` and checks that the board acknowledged it.
+    fn command(&self, request: &[u8]) -> Result<(), BusFault> {
+        match self.transact(request)? {
+            ACK => Ok(()),
+            reply => {
+                *self.last_error.borrow_mut() = Some(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    std::format!("expected an acknowledgement, got ${reply:02X}"),
+                ));
+                Err(BusFault::Device)
+            }
+        }
+    }
+}
+
+impl<L: Read + Write> FallibleBus for HilBus<L> {
+    fn try_get_byte(&self, address: u16) -> Result<u8, BusError> {
+        let [lo, hi] = address.to_le_bytes();
+        self.clock_error
+            .map_or_else(|| self.transact(&[READ, lo, hi]), Err)
+            .map_err(|fault| BusError {
+                access: Access::Read,
+                address,
+                fault,
+            })
+    }
+
+    fn try_set_byte(&mut self, address: u16, value: u8) -> Result<(), BusError> {
+        let [lo, hi] = address.to_le_bytes();
+        self.clock_error
+            .take()
+            .map_or_else(|| self.command(&[WRITE, lo, hi, value]), Err)
+            .map_err(|fault| BusError {
+                access: Access::Write,
+                address,
+                fault,
+            })
+    }
+
+    fn phi2(&mut self, _cycle: u64) {
+        if self.clocked && self.clock_error.is_none() {
+            self.clock_error = self.command(&[CLOCK]).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::{Bus, Fallible};
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+
+    /// A memory board at the far end of the link.
+    struct Board {
+        memory: Vec<u8>,
+        request: Vec<u8>,
+        replies: VecDeque<u8>,
+        clocks: usize,
+    }
+
+    impl Board {
+        fn new() -> Board {
+            Board {
+                memory: alloc::vec![0; 0x10000],
+                request: Vec::new(),
+                replies: VecDeque::new(),
+                clocks: 0,
+            }
+        }
+    }
+
+    impl Write for Board {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            for &byte in bytes {
+                self.request.push(byte);
+                match *self.request.as_slice() {
+                    [READ, lo, hi] => {
+                        let address = usize::from(u16::from_le_bytes([lo, hi]));
+                        self.replies.push_back(self.memory[address]);
+                    }
+                    [WRITE, lo, hi, value] => {
+                        self.memory[usize::from(u16::from_le_bytes([lo, hi]))] = value;
+                        self.replies.push_back(ACK);
+                    }
+                    [CLOCK] => {
+                        self.clocks += 1;
+                        self.replies.push_back(ACK);
+                    }
+                    _ => continue,
+                }
+                self.request.clear();
+            }
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Board {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.replies.pop_front() {
+                Some(byte) => {
+                    buffer[0] = byte;
+                    Ok(1)
+                }
+                None => Err(io::ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn runs_a_program_out_of_board_memory() {
+        let mut board = Board::new();
+        // LDA #$42; STA $0300; INC $0300
+        board.memory[0x0200..0x0208]
+            .copy_from_slice(&[0xa9, 0x42, 0x8d, 0x00, 0x03, 0xee, 0x00, 0x03]);
+        let mut bus = HilBus::new(board);
+        bus.set_clocked(true);
+        let mut cpu = CPU::new(Fallible::new(bus), Nmos6502);
+        cpu.registers.program_counter = 0x0200;
+        for _ in 0..3 {
+            cpu.single_step();
+        }
+
+        assert_eq!(cpu.memory.take_error(), None);
+        let board = cpu.memory.into_inner().into_inner();
+        assert_eq!(board.memory[0x0300], 0x43);
+        assert_eq!(board.clocks, 2 + 4 + 6);
+    }
+
+    #[test]
+    fn a_silent_board_times_out() {
+        struct Silent;
+
+        impl Write for Silent {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Read for Silent {
+            fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::TimedOut.into())
+            }
+        }
+
+        let bus = HilBus::new(Silent);
+        assert_eq!(
+            bus.try_get_byte(0x1234),
+            Err(BusError {
+                access: Access::Read,
+                address: 0x1234,
+                fault: BusFault::Timeout
+            })
+        );
+        assert_eq!(
+            bus.take_io_error().map(|error| error.kind()),
+            Some(io::ErrorKind::TimedOut)
+        );
+    }
+}
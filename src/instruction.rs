@@ -33,9 +33,21 @@ pub enum Instruction {
     // ADd with Carry. This one has now decimal mode.
     ADCnd,
 
+    // AND then Logical shift Right (undocumented)
+    ALR,
+
+    // AND, copying bit 7 to the carry (undocumented)
+    ANC,
+
     // logical AND (bitwise)
     AND,
 
+    // AND then Rotate Right (undocumented)
+    ARR,
+
+    // AND then Rotate Right. This one has no decimal mode.
+    ARRnd,
+
     // Arithmetic Shift Left
     ASL,
 
@@ -96,6 +108,9 @@ pub enum Instruction {
     // Compare Y register
     CPY,
 
+    // DEC then ComPare (undocumented)
+    DCP,
+
     // DECrement memory
     DEC,
 
@@ -117,12 +132,24 @@ pub enum Instruction {
     // INcrement Y register
     INY,
 
+    // INC then SuBtract with carry (undocumented)
+    ISC,
+
+    // INC then SuBtract with carry. This one has no decimal mode.
+    ISCnd,
+
     // JuMP
     JMP,
 
     // Jump to SubRoutine
     JSR,
 
+    // AND with the stack pointer, into A, X and S (undocumented)
+    LAS,
+
+    // LoaD Accumulator and X (undocumented)
+    LAX,
+
     // LoaD Accumulator
     LDA,
 
@@ -165,24 +192,39 @@ pub enum Instruction {
     // PuLl Processor status
     PLP,
 
+    // ROL then AND (undocumented)
+    RLA,
+
     // ROtate Left
     ROL,
 
     // ROtate Right
     ROR,
 
+    // ROR then ADd with carry (undocumented)
+    RRA,
+
+    // ROR then ADd with carry. This one has no decimal mode.
+    RRAnd,
+
     // ReTurn from Interrupt
     RTI,
 
     // ReTurn from Subroutine
     RTS,
 
+    // Store A AND X (undocumented)
+    SAX,
+
     // SuBtract with Carry
     SBC,
 
     // SuBtract with Carry. This one has now decimal mode.
     SBCnd,
 
+    // Subtract from A AND X, into X (undocumented)
+    SBX,
+
     // SEt Carry flag
     SEC,
 
@@ -192,6 +234,12 @@ pub enum Instruction {
     // SEt Interrupt disable
     SEI,
 
+    // ASL then ORA (undocumented)
+    SLO,
+
+    // LSR then EOR (undocumented)
+    SRE,
+
     // STore Accumulator
     STA,
 
@@ -229,6 +277,100 @@ pub enum Instruction {
     TYA,
 }
 
+impl Instruction {
+    /// The assembler mnemonic, e. g. `"LDA"`. Variants that only differ in
+    /// behaviour, such as [`Instruction::ADCnd`], share the mnemonic of the
+    /// instruction they replace.
+    #[must_use]
+    pub const fn mnemonic(self) -> &'static str {
+        match self {
+            Instruction::ADC => "ADC",
+            Instruction::ADCnd => "ADC",
+            Instruction::ALR => "ALR",
+            Instruction::ANC => "ANC",
+            Instruction::AND => "AND",
+            Instruction::ARR => "ARR",
+            Instruction::ARRnd => "ARR",
+            Instruction::ASL => "ASL",
+            Instruction::BCC => "BCC",
+            Instruction::BCS => "BCS",
+            Instruction::BEQ => "BEQ",
+            Instruction::BIT => "BIT",
+            Instruction::BMI => "BMI",
+            Instruction::BNE => "BNE",
+            Instruction::BPL => "BPL",
+            Instruction::BRA => "BRA",
+            Instruction::BRK => "BRK",
+            Instruction::BRKcld => "BRK",
+            Instruction::BVC => "BVC",
+            Instruction::BVS => "BVS",
+            Instruction::CLC => "CLC",
+            Instruction::CLD => "CLD",
+            Instruction::CLI => "CLI",
+            Instruction::CLV => "CLV",
+            Instruction::CMP => "CMP",
+            Instruction::CPX => "CPX",
+            Instruction::CPY => "CPY",
+            Instruction::DCP => "DCP",
+            Instruction::DEC => "DEC",
+            Instruction::DEX => "DEX",
+            Instruction::DEY => "DEY",
+            Instruction::EOR => "EOR",
+            Instruction::INC => "INC",
+            Instruction::INX => "INX",
+            Instruction::INY => "INY",
+            Instruction::ISC => "ISC",
+            Instruction::ISCnd => "ISC",
+            Instruction::JMP => "JMP",
+            Instruction::JSR => "JSR",
+            Instruction::LAS => "LAS",
+            Instruction::LAX => "LAX",
+            Instruction::LDA => "LDA",
+            Instruction::LDX => "LDX",
+            Instruction::LDY => "LDY",
+            Instruction::LSR => "LSR",
+            Instruction::NOP => "NOP",
+            Instruction::ORA => "ORA",
+            Instruction::PHA => "PHA",
+            Instruction::PHX => "PHX",
+            Instruction::PHY => "PHY",
+            Instruction::PHP => "PHP",
+            Instruction::PLA => "PLA",
+            Instruction::PLX => "PLX",
+            Instruction::PLY => "PLY",
+            Instruction::PLP => "PLP",
+            Instruction::RLA => "RLA",
+            Instruction::ROL => "ROL",
+            Instruction::ROR => "ROR",
+            Instruction::RRA => "RRA",
+            Instruction::RRAnd => "RRA",
+            Instruction::RTI => "RTI",
+            Instruction::RTS => "RTS",
+            Instruction::SAX => "SAX",
+            Instruction::SBC => "SBC",
+            Instruction::SBCnd => "SBC",
+            Instruction::SBX => "SBX",
+            Instruction::SEC => "SEC",
+            Instruction::SED => "SED",
+            Instruction::SEI => "SEI",
+            Instruction::SLO => "SLO",
+            Instruction::SRE => "SRE",
+            Instruction::STA => "STA",
+            Instruction::STX => "STX",
+            Instruction::STY => "STY",
+            Instruction::STZ => "STZ",
+            Instruction::TAX => "TAX",
+            Instruction::TAY => "TAY",
+            Instruction::TRB => "TRB",
+            Instruction::TSB => "TSB",
+            Instruction::TSX => "TSX",
+            Instruction::TXA => "TXA",
+            Instruction::TXS => "TXS",
+            Instruction::TYA => "TYA",
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum OpInput {
     UseImplied,
@@ -237,7 +379,7 @@ pub enum OpInput {
     UseAddress(u16),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum AddressingMode {
     // work directly on accumulator, e. g. `lsr a`.
     Accumulator,
@@ -283,6 +425,9 @@ pub enum AddressingMode {
 
     // Address stored at constant zero page address
     ZeroPageIndirect,
+
+    // jump to address stored at (16-bit address plus X register), e. g. `jmp ($1000,X)`.
+    AbsoluteIndexedIndirect,
 }
 
 impl AddressingMode {
@@ -304,276 +449,449 @@ impl AddressingMode {
             AddressingMode::IndexedIndirectX => 1,
             AddressingMode::IndirectIndexedY => 1,
             AddressingMode::ZeroPageIndirect => 1,
+            AddressingMode::AbsoluteIndexedIndirect => 2,
         }
     }
 }
 
 pub type DecodedInstr = (Instruction, OpInput);
 
+/// Base cycle counts for every NMOS 6502 opcode, including the undocumented
+/// ones. Page-crossing and branch-taken penalties are not included.
+#[rustfmt::skip]
+pub const NMOS6502_CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 1
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 2
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 3
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 4
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 5
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 6
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 7
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 8
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 9
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // a
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // b
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // c
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // d
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // e
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // f
+];
+
+/// Base cycle counts for every 65C02 opcode. The undefined opcodes are the
+/// NOPs of the original part, without Rockwell's bit instructions or WDC's
+/// `WAI` and `STP`, and `BRA` is listed like the other branches, with the
+/// taken cycle counted as a penalty.
+#[rustfmt::skip]
+pub const CMOS6502_CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+    7, 6, 2, 1, 5, 3, 5, 1, 3, 2, 2, 1, 6, 4, 6, 1, // 0
+    2, 5, 5, 1, 5, 4, 6, 1, 2, 4, 2, 1, 6, 4, 6, 1, // 1
+    6, 6, 2, 1, 3, 3, 5, 1, 4, 2, 2, 1, 4, 4, 6, 1, // 2
+    2, 5, 5, 1, 4, 4, 6, 1, 2, 4, 2, 1, 4, 4, 6, 1, // 3
+    6, 6, 2, 1, 3, 3, 5, 1, 3, 2, 2, 1, 3, 4, 6, 1, // 4
+    2, 5, 5, 1, 4, 4, 6, 1, 2, 4, 3, 1, 8, 4, 6, 1, // 5
+    6, 6, 2, 1, 3, 3, 5, 1, 4, 2, 2, 1, 6, 4, 6, 1, // 6
+    2, 5, 5, 1, 4, 4, 6, 1, 2, 4, 4, 1, 6, 4, 6, 1, // 7
+    2, 6, 2, 1, 3, 3, 3, 1, 2, 2, 2, 1, 4, 4, 4, 1, // 8
+    2, 6, 5, 1, 4, 4, 4, 1, 2, 5, 2, 1, 4, 5, 5, 1, // 9
+    2, 6, 2, 1, 3, 3, 3, 1, 2, 2, 2, 1, 4, 4, 4, 1, // a
+    2, 5, 5, 1, 4, 4, 4, 1, 2, 4, 2, 1, 4, 4, 4, 1, // b
+    2, 6, 2, 1, 3, 3, 5, 1, 2, 2, 2, 1, 4, 4, 6, 1, // c
+    2, 5, 5, 1, 4, 4, 6, 1, 2, 4, 3, 1, 4, 4, 7, 1, // d
+    2, 6, 2, 1, 3, 3, 5, 1, 2, 2, 2, 1, 4, 4, 6, 1, // e
+    2, 5, 5, 1, 4, 4, 6, 1, 2, 4, 4, 1, 4, 4, 7, 1, // f
+];
+
+/// Builds a 256-entry decode table at compile time from a `const fn` that
+/// decodes one opcode, so decoding is a lookup.
+macro_rules! opcode_table {
+    ($decode:ident) => {{
+        let mut table = [None; 256];
+        let mut opcode = 0;
+        while opcode < table.len() {
+            #[allow(clippy::cast_possible_truncation)]
+            let byte = opcode as u8;
+            table[opcode] = $decode(byte);
+            opcode += 1;
+        }
+        table
+    }};
+}
+
 /// The NMOS 6502 variant. This one is present in the Commodore 64, early Apple IIs, etc.
 #[derive(Copy, Clone, Debug)]
 pub struct Nmos6502;
 
 impl crate::Variant for Nmos6502 {
-    fn decode(opcode: u8) -> Option<(Instruction, AddressingMode)> {
-        match opcode {
-            0x00 => Some((Instruction::BRK, AddressingMode::Implied)),
-            0x01 => Some((Instruction::ORA, AddressingMode::IndexedIndirectX)),
-            0x02 => None,
-            0x03 => None,
-            0x04 => None,
-            0x05 => Some((Instruction::ORA, AddressingMode::ZeroPage)),
-            0x06 => Some((Instruction::ASL, AddressingMode::ZeroPage)),
-            0x07 => None,
-            0x08 => Some((Instruction::PHP, AddressingMode::Implied)),
-            0x09 => Some((Instruction::ORA, AddressingMode::Immediate)),
-            0x0a => Some((Instruction::ASL, AddressingMode::Accumulator)),
-            0x0b => None,
-            0x0c => None,
-            0x0d => Some((Instruction::ORA, AddressingMode::Absolute)),
-            0x0e => Some((Instruction::ASL, AddressingMode::Absolute)),
-            0x0f => None,
-            0x10 => Some((Instruction::BPL, AddressingMode::Relative)),
-            0x11 => Some((Instruction::ORA, AddressingMode::IndirectIndexedY)),
-            0x12 => None,
-            0x13 => None,
-            0x14 => None,
-            0x15 => Some((Instruction::ORA, AddressingMode::ZeroPageX)),
-            0x16 => Some((Instruction::ASL, AddressingMode::ZeroPageX)),
-            0x17 => None,
-            0x18 => Some((Instruction::CLC, AddressingMode::Implied)),
-            0x19 => Some((Instruction::ORA, AddressingMode::AbsoluteY)),
-            0x1a => None,
-            0x1b => None,
-            0x1c => None,
-            0x1d => Some((Instruction::ORA, AddressingMode::AbsoluteX)),
-            0x1e => Some((Instruction::ASL, AddressingMode::AbsoluteX)),
-            0x1f => None,
-            0x20 => Some((Instruction::JSR, AddressingMode::Absolute)),
-            0x21 => Some((Instruction::AND, AddressingMode::IndexedIndirectX)),
-            0x22 => None,
-            0x23 => None,
-            0x24 => Some((Instruction::BIT, AddressingMode::ZeroPage)),
-            0x25 => Some((Instruction::AND, AddressingMode::ZeroPage)),
-            0x26 => Some((Instruction::ROL, AddressingMode::ZeroPage)),
-            0x27 => None,
-            0x28 => Some((Instruction::PLP, AddressingMode::Implied)),
-            0x29 => Some((Instruction::AND, AddressingMode::Immediate)),
-            0x2a => Some((Instruction::ROL, AddressingMode::Accumulator)),
-            0x2b => None,
-            0x2c => Some((Instruction::BIT, AddressingMode::Absolute)),
-            0x2d => Some((Instruction::AND, AddressingMode::Absolute)),
-            0x2e => Some((Instruction::ROL, AddressingMode::Absolute)),
-            0x2f => None,
-            0x30 => Some((Instruction::BMI, AddressingMode::Relative)),
-            0x31 => Some((Instruction::AND, AddressingMode::IndirectIndexedY)),
-            0x32 => None,
-            0x33 => None,
-            0x34 => None,
-            0x35 => Some((Instruction::AND, AddressingMode::ZeroPageX)),
-            0x36 => Some((Instruction::ROL, AddressingMode::ZeroPageX)),
-            0x37 => None,
-            0x38 => Some((Instruction::SEC, AddressingMode::Implied)),
-            0x39 => Some((Instruction::AND, AddressingMode::AbsoluteY)),
-            0x3a => None,
-            0x3b => None,
-            0x3c => None,
-            0x3d => Some((Instruction::AND, AddressingMode::AbsoluteX)),
-            0x3e => Some((Instruction::ROL, AddressingMode::AbsoluteX)),
-            0x3f => None,
-            0x40 => Some((Instruction::RTI, AddressingMode::Implied)),
-            0x41 => Some((Instruction::EOR, AddressingMode::IndexedIndirectX)),
-            0x42 => None,
-            0x43 => None,
-            0x44 => None,
-            0x45 => Some((Instruction::EOR, AddressingMode::ZeroPage)),
-            0x46 => Some((Instruction::LSR, AddressingMode::ZeroPage)),
-            0x47 => None,
-            0x48 => Some((Instruction::PHA, AddressingMode::Implied)),
-            0x49 => Some((Instruction::EOR, AddressingMode::Immediate)),
-            0x4a => Some((Instruction::LSR, AddressingMode::Accumulator)),
-            0x4b => None,
-            0x4c => Some((Instruction::JMP, AddressingMode::Absolute)),
-            0x4d => Some((Instruction::EOR, AddressingMode::Absolute)),
-            0x4e => Some((Instruction::LSR, AddressingMode::Absolute)),
-            0x4f => None,
-            0x50 => Some((Instruction::BVC, AddressingMode::Relative)),
-            0x51 => Some((Instruction::EOR, AddressingMode::IndirectIndexedY)),
-            0x52 => None,
-            0x53 => None,
-            0x54 => None,
-            0x55 => Some((Instruction::EOR, AddressingMode::ZeroPageX)),
-            0x56 => Some((Instruction::LSR, AddressingMode::ZeroPageX)),
-            0x57 => None,
-            0x58 => Some((Instruction::CLI, AddressingMode::Implied)),
-            0x59 => Some((Instruction::EOR, AddressingMode::AbsoluteY)),
-            0x5a => None,
-            0x5b => None,
-            0x5c => None,
-            0x5d => Some((Instruction::EOR, AddressingMode::AbsoluteX)),
-            0x5e => Some((Instruction::LSR, AddressingMode::AbsoluteX)),
-            0x5f => None,
-            0x60 => Some((Instruction::RTS, AddressingMode::Implied)),
-            0x61 => Some((Instruction::ADC, AddressingMode::IndexedIndirectX)),
-            0x62 => None,
-            0x63 => None,
-            0x64 => None,
-            0x65 => Some((Instruction::ADC, AddressingMode::ZeroPage)),
-            0x66 => Some((Instruction::ROR, AddressingMode::ZeroPage)),
-            0x67 => None,
-            0x68 => Some((Instruction::PLA, AddressingMode::Implied)),
-            0x69 => Some((Instruction::ADC, AddressingMode::Immediate)),
-            0x6a => Some((Instruction::ROR, AddressingMode::Accumulator)),
-            0x6b => None,
-            0x6c => Some((Instruction::JMP, AddressingMode::BuggyIndirect)),
-            0x6d => Some((Instruction::ADC, AddressingMode::Absolute)),
-            0x6e => Some((Instruction::ROR, AddressingMode::Absolute)),
-            0x6f => None,
-            0x70 => Some((Instruction::BVS, AddressingMode::Relative)),
-            0x71 => Some((Instruction::ADC, AddressingMode::IndirectIndexedY)),
-            0x72 => None,
-            0x73 => None,
-            0x74 => None,
-            0x75 => Some((Instruction::ADC, AddressingMode::ZeroPageX)),
-            0x76 => Some((Instruction::ROR, AddressingMode::ZeroPageX)),
-            0x77 => None,
-            0x78 => Some((Instruction::SEI, AddressingMode::Implied)),
-            0x79 => Some((Instruction::ADC, AddressingMode::AbsoluteY)),
-            0x7a => None,
-            0x7b => None,
-            0x7c => None,
-            0x7d => Some((Instruction::ADC, AddressingMode::AbsoluteX)),
-            0x7e => Some((Instruction::ROR, AddressingMode::AbsoluteX)),
-            0x7f => None,
-            0x80 => None,
-            0x81 => Some((Instruction::STA, AddressingMode::IndexedIndirectX)),
-            0x82 => None,
-            0x83 => None,
-            0x84 => Some((Instruction::STY, AddressingMode::ZeroPage)),
-            0x85 => Some((Instruction::STA, AddressingMode::ZeroPage)),
-            0x86 => Some((Instruction::STX, AddressingMode::ZeroPage)),
-            0x87 => None,
-            0x88 => Some((Instruction::DEY, AddressingMode::Implied)),
-            0x89 => None,
-            0x8a => Some((Instruction::TXA, AddressingMode::Implied)),
-            0x8b => None,
-            0x8c => Some((Instruction::STY, AddressingMode::Absolute)),
-            0x8d => Some((Instruction::STA, AddressingMode::Absolute)),
-            0x8e => Some((Instruction::STX, AddressingMode::Absolute)),
-            0x8f => None,
-            0x90 => Some((Instruction::BCC, AddressingMode::Relative)),
-            0x91 => Some((Instruction::STA, AddressingMode::IndirectIndexedY)),
-            0x92 => None,
-            0x93 => None,
-            0x94 => Some((Instruction::STY, AddressingMode::ZeroPageX)),
-            0x95 => Some((Instruction::STA, AddressingMode::ZeroPageX)),
-            0x96 => Some((Instruction::STX, AddressingMode::ZeroPageY)),
-            0x97 => None,
-            0x98 => Some((Instruction::TYA, AddressingMode::Implied)),
-            0x99 => Some((Instruction::STA, AddressingMode::AbsoluteY)),
-            0x9a => Some((Instruction::TXS, AddressingMode::Implied)),
-            0x9b => None,
-            0x9c => None,
-            0x9d => Some((Instruction::STA, AddressingMode::AbsoluteX)),
-            0x9e => None,
-            0x9f => None,
-            0xa0 => Some((Instruction::LDY, AddressingMode::Immediate)),
-            0xa1 => Some((Instruction::LDA, AddressingMode::IndexedIndirectX)),
-            0xa2 => Some((Instruction::LDX, AddressingMode::Immediate)),
-            0xa3 => None,
-            0xa4 => Some((Instruction::LDY, AddressingMode::ZeroPage)),
-            0xa5 => Some((Instruction::LDA, AddressingMode::ZeroPage)),
-            0xa6 => Some((Instruction::LDX, AddressingMode::ZeroPage)),
-            0xa7 => None,
-            0xa8 => Some((Instruction::TAY, AddressingMode::Implied)),
-            0xa9 => Some((Instruction::LDA, AddressingMode::Immediate)),
-            0xaa => Some((Instruction::TAX, AddressingMode::Implied)),
-            0xab => None,
-            0xac => Some((Instruction::LDY, AddressingMode::Absolute)),
-            0xad => Some((Instruction::LDA, AddressingMode::Absolute)),
-            0xae => Some((Instruction::LDX, AddressingMode::Absolute)),
-            0xaf => None,
-            0xb0 => Some((Instruction::BCS, AddressingMode::Relative)),
-            0xb1 => Some((Instruction::LDA, AddressingMode::IndirectIndexedY)),
-            0xb2 => None,
-            0xb3 => None,
-            0xb4 => Some((Instruction::LDY, AddressingMode::ZeroPageX)),
-            0xb5 => Some((Instruction::LDA, AddressingMode::ZeroPageX)),
-            0xb6 => Some((Instruction::LDX, AddressingMode::ZeroPageY)),
-            0xb7 => None,
-            0xb8 => Some((Instruction::CLV, AddressingMode::Implied)),
-            0xb9 => Some((Instruction::LDA, AddressingMode::AbsoluteY)),
-            0xba => Some((Instruction::TSX, AddressingMode::Implied)),
-            0xbb => None,
-            0xbc => Some((Instruction::LDY, AddressingMode::AbsoluteX)),
-            0xbd => Some((Instruction::LDA, AddressingMode::AbsoluteX)),
-            0xbe => Some((Instruction::LDX, AddressingMode::AbsoluteY)),
-            0xbf => None,
-            0xc0 => Some((Instruction::CPY, AddressingMode::Immediate)),
-            0xc1 => Some((Instruction::CMP, AddressingMode::IndexedIndirectX)),
-            0xc2 => None,
-            0xc3 => None,
-            0xc4 => Some((Instruction::CPY, AddressingMode::ZeroPage)),
-            0xc5 => Some((Instruction::CMP, AddressingMode::ZeroPage)),
-            0xc6 => Some((Instruction::DEC, AddressingMode::ZeroPage)),
-            0xc7 => None,
-            0xc8 => Some((Instruction::INY, AddressingMode::Implied)),
-            0xc9 => Some((Instruction::CMP, AddressingMode::Immediate)),
-            0xca => Some((Instruction::DEX, AddressingMode::Implied)),
-            0xcb => None,
-            0xcc => Some((Instruction::CPY, AddressingMode::Absolute)),
-            0xcd => Some((Instruction::CMP, AddressingMode::Absolute)),
-            0xce => Some((Instruction::DEC, AddressingMode::Absolute)),
-            0xcf => None,
-            0xd0 => Some((Instruction::BNE, AddressingMode::Relative)),
-            0xd1 => Some((Instruction::CMP, AddressingMode::IndirectIndexedY)),
-            0xd2 => None,
-            0xd3 => None,
-            0xd4 => None,
-            0xd5 => Some((Instruction::CMP, AddressingMode::ZeroPageX)),
-            0xd6 => Some((Instruction::DEC, AddressingMode::ZeroPageX)),
-            0xd7 => None,
-            0xd8 => Some((Instruction::CLD, AddressingMode::Implied)),
-            0xd9 => Some((Instruction::CMP, AddressingMode::AbsoluteY)),
-            0xda => None,
-            0xdb => None,
-            0xdc => None,
-            0xdd => Some((Instruction::CMP, AddressingMode::AbsoluteX)),
-            0xde => Some((Instruction::DEC, AddressingMode::AbsoluteX)),
-            0xdf => None,
-            0xe0 => Some((Instruction::CPX, AddressingMode::Immediate)),
-            0xe1 => Some((Instruction::SBC, AddressingMode::IndexedIndirectX)),
-            0xe2 => None,
-            0xe3 => None,
-            0xe4 => Some((Instruction::CPX, AddressingMode::ZeroPage)),
-            0xe5 => Some((Instruction::SBC, AddressingMode::ZeroPage)),
-            0xe6 => Some((Instruction::INC, AddressingMode::ZeroPage)),
-            0xe7 => None,
-            0xe8 => Some((Instruction::INX, AddressingMode::Implied)),
-            0xe9 => Some((Instruction::SBC, AddressingMode::Immediate)),
-            0xea => Some((Instruction::NOP, AddressingMode::Implied)),
-            0xeb => None,
-            0xec => Some((Instruction::CPX, AddressingMode::Absolute)),
-            0xed => Some((Instruction::SBC, AddressingMode::Absolute)),
-            0xee => Some((Instruction::INC, AddressingMode::Absolute)),
-            0xef => None,
-            0xf0 => Some((Instruction::BEQ, AddressingMode::Relative)),
-            0xf1 => Some((Instruction::SBC, AddressingMode::IndirectIndexedY)),
-            0xf2 => None,
-            0xf3 => None,
-            0xf4 => None,
-            0xf5 => Some((Instruction::SBC, AddressingMode::ZeroPageX)),
-            0xf6 => Some((Instruction::INC, AddressingMode::ZeroPageX)),
-            0xf7 => None,
-            0xf8 => Some((Instruction::SED, AddressingMode::Implied)),
-            0xf9 => Some((Instruction::SBC, AddressingMode::AbsoluteY)),
-            0xfa => None,
-            0xfb => None,
-            0xfc => None,
-            0xfd => Some((Instruction::SBC, AddressingMode::AbsoluteX)),
-            0xfe => Some((Instruction::INC, AddressingMode::AbsoluteX)),
-            0xff => None,
-        }
+    const OPCODES: [Option<(Instruction, AddressingMode)>; 256] = NMOS6502_OPCODES;
+
+    fn is_undocumented(opcode: u8) -> bool {
+        nmos6502(opcode).is_none()
+    }
+}
+
+/// Decodings of every NMOS 6502 opcode, including the stable undocumented
+/// ones. `None` for the opcodes that jam the CPU and the unstable ones whose
+/// results depend on the chip.
+pub const NMOS6502_OPCODES: [Option<(Instruction, AddressingMode)>; 256] =
+    opcode_table!(nmos6502_undocumented);
+
+const fn nmos6502(opcode: u8) -> Option<(Instruction, AddressingMode)> {
+    match opcode {
+        0x00 => Some((Instruction::BRK, AddressingMode::Implied)),
+        0x01 => Some((Instruction::ORA, AddressingMode::IndexedIndirectX)),
+        0x02 => None,
+        0x03 => None,
+        0x04 => None,
+        0x05 => Some((Instruction::ORA, AddressingMode::ZeroPage)),
+        0x06 => Some((Instruction::ASL, AddressingMode::ZeroPage)),
+        0x07 => None,
+        0x08 => Some((Instruction::PHP, AddressingMode::Implied)),
+        0x09 => Some((Instruction::ORA, AddressingMode::Immediate)),
+        0x0a => Some((Instruction::ASL, AddressingMode::Accumulator)),
+        0x0b => None,
+        0x0c => None,
+        0x0d => Some((Instruction::ORA, AddressingMode::Absolute)),
+        0x0e => Some((Instruction::ASL, AddressingMode::Absolute)),
+        0x0f => None,
+        0x10 => Some((Instruction::BPL, AddressingMode::Relative)),
+        0x11 => Some((Instruction::ORA, AddressingMode::IndirectIndexedY)),
+        0x12 => None,
+        0x13 => None,
+        0x14 => None,
+        0x15 => Some((Instruction::ORA, AddressingMode::ZeroPageX)),
+        0x16 => Some((Instruction::ASL, AddressingMode::ZeroPageX)),
+        0x17 => None,
+        0x18 => Some((Instruction::CLC, AddressingMode::Implied)),
+        0x19 => Some((Instruction::ORA, AddressingMode::AbsoluteY)),
+        0x1a => None,
+        0x1b => None,
+        0x1c => None,
+        0x1d => Some((Instruction::ORA, AddressingMode::AbsoluteX)),
+        0x1e => Some((Instruction::ASL, AddressingMode::AbsoluteX)),
+        0x1f => None,
+        0x20 => Some((Instruction::JSR, AddressingMode::Absolute)),
+        0x21 => Some((Instruction::AND, AddressingMode::IndexedIndirectX)),
+        0x22 => None,
+        0x23 => None,
+        0x24 => Some((Instruction::BIT, AddressingMode::ZeroPage)),
+        0x25 => Some((Instruction::AND, AddressingMode::ZeroPage)),
+        0x26 => Some((Instruction::ROL, AddressingMode::ZeroPage)),
+        0x27 => None,
+        0x28 => Some((Instruction::PLP, AddressingMode::Implied)),
+        0x29 => Some((Instruction::AND, AddressingMode::Immediate)),
+        0x2a => Some((Instruction::ROL, AddressingMode::Accumulator)),
+        0x2b => None,
+        0x2c => Some((Instruction::BIT, AddressingMode::Absolute)),
+        0x2d => Some((Instruction::AND, AddressingMode::Absolute)),
+        0x2e => Some((Instruction::ROL, AddressingMode::Absolute)),
+        0x2f => None,
+        0x30 => Some((Instruction::BMI, AddressingMode::Relative)),
+        0x31 => Some((Instruction::AND, AddressingMode::IndirectIndexedY)),
+        0x32 => None,
+        0x33 => None,
+        0x34 => None,
+        0x35 => Some((Instruction::AND, AddressingMode::ZeroPageX)),
+        0x36 => Some((Instruction::ROL, AddressingMode::ZeroPageX)),
+        0x37 => None,
+        0x38 => Some((Instruction::SEC, AddressingMode::Implied)),
+        0x39 => Some((Instruction::AND, AddressingMode::AbsoluteY)),
+        0x3a => None,
+        0x3b => None,
+        0x3c => None,
+        0x3d => Some((Instruction::AND, AddressingMode::AbsoluteX)),
+        0x3e => Some((Instruction::ROL, AddressingMode::AbsoluteX)),
+        0x3f => None,
+        0x40 => Some((Instruction::RTI, AddressingMode::Implied)),
+        0x41 => Some((Instruction::EOR, AddressingMode::IndexedIndirectX)),
+        0x42 => None,
+        0x43 => None,
+        0x44 => None,
+        0x45 => Some((Instruction::EOR, AddressingMode::ZeroPage)),
+        0x46 => Some((Instruction::LSR, AddressingMode::ZeroPage)),
+        0x47 => None,
+        0x48 => Some((Instruction::PHA, AddressingMode::Implied)),
+        0x49 => Some((Instruction::EOR, AddressingMode::Immediate)),
+        0x4a => Some((Instruction::LSR, AddressingMode::Accumulator)),
+        0x4b => None,
+        0x4c => Some((Instruction::JMP, AddressingMode::Absolute)),
+        0x4d => Some((Instruction::EOR, AddressingMode::Absolute)),
+        0x4e => Some((Instruction::LSR, AddressingMode::Absolute)),
+        0x4f => None,
+        0x50 => Some((Instruction::BVC, AddressingMode::Relative)),
+        0x51 => Some((Instruction::EOR, AddressingMode::IndirectIndexedY)),
+        0x52 => None,
+        0x53 => None,
+        0x54 => None,
+        0x55 => Some((Instruction::EOR, AddressingMode::ZeroPageX)),
+        0x56 => Some((Instruction::LSR, AddressingMode::ZeroPageX)),
+        0x57 => None,
+        0x58 => Some((Instruction::CLI, AddressingMode::Implied)),
+        0x59 => Some((Instruction::EOR, AddressingMode::AbsoluteY)),
+        0x5a => None,
+        0x5b => None,
+        0x5c => None,
+        0x5d => Some((Instruction::EOR, AddressingMode::AbsoluteX)),
+        0x5e => Some((Instruction::LSR, AddressingMode::AbsoluteX)),
+        0x5f => None,
+        0x60 => Some((Instruction::RTS, AddressingMode::Implied)),
+        0x61 => Some((Instruction::ADC, AddressingMode::IndexedIndirectX)),
+        0x62 => None,
+        0x63 => None,
+        0x64 => None,
+        0x65 => Some((Instruction::ADC, AddressingMode::ZeroPage)),
+        0x66 => Some((Instruction::ROR, AddressingMode::ZeroPage)),
+        0x67 => None,
+        0x68 => Some((Instruction::PLA, AddressingMode::Implied)),
+        0x69 => Some((Instruction::ADC, AddressingMode::Immediate)),
+        0x6a => Some((Instruction::ROR, AddressingMode::Accumulator)),
+        0x6b => None,
+        0x6c => Some((Instruction::JMP, AddressingMode::BuggyIndirect)),
+        0x6d => Some((Instruction::ADC, AddressingMode::Absolute)),
+        0x6e => Some((Instruction::ROR, AddressingMode::Absolute)),
+        0x6f => None,
+        0x70 => Some((Instruction::BVS, AddressingMode::Relative)),
+        0x71 => Some((Instruction::ADC, AddressingMode::IndirectIndexedY)),
+        0x72 => None,
+        0x73 => None,
+        0x74 => None,
+        0x75 => Some((Instruction::ADC, AddressingMode::ZeroPageX)),
+        0x76 => Some((Instruction::ROR, AddressingMode::ZeroPageX)),
+        0x77 => None,
+        0x78 => Some((Instruction::SEI, AddressingMode::Implied)),
+        0x79 => Some((Instruction::ADC, AddressingMode::AbsoluteY)),
+        0x7a => None,
+        0x7b => None,
+        0x7c => None,
+        0x7d => Some((Instruction::ADC, AddressingMode::AbsoluteX)),
+        0x7e => Some((Instruction::ROR, AddressingMode::AbsoluteX)),
+        0x7f => None,
+        0x80 => None,
+        0x81 => Some((Instruction::STA, AddressingMode::IndexedIndirectX)),
+        0x82 => None,
+        0x83 => None,
+        0x84 => Some((Instruction::STY, AddressingMode::ZeroPage)),
+        0x85 => Some((Instruction::STA, AddressingMode::ZeroPage)),
+        0x86 => Some((Instruction::STX, AddressingMode::ZeroPage)),
+        0x87 => None,
+        0x88 => Some((Instruction::DEY, AddressingMode::Implied)),
+        0x89 => None,
+        0x8a => Some((Instruction::TXA, AddressingMode::Implied)),
+        0x8b => None,
+        0x8c => Some((Instruction::STY, AddressingMode::Absolute)),
+        0x8d => Some((Instruction::STA, AddressingMode::Absolute)),
+        0x8e => Some((Instruction::STX, AddressingMode::Absolute)),
+        0x8f => None,
+        0x90 => Some((Instruction::BCC, AddressingMode::Relative)),
+        0x91 => Some((Instruction::STA, AddressingMode::IndirectIndexedY)),
+        0x92 => None,
+        0x93 => None,
+        0x94 => Some((Instruction::STY, AddressingMode::ZeroPageX)),
+        0x95 => Some((Instruction::STA, AddressingMode::ZeroPageX)),
+        0x96 => Some((Instruction::STX, AddressingMode::ZeroPageY)),
+        0x97 => None,
+        0x98 => Some((Instruction::TYA, AddressingMode::Implied)),
+        0x99 => Some((Instruction::STA, AddressingMode::AbsoluteY)),
+        0x9a => Some((Instruction::TXS, AddressingMode::Implied)),
+        0x9b => None,
+        0x9c => None,
+        0x9d => Some((Instruction::STA, AddressingMode::AbsoluteX)),
+        0x9e => None,
+        0x9f => None,
+        0xa0 => Some((Instruction::LDY, AddressingMode::Immediate)),
+        0xa1 => Some((Instruction::LDA, AddressingMode::IndexedIndirectX)),
+        0xa2 => Some((Instruction::LDX, AddressingMode::Immediate)),
+        0xa3 => None,
+        0xa4 => Some((Instruction::LDY, AddressingMode::ZeroPage)),
+        0xa5 => Some((Instruction::LDA, AddressingMode::ZeroPage)),
+        0xa6 => Some((Instruction::LDX, AddressingMode::ZeroPage)),
+        0xa7 => None,
+        0xa8 => Some((Instruction::TAY, AddressingMode::Implied)),
+        0xa9 => Some((Instruction::LDA, AddressingMode::Immediate)),
+        0xaa => Some((Instruction::TAX, AddressingMode::Implied)),
+        0xab => None,
+        0xac => Some((Instruction::LDY, AddressingMode::Absolute)),
+        0xad => Some((Instruction::LDA, AddressingMode::Absolute)),
+        0xae => Some((Instruction::LDX, AddressingMode::Absolute)),
+        0xaf => None,
+        0xb0 => Some((Instruction::BCS, AddressingMode::Relative)),
+        0xb1 => Some((Instruction::LDA, AddressingMode::IndirectIndexedY)),
+        0xb2 => None,
+        0xb3 => None,
+        0xb4 => Some((Instruction::LDY, AddressingMode::ZeroPageX)),
+        0xb5 => Some((Instruction::LDA, AddressingMode::ZeroPageX)),
+        0xb6 => Some((Instruction::LDX, AddressingMode::ZeroPageY)),
+        0xb7 => None,
+        0xb8 => Some((Instruction::CLV, AddressingMode::Implied)),
+        0xb9 => Some((Instruction::LDA, AddressingMode::AbsoluteY)),
+        0xba => Some((Instruction::TSX, AddressingMode::Implied)),
+        0xbb => None,
+        0xbc => Some((Instruction::LDY, AddressingMode::AbsoluteX)),
+        0xbd => Some((Instruction::LDA, AddressingMode::AbsoluteX)),
+        0xbe => Some((Instruction::LDX, AddressingMode::AbsoluteY)),
+        0xbf => None,
+        0xc0 => Some((Instruction::CPY, AddressingMode::Immediate)),
+        0xc1 => Some((Instruction::CMP, AddressingMode::IndexedIndirectX)),
+        0xc2 => None,
+        0xc3 => None,
+        0xc4 => Some((Instruction::CPY, AddressingMode::ZeroPage)),
+        0xc5 => Some((Instruction::CMP, AddressingMode::ZeroPage)),
+        0xc6 => Some((Instruction::DEC, AddressingMode::ZeroPage)),
+        0xc7 => None,
+        0xc8 => Some((Instruction::INY, AddressingMode::Implied)),
+        0xc9 => Some((Instruction::CMP, AddressingMode::Immediate)),
+        0xca => Some((Instruction::DEX, AddressingMode::Implied)),
+        0xcb => None,
+        0xcc => Some((Instruction::CPY, AddressingMode::Absolute)),
+        0xcd => Some((Instruction::CMP, AddressingMode::Absolute)),
+        0xce => Some((Instruction::DEC, AddressingMode::Absolute)),
+        0xcf => None,
+        0xd0 => Some((Instruction::BNE, AddressingMode::Relative)),
+        0xd1 => Some((Instruction::CMP, AddressingMode::IndirectIndexedY)),
+        0xd2 => None,
+        0xd3 => None,
+        0xd4 => None,
+        0xd5 => Some((Instruction::CMP, AddressingMode::ZeroPageX)),
+        0xd6 => Some((Instruction::DEC, AddressingMode::ZeroPageX)),
+        0xd7 => None,
+        0xd8 => Some((Instruction::CLD, AddressingMode::Implied)),
+        0xd9 => Some((Instruction::CMP, AddressingMode::AbsoluteY)),
+        0xda => None,
+        0xdb => None,
+        0xdc => None,
+        0xdd => Some((Instruction::CMP, AddressingMode::AbsoluteX)),
+        0xde => Some((Instruction::DEC, AddressingMode::AbsoluteX)),
+        0xdf => None,
+        0xe0 => Some((Instruction::CPX, AddressingMode::Immediate)),
+        0xe1 => Some((Instruction::SBC, AddressingMode::IndexedIndirectX)),
+        0xe2 => None,
+        0xe3 => None,
+        0xe4 => Some((Instruction::CPX, AddressingMode::ZeroPage)),
+        0xe5 => Some((Instruction::SBC, AddressingMode::ZeroPage)),
+        0xe6 => Some((Instruction::INC, AddressingMode::ZeroPage)),
+        0xe7 => None,
+        0xe8 => Some((Instruction::INX, AddressingMode::Implied)),
+        0xe9 => Some((Instruction::SBC, AddressingMode::Immediate)),
+        0xea => Some((Instruction::NOP, AddressingMode::Implied)),
+        0xeb => None,
+        0xec => Some((Instruction::CPX, AddressingMode::Absolute)),
+        0xed => Some((Instruction::SBC, AddressingMode::Absolute)),
+        0xee => Some((Instruction::INC, AddressingMode::Absolute)),
+        0xef => None,
+        0xf0 => Some((Instruction::BEQ, AddressingMode::Relative)),
+        0xf1 => Some((Instruction::SBC, AddressingMode::IndirectIndexedY)),
+        0xf2 => None,
+        0xf3 => None,
+        0xf4 => None,
+        0xf5 => Some((Instruction::SBC, AddressingMode::ZeroPageX)),
+        0xf6 => Some((Instruction::INC, AddressingMode::ZeroPageX)),
+        0xf7 => None,
+        0xf8 => Some((Instruction::SED, AddressingMode::Implied)),
+        0xf9 => Some((Instruction::SBC, AddressingMode::AbsoluteY)),
+        0xfa => None,
+        0xfb => None,
+        0xfc => None,
+        0xfd => Some((Instruction::SBC, AddressingMode::AbsoluteX)),
+        0xfe => Some((Instruction::INC, AddressingMode::AbsoluteX)),
+        0xff => None,
+    }
+}
+
+/// The undocumented NMOS 6502 opcodes that behave the same on every chip,
+/// falling back to [`nmos6502`] for the documented ones. `$EB` is a copy of
+/// `SBC #`, and the rest of the unused opcodes outside the `$x2` column are
+/// `NOP`s that read their operand.
+const fn nmos6502_undocumented(opcode: u8) -> Option<(Instruction, AddressingMode)> {
+    match opcode {
+        0x03 => Some((Instruction::SLO, AddressingMode::IndexedIndirectX)),
+        0x04 => Some((Instruction::NOP, AddressingMode::ZeroPage)),
+        0x07 => Some((Instruction::SLO, AddressingMode::ZeroPage)),
+        0x0b => Some((Instruction::ANC, AddressingMode::Immediate)),
+        0x0c => Some((Instruction::NOP, AddressingMode::Absolute)),
+        0x0f => Some((Instruction::SLO, AddressingMode::Absolute)),
+        0x13 => Some((Instruction::SLO, AddressingMode::IndirectIndexedY)),
+        0x14 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0x17 => Some((Instruction::SLO, AddressingMode::ZeroPageX)),
+        0x1a => Some((Instruction::NOP, AddressingMode::Implied)),
+        0x1b => Some((Instruction::SLO, AddressingMode::AbsoluteY)),
+        0x1c => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0x1f => Some((Instruction::SLO, AddressingMode::AbsoluteX)),
+        0x23 => Some((Instruction::RLA, AddressingMode::IndexedIndirectX)),
+        0x27 => Some((Instruction::RLA, AddressingMode::ZeroPage)),
+        0x2b => Some((Instruction::ANC, AddressingMode::Immediate)),
+        0x2f => Some((Instruction::RLA, AddressingMode::Absolute)),
+        0x33 => Some((Instruction::RLA, AddressingMode::IndirectIndexedY)),
+        0x34 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0x37 => Some((Instruction::RLA, AddressingMode::ZeroPageX)),
+        0x3a => Some((Instruction::NOP, AddressingMode::Implied)),
+        0x3b => Some((Instruction::RLA, AddressingMode::AbsoluteY)),
+        0x3c => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0x3f => Some((Instruction::RLA, AddressingMode::AbsoluteX)),
+        0x43 => Some((Instruction::SRE, AddressingMode::IndexedIndirectX)),
+        0x44 => Some((Instruction::NOP, AddressingMode::ZeroPage)),
+        0x47 => Some((Instruction::SRE, AddressingMode::ZeroPage)),
+        0x4b => Some((Instruction::ALR, AddressingMode::Immediate)),
+        0x4f => Some((Instruction::SRE, AddressingMode::Absolute)),
+        0x53 => Some((Instruction::SRE, AddressingMode::IndirectIndexedY)),
+        0x54 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0x57 => Some((Instruction::SRE, AddressingMode::ZeroPageX)),
+        0x5a => Some((Instruction::NOP, AddressingMode::Implied)),
+        0x5b => Some((Instruction::SRE, AddressingMode::AbsoluteY)),
+        0x5c => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0x5f => Some((Instruction::SRE, AddressingMode::AbsoluteX)),
+        0x63 => Some((Instruction::RRA, AddressingMode::IndexedIndirectX)),
+        0x64 => Some((Instruction::NOP, AddressingMode::ZeroPage)),
+        0x67 => Some((Instruction::RRA, AddressingMode::ZeroPage)),
+        0x6b => Some((Instruction::ARR, AddressingMode::Immediate)),
+        0x6f => Some((Instruction::RRA, AddressingMode::Absolute)),
+        0x73 => Some((Instruction::RRA, AddressingMode::IndirectIndexedY)),
+        0x74 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0x77 => Some((Instruction::RRA, AddressingMode::ZeroPageX)),
+        0x7a => Some((Instruction::NOP, AddressingMode::Implied)),
+        0x7b => Some((Instruction::RRA, AddressingMode::AbsoluteY)),
+        0x7c => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0x7f => Some((Instruction::RRA, AddressingMode::AbsoluteX)),
+        0x80 => Some((Instruction::NOP, AddressingMode::Immediate)),
+        0x82 => Some((Instruction::NOP, AddressingMode::Immediate)),
+        0x83 => Some((Instruction::SAX, AddressingMode::IndexedIndirectX)),
+        0x87 => Some((Instruction::SAX, AddressingMode::ZeroPage)),
+        0x89 => Some((Instruction::NOP, AddressingMode::Immediate)),
+        0x8f => Some((Instruction::SAX, AddressingMode::Absolute)),
+        0x97 => Some((Instruction::SAX, AddressingMode::ZeroPageY)),
+        0xa3 => Some((Instruction::LAX, AddressingMode::IndexedIndirectX)),
+        0xa7 => Some((Instruction::LAX, AddressingMode::ZeroPage)),
+        0xaf => Some((Instruction::LAX, AddressingMode::Absolute)),
+        0xb3 => Some((Instruction::LAX, AddressingMode::IndirectIndexedY)),
+        0xb7 => Some((Instruction::LAX, AddressingMode::ZeroPageY)),
+        0xbb => Some((Instruction::LAS, AddressingMode::AbsoluteY)),
+        0xbf => Some((Instruction::LAX, AddressingMode::AbsoluteY)),
+        0xc2 => Some((Instruction::NOP, AddressingMode::Immediate)),
+        0xc3 => Some((Instruction::DCP, AddressingMode::IndexedIndirectX)),
+        0xc7 => Some((Instruction::DCP, AddressingMode::ZeroPage)),
+        0xcb => Some((Instruction::SBX, AddressingMode::Immediate)),
+        0xcf => Some((Instruction::DCP, AddressingMode::Absolute)),
+        0xd3 => Some((Instruction::DCP, AddressingMode::IndirectIndexedY)),
+        0xd4 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0xd7 => Some((Instruction::DCP, AddressingMode::ZeroPageX)),
+        0xda => Some((Instruction::NOP, AddressingMode::Implied)),
+        0xdb => Some((Instruction::DCP, AddressingMode::AbsoluteY)),
+        0xdc => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0xdf => Some((Instruction::DCP, AddressingMode::AbsoluteX)),
+        0xe2 => Some((Instruction::NOP, AddressingMode::Immediate)),
+        0xe3 => Some((Instruction::ISC, AddressingMode::IndexedIndirectX)),
+        0xe7 => Some((Instruction::ISC, AddressingMode::ZeroPage)),
+        0xeb => Some((Instruction::SBC, AddressingMode::Immediate)),
+        0xef => Some((Instruction::ISC, AddressingMode::Absolute)),
+        0xf3 => Some((Instruction::ISC, AddressingMode::IndirectIndexedY)),
+        0xf4 => Some((Instruction::NOP, AddressingMode::ZeroPageX)),
+        0xf7 => Some((Instruction::ISC, AddressingMode::ZeroPageX)),
+        0xfa => Some((Instruction::NOP, AddressingMode::Implied)),
+        0xfb => Some((Instruction::ISC, AddressingMode::AbsoluteY)),
+        0xfc => Some((Instruction::NOP, AddressingMode::AbsoluteX)),
+        0xff => Some((Instruction::ISC, AddressingMode::AbsoluteX)),
+        _ => nmos6502(opcode),
     }
 }
 
@@ -583,17 +901,26 @@ impl crate::Variant for Nmos6502 {
 pub struct Ricoh2a03;
 
 impl crate::Variant for Ricoh2a03 {
-    fn decode(opcode: u8) -> Option<(Instruction, AddressingMode)> {
-        // It's the same as on NMOS, but doesn't support decimal mode.
-        match Nmos6502::decode(opcode) {
-            Some((Instruction::ADC, addressing_mode)) => {
-                Some((Instruction::ADCnd, addressing_mode))
-            }
-            Some((Instruction::SBC, addressing_mode)) => {
-                Some((Instruction::SBCnd, addressing_mode))
-            }
-            something_else => something_else,
-        }
+    const OPCODES: [Option<(Instruction, AddressingMode)>; 256] = RICOH2A03_OPCODES;
+
+    fn is_undocumented(opcode: u8) -> bool {
+        nmos6502(opcode).is_none()
+    }
+}
+
+/// Decodings of every 2A03 opcode.
+pub const RICOH2A03_OPCODES: [Option<(Instruction, AddressingMode)>; 256] =
+    opcode_table!(ricoh2a03);
+
+const fn ricoh2a03(opcode: u8) -> Option<(Instruction, AddressingMode)> {
+    // It's the same as on NMOS, but doesn't support decimal mode.
+    match nmos6502_undocumented(opcode) {
+        Some((Instruction::ADC, addressing_mode)) => Some((Instruction::ADCnd, addressing_mode)),
+        Some((Instruction::SBC, addressing_mode)) => Some((Instruction::SBCnd, addressing_mode)),
+        Some((Instruction::ARR, addressing_mode)) => Some((Instruction::ARRnd, addressing_mode)),
+        Some((Instruction::ISC, addressing_mode)) => Some((Instruction::ISCnd, addressing_mode)),
+        Some((Instruction::RRA, addressing_mode)) => Some((Instruction::RRAnd, addressing_mode)),
+        something_else => something_else,
     }
 }
 
@@ -603,12 +930,18 @@ impl crate::Variant for Ricoh2a03 {
 pub struct RevisionA;
 
 impl crate::Variant for RevisionA {
-    fn decode(opcode: u8) -> Option<(Instruction, AddressingMode)> {
-        // It's the same as on NMOS, but has no ROR instruction.
-        match Nmos6502::decode(opcode) {
-            Some((Instruction::ROR, _)) => None,
-            something_else => something_else,
-        }
+    const OPCODES: [Option<(Instruction, AddressingMode)>; 256] = REVISION_A_OPCODES;
+}
+
+/// Decodings of every opcode on a Revision A 6502.
+pub const REVISION_A_OPCODES: [Option<(Instruction, AddressingMode)>; 256] =
+    opcode_table!(revision_a);
+
+const fn revision_a(opcode: u8) -> Option<(Instruction, AddressingMode)> {
+    // It's the same as on NMOS, but has no ROR instruction.
+    match nmos6502(opcode) {
+        Some((Instruction::ROR, _)) => None,
+        something_else => something_else,
     }
 }
 
@@ -617,36 +950,93 @@ impl crate::Variant for RevisionA {
 pub struct Cmos6502;
 
 impl crate::Variant for Cmos6502 {
-    fn decode(opcode: u8) -> Option<(Instruction, AddressingMode)> {
-        // TODO: We obviously need to add the other CMOS instructions here.
-        match opcode {
-            0x00 => Some((Instruction::BRKcld, AddressingMode::Implied)),
-            0x1a => Some((Instruction::INC, AddressingMode::Accumulator)),
-            0x3a => Some((Instruction::DEC, AddressingMode::Accumulator)),
-            0x6c => Some((Instruction::JMP, AddressingMode::Indirect)),
-            0x80 => Some((Instruction::BRA, AddressingMode::Relative)),
-            0x64 => Some((Instruction::STZ, AddressingMode::ZeroPage)),
-            0x74 => Some((Instruction::STZ, AddressingMode::ZeroPageX)),
-            0x9c => Some((Instruction::STZ, AddressingMode::Absolute)),
-            0x9e => Some((Instruction::STZ, AddressingMode::AbsoluteX)),
-            0x7a => Some((Instruction::PLY, AddressingMode::Implied)),
-            0xfa => Some((Instruction::PLX, AddressingMode::Implied)),
-            0x5a => Some((Instruction::PHY, AddressingMode::Implied)),
-            0xda => Some((Instruction::PHX, AddressingMode::Implied)),
-            0x04 => Some((Instruction::TSB, AddressingMode::ZeroPage)),
-            0x14 => Some((Instruction::TRB, AddressingMode::ZeroPage)),
-            0x0c => Some((Instruction::TSB, AddressingMode::Absolute)),
-            0x1c => Some((Instruction::TRB, AddressingMode::Absolute)),
-            0x12 => Some((Instruction::ORA, AddressingMode::ZeroPageIndirect)),
-            0x32 => Some((Instruction::AND, AddressingMode::ZeroPageIndirect)),
-            0x52 => Some((Instruction::EOR, AddressingMode::ZeroPageIndirect)),
-            0x72 => Some((Instruction::ADC, AddressingMode::ZeroPageIndirect)),
-            0x92 => Some((Instruction::STA, AddressingMode::ZeroPageIndirect)),
-            0xb2 => Some((Instruction::LDA, AddressingMode::ZeroPageIndirect)),
-            0xd2 => Some((Instruction::CMP, AddressingMode::ZeroPageIndirect)),
-            0xf2 => Some((Instruction::SBC, AddressingMode::ZeroPageIndirect)),
-            0x89 => Some((Instruction::BIT, AddressingMode::Immediate)),
-            _ => Nmos6502::decode(opcode),
-        }
+    const OPCODES: [Option<(Instruction, AddressingMode)>; 256] = CMOS6502_OPCODES;
+
+    fn cycles(opcode: u8) -> u8 {
+        CMOS6502_CYCLES[usize::from(opcode)]
+    }
+
+    fn decimal_extra_cycle() -> bool {
+        true
+    }
+
+    fn adc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, crate::registers::Status) {
+        crate::alu::adc_cmos(a, b, carry, decimal)
+    }
+
+    fn sbc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, crate::registers::Status) {
+        crate::alu::sbc_cmos(a, b, carry, decimal)
+    }
+
+    /// The shifts and rotates on `abs,X` take six cycles rather than seven,
+    /// and pay for the page crossing like a read does.
+    fn page_cross_penalty(instruction: Instruction, mode: AddressingMode) -> bool {
+        let shift = mode == AddressingMode::AbsoluteX
+            && matches!(
+                instruction,
+                Instruction::ASL | Instruction::LSR | Instruction::ROL | Instruction::ROR
+            );
+        shift || crate::disasm::classify(instruction, mode) == crate::disasm::Class::Read
+    }
+
+    fn clears_decimal_on_reset() -> bool {
+        true
+    }
+}
+
+/// Decodings of every 65C02 opcode.
+pub const CMOS6502_OPCODES: [Option<(Instruction, AddressingMode)>; 256] = opcode_table!(cmos6502);
+
+// Every opcode decodes, but the table is built like the others.
+#[allow(clippy::unnecessary_wraps)]
+const fn cmos6502(opcode: u8) -> Option<(Instruction, AddressingMode)> {
+    match opcode {
+        0x00 => Some((Instruction::BRKcld, AddressingMode::Implied)),
+        0x1a => Some((Instruction::INC, AddressingMode::Accumulator)),
+        0x3a => Some((Instruction::DEC, AddressingMode::Accumulator)),
+        0x6c => Some((Instruction::JMP, AddressingMode::Indirect)),
+        0x80 => Some((Instruction::BRA, AddressingMode::Relative)),
+        0x64 => Some((Instruction::STZ, AddressingMode::ZeroPage)),
+        0x74 => Some((Instruction::STZ, AddressingMode::ZeroPageX)),
+        0x9c => Some((Instruction::STZ, AddressingMode::Absolute)),
+        0x9e => Some((Instruction::STZ, AddressingMode::AbsoluteX)),
+        0x7a => Some((Instruction::PLY, AddressingMode::Implied)),
+        0xfa => Some((Instruction::PLX, AddressingMode::Implied)),
+        0x5a => Some((Instruction::PHY, AddressingMode::Implied)),
+        0xda => Some((Instruction::PHX, AddressingMode::Implied)),
+        0x04 => Some((Instruction::TSB, AddressingMode::ZeroPage)),
+        0x14 => Some((Instruction::TRB, AddressingMode::ZeroPage)),
+        0x0c => Some((Instruction::TSB, AddressingMode::Absolute)),
+        0x1c => Some((Instruction::TRB, AddressingMode::Absolute)),
+        0x12 => Some((Instruction::ORA, AddressingMode::ZeroPageIndirect)),
+        0x32 => Some((Instruction::AND, AddressingMode::ZeroPageIndirect)),
+        0x52 => Some((Instruction::EOR, AddressingMode::ZeroPageIndirect)),
+        0x72 => Some((Instruction::ADC, AddressingMode::ZeroPageIndirect)),
+        0x92 => Some((Instruction::STA, AddressingMode::ZeroPageIndirect)),
+        0xb2 => Some((Instruction::LDA, AddressingMode::ZeroPageIndirect)),
+        0xd2 => Some((Instruction::CMP, AddressingMode::ZeroPageIndirect)),
+        0xf2 => Some((Instruction::SBC, AddressingMode::ZeroPageIndirect)),
+        0x89 => Some((Instruction::BIT, AddressingMode::Immediate)),
+        0x34 => Some((Instruction::BIT, AddressingMode::ZeroPageX)),
+        0x3c => Some((Instruction::BIT, AddressingMode::AbsoluteX)),
+        0x7c => Some((Instruction::JMP, AddressingMode::AbsoluteIndexedIndirect)),
+        _ => match nmos6502(opcode) {
+            Some(decoded) => Some(decoded),
+            None => Some((Instruction::NOP, cmos_nop_mode(opcode))),
+        },
+    }
+}
+
+/// How many operand bytes the 65C02 skips over for an opcode it doesn't
+/// define. Every such opcode is a `NOP`, most of them taking a single
+/// cycle; Rockwell's bit manipulation instructions and WDC's `WAI` and
+/// `STP` are not emulated.
+const fn cmos_nop_mode(opcode: u8) -> AddressingMode {
+    match opcode {
+        0x44 => AddressingMode::ZeroPage,
+        0x54 | 0xd4 | 0xf4 => AddressingMode::ZeroPageX,
+        0x5c | 0xdc | 0xfc => AddressingMode::Absolute,
+        _ if opcode & 0x0f == 0x02 => AddressingMode::Immediate,
+        _ => AddressingMode::Implied,
     }
 }
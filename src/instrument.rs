@@ -0,0 +1,120 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Support for the `tracing` feature.
+//!
+//! The CPU emits a `trace` level event for every instruction, and opens a
+//! `debug` level span for every subroutine call and interrupt that stays
+//! entered until the matching `RTS` or `RTI`. Everything is emitted under
+//! the `mos6502::cpu` target.
+
+use alloc::vec::Vec;
+use tracing::Span;
+
+/// Spans are only opened up to this depth, so that code which leaves
+/// subroutines without returning (e.g. by resetting the stack pointer)
+/// can't grow the stack without bound.
+const MAX_DEPTH: usize = 256;
+
+/// The stack of entered subroutine and interrupt spans.
+#[derive(Debug)]
+pub(crate) struct Spans {
+    open: Vec<Span>,
+    /// Calls made past [`MAX_DEPTH`], whose returns must not close a span.
+    skipped: usize,
+}
+
+impl Spans {
+    pub(crate) fn enter(&mut self, span: Span) {
+        if self.open.len() >= MAX_DEPTH {
+            self.skipped += 1;
+            return;
+        }
+        if let Some(id) = span.id() {
+            tracing::dispatcher::get_default(|dispatch| dispatch.enter(&id));
+        }
+        self.open.push(span);
+    }
+
+    pub(crate) fn exit(&mut self) {
+        if self.skipped > 0 {
+            self.skipped -= 1;
+            return;
+        }
+        if let Some(span) = self.open.pop() {
+            if let Some(id) = span.id() {
+                tracing::dispatcher::get_default(|dispatch| dispatch.exit(&id));
+            }
+        }
+    }
+}
+
+/// Room for spans up to [`MAX_DEPTH`] is reserved up front, so that
+/// entering one never allocates while the CPU executes.
+impl Default for Spans {
+    fn default() -> Self {
+        Spans {
+            open: Vec::with_capacity(MAX_DEPTH),
+            skipped: 0,
+        }
+    }
+}
+
+/// A cloned CPU starts without any entered spans; they belong to the
+/// original.
+impl Clone for Spans {
+    fn clone(&self) -> Self {
+        Spans::default()
+    }
+}
+
+impl Drop for Spans {
+    fn drop(&mut self) {
+        while !self.open.is_empty() {
+            self.exit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_past_max_depth_do_not_close_spans() {
+        let mut spans = Spans::default();
+        for _ in 0..MAX_DEPTH + 2 {
+            spans.enter(Span::none());
+        }
+        assert_eq!(spans.open.len(), MAX_DEPTH);
+        spans.exit();
+        spans.exit();
+        assert_eq!(spans.open.len(), MAX_DEPTH);
+        spans.exit();
+        assert_eq!(spans.open.len(), MAX_DEPTH - 1);
+    }
+}
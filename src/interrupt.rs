@@ -0,0 +1,325 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Arbitration between the interrupt sources of the CPU.
+//!
+//! The 6502 has two interrupt inputs. NMI is edge-triggered: pulling the
+//! line low latches a request that is serviced once, however long the line
+//! stays low. IRQ is level-triggered and masked by the I flag: it is
+//! serviced at the end of every instruction while it is asserted and
+//! unmasked. `BRK` is a third way in, through the IRQ vector.
+//!
+//! An [`InterruptController`] holds the state of both lines and decides
+//! what the CPU does when several of them compete:
+//!
+//! - At the end of an instruction a latched NMI wins over an IRQ, which
+//!   stays asserted and is serviced after the NMI handler unmasks it.
+//! - An NMI arriving while `BRK` is being executed hijacks it: `BRK` pushes
+//!   its return address and the status with B set as usual, but continues
+//!   at the NMI handler, and the NMI is consumed.
+//!
+//! Devices implement [`IrqSource`]. Real systems wire-OR the IRQ outputs of
+//! all their devices onto the one input, which an [`IrqLine`] models: it is
+//! asserted while any of its sources is, and can say which ones are.
+//!
+//! ```
+//! use mos6502::interrupt::{InterruptController, Request};
+//!
+//! let mut interrupts = InterruptController::new();
+//! interrupts.set_irq(true);
+//! interrupts.set_nmi(true);
+//! assert_eq!(interrupts.poll(false), Some(Request::Nmi));
+//! // The NMI was serviced but the IRQ is still asserted.
+//! assert_eq!(interrupts.poll(false), Some(Request::Irq));
+//! assert_eq!(interrupts.poll(true), None);
+//! ```
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::memory::{IRQ_INTERRUPT_VECTOR_LO, NMI_INTERRUPT_VECTOR_LO};
+
+/// A hardware interrupt the CPU services between instructions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    Nmi,
+    Irq,
+}
+
+impl Request {
+    /// Address of the low byte of the handler's vector.
+    #[must_use]
+    pub const fn vector(self) -> u16 {
+        match self {
+            Request::Nmi => NMI_INTERRUPT_VECTOR_LO,
+            Request::Irq => IRQ_INTERRUPT_VECTOR_LO,
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Request::Nmi => "NMI",
+            Request::Irq => "IRQ",
+        })
+    }
+}
+
+/// The interrupt lines of a CPU and the requests pending on them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterruptController {
+    /// Level of the IRQ input.
+    irq: bool,
+    /// Level of the NMI input.
+    nmi: bool,
+    /// Whether an NMI edge has been seen and not serviced yet.
+    nmi_pending: bool,
+}
+
+impl InterruptController {
+    /// Both lines released and nothing pending.
+    #[must_use]
+    pub const fn new() -> InterruptController {
+        InterruptController {
+            irq: false,
+            nmi: false,
+            nmi_pending: false,
+        }
+    }
+
+    /// Drives the IRQ input; `true` asserts it.
+    pub const fn set_irq(&mut self, asserted: bool) {
+        self.irq = asserted;
+    }
+
+    /// Drives the NMI input; `true` asserts it. Asserting a released line
+    /// latches a request.
+    pub const fn set_nmi(&mut self, asserted: bool) {
+        if asserted && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = asserted;
+    }
+
+    #[must_use]
+    pub const fn irq_asserted(&self) -> bool {
+        self.irq
+    }
+
+    #[must_use]
+    pub const fn nmi_asserted(&self) -> bool {
+        self.nmi
+    }
+
+    /// Whether an NMI has been latched and not serviced yet.
+    #[must_use]
+    pub const fn nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    /// The state packed into a byte: bit 0 is the IRQ line, bit 1 the NMI
+    /// line and bit 2 a latched NMI, as stored in snapshots.
+    #[must_use]
+    pub const fn to_bits(self) -> u8 {
+        self.irq as u8 | (self.nmi as u8) << 1 | (self.nmi_pending as u8) << 2
+    }
+
+    /// The state packed by [`InterruptController::to_bits`]. Other bits are
+    /// ignored.
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> InterruptController {
+        InterruptController {
+            irq: bits & 0x01 != 0,
+            nmi: bits & 0x02 != 0,
+            nmi_pending: bits & 0x04 != 0,
+        }
+    }
+
+    /// The interrupt to service at the end of an instruction, if any, given
+    /// whether the I flag masks IRQs. Servicing an NMI consumes its request.
+    pub const fn poll(&mut self, irq_masked: bool) -> Option<Request> {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Request::Nmi)
+        } else if self.irq && !irq_masked {
+            Some(Request::Irq)
+        } else {
+            None
+        }
+    }
+
+    /// Drops a latched NMI, as a reset does. The lines keep their levels,
+    /// which are driven from outside the CPU.
+    pub const fn reset(&mut self) {
+        self.nmi_pending = false;
+    }
+
+    /// The vector `BRK` jumps through: the NMI vector if an NMI is pending,
+    /// which `BRK` then consumes, and the IRQ vector otherwise.
+    pub const fn brk_vector(&mut self) -> u16 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_INTERRUPT_VECTOR_LO
+        } else {
+            IRQ_INTERRUPT_VECTOR_LO
+        }
+    }
+}
+
+/// A device with an IRQ output.
+pub trait IrqSource {
+    /// Returns `true` while the device asserts the IRQ line.
+    fn irq_pending(&self) -> bool;
+}
+
+impl<T: IrqSource + ?Sized> IrqSource for &T {
+    fn irq_pending(&self) -> bool {
+        (**self).irq_pending()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: IrqSource + ?Sized> IrqSource for Box<T> {
+    fn irq_pending(&self) -> bool {
+        (**self).irq_pending()
+    }
+}
+
+/// Lets a device be shared between the IRQ line and the bus that maps its
+/// registers.
+#[cfg(feature = "alloc")]
+impl<T: IrqSource + ?Sized> IrqSource for Rc<RefCell<T>> {
+    fn irq_pending(&self) -> bool {
+        self.borrow().irq_pending()
+    }
+}
+
+/// The IRQ outputs of several devices, wired together.
+///
+/// ```
+/// use std::cell::Cell;
+///
+/// use mos6502::interrupt::{IrqLine, IrqSource};
+///
+/// struct Timer(Cell<bool>);
+///
+/// impl IrqSource for Timer {
+///     fn irq_pending(&self) -> bool {
+///         self.0.get()
+///     }
+/// }
+///
+/// let (via, acia) = (Timer(Cell::new(false)), Timer(Cell::new(true)));
+/// let mut line = IrqLine::new();
+/// line.add("via", &via);
+/// line.add("acia", &acia);
+/// assert!(line.asserted());
+/// assert_eq!(line.asserting().collect::<Vec<_>>(), ["acia"]);
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Default)]
+pub struct IrqLine<'a> {
+    sources: Vec<(String, Box<dyn IrqSource + 'a>)>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> IrqLine<'a> {
+    #[must_use]
+    pub const fn new() -> IrqLine<'a> {
+        IrqLine {
+            sources: Vec::new(),
+        }
+    }
+
+    /// Connects a device to the line under `name`, which is what
+    /// [`IrqLine::asserting`] reports it as.
+    pub fn add(&mut self, name: impl Into<String>, source: impl IrqSource + 'a) {
+        self.sources.push((name.into(), Box::new(source)));
+    }
+
+    /// Whether any device is connected.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Whether any device asserts the line.
+    #[must_use]
+    pub fn asserted(&self) -> bool {
+        self.sources.iter().any(|(_, source)| source.irq_pending())
+    }
+
+    /// The names of the devices asserting the line, in the order they were
+    /// added.
+    pub fn asserting(&self) -> impl Iterator<Item = &str> + '_ {
+        self.sources
+            .iter()
+            .filter(|(_, source)| source.irq_pending())
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Debug for IrqLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.sources.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nmi_is_edge_triggered() {
+        let mut interrupts = InterruptController::new();
+        interrupts.set_nmi(true);
+        assert_eq!(interrupts.poll(true), Some(Request::Nmi));
+        // Holding the line low doesn't request another.
+        interrupts.set_nmi(true);
+        assert_eq!(interrupts.poll(true), None);
+        interrupts.set_nmi(false);
+        interrupts.set_nmi(true);
+        assert!(interrupts.nmi_pending());
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        let mut interrupts = InterruptController::new();
+        assert_eq!(interrupts.brk_vector(), IRQ_INTERRUPT_VECTOR_LO);
+        interrupts.set_nmi(true);
+        assert_eq!(interrupts.brk_vector(), NMI_INTERRUPT_VECTOR_LO);
+        assert_eq!(interrupts.poll(false), None);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A memory-mapped keyboard fed by the host.
+//!
+//! [`Keyboard`] gives emulated programs a keyboard interface like those of
+//! real machines, with two registers:
+//!
+//! | Offset | Register | Read                                   | Write            |
+//! |--------|----------|----------------------------------------|------------------|
+//! | 0      | `DATA`   | the latched key code                   | ignored          |
+//! | 1      | `STATUS` | bit 7 set while a key waits (strobe)   | acknowledges key |
+//!
+//! The front-end queues key codes with [`Keyboard::press`]. The first is
+//! latched into `DATA` with the strobe set; acknowledging it by writing to
+//! `STATUS` clears the strobe and latches the next queued key, if any.
+//! `DATA` keeps the last key after the strobe is cleared. With
+//! [`Keyboard::set_interrupts`], [`Keyboard::irq_pending`] follows the
+//! strobe, for wiring to the CPU's IRQ line.
+
+use alloc::collections::VecDeque;
+use core::ops::Range;
+
+use crate::interrupt::IrqSource;
+use crate::memory::{Bus, BusError};
+
+pub const DATA: u16 = 0;
+pub const STATUS: u16 = 1;
+
+/// The strobe bit of [`STATUS`].
+pub const STROBE: u8 = 0x80;
+
+/// A bus with keyboard registers at `base` and `base + 1`.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::keyboard::{Keyboard, STROBE};
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut bus = Keyboard::new(Memory::new(), 0xD000);
+/// bus.type_text("HI");
+/// assert_eq!(bus.get_byte(0xD001), STROBE);
+/// assert_eq!(bus.get_byte(0xD000), b'H');
+/// bus.set_byte(0xD001, 0);
+/// assert_eq!(bus.get_byte(0xD000), b'I');
+/// bus.set_byte(0xD001, 0);
+/// assert_eq!(bus.get_byte(0xD001), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Keyboard<B: Bus> {
+    inner: B,
+    base: u16,
+    data: u8,
+    strobe: bool,
+    queue: VecDeque<u8>,
+    interrupts: bool,
+}
+
+impl<B: Bus> Keyboard<B> {
+    /// Maps the keyboard registers at `base` over `inner`.
+    pub const fn new(inner: B, base: u16) -> Self {
+        Keyboard {
+            inner,
+            base,
+            data: 0,
+            strobe: false,
+            queue: VecDeque::new(),
+            interrupts: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    /// Queues a key code, latching it at once if no key is waiting.
+    pub fn press(&mut self, key: u8) {
+        if self.strobe {
+            self.queue.push_back(key);
+        } else {
+            self.data = key;
+            self.strobe = true;
+        }
+    }
+
+    /// Queues the bytes of `text`, with newlines turned into carriage
+    /// returns as most 6502 software expects.
+    pub fn type_text(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.press(if byte == b'\n' { b'\r' } else { byte });
+        }
+    }
+
+    /// Keys queued behind the latched one.
+    #[must_use]
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Drops the latched key and any queued ones.
+    pub fn clear(&mut self) {
+        self.strobe = false;
+        self.queue.clear();
+    }
+
+    /// Makes [`Keyboard::irq_pending`] report the strobe.
+    pub const fn set_interrupts(&mut self, enabled: bool) {
+        self.interrupts = enabled;
+    }
+
+    /// Returns `true` if interrupts are enabled and a key is waiting.
+    #[must_use]
+    pub const fn irq_pending(&self) -> bool {
+        self.interrupts && self.strobe
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the keyboard, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset <= STATUS).then_some(offset)
+    }
+}
+
+impl<B: Bus> IrqSource for Keyboard<B> {
+    fn irq_pending(&self) -> bool {
+        Keyboard::irq_pending(self)
+    }
+}
+
+impl<B: Bus> Bus for Keyboard<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.register(address) {
+            Some(DATA) => self.data,
+            Some(_) => {
+                if self.strobe {
+                    STROBE
+                } else {
+                    0
+                }
+            }
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(STATUS) => {
+                self.strobe = false;
+                if let Some(key) = self.queue.pop_front() {
+                    self.press(key);
+                }
+            }
+            Some(_) => {}
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    /// Clears the latched key and disables interrupts. Keys still queued
+    /// by the host are kept.
+    fn reset(&mut self) {
+        self.data = 0;
+        self.strobe = false;
+        self.interrupts = false;
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn program_polls_the_strobe() {
+        let mut cpu = CPU::new(Keyboard::new(Memory::new(), 0xD000), Nmos6502);
+        // loop: BIT STATUS; BPL loop; LDA DATA; STA STATUS; STA $10
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0x2c, 0x01, 0xd0, 0x10, 0xfb, 0xad, 0x00, 0xd0, 0x8d, 0x01, 0xd0, 0x85, 0x10,
+            ],
+        );
+        cpu.registers.program_counter = 0x0200;
+        for _ in 0..10 {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.registers.program_counter, 0x0200);
+
+        cpu.memory.type_text("\n");
+        while cpu.registers.program_counter != 0x020d {
+            cpu.single_step();
+        }
+        assert_eq!(cpu.memory.get_byte(0x10), b'\r');
+        assert_eq!(cpu.memory.get_byte(0xD001), 0);
+    }
+
+    #[test]
+    fn interrupts_follow_the_strobe() {
+        let mut keyboard = Keyboard::new(Memory::new(), 0xD000);
+        keyboard.press(b'a');
+        assert!(!keyboard.irq_pending());
+        keyboard.set_interrupts(true);
+        assert!(keyboard.irq_pending());
+        keyboard.set_byte(0xD001, 0);
+        assert!(!keyboard.irq_pending());
+        assert_eq!(keyboard.get_byte(0xD000), b'a');
+    }
+}
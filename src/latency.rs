@@ -0,0 +1,159 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Measuring interrupt latency.
+//!
+//! The latency of an interrupt is the time from a device asserting its line
+//! to the first cycle of the handler: the rest of the instruction being
+//! executed, any time spent with interrupts masked, and the seven cycles of
+//! the interrupt sequence. [`LatencyStats`] collects the latency of every
+//! interrupt a [`Machine`](crate::machine::Machine) takes, so a worst case
+//! claimed for some firmware can be checked against a run of it.
+//!
+//! The CPU sees its interrupt lines change between instructions, so
+//! latencies are measured from the end of the instruction during which the
+//! line was asserted.
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use crate::interrupt::Request;
+
+/// The latencies of the interrupts taken during a run, in cycles.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::interrupt::Request;
+/// use mos6502::latency::LatencyStats;
+///
+/// let mut stats = LatencyStats::new();
+/// for cycles in [9, 12, 9, 7] {
+///     stats.record(Request::Irq, cycles);
+/// }
+/// assert_eq!(stats.max(Request::Irq), Some(12));
+/// assert_eq!(stats.count(Request::Nmi), 0);
+/// assert_eq!(
+///     stats.to_string(),
+///     "IRQ: 4 taken, 7 to 12 cycles, mean 9.2\n  7: 1\n  9: 2\n  12: 1\n"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    irq: BTreeMap<u64, u64>,
+    nmi: BTreeMap<u64, u64>,
+}
+
+impl LatencyStats {
+    #[must_use]
+    pub const fn new() -> LatencyStats {
+        LatencyStats {
+            irq: BTreeMap::new(),
+            nmi: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a `request` that took `cycles` to reach its handler.
+    pub fn record(&mut self, request: Request, cycles: u64) {
+        *self.histogram_mut(request).entry(cycles).or_default() += 1;
+    }
+
+    /// How many latencies of `request` were recorded.
+    #[must_use]
+    pub fn count(&self, request: Request) -> u64 {
+        self.histogram(request).map(|(_, count)| count).sum()
+    }
+
+    #[must_use]
+    pub fn min(&self, request: Request) -> Option<u64> {
+        self.histogram(request).next().map(|(cycles, _)| cycles)
+    }
+
+    /// The worst case.
+    #[must_use]
+    pub fn max(&self, request: Request) -> Option<u64> {
+        self.histogram(request)
+            .next_back()
+            .map(|(cycles, _)| cycles)
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean(&self, request: Request) -> Option<f64> {
+        let count = self.count(request);
+        let total: u64 = self
+            .histogram(request)
+            .map(|(cycles, count)| cycles * count)
+            .sum();
+        (count > 0).then(|| total as f64 / count as f64)
+    }
+
+    /// Each latency recorded for `request` and how many times it occurred,
+    /// shortest first.
+    #[must_use]
+    pub fn histogram(&self, request: Request) -> impl DoubleEndedIterator<Item = (u64, u64)> + '_ {
+        let histogram = match request {
+            Request::Irq => &self.irq,
+            Request::Nmi => &self.nmi,
+        };
+        histogram.iter().map(|(&cycles, &count)| (cycles, count))
+    }
+
+    /// Forgets every latency recorded.
+    pub fn clear(&mut self) {
+        self.irq.clear();
+        self.nmi.clear();
+    }
+
+    const fn histogram_mut(&mut self, request: Request) -> &mut BTreeMap<u64, u64> {
+        match request {
+            Request::Irq => &mut self.irq,
+            Request::Nmi => &mut self.nmi,
+        }
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for request in [Request::Nmi, Request::Irq] {
+            let (Some(min), Some(max), Some(mean)) =
+                (self.min(request), self.max(request), self.mean(request))
+            else {
+                continue;
+            };
+            writeln!(
+                f,
+                "{request}: {} taken, {min} to {max} cycles, mean {mean:.1}",
+                self.count(request)
+            )?;
+            for (cycles, count) in self.histogram(request) {
+                writeln!(f, "  {cycles}: {count}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -38,7 +38,7 @@
     variant_size_differences,
     clippy::missing_const_for_fn
 )]
-#![deny(anonymous_parameters, macro_use_extern_crate, pointer_structural_match)]
+#![deny(anonymous_parameters, macro_use_extern_crate)]
 #![allow(clippy::module_name_repetitions)]
 // Registers and ops follow the 6502 naming convention and have similar names at
 // times
@@ -47,19 +47,210 @@
 #![allow(clippy::too_many_lines)]
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+// Only used by the `mos6502-tui` binary.
+#[cfg(feature = "tui")]
+use ratatui as _;
+#[cfg(feature = "std")]
+extern crate std;
+// Only used by the serde tests.
+#[cfg(all(test, not(feature = "serde")))]
+use serde_json as _;
+// Only used by the benchmarks.
+#[cfg(test)]
+use criterion as _;
+
+#[cfg(feature = "std")]
+pub mod acia;
+pub mod alu;
+#[cfg(feature = "alloc")]
+pub mod asm;
+#[cfg(feature = "alloc")]
+pub mod banked;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod battery;
+#[cfg(feature = "alloc")]
+pub mod buslog;
+pub mod cc65;
+#[cfg(feature = "std")]
+pub mod console;
+pub mod coroutine;
+#[cfg(feature = "alloc")]
+pub mod coverage;
 #[doc = include_str!("../README.md")]
 pub mod cpu;
+#[cfg(feature = "alloc")]
+pub mod dbginfo;
+pub mod dialect;
+#[cfg(feature = "alloc")]
+pub mod diff;
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod disk;
+#[cfg(feature = "std")]
+pub mod ehbasic;
+#[cfg(feature = "std")]
+pub mod execlog;
+#[cfg(feature = "alloc")]
+pub mod explain;
+#[cfg(feature = "alloc")]
+pub mod frame;
+#[cfg(feature = "alloc")]
+pub mod framebuffer;
+#[cfg(feature = "gdbstub")]
+pub mod gdbstub;
+#[cfg(feature = "alloc")]
+pub mod golden;
+pub mod harness;
+#[cfg(feature = "alloc")]
+pub mod heatmap;
+#[cfg(feature = "std")]
+pub mod hil;
 pub mod instruction;
+#[cfg(feature = "tracing")]
+mod instrument;
+pub mod interrupt;
+#[cfg(feature = "alloc")]
+pub mod keyboard;
+#[cfg(feature = "alloc")]
+pub mod latency;
+#[cfg(feature = "alloc")]
+pub mod loader;
+#[cfg(feature = "alloc")]
+pub mod lockstep;
+#[cfg(feature = "alloc")]
+pub mod machine;
+pub mod mapper;
 pub mod memory;
+#[cfg(feature = "alloc")]
+pub mod multicore;
+#[cfg(feature = "netlist")]
+pub mod netlist;
+#[cfg(feature = "alloc")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod pacing;
+#[cfg(feature = "alloc")]
+pub mod profile;
+#[cfg(feature = "alloc")]
+pub mod program;
+pub mod random;
 pub mod registers;
+#[cfg(feature = "std")]
+pub mod rtc;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod semihost;
+#[cfg(feature = "alloc")]
+pub mod smc;
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+#[cfg(feature = "alloc")]
+pub mod stack;
+pub mod state;
+#[cfg(feature = "alloc")]
+pub mod strict;
+#[cfg(feature = "alloc")]
+pub mod symbols;
+pub mod system;
+#[cfg(feature = "alloc")]
+pub mod taint;
+pub mod testsuite;
+pub mod tracefilter;
+#[cfg(feature = "std")]
+pub mod tracelog;
+#[cfg(feature = "alloc")]
+pub mod tracepoint;
+pub mod tstate;
+pub mod via;
+#[cfg(feature = "alloc")]
+pub mod vice;
+#[cfg(feature = "alloc")]
+pub mod waitstate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// Trait for 6502 variant. This is the mechanism allowing the different 6502-like CPUs to be
 /// emulated. It allows a struct to decode an opcode into its instruction and addressing mode.
 pub trait Variant {
+    /// What every opcode decodes to, indexed by opcode. The CPU builds its
+    /// dispatch table from this at compile time.
+    const OPCODES: [Option<(
+        crate::instruction::Instruction,
+        crate::instruction::AddressingMode,
+    )>; 256];
+
+    #[must_use]
     fn decode(
         opcode: u8,
     ) -> Option<(
         crate::instruction::Instruction,
         crate::instruction::AddressingMode,
-    )>;
+    )> {
+        Self::OPCODES[usize::from(opcode)]
+    }
+
+    /// Returns the number of cycles `opcode` takes, not counting page-crossing
+    /// or branch penalties. Defaults to the NMOS 6502 timings.
+    #[must_use]
+    fn cycles(opcode: u8) -> u8 {
+        crate::instruction::NMOS6502_CYCLES[usize::from(opcode)]
+    }
+
+    /// Whether ADC and SBC take an extra cycle in decimal mode, as on the
+    /// 65C02.
+    #[must_use]
+    fn decimal_extra_cycle() -> bool {
+        false
+    }
+
+    /// `ADC` on this variant, as [`alu::adc`] computes it. Defaults to the
+    /// NMOS 6502, whose decimal-mode N, V and Z come from intermediate
+    /// values.
+    #[must_use]
+    fn adc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, crate::registers::Status) {
+        crate::alu::adc(a, b, carry, decimal)
+    }
+
+    /// `SBC` on this variant, as [`alu::sbc`] computes it. Defaults to the
+    /// NMOS 6502.
+    #[must_use]
+    fn sbc(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, crate::registers::Status) {
+        crate::alu::sbc(a, b, carry, decimal)
+    }
+
+    /// Whether `instruction` in `mode` takes an extra cycle when indexing
+    /// carries into the high byte of the address. Defaults to the NMOS rule:
+    /// instructions that only read their operand pay for the crossing,
+    /// while writes and read-modify-writes always take the extra cycle.
+    #[must_use]
+    fn page_cross_penalty(
+        instruction: crate::instruction::Instruction,
+        mode: crate::instruction::AddressingMode,
+    ) -> bool {
+        crate::disasm::classify(instruction, mode) == crate::disasm::Class::Read
+    }
+
+    /// Whether a reset clears the decimal flag. The NMOS parts leave it as
+    /// it was; the 65C02 clears it.
+    #[must_use]
+    fn clears_decimal_on_reset() -> bool {
+        false
+    }
+
+    /// Whether `opcode` is an undocumented one that [`Variant::decode`]
+    /// decodes anyway. A [`Machine`](crate::machine::Machine) only executes
+    /// these under [`IllegalOpcodePolicy::Accurate`], and the assembler
+    /// only picks them when no documented opcode will do. Defaults to none.
+    ///
+    /// [`IllegalOpcodePolicy::Accurate`]: crate::machine::IllegalOpcodePolicy::Accurate
+    #[must_use]
+    fn is_undocumented(opcode: u8) -> bool {
+        let _ = opcode;
+        false
+    }
 }
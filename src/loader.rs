@@ -0,0 +1,458 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Getting programs into memory from the usual file formats.
+//!
+//! A [`Loader`] writes images to a [`Bus`] and keeps track of what it has
+//! loaded, so that an image running past `$FFFF` or over an earlier one is
+//! reported as a [`LoadError`] rather than silently wrapping around or
+//! overwriting code. It reads:
+//!
+//! - raw binaries, at an address given by the caller;
+//! - Commodore PRG files, which start with their two-byte load address;
+//! - Intel HEX, as written by most cross-assemblers;
+//! - the PRG ROM of an iNES cartridge image with no mapper. [`Ines`] takes
+//!   the file apart for the bank-switching mappers in [`crate::mapper`].
+//!
+//! # Examples
+//!
+//! ```
+//! use mos6502::loader::{LoadError, Loader};
+//! use mos6502::memory::{Bus, Memory};
+//!
+//! let mut memory = Memory::new();
+//! let mut loader = Loader::new(&mut memory);
+//! // A PRG file loading LDA #$01; RTS at $C000.
+//! assert_eq!(loader.prg(&[0x00, 0xc0, 0xa9, 0x01, 0x60]), Ok(0xc000));
+//! assert_eq!(
+//!     loader.bin(&[0xea], 0xc002),
+//!     Err(LoadError::Overlap { address: 0xc002 })
+//! );
+//! assert_eq!(memory.get_byte(0xc001), 0x01);
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::memory::Bus;
+
+/// Why an image couldn't be loaded. Nothing is written when loading fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// `len` bytes at `address` run past `$FFFF`.
+    Overflow { address: u16, len: usize },
+    /// The byte at `address` was already loaded.
+    Overlap { address: u16 },
+    /// The file ends before its header says it should.
+    Truncated,
+    /// The file doesn't start with the iNES signature.
+    NotInes,
+    /// The iNES image needs a mapper; see [`Ines::mapper`].
+    UnsupportedMapper(u8),
+    /// A malformed Intel HEX record, on the given one-based line.
+    Hex { line: usize, message: &'static str },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Overflow { address, len } => {
+                write!(f, "{len} bytes at ${address:04X} run past $FFFF")
+            }
+            LoadError::Overlap { address } => {
+                write!(f, "${address:04X} is already loaded")
+            }
+            LoadError::Truncated => f.write_str("file is truncated"),
+            LoadError::NotInes => f.write_str("not an iNES image"),
+            LoadError::UnsupportedMapper(mapper) => {
+                write!(f, "iNES mapper {mapper} is not supported")
+            }
+            LoadError::Hex { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
+
+/// An iNES cartridge image, taken apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ines<'a> {
+    /// The iNES mapper number; 0 is NROM, with no bank switching.
+    pub mapper: u8,
+    /// Program ROM, a multiple of 16 KB.
+    pub prg_rom: &'a [u8],
+    /// Character ROM for the PPU, a multiple of 8 KB.
+    pub chr_rom: &'a [u8],
+    /// 512 bytes the cartridge loads at `$7000`, if it has them.
+    pub trainer: Option<&'a [u8]>,
+    /// Whether the nametables mirror vertically rather than horizontally.
+    pub vertical_mirroring: bool,
+    /// Whether the cartridge has battery-backed PRG RAM.
+    pub battery: bool,
+}
+
+impl<'a> Ines<'a> {
+    const HEADER_LEN: usize = 16;
+    const TRAINER_LEN: usize = 512;
+    const CHR_BANK_SIZE: usize = 0x2000;
+
+    /// Parses the header of `file` and splits off its ROMs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::NotInes`] if the signature is missing and
+    /// [`LoadError::Truncated`] if the file is shorter than the ROMs its
+    /// header declares.
+    pub fn parse(file: &'a [u8]) -> Result<Ines<'a>, LoadError> {
+        let header = file.get(..Ines::HEADER_LEN).ok_or(LoadError::Truncated)?;
+        if header[..4] != *b"NES\x1a" {
+            return Err(LoadError::NotInes);
+        }
+        let flags = header[6];
+        let prg_len = usize::from(header[4]) * crate::mapper::PRG_BANK_SIZE;
+        let chr_len = usize::from(header[5]) * Ines::CHR_BANK_SIZE;
+        let trainer_len = if flags & 0x04 != 0 {
+            Ines::TRAINER_LEN
+        } else {
+            0
+        };
+
+        let rest = &file[Ines::HEADER_LEN..];
+        let trainer = rest.get(..trainer_len).ok_or(LoadError::Truncated)?;
+        let rest = &rest[trainer_len..];
+        let prg_rom = rest.get(..prg_len).ok_or(LoadError::Truncated)?;
+        let chr_rom = rest
+            .get(prg_len..prg_len + chr_len)
+            .ok_or(LoadError::Truncated)?;
+        Ok(Ines {
+            mapper: (flags >> 4) | (header[7] & 0xf0),
+            prg_rom,
+            chr_rom,
+            trainer: (trainer_len != 0).then_some(trainer),
+            vertical_mirroring: flags & 0x01 != 0,
+            battery: flags & 0x02 != 0,
+        })
+    }
+}
+
+/// Loads images into a bus, refusing any that overflow memory or overlap
+/// one loaded before.
+#[derive(Debug)]
+pub struct Loader<'a, B: ?Sized> {
+    bus: &'a mut B,
+    /// What has been loaded, as `(address, len)`.
+    regions: Vec<(u16, usize)>,
+}
+
+impl<'a, B: Bus + ?Sized> Loader<'a, B> {
+    pub const fn new(bus: &'a mut B) -> Loader<'a, B> {
+        Loader {
+            bus,
+            regions: Vec::new(),
+        }
+    }
+
+    /// What has been loaded so far, as `(address, len)` in the order it
+    /// was loaded.
+    pub fn regions(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.regions.iter().copied()
+    }
+
+    /// Loads `bytes` at `address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::Overflow`] or [`LoadError::Overlap`].
+    pub fn bin(&mut self, bytes: &[u8], address: u16) -> Result<(), LoadError> {
+        self.load(&[(address, bytes)])
+    }
+
+    /// Loads a Commodore PRG file at the address in its first two bytes,
+    /// and returns that address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::Truncated`] if there is no load address, or
+    /// [`LoadError::Overflow`] or [`LoadError::Overlap`].
+    pub fn prg(&mut self, file: &[u8]) -> Result<u16, LoadError> {
+        let [lo, hi, bytes @ ..] = file else {
+            return Err(LoadError::Truncated);
+        };
+        let address = u16::from_le_bytes([*lo, *hi]);
+        self.bin(bytes, address)?;
+        Ok(address)
+    }
+
+    /// Loads an Intel HEX file, and returns the start address from its
+    /// start address record, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::Hex`] for a malformed record, including one
+    /// with a bad checksum, or [`LoadError::Overflow`] or
+    /// [`LoadError::Overlap`].
+    pub fn hex(&mut self, text: &str) -> Result<Option<u16>, LoadError> {
+        let (records, start) = parse_hex(text)?;
+        let chunks: Vec<(u16, &[u8])> = records
+            .iter()
+            .map(|(address, bytes)| (*address, bytes.as_slice()))
+            .collect();
+        self.load(&chunks)?;
+        Ok(start)
+    }
+
+    /// Loads the PRG ROM of an iNES image with no mapper at `$8000`. A
+    /// single 16 KB bank is mirrored at `$C000`, as on the cartridge.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::UnsupportedMapper`] for any mapper but NROM,
+    /// whose banks have to be switched by a [`crate::mapper::Mapper`]
+    /// instead, or [`LoadError::Overlap`].
+    pub fn ines(&mut self, rom: &Ines) -> Result<(), LoadError> {
+        const PRG_ROM_START: u16 = 0x8000;
+        const PRG_ROM_UPPER_HALF: u16 = 0xc000;
+
+        if rom.mapper != 0 || rom.prg_rom.len() > 2 * crate::mapper::PRG_BANK_SIZE {
+            return Err(LoadError::UnsupportedMapper(rom.mapper));
+        }
+        if rom.prg_rom.len() == crate::mapper::PRG_BANK_SIZE {
+            self.load(&[
+                (PRG_ROM_START, rom.prg_rom),
+                (PRG_ROM_UPPER_HALF, rom.prg_rom),
+            ])
+        } else {
+            self.load(&[(PRG_ROM_START, rom.prg_rom)])
+        }
+    }
+
+    /// Claims every chunk before writing any, so that a failed load
+    /// leaves memory alone.
+    fn load(&mut self, chunks: &[(u16, &[u8])]) -> Result<(), LoadError> {
+        let before = self.regions.len();
+        for &(address, bytes) in chunks {
+            if let Err(error) = self.claim(address, bytes.len()) {
+                self.regions.truncate(before);
+                return Err(error);
+            }
+        }
+        for &(address, bytes) in chunks {
+            self.bus.set_bytes(address, bytes);
+        }
+        Ok(())
+    }
+
+    fn claim(&mut self, address: u16, len: usize) -> Result<(), LoadError> {
+        let start = usize::from(address);
+        if start + len > 0x1_0000 {
+            return Err(LoadError::Overflow { address, len });
+        }
+        let overlap = self
+            .regions
+            .iter()
+            .filter(|&&(other, other_len)| {
+                let other = usize::from(other);
+                start < other + other_len && other < start + len
+            })
+            .map(|&(other, _)| other.max(address))
+            .min();
+        if let Some(address) = overlap {
+            return Err(LoadError::Overlap { address });
+        }
+        if len > 0 {
+            self.regions.push((address, len));
+        }
+        Ok(())
+    }
+}
+
+/// The data records of an Intel HEX file, with the start address if given.
+type HexRecords = (Vec<(u16, Vec<u8>)>, Option<u16>);
+
+fn parse_hex(text: &str) -> Result<HexRecords, LoadError> {
+    let mut records = Vec::new();
+    let mut start = None;
+    // Added to record addresses by the extended address records.
+    let mut base = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let error = |message| LoadError::Hex {
+            line: index + 1,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line.strip_prefix(':').ok_or(error("expected ':'"))?;
+        if digits.len() % 2 != 0 {
+            return Err(error("odd number of digits"));
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| {
+                digits
+                    .get(i..i + 2)
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(error("invalid hex digit"))?;
+        let [len, hi, lo, kind, rest @ ..] = bytes.as_slice() else {
+            return Err(error("record too short"));
+        };
+        let data = rest
+            .get(..usize::from(*len))
+            .filter(|_| rest.len() == usize::from(*len) + 1)
+            .ok_or(error("length doesn't match the record"))?;
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("bad checksum"));
+        }
+
+        let offset = u32::from(u16::from_be_bytes([*hi, *lo]));
+        let value = |data: &[u8]| {
+            data.iter()
+                .fold(0u32, |value, byte| value << 8 | u32::from(*byte))
+        };
+        match (kind, data.len()) {
+            (0x00, _) => {
+                let address =
+                    u16::try_from(base + offset).map_err(|_| error("address past $FFFF"))?;
+                records.push((address, data.to_vec()));
+            }
+            (0x01, _) => break,
+            (0x02, 2) => base = value(data) << 4,
+            (0x04, 2) => base = value(data) << 16,
+            // Start segment address, CS:IP, and start linear address.
+            (0x03 | 0x05, 4) => {
+                let address = if *kind == 0x03 {
+                    (value(&data[..2]) << 4) + value(&data[2..])
+                } else {
+                    value(data)
+                };
+                let address =
+                    u16::try_from(address).map_err(|_| error("start address past $FFFF"))?;
+                start = Some(address);
+            }
+            (0x02..=0x05, _) => return Err(error("wrong length for record type")),
+            _ => return Err(error("unknown record type")),
+        }
+    }
+    Ok((records, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn intel_hex_loads_records_and_start() {
+        let text = "\
+:0300300002337A1E
+:02C00000A90194
+:040000050000C00037
+:00000001FF
+";
+        let mut memory = Memory::new();
+        let mut loader = Loader::new(&mut memory);
+        assert_eq!(loader.hex(text), Ok(Some(0xc000)));
+        assert_eq!(
+            loader.regions().collect::<Vec<_>>(),
+            [(0x0030, 3), (0xc000, 2)]
+        );
+        assert_eq!(memory.get_byte(0x0031), 0x33);
+        assert_eq!(memory.get_byte(0xc001), 0x01);
+    }
+
+    #[test]
+    fn intel_hex_reports_bad_records() {
+        let mut memory = Memory::new();
+        let mut loader = Loader::new(&mut memory);
+        assert_eq!(
+            loader.hex("\n:02C00000A90195"),
+            Err(LoadError::Hex {
+                line: 2,
+                message: "bad checksum"
+            })
+        );
+        // An extended linear address puts the data above 64 KB.
+        assert_eq!(
+            loader.hex(":020000040001F9\n:01000000EA15"),
+            Err(LoadError::Hex {
+                line: 2,
+                message: "address past $FFFF"
+            })
+        );
+        assert_eq!(loader.regions().count(), 0);
+    }
+
+    #[test]
+    fn loads_are_checked_against_each_other() {
+        let mut memory = Memory::new();
+        let mut loader = Loader::new(&mut memory);
+        assert_eq!(
+            loader.bin(&[0; 3], 0xfffe),
+            Err(LoadError::Overflow {
+                address: 0xfffe,
+                len: 3
+            })
+        );
+        loader.bin(&[1; 4], 0x1000).unwrap();
+        assert_eq!(
+            loader.bin(&[2; 4], 0x0ffe),
+            Err(LoadError::Overlap { address: 0x1000 })
+        );
+        assert_eq!(loader.prg(&[0x00]), Err(LoadError::Truncated));
+        assert_eq!(memory.get_byte(0x0ffe), 0);
+    }
+
+    #[test]
+    fn ines_mirrors_a_single_bank() {
+        let mut file = b"NES\x1a\x01\x01\x00\x00".to_vec();
+        file.resize(16, 0);
+        file.extend((0..0x4000).map(|i: u32| i.to_le_bytes()[1]));
+        file.resize(16 + 0x4000 + 0x2000, 0xcc);
+        let rom = Ines::parse(&file).unwrap();
+        assert_eq!((rom.mapper, rom.chr_rom.len()), (0, 0x2000));
+
+        let mut memory = Memory::new();
+        Loader::new(&mut memory).ines(&rom).unwrap();
+        assert_eq!(memory.get_byte(0x8100), 0x01);
+        assert_eq!(memory.get_byte(0xc100), 0x01);
+
+        assert_eq!(Ines::parse(&file[..100]), Err(LoadError::Truncated));
+        assert_eq!(
+            Ines::parse(b"NES\x00 and more bytes"),
+            Err(LoadError::NotInes)
+        );
+        file[6] = 0x10;
+        assert_eq!(
+            Loader::new(&mut memory).ines(&Ines::parse(&file).unwrap()),
+            Err(LoadError::UnsupportedMapper(1))
+        );
+    }
+}
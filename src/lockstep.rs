@@ -0,0 +1,300 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Running two machines side by side and finding where they disagree.
+//!
+//! [`Lockstep`] executes the same program on two [`Machine`]s one
+//! instruction at a time and compares their architectural state after each
+//! one, stopping at the first [`Divergence`]. Pairing a known-good variant
+//! with a new one, or the interpreter with another backend, pins a bug down
+//! to the exact instruction that introduced it.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::machine::{Machine, StopReason};
+use crate::memory::Bus;
+use crate::registers::Registers;
+use crate::Variant;
+
+/// Maximum number of differing bytes recorded in a [`Divergence`].
+const MAX_MEMORY_DIFFS: usize = 16;
+
+/// The state of one machine at a divergence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Side {
+    pub registers: Registers,
+    pub cycles: u64,
+    /// Why the machine stopped after the instruction, if it did.
+    pub stop: Option<StopReason>,
+}
+
+/// The first point at which two machines disagreed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of instructions both machines executed before this one.
+    pub instruction: u64,
+    /// Address of the instruction after which the states differ.
+    pub pc: u16,
+    pub left: Side,
+    pub right: Side,
+    /// Differing bytes as `(address, left, right)`, in address order. At
+    /// most the first 16 are recorded.
+    pub memory: Vec<(u16, u8, u8)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "divergence after instruction {} at ${:04X}:",
+            self.instruction, self.pc
+        )?;
+        for (name, side) in [("left", &self.left), ("right", &self.right)] {
+            let r = &side.registers;
+            write!(
+                f,
+                "  {name:<5} PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                r.program_counter,
+                r.accumulator,
+                r.index_x,
+                r.index_y,
+                r.status.to_byte(),
+                r.stack_pointer.0,
+                side.cycles
+            )?;
+            if let Some(stop) = side.stop {
+                write!(f, " ({stop})")?;
+            }
+            writeln!(f)?;
+        }
+        for (address, left, right) in &self.memory {
+            writeln!(f, "  ${address:04X}: ${left:02X} vs ${right:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Two machines executed in lockstep.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::{Nmos6502, Ricoh2a03};
+/// use mos6502::lockstep::Lockstep;
+/// use mos6502::machine::Machine;
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut memory = Memory::new();
+/// // SED; LDA #$09; CLC; ADC #$01; BRK
+/// memory.set_bytes(0x0000, &[0xf8, 0xa9, 0x09, 0x18, 0x69, 0x01, 0x00]);
+/// let mut lockstep = Lockstep::new(
+///     Machine::new(CPU::new(memory, Nmos6502)),
+///     Machine::new(CPU::new(memory, Ricoh2a03)),
+/// );
+///
+/// // The 2A03 has no decimal mode.
+/// let divergence = lockstep.run(Some(100)).unwrap_err();
+/// assert_eq!(divergence.pc, 0x0004);
+/// assert_eq!(divergence.left.registers.accumulator, 0x10);
+/// assert_eq!(divergence.right.registers.accumulator, 0x0a);
+/// ```
+pub struct Lockstep<MA: Bus, VA: Variant, MB: Bus, VB: Variant> {
+    pub left: Machine<MA, VA>,
+    pub right: Machine<MB, VB>,
+    compare_cycles: bool,
+    memory_interval: u64,
+    executed: u64,
+}
+
+impl<MA: Bus, VA: Variant, MB: Bus, VB: Variant> Lockstep<MA, VA, MB, VB> {
+    /// Pairs two machines, which should start out in the same state.
+    ///
+    /// By default registers are compared after every instruction and all
+    /// of memory as well, while cycle counts are not compared.
+    pub const fn new(left: Machine<MA, VA>, right: Machine<MB, VB>) -> Self {
+        Lockstep {
+            left,
+            right,
+            compare_cycles: false,
+            memory_interval: 1,
+            executed: 0,
+        }
+    }
+
+    /// Also treats differing cycle counts as a divergence.
+    pub const fn set_compare_cycles(&mut self, compare: bool) {
+        self.compare_cycles = compare;
+    }
+
+    /// Compares memory only after every `interval` instructions, which is
+    /// much faster for long runs. The divergence is then reported at the
+    /// first comparison that sees it rather than at the instruction that
+    /// caused it. An interval of zero never compares memory.
+    pub const fn set_memory_interval(&mut self, interval: u64) {
+        self.memory_interval = interval;
+    }
+
+    /// Number of instructions executed by both machines so far.
+    #[must_use]
+    pub const fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    /// Executes one instruction on both machines and compares them.
+    ///
+    /// Returns why both machines stopped if they did so identically.
+    ///
+    /// # Errors
+    ///
+    /// Returns the divergence if the machines disagree afterwards.
+    pub fn step(&mut self) -> Result<Option<StopReason>, Divergence> {
+        let pc = self.left.cpu.registers.program_counter;
+        let left_stop = self.left.step();
+        let right_stop = self.right.step();
+        let instruction = self.executed;
+        self.executed += 1;
+
+        let check_memory =
+            self.memory_interval != 0 && self.executed.is_multiple_of(self.memory_interval);
+        let memory = if check_memory {
+            self.memory_diffs()
+        } else {
+            Vec::new()
+        };
+        let (left, right) = (&self.left.cpu, &self.right.cpu);
+        if left_stop == right_stop
+            && left.registers == right.registers
+            && (!self.compare_cycles || left.cycles == right.cycles)
+            && memory.is_empty()
+        {
+            return Ok(left_stop);
+        }
+
+        Err(Divergence {
+            instruction,
+            pc,
+            left: Side {
+                registers: left.registers,
+                cycles: left.cycles,
+                stop: left_stop,
+            },
+            right: Side {
+                registers: right.registers,
+                cycles: right.cycles,
+                stop: right_stop,
+            },
+            memory,
+        })
+    }
+
+    /// Executes instructions until the machines diverge, both stop for the
+    /// same reason, or `max_instructions` have run. Returns the number of
+    /// instructions executed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first divergence.
+    pub fn run(&mut self, max_instructions: Option<u64>) -> Result<u64, Divergence> {
+        let start = self.executed;
+        while max_instructions.is_none_or(|max| self.executed - start < max) {
+            if self.step()?.is_some() {
+                break;
+            }
+        }
+        Ok(self.executed - start)
+    }
+
+    fn memory_diffs(&self) -> Vec<(u16, u8, u8)> {
+        (0..=u16::MAX)
+            .filter_map(|address| {
+                let left = self.left.cpu.memory.get_byte(address);
+                let right = self.right.cpu.memory.get_byte(address);
+                (left != right).then_some((address, left, right))
+            })
+            .take(MAX_MEMORY_DIFFS)
+            .collect()
+    }
+}
+
+impl<MA: Bus, VA: Variant, MB: Bus, VB: Variant> fmt::Debug for Lockstep<MA, VA, MB, VB> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lockstep")
+            .field("left", &self.left)
+            .field("right", &self.right)
+            .field("compare_cycles", &self.compare_cycles)
+            .field("memory_interval", &self.memory_interval)
+            .field("executed", &self.executed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::{Cmos6502, Nmos6502};
+    use crate::memory::Memory;
+
+    #[test]
+    fn finds_a_memory_divergence() {
+        let mut memory = Memory::new();
+        // LDA #$FF; LDX #$02; loop: DEX; BNE loop; STA $10; JMP ($00FF)
+        memory.set_bytes(
+            0x0200,
+            &[
+                0xa9, 0xff, 0xa2, 0x02, 0xca, 0xd0, 0xfd, 0x85, 0x10, 0x6c, 0xff, 0x00,
+            ],
+        );
+        memory.set_bytes(0x00ff, &[0x00, 0x03]);
+        memory.set_byte(0x0000, 0x04);
+        let machine = |memory| {
+            let mut cpu = CPU::new(memory, Nmos6502);
+            cpu.registers.program_counter = 0x0200;
+            Machine::new(cpu)
+        };
+        let mut right = machine(memory);
+        right.cpu.memory.set_byte(0x0010, 0x01);
+        let mut lockstep = Lockstep::new(machine(memory), right);
+
+        let divergence = lockstep.run(None).unwrap_err();
+        assert_eq!(divergence.instruction, 0);
+        assert_eq!(divergence.memory, [(0x0010, 0x00, 0x01)]);
+
+        // The NMOS indirect JMP bug: $00FF/$0000 versus $00FF/$0100.
+        let mut left = machine(memory);
+        left.cpu.registers.program_counter = 0x0209;
+        let mut right = Machine::new(CPU::new(memory, Cmos6502));
+        right.cpu.registers.program_counter = 0x0209;
+        let mut lockstep = Lockstep::new(left, right);
+        let divergence = lockstep.step().unwrap_err();
+        assert_eq!(divergence.pc, 0x0209);
+        assert_eq!(divergence.left.registers.program_counter, 0x0400);
+        assert_eq!(divergence.right.registers.program_counter, 0x0300);
+    }
+}
@@ -0,0 +1,2629 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A CPU together with the state needed to debug it.
+//!
+//! [`Machine`] wraps a [`CPU`] and adds breakpoints, watchpoints,
+//! tracepoints and controlled execution that reports why it stopped, which
+//! is what monitors and debugger front-ends are built on.
+
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::asm::{assemble_for, AsmError, Assembly};
+use crate::cpu::CPU;
+use crate::diff::StateDiff;
+use crate::disasm::{Disassembler, EffectiveAddress, OwnedLine};
+#[cfg(feature = "std")]
+use crate::execlog::Record;
+use crate::instruction::{Instruction, OpInput};
+use crate::interrupt::Request;
+use crate::latency::LatencyStats;
+use crate::loader::{LoadError, Loader};
+use crate::memory::{Access, Bus, BusError};
+use crate::observer::{InstructionEvent, Observer};
+#[cfg(feature = "std")]
+use crate::pacing::Pacer;
+use crate::registers::Flags;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::snapshot::{Snapshot, SnapshotRing};
+use crate::stack::StackView;
+use crate::state::{CpuState, HexDump};
+use crate::system::Resettable;
+use crate::tracepoint::{TraceAction, Tracepoint, TracepointId};
+use crate::Variant;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Why execution stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program counter reached a breakpoint. The instruction there has
+    /// not executed yet.
+    Breakpoint(u16),
+    /// The opcode at `pc` is not valid for the variant. It was not executed.
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// The instruction or cycle budget of `count` ran out.
+    LimitReached { limit: Limit, count: u64 },
+    /// The cycle set with [`Machine::break_at_cycle`] was reached; the
+    /// counter now reads this value.
+    CycleReached(u64),
+    /// The number of instructions set with
+    /// [`Machine::break_after_instructions`] has executed.
+    InstructionsExecuted(u64),
+    /// An interrupt was taken, with [`Machine::break_on_interrupts`] set.
+    /// `from` is the address of the `BRK`, or of the instruction an IRQ
+    /// returns to. The handler has not executed yet.
+    Interrupt {
+        kind: Interrupt,
+        vector: u16,
+        from: u16,
+    },
+    /// `RTI` returned to `to`, with [`Machine::break_on_rti`] set.
+    ReturnFromInterrupt { to: u16 },
+    /// The stack pointer left the range set with [`Machine::break_on_stack`]
+    /// and is now `sp`.
+    StackOutOfRange { sp: u8 },
+    /// The opcode about to be fetched from `pc` is one set with
+    /// [`Machine::break_on_opcode`]. It has not executed yet.
+    OpcodeFetch { pc: u16, opcode: u8 },
+    /// The instruction at `pc` made a bus access that failed. It completed,
+    /// with the failed access reading `$FF` or writing nothing.
+    BusError { pc: u16, error: BusError },
+    /// The instruction at `pc` made an access to `address` that a
+    /// watchpoint covers. It completed; `value` is the byte read or written.
+    Watchpoint {
+        pc: u16,
+        access: Access,
+        address: u16,
+        value: u8,
+    },
+    /// The condition passed to [`Machine::run_until`] held.
+    ConditionMet,
+    /// An execution hook returned [`HookAction::Stop`] for the instruction
+    /// at `pc`. It executed only if the hook ran after it.
+    Hook { pc: u16 },
+    /// A [`PauseToken`] asked the machine to pause.
+    Paused,
+}
+
+/// Pauses a [`Machine`] running on another thread.
+///
+/// [`Machine::run`] checks the token before every instruction and returns
+/// [`StopReason::Paused`] once it is paused, leaving the machine at an
+/// instruction boundary where its state can be inspected. It keeps
+/// returning immediately until the token is resumed.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+/// use std::time::Duration;
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::machine::{Limit, Machine, StopReason};
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+/// // loop: JMP loop
+/// machine.cpu.memory.set_bytes(0x0000, &[0x4c, 0x00, 0x00]);
+/// let token = machine.pause_token();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(10));
+///     token.pause();
+/// });
+/// assert_eq!(machine.run(None).stop, StopReason::Paused);
+/// machine.resume();
+/// let limit = StopReason::LimitReached {
+///     limit: Limit::Instructions,
+///     count: 1,
+/// };
+/// assert_eq!(machine.run(Some(1)).stop, limit);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PauseToken(Arc<AtomicBool>);
+
+impl PauseToken {
+    #[must_use]
+    pub fn new() -> PauseToken {
+        PauseToken::default()
+    }
+
+    /// Asks the machine to stop before its next instruction.
+    pub fn pause(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Lets the machine run again.
+    pub fn resume(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Budgets that stop every [`Machine::run`] with
+/// [`StopReason::LimitReached`] instead of letting a runaway program loop
+/// forever, e.g. in a test harness. Each run is measured from its start.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Watchdog {
+    pub instructions: Option<u64>,
+    pub cycles: Option<u64>,
+}
+
+impl Watchdog {
+    /// The tighter of each budget in `self` and `other`.
+    fn min(self, other: Watchdog) -> Watchdog {
+        let min = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Watchdog {
+            instructions: min(self.instructions, other.instructions),
+            cycles: min(self.cycles, other.cycles),
+        }
+    }
+}
+
+/// Which accesses a watchpoint stops on; see [`Machine::add_watchpoint`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    const fn covers(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (Watch::ReadWrite, _) | (Watch::Read, Access::Read) | (Watch::Write, Access::Write)
+        )
+    }
+}
+
+/// What [`Machine::step_instructions`] got done.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Steps {
+    /// Instructions executed.
+    pub executed: u64,
+    /// Why stepping stopped, if an instruction stopped it. This can be a
+    /// breakpoint at the next instruction even if all were executed.
+    pub stop: Option<StopReason>,
+}
+
+/// What a [`Machine`] does with an opcode its CPU variant doesn't define,
+/// or only decodes as an undocumented instruction (see
+/// [`Variant::is_undocumented`]).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IllegalOpcodePolicy {
+    /// Executes opcodes exactly as the variant decodes them, including any
+    /// undocumented ones it implements, such as `LAX` and `DCP` on the NMOS
+    /// 6502. One it has no behavior for stops as
+    /// with [`IllegalOpcodePolicy::Error`], since there is nothing accurate
+    /// to do.
+    Accurate,
+    /// Skips the opcode as a `NOP`, taking the bytes and cycles the NMOS
+    /// 6502 would for it.
+    TreatAsNop,
+    /// Hands the opcode to the hook set with
+    /// [`Machine::set_illegal_opcode_hook`], or stops as with
+    /// [`IllegalOpcodePolicy::Error`] if there is none.
+    Trap,
+    /// Stops with [`StopReason::IllegalOpcode`] without executing it.
+    #[default]
+    Error,
+}
+
+/// Emulates an illegal opcode for [`IllegalOpcodePolicy::Trap`]. It is
+/// called with the program counter still at the opcode, which is passed
+/// along, and must move it on itself. Returning a reason stops execution.
+pub type IllegalOpcodeHook<M, V> = Box<dyn FnMut(&mut CPU<M, V>, u8) -> Option<StopReason>>;
+
+/// What an execution hook wants done; see [`Machine::set_pre_exec_hook`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HookAction {
+    /// Carry on as usual.
+    #[default]
+    Continue,
+    /// Don't execute the instruction. The hook is expected to have done
+    /// its work and moved the program counter on itself. After the
+    /// instruction there is nothing left to skip, and this is the same as
+    /// [`HookAction::Continue`].
+    Skip,
+    /// Stop with [`StopReason::Hook`].
+    Stop,
+}
+
+/// Called before each instruction with the instruction about to run; see
+/// [`Machine::set_pre_exec_hook`].
+pub type PreExecHook<M, V> = Box<dyn FnMut(&mut CPU<M, V>, &OwnedLine) -> HookAction>;
+
+/// Called after each instruction with what it did; see
+/// [`Machine::set_post_exec_hook`].
+pub type PostExecHook<M, V> = Box<dyn FnMut(&mut CPU<M, V>, &InstructionEvent) -> HookAction>;
+
+/// Length of an instruction on the NMOS 6502, including undocumented ones,
+/// which follows from the low five bits of its opcode.
+const fn nmos_length(opcode: u8) -> u16 {
+    match opcode & 0x1f {
+        0x00 => match opcode {
+            0x20 => 3,
+            0x40 | 0x60 => 1,
+            _ => 2,
+        },
+        0x02 => {
+            if opcode >= 0x80 {
+                2
+            } else {
+                1
+            }
+        }
+        0x08 | 0x0a | 0x12 | 0x18 | 0x1a => 1,
+        0x0c..=0x0f | 0x19 | 0x1b..=0x1f => 3,
+        _ => 2,
+    }
+}
+
+/// A kind of interrupt.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Interrupt::Nmi => "NMI",
+            Interrupt::Irq => "IRQ",
+            Interrupt::Brk => "BRK",
+        })
+    }
+}
+
+/// A budget that stops [`Machine::run`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    Cycles,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Instructions => "instruction",
+            Limit::Cycles => "cycle",
+        })
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(pc) => write!(f, "breakpoint at ${pc:04X}"),
+            StopReason::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode ${opcode:02X} at ${pc:04X}")
+            }
+            StopReason::LimitReached { limit, count } => {
+                write!(f, "{limit} limit of {count} reached")
+            }
+            StopReason::CycleReached(cycle) => write!(f, "reached cycle {cycle}"),
+            StopReason::InstructionsExecuted(count) => {
+                write!(f, "executed {count} instructions")
+            }
+            StopReason::Interrupt { kind, vector, from } => {
+                write!(f, "{kind} at ${from:04X} through vector ${vector:04X}")
+            }
+            StopReason::ReturnFromInterrupt { to } => write!(f, "RTI to ${to:04X}"),
+            StopReason::StackOutOfRange { sp } => {
+                write!(f, "stack pointer out of range: ${sp:02X}")
+            }
+            StopReason::OpcodeFetch { pc, opcode } => {
+                write!(f, "fetch of opcode ${opcode:02X} at ${pc:04X}")
+            }
+            StopReason::BusError { pc, error } => {
+                write!(f, "{error} at ${pc:04X}")
+            }
+            StopReason::Watchpoint {
+                pc,
+                access,
+                address,
+                value,
+            } => {
+                let verb = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                };
+                write!(f, "{verb} of ${value:02X} at ${address:04X} from ${pc:04X}")
+            }
+            StopReason::ConditionMet => f.write_str("condition met"),
+            StopReason::Hook { pc } => write!(f, "stopped by hook at ${pc:04X}"),
+            StopReason::Paused => f.write_str("paused"),
+        }
+    }
+}
+
+/// Why [`Machine::seek_to_cycle`] failed.
+// An error is returned once per seek, so its size doesn't matter.
+#[allow(variant_size_differences)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SeekError {
+    /// Rewinding was not enabled with [`Machine::enable_rewind`].
+    Disabled,
+    /// The cycle is older than the oldest snapshot kept, taken at `oldest`.
+    TooOld { oldest: u64 },
+    /// Execution stopped before the cycle was reached. The machine is left
+    /// at the instruction that stopped it.
+    Stopped(StopReason),
+}
+
+impl fmt::Display for SeekError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeekError::Disabled => f.write_str("rewinding is not enabled"),
+            SeekError::TooOld { oldest } => {
+                write!(f, "the oldest snapshot kept is at cycle {oldest}")
+            }
+            SeekError::Stopped(reason) => write!(f, "stopped early: {reason}"),
+        }
+    }
+}
+
+/// What a call to [`Machine::run`] got done.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExecutionReport {
+    pub stop: StopReason,
+    /// Instructions executed.
+    pub instructions: u64,
+    /// Cycles executed, including those of interrupt sequences.
+    pub cycles: u64,
+    /// IRQs and NMIs taken.
+    pub interrupts: u64,
+    /// Program counter when execution stopped.
+    pub pc: u16,
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} after {} instructions, {} cycles and {} interrupts",
+            self.stop, self.instructions, self.cycles, self.interrupts
+        )
+    }
+}
+
+/// What [`Machine::run_for`] got done.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeSlice {
+    /// Cycles executed.
+    pub cycles: u64,
+    /// Why execution stopped before the slice was used up, if it did.
+    pub stop: Option<StopReason>,
+}
+
+/// The recent instructions kept for [`Machine::crash_report`], each
+/// followed by its bus accesses.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+struct History {
+    records: VecDeque<Record>,
+    instructions: usize,
+    capacity: usize,
+}
+
+#[cfg(feature = "std")]
+impl History {
+    fn record(&mut self, before: Record, accesses: &[(Access, u16, u8)]) {
+        if self.instructions == self.capacity {
+            self.records.pop_front();
+            while matches!(self.records.front(), Some(Record::Bus { .. })) {
+                self.records.pop_front();
+            }
+        } else {
+            self.instructions += 1;
+        }
+        self.records.push_back(before);
+        self.records.extend(
+            accesses
+                .iter()
+                .map(|&(access, address, value)| Record::Bus {
+                    access,
+                    address,
+                    value,
+                }),
+        );
+    }
+}
+
+/// A CPU with breakpoints.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::machine::{Machine, StopReason};
+/// use mos6502::memory::{Bus, Memory};
+///
+/// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+/// // LDX #$03; loop: DEX; BNE loop; NOP
+/// machine.cpu.memory.set_bytes(0x0000, &[0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xea]);
+/// machine.add_breakpoint(0x0005);
+/// assert_eq!(machine.run(None).stop, StopReason::Breakpoint(0x0005));
+/// assert_eq!(machine.cpu.registers.index_x, 0);
+/// ```
+pub struct Machine<M: Bus, V: Variant> {
+    pub cpu: CPU<M, V>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeMap<u16, Watch>,
+    opcode_breaks: BTreeSet<u8>,
+    #[cfg(feature = "scripting")]
+    conditions: BTreeMap<u16, Script>,
+    tracepoints: BTreeMap<TracepointId, Tracepoint>,
+    next_tracepoint: u32,
+    trace_output: Vec<String>,
+    /// Instructions executed through [`Machine::step`].
+    instructions: u64,
+    /// Cycles taken by the last [`Machine::step`].
+    step_cycles: u64,
+    cycle_break: Option<u64>,
+    /// The instruction count to break at, and the count when it was set.
+    instruction_break: Option<(u64, u64)>,
+    break_on_interrupts: bool,
+    break_on_rti: bool,
+    stack_range: Option<RangeInclusive<u8>>,
+    pause: PauseToken,
+    watchdog: Watchdog,
+    rewind: Option<SnapshotRing>,
+    /// Set while [`Machine::seek_to_cycle`] replays instructions, which
+    /// then only execute and leave tracing and stopping out.
+    replaying: bool,
+    observers: Vec<Box<dyn Observer>>,
+    devices: Vec<Box<dyn Resettable>>,
+    latency: LatencyStats,
+    illegal_opcodes: IllegalOpcodePolicy,
+    illegal_opcode_hook: Option<IllegalOpcodeHook<M, V>>,
+    pre_exec_hook: Option<PreExecHook<M, V>>,
+    post_exec_hook: Option<PostExecHook<M, V>>,
+    /// Clock that [`Machine::run_for`] keeps to.
+    #[cfg(feature = "std")]
+    pacer: Option<Pacer>,
+    /// Cycles that earlier slices ran past their budget, owed back by the
+    /// next one, for [`Machine::run_for`] and [`Machine::run_frame`].
+    overrun: u64,
+    #[cfg(feature = "std")]
+    history: Option<History>,
+}
+
+impl<M: Bus, V: Variant> Machine<M, V> {
+    /// A machine that stops at illegal opcodes, as with
+    /// [`IllegalOpcodePolicy::Error`].
+    pub fn new(cpu: CPU<M, V>) -> Machine<M, V> {
+        Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::Error)
+    }
+
+    /// A machine that handles illegal opcodes according to `policy`.
+    pub fn with_illegal_opcodes(cpu: CPU<M, V>, policy: IllegalOpcodePolicy) -> Machine<M, V> {
+        Machine {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            opcode_breaks: BTreeSet::new(),
+            #[cfg(feature = "scripting")]
+            conditions: BTreeMap::new(),
+            tracepoints: BTreeMap::new(),
+            next_tracepoint: 0,
+            trace_output: Vec::new(),
+            instructions: 0,
+            step_cycles: 0,
+            cycle_break: None,
+            instruction_break: None,
+            break_on_interrupts: false,
+            break_on_rti: false,
+            stack_range: None,
+            pause: PauseToken::new(),
+            watchdog: Watchdog::default(),
+            rewind: None,
+            replaying: false,
+            observers: Vec::new(),
+            devices: Vec::new(),
+            latency: LatencyStats::new(),
+            illegal_opcodes: policy,
+            illegal_opcode_hook: None,
+            pre_exec_hook: None,
+            post_exec_hook: None,
+            #[cfg(feature = "std")]
+            pacer: None,
+            overrun: 0,
+            #[cfg(feature = "std")]
+            history: None,
+        }
+    }
+
+    /// Stops execution whenever the program counter reaches `address`.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint, returning whether it was set.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        #[cfg(feature = "scripting")]
+        self.conditions.remove(&address);
+        self.breakpoints.remove(&address)
+    }
+
+    /// Sets a breakpoint at `address` that only stops when `condition`
+    /// holds. A condition that fails to run stops execution, and its error
+    /// is added to the [trace output](Machine::take_trace_output).
+    #[cfg(feature = "scripting")]
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: Script) {
+        self.breakpoints.insert(address);
+        self.conditions.insert(address, condition);
+    }
+
+    /// The condition of the breakpoint at `address`, if it has one.
+    #[cfg(feature = "scripting")]
+    #[must_use]
+    pub fn breakpoint_condition(&self, address: u16) -> Option<&Script> {
+        self.conditions.get(&address)
+    }
+
+    /// Adds the breakpoint if it is not set and removes it if it is. Returns
+    /// whether it is now set.
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.remove_breakpoint(address) {
+            false
+        } else {
+            self.add_breakpoint(address);
+            true
+        }
+    }
+
+    #[must_use]
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    /// Iterates over the breakpoints in address order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        #[cfg(feature = "scripting")]
+        self.conditions.clear();
+        self.breakpoints.clear();
+    }
+
+    /// Stops execution after an instruction that reads or writes `address`,
+    /// as `watch` says, replacing any watchpoint already there. Every bus
+    /// access is recorded while there are watchpoints, including the
+    /// operand fetches and dummy accesses observers see.
+    pub fn add_watchpoint(&mut self, address: u16, watch: Watch) {
+        self.watchpoints.insert(address, watch);
+        self.cpu.record_accesses(true);
+    }
+
+    /// Returns whether there was a watchpoint at `address`.
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        let removed = self.watchpoints.remove(&address).is_some();
+        self.update_recording();
+        removed
+    }
+
+    /// Iterates over the watchpoints in address order.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (u16, Watch)> + '_ {
+        self.watchpoints
+            .iter()
+            .map(|(&address, &watch)| (address, watch))
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+        self.update_recording();
+    }
+
+    /// Records bus accesses only while something needs them.
+    fn update_recording(&mut self) {
+        #[cfg(feature = "std")]
+        let history = self.history.is_some();
+        #[cfg(not(feature = "std"))]
+        let history = false;
+        self.cpu
+            .record_accesses(history || !self.observers.is_empty() || !self.watchpoints.is_empty());
+    }
+
+    /// The first recorded access a watchpoint covers.
+    fn watch_hit(&self, pc: u16) -> Option<StopReason> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        self.cpu
+            .recorded_accesses()
+            .iter()
+            .find(|(access, address, _)| {
+                self.watchpoints
+                    .get(address)
+                    .is_some_and(|watch| watch.covers(*access))
+            })
+            .map(|&(access, address, value)| StopReason::Watchpoint {
+                pc,
+                access,
+                address,
+                value,
+            })
+    }
+
+    #[must_use]
+    pub const fn illegal_opcode_policy(&self) -> IllegalOpcodePolicy {
+        self.illegal_opcodes
+    }
+
+    /// Sets the hook that emulates illegal opcodes under
+    /// [`IllegalOpcodePolicy::Trap`].
+    pub fn set_illegal_opcode_hook(
+        &mut self,
+        hook: impl FnMut(&mut CPU<M, V>, u8) -> Option<StopReason> + 'static,
+    ) {
+        self.illegal_opcode_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook called before every instruction the machine executes,
+    /// with the CPU and the instruction as disassembled from memory. The
+    /// hook can change the CPU's state, skip the instruction or stop the
+    /// machine, which is how ROM routines are trapped and emulated on the
+    /// host, e.g. a KERNAL load in a C64 emulator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::{HookAction, Machine, StopReason};
+    /// use mos6502::memory::{Bus, Memory};
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// // JSR $FFD2; BRK
+    /// machine.cpu.memory.set_bytes(0x0200, &[0x20, 0xd2, 0xff, 0x00]);
+    /// machine.cpu.registers.program_counter = 0x0200;
+    /// // Emulate the routine at $FFD2 as if it were an RTS.
+    /// machine.set_pre_exec_hook(|cpu, line| match line.address {
+    ///     0xffd2 => {
+    ///         let return_address = u16::from_le_bytes([cpu.pull_byte(), cpu.pull_byte()]);
+    ///         cpu.registers.program_counter = return_address.wrapping_add(1);
+    ///         HookAction::Skip
+    ///     }
+    ///     0x0203 => HookAction::Stop,
+    ///     _ => HookAction::Continue,
+    /// });
+    /// assert_eq!(machine.run(None).stop, StopReason::Hook { pc: 0x0203 });
+    /// ```
+    pub fn set_pre_exec_hook(
+        &mut self,
+        hook: impl FnMut(&mut CPU<M, V>, &OwnedLine) -> HookAction + 'static,
+    ) {
+        self.pre_exec_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook called after every instruction the machine executes,
+    /// with the CPU and the same event observers get. It can change the
+    /// CPU's state or stop the machine.
+    pub fn set_post_exec_hook(
+        &mut self,
+        hook: impl FnMut(&mut CPU<M, V>, &InstructionEvent) -> HookAction + 'static,
+    ) {
+        self.post_exec_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_exec_hooks(&mut self) {
+        self.pre_exec_hook = None;
+        self.post_exec_hook = None;
+    }
+
+    /// Stops whenever the next opcode fetched is `opcode`, wherever it is,
+    /// before it executes. Breaking on `$00` finds where a program first
+    /// runs into a `BRK`.
+    pub fn break_on_opcode(&mut self, opcode: u8) {
+        self.opcode_breaks.insert(opcode);
+    }
+
+    /// Removes a break set with [`Machine::break_on_opcode`], returning
+    /// whether it was set.
+    pub fn remove_opcode_break(&mut self, opcode: u8) -> bool {
+        self.opcode_breaks.remove(&opcode)
+    }
+
+    /// Iterates over the opcodes broken on, in order.
+    pub fn opcode_breaks(&self) -> impl Iterator<Item = u8> + '_ {
+        self.opcode_breaks.iter().copied()
+    }
+
+    /// Stops once the cycle counter reaches `cycle`, at the end of the
+    /// instruction that crosses it. Replaces any earlier cycle break, and is
+    /// cleared when hit.
+    pub const fn break_at_cycle(&mut self, cycle: u64) {
+        self.cycle_break = Some(cycle);
+    }
+
+    /// Stops after `count` more instructions have executed. Replaces any
+    /// earlier instruction break, and is cleared when hit.
+    pub const fn break_after_instructions(&mut self, count: u64) {
+        self.instruction_break = Some((self.instructions + count, count));
+    }
+
+    /// Clears the breaks set with [`Machine::break_at_cycle`] and
+    /// [`Machine::break_after_instructions`].
+    pub const fn clear_count_breaks(&mut self) {
+        self.cycle_break = None;
+        self.instruction_break = None;
+    }
+
+    /// Stops whenever an IRQ or NMI is taken or a `BRK` executes, before
+    /// the handler runs.
+    pub const fn break_on_interrupts(&mut self, enabled: bool) {
+        self.break_on_interrupts = enabled;
+    }
+
+    /// Stops whenever an `RTI` returns from an interrupt handler.
+    pub const fn break_on_rti(&mut self, enabled: bool) {
+        self.break_on_rti = enabled;
+    }
+
+    /// Stops when the stack pointer leaves `range`, such as when runaway
+    /// recursion or unbalanced pushes take it below a low-water mark. Only
+    /// leaving the range stops, so execution can continue outside it; the
+    /// break fires again once the stack pointer has come back in range and
+    /// leaves it again. `None` disables the check.
+    pub const fn break_on_stack(&mut self, range: Option<RangeInclusive<u8>>) {
+        self.stack_range = range;
+    }
+
+    /// A token that pauses [`Machine::run`] from another thread. All
+    /// tokens of a machine are clones of the same one.
+    #[must_use]
+    pub fn pause_token(&self) -> PauseToken {
+        self.pause.clone()
+    }
+
+    /// Clears a pause, so that the next [`Machine::run`] executes again.
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Sets the budgets that apply to every [`Machine::run`], on top of
+    /// the instruction limit passed to it.
+    pub const fn set_watchdog(&mut self, watchdog: Watchdog) {
+        self.watchdog = watchdog;
+    }
+
+    #[must_use]
+    pub const fn watchdog(&self) -> Watchdog {
+        self.watchdog
+    }
+
+    /// Number of instructions executed through the machine.
+    #[must_use]
+    pub const fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Cycles the last [`Machine::step`] took, including page-crossing and
+    /// branch penalties, wait states and any interrupt sequence taken after
+    /// the instruction. The running total is `cpu.cycles`.
+    #[must_use]
+    pub const fn step_cycles(&self) -> u64 {
+        self.step_cycles
+    }
+
+    /// The latencies of the interrupts taken so far, from the assertion of
+    /// the line to the handler.
+    #[must_use]
+    pub const fn interrupt_latency(&self) -> &LatencyStats {
+        &self.latency
+    }
+
+    /// Forgets the interrupt latencies measured so far.
+    pub fn clear_interrupt_latency(&mut self) {
+        self.latency.clear();
+    }
+
+    /// Performs `action` every time the instruction at `address` is about
+    /// to execute, without stopping.
+    pub fn add_tracepoint(&mut self, address: u16, action: TraceAction) -> TracepointId {
+        let id = TracepointId(self.next_tracepoint);
+        self.next_tracepoint += 1;
+        self.tracepoints
+            .insert(id, Tracepoint::new(address, action));
+        id
+    }
+
+    pub fn remove_tracepoint(&mut self, id: TracepointId) -> Option<Tracepoint> {
+        self.tracepoints.remove(&id)
+    }
+
+    #[must_use]
+    pub fn tracepoint(&self, id: TracepointId) -> Option<&Tracepoint> {
+        self.tracepoints.get(&id)
+    }
+
+    /// Iterates over the tracepoints in the order they were added.
+    pub fn tracepoints(&self) -> impl Iterator<Item = (TracepointId, &Tracepoint)> + '_ {
+        self.tracepoints
+            .iter()
+            .map(|(&id, tracepoint)| (id, tracepoint))
+    }
+
+    /// Removes and returns the messages output by tracepoints so far.
+    pub fn take_trace_output(&mut self) -> Vec<String> {
+        core::mem::take(&mut self.trace_output)
+    }
+
+    /// Registers an observer, which sees every event from here on after
+    /// those added before it. Bus accesses are only recorded while there
+    /// are observers.
+    ///
+    /// Only instructions executed through the machine are observed, not
+    /// those executed by calling [`CPU::single_step`] directly or replayed
+    /// by [`Machine::seek_to_cycle`].
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        if self.observers.is_empty() {
+            self.cpu.record_accesses(true);
+        }
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+        self.update_recording();
+    }
+
+    /// Resets a device along with the machine. Devices on the bus are reset
+    /// through [`Bus::reset`] without being added.
+    pub fn add_device(&mut self, device: impl Resettable + 'static) {
+        self.devices.push(Box::new(device));
+    }
+
+    /// Drives the CPU's IRQ input; `true` asserts it. The interrupt is
+    /// taken after each instruction while the line is asserted and the I
+    /// flag is clear, so a device holds it until the handler acknowledges
+    /// it.
+    pub const fn irq(&mut self, asserted: bool) {
+        self.cpu.set_irq(asserted);
+    }
+
+    /// Pulses the CPU's NMI input, requesting one NMI after the next
+    /// instruction whatever the I flag.
+    pub const fn nmi(&mut self) {
+        self.cpu.set_nmi(true);
+        self.cpu.set_nmi(false);
+    }
+
+    /// Resets the devices on the bus and those added with
+    /// [`Machine::add_device`], then the CPU, which starts at the reset
+    /// vector, and tells the observers. Memory keeps its contents.
+    pub fn reset(&mut self) {
+        // Devices first, so that a mapper has switched back to the banks
+        // the reset vector is read from.
+        self.cpu.memory.reset();
+        for device in &mut self.devices {
+            device.reset();
+        }
+        self.cpu.reset();
+        for observer in &mut self.observers {
+            observer.on_reset();
+        }
+    }
+
+    /// A stable hash of the machine's registers, flags and memory. See
+    /// [`CPU::state_hash`].
+    #[must_use]
+    pub fn state_hash(&self) -> u64 {
+        self.cpu.state_hash()
+    }
+
+    /// Pushes `value` on the stack without clocking the bus. See
+    /// [`CPU::push_byte`].
+    pub fn push_byte(&mut self, value: u8) {
+        self.cpu.push_byte(value);
+    }
+
+    /// Pulls a byte off the stack without clocking the bus. See
+    /// [`CPU::pull_byte`].
+    pub fn pull_byte(&mut self) -> u8 {
+        self.cpu.pull_byte()
+    }
+
+    /// Captures the CPU and memory state, such as for a save state. See
+    /// [`Snapshot::to_bytes`] for storing it.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(&self.cpu)
+    }
+
+    /// The registers and cycle counter, which unlike a
+    /// [`Machine::snapshot`] leaves memory out and is cheap enough to take
+    /// after every instruction. [`StateDiff::between_states`] compares two.
+    #[must_use]
+    pub const fn cpu_state(&self) -> CpuState {
+        CpuState::capture(&self.cpu)
+    }
+
+    /// `len` bytes of memory from `start` as a hex dump, to print or
+    /// `to_string`.
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::Machine;
+    /// use mos6502::memory::{Bus, Memory};
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// machine.cpu.memory.set_bytes(0x0400, b"HELLO");
+    /// assert_eq!(
+    ///     machine.hexdump(0x0400, 5).to_string(),
+    ///     "0400  48 45 4C 4C 4F                                    |HELLO|\n"
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn hexdump(&self, start: u16, len: usize) -> HexDump<'_, M> {
+        HexDump::new(&self.cpu.memory, start, len)
+    }
+
+    /// The differences between the states of machines `old` and `new`.
+    #[must_use]
+    pub fn diff(old: &Machine<M, V>, new: &Machine<M, V>) -> StateDiff {
+        let (old, new) = (&old.cpu, &new.cpu);
+        StateDiff::compute(
+            (&old.registers, old.cycles, |address| {
+                old.memory.get_byte(address)
+            }),
+            (&new.registers, new.cycles, |address| {
+                new.memory.get_byte(address)
+            }),
+        )
+    }
+
+    /// What changed since `snapshot` was taken, such as since the machine
+    /// last stopped.
+    #[must_use]
+    pub fn changes_since(&self, snapshot: &Snapshot) -> StateDiff {
+        let cpu = &self.cpu;
+        StateDiff::compute(
+            (&snapshot.registers, snapshot.cycles, |address| {
+                snapshot.memory()[usize::from(address)]
+            }),
+            (&cpu.registers, cpu.cycles, |address| {
+                cpu.memory.get_byte(address)
+            }),
+        )
+    }
+
+    /// Restores a state captured with [`Machine::snapshot`]. Breakpoints are
+    /// kept. From here the machine runs exactly as it did from the captured
+    /// state, as long as its devices are put back too.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        snapshot.restore(&mut self.cpu);
+    }
+
+    /// Snapshots the machine every `interval` cycles as it steps, keeping
+    /// the latest `capacity` snapshots, so that it can be rewound with
+    /// [`Machine::seek_to_cycle`]. The current state is captured at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` or `capacity` is zero.
+    pub fn enable_rewind(&mut self, interval: u64, capacity: usize) {
+        let mut ring = SnapshotRing::new(interval, capacity);
+        ring.record(&self.cpu);
+        self.rewind = Some(ring);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Keeps the last `instructions` instructions with their bus accesses,
+    /// and makes the `run` methods print a [`Machine::crash_report`] to
+    /// standard error if the emulator panics, before unwinding further.
+    ///
+    /// Internal bugs then come with the context needed to reproduce them,
+    /// at the cost of recording every bus access.
+    #[cfg(feature = "std")]
+    pub fn enable_crash_report(&mut self, instructions: usize) {
+        self.cpu.record_accesses(true);
+        self.history = Some(History {
+            records: VecDeque::new(),
+            instructions: 0,
+            capacity: instructions,
+        });
+    }
+
+    #[cfg(feature = "std")]
+    pub fn disable_crash_report(&mut self) {
+        self.history = None;
+        self.update_recording();
+    }
+
+    /// The recent instructions with their bus accesses, any accesses made
+    /// by an instruction that didn't finish, and the registers, as kept
+    /// since [`Machine::enable_crash_report`].
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn crash_report(&self) -> String {
+        use core::fmt::Write;
+
+        let mut report = String::from("recent instructions:\n");
+        for record in self.history.iter().flat_map(|history| &history.records) {
+            let _ = writeln!(report, "{record}");
+        }
+        let pending = self.cpu.recorded_accesses();
+        if !pending.is_empty() {
+            report.push_str("unfinished instruction:\n");
+            for &(access, address, value) in pending {
+                let record = Record::Bus {
+                    access,
+                    address,
+                    value,
+                };
+                let _ = writeln!(report, "{record}");
+            }
+        }
+        let _ = write!(report, "registers:\n{}", Record::instruction(&self.cpu));
+        report
+    }
+
+    /// Where the instruction at PC will read or write, and whether it crosses
+    /// a page getting there, without executing it. See
+    /// [`Line::effective_address`](crate::disasm::Line::effective_address).
+    #[must_use]
+    pub fn effective_address(&self) -> Option<EffectiveAddress> {
+        let cpu = &self.cpu;
+        let pc = cpu.registers.program_counter;
+        let bytes = [0, 1, 2].map(|offset| cpu.memory.get_byte(pc.wrapping_add(offset)));
+        Disassembler::<V>::for_variant(&bytes, pc)
+            .next()?
+            .effective_address(&cpu.registers, &cpu.memory)
+    }
+
+    /// Loads `bytes` into memory at `address`. A [`Loader`] reads the
+    /// other usual formats, and checks several loads against each other.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::Overflow`] if the bytes run past `$FFFF`.
+    pub fn load_bin(&mut self, bytes: &[u8], address: u16) -> Result<(), LoadError> {
+        Loader::new(&mut self.cpu.memory).bin(bytes, address)
+    }
+
+    /// Assembles `source` for this machine's variant and loads the result
+    /// at its origin, which is `$0000` unless the source sets one with
+    /// `.org`. The program counter is left alone; the returned
+    /// [`Assembly`] has the origin and the symbols to start from.
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::Machine;
+    /// use mos6502::memory::Memory;
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// let program = machine.load_asm("LDX #$08\nloop: DEX\nBNE loop").unwrap();
+    /// machine.cpu.registers.program_counter = program.origin;
+    /// machine.step_instructions(17);
+    /// assert_eq!(machine.cpu.registers.index_x, 0);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that cannot be assembled, with its line
+    /// number. Memory is untouched in that case.
+    pub fn load_asm(&mut self, source: &str) -> Result<Assembly, AsmError> {
+        let assembly = assemble_for::<V>(source)?;
+        self.cpu.memory.set_bytes(assembly.origin, &assembly.bytes);
+        Ok(assembly)
+    }
+
+    /// Disassembles `count` instructions from memory starting at `pc`, as a
+    /// debugger's code view would. Bytes that don't decode on this variant
+    /// come out as single-byte lines.
+    #[must_use]
+    pub fn disassemble_at(&self, pc: u16, count: usize) -> Vec<OwnedLine> {
+        // Enough bytes for `count` three-byte instructions, but no more than
+        // the whole address space.
+        let len = count.saturating_mul(3).min(0x1_0000);
+        let bytes: Vec<u8> = (0..=u16::MAX)
+            .take(len)
+            .map(|offset| self.cpu.memory.get_byte(pc.wrapping_add(offset)))
+            .collect();
+        Disassembler::<V>::for_variant(&bytes, pc)
+            .take(count)
+            .map(OwnedLine::from)
+            .collect()
+    }
+
+    /// A monitor-style dump of the machine, as for a crash report: the
+    /// registers and flags, the top of the stack, the zero page and the
+    /// code around PC.
+    #[must_use]
+    pub fn dump(&self) -> String {
+        use core::fmt::Write;
+
+        const STACK_ENTRIES: usize = 8;
+        const LINES_BEFORE: usize = 3;
+        const LINES_AFTER: u16 = 4;
+
+        let cpu = &self.cpu;
+        let registers = &cpu.registers;
+        let pc = registers.program_counter;
+        let mut dump = String::new();
+        let _ = writeln!(
+            dump,
+            "registers: PC:{pc:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} CYC:{}",
+            registers.accumulator,
+            registers.index_x,
+            registers.index_y,
+            registers.stack_pointer.0,
+            registers.status.to_byte(),
+            cpu.cycles
+        );
+        let _ = writeln!(dump, "flags:     {}", Flags(registers.status));
+
+        dump.push_str("stack:\n");
+        let stack = StackView::capture(cpu);
+        for entry in stack.entries.iter().take(STACK_ENTRIES) {
+            let _ = writeln!(dump, "  {:04X}: {}", entry.address, entry.kind);
+        }
+        if stack.entries.is_empty() {
+            dump.push_str("  empty\n");
+        }
+
+        dump.push_str("zero page:\n");
+        for row in (0..0x100).step_by(16) {
+            let _ = write!(dump, "  {row:04X}:");
+            for address in row..row + 16 {
+                let _ = write!(dump, " {:02X}", cpu.memory.get_byte(address));
+            }
+            dump.push('\n');
+        }
+
+        dump.push_str("code:\n");
+        let start = code_start::<V>(&cpu.memory, pc);
+        // Enough bytes for the lines after PC, however long they are.
+        let len = pc.wrapping_sub(start) + 3 * (LINES_AFTER + 1);
+        let bytes: Vec<u8> = (0..len)
+            .map(|offset| cpu.memory.get_byte(start.wrapping_add(offset)))
+            .collect();
+        let lines: Vec<_> = Disassembler::<V>::for_variant(&bytes, start).collect();
+        let at = lines
+            .iter()
+            .position(|line| line.address == pc)
+            .unwrap_or(0);
+        let first = at.saturating_sub(LINES_BEFORE);
+        for line in lines
+            .iter()
+            .skip(first)
+            .take(at - first + usize::from(LINES_AFTER) + 1)
+        {
+            let marker = if line.address == pc { '>' } else { ' ' };
+            let _ = write!(dump, "{marker} {:04X}:", line.address);
+            for byte in line.bytes {
+                let _ = write!(dump, " {byte:02X}");
+            }
+            let padding = 3 * (3 - line.bytes.len());
+            let _ = writeln!(dump, "{:padding$}  {line}", "");
+        }
+        dump
+    }
+
+    /// The snapshots kept for rewinding, if enabled.
+    #[must_use]
+    pub const fn rewind(&self) -> Option<&SnapshotRing> {
+        self.rewind.as_ref()
+    }
+
+    /// Moves the machine to the first instruction boundary at or after
+    /// `cycle`, which may lie in the past or the future, and returns the
+    /// cycle actually reached.
+    ///
+    /// Going back restores the newest snapshot taken at or before `cycle`
+    /// and executes forward from it as [`Machine::step`] does, with the
+    /// same execution hooks and illegal opcode policy, but ignoring
+    /// breakpoints, watchpoints, tracepoints and observers. Replay is only
+    /// faithful if the bus behaves the same way the second time, so devices
+    /// fed from outside (such as a host keyboard) may diverge. Snapshots
+    /// newer than the restored one are discarded and retaken on the way.
+    ///
+    /// # Errors
+    ///
+    /// Fails if rewinding is disabled, if `cycle` is older than every
+    /// snapshot kept, or if an illegal opcode the policy doesn't handle or
+    /// a hook stops execution first.
+    pub fn seek_to_cycle(&mut self, cycle: u64) -> Result<u64, SeekError> {
+        let ring = self.rewind.as_mut().ok_or(SeekError::Disabled)?;
+        let snapshot = ring.at_or_before(cycle).ok_or(SeekError::TooOld {
+            oldest: ring.iter().next().map_or(0, |oldest| oldest.cycles),
+        })?;
+
+        // Replaying from the present is cheaper than from a snapshot when
+        // the target is ahead of both.
+        if self.cpu.cycles > cycle || self.cpu.cycles < snapshot.cycles {
+            snapshot.restore(&mut self.cpu);
+            ring.truncate_after(snapshot.cycles);
+        }
+
+        self.replaying = true;
+        let mut stop = None;
+        while self.cpu.cycles < cycle && stop.is_none() {
+            stop = self.step();
+        }
+        self.replaying = false;
+        self.cpu.drain_accesses().for_each(drop);
+        stop.map_or(Ok(self.cpu.cycles), |reason| {
+            Err(SeekError::Stopped(reason))
+        })
+    }
+
+    /// Executes one instruction, regardless of any breakpoint at the
+    /// program counter.
+    ///
+    /// Returns the reason execution can't continue, if any: an illegal
+    /// opcode, or a breakpoint at the next instruction.
+    pub fn step(&mut self) -> Option<StopReason> {
+        let pc = self.cpu.registers.program_counter;
+        for tracepoint in self.tracepoints.values_mut() {
+            if tracepoint.address == pc && !self.replaying {
+                self.trace_output.extend(tracepoint.hit(&self.cpu));
+            }
+        }
+        if let Some(hook) = &mut self.pre_exec_hook {
+            let bytes = [0, 1, 2].map(|offset| self.cpu.memory.get_byte(pc.wrapping_add(offset)));
+            let line = Disassembler::<V>::for_variant(&bytes, pc).next()?;
+            match hook(&mut self.cpu, &line.into()) {
+                HookAction::Continue => {}
+                HookAction::Skip => return None,
+                HookAction::Stop => return Some(StopReason::Hook { pc }),
+            }
+        }
+        let opcode = self.cpu.memory.get_byte(pc);
+        let start = self.cpu.cycles;
+        let sp_before = self.cpu.registers.stack_pointer.0;
+        #[cfg(feature = "std")]
+        let before =
+            (self.history.is_some() && !self.replaying).then(|| Record::instruction(&self.cpu));
+        // Drop whatever was executed behind the machine's back.
+        self.cpu.drain_accesses().for_each(drop);
+        let decoded = if V::is_undocumented(opcode)
+            && self.illegal_opcodes != IllegalOpcodePolicy::Accurate
+        {
+            None
+        } else {
+            self.cpu.single_step()
+        };
+        let instruction = match decoded {
+            Some(instruction) => instruction,
+            None => match self.illegal_opcode(pc, opcode) {
+                Ok(()) => (Instruction::NOP, OpInput::UseImplied),
+                Err(reason) => return Some(reason),
+            },
+        };
+        #[cfg(feature = "std")]
+        if let (Some(history), Some(before)) = (&mut self.history, before) {
+            history.record(before, self.cpu.recorded_accesses());
+        }
+        self.step_cycles = self.cpu.cycles - start;
+        let watch_hit = self.watch_hit(pc);
+        if !self.replaying {
+            self.instructions += 1;
+            if let (Some(request), Some(cycles)) =
+                (self.cpu.interrupt_taken(), self.cpu.interrupt_latency())
+            {
+                self.latency.record(request, cycles);
+            }
+        }
+        let event = InstructionEvent {
+            pc,
+            opcode,
+            instruction,
+            cycles: self.cpu.cycles - start,
+            next_pc: self.cpu.registers.program_counter,
+        };
+        if self.observers.is_empty() || self.replaying {
+            self.cpu.drain_accesses().for_each(drop);
+        } else {
+            self.notify(event);
+        }
+        let hook_stop = self
+            .post_exec_hook
+            .as_mut()
+            .is_some_and(|hook| hook(&mut self.cpu, &event) == HookAction::Stop);
+        if let Some(ring) = &mut self.rewind {
+            ring.record(&self.cpu);
+        }
+        let bus_error = self.cpu.memory.take_error();
+        if self.replaying {
+            return None;
+        }
+        if let Some(error) = bus_error {
+            return Some(StopReason::BusError { pc, error });
+        }
+        if watch_hit.is_some() {
+            return watch_hit;
+        }
+        if hook_stop {
+            return Some(StopReason::Hook { pc });
+        }
+        if self
+            .cycle_break
+            .is_some_and(|cycle| self.cpu.cycles >= cycle)
+        {
+            self.cycle_break = None;
+            return Some(StopReason::CycleReached(self.cpu.cycles));
+        }
+        if let Some((target, count)) = self.instruction_break {
+            if self.instructions >= target {
+                self.instruction_break = None;
+                return Some(StopReason::InstructionsExecuted(count));
+            }
+        }
+        if let Some(reason) = self.interrupt_stop(pc, instruction.0) {
+            return Some(reason);
+        }
+        if let Some(range) = &self.stack_range {
+            let sp = self.cpu.registers.stack_pointer.0;
+            if range.contains(&sp_before) && !range.contains(&sp) {
+                return Some(StopReason::StackOutOfRange { sp });
+            }
+        }
+        let pc = self.cpu.registers.program_counter;
+        if self.breakpoints.contains(&pc) && self.condition_holds(pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        if self.opcode_breaks.is_empty() {
+            return None;
+        }
+        let opcode = self.cpu.memory.get_byte(pc);
+        self.opcode_breaks
+            .contains(&opcode)
+            .then_some(StopReason::OpcodeFetch { pc, opcode })
+    }
+
+    #[cfg(feature = "scripting")]
+    fn condition_holds(&mut self, pc: u16) -> bool {
+        let Some(condition) = self.conditions.get(&pc) else {
+            return true;
+        };
+        condition.test(&self.cpu).unwrap_or_else(|error| {
+            self.trace_output.push(alloc::format!(
+                "breakpoint condition at ${pc:04X} failed: {error}"
+            ));
+            true
+        })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    #[allow(clippy::unused_self)]
+    const fn condition_holds(&self, _pc: u16) -> bool {
+        true
+    }
+
+    /// The stop reason for an interrupt entered or left by `instruction`,
+    /// which was at `pc`, if breaking on it.
+    fn interrupt_stop(&self, pc: u16, instruction: Instruction) -> Option<StopReason> {
+        if let Some(request) = self
+            .cpu
+            .interrupt_taken()
+            .filter(|_| self.break_on_interrupts)
+        {
+            // The return address was pushed below the status.
+            let sp = self.cpu.registers.stack_pointer.to_u16();
+            let lo = self.cpu.memory.get_byte(0x0100 | (sp + 2) & 0xff);
+            let hi = self.cpu.memory.get_byte(0x0100 | (sp + 3) & 0xff);
+            return Some(StopReason::Interrupt {
+                kind: match request {
+                    Request::Nmi => Interrupt::Nmi,
+                    Request::Irq => Interrupt::Irq,
+                },
+                vector: request.vector(),
+                from: u16::from_le_bytes([lo, hi]),
+            });
+        }
+        match instruction {
+            Instruction::BRK | Instruction::BRKcld if self.break_on_interrupts => {
+                Some(StopReason::Interrupt {
+                    kind: Interrupt::Brk,
+                    vector: self.cpu.brk_vector(),
+                    from: pc,
+                })
+            }
+            Instruction::RTI if self.break_on_rti => Some(StopReason::ReturnFromInterrupt {
+                to: self.cpu.registers.program_counter,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Handles the illegal opcode at `pc` according to the policy. When it
+    /// is handled, it is reported as a `NOP`.
+    fn illegal_opcode(&mut self, pc: u16, opcode: u8) -> Result<(), StopReason> {
+        let stop = StopReason::IllegalOpcode { pc, opcode };
+        match self.illegal_opcodes {
+            IllegalOpcodePolicy::Accurate | IllegalOpcodePolicy::Error => Err(stop),
+            IllegalOpcodePolicy::TreatAsNop => {
+                self.cpu.registers.program_counter = pc.wrapping_add(nmos_length(opcode));
+                let start = self.cpu.cycles;
+                self.cpu.cycles += u64::from(V::cycles(opcode));
+                for cycle in start..self.cpu.cycles {
+                    self.cpu.memory.phi2(cycle);
+                }
+                Ok(())
+            }
+            IllegalOpcodePolicy::Trap => {
+                let hook = self.illegal_opcode_hook.as_mut().ok_or(stop)?;
+                hook(&mut self.cpu, opcode).map_or(Ok(()), Err)
+            }
+        }
+    }
+
+    fn notify(&mut self, event: InstructionEvent) {
+        for &cycle in self.cpu.bus_cycles() {
+            for observer in &mut self.observers {
+                observer.on_cycle(cycle);
+            }
+        }
+        for (access, address, value) in self.cpu.drain_accesses() {
+            for observer in &mut self.observers {
+                match access {
+                    Access::Read => observer.on_read(address, value),
+                    Access::Write => observer.on_write(address, value),
+                }
+            }
+        }
+        for observer in &mut self.observers {
+            observer.on_instruction(&event);
+        }
+        if self.cpu.interrupt_taken().is_some() {
+            for observer in &mut self.observers {
+                observer.on_irq(event.next_pc);
+            }
+        }
+    }
+
+    /// Executes `count` instructions, stopping early if one of them stops
+    /// execution as [`Machine::step`] would, and reports how many ran.
+    /// Unlike [`Machine::run`], neither the [`Watchdog`] nor a
+    /// [`PauseToken`] apply.
+    pub fn step_instructions(&mut self, count: u64) -> Steps {
+        let mut executed = 0;
+        while executed < count {
+            let stop = self.guarded_step();
+            if !matches!(stop, Some(StopReason::IllegalOpcode { .. })) {
+                executed += 1;
+            }
+            if stop.is_some() {
+                return Steps { executed, stop };
+            }
+        }
+        Steps {
+            executed,
+            stop: None,
+        }
+    }
+
+    /// Executes instructions until one of them stops execution, until
+    /// `max_instructions` have run or until the [`Watchdog`] runs out.
+    ///
+    /// The first instruction always executes, so that calling `run` again
+    /// after a breakpoint continues past it, unless the machine was paused
+    /// through its [`PauseToken`].
+    pub fn run(&mut self, max_instructions: Option<u64>) -> ExecutionReport {
+        let limits = Watchdog {
+            instructions: max_instructions,
+            cycles: None,
+        };
+        self.run_with(limits, |_| None)
+    }
+
+    /// Like [`Machine::run`], but also stops with
+    /// [`StopReason::ConditionMet`] once `condition` holds after an
+    /// instruction.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::{Machine, StopReason};
+    /// use mos6502::memory::{Bus, Memory};
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// // loop: INX; JMP loop
+    /// machine.cpu.memory.set_bytes(0x0000, &[0xe8, 0x4c, 0x00, 0x00]);
+    /// let report = machine.run_until(|machine| machine.cpu.registers.index_x == 10);
+    /// assert_eq!(report.stop, StopReason::ConditionMet);
+    /// assert_eq!(report.instructions, 19);
+    /// ```
+    pub fn run_until(&mut self, mut condition: impl FnMut(&Self) -> bool) -> ExecutionReport {
+        self.run_with(Watchdog::default(), |machine| {
+            condition(machine).then_some(StopReason::ConditionMet)
+        })
+    }
+
+    /// Like [`Machine::run`], but stops with [`StopReason::LimitReached`]
+    /// once at least `cycles` cycles have passed. The last instruction
+    /// always completes, so the run may go a few cycles over.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> ExecutionReport {
+        let limits = Watchdog {
+            instructions: None,
+            cycles: Some(cycles),
+        };
+        self.run_with(limits, |_| None)
+    }
+
+    /// Executes whole instructions for a budget of `cycles`, such as the
+    /// 29,780 cycles of an NES frame, for front-ends that run the CPU
+    /// between frames.
+    ///
+    /// The last instruction always completes, and the cycles it runs past
+    /// the budget are taken off the next call, so frame after frame the
+    /// machine keeps to the budget on average. The report's `cycles` are
+    /// those that actually ran.
+    ///
+    /// The run also stops early for anything that stops [`Machine::run`],
+    /// such as a breakpoint or, with [`Machine::break_on_interrupts`] set,
+    /// an NMI or IRQ being taken, which lets the front-end react to it
+    /// mid-frame. Nothing is owed back then: calling again with what is
+    /// left of the budget finishes the frame on time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::{Limit, Machine, StopReason};
+    /// use mos6502::memory::{Bus, Memory};
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// // loop: JMP loop
+    /// machine.cpu.memory.set_bytes(0x0000, &[0x4c, 0x00, 0x00]);
+    /// let limit = StopReason::LimitReached {
+    ///     limit: Limit::Cycles,
+    ///     count: 29_780,
+    /// };
+    /// assert_eq!(machine.run_frame(29_780).stop, limit);
+    /// // The 3-cycle JMPs overshoot the first frames, which the third one
+    /// // gives back.
+    /// let frames = [(); 2].map(|()| machine.run_frame(29_780).cycles);
+    /// assert_eq!(frames, [29_781, 29_778]);
+    /// assert_eq!(machine.cpu.cycles, 3 * 29_780);
+    /// ```
+    pub fn run_frame(&mut self, cycles: u64) -> ExecutionReport {
+        let owed = cycles.saturating_sub(self.overrun);
+        let report = self.run_with(
+            Watchdog {
+                instructions: None,
+                cycles: Some(owed),
+            },
+            |_| None,
+        );
+        if report.cycles >= owed {
+            self.overrun = self.overrun.saturating_sub(cycles) + (report.cycles - owed);
+        }
+        report
+    }
+
+    /// Like [`Machine::run`], but throttled by `pacer` to its clock
+    /// frequency.
+    #[cfg(feature = "std")]
+    pub fn run_paced(
+        &mut self,
+        pacer: &mut Pacer,
+        max_instructions: Option<u64>,
+    ) -> ExecutionReport {
+        let limits = Watchdog {
+            instructions: max_instructions,
+            cycles: None,
+        };
+        self.run_with(limits, |machine| {
+            pacer.pace(machine.cpu.cycles);
+            None
+        })
+    }
+
+    /// Sets the clock that [`Machine::run_for`] keeps to, or runs it
+    /// unthrottled with `None`.
+    #[cfg(feature = "std")]
+    pub const fn set_pacer(&mut self, pacer: Option<Pacer>) {
+        self.pacer = pacer;
+        self.overrun = 0;
+    }
+
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub const fn pacer(&self) -> Option<&Pacer> {
+        self.pacer.as_ref()
+    }
+
+    /// Executes for at most `budget` of host time, the natural API for
+    /// frame-driven front-ends.
+    ///
+    /// With a pacer set, runs the cycles that fit in `budget` at its clock
+    /// frequency without sleeping, so calling this once per frame keeps
+    /// authentic speed; cycles an instruction runs past the budget are
+    /// taken off the next slice. Without one, runs as fast as the host
+    /// allows until `budget` has passed. Either way, stops early at a
+    /// breakpoint or illegal opcode, or if the host can't keep up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mos6502::cpu::CPU;
+    /// use mos6502::instruction::Nmos6502;
+    /// use mos6502::machine::Machine;
+    /// use mos6502::memory::{Bus, Memory};
+    /// use mos6502::pacing::Pacer;
+    ///
+    /// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+    /// // loop: JMP loop
+    /// machine.cpu.memory.set_bytes(0x0000, &[0x4c, 0x00, 0x00]);
+    /// machine.set_pacer(Some(Pacer::new(Pacer::NES_NTSC)));
+    /// let slice = machine.run_for(Duration::from_millis(16));
+    /// assert!(slice.cycles <= 28_636 + 2);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn run_for(&mut self, budget: Duration) -> TimeSlice {
+        /// Instructions between reads of the host clock.
+        const CHECK_INTERVAL: u32 = 256;
+
+        let deadline = Instant::now() + budget;
+        let target = self.pacer.map(|pacer| {
+            let owed = pacer.cycles_in(budget).saturating_sub(self.overrun);
+            self.overrun = self.overrun.saturating_sub(pacer.cycles_in(budget));
+            self.cpu.cycles + owed
+        });
+        let start = self.cpu.cycles;
+        let mut until_check = CHECK_INTERVAL;
+        let mut stop = None;
+        loop {
+            if target.is_some_and(|target| self.cpu.cycles >= target) {
+                break;
+            }
+            until_check -= 1;
+            if until_check == 0 {
+                until_check = CHECK_INTERVAL;
+                if Instant::now() >= deadline {
+                    break;
+                }
+            }
+            if let Some(reason) = self.guarded_step() {
+                stop = Some(reason);
+                break;
+            }
+        }
+        if let Some(target) = target {
+            self.overrun += self.cpu.cycles.saturating_sub(target);
+        }
+        TimeSlice {
+            cycles: self.cpu.cycles - start,
+            stop,
+        }
+    }
+
+    /// [`Machine::step`], printing a crash report if it panics while one
+    /// is enabled.
+    #[cfg(feature = "std")]
+    fn guarded_step(&mut self) -> Option<StopReason> {
+        if self.history.is_none() {
+            return self.step();
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| self.step())) {
+            Ok(stop) => stop,
+            Err(payload) => {
+                std::eprintln!("{}", self.crash_report());
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn guarded_step(&mut self) -> Option<StopReason> {
+        self.step()
+    }
+
+    /// Runs within `limits` and the [`Watchdog`], stopping also when
+    /// `after_step` gives a reason to.
+    fn run_with(
+        &mut self,
+        limits: Watchdog,
+        mut after_step: impl FnMut(&Self) -> Option<StopReason>,
+    ) -> ExecutionReport {
+        let limits = limits.min(self.watchdog);
+        let start = self.cpu.cycles;
+        let start_instructions = self.instructions;
+        let mut executed = 0;
+        let mut interrupts = 0;
+        let stop = loop {
+            if let Some(count) = limits.instructions.filter(|&max| executed >= max) {
+                break StopReason::LimitReached {
+                    limit: Limit::Instructions,
+                    count,
+                };
+            }
+            if let Some(count) = limits.cycles.filter(|&max| self.cpu.cycles - start >= max) {
+                break StopReason::LimitReached {
+                    limit: Limit::Cycles,
+                    count,
+                };
+            }
+            if self.pause.is_paused() {
+                break StopReason::Paused;
+            }
+            let before = self.instructions;
+            let stop = self.guarded_step();
+            if self.instructions > before && self.cpu.interrupt_taken().is_some() {
+                interrupts += 1;
+            }
+            if let Some(reason) = stop.or_else(|| after_step(self)) {
+                break reason;
+            }
+            executed += 1;
+        };
+        ExecutionReport {
+            stop,
+            instructions: self.instructions - start_instructions,
+            cycles: self.cpu.cycles - start,
+            interrupts,
+            pc: self.cpu.registers.program_counter,
+        }
+    }
+}
+
+/// The address, up to 9 bytes before `pc`, from which instructions decode
+/// straight through to `pc`, or `pc` itself if there is none.
+fn code_start<V: Variant>(memory: &impl Bus, pc: u16) -> u16 {
+    (1..=9u16)
+        .rev()
+        .map(|back| pc.wrapping_sub(back))
+        .find(|&start| {
+            let bytes: Vec<u8> = (0..pc.wrapping_sub(start))
+                .map(|offset| memory.get_byte(start.wrapping_add(offset)))
+                .collect();
+            Disassembler::<V>::for_variant(&bytes, start).all(|line| line.decoded.is_some())
+        })
+        .unwrap_or(pc)
+}
+
+impl<M: Bus, V: Variant> fmt::Debug for Machine<M, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("Machine");
+        f.field("cpu", &self.cpu)
+            .field("breakpoints", &self.breakpoints)
+            .field("watchpoints", &self.watchpoints)
+            .field("opcode_breaks", &self.opcode_breaks)
+            .field("illegal_opcodes", &self.illegal_opcodes)
+            .field("tracepoints", &self.tracepoints)
+            .field("rewind", &self.rewind.as_ref().map(SnapshotRing::len))
+            .field("observers", &self.observers.len());
+        #[cfg(feature = "scripting")]
+        f.field("conditions", &self.conditions);
+        #[cfg(feature = "std")]
+        f.field("pacer", &self.pacer)
+            .field("history", &self.history.as_ref().map(|h| h.instructions));
+        f.finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{Cmos6502, Nmos6502};
+    use crate::memory::Memory;
+    use alloc::string::ToString;
+
+    fn machine(program: &[u8]) -> Machine<Memory, Nmos6502> {
+        let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+        machine.cpu.memory.set_bytes(0x0000, program);
+        machine
+    }
+
+    const fn instruction_limit(count: u64) -> StopReason {
+        StopReason::LimitReached {
+            limit: Limit::Instructions,
+            count,
+        }
+    }
+
+    const fn cycle_limit(count: u64) -> StopReason {
+        StopReason::LimitReached {
+            limit: Limit::Cycles,
+            count,
+        }
+    }
+
+    #[test]
+    fn reset_and_interrupts_go_through_the_vectors() {
+        // $0200: CLI; NOP; NOP  $0300: INX; RTI  $0400: INY; RTI
+        let mut machine = machine(&[]);
+        machine.cpu.memory.set_bytes(0x0200, &[0x58, 0xea, 0xea]);
+        machine.cpu.memory.set_bytes(0x0300, &[0xe8, 0x40]);
+        machine.cpu.memory.set_bytes(0x0400, &[0xc8, 0x40]);
+        machine
+            .cpu
+            .memory
+            .set_bytes(0xfffa, &[0x00, 0x04, 0x00, 0x02, 0x00, 0x03]);
+
+        machine.reset();
+        assert_eq!(machine.cpu.registers.program_counter, 0x0200);
+        assert_eq!(machine.cpu.cycles, 7);
+
+        // The IRQ is masked until CLI has run.
+        machine.irq(true);
+        machine.step();
+        assert_eq!(machine.cpu.registers.program_counter, 0x0300);
+        assert_eq!(machine.step_cycles(), 2 + 7);
+        // The handler runs with IRQs masked, so it isn't re-entered.
+        machine.irq(false);
+        machine.step();
+        machine.step();
+        assert_eq!(machine.cpu.registers.program_counter, 0x0201);
+        assert_eq!(machine.cpu.registers.index_x, 1);
+
+        machine.nmi();
+        machine.step();
+        assert_eq!(machine.cpu.registers.program_counter, 0x0400);
+        // The pushed status has B clear, unlike BRK's.
+        let sp = machine.cpu.registers.stack_pointer.0;
+        let pushed = machine
+            .cpu
+            .memory
+            .get_byte(0x0100 | u16::from(sp.wrapping_add(1)));
+        assert_eq!(pushed & crate::registers::Status::PS_BRK.bits(), 0);
+        machine.step();
+        machine.step();
+        assert_eq!(machine.cpu.registers.program_counter, 0x0202);
+        assert_eq!(machine.cpu.registers.index_y, 1);
+    }
+
+    #[test]
+    fn step_reports_its_cycles() {
+        // LDA $01FF,X; BNE +0
+        let mut machine = machine(&[0xbd, 0xff, 0x01, 0xd0, 0x00]);
+        machine.cpu.registers.index_x = 1;
+        machine.cpu.memory.set_byte(0x0200, 1);
+        machine.step();
+        assert_eq!(machine.step_cycles(), 5);
+        machine.step();
+        assert_eq!(machine.step_cycles(), 3);
+        assert_eq!(machine.cpu.cycles, 8);
+    }
+
+    #[test]
+    fn watchpoints_stop_after_the_access() {
+        // LDA #$42; STA $10; LDX $10; NOP
+        let mut machine = machine(&[0xa9, 0x42, 0x85, 0x10, 0xa6, 0x10, 0xea]);
+        machine.add_watchpoint(0x0010, Watch::Read);
+        machine.add_watchpoint(0x0011, Watch::ReadWrite);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::Watchpoint {
+                pc: 0x0004,
+                access: Access::Read,
+                address: 0x0010,
+                value: 0x42
+            }
+        );
+        assert_eq!(machine.cpu.registers.index_x, 0x42);
+
+        machine.reset();
+        machine.cpu.registers.program_counter = 0x0000;
+        machine.add_watchpoint(0x0010, Watch::Write);
+        assert_eq!(
+            machine.run(None).stop.to_string(),
+            "write of $42 at $0010 from $0002"
+        );
+        assert!(machine.remove_watchpoint(0x0010));
+        machine.clear_watchpoints();
+        assert_eq!(machine.watchpoints().count(), 0);
+        assert!(!machine.cpu.is_recording_accesses());
+    }
+
+    #[test]
+    fn exec_hooks_patch_and_stop() {
+        // LDA #$01; LDA #$02; LDX #$03; NOP
+        let mut machine = machine(&[0xa9, 0x01, 0xa9, 0x02, 0xa2, 0x03, 0xea]);
+        machine.set_pre_exec_hook(|cpu, line| {
+            if line.to_string() == "LDA #$02" {
+                cpu.registers.program_counter = line.address.wrapping_add(2);
+                return HookAction::Skip;
+            }
+            HookAction::Continue
+        });
+        machine.set_post_exec_hook(|_, event| {
+            if event.instruction.0 == Instruction::LDX {
+                HookAction::Stop
+            } else {
+                HookAction::Continue
+            }
+        });
+        let report = machine.run(None);
+        assert_eq!(report.stop, StopReason::Hook { pc: 0x0004 });
+        assert_eq!(report.instructions, 2);
+        assert_eq!(machine.cpu.registers.accumulator, 0x01);
+        assert_eq!(machine.cpu.registers.index_x, 0x03);
+
+        machine.clear_exec_hooks();
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.cpu.registers.program_counter, 0x0007);
+    }
+
+    #[test]
+    fn run_for_cycles_finishes_the_last_instruction() {
+        // loop: JMP loop
+        let mut machine = machine(&[0x4c, 0x00, 0x00]);
+        let report = machine.run_for_cycles(10);
+        assert_eq!(report.stop, cycle_limit(10));
+        assert_eq!((report.instructions, report.cycles), (4, 12));
+    }
+
+    #[test]
+    fn run_frame_can_stop_for_an_interrupt_and_resume() {
+        // loop: JMP loop  $0300: RTI
+        let mut machine = machine(&[0x4c, 0x00, 0x00]);
+        machine.cpu.memory.set_bytes(0x0300, &[0x40]);
+        machine.cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        assert_eq!(machine.run_frame(10).cycles, 12);
+
+        machine.break_on_interrupts(true);
+        machine.nmi();
+        machine.cpu.memory.set_bytes(0xfffa, &[0x00, 0x03]);
+        let report = machine.run_frame(30);
+        assert!(matches!(report.stop, StopReason::Interrupt { .. }));
+        assert_eq!(report.cycles, 3 + 7);
+        // The overshoot of the first frame is still owed, so the two frames
+        // keep to their budgets.
+        let report = machine.run_frame(30 - report.cycles);
+        assert_eq!(report.stop, cycle_limit(30 - 12));
+        assert_eq!(machine.cpu.cycles, 10 + 30);
+    }
+
+    #[test]
+    fn load_bin_refuses_to_wrap() {
+        let mut machine = machine(&[]);
+        machine.load_bin(&[0xe8, 0xe8], 0x0200).unwrap();
+        machine.cpu.registers.program_counter = 0x0200;
+        machine.step_instructions(2);
+        assert_eq!(machine.cpu.registers.index_x, 2);
+        assert_eq!(
+            machine.load_bin(&[0xea; 2], 0xffff),
+            Err(LoadError::Overflow {
+                address: 0xffff,
+                len: 2
+            })
+        );
+    }
+
+    #[test]
+    fn load_asm_uses_the_machine_variant() {
+        let mut machine = Machine::new(CPU::new(Memory::new(), Cmos6502));
+        let program = machine
+            .load_asm(".org $0300\nstart: STZ $10\n BRA start")
+            .unwrap();
+        assert_eq!(program.symbols.address_of("start"), Some(0x0300));
+        assert_eq!(
+            machine.cpu.memory.get_bytes(0x0300..0x0304),
+            [0x64, 0x10, 0x80, 0xfc]
+        );
+        let error = machine.load_asm("NOP\nLDA ($10)\nBOGUS").unwrap_err();
+        assert_eq!(error.line, 3);
+    }
+
+    #[test]
+    fn disassembles_memory() {
+        // LDA ($10),Y; STA $C000,X; BRK
+        let machine = machine(&[0xb1, 0x10, 0x9d, 0x00, 0xc0, 0x00]);
+        let lines: Vec<String> = machine
+            .disassemble_at(0x0000, 3)
+            .iter()
+            .map(|line| alloc::format!("{:04X} {line}", line.address))
+            .collect();
+        assert_eq!(lines, ["0000 LDA ($10),Y", "0002 STA $C000,X", "0005 BRK"]);
+        // Wraps around the top of memory.
+        assert_eq!(machine.disassemble_at(0xffff, 2)[1].address, 0x0000);
+    }
+
+    #[test]
+    fn illegal_opcodes_follow_the_policy() {
+        // SLO $10 (undocumented); SKB #$01 (undocumented); INX; JAM
+        let program = [0x07, 0x10, 0x80, 0x01, 0xe8, 0x02];
+        let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+        machine.cpu.memory.set_bytes(0x0000, &program);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::IllegalOpcode {
+                pc: 0x0000,
+                opcode: 0x07
+            }
+        );
+        assert_eq!(machine.cpu.cycles, 0);
+
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let mut machine = Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::TreatAsNop);
+        machine.cpu.memory.set_bytes(0x0000, &program);
+        assert_eq!(machine.run(Some(4)).stop, instruction_limit(4));
+        assert_eq!(machine.cpu.registers.program_counter, 0x0006);
+        assert_eq!(machine.cpu.registers.index_x, 1);
+        assert_eq!(machine.cpu.cycles, 5 + 2 + 2 + 2);
+
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let mut machine = Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::Accurate);
+        machine.cpu.memory.set_bytes(0x0000, &program);
+        machine.cpu.memory.set_byte(0x0010, 0x41);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::IllegalOpcode {
+                pc: 0x0005,
+                opcode: 0x02
+            }
+        );
+        assert_eq!(machine.cpu.memory.get_byte(0x0010), 0x82);
+        assert_eq!(machine.cpu.registers.accumulator, 0x82);
+        assert_eq!(machine.cpu.cycles, 5 + 2 + 2);
+
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let mut machine = Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::Trap);
+        machine.cpu.memory.set_bytes(0x0000, &program);
+        machine.set_illegal_opcode_hook(|cpu, opcode| {
+            if opcode == 0x02 {
+                return Some(StopReason::Paused);
+            }
+            cpu.registers.accumulator = opcode;
+            cpu.registers.program_counter += 2;
+            None
+        });
+        assert_eq!(machine.run(None).stop, StopReason::Paused);
+        assert_eq!(machine.cpu.registers.accumulator, 0x80);
+        assert_eq!(machine.cpu.registers.program_counter, 0x0005);
+        assert_eq!(machine.illegal_opcode_policy(), IllegalOpcodePolicy::Trap);
+    }
+
+    #[test]
+    fn stops_after_an_instruction_whose_access_failed() {
+        use crate::memory::{BusFault, Fallible, FallibleBus};
+
+        struct NoWrites(Memory);
+
+        impl FallibleBus for NoWrites {
+            fn try_get_byte(&self, address: u16) -> Result<u8, BusError> {
+                Ok(self.0.get_byte(address))
+            }
+
+            fn try_set_byte(&mut self, address: u16, _value: u8) -> Result<(), BusError> {
+                Err(BusError {
+                    access: Access::Write,
+                    address,
+                    fault: BusFault::Device,
+                })
+            }
+        }
+
+        // INX; STX $D000; INX
+        let mut memory = Memory::new();
+        memory.set_bytes(0x0200, &[0xe8, 0x8e, 0x00, 0xd0, 0xe8]);
+        let mut cpu = CPU::new(Fallible::new(NoWrites(memory)), Nmos6502);
+        cpu.registers.program_counter = 0x0200;
+        let mut machine = Machine::new(cpu);
+        let stop = machine.run(None).stop;
+        assert_eq!(
+            stop,
+            StopReason::BusError {
+                pc: 0x0201,
+                error: BusError {
+                    access: Access::Write,
+                    address: 0xd000,
+                    fault: BusFault::Device
+                }
+            }
+        );
+        assert_eq!(
+            alloc::format!("{stop}"),
+            "write to $D000 failed: device fault at $0201"
+        );
+        assert_eq!(machine.cpu.registers.program_counter, 0x0204);
+    }
+
+    #[test]
+    fn breaks_on_fetching_an_opcode_anywhere() {
+        // INX; INX; JMP $0010, then BRK at $0010
+        let mut machine = machine(&[0xe8, 0xe8, 0x4c, 0x10, 0x00]);
+        machine.break_on_opcode(0x00);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::OpcodeFetch {
+                pc: 0x0010,
+                opcode: 0x00
+            }
+        );
+        assert_eq!(machine.cpu.registers.index_x, 2);
+        assert!(machine.remove_opcode_break(0x00));
+        assert_eq!(machine.opcode_breaks().count(), 0);
+    }
+
+    #[test]
+    fn run_reports_what_it_did() {
+        // INX; INX, with an RTI handler at $0300
+        let mut machine = machine(&[0xe8, 0xe8]);
+        machine.cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        machine.cpu.memory.set_byte(0x0300, 0x40);
+        machine
+            .cpu
+            .registers
+            .status
+            .remove(crate::registers::Status::PS_DISABLE_INTERRUPTS);
+        machine.cpu.set_irq(true);
+        machine.break_on_rti(true);
+
+        let report = machine.run(Some(1));
+        assert_eq!(
+            report,
+            ExecutionReport {
+                stop: instruction_limit(1),
+                instructions: 1,
+                cycles: 2 + 7,
+                interrupts: 1,
+                pc: 0x0300,
+            }
+        );
+        assert_eq!(
+            alloc::format!("{report}"),
+            "instruction limit of 1 reached after 1 instructions, 9 cycles and 1 interrupts"
+        );
+
+        machine.cpu.set_irq(false);
+        let report = machine.run(None);
+        assert_eq!(report.stop, StopReason::ReturnFromInterrupt { to: 0x0001 });
+        assert_eq!((report.instructions, report.interrupts), (1, 0));
+        assert_eq!(report.pc, 0x0001);
+    }
+
+    #[test]
+    fn run_continues_past_the_breakpoint_it_stopped_at() {
+        // loop: INX; JMP loop
+        let mut machine = machine(&[0xe8, 0x4c, 0x00, 0x00]);
+        machine.add_breakpoint(0x0000);
+        assert_eq!(machine.run(None).stop, StopReason::Breakpoint(0x0000));
+        assert_eq!(machine.run(None).stop, StopReason::Breakpoint(0x0000));
+        assert_eq!(machine.cpu.registers.index_x, 2);
+        assert!(!machine.toggle_breakpoint(0x0000));
+        assert_eq!(machine.run(Some(10)).stop, instruction_limit(10));
+    }
+
+    #[test]
+    fn illegal_opcode_stops_without_executing() {
+        let mut machine = machine(&[0xea, 0x02]);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::IllegalOpcode {
+                pc: 0x0001,
+                opcode: 0x02
+            }
+        );
+        assert_eq!(machine.cpu.registers.program_counter, 0x0001);
+    }
+
+    #[test]
+    fn seek_to_an_earlier_cycle() {
+        // loop: INX; STX $10; JMP loop
+        let mut machine = machine(&[0xe8, 0x86, 0x10, 0x4c, 0x00, 0x00]);
+        machine.enable_rewind(100, 8);
+        // Each iteration takes 2 + 3 + 3 = 8 cycles.
+        machine.run(Some(3 * 1000));
+        assert_eq!(machine.cpu.cycles, 8000);
+        assert_eq!(machine.rewind().unwrap().len(), 8);
+
+        let hash = machine.state_hash();
+        let oldest = machine.rewind().unwrap().iter().next().unwrap().cycles;
+
+        // Iterations end on cycles 8n + 2, 8n + 5 and 8n + 8.
+        assert_eq!(machine.seek_to_cycle(7500), Ok(7501));
+        // The STX of iteration 938 just stored X = 938 % 256.
+        assert_eq!(machine.cpu.memory.get_byte(0x0010), 0xAA);
+        assert_eq!(machine.seek_to_cycle(10), Err(SeekError::TooOld { oldest }));
+
+        // Going forward again lands on the same state.
+        assert_eq!(machine.seek_to_cycle(8000), Ok(8000));
+        assert_eq!(machine.state_hash(), hash);
+    }
+
+    #[test]
+    fn seeking_replays_illegal_opcodes_by_the_policy() {
+        use alloc::rc::Rc;
+        use core::cell::Cell;
+
+        // loop: NOP; JAM; INX; JMP loop
+        let program = [0xea, 0x02, 0xe8, 0x4c, 0x00, 0x02];
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let mut machine = Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::TreatAsNop);
+        machine.cpu.memory.set_bytes(0x0200, &program);
+        machine.cpu.registers.program_counter = 0x0200;
+        machine.enable_rewind(1000, 4);
+        machine.run(Some(20));
+        assert_eq!(machine.seek_to_cycle(5), Ok(6));
+        assert_eq!(machine.cpu.registers.program_counter, 0x0203);
+        assert_eq!(machine.cpu.registers.index_x, 1);
+        // Replay leaves the instruction count alone.
+        assert_eq!(machine.instructions(), 20);
+
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let mut machine = Machine::with_illegal_opcodes(cpu, IllegalOpcodePolicy::Trap);
+        machine.cpu.memory.set_bytes(0x0200, &program);
+        machine.cpu.registers.program_counter = 0x0200;
+        let traps = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&traps);
+        machine.set_illegal_opcode_hook(move |cpu, _| {
+            counter.set(counter.get() + 1);
+            cpu.registers.program_counter += 1;
+            cpu.cycles += 2;
+            None
+        });
+        machine.enable_rewind(1000, 4);
+        machine.run(Some(20));
+        assert_eq!(traps.get(), 5);
+        assert_eq!(machine.seek_to_cycle(5), Ok(6));
+        assert_eq!(traps.get(), 6);
+        assert_eq!(machine.cpu.registers.index_x, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn run_for_repays_overrun_cycles() {
+        // loop: JMP loop
+        let mut machine = machine(&[0x4c, 0x00, 0x00]);
+        // 10 cycles per millisecond, so each slice overruns by 2 cycles of
+        // the 3-cycle JMP until the owed cycles add up.
+        machine.set_pacer(Some(Pacer::new(10_000)));
+        let slices = [(); 3].map(|()| machine.run_for(Duration::from_millis(1)).cycles);
+        assert_eq!(slices, [12, 9, 9]);
+        assert_eq!(machine.cpu.cycles, 30);
+
+        machine.add_breakpoint(0x0000);
+        let slice = machine.run_for(Duration::from_millis(1));
+        assert_eq!(slice.stop, Some(StopReason::Breakpoint(0x0000)));
+        assert_eq!(slice.cycles, 3);
+    }
+
+    #[test]
+    fn tracepoints_do_not_stop_execution() {
+        // LDX #$03; loop: DEX; BNE loop; NOP
+        let mut machine = machine(&[0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xea]);
+        let dex = machine.add_tracepoint(0x0002, TraceAction::Log("X={x}".into()));
+        let nop = machine.add_tracepoint(0x0005, TraceAction::Count);
+        assert_eq!(machine.run(Some(8)).stop, instruction_limit(8));
+        assert_eq!(machine.take_trace_output(), ["X=03", "X=02", "X=01"]);
+        assert_eq!(machine.tracepoint(dex).map(|t| t.hits), Some(3));
+        assert_eq!(machine.tracepoint(nop).map(|t| t.hits), Some(1));
+        assert!(machine.take_trace_output().is_empty());
+    }
+
+    #[test]
+    fn break_on_cycle_and_instruction_count() {
+        // loop: INX; JMP loop
+        let mut machine = machine(&[0xe8, 0x4c, 0x00, 0x00]);
+        // Iterations take 2 + 3 cycles, so cycle 11 is crossed by the third
+        // INX, which ends on cycle 12.
+        machine.break_at_cycle(11);
+        assert_eq!(machine.run(None).stop, StopReason::CycleReached(12));
+        assert_eq!(machine.instructions(), 5);
+
+        machine.break_after_instructions(5);
+        assert_eq!(machine.run(None).stop, StopReason::InstructionsExecuted(5));
+        assert_eq!(machine.instructions(), 10);
+        assert_eq!(machine.cpu.registers.index_x, 5);
+        assert_eq!(machine.run(Some(100)).stop, instruction_limit(100));
+    }
+
+    #[test]
+    fn break_on_interrupt_entry_and_return() {
+        // NOP; NOP; BRK, with a handler of RTI at $0300.
+        let mut machine = machine(&[0xea, 0xea, 0x00]);
+        machine.cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        machine.cpu.memory.set_byte(0x0300, 0x40);
+        machine
+            .cpu
+            .registers
+            .status
+            .remove(crate::registers::Status::PS_DISABLE_INTERRUPTS);
+        machine.break_on_interrupts(true);
+        machine.break_on_rti(true);
+
+        machine.cpu.set_irq(true);
+        let irq = StopReason::Interrupt {
+            kind: Interrupt::Irq,
+            vector: 0xfffe,
+            from: 0x0001,
+        };
+        assert_eq!(machine.run(None).stop, irq);
+        assert_eq!(machine.cpu.registers.program_counter, 0x0300);
+        assert_eq!(alloc::format!("{irq}"), "IRQ at $0001 through vector $FFFE");
+
+        machine.cpu.set_irq(false);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::ReturnFromInterrupt { to: 0x0001 }
+        );
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::Interrupt {
+                kind: Interrupt::Brk,
+                vector: 0xfffe,
+                from: 0x0002,
+            }
+        );
+    }
+
+    #[test]
+    fn break_on_a_hijacked_brk_reports_the_nmi_vector() {
+        // BRK, with an NMI arriving as it starts and its handler at $0400.
+        let mut machine = machine(&[0x00, 0x00]);
+        machine.cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        machine.cpu.memory.set_bytes(0xfffa, &[0x00, 0x04]);
+        machine.break_on_interrupts(true);
+
+        machine.cpu.set_nmi(true);
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::Interrupt {
+                kind: Interrupt::Brk,
+                vector: 0xfffa,
+                from: 0x0000,
+            }
+        );
+        assert_eq!(machine.cpu.registers.program_counter, 0x0400);
+    }
+
+    #[test]
+    fn break_when_the_stack_leaves_its_range() {
+        // loop: PHA; JMP loop
+        let mut machine = machine(&[0x48, 0x4c, 0x00, 0x00]);
+        machine.cpu.registers.stack_pointer.0 = 0xff;
+        machine.break_on_stack(Some(0xf0..=0xff));
+        assert_eq!(
+            machine.run(None).stop,
+            StopReason::StackOutOfRange { sp: 0xef }
+        );
+        // Further pushes stay out of range without stopping again.
+        assert_eq!(machine.run(Some(10)).stop, instruction_limit(10));
+    }
+
+    #[test]
+    fn push_and_pull_wrap_within_page_one() {
+        let mut machine = machine(&[]);
+        machine.cpu.registers.stack_pointer.0 = 0x00;
+        machine.push_byte(0xaa);
+        machine.push_byte(0xbb);
+        assert_eq!(machine.cpu.memory.get_byte(0x0100), 0xaa);
+        assert_eq!(machine.cpu.memory.get_byte(0x01ff), 0xbb);
+        assert_eq!(machine.pull_byte(), 0xbb);
+        assert_eq!(machine.pull_byte(), 0xaa);
+        assert_eq!(machine.cpu.registers.stack_pointer.0, 0x00);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn conditional_breakpoints_and_script_tracepoints() {
+        use crate::script::ScriptEngine;
+
+        // loop: INX; JMP loop
+        let mut machine = machine(&[0xe8, 0x4c, 0x00, 0x00]);
+        let engine = ScriptEngine::new();
+        machine.add_conditional_breakpoint(0x0001, engine.compile("x == 3").unwrap());
+        machine.add_tracepoint(
+            0x0000,
+            TraceAction::Script(engine.compile("if x % 2 == 0 { `even ${x}` }").unwrap()),
+        );
+        assert_eq!(machine.run(None).stop, StopReason::Breakpoint(0x0001));
+        assert_eq!(machine.cpu.registers.index_x, 3);
+        assert_eq!(machine.take_trace_output(), ["even 0", "even 2"]);
+    }
+
+    #[test]
+    fn watchdog_limits_every_run() {
+        // loop: JMP loop
+        let mut machine = machine(&[0x4c, 0x00, 0x00]);
+        machine.set_watchdog(Watchdog {
+            instructions: None,
+            cycles: Some(10),
+        });
+        // Four JMPs take the run past 10 cycles.
+        let stop = machine.run(None).stop;
+        assert_eq!(stop, cycle_limit(10));
+        assert_eq!(alloc::format!("{stop}"), "cycle limit of 10 reached");
+        assert_eq!(machine.cpu.cycles, 12);
+        assert_eq!(machine.run(Some(2)).stop, instruction_limit(2));
+        assert_eq!(machine.cpu.cycles, 18);
+
+        machine.set_watchdog(Watchdog {
+            instructions: Some(5),
+            cycles: None,
+        });
+        assert_eq!(machine.run(None).stop, instruction_limit(5));
+        assert_eq!(machine.cpu.cycles, 33);
+    }
+
+    #[test]
+    fn step_instructions_reports_how_many_ran() {
+        // INX; INX; INX; (illegal)
+        let mut machine = machine(&[0xe8, 0xe8, 0xe8, 0x02]);
+        assert_eq!(
+            machine.step_instructions(2),
+            Steps {
+                executed: 2,
+                stop: None
+            }
+        );
+        assert_eq!(
+            machine.step_instructions(5),
+            Steps {
+                executed: 1,
+                stop: Some(StopReason::IllegalOpcode {
+                    pc: 0x0003,
+                    opcode: 0x02
+                })
+            }
+        );
+        assert_eq!(machine.cpu.registers.index_x, 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn crash_report_keeps_recent_instructions() {
+        // LDA $10; STA $11; INX; INX
+        let mut machine = machine(&[0xa5, 0x10, 0x85, 0x11, 0xe8, 0xe8]);
+        machine.enable_crash_report(2);
+        assert_eq!(machine.run(Some(3)).stop, instruction_limit(3));
+        let report = machine.crash_report();
+        assert!(!report.contains("0000  A5"), "{report}");
+        assert!(report.contains("0002  85"), "{report}");
+        assert!(report.contains("W $0011 = $00"), "{report}");
+        assert!(report.contains("0004  E8"), "{report}");
+        assert!(
+            report.ends_with("0005  E8  A:00 X:01 Y:00 P:24 SP:FD CYC:8"),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn dumps_registers_stack_zero_page_and_code() {
+        // LDX #$05; JSR sub; BRK; sub: PHA; INX
+        let mut machine = machine(&[0xa2, 0x05, 0x20, 0x06, 0x00, 0x00, 0x48, 0xe8]);
+        machine.cpu.registers.stack_pointer.0 = 0xff;
+        machine.step_instructions(3);
+
+        let dump = machine.dump();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines[..5],
+            [
+                "registers: PC:0007 A:00 X:05 Y:00 SP:FC P:24 CYC:11",
+                "flags:     ..-..I..",
+                "stack:",
+                "  01FD: $00",
+                "  01FE: JSR $0002 -> $0005",
+            ]
+        );
+        assert_eq!(lines[5], "zero page:");
+        assert_eq!(
+            lines[6],
+            "  0000: A2 05 20 06 00 00 48 E8 00 00 00 00 00 00 00 00"
+        );
+        assert_eq!(
+            lines[22..],
+            [
+                "code:",
+                "  0002: 20 06 00  JSR $0006",
+                "  0005: 00        BRK",
+                "  0006: 48        PHA",
+                "> 0007: E8        INX",
+                "  0008: 00        BRK",
+                "  0009: 00        BRK",
+                "  000A: 00        BRK",
+                "  000B: 00        BRK",
+            ]
+        );
+    }
+
+    #[test]
+    fn measures_interrupt_latency() {
+        // SEI; NOP; NOP; CLI, with both vectors pointing at a run of NOPs.
+        let mut machine = machine(&[0x78, 0xea, 0xea, 0x58]);
+        machine.cpu.memory.set_bytes(0x0300, &[0xea; 4]);
+        machine
+            .cpu
+            .memory
+            .set_bytes(0xfffa, &[0x00, 0x03, 0x00, 0x00, 0x00, 0x03]);
+        machine.step();
+        machine.cpu.set_irq(true);
+        machine.step_instructions(3);
+        // Masked for three instructions, then the interrupt sequence.
+        assert_eq!(machine.cpu.interrupt_latency(), Some(2 + 2 + 2 + 7));
+
+        machine.cpu.set_nmi(true);
+        machine.step();
+        assert_eq!(machine.cpu.interrupt_latency(), Some(2 + 7));
+
+        let stats = machine.interrupt_latency();
+        assert_eq!(stats.max(Request::Irq), Some(13));
+        assert_eq!(stats.count(Request::Nmi), 1);
+        machine.clear_interrupt_latency();
+        assert_eq!(machine.interrupt_latency().count(Request::Irq), 0);
+    }
+
+    #[test]
+    fn reset_reaches_the_bus_and_added_devices() {
+        use crate::mapper::{Mapper, MapperBus, Uxrom};
+        use crate::system::Resettable;
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        struct Timer(u16);
+
+        impl Resettable for Timer {
+            fn reset(&mut self) {
+                self.0 = 0xffff;
+            }
+        }
+
+        let prg_rom = alloc::vec![0; 4 * 0x4000];
+        let bus = MapperBus::new(Uxrom::new(&prg_rom).unwrap(), Memory::new());
+        let mut machine = Machine::new(CPU::new(bus, Nmos6502));
+        let timer = Rc::new(RefCell::new(Timer(0x1234)));
+        machine.add_device(Rc::clone(&timer));
+        machine.cpu.memory.set_byte(0x8000, 2);
+        assert_eq!(machine.cpu.memory.mapper().prg_bank(0x8000), Some(2));
+
+        machine.reset();
+        assert_eq!(machine.cpu.memory.mapper().prg_bank(0x8000), Some(0));
+        assert_eq!(timer.borrow().0, 0xffff);
+    }
+}
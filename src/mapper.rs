@@ -0,0 +1,571 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Cartridge mappers for NES-style bank-switched program ROMs.
+//!
+//! A [`Mapper`] owns the cartridge address space (`$4020-$FFFF`) as seen by
+//! the CPU. [`MapperBus`] layers a mapper on top of any other [`Bus`], which
+//! keeps serving the addresses the mapper does not drive (internal RAM, I/O
+//! registers, or unmapped cartridge space).
+//!
+//! Only the CPU side of the cartridge is modelled. CHR bank registers are
+//! tracked so front-ends can inspect them, but there is no PPU here to use
+//! them.
+
+use core::fmt;
+use core::ops::Range;
+
+use crate::interrupt::IrqSource;
+use crate::memory::{Bus, BusError};
+
+/// First address of cartridge space on the NES.
+pub const CARTRIDGE_ADDRESS_LO: u16 = 0x4020;
+
+/// Size of a 16 KB PRG ROM bank.
+pub const PRG_BANK_SIZE: usize = 0x4000;
+
+const PRG_ROM_START: u16 = 0x8000;
+const PRG_ROM_UPPER_HALF: u16 = 0xC000;
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+/// The CPU-facing side of a cartridge mapper.
+pub trait Mapper {
+    /// Reads a byte from cartridge space.
+    ///
+    /// Returns `None` if the mapper does not drive the data bus at
+    /// `address`, in which case the read falls through to the underlying bus.
+    fn cpu_read(&self, address: u16) -> Option<u8>;
+
+    /// Writes a byte to cartridge space, which usually updates mapper
+    /// registers rather than memory.
+    ///
+    /// Returns `false` if the mapper ignores writes to `address`, in which
+    /// case the write falls through to the underlying bus.
+    fn cpu_write(&mut self, address: u16, value: u8) -> bool;
+
+    /// Returns the 16 KB PRG ROM bank currently visible at `address`, or
+    /// `None` if `address` is not backed by PRG ROM.
+    fn prg_bank(&self, address: u16) -> Option<usize>;
+
+    /// Returns `true` while the mapper asserts the IRQ line.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Returns the mapper registers to their power-on state.
+    fn reset(&mut self) {}
+}
+
+/// Why a PRG ROM image can't be used with a mapper.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PrgRomError {
+    /// The image is empty.
+    Empty,
+    /// The image, `len` bytes long, isn't a whole number of 16 KB banks.
+    PartialBank { len: usize },
+    /// The image has more banks than the mapper can select.
+    TooLarge { banks: usize, max: usize },
+}
+
+impl fmt::Display for PrgRomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrgRomError::Empty => f.write_str("PRG ROM is empty"),
+            PrgRomError::PartialBank { len } => {
+                write!(
+                    f,
+                    "PRG ROM of {len} bytes is not a whole number of 16 KB banks"
+                )
+            }
+            PrgRomError::TooLarge { banks, max } => {
+                write!(
+                    f,
+                    "PRG ROM has {banks} banks, more than the {max} the mapper selects"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PrgRomError {}
+
+/// Checks that `prg_rom` is between one and `max` whole banks.
+const fn check_prg_rom(prg_rom: &[u8], max: usize) -> Result<(), PrgRomError> {
+    let len = prg_rom.len();
+    let banks = len / PRG_BANK_SIZE;
+    if len == 0 {
+        Err(PrgRomError::Empty)
+    } else if !len.is_multiple_of(PRG_BANK_SIZE) {
+        Err(PrgRomError::PartialBank { len })
+    } else if banks > max {
+        Err(PrgRomError::TooLarge { banks, max })
+    } else {
+        Ok(())
+    }
+}
+
+/// Banks in a PRG ROM checked with [`check_prg_rom`].
+const fn bank_count(prg_rom: &[u8]) -> usize {
+    prg_rom.len() / PRG_BANK_SIZE
+}
+
+fn read_bank(prg_rom: &[u8], bank: usize, address: u16) -> u8 {
+    prg_rom[bank % bank_count(prg_rom) * PRG_BANK_SIZE + usize::from(address) % PRG_BANK_SIZE]
+}
+
+/// Mapper 0: 16 KB or 32 KB of fixed PRG ROM.
+///
+/// A 16 KB image is mirrored into both halves of `$8000-$FFFF`.
+#[derive(Copy, Clone, Debug)]
+pub struct Nrom<'a> {
+    prg_rom: &'a [u8],
+}
+
+impl<'a> Nrom<'a> {
+    /// # Errors
+    ///
+    /// Fails unless `prg_rom` is one or two 16 KB banks.
+    pub const fn new(prg_rom: &'a [u8]) -> Result<Nrom<'a>, PrgRomError> {
+        if let Err(error) = check_prg_rom(prg_rom, 2) {
+            return Err(error);
+        }
+        Ok(Nrom { prg_rom })
+    }
+}
+
+impl Mapper for Nrom<'_> {
+    fn cpu_read(&self, address: u16) -> Option<u8> {
+        self.prg_bank(address)
+            .map(|bank| read_bank(self.prg_rom, bank, address))
+    }
+
+    fn cpu_write(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+
+    fn prg_bank(&self, address: u16) -> Option<usize> {
+        match address {
+            PRG_ROM_START..PRG_ROM_UPPER_HALF => Some(0),
+            PRG_ROM_UPPER_HALF.. => Some(bank_count(self.prg_rom) - 1),
+            _ => None,
+        }
+    }
+}
+
+/// Mapper 2: a switchable 16 KB bank at `$8000` and the last bank fixed at
+/// `$C000`. Any write to `$8000-$FFFF` selects the switchable bank.
+#[derive(Copy, Clone, Debug)]
+pub struct Uxrom<'a> {
+    prg_rom: &'a [u8],
+    bank: u8,
+}
+
+impl<'a> Uxrom<'a> {
+    /// # Errors
+    ///
+    /// Fails unless `prg_rom` is between one and 256 16 KB banks, as many
+    /// as the 8-bit bank register selects.
+    pub const fn new(prg_rom: &'a [u8]) -> Result<Uxrom<'a>, PrgRomError> {
+        if let Err(error) = check_prg_rom(prg_rom, 256) {
+            return Err(error);
+        }
+        Ok(Uxrom { prg_rom, bank: 0 })
+    }
+}
+
+impl Mapper for Uxrom<'_> {
+    fn cpu_read(&self, address: u16) -> Option<u8> {
+        self.prg_bank(address)
+            .map(|bank| read_bank(self.prg_rom, bank, address))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> bool {
+        if address >= PRG_ROM_START {
+            self.bank = value;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> Option<usize> {
+        match address {
+            PRG_ROM_START..PRG_ROM_UPPER_HALF => {
+                Some(usize::from(self.bank) % bank_count(self.prg_rom))
+            }
+            PRG_ROM_UPPER_HALF.. => Some(bank_count(self.prg_rom) - 1),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.bank = 0;
+    }
+}
+
+/// Mapper 1 (Nintendo MMC1): registers loaded through a 5-bit serial shift
+/// register, selectable PRG banking modes and 8 KB of PRG RAM at `$6000`.
+#[derive(Clone, Debug)]
+pub struct Mmc1<'a> {
+    prg_rom: &'a [u8],
+    prg_ram: [u8; PRG_RAM_SIZE],
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl<'a> Mmc1<'a> {
+    const CONTROL_POWER_ON: u8 = 0x0C;
+
+    /// # Errors
+    ///
+    /// Fails unless `prg_rom` is between one and 16 16 KB banks, as many as
+    /// the 4-bit PRG bank register selects.
+    pub const fn new(prg_rom: &'a [u8]) -> Result<Mmc1<'a>, PrgRomError> {
+        if let Err(error) = check_prg_rom(prg_rom, 16) {
+            return Err(error);
+        }
+        Ok(Mmc1 {
+            prg_rom,
+            prg_ram: [0; PRG_RAM_SIZE],
+            shift: 0,
+            shift_count: 0,
+            control: Self::CONTROL_POWER_ON,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        })
+    }
+
+    /// The control register: mirroring (bits 0-1), PRG banking mode
+    /// (bits 2-3) and CHR banking mode (bit 4).
+    #[must_use]
+    pub const fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// The two CHR bank registers.
+    #[must_use]
+    pub const fn chr_banks(&self) -> (u8, u8) {
+        (self.chr_bank_0, self.chr_bank_1)
+    }
+
+    const fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    const fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+}
+
+impl Mapper for Mmc1<'_> {
+    fn cpu_read(&self, address: u16) -> Option<u8> {
+        if (PRG_RAM_START..PRG_ROM_START).contains(&address) {
+            return self
+                .prg_ram_enabled()
+                .then(|| self.prg_ram[usize::from(address - PRG_RAM_START)]);
+        }
+        self.prg_bank(address)
+            .map(|bank| read_bank(self.prg_rom, bank, address))
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            PRG_RAM_START..PRG_ROM_START => {
+                if self.prg_ram_enabled() {
+                    self.prg_ram[usize::from(address - PRG_RAM_START)] = value;
+                }
+                true
+            }
+            PRG_ROM_START.. => {
+                if value & 0x80 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= Self::CONTROL_POWER_ON;
+                } else {
+                    self.shift |= (value & 0x01) << self.shift_count;
+                    self.shift_count += 1;
+                    if self.shift_count == 5 {
+                        self.write_register(address, self.shift);
+                        self.shift = 0;
+                        self.shift_count = 0;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> Option<usize> {
+        if address < PRG_ROM_START {
+            return None;
+        }
+        let upper = address >= PRG_ROM_UPPER_HALF;
+        let selected = usize::from(self.prg_bank & 0x0F);
+        let bank = match (self.control >> 2) & 0x03 {
+            // 32 KB mode: the low bit of the bank number is ignored.
+            0 | 1 => (selected & !1) + usize::from(upper),
+            // First bank fixed at $8000, switchable bank at $C000.
+            2 => {
+                if upper {
+                    selected
+                } else {
+                    0
+                }
+            }
+            // Switchable bank at $8000, last bank fixed at $C000.
+            _ => {
+                if upper {
+                    bank_count(self.prg_rom) - 1
+                } else {
+                    selected
+                }
+            }
+        };
+        Some(bank % bank_count(self.prg_rom))
+    }
+
+    fn reset(&mut self) {
+        self.shift = 0;
+        self.shift_count = 0;
+        self.control = Self::CONTROL_POWER_ON;
+    }
+}
+
+/// A bus that routes cartridge space through a [`Mapper`] and everything
+/// else to an underlying bus.
+#[derive(Clone, Debug)]
+pub struct MapperBus<M: Mapper, B: Bus> {
+    mapper: M,
+    inner: B,
+}
+
+impl<M: Mapper, B: Bus> MapperBus<M, B> {
+    pub const fn new(mapper: M, inner: B) -> MapperBus<M, B> {
+        MapperBus { mapper, inner }
+    }
+
+    pub const fn mapper(&self) -> &M {
+        &self.mapper
+    }
+
+    pub const fn mapper_mut(&mut self) -> &mut M {
+        &mut self.mapper
+    }
+
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Returns `true` while the mapper asserts the IRQ line.
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+}
+
+impl<M: Mapper, B: Bus> IrqSource for MapperBus<M, B> {
+    fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+}
+
+impl<M: Mapper, B: Bus> Bus for MapperBus<M, B> {
+    /// Returns bytes from the underlying bus; the mapper is not consulted.
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        if address >= CARTRIDGE_ADDRESS_LO {
+            if let Some(value) = self.mapper.cpu_read(address) {
+                return value;
+            }
+        }
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if address < CARTRIDGE_ADDRESS_LO || !self.mapper.cpu_write(address, value) {
+            self.inner.set_byte(address, value);
+        }
+    }
+
+    /// Returns the mapper to its power-on banks, see [`Mapper::reset`].
+    fn reset(&mut self) {
+        self.mapper.reset();
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    // Eight 16 KB banks, each filled with its own bank number.
+    static PRG_128K: [u8; 8 * PRG_BANK_SIZE] = {
+        let mut prg = [0; 8 * PRG_BANK_SIZE];
+        let mut i = 0;
+        while i < prg.len() {
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                prg[i] = (i / PRG_BANK_SIZE) as u8;
+            }
+            i += 1;
+        }
+        prg
+    };
+
+    fn mmc1_write(bus: &mut MapperBus<Mmc1, Memory>, address: u16, value: u8) {
+        for bit in 0..5 {
+            bus.set_byte(address, (value >> bit) & 0x01);
+        }
+    }
+
+    #[test]
+    fn constructors_reject_badly_sized_roms() {
+        assert_eq!(Nrom::new(&[]).unwrap_err(), PrgRomError::Empty);
+        assert_eq!(
+            Uxrom::new(&PRG_128K[..=PRG_BANK_SIZE]).unwrap_err(),
+            PrgRomError::PartialBank {
+                len: PRG_BANK_SIZE + 1
+            }
+        );
+        assert_eq!(
+            Nrom::new(&PRG_128K[..3 * PRG_BANK_SIZE]).unwrap_err(),
+            PrgRomError::TooLarge { banks: 3, max: 2 }
+        );
+        assert!(Mmc1::new(&PRG_128K[..0x1000]).is_err());
+    }
+
+    #[test]
+    fn nrom_mirrors_16k_image() {
+        let bus = MapperBus::new(
+            Nrom::new(&PRG_128K[..PRG_BANK_SIZE]).unwrap(),
+            Memory::new(),
+        );
+        assert_eq!(bus.get_byte(0x8000), 0);
+        assert_eq!(bus.get_byte(0xC000), 0);
+        assert_eq!(bus.mapper().prg_bank(0xFFFF), Some(0));
+    }
+
+    #[test]
+    fn nrom_ignores_writes_to_rom_and_falls_through_below() {
+        let mut bus = MapperBus::new(
+            Nrom::new(&PRG_128K[..2 * PRG_BANK_SIZE]).unwrap(),
+            Memory::new(),
+        );
+        bus.set_byte(0x8000, 0x55);
+        assert_eq!(bus.get_byte(0x8000), 0);
+        assert_eq!(bus.get_byte(0xC000), 1);
+        bus.set_byte(0x0200, 0x55);
+        assert_eq!(bus.get_byte(0x0200), 0x55);
+    }
+
+    #[test]
+    fn uxrom_switches_lower_bank_and_fixes_last() {
+        let mut bus = MapperBus::new(Uxrom::new(&PRG_128K).unwrap(), Memory::new());
+        assert_eq!(bus.get_byte(0x8000), 0);
+        assert_eq!(bus.get_byte(0xC000), 7);
+        bus.set_byte(0x8000, 3);
+        assert_eq!(bus.get_byte(0x8000), 3);
+        assert_eq!(bus.get_byte(0xFFFF), 7);
+        bus.mapper_mut().reset();
+        assert_eq!(bus.get_byte(0x8000), 0);
+    }
+
+    #[test]
+    fn mmc1_power_on_fixes_last_bank() {
+        let bus = MapperBus::new(Mmc1::new(&PRG_128K).unwrap(), Memory::new());
+        assert_eq!(bus.get_byte(0x8000), 0);
+        assert_eq!(bus.get_byte(0xC000), 7);
+    }
+
+    #[test]
+    fn mmc1_serial_writes_select_banks() {
+        let mut bus = MapperBus::new(Mmc1::new(&PRG_128K).unwrap(), Memory::new());
+        mmc1_write(&mut bus, 0xE000, 5);
+        assert_eq!(bus.get_byte(0x8000), 5);
+        assert_eq!(bus.get_byte(0xC000), 7);
+
+        // Mode 2: first bank fixed, switchable bank at $C000.
+        mmc1_write(&mut bus, 0x8000, 0x08);
+        assert_eq!(bus.get_byte(0x8000), 0);
+        assert_eq!(bus.get_byte(0xC000), 5);
+
+        // Mode 0: 32 KB switching, low bit ignored.
+        mmc1_write(&mut bus, 0x8000, 0x00);
+        assert_eq!(bus.get_byte(0x8000), 4);
+        assert_eq!(bus.get_byte(0xC000), 5);
+
+        mmc1_write(&mut bus, 0xA000, 0x11);
+        assert_eq!(bus.mapper().chr_banks(), (0x11, 0));
+    }
+
+    #[test]
+    fn mmc1_reset_bit_restores_mode_3() {
+        let mut bus = MapperBus::new(Mmc1::new(&PRG_128K).unwrap(), Memory::new());
+        mmc1_write(&mut bus, 0x8000, 0x00);
+        bus.set_byte(0x8000, 0x01);
+        bus.set_byte(0x8000, 0x80);
+        assert_eq!(bus.mapper().control() & 0x0C, 0x0C);
+        mmc1_write(&mut bus, 0xE000, 2);
+        assert_eq!(bus.get_byte(0x8000), 2);
+    }
+
+    #[test]
+    fn mmc1_prg_ram_can_be_disabled() {
+        let mut bus = MapperBus::new(Mmc1::new(&PRG_128K).unwrap(), Memory::new());
+        bus.set_byte(0x6000, 0x42);
+        assert_eq!(bus.get_byte(0x6000), 0x42);
+        mmc1_write(&mut bus, 0xE000, 0x10);
+        bus.set_byte(0x6000, 0x24);
+        assert_eq!(bus.inner().get_byte(0x6000), 0);
+        mmc1_write(&mut bus, 0xE000, 0x00);
+        assert_eq!(bus.get_byte(0x6000), 0x42);
+    }
+}
@@ -34,6 +34,8 @@
 //     end: Address,
 // }
 
+use core::cell::Cell;
+use core::fmt;
 use core::ops::Range;
 
 const ADDR_LO_BARE: u16 = 0x0000;
@@ -43,9 +45,54 @@ pub const MEMORY_ADDRESS_LO: u16 = ADDR_LO_BARE;
 pub const MEMORY_ADDRESS_HI: u16 = ADDR_HI_BARE;
 pub const STACK_ADDRESS_LO: u16 = 0x0100;
 pub const STACK_ADDRESS_HI: u16 = 0x01FF;
+pub const NMI_INTERRUPT_VECTOR_LO: u16 = 0xFFFA;
+pub const NMI_INTERRUPT_VECTOR_HI: u16 = 0xFFFB;
+pub const RESET_VECTOR_LO: u16 = 0xFFFC;
+pub const RESET_VECTOR_HI: u16 = 0xFFFD;
 pub const IRQ_INTERRUPT_VECTOR_LO: u16 = 0xFFFE;
 pub const IRQ_INTERRUPT_VECTOR_HI: u16 = 0xFFFF;
 
+/// The direction of a bus access.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Why a bus access failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusFault {
+    /// Nothing answers at the address.
+    Unmapped,
+    /// The device at the address reported a fault.
+    Device,
+    /// The device didn't answer in time, such as memory on a remote host.
+    Timeout,
+}
+
+/// A bus access that failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusError {
+    pub access: Access,
+    pub address: u16,
+    pub fault: BusFault,
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read of",
+            Access::Write => "write to",
+        };
+        let fault = match self.fault {
+            BusFault::Unmapped => "nothing is mapped there",
+            BusFault::Device => "device fault",
+            BusFault::Timeout => "timed out",
+        };
+        write!(f, "{access} ${:04X} failed: {fault}", self.address)
+    }
+}
+
 const MEMORY_SIZE: usize = (ADDR_HI_BARE - ADDR_LO_BARE) as usize + 1usize;
 
 // FIXME: Should this use indirection for `bytes`?
@@ -73,7 +120,60 @@ impl Default for Memory {
 /// memory.set_byte(0x0000, 0x12);
 /// assert_eq!(memory.get_byte(0x0000), 0x12);
 /// ```
+///
+/// A system with mirrored RAM, an I/O register and ROM maps addresses
+/// itself:
+///
+/// ```
+/// use core::ops::Range;
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::Bus;
+///
+/// struct System {
+///     ram: [u8; 0x800],
+///     latch: u8,
+///     rom: [u8; 0x8000],
+/// }
+///
+/// impl Bus for System {
+///     fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+///         &self.rom[range.start - 0x8000..range.end - 0x8000]
+///     }
+///
+///     fn get_byte(&self, address: u16) -> u8 {
+///         match address {
+///             0x0000..=0x1fff => self.ram[usize::from(address) % 0x800],
+///             0x2000 => self.latch,
+///             0x8000.. => self.rom[usize::from(address) - 0x8000],
+///             _ => 0xff,
+///         }
+///     }
+///
+///     fn set_byte(&mut self, address: u16, value: u8) {
+///         match address {
+///             0x0000..=0x1fff => self.ram[usize::from(address) % 0x800] = value,
+///             0x2000 => self.latch = value,
+///             _ => {}
+///         }
+///     }
+/// }
+///
+/// let mut system = System { ram: [0; 0x800], latch: 0, rom: [0; 0x8000] };
+/// // LDA #$42; STA $0800; LDA $0000; STA $2000
+/// system.rom[..10].copy_from_slice(&[0xa9, 0x42, 0x8d, 0x00, 0x08, 0xad, 0x00, 0x00, 0x8d, 0x00]);
+/// system.rom[10] = 0x20;
+/// let mut cpu = CPU::new(system, Nmos6502);
+/// cpu.registers.program_counter = 0x8000;
+/// for _ in 0..4 {
+///     cpu.single_step();
+/// }
+/// assert_eq!(cpu.memory.latch, 0x42);
+/// ```
 pub trait Bus {
+    /// Lends the bytes in `range` for tools that want a slice of memory.
+    /// The CPU itself only uses [`Bus::get_byte`] and [`Bus::set_byte`], so
+    /// a bus with I/O registers can limit this to the memory it holds.
     fn get_bytes(&self, range: Range<usize>) -> &[u8];
 
     /// Returns the byte at the given address.
@@ -98,10 +198,194 @@ pub trait Bus {
             self.set_byte(start + i, values[i as usize]);
         }
     }
+
+    /// Called once for every CPU clock cycle, with the number of the cycle
+    /// that just completed. Chips clocked by phi2 can be driven from here
+    /// without intercepting individual memory accesses.
+    ///
+    /// The CPU currently executes whole instructions at a time, so the calls
+    /// for an instruction's cycles are made after all of its bus activity.
+    ///
+    /// The default implementation does nothing.
+    fn phi2(&mut self, _cycle: u64) {}
+
+    /// Called when the machine is reset, for devices on the bus to return to
+    /// their power-on state as they would when the RESET line is pulled.
+    /// Memory keeps its contents. Buses that wrap another should pass the
+    /// call on.
+    ///
+    /// The default implementation does nothing.
+    fn reset(&mut self) {}
+
+    /// Returns the number of extra cycles an access to `address` takes, for
+    /// slow memory that holds the CPU with RDY. The CPU adds them to its
+    /// cycle count for every read and write, dummy accesses included.
+    ///
+    /// The default implementation adds none.
+    fn wait_states(&self, _address: u16) -> u8 {
+        0
+    }
+
+    /// Returns and clears the first access that failed since the last call,
+    /// for buses that can fail, such as a [`Fallible`] one. Execution
+    /// through a [`Machine`] checks after every instruction and stops with
+    /// [`StopReason::BusError`].
+    ///
+    /// The default implementation never fails.
+    ///
+    /// [`Machine`]: crate::machine::Machine
+    /// [`StopReason::BusError`]: crate::machine::StopReason::BusError
+    fn take_error(&mut self) -> Option<BusError> {
+        None
+    }
+}
+
+/// A bus whose accesses can fail, so that a custom bus can say so rather
+/// than invent a value for an address nothing answers at. Wrap it in
+/// [`Fallible`] to run a CPU on it.
+pub trait FallibleBus {
+    /// Returns the byte at the given address.
+    ///
+    /// # Errors
+    ///
+    /// Fails if nothing can supply the byte.
+    fn try_get_byte(&self, address: u16) -> Result<u8, BusError>;
+
+    /// Sets the byte at the given address to the given value.
+    ///
+    /// # Errors
+    ///
+    /// Fails if nothing can take the byte.
+    fn try_set_byte(&mut self, address: u16, value: u8) -> Result<(), BusError>;
+
+    /// See [`Bus::phi2`].
+    fn phi2(&mut self, _cycle: u64) {}
+
+    /// See [`Bus::wait_states`].
+    fn wait_states(&self, _address: u16) -> u8 {
+        0
+    }
+
+    /// See [`Bus::reset`].
+    fn reset(&mut self) {}
+}
+
+/// Runs a CPU on a [`FallibleBus`].
+///
+/// The CPU executes whole instructions, so one that makes a failed access
+/// still completes: a failed read gives `$FF` and a failed write is
+/// dropped. The first error is kept until [`Bus::take_error`] collects it,
+/// which a [`Machine`](crate::machine::Machine) does after every
+/// instruction to stop with a bus error.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Access, Bus, BusError, BusFault, Fallible, FallibleBus};
+///
+/// struct Rom([u8; 4]);
+///
+/// impl FallibleBus for Rom {
+///     fn try_get_byte(&self, address: u16) -> Result<u8, BusError> {
+///         self.0.get(usize::from(address)).copied().ok_or(BusError {
+///             access: Access::Read,
+///             address,
+///             fault: BusFault::Unmapped,
+///         })
+///     }
+///
+///     fn try_set_byte(&mut self, address: u16, _value: u8) -> Result<(), BusError> {
+///         Err(BusError { access: Access::Write, address, fault: BusFault::Device })
+///     }
+/// }
+///
+/// // LDA $1234
+/// let bus = Fallible::new(Rom([0xad, 0x34, 0x12, 0xea]));
+/// let mut cpu = CPU::new(bus, Nmos6502);
+/// cpu.single_step();
+/// assert_eq!(cpu.registers.accumulator, 0xff);
+/// let error = cpu.memory.take_error().unwrap();
+/// assert_eq!((error.address, error.fault), (0x1234, BusFault::Unmapped));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Fallible<B: FallibleBus> {
+    inner: B,
+    error: Cell<Option<BusError>>,
+}
+
+impl<B: FallibleBus> Fallible<B> {
+    pub const fn new(inner: B) -> Fallible<B> {
+        Fallible {
+            inner,
+            error: Cell::new(None),
+        }
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn fail(&self, error: BusError) {
+        if self.error.get().is_none() {
+            self.error.set(Some(error));
+        }
+    }
+}
+
+impl<B: FallibleBus> Bus for Fallible<B> {
+    /// Slices can't be lent out of a bus whose reads can fail.
+    ///
+    /// # Panics
+    ///
+    /// Always panics; use [`Fallible::inner`] instead.
+    fn get_bytes(&self, _range: Range<usize>) -> &[u8] {
+        panic!("a fallible bus can't lend out slices; use Fallible::inner")
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self.inner.try_get_byte(address).unwrap_or_else(|error| {
+            self.fail(error);
+            0xff
+        })
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if let Err(error) = self.inner.try_set_byte(address, value) {
+            self.fail(error);
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.error.take()
+    }
 }
 
 impl Memory {
     #[must_use]
+    #[allow(clippy::large_stack_arrays)]
     pub const fn new() -> Memory {
         Memory {
             bytes: [0; MEMORY_SIZE],
@@ -136,10 +420,116 @@ impl Bus for Memory {
     }
 }
 
+/// All 64 KiB of the address space, borrowed rather than owned, for targets
+/// where the memory is a `static` buffer or lives somewhere the caller
+/// chooses. The CPU then holds only the reference, and the caller gets the
+/// buffer back with everything the program wrote once the CPU is dropped.
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::Bus;
+///
+/// let mut ram = [0; 0x1_0000];
+/// // LDA #$42; STA $10; BRK
+/// ram[0x0000..0x0005].copy_from_slice(&[0xa9, 0x42, 0x85, 0x10, 0x00]);
+/// let mut cpu = CPU::new(&mut ram, Nmos6502);
+/// cpu.single_step();
+/// cpu.single_step();
+/// drop(cpu);
+/// assert_eq!(ram[0x10], 0x42);
+/// ```
+impl Bus for &mut [u8; MEMORY_SIZE] {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        &self[range]
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self[usize::from(address)]
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self[usize::from(address)] = value;
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        let start = usize::from(start);
+        self[start..start + values.len()].copy_from_slice(values);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// RAM in the lower half, nothing in the upper.
+    struct HalfMapped(Memory);
+
+    impl FallibleBus for HalfMapped {
+        fn try_get_byte(&self, address: u16) -> Result<u8, BusError> {
+            if address < 0x8000 {
+                Ok(self.0.get_byte(address))
+            } else {
+                Err(BusError {
+                    access: Access::Read,
+                    address,
+                    fault: BusFault::Unmapped,
+                })
+            }
+        }
+
+        fn try_set_byte(&mut self, address: u16, value: u8) -> Result<(), BusError> {
+            if address < 0x8000 {
+                self.0.set_byte(address, value);
+                Ok(())
+            } else {
+                Err(BusError {
+                    access: Access::Write,
+                    address,
+                    fault: BusFault::Timeout,
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn fallible_bus_keeps_the_first_error() {
+        let mut bus = Fallible::new(HalfMapped(Memory::new()));
+        bus.set_byte(0x1000, 0x42);
+        assert_eq!(bus.get_byte(0x1000), 0x42);
+        assert_eq!(bus.take_error(), None);
+
+        bus.set_byte(0x9000, 0x01);
+        assert_eq!(bus.get_byte(0xa000), 0xff);
+        assert_eq!(
+            bus.take_error(),
+            Some(BusError {
+                access: Access::Write,
+                address: 0x9000,
+                fault: BusFault::Timeout
+            })
+        );
+        assert_eq!(bus.take_error(), None);
+    }
+
+    #[test]
+    // The test wants the whole address space, like `Memory` has.
+    #[allow(clippy::large_stack_arrays)]
+    fn borrowed_memory_matches_owned() {
+        let mut bytes = [0; MEMORY_SIZE];
+        let mut owned = Memory::new();
+        let mut borrowed = &mut bytes;
+        owned.set_bytes(0xFFFD, &[1, 2, 3]);
+        borrowed.set_bytes(0xFFFD, &[1, 2, 3]);
+        owned.set_byte(0x1234, 0x56);
+        borrowed.set_byte(0x1234, 0x56);
+        assert_eq!(borrowed.get_bytes(0xFFFC..0x10000), [0, 1, 2, 3]);
+        assert_eq!(
+            borrowed.get_bytes(0..MEMORY_SIZE),
+            owned.get_bytes(0..MEMORY_SIZE)
+        );
+    }
+
     #[test]
     #[should_panic(expected = "range end index 65537 out of range for slice of length 65536")]
     fn test_memory_overflow_panic() {
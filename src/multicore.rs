@@ -0,0 +1,349 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Several CPUs sharing one bus.
+//!
+//! Dual-processor designs, such as a Commodore computer and its disk drive
+//! or a multiprocessor single-board computer, are modelled by attaching
+//! every core to the same bus through a [`SharedBus`] handle. A
+//! [`Multiprocessor`] interleaves the cores an instruction at a time, always
+//! stepping the one that is furthest behind, so that cores clocked at
+//! different [`ClockDivider`] ratios stay in step with each other.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell, RefMut};
+use core::fmt;
+use core::ops::Range;
+
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::{Bus, BusError};
+use crate::system::ClockDivider;
+use crate::Variant;
+
+/// A handle to a bus shared by several CPUs.
+///
+/// Reads and writes go straight to the shared bus. Only one handle drives
+/// [`Bus::phi2`] and [`Bus::reset`], so that devices on the bus see a single
+/// clock rather than one per core.
+pub struct SharedBus<B> {
+    bus: Rc<RefCell<B>>,
+    clocked: bool,
+}
+
+impl<B: Bus> SharedBus<B> {
+    /// Shares `bus`. The returned handle clocks it.
+    pub fn new(bus: B) -> SharedBus<B> {
+        SharedBus {
+            bus: Rc::new(RefCell::new(bus)),
+            clocked: true,
+        }
+    }
+
+    /// Another handle to the same bus, which does not clock it.
+    #[must_use]
+    pub fn handle(&self) -> SharedBus<B> {
+        SharedBus {
+            bus: Rc::clone(&self.bus),
+            clocked: false,
+        }
+    }
+
+    /// Whether this handle forwards [`Bus::phi2`].
+    #[must_use]
+    pub const fn is_clocked(&self) -> bool {
+        self.clocked
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the bus is mutably borrowed.
+    #[must_use]
+    pub fn borrow(&self) -> Ref<'_, B> {
+        self.bus.borrow()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the bus is borrowed.
+    #[must_use]
+    pub fn borrow_mut(&self) -> RefMut<'_, B> {
+        self.bus.borrow_mut()
+    }
+}
+
+impl<B: Bus> Bus for SharedBus<B> {
+    /// Slices can't be lent out of the shared bus, which is behind a
+    /// `RefCell`.
+    ///
+    /// # Panics
+    ///
+    /// Always panics; use [`SharedBus::borrow`] instead.
+    fn get_bytes(&self, _range: Range<usize>) -> &[u8] {
+        panic!("a shared bus can't lend out slices; use SharedBus::borrow")
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self.bus.borrow().get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self.bus.borrow_mut().set_byte(address, value);
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        self.bus.borrow_mut().set_bytes(start, values);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        if self.clocked {
+            self.bus.borrow_mut().phi2(cycle);
+        }
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.bus.borrow().wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        if self.clocked {
+            self.bus.borrow_mut().reset();
+        }
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.bus.borrow_mut().take_error()
+    }
+}
+
+impl<B> fmt::Debug for SharedBus<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedBus")
+            .field("clocked", &self.clocked)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Identifies a core of a [`Multiprocessor`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CoreId(usize);
+
+impl CoreId {
+    /// The position of the core, in the order the cores were added.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// CPUs attached to one bus, stepped in turn.
+///
+/// Time is measured in master cycles. Each core runs at its own
+/// [`ClockDivider`] ratio of the master clock, and the bus is clocked by the
+/// first core.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::multicore::Multiprocessor;
+/// use mos6502::system::ClockDivider;
+///
+/// let mut system = Multiprocessor::new(Memory::new());
+/// let main = system.add_core(Nmos6502, ClockDivider::CPU);
+/// let helper = system.add_core(Nmos6502, ClockDivider::CPU);
+/// // The helper waits for a request in $10 and answers it in $11.
+/// // wait: LDA $10; BEQ wait; ASL A; STA $11; BRK
+/// system.bus_mut().set_bytes(0x0300, &[0xa5, 0x10, 0xf0, 0xfc, 0x0a, 0x85, 0x11, 0x00]);
+/// // LDA #$15; STA $10; BRK
+/// system.bus_mut().set_bytes(0x0200, &[0xa9, 0x15, 0x85, 0x10, 0x00]);
+/// system.core_mut(main).registers.program_counter = 0x0200;
+/// system.core_mut(helper).registers.program_counter = 0x0300;
+///
+/// system.run_to(100).unwrap();
+/// assert_eq!(system.bus().get_byte(0x11), 0x2a);
+/// ```
+pub struct Multiprocessor<B: Bus, V: Variant> {
+    bus: SharedBus<B>,
+    cores: Vec<(CPU<SharedBus<B>, V>, ClockDivider)>,
+}
+
+impl<B: Bus, V: Variant> Multiprocessor<B, V> {
+    /// A system with no cores yet.
+    pub fn new(bus: B) -> Multiprocessor<B, V> {
+        Multiprocessor {
+            bus: SharedBus::new(bus),
+            cores: Vec::new(),
+        }
+    }
+
+    /// Attaches a core clocked at `divider` times the master clock. Its
+    /// clock starts at the current master cycle.
+    pub fn add_core(&mut self, variant: V, divider: ClockDivider) -> CoreId {
+        let bus = SharedBus {
+            bus: Rc::clone(&self.bus.bus),
+            // The first core clocks the bus.
+            clocked: self.cores.is_empty(),
+        };
+        let mut cpu = CPU::new(bus, variant);
+        cpu.cycles = divider.ticks_at(self.master_cycles());
+        self.cores.push((cpu, divider));
+        CoreId(self.cores.len() - 1)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `id` is not a core of this system.
+    #[must_use]
+    pub fn core(&self, id: CoreId) -> &CPU<SharedBus<B>, V> {
+        &self.cores[id.0].0
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `id` is not a core of this system.
+    pub fn core_mut(&mut self, id: CoreId) -> &mut CPU<SharedBus<B>, V> {
+        &mut self.cores[id.0].0
+    }
+
+    /// The clock ratio of a core.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a core of this system.
+    #[must_use]
+    pub fn divider(&self, id: CoreId) -> ClockDivider {
+        self.cores[id.0].1
+    }
+
+    /// Iterates over the cores in the order they were added.
+    pub fn cores(&self) -> impl Iterator<Item = (CoreId, &CPU<SharedBus<B>, V>)> {
+        self.cores
+            .iter()
+            .enumerate()
+            .map(|(index, (cpu, _))| (CoreId(index), cpu))
+    }
+
+    /// # Panics
+    ///
+    /// Panics if a core is accessing the bus.
+    #[must_use]
+    pub fn bus(&self) -> Ref<'_, B> {
+        self.bus.borrow()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if a core is accessing the bus.
+    #[must_use]
+    pub fn bus_mut(&self) -> RefMut<'_, B> {
+        self.bus.borrow_mut()
+    }
+
+    /// The master cycle that every core has reached, or 0 without cores.
+    #[must_use]
+    pub fn master_cycles(&self) -> u64 {
+        self.cores
+            .iter()
+            .map(|(cpu, divider)| divider.master_cycles_at(cpu.cycles))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Executes one instruction on the core furthest behind, preferring
+    /// the earliest added on a tie.
+    ///
+    /// Returns the core that stepped and what it executed, which is `None`
+    /// for an illegal opcode, or `None` altogether without cores.
+    pub fn step(&mut self) -> Option<(CoreId, Option<DecodedInstr>)> {
+        let (index, (cpu, _)) = self
+            .cores
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, (cpu, divider))| divider.master_cycles_at(cpu.cycles))?;
+        Some((CoreId(index), cpu.single_step()))
+    }
+
+    /// Steps the cores until all of them have reached `master_cycle`.
+    ///
+    /// # Errors
+    ///
+    /// Stops at the first core to reach an illegal opcode and returns it.
+    pub fn run_to(&mut self, master_cycle: u64) -> Result<(), CoreId> {
+        while self.master_cycles() < master_cycle {
+            match self.step() {
+                Some((id, None)) => return Err(id),
+                Some((_, Some(_))) => {}
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: Bus, V: Variant> fmt::Debug for Multiprocessor<B, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Multiprocessor")
+            .field("cores", &self.cores)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn cores_interleave_at_their_clock_ratios() {
+        let mut system = Multiprocessor::new(Memory::new());
+        let fast = system.add_core(Nmos6502, ClockDivider::CPU);
+        let slow = system.add_core(Nmos6502, ClockDivider::divide(2));
+        // loop: INC $10; JMP loop, and the same counting in $11.
+        system
+            .bus_mut()
+            .set_bytes(0x0200, &[0xe6, 0x10, 0x4c, 0x00, 0x02]);
+        system
+            .bus_mut()
+            .set_bytes(0x0300, &[0xe6, 0x11, 0x4c, 0x00, 0x03]);
+        system.core_mut(fast).registers.program_counter = 0x0200;
+        system.core_mut(slow).registers.program_counter = 0x0300;
+
+        // Each iteration takes 5 + 3 = 8 cycles, so the fast core completes
+        // 1000 of them and the slow core 500.
+        assert_eq!(system.run_to(8000), Ok(()));
+        assert_eq!(system.core(fast).cycles, 8000);
+        assert_eq!(system.core(slow).cycles, 4000);
+        assert_eq!(system.bus().get_byte(0x10), 0xe8);
+        assert_eq!(system.bus().get_byte(0x11), 0xf4);
+        assert!(system.core(fast).memory.is_clocked());
+        assert!(!system.core(slow).memory.is_clocked());
+    }
+}
@@ -0,0 +1,813 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A transistor-level simulation of the NMOS 6502, as an accuracy oracle.
+//!
+//! The [Visual 6502](http://www.visual6502.org) project recovered the
+//! chip's netlist from die photographs: every transistor, which node drives
+//! its gate and which two nodes it connects. [`GateLevel`] simulates that
+//! netlist the way perfect6502 does, switch by switch, so its bus activity
+//! is by construction what the real chip does. It is several orders of
+//! magnitude slower than [`CPU`], and is meant for checking the fast core
+//! over short windows of instructions with [`compare`], not for running
+//! programs.
+//!
+//! The netlist itself is not distributed with this crate. Load it from the
+//! `segdefs.js`, `transdefs.js` and `nodenames.js` files of Visual 6502 with
+//! [`Netlist::parse`].
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::memory::{Access, Bus};
+use crate::registers::{Registers, StackPointer, Status};
+pub use crate::tstate::BusCycle;
+use crate::Variant;
+
+/// Passes through a node list before giving up on an oscillating circuit.
+const MAX_ITERATIONS: usize = 100;
+
+/// Cycles after which an instruction that hasn't fetched another opcode is
+/// assumed to have jammed the chip.
+const MAX_INSTRUCTION_CYCLES: usize = 32;
+
+/// Error raised for a netlist that cannot be used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetlistError {
+    /// A line of one of the source files could not be read.
+    Parse {
+        file: &'static str,
+        /// One-based line number.
+        line: usize,
+    },
+    /// A node the simulation needs has no name in `nodenames.js`.
+    MissingNode(String),
+}
+
+impl fmt::Display for NetlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetlistError::Parse { file, line } => write!(f, "{file}, line {line}: malformed entry"),
+            NetlistError::MissingNode(name) => write!(f, "netlist has no node named {name}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NetlistError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Transistor {
+    gate: usize,
+    c1: usize,
+    c2: usize,
+}
+
+/// The nodes and transistors of a chip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Netlist {
+    pullups: Vec<bool>,
+    transistors: Vec<Transistor>,
+    names: BTreeMap<String, usize>,
+}
+
+impl Netlist {
+    /// Reads a netlist in the format of Visual 6502's data files.
+    ///
+    /// Only the parts the simulation needs are read: the node number and
+    /// pull-up marker of each segment, the gate and channel nodes of each
+    /// transistor, and the `name: node` pairs.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first entry that cannot be read.
+    pub fn parse(segdefs: &str, transdefs: &str, nodenames: &str) -> Result<Netlist, NetlistError> {
+        let mut netlist = Netlist::default();
+        for (line, fields) in entries(segdefs) {
+            let error = || NetlistError::Parse {
+                file: "segdefs",
+                line,
+            };
+            let node = number(fields.first()).ok_or_else(error)?;
+            let pullup = match fields.get(1).map(|field| field.trim_matches(['\'', '"'])) {
+                Some("+") => true,
+                Some("-") => false,
+                _ => return Err(error()),
+            };
+            if netlist.pullups.len() <= node {
+                netlist.pullups.resize(node + 1, false);
+            }
+            netlist.pullups[node] |= pullup;
+        }
+        for (line, fields) in entries(transdefs) {
+            let error = || NetlistError::Parse {
+                file: "transdefs",
+                line,
+            };
+            let gate = number(fields.get(1)).ok_or_else(error)?;
+            let c1 = number(fields.get(2)).ok_or_else(error)?;
+            let c2 = number(fields.get(3)).ok_or_else(error)?;
+            netlist.transistors.push(Transistor { gate, c1, c2 });
+        }
+        for (index, line) in nodenames.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default();
+            let Some((name, node)) = line.split_once(':') else {
+                continue;
+            };
+            let name = name.trim().trim_matches(['\'', '"']);
+            let node = node.trim().trim_end_matches(',');
+            let Ok(node) = node.parse() else {
+                return Err(NetlistError::Parse {
+                    file: "nodenames",
+                    line: index + 1,
+                });
+            };
+            netlist.names.insert(name.into(), node);
+        }
+
+        let highest = netlist
+            .transistors
+            .iter()
+            .flat_map(|t| [t.gate, t.c1, t.c2])
+            .chain(netlist.names.values().copied())
+            .max();
+        if let Some(highest) = highest {
+            if netlist.pullups.len() <= highest {
+                netlist.pullups.resize(highest + 1, false);
+            }
+        }
+        Ok(netlist)
+    }
+
+    /// Number of nodes, including unused node numbers.
+    #[must_use]
+    pub const fn nodes(&self) -> usize {
+        self.pullups.len()
+    }
+
+    #[must_use]
+    pub const fn transistors(&self) -> usize {
+        self.transistors.len()
+    }
+
+    /// The node with the given name.
+    #[must_use]
+    pub fn node(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    fn require(&self, name: &str) -> Result<usize, NetlistError> {
+        self.node(name)
+            .ok_or_else(|| NetlistError::MissingNode(name.into()))
+    }
+
+    fn require_bits<const N: usize>(&self, prefix: &str) -> Result<[usize; N], NetlistError> {
+        let mut nodes = [0; N];
+        for (bit, node) in nodes.iter_mut().enumerate() {
+            *node = self.require(&format!("{prefix}{bit}"))?;
+        }
+        Ok(nodes)
+    }
+}
+
+/// The bracketed entries of a JavaScript array literal, one per line, as
+/// their comma-separated fields.
+fn entries(source: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(index, line)| {
+        let entry = line.trim().strip_prefix('[')?;
+        let fields = entry.split(',').map(str::trim).collect();
+        Some((index + 1, fields))
+    })
+}
+
+fn number(field: Option<&&str>) -> Option<usize> {
+    field?.trim().parse().ok()
+}
+
+/// What decides the level of a group of connected nodes, weakest first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Drive {
+    /// Nothing: the group floats and keeps its charge low.
+    Nothing,
+    /// A node of the group still holds a high charge.
+    Charged,
+    PullUp,
+    PullDown,
+    Vcc,
+    Vss,
+}
+
+/// The switch-level state of a netlist.
+#[derive(Clone, Debug)]
+struct Switches {
+    transistors: Vec<Transistor>,
+    vcc: usize,
+    vss: usize,
+    high: Vec<bool>,
+    pullup: Vec<bool>,
+    pulldown: Vec<bool>,
+    on: Vec<bool>,
+    /// Transistors gated by each node.
+    gates: Vec<Vec<usize>>,
+    /// Transistors with a channel connected to each node.
+    channels: Vec<Vec<usize>>,
+    group: Vec<usize>,
+    in_group: Vec<bool>,
+    queued: Vec<bool>,
+}
+
+impl Switches {
+    fn new(netlist: &Netlist, vcc: usize, vss: usize) -> Switches {
+        let nodes = netlist.nodes();
+        let mut gates = vec![Vec::new(); nodes];
+        let mut channels = vec![Vec::new(); nodes];
+        for (index, t) in netlist.transistors.iter().enumerate() {
+            gates[t.gate].push(index);
+            channels[t.c1].push(index);
+            channels[t.c2].push(index);
+        }
+        Switches {
+            transistors: netlist.transistors.clone(),
+            vcc,
+            vss,
+            high: vec![false; nodes],
+            pullup: netlist.pullups.clone(),
+            pulldown: vec![false; nodes],
+            on: vec![false; netlist.transistors.len()],
+            gates,
+            channels,
+            group: Vec::new(),
+            in_group: vec![false; nodes],
+            queued: vec![false; nodes],
+        }
+    }
+
+    /// Turns everything off and lets the circuit settle.
+    fn power_on(&mut self) {
+        self.high.fill(false);
+        self.on.fill(false);
+        self.recalc((0..self.high.len()).collect());
+    }
+
+    /// Drives `nodes` to the given levels and lets the circuit settle.
+    fn set(&mut self, nodes: &[(usize, bool)]) {
+        for &(node, high) in nodes {
+            self.pullup[node] = high;
+            self.pulldown[node] = !high;
+        }
+        self.recalc(nodes.iter().map(|&(node, _)| node).collect());
+    }
+
+    fn read(&self, nodes: &[usize]) -> u16 {
+        nodes
+            .iter()
+            .rev()
+            .fold(0, |value, &node| value << 1 | u16::from(self.high[node]))
+    }
+
+    fn recalc(&mut self, mut pending: Vec<usize>) {
+        for _ in 0..MAX_ITERATIONS {
+            if pending.is_empty() {
+                return;
+            }
+            let mut next = Vec::new();
+            for &node in &pending {
+                self.recalc_group(node, &mut next);
+            }
+            for &node in &next {
+                self.queued[node] = false;
+            }
+            pending = next;
+        }
+    }
+
+    /// Settles the group of nodes connected to `node`, queueing the nodes
+    /// whose connections change as a result.
+    fn recalc_group(&mut self, node: usize, next: &mut Vec<usize>) {
+        if node == self.vcc || node == self.vss {
+            return;
+        }
+        let mut group = core::mem::take(&mut self.group);
+        let mut drive = Drive::Nothing;
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if node == self.vss {
+                drive = drive.max(Drive::Vss);
+                continue;
+            }
+            if node == self.vcc {
+                drive = drive.max(Drive::Vcc);
+                continue;
+            }
+            if self.in_group[node] {
+                continue;
+            }
+            self.in_group[node] = true;
+            group.push(node);
+            if self.pulldown[node] {
+                drive = drive.max(Drive::PullDown);
+            }
+            if self.pullup[node] {
+                drive = drive.max(Drive::PullUp);
+            }
+            if self.high[node] {
+                drive = drive.max(Drive::Charged);
+            }
+            for &index in &self.channels[node] {
+                if self.on[index] {
+                    let t = self.transistors[index];
+                    stack.push(if t.c1 == node { t.c2 } else { t.c1 });
+                }
+            }
+        }
+
+        let high = matches!(drive, Drive::Charged | Drive::PullUp | Drive::Vcc);
+        for &node in &group {
+            self.in_group[node] = false;
+            if self.high[node] == high {
+                continue;
+            }
+            self.high[node] = high;
+            for &index in &self.gates[node] {
+                self.on[index] = high;
+                let t = self.transistors[index];
+                for channel in [t.c1, t.c2] {
+                    if !self.queued[channel] {
+                        self.queued[channel] = true;
+                        next.push(channel);
+                    }
+                }
+            }
+        }
+        group.clear();
+        self.group = group;
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Pins {
+    clk0: usize,
+    res: usize,
+    rdy: usize,
+    so: usize,
+    irq: usize,
+    nmi: usize,
+    rw: usize,
+    sync: usize,
+    ab: [usize; 16],
+    db: [usize; 8],
+    a: [usize; 8],
+    x: [usize; 8],
+    y: [usize; 8],
+    s: [usize; 8],
+    /// The status bits that exist as latches; B and bit 5 don't.
+    p: [Option<usize>; 8],
+}
+
+/// An NMOS 6502 simulated transistor by transistor, attached to `memory`.
+#[derive(Clone, Debug)]
+pub struct GateLevel<B: Bus> {
+    switches: Switches,
+    pins: Pins,
+    memory: B,
+    cycles: u64,
+    /// The opcode fetch that starts the next instruction.
+    fetch: BusCycle,
+}
+
+impl<B: Bus> GateLevel<B> {
+    /// Builds the chip from `netlist` and resets it, leaving it at the
+    /// first opcode fetch from the reset vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the netlist lacks a node the simulation drives
+    /// or reads.
+    pub fn new(netlist: &Netlist, memory: B) -> Result<Self, NetlistError> {
+        let vss = netlist
+            .node("vss")
+            .or_else(|| netlist.node("gnd"))
+            .ok_or_else(|| NetlistError::MissingNode("vss".into()))?;
+        let vcc = netlist.require("vcc")?;
+        let pins = Pins {
+            clk0: netlist.require("clk0")?,
+            res: netlist.require("res")?,
+            rdy: netlist.require("rdy")?,
+            so: netlist.require("so")?,
+            irq: netlist.require("irq")?,
+            nmi: netlist.require("nmi")?,
+            rw: netlist.require("rw")?,
+            sync: netlist.require("sync")?,
+            ab: netlist.require_bits("ab")?,
+            db: netlist.require_bits("db")?,
+            a: netlist.require_bits("a")?,
+            x: netlist.require_bits("x")?,
+            y: netlist.require_bits("y")?,
+            s: netlist.require_bits("s")?,
+            p: core::array::from_fn(|bit| netlist.node(&format!("p{bit}"))),
+        };
+        let mut chip = GateLevel {
+            switches: Switches::new(netlist, vcc, vss),
+            pins,
+            memory,
+            cycles: 0,
+            fetch: BusCycle {
+                address: 0,
+                data: 0,
+                access: Access::Read,
+                sync: true,
+            },
+        };
+        chip.reset();
+        Ok(chip)
+    }
+
+    /// Powers the chip up and holds RESET low for eight cycles, then runs
+    /// the reset sequence up to the first opcode fetch.
+    pub fn reset(&mut self) {
+        let pins = self.pins;
+        self.switches.power_on();
+        self.switches.set(&[
+            (pins.res, false),
+            (pins.clk0, true),
+            (pins.rdy, true),
+            (pins.so, false),
+            (pins.irq, true),
+            (pins.nmi, true),
+        ]);
+        for _ in 0..16 {
+            self.half_cycle();
+        }
+        self.switches.set(&[(pins.res, true)]);
+        self.cycles = 0;
+        for _ in 0..MAX_INSTRUCTION_CYCLES {
+            self.fetch = self.cycle();
+            if self.fetch.sync {
+                break;
+            }
+        }
+    }
+
+    /// Full clock cycles run since the end of the reset.
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The address of the next instruction, whose opcode has been fetched.
+    #[must_use]
+    pub const fn pc(&self) -> u16 {
+        self.fetch.address
+    }
+
+    /// The register latches.
+    ///
+    /// The chip finishes some instructions during the opcode fetch of the
+    /// next one, so a register may not yet hold the last result. The
+    /// program counter is the address of the next instruction.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn registers(&self) -> Registers {
+        // Each read is of eight nodes.
+        let read = |nodes: &[usize; 8]| self.switches.read(nodes) as u8;
+        let mut status = Status::PS_UNUSED | Status::PS_BRK;
+        for (bit, node) in self.pins.p.iter().enumerate() {
+            if let Some(node) = *node {
+                status.set(Status::from_bits_retain(1 << bit), self.switches.high[node]);
+            }
+        }
+        Registers {
+            accumulator: read(&self.pins.a),
+            index_x: read(&self.pins.x),
+            index_y: read(&self.pins.y),
+            stack_pointer: StackPointer(read(&self.pins.s)),
+            program_counter: self.pc(),
+            status,
+        }
+    }
+
+    #[must_use]
+    pub const fn memory(&self) -> &B {
+        &self.memory
+    }
+
+    pub const fn memory_mut(&mut self) -> &mut B {
+        &mut self.memory
+    }
+
+    /// Drives the IRQ line; `true` asserts it.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.switches.set(&[(self.pins.irq, !asserted)]);
+    }
+
+    /// Drives the NMI line; `true` asserts it.
+    pub fn set_nmi(&mut self, asserted: bool) {
+        self.switches.set(&[(self.pins.nmi, !asserted)]);
+    }
+
+    /// Inverts the clock, serving the bus on the rising edge of φ0.
+    #[allow(clippy::cast_possible_truncation)]
+    fn half_cycle(&mut self) -> Option<BusCycle> {
+        let pins = self.pins;
+        let rising = !self.switches.high[pins.clk0];
+        self.switches.set(&[(pins.clk0, rising)]);
+        if !rising {
+            return None;
+        }
+        let address = self.switches.read(&pins.ab);
+        let (access, data) = if self.switches.high[pins.rw] {
+            let data = self.memory.get_byte(address);
+            let bits: [(usize, bool); 8] =
+                core::array::from_fn(|bit| (pins.db[bit], data >> bit & 1 != 0));
+            self.switches.set(&bits);
+            (Access::Read, data)
+        } else {
+            // The data bus is eight nodes wide.
+            let data = self.switches.read(&pins.db) as u8;
+            self.memory.set_byte(address, data);
+            (Access::Write, data)
+        };
+        Some(BusCycle {
+            address,
+            data,
+            access,
+            sync: self.switches.high[pins.sync],
+        })
+    }
+
+    /// Runs one full clock cycle.
+    pub fn cycle(&mut self) -> BusCycle {
+        let cycle = loop {
+            if let Some(cycle) = self.half_cycle() {
+                break cycle;
+            }
+        };
+        self.cycles += 1;
+        cycle
+    }
+
+    /// Runs the instruction whose opcode has been fetched, returning its
+    /// cycles from the opcode fetch up to the fetch of the next opcode.
+    ///
+    /// An instruction that jams the chip never fetches another opcode, so
+    /// it is cut off after 32 cycles.
+    pub fn step_instruction(&mut self) -> Vec<BusCycle> {
+        let mut cycles = vec![self.fetch];
+        while cycles.len() < MAX_INSTRUCTION_CYCLES {
+            let cycle = self.cycle();
+            if cycle.sync {
+                self.fetch = cycle;
+                return cycles;
+            }
+            cycles.push(cycle);
+        }
+        self.fetch = self.cycle();
+        cycles
+    }
+}
+
+impl<B: Bus> crate::cpu::Cpu for GateLevel<B> {
+    type Bus = B;
+    /// The bus activity of the instruction.
+    type Step = Vec<BusCycle>;
+
+    fn step(&mut self) -> Option<Vec<BusCycle>> {
+        Some(self.step_instruction())
+    }
+
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn set_irq(&mut self, asserted: bool) {
+        GateLevel::set_irq(self, asserted);
+    }
+
+    fn set_nmi(&mut self, asserted: bool) {
+        GateLevel::set_nmi(self, asserted);
+    }
+
+    fn registers(&self) -> Registers {
+        GateLevel::registers(self)
+    }
+
+    fn memory(&self) -> &B {
+        &self.memory
+    }
+
+    fn memory_mut(&mut self) -> &mut B {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        GateLevel::reset(self);
+    }
+}
+
+/// The first instruction whose bus activity differed between the fast core
+/// and the simulated chip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CycleDivergence {
+    /// Number of instructions that matched before this one.
+    pub instruction: u64,
+    pub pc: u16,
+    /// The accesses the fast core made.
+    pub fast: Vec<(Access, u16, u8)>,
+    /// The chip's cycles.
+    pub chip: Vec<BusCycle>,
+}
+
+impl fmt::Display for CycleDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "bus activity differs in instruction {} at ${:04X}:",
+            self.instruction, self.pc
+        )?;
+        writeln!(f, "  cycle  fast          chip")?;
+        for index in 0..self.fast.len().max(self.chip.len()) {
+            write!(f, "  {index:>5}  ")?;
+            match self.fast.get(index) {
+                Some(&(access, address, data)) => {
+                    write!(f, "{} ${address:04X} ${data:02X}", direction(access))?;
+                }
+                None => f.write_str("           ")?,
+            }
+            match self.chip.get(index) {
+                Some(cycle) => writeln!(
+                    f,
+                    "   {} ${:04X} ${:02X}",
+                    direction(cycle.access),
+                    cycle.address,
+                    cycle.data
+                )?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+const fn direction(access: Access) -> char {
+    match access {
+        Access::Read => 'R',
+        Access::Write => 'W',
+    }
+}
+
+/// Runs `instructions` instructions on both `cpu` and `chip`, comparing
+/// the bus activity of each cycle by cycle.
+///
+/// `cpu` must start in the chip's state, typically by copying
+/// [`GateLevel::registers`] after a reset, with the same memory contents.
+/// Only the accesses are compared; cycles in which the fast core does not
+/// touch the bus show up as a divergence.
+///
+/// # Errors
+///
+/// Returns the first instruction whose accesses differ, including the
+/// address, direction, data and number of cycles.
+pub fn compare<M: Bus, V: Variant, B: Bus>(
+    cpu: &mut CPU<M, V>,
+    chip: &mut GateLevel<B>,
+    instructions: u64,
+) -> Result<u64, CycleDivergence> {
+    cpu.record_accesses(true);
+    let mut result = Ok(instructions);
+    for instruction in 0..instructions {
+        let pc = chip.pc();
+        cpu.single_step();
+        let fast: Vec<_> = cpu.drain_accesses().collect();
+        let cycles = chip.step_instruction();
+        let matches = fast.len() == cycles.len()
+            && fast
+                .iter()
+                .zip(&cycles)
+                .all(|(&(access, address, data), cycle)| {
+                    (access, address, data) == (cycle.access, cycle.address, cycle.data)
+                });
+        if !matches {
+            result = Err(CycleDivergence {
+                instruction,
+                pc,
+                fast,
+                chip: cycles,
+            });
+            break;
+        }
+    }
+    cpu.record_accesses(false);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An NMOS inverter feeding a second one through a pass transistor.
+    const SEGDEFS: &str = "var segdefs = [\n\
+        [ 1,'+',0,10,10],\n\
+        [ 2,'-',0,10,10],\n\
+        [ 3,'-',0,10,10],\n\
+        [ 4,'+',0,10,10],\n\
+        [ 5,'-',0,10,10],\n\
+        [ 6,'+',0,10,10],\n\
+        [ 7,'-',0,10,10],\n\
+        ]";
+    const TRANSDEFS: &str = "var transdefs = [\n\
+        ['t0',3,4,2,[0,0,0,0],[0,0,0,0,0],false],\n\
+        ['t1',7,4,5,[0,0,0,0],[0,0,0,0,0],false],\n\
+        ['t2',5,6,2,[0,0,0,0],[0,0,0,0,0],false],\n\
+        ]";
+    const NODENAMES: &str = "var nodenames ={\n\
+        vcc: 1,\n\
+        vss: 2, // ground\n\
+        in: 3,\n\
+        out: 4,\n\
+        pass: 7,\n\
+        inverted: 6,\n\
+        }";
+
+    fn switches() -> (Netlist, Switches) {
+        let netlist = Netlist::parse(SEGDEFS, TRANSDEFS, NODENAMES).unwrap();
+        let mut switches = Switches::new(&netlist, 1, 2);
+        switches.power_on();
+        (netlist, switches)
+    }
+
+    #[test]
+    fn parses_visual6502_files() {
+        let (netlist, _) = switches();
+        assert_eq!(netlist.nodes(), 8);
+        assert_eq!(netlist.transistors(), 3);
+        assert_eq!(netlist.node("out"), Some(4));
+        assert_eq!(
+            Netlist::parse("[ 1,'x',0]", "", ""),
+            Err(NetlistError::Parse {
+                file: "segdefs",
+                line: 1
+            })
+        );
+        assert_eq!(
+            GateLevel::new(&netlist, crate::memory::Memory::new()).err(),
+            Some(NetlistError::MissingNode("clk0".into()))
+        );
+    }
+
+    #[test]
+    fn switches_settle() {
+        let (_, mut switches) = switches();
+        // The pulled-up output is high while the input is low, and the node
+        // behind the pass transistor floats low.
+        assert!(switches.high[4]);
+        assert!(!switches.high[5]);
+        assert!(switches.high[6]);
+
+        // Turning the pass transistor on joins the two nodes into one group,
+        // which the pull-up holds high.
+        switches.set(&[(7, true)]);
+        assert!(switches.high[5]);
+        assert!(!switches.high[6]);
+
+        // Once it is off again, the node keeps its charge.
+        switches.set(&[(7, false)]);
+        switches.set(&[(3, true)]);
+        assert!(!switches.high[4]);
+        assert!(switches.high[5]);
+        assert!(!switches.high[6]);
+
+        // Ground wins over the charge.
+        switches.set(&[(7, true)]);
+        assert!(!switches.high[5]);
+        assert!(switches.high[6]);
+    }
+}
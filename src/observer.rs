@@ -0,0 +1,469 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Watching a [`Machine`] run.
+//!
+//! Tracing, coverage, profiling and watchpoints all need to see the same
+//! events. Instead of each wrapping the CPU or the bus in its own way, they
+//! implement [`Observer`] and are registered with
+//! [`Machine::add_observer`], which passes every event to each observer in
+//! the order they were added.
+//!
+//! A [`ChannelObserver`] forwards the events to another thread, such as a
+//! UI or logger, without holding up the emulation.
+//!
+//! [`Machine`]: crate::machine::Machine
+//! [`Machine::add_observer`]: crate::machine::Machine::add_observer
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+
+use crate::coverage::Coverage;
+use crate::instruction::DecodedInstr;
+use crate::profile::Profile;
+use crate::tstate::BusCycle;
+
+/// An instruction that was executed.
+#[derive(Copy, Clone, Debug)]
+pub struct InstructionEvent {
+    pub pc: u16,
+    pub opcode: u8,
+    pub instruction: DecodedInstr,
+    /// Cycles taken, including an interrupt that followed it.
+    pub cycles: u64,
+    /// Program counter afterwards, which is the handler address if an
+    /// interrupt followed.
+    pub next_pc: u16,
+}
+
+/// Receives the events of a running machine.
+///
+/// The events of an instruction are delivered once it has executed: first
+/// the pin state of each of its cycles, then each bus access it made, in
+/// order, then [`Observer::on_instruction`], then [`Observer::on_irq`] if an
+/// interrupt followed. Every method does nothing by default.
+pub trait Observer {
+    fn on_instruction(&mut self, _event: &InstructionEvent) {}
+
+    /// A byte read from the bus, including opcodes, operands and pointers.
+    fn on_read(&mut self, _address: u16, _value: u8) {}
+
+    fn on_write(&mut self, _address: u16, _value: u8) {}
+
+    /// The pins at the end of a clock cycle, including those of an interrupt
+    /// sequence. There is one per cycle only with [`CPU::cycle_accurate`]
+    /// set; otherwise the dummy accesses are missing.
+    ///
+    /// [`CPU::cycle_accurate`]: crate::cpu::CPU::cycle_accurate
+    fn on_cycle(&mut self, _cycle: BusCycle) {}
+
+    /// An IRQ or NMI was taken, entering the handler at `handler`.
+    fn on_irq(&mut self, _handler: u16) {}
+
+    fn on_reset(&mut self) {}
+}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        (**self).on_instruction(event);
+    }
+
+    fn on_read(&mut self, address: u16, value: u8) {
+        (**self).on_read(address, value);
+    }
+
+    fn on_write(&mut self, address: u16, value: u8) {
+        (**self).on_write(address, value);
+    }
+
+    fn on_cycle(&mut self, cycle: BusCycle) {
+        (**self).on_cycle(cycle);
+    }
+
+    fn on_irq(&mut self, handler: u16) {
+        (**self).on_irq(handler);
+    }
+
+    fn on_reset(&mut self) {
+        (**self).on_reset();
+    }
+}
+
+impl<T: Observer + ?Sized> Observer for Box<T> {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        (**self).on_instruction(event);
+    }
+
+    fn on_read(&mut self, address: u16, value: u8) {
+        (**self).on_read(address, value);
+    }
+
+    fn on_write(&mut self, address: u16, value: u8) {
+        (**self).on_write(address, value);
+    }
+
+    fn on_cycle(&mut self, cycle: BusCycle) {
+        (**self).on_cycle(cycle);
+    }
+
+    fn on_irq(&mut self, handler: u16) {
+        (**self).on_irq(handler);
+    }
+
+    fn on_reset(&mut self) {
+        (**self).on_reset();
+    }
+}
+
+/// Lets an observer be registered while its results stay accessible.
+impl<T: Observer + ?Sized> Observer for Rc<RefCell<T>> {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        self.borrow_mut().on_instruction(event);
+    }
+
+    fn on_read(&mut self, address: u16, value: u8) {
+        self.borrow_mut().on_read(address, value);
+    }
+
+    fn on_write(&mut self, address: u16, value: u8) {
+        self.borrow_mut().on_write(address, value);
+    }
+
+    fn on_cycle(&mut self, cycle: BusCycle) {
+        self.borrow_mut().on_cycle(cycle);
+    }
+
+    fn on_irq(&mut self, handler: u16) {
+        self.borrow_mut().on_irq(handler);
+    }
+
+    fn on_reset(&mut self) {
+        self.borrow_mut().on_reset();
+    }
+}
+
+impl Observer for Coverage {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        self.record(event.pc);
+    }
+}
+
+impl Observer for Profile {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        self.record(
+            event.pc,
+            event.opcode,
+            event.instruction.0,
+            event.cycles,
+            event.next_pc,
+        );
+    }
+}
+
+/// An event forwarded by a [`ChannelObserver`].
+// Events are sent one at a time, so the size of the largest doesn't matter.
+#[allow(variant_size_differences)]
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub enum Event {
+    Instruction(InstructionEvent),
+    Read { address: u16, value: u8 },
+    Write { address: u16, value: u8 },
+    Cycle(BusCycle),
+    Irq { handler: u16 },
+    Reset,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+enum EventSender {
+    Unbounded(Sender<Event>),
+    Bounded(SyncSender<Event>),
+}
+
+/// Forwards events into a channel, to be consumed by another thread.
+///
+/// Sending never blocks the emulation. A bounded channel drops the events
+/// that don't fit and counts them; once the receiver is gone, events are
+/// discarded.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::machine::Machine;
+/// use mos6502::memory::Memory;
+/// use mos6502::observer::{ChannelObserver, Event};
+///
+/// let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+/// let (observer, events) = ChannelObserver::unbounded();
+/// machine.add_observer(observer);
+///
+/// let logger = std::thread::spawn(move || {
+///     events
+///         .iter()
+///         .filter(|event| matches!(event, Event::Instruction(_)))
+///         .count()
+/// });
+/// machine.run(Some(10));
+/// drop(machine);
+/// assert_eq!(logger.join().unwrap(), 10);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ChannelObserver {
+    sender: EventSender,
+    dropped: u64,
+}
+
+#[cfg(feature = "std")]
+impl ChannelObserver {
+    /// Forwards every event, buffering as many as the receiver falls
+    /// behind by.
+    #[must_use]
+    pub fn unbounded() -> (ChannelObserver, Receiver<Event>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            ChannelObserver::new(EventSender::Unbounded(sender)),
+            receiver,
+        )
+    }
+
+    /// Buffers up to `capacity` events and drops the rest until the
+    /// receiver catches up.
+    #[must_use]
+    pub fn bounded(capacity: usize) -> (ChannelObserver, Receiver<Event>) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        (ChannelObserver::new(EventSender::Bounded(sender)), receiver)
+    }
+
+    const fn new(sender: EventSender) -> ChannelObserver {
+        ChannelObserver { sender, dropped: 0 }
+    }
+
+    /// Number of events dropped because a bounded channel was full.
+    #[must_use]
+    pub const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn send(&mut self, event: Event) {
+        match &self.sender {
+            EventSender::Unbounded(sender) => {
+                // Nobody is listening any more.
+                let _ = sender.send(event);
+            }
+            EventSender::Bounded(sender) => {
+                if let Err(TrySendError::Full(_)) = sender.try_send(event) {
+                    self.dropped += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Observer for ChannelObserver {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        self.send(Event::Instruction(*event));
+    }
+
+    fn on_read(&mut self, address: u16, value: u8) {
+        self.send(Event::Read { address, value });
+    }
+
+    fn on_write(&mut self, address: u16, value: u8) {
+        self.send(Event::Write { address, value });
+    }
+
+    fn on_cycle(&mut self, cycle: BusCycle) {
+        self.send(Event::Cycle(cycle));
+    }
+
+    fn on_irq(&mut self, handler: u16) {
+        self.send(Event::Irq { handler });
+    }
+
+    fn on_reset(&mut self) {
+        self.send(Event::Reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::machine::Machine;
+    use crate::memory::{Bus, Memory};
+    use crate::registers::{StackPointer, Status};
+    use alloc::vec::Vec;
+
+    #[derive(Default)]
+    struct Log {
+        reads: Vec<(u16, u8)>,
+        writes: Vec<(u16, u8)>,
+        cycles: Vec<BusCycle>,
+        instructions: Vec<u16>,
+        irqs: Vec<u16>,
+        resets: usize,
+    }
+
+    impl Observer for Log {
+        fn on_instruction(&mut self, event: &InstructionEvent) {
+            self.instructions.push(event.pc);
+        }
+
+        fn on_read(&mut self, address: u16, value: u8) {
+            self.reads.push((address, value));
+        }
+
+        fn on_write(&mut self, address: u16, value: u8) {
+            self.writes.push((address, value));
+        }
+
+        fn on_cycle(&mut self, cycle: BusCycle) {
+            self.cycles.push(cycle);
+        }
+
+        fn on_irq(&mut self, handler: u16) {
+            self.irqs.push(handler);
+        }
+
+        fn on_reset(&mut self) {
+            self.resets += 1;
+        }
+    }
+
+    #[test]
+    fn observers_see_every_event() {
+        let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+        // LDA $10; INC $11; NOP
+        machine
+            .cpu
+            .memory
+            .set_bytes(0x0000, &[0xa5, 0x10, 0xe6, 0x11, 0xea]);
+        machine.cpu.memory.set_bytes(0x0010, &[0x42, 0x07]);
+        machine.cpu.memory.set_bytes(0xfffe, &[0x00, 0x03]);
+        machine.cpu.registers.stack_pointer = StackPointer(0xff);
+        machine
+            .cpu
+            .registers
+            .status
+            .remove(Status::PS_DISABLE_INTERRUPTS);
+        let log = Rc::new(RefCell::new(Log::default()));
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        machine.add_observer(Rc::clone(&log));
+        machine.add_observer(Rc::clone(&coverage));
+
+        machine.step();
+        machine.step();
+        machine.cpu.set_irq(true);
+        machine.step();
+        machine.reset();
+
+        let log = log.borrow();
+        assert_eq!(log.instructions, [0x0000, 0x0002, 0x0004]);
+        assert_eq!(
+            log.reads[..5],
+            [
+                (0x0000, 0xa5),
+                (0x0001, 0x10),
+                (0x0010, 0x42),
+                (0x0002, 0xe6),
+                (0x0003, 0x11)
+            ]
+        );
+        // The INC, then the return address and status pushed for the IRQ.
+        assert_eq!(
+            log.writes,
+            [
+                (0x0011, 0x08),
+                (0x01ff, 0x00),
+                (0x01fe, 0x05),
+                (0x01fd, 0x20)
+            ]
+        );
+        assert_eq!(log.irqs, [0x0300]);
+        assert_eq!(log.resets, 1);
+        assert_eq!(coverage.borrow().covered(), 3);
+    }
+
+    #[test]
+    fn cycles_carry_the_pin_state() {
+        let mut machine = Machine::new(CPU::new(Memory::new(), Nmos6502));
+        machine.cpu.cycle_accurate = true;
+        // INC $10
+        machine.cpu.memory.set_bytes(0x0000, &[0xe6, 0x10]);
+        let log = Rc::new(RefCell::new(Log::default()));
+        machine.add_observer(Rc::clone(&log));
+
+        machine.step();
+
+        let log = log.borrow();
+        let pins: Vec<_> = log
+            .cycles
+            .iter()
+            .map(|cycle| (cycle.address, cycle.data, cycle.read_write(), cycle.sync))
+            .collect();
+        assert_eq!(
+            pins,
+            [
+                (0x0000, 0xe6, true, true),
+                (0x0001, 0x10, true, false),
+                (0x0010, 0x00, true, false),
+                (0x0010, 0x00, false, false),
+                (0x0010, 0x01, false, false),
+            ]
+        );
+        assert_eq!(log.cycles.len() as u64, machine.cpu.cycles);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn bounded_channel_drops_what_does_not_fit() {
+        let (mut observer, events) = ChannelObserver::bounded(2);
+        observer.on_write(0x0010, 1);
+        observer.on_write(0x0011, 2);
+        observer.on_reset();
+        assert_eq!(observer.dropped(), 1);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(Event::Write {
+                address: 0x0010,
+                value: 1
+            })
+        ));
+        observer.on_reset();
+        assert_eq!(observer.dropped(), 1);
+        assert_eq!(events.try_iter().count(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Running at the speed of the real hardware.
+//!
+//! The emulator normally runs as fast as the host allows. Interactive
+//! systems need authentic speed instead: a [`Pacer`] compares the cycle
+//! counter with the host clock and sleeps whenever the emulation gets ahead
+//! of the target frequency.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How far ahead of the host clock the emulation may get before sleeping.
+/// Host sleeps are coarse, so pacing is done in slices of about this size.
+const SLICE: Duration = Duration::from_millis(1);
+
+/// How far behind the host clock the emulation may fall before the pacer
+/// gives up catching up, e.g. after the process was suspended.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Throttles execution to a target clock frequency.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::Memory;
+/// use mos6502::pacing::Pacer;
+///
+/// let mut cpu = CPU::new(Memory::new(), Nmos6502);
+/// let mut pacer = Pacer::new(Pacer::APPLE_II);
+/// while cpu.single_step().is_some() {
+///     pacer.pace(cpu.cycles);
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pacer {
+    hz: u64,
+    /// Host time and cycle count that pacing is measured from.
+    origin: Option<(Instant, u64)>,
+    /// Cycle count at which the host clock is checked next.
+    next_check: u64,
+}
+
+impl Pacer {
+    /// The Apple II and NTSC Commodore 64 clock, 1.023 MHz.
+    pub const APPLE_II: u64 = 1_022_727;
+    /// The PAL Commodore 64 clock, 0.985 MHz.
+    pub const C64_PAL: u64 = 985_248;
+    /// The NTSC NES CPU clock, 1.79 MHz.
+    pub const NES_NTSC: u64 = 1_789_773;
+    /// The PAL NES CPU clock, 1.66 MHz.
+    pub const NES_PAL: u64 = 1_662_607;
+
+    /// Paces execution to `hz` cycles per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hz` is zero.
+    #[must_use]
+    pub const fn new(hz: u64) -> Pacer {
+        assert!(hz > 0, "clock frequency must not be zero");
+        Pacer {
+            hz,
+            origin: None,
+            next_check: 0,
+        }
+    }
+
+    /// The target clock frequency in hertz.
+    #[must_use]
+    pub const fn hz(&self) -> u64 {
+        self.hz
+    }
+
+    /// Host time that `cycles` take at the target frequency.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn duration_of(&self, cycles: u64) -> Duration {
+        let nanos = cycles as u128 * 1_000_000_000 / self.hz as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Cycles that run in `duration` at the target frequency.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn cycles_in(&self, duration: Duration) -> u64 {
+        (duration.as_nanos() * self.hz as u128 / 1_000_000_000) as u64
+    }
+
+    /// Sleeps until the host clock catches up with the emulated clock at
+    /// `cycles`.
+    ///
+    /// Cheap enough to call after every instruction: the host clock is only
+    /// read about once per millisecond of emulated time. The first call
+    /// starts the clock.
+    pub fn pace(&mut self, cycles: u64) {
+        if cycles < self.next_check {
+            return;
+        }
+        self.next_check = cycles + self.cycles_in(SLICE);
+
+        let now = Instant::now();
+        let Some((start, start_cycles)) = self.origin else {
+            self.origin = Some((now, cycles));
+            return;
+        };
+        let target = start + self.duration_of(cycles.saturating_sub(start_cycles));
+        if let Some(ahead) = target.checked_duration_since(now) {
+            thread::sleep(ahead);
+        } else if now - target > MAX_LAG {
+            // Running flat out would not catch up in any useful way;
+            // continue at the target speed from here.
+            self.origin = Some((now, cycles));
+        }
+    }
+
+    /// Forgets the clock origin, e.g. after the emulation was paused. The
+    /// next call to [`Pacer::pace`] starts the clock again.
+    pub const fn reset(&mut self) {
+        self.origin = None;
+        self.next_check = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_between_cycles_and_time() {
+        let pacer = Pacer::new(Pacer::NES_NTSC);
+        assert_eq!(pacer.cycles_in(Duration::from_secs(1)), 1_789_773);
+        assert_eq!(pacer.duration_of(1_789_773), Duration::from_secs(1));
+        assert_eq!(pacer.duration_of(0), Duration::ZERO);
+    }
+
+    #[test]
+    fn sleeps_when_ahead() {
+        let mut pacer = Pacer::new(10_000);
+        let start = Instant::now();
+        pacer.pace(0);
+        // 300 cycles at 10 kHz take 30 ms.
+        for cycles in (0..=300).step_by(5) {
+            pacer.pace(cycles);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(25));
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Measuring where a program spends its time.
+//!
+//! [`Profile`] keeps a histogram of the opcodes a program executed, which
+//! gives its instruction mix and shows which opcodes a test program never
+//! exercised. It also counts executions and cycles per address and per
+//! subroutine, so the [hottest](Profile::hottest_addresses) parts of a
+//! program stand out as optimization targets.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cpu::CPU;
+use crate::instruction::{DecodedInstr, Instruction};
+use crate::memory::Bus;
+use crate::Variant;
+
+/// Deepest subroutine nesting tracked; deeper calls are attributed to the
+/// innermost tracked routine.
+const MAX_DEPTH: usize = 256;
+
+/// What to rank hotspots by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Number of instructions executed, or calls for a routine.
+    Count,
+    /// Number of cycles spent.
+    Cycles,
+}
+
+/// An address or routine and the time spent in it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Hotspot {
+    pub address: u16,
+    /// Executions of the instruction at `address`, or calls of the routine
+    /// starting there.
+    pub count: u64,
+    /// Cycles spent at `address`, or in the routine's own instructions
+    /// (excluding the routines it calls).
+    pub cycles: u64,
+}
+
+impl Hotspot {
+    const fn value(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::Count => self.count,
+            Metric::Cycles => self.cycles,
+        }
+    }
+}
+
+/// Execution statistics gathered while a program runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    opcodes: [u64; 256],
+    counts: Vec<u64>,
+    cycles: Vec<u64>,
+    /// Calls and cycles per routine entry point.
+    routines: BTreeMap<u16, (u64, u64)>,
+    /// Entry points of the routines currently executing, innermost last.
+    calls: Vec<u16>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::new()
+    }
+}
+
+impl Profile {
+    #[must_use]
+    pub fn new() -> Profile {
+        Profile {
+            opcodes: [0; 256],
+            counts: vec![0; 0x10000],
+            cycles: vec![0; 0x10000],
+            routines: BTreeMap::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Records `instruction`, encoded as `opcode` at `pc`, which took
+    /// `cycles` cycles and left PC at `next_pc`.
+    pub fn record(
+        &mut self,
+        pc: u16,
+        opcode: u8,
+        instruction: Instruction,
+        cycles: u64,
+        next_pc: u16,
+    ) {
+        let count = &mut self.opcodes[usize::from(opcode)];
+        *count = count.saturating_add(1);
+        self.counts[usize::from(pc)] += 1;
+        self.cycles[usize::from(pc)] += cycles;
+        if let Some(routine) = self.calls.last() {
+            self.routines.entry(*routine).or_default().1 += cycles;
+        }
+
+        match instruction {
+            Instruction::JSR => {
+                if self.calls.len() == MAX_DEPTH {
+                    self.calls.remove(0);
+                }
+                self.calls.push(next_pc);
+                self.routines.entry(next_pc).or_default().0 += 1;
+            }
+            Instruction::RTS => {
+                self.calls.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Executes one instruction on `cpu`, recording it.
+    pub fn step<M: Bus, V: Variant>(&mut self, cpu: &mut CPU<M, V>) -> Option<DecodedInstr> {
+        let pc = cpu.registers.program_counter;
+        let opcode = cpu.memory.get_byte(pc);
+        let start = cpu.cycles;
+        let decoded = cpu.single_step();
+        if let Some((instruction, _)) = decoded {
+            let next_pc = cpu.registers.program_counter;
+            self.record(pc, opcode, instruction, cpu.cycles - start, next_pc);
+        }
+        decoded
+    }
+
+    /// How many times `opcode` was executed.
+    #[must_use]
+    pub const fn opcode_count(&self, opcode: u8) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// The opcodes executed at least once, with their counts, in opcode
+    /// order.
+    pub fn opcodes(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        (0..=u8::MAX)
+            .map(|opcode| (opcode, self.opcode_count(opcode)))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    /// Total number of instructions recorded.
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Total number of cycles recorded.
+    #[must_use]
+    pub fn cycles(&self) -> u64 {
+        self.cycles.iter().sum()
+    }
+
+    /// Execution counts per mnemonic, combining the opcodes of every
+    /// addressing mode as decoded by `V`, busiest first.
+    #[must_use]
+    pub fn instruction_mix<V: Variant>(&self) -> Vec<(&'static str, u64)> {
+        let mut mix: Vec<(&'static str, u64)> = Vec::new();
+        for (opcode, count) in self.opcodes() {
+            let Some((instruction, _)) = V::decode(opcode) else {
+                continue;
+            };
+            let mnemonic = instruction.mnemonic();
+            match mix.iter_mut().find(|(name, _)| *name == mnemonic) {
+                Some((_, total)) => *total += count,
+                None => mix.push((mnemonic, count)),
+            }
+        }
+        mix.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        mix
+    }
+
+    /// How many times the instruction at `address` was executed, and the
+    /// cycles it took in total.
+    #[must_use]
+    pub fn address(&self, address: u16) -> Hotspot {
+        Hotspot {
+            address,
+            count: self.counts[usize::from(address)],
+            cycles: self.cycles[usize::from(address)],
+        }
+    }
+
+    /// The `n` addresses with the highest `metric`, highest first.
+    #[must_use]
+    pub fn hottest_addresses(&self, n: usize, metric: Metric) -> Vec<Hotspot> {
+        let spots = (0..=u16::MAX)
+            .map(|address| self.address(address))
+            .filter(|spot| spot.count > 0);
+        top(spots, n, metric)
+    }
+
+    /// The `n` subroutines, identified by the target of the `JSR` that
+    /// called them, with the highest `metric`, highest first.
+    #[must_use]
+    pub fn hottest_routines(&self, n: usize, metric: Metric) -> Vec<Hotspot> {
+        let spots = self
+            .routines
+            .iter()
+            .map(|(&address, &(count, cycles))| Hotspot {
+                address,
+                count,
+                cycles,
+            });
+        top(spots, n, metric)
+    }
+
+    /// Forgets everything recorded.
+    pub fn clear(&mut self) {
+        self.opcodes.fill(0);
+        self.counts.fill(0);
+        self.cycles.fill(0);
+        self.routines.clear();
+        self.calls.clear();
+    }
+}
+
+fn top(spots: impl Iterator<Item = Hotspot>, n: usize, metric: Metric) -> Vec<Hotspot> {
+    let mut spots: Vec<Hotspot> = spots.collect();
+    spots.sort_by(|a, b| {
+        b.value(metric)
+            .cmp(&a.value(metric))
+            .then(a.address.cmp(&b.address))
+    });
+    spots.truncate(n);
+    spots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn counts_opcodes_and_mnemonics() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        // LDX #$03; loop: DEX; BNE loop; LDA #$00; LDA $10
+        cpu.memory.set_bytes(
+            0x0000,
+            &[0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xa9, 0x00, 0xa5, 0x10],
+        );
+        let mut profile = Profile::new();
+        while cpu.registers.program_counter != 0x0009 {
+            profile.step(&mut cpu);
+        }
+
+        assert_eq!(profile.opcode_count(0xca), 3);
+        assert_eq!(profile.opcode_count(0xd0), 3);
+        assert_eq!(profile.opcode_count(0xea), 0);
+        assert_eq!(profile.instructions(), 9);
+        assert_eq!(
+            profile.instruction_mix::<Nmos6502>(),
+            [("BNE", 3), ("DEX", 3), ("LDA", 2), ("LDX", 1)]
+        );
+    }
+
+    #[test]
+    fn hottest_addresses_and_routines() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        //         LDY #$02
+        // outer:  JSR delay
+        //         DEY
+        //         BNE outer
+        //         BRK
+        // delay:  LDX #$04
+        // loop:   DEX
+        //         BNE loop
+        //         RTS
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0xa0, 0x02, 0x20, 0x09, 0x02, 0x88, 0xd0, 0xfa, 0x00, 0xa2, 0x04, 0xca, 0xd0, 0xfd,
+                0x60,
+            ],
+        );
+        cpu.registers.program_counter = 0x0200;
+        let mut profile = Profile::new();
+        while cpu.registers.program_counter != 0x0208 {
+            profile.step(&mut cpu);
+        }
+
+        let by_count = profile.hottest_addresses(2, Metric::Count);
+        assert_eq!(by_count[0].address, 0x020B);
+        assert_eq!(by_count[0].count, 8);
+        assert_eq!(by_count[1].address, 0x020C);
+        // BNE loop: three taken (3 cycles) and one not (2) per call.
+        assert_eq!(by_count[1].cycles, 2 * (3 * 3 + 2));
+
+        let by_cycles = profile.hottest_addresses(1, Metric::Cycles);
+        assert_eq!(by_cycles[0].address, 0x020C);
+
+        // Per call: LDX 2, DEX 4 * 2, BNE 11 and RTS 6.
+        let routines = profile.hottest_routines(5, Metric::Cycles);
+        assert_eq!(
+            routines,
+            [Hotspot {
+                address: 0x0209,
+                count: 2,
+                cycles: 2 * (2 + 8 + 11 + 6),
+            }]
+        );
+    }
+}
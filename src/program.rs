@@ -0,0 +1,519 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Building programs in Rust, one instruction at a time.
+//!
+//! [`Program`] has a method for every instruction and addressing mode, so
+//! tests and examples can write a program without an assembler source:
+//!
+//! ```
+//! use mos6502::program::Program;
+//!
+//! let mut program = Program::new(0x0200);
+//! program
+//!     .ldx_imm(8)
+//!     .label("loop")
+//!     .dex()
+//!     .bne("loop")
+//!     .stx(0x0300)
+//!     .brk();
+//! let assembly = program.build().unwrap();
+//! assert_eq!(
+//!     assembly.bytes,
+//!     [0xa2, 0x08, 0xca, 0xd0, 0xfd, 0x8e, 0x00, 0x03, 0x00]
+//! );
+//! assert_eq!(assembly.symbols.address_of("loop"), Some(0x0202));
+//! ```
+//!
+//! Methods are named after the mnemonic, with a suffix for the addressing
+//! mode: none for absolute addresses, branches and implied operands, `_a`
+//! for the accumulator, `_imm` for immediate values, `_zp`, `_zp_x` and
+//! `_zp_y` for zero page, `_x` and `_y` for indexed absolute addresses,
+//! `_ind_x`, `_ind_y` and `_ind` for indirect zero page pointers, and
+//! `jmp_ind` and `jmp_ind_x` for indirect jumps. Absolute addresses and branch targets
+//! are either numbers or the names of labels, which may be defined after
+//! they are used.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::asm::{opcode, Assembly};
+use crate::instruction::{AddressingMode, Nmos6502};
+use crate::symbols::SymbolTable;
+use crate::Variant;
+
+/// An address, or the label naming it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Address(u16),
+    Label(String),
+}
+
+impl From<u16> for Target {
+    fn from(address: u16) -> Target {
+        Target::Address(address)
+    }
+}
+
+impl From<&str> for Target {
+    fn from(label: &str) -> Target {
+        Target::Label(label.into())
+    }
+}
+
+impl From<String> for Target {
+    fn from(label: String) -> Target {
+        Target::Label(label)
+    }
+}
+
+/// Error raised when a program can't be built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The variant has no such instruction.
+    Unsupported {
+        mnemonic: &'static str,
+        mode: AddressingMode,
+    },
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    /// The branch at `at` can't reach `target`.
+    BranchOutOfRange {
+        at: u16,
+        target: u16,
+    },
+    /// The program runs past $FFFF.
+    TooLarge,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Unsupported { mnemonic, mode } => {
+                write!(f, "{mnemonic} has no {mode:?} addressing mode")
+            }
+            BuildError::DuplicateLabel(label) => write!(f, "label `{label}` is defined twice"),
+            BuildError::UndefinedLabel(label) => write!(f, "label `{label}` is not defined"),
+            BuildError::BranchOutOfRange { at, target } => {
+                write!(f, "branch at ${at:04X} can't reach ${target:04X}")
+            }
+            BuildError::TooLarge => f.write_str("program runs past the end of memory"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BuildError {}
+
+enum Operand {
+    None,
+    Byte(u8),
+    Target(Target),
+}
+
+/// A target to fill in once every label is known.
+#[derive(Debug)]
+struct Fixup {
+    /// Offset of the operand in the program.
+    offset: usize,
+    target: Target,
+    relative: bool,
+}
+
+/// A program under construction, for the variant `V`.
+#[derive(Debug)]
+pub struct Program<V = Nmos6502> {
+    origin: u16,
+    bytes: Vec<u8>,
+    symbols: SymbolTable,
+    fixups: Vec<Fixup>,
+    /// The first error, reported by [`Program::build`].
+    error: Option<BuildError>,
+    variant: PhantomData<V>,
+}
+
+impl Program {
+    /// Starts an NMOS 6502 program at `origin`.
+    #[must_use]
+    pub const fn new(origin: u16) -> Self {
+        Self::for_variant(origin)
+    }
+}
+
+/// Defines methods for instructions without an operand.
+macro_rules! implied {
+    ($($method:ident $mnemonic:literal),* $(,)?) => {$(
+        #[doc = concat!("`", $mnemonic, "`")]
+        pub fn $method(&mut self) -> &mut Self {
+            self.instruction($mnemonic, &[AddressingMode::Implied], Operand::None)
+        }
+    )*};
+}
+
+/// Defines methods for instructions operating on the accumulator.
+macro_rules! accumulator {
+    ($($method:ident $mnemonic:literal),* $(,)?) => {$(
+        #[doc = concat!("`", $mnemonic, " A`")]
+        pub fn $method(&mut self) -> &mut Self {
+            self.instruction($mnemonic, &[AddressingMode::Accumulator], Operand::None)
+        }
+    )*};
+}
+
+/// Defines methods for instructions with a one-byte operand.
+macro_rules! byte {
+    ($mode:ident, $syntax:literal: $($method:ident $mnemonic:literal),* $(,)?) => {$(
+        #[doc = concat!("`", $mnemonic, " ", $syntax, "`")]
+        pub fn $method(&mut self, value: u8) -> &mut Self {
+            self.instruction($mnemonic, &[AddressingMode::$mode], Operand::Byte(value))
+        }
+    )*};
+}
+
+/// Defines methods for instructions addressing a [`Target`].
+macro_rules! target {
+    ($modes:expr, $syntax:literal: $($method:ident $mnemonic:literal),* $(,)?) => {$(
+        #[doc = concat!("`", $mnemonic, " ", $syntax, "`")]
+        pub fn $method(&mut self, target: impl Into<Target>) -> &mut Self {
+            self.instruction($mnemonic, $modes, Operand::Target(target.into()))
+        }
+    )*};
+}
+
+impl<V: Variant> Program<V> {
+    /// Starts a program for the variant `V` at `origin`.
+    #[must_use]
+    pub const fn for_variant(origin: u16) -> Self {
+        Program {
+            origin,
+            bytes: Vec::new(),
+            symbols: SymbolTable::new(),
+            fixups: Vec::new(),
+            error: None,
+            variant: PhantomData,
+        }
+    }
+
+    /// The address of the next byte to be emitted.
+    #[must_use]
+    pub const fn address(&self) -> u16 {
+        #[allow(clippy::cast_possible_truncation)]
+        self.origin.wrapping_add(self.bytes.len() as u16)
+    }
+
+    /// Names the address of the next byte.
+    pub fn label(&mut self, name: &str) -> &mut Self {
+        let address = self.address();
+        self.define(name, address)
+    }
+
+    /// Names `address`, for use as a target.
+    pub fn define(&mut self, name: &str, address: u16) -> &mut Self {
+        if self.symbols.address_of(name).is_some() {
+            self.fail(BuildError::DuplicateLabel(name.into()));
+        } else {
+            self.symbols.insert(name, address);
+        }
+        self
+    }
+
+    pub fn byte(&mut self, value: u8) -> &mut Self {
+        self.bytes.push(value);
+        self
+    }
+
+    pub fn bytes(&mut self, values: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(values);
+        self
+    }
+
+    /// A little-endian word holding an address, such as a vector.
+    pub fn word(&mut self, target: impl Into<Target>) -> &mut Self {
+        self.fixups.push(Fixup {
+            offset: self.bytes.len(),
+            target: target.into(),
+            relative: false,
+        });
+        self.bytes(&[0, 0])
+    }
+
+    implied!(
+        brk "BRK", clc "CLC", cld "CLD", cli "CLI", clv "CLV", dex "DEX", dey "DEY",
+        inx "INX", iny "INY", nop "NOP", pha "PHA", php "PHP", phx "PHX", phy "PHY",
+        pla "PLA", plp "PLP", plx "PLX", ply "PLY", rti "RTI", rts "RTS", sec "SEC",
+        sed "SED", sei "SEI", tax "TAX", tay "TAY", tsx "TSX", txa "TXA", txs "TXS",
+        tya "TYA",
+    );
+
+    accumulator!(
+        asl_a "ASL", dec_a "DEC", inc_a "INC", lsr_a "LSR", rol_a "ROL", ror_a "ROR",
+    );
+
+    byte!(Immediate, "#value":
+        adc_imm "ADC", and_imm "AND", bit_imm "BIT", cmp_imm "CMP", cpx_imm "CPX",
+        cpy_imm "CPY", eor_imm "EOR", lda_imm "LDA", ldx_imm "LDX", ldy_imm "LDY",
+        ora_imm "ORA", sbc_imm "SBC",
+    );
+
+    byte!(ZeroPage, "zp":
+        adc_zp "ADC", and_zp "AND", asl_zp "ASL", bit_zp "BIT", cmp_zp "CMP",
+        cpx_zp "CPX", cpy_zp "CPY", dec_zp "DEC", eor_zp "EOR", inc_zp "INC",
+        lda_zp "LDA", ldx_zp "LDX", ldy_zp "LDY", lsr_zp "LSR", ora_zp "ORA",
+        rol_zp "ROL", ror_zp "ROR", sbc_zp "SBC", sta_zp "STA", stx_zp "STX",
+        sty_zp "STY", stz_zp "STZ", trb_zp "TRB", tsb_zp "TSB",
+    );
+
+    byte!(ZeroPageX, "zp,X":
+        adc_zp_x "ADC", and_zp_x "AND", asl_zp_x "ASL", bit_zp_x "BIT", cmp_zp_x "CMP",
+        dec_zp_x "DEC", eor_zp_x "EOR", inc_zp_x "INC", lda_zp_x "LDA", ldy_zp_x "LDY",
+        lsr_zp_x "LSR", ora_zp_x "ORA", rol_zp_x "ROL", ror_zp_x "ROR", sbc_zp_x "SBC",
+        sta_zp_x "STA", sty_zp_x "STY", stz_zp_x "STZ",
+    );
+
+    byte!(ZeroPageY, "zp,Y": ldx_zp_y "LDX", stx_zp_y "STX");
+
+    byte!(IndexedIndirectX, "(zp,X)":
+        adc_ind_x "ADC", and_ind_x "AND", cmp_ind_x "CMP", eor_ind_x "EOR",
+        lda_ind_x "LDA", ora_ind_x "ORA", sbc_ind_x "SBC", sta_ind_x "STA",
+    );
+
+    byte!(IndirectIndexedY, "(zp),Y":
+        adc_ind_y "ADC", and_ind_y "AND", cmp_ind_y "CMP", eor_ind_y "EOR",
+        lda_ind_y "LDA", ora_ind_y "ORA", sbc_ind_y "SBC", sta_ind_y "STA",
+    );
+
+    byte!(ZeroPageIndirect, "(zp)":
+        adc_ind "ADC", and_ind "AND", cmp_ind "CMP", eor_ind "EOR",
+        lda_ind "LDA", ora_ind "ORA", sbc_ind "SBC", sta_ind "STA",
+    );
+
+    target!(&[AddressingMode::Absolute], "address":
+        adc "ADC", and "AND", asl "ASL", bit "BIT", cmp "CMP", cpx "CPX", cpy "CPY",
+        dec "DEC", eor "EOR", inc "INC", jmp "JMP", jsr "JSR", lda "LDA", ldx "LDX",
+        ldy "LDY", lsr "LSR", ora "ORA", rol "ROL", ror "ROR", sbc "SBC", sta "STA",
+        stx "STX", sty "STY", stz "STZ", trb "TRB", tsb "TSB",
+    );
+
+    target!(&[AddressingMode::AbsoluteX], "address,X":
+        adc_x "ADC", and_x "AND", asl_x "ASL", bit_x "BIT", cmp_x "CMP", dec_x "DEC",
+        eor_x "EOR", inc_x "INC", lda_x "LDA", ldy_x "LDY", lsr_x "LSR", ora_x "ORA",
+        rol_x "ROL", ror_x "ROR", sbc_x "SBC", sta_x "STA", stz_x "STZ",
+    );
+
+    target!(&[AddressingMode::AbsoluteY], "address,Y":
+        adc_y "ADC", and_y "AND", cmp_y "CMP", eor_y "EOR", lda_y "LDA", ldx_y "LDX",
+        ora_y "ORA", sbc_y "SBC", sta_y "STA",
+    );
+
+    target!(&[AddressingMode::Indirect, AddressingMode::BuggyIndirect], "(address)": jmp_ind "JMP");
+
+    target!(&[AddressingMode::AbsoluteIndexedIndirect], "(address,X)": jmp_ind_x "JMP");
+
+    target!(&[AddressingMode::Relative], "label":
+        bcc "BCC", bcs "BCS", beq "BEQ", bmi "BMI", bne "BNE", bpl "BPL", bra "BRA",
+        bvc "BVC", bvs "BVS",
+    );
+
+    /// Resolves every target and returns the program.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first instruction the variant doesn't have, or the first
+    /// target that can't be resolved.
+    pub fn build(&self) -> Result<Assembly, BuildError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        if usize::from(self.origin) + self.bytes.len() > 0x10000 {
+            return Err(BuildError::TooLarge);
+        }
+
+        let mut bytes = self.bytes.clone();
+        for fixup in &self.fixups {
+            let target = match &fixup.target {
+                Target::Address(address) => *address,
+                Target::Label(label) => self
+                    .symbols
+                    .address_of(label)
+                    .ok_or_else(|| BuildError::UndefinedLabel(label.clone()))?,
+            };
+            #[allow(clippy::cast_possible_truncation)]
+            let at = self.origin.wrapping_add(fixup.offset as u16);
+            if fixup.relative {
+                // The offset counts from the instruction after the branch.
+                let offset = i32::from(target) - (i32::from(at) + 1);
+                let at = at - 1;
+                let offset = i8::try_from(offset)
+                    .map_err(|_| BuildError::BranchOutOfRange { at, target })?;
+                bytes[fixup.offset] = offset.to_le_bytes()[0];
+            } else {
+                bytes[fixup.offset..fixup.offset + 2].copy_from_slice(&target.to_le_bytes());
+            }
+        }
+
+        Ok(Assembly {
+            origin: self.origin,
+            bytes,
+            symbols: self.symbols.clone(),
+            listing: vec![],
+        })
+    }
+
+    fn instruction(
+        &mut self,
+        mnemonic: &'static str,
+        modes: &[AddressingMode],
+        operand: Operand,
+    ) -> &mut Self {
+        let Some((opcode, mode)) = modes
+            .iter()
+            .find_map(|mode| opcode::<V>(mnemonic, *mode).map(|opcode| (opcode, *mode)))
+        else {
+            let mode = modes[0];
+            return self.fail(BuildError::Unsupported { mnemonic, mode });
+        };
+
+        self.bytes.push(opcode);
+        match operand {
+            Operand::None => {}
+            Operand::Byte(value) => self.bytes.push(value),
+            Operand::Target(target) => {
+                let relative = mode == AddressingMode::Relative;
+                self.fixups.push(Fixup {
+                    offset: self.bytes.len(),
+                    target,
+                    relative,
+                });
+                self.bytes
+                    .resize(self.bytes.len() + if relative { 1 } else { 2 }, 0);
+            }
+        }
+        self
+    }
+
+    fn fail(&mut self, error: BuildError) -> &mut Self {
+        self.error.get_or_insert(error);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::instruction::Cmos6502;
+
+    #[test]
+    fn matches_the_assembler() {
+        let mut program = Program::new(0xc000);
+        program
+            .define("screen", 0x0400)
+            .label("reset")
+            .ldy_imm(0)
+            .label("copy")
+            .lda_y("text")
+            .beq("done")
+            .sta_y("screen")
+            .iny()
+            .bne("copy")
+            .label("done")
+            .jmp_ind("vector")
+            .label("vector")
+            .word("reset")
+            .label("text")
+            .bytes(b"hi")
+            .byte(0);
+        let built = program.build().unwrap();
+
+        let source = "
+            screen = $0400
+                    .org $C000
+            reset:  LDY #0
+            copy:   LDA text,Y
+                    BEQ done
+                    STA screen,Y
+                    INY
+                    BNE copy
+            done:   JMP (vector)
+            vector: .word reset
+            text:   .byte \"hi\", 0
+        ";
+        let assembled = assemble(source).unwrap();
+        assert_eq!(built.origin, assembled.origin);
+        assert_eq!(built.bytes, assembled.bytes);
+        assert_eq!(built.symbols, assembled.symbols);
+    }
+
+    #[test]
+    fn instructions_follow_the_variant() {
+        let mut nmos = Program::new(0x0200);
+        nmos.stz(0x0300);
+        assert_eq!(
+            nmos.build(),
+            Err(BuildError::Unsupported {
+                mnemonic: "STZ",
+                mode: AddressingMode::Absolute
+            })
+        );
+
+        let mut cmos = Program::<Cmos6502>::for_variant(0x0200);
+        cmos.stz(0x0300).lda_ind(0x10).bra("end").label("end");
+        assert_eq!(
+            cmos.build().unwrap().bytes,
+            [0x9c, 0x00, 0x03, 0xb2, 0x10, 0x80, 0x00]
+        );
+    }
+
+    #[test]
+    fn reports_bad_labels() {
+        let mut program = Program::new(0x0200);
+        program.jmp("nowhere");
+        assert_eq!(
+            program.build(),
+            Err(BuildError::UndefinedLabel("nowhere".into()))
+        );
+
+        program.label("twice").label("twice");
+        assert_eq!(
+            program.build(),
+            Err(BuildError::DuplicateLabel("twice".into()))
+        );
+
+        let mut program = Program::new(0x0200);
+        program.bne("far").bytes(&[0; 200]).label("far");
+        assert_eq!(
+            program.build(),
+            Err(BuildError::BranchOutOfRange {
+                at: 0x0200,
+                target: 0x02ca
+            })
+        );
+    }
+}
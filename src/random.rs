@@ -0,0 +1,183 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A memory-mapped random number generator.
+//!
+//! Games and demos often read a "random" register, such as `$FE` in the
+//! Easy6502 simulator. [`RandomByte`] maps one at any address, returning a
+//! new pseudo-random byte on every read. The sequence only depends on the
+//! seed, so runs stay reproducible: tests can pin the seed, and the
+//! generator's [`state`](RandomByte::state) can be saved and restored
+//! along with the rest of the machine for record and replay.
+
+use core::cell::Cell;
+use core::ops::Range;
+
+use crate::memory::{Bus, BusError};
+
+/// Added to the state before each output, as in `SplitMix64`.
+pub(crate) const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The `SplitMix64` finalizer, which turns consecutive inputs into unrelated
+/// outputs.
+pub(crate) const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A bus with a random byte register at one address. Writes to the
+/// register are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::random::RandomByte;
+///
+/// let first = RandomByte::new(Memory::new(), 0x00FE, 42);
+/// let second = RandomByte::new(Memory::new(), 0x00FE, 42);
+/// let bytes: Vec<u8> = (0..8).map(|_| first.get_byte(0x00FE)).collect();
+/// assert!((0..8).all(|i| second.get_byte(0x00FE) == bytes[i]));
+/// ```
+#[derive(Debug)]
+pub struct RandomByte<B: Bus> {
+    inner: B,
+    address: u16,
+    state: Cell<u64>,
+}
+
+impl<B: Bus> RandomByte<B> {
+    pub const fn new(inner: B, address: u16, seed: u64) -> Self {
+        RandomByte {
+            inner,
+            address,
+            state: Cell::new(seed),
+        }
+    }
+
+    #[must_use]
+    pub const fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The generator state, from which the sequence continues.
+    #[must_use]
+    pub const fn state(&self) -> u64 {
+        self.state.get()
+    }
+
+    /// Restarts the sequence from `state`, as returned by
+    /// [`RandomByte::state`] or passed as a seed.
+    pub const fn set_state(&mut self, state: u64) {
+        self.state = Cell::new(state);
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the device, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn next(&self) -> u8 {
+        let state = self.state.get().wrapping_add(GAMMA);
+        self.state.set(state);
+        mix(state).to_le_bytes()[0]
+    }
+}
+
+impl<B: Bus> Bus for RandomByte<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        if address == self.address {
+            self.next()
+        } else {
+            self.inner.get_byte(address)
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if address != self.address {
+            self.inner.set_byte(address, value);
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn state_resumes_the_sequence() {
+        let mut random = RandomByte::new(Memory::new(), 0xFE, 1);
+        random.get_byte(0xFE);
+        let state = random.state();
+        let expected = [random.get_byte(0xFE), random.get_byte(0xFE)];
+        random.set_state(state);
+        assert_eq!([random.get_byte(0xFE), random.get_byte(0xFE)], expected);
+        assert_ne!(expected[0], expected[1]);
+    }
+
+    #[test]
+    fn other_addresses_reach_memory() {
+        let mut random = RandomByte::new(Memory::new(), 0xFE, 1);
+        random.set_byte(0xFE, 0x12);
+        random.set_byte(0xFF, 0x34);
+        assert_eq!(random.inner().get_byte(0xFE), 0);
+        assert_eq!(random.get_byte(0xFF), 0x34);
+    }
+}
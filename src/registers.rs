@@ -25,6 +25,9 @@
 // ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
 // POSSIBILITY OF SUCH DAMAGE.
 
+use core::fmt;
+use core::str::FromStr;
+
 use bitflags::bitflags;
 
 // Useful for constructing Status instances
@@ -59,6 +62,7 @@ impl StatusArgs {
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Status: u8 {
         const PS_NEGATIVE           = 0b1000_0000;
         const PS_OVERFLOW           = 0b0100_0000;
@@ -118,6 +122,23 @@ impl Status {
         out
     }
 
+    /// The status as a byte, as pushed by `PHP` and interrupts or shown by
+    /// a debugger.
+    ///
+    /// Bit 5 is not stored on a real 6502 and always reads as set, whatever
+    /// this value holds internally.
+    #[must_use]
+    pub const fn to_byte(self) -> u8 {
+        self.bits() | Status::PS_UNUSED.bits()
+    }
+
+    /// The status loaded from a byte, as by `PLP` and `RTI`. Bit 5 stays set
+    /// even if `byte` has it clear.
+    #[must_use]
+    pub const fn from_byte(byte: u8) -> Status {
+        Status::from_bits_truncate(byte | Status::PS_UNUSED.bits())
+    }
+
     pub fn and(&mut self, rhs: Status) {
         *self &= rhs;
     }
@@ -147,7 +168,72 @@ impl Default for Status {
     }
 }
 
+/// One of the flags in the status register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Flag {
+    Negative,
+    Overflow,
+    Break,
+    Decimal,
+    InterruptDisable,
+    Zero,
+    Carry,
+}
+
+impl Flag {
+    /// Every flag, in the order of the status register from the top down.
+    pub const ALL: [Flag; 7] = [
+        Flag::Negative,
+        Flag::Overflow,
+        Flag::Break,
+        Flag::Decimal,
+        Flag::InterruptDisable,
+        Flag::Zero,
+        Flag::Carry,
+    ];
+
+    /// The flag's bit in the status register.
+    #[must_use]
+    pub const fn status(self) -> Status {
+        match self {
+            Flag::Negative => Status::PS_NEGATIVE,
+            Flag::Overflow => Status::PS_OVERFLOW,
+            Flag::Break => Status::PS_BRK,
+            Flag::Decimal => Status::PS_DECIMAL_MODE,
+            Flag::InterruptDisable => Status::PS_DISABLE_INTERRUPTS,
+            Flag::Zero => Status::PS_ZERO,
+            Flag::Carry => Status::PS_CARRY,
+        }
+    }
+}
+
+/// Flags written as `NV-BDIZC`, with a dot for each clear flag.
+pub(crate) struct Flags(pub(crate) Status);
+
+/// The bits of the status register from the top down, with their letters.
+const FLAG_NAMES: [(Status, char); 8] = [
+    (Status::PS_NEGATIVE, 'N'),
+    (Status::PS_OVERFLOW, 'V'),
+    (Status::PS_UNUSED, '-'),
+    (Status::PS_BRK, 'B'),
+    (Status::PS_DECIMAL_MODE, 'D'),
+    (Status::PS_DISABLE_INTERRUPTS, 'I'),
+    (Status::PS_ZERO, 'Z'),
+    (Status::PS_CARRY, 'C'),
+];
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (flag, name) in FLAG_NAMES {
+            let c = if self.0.contains(flag) { name } else { '.' };
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StackPointer(pub u8);
 
 impl StackPointer {
@@ -157,16 +243,17 @@ impl StackPointer {
         u16::from_le_bytes([val, 0x01])
     }
 
-    pub fn decrement(&mut self) {
+    pub const fn decrement(&mut self) {
         self.0 = self.0.wrapping_sub(1);
     }
 
-    pub fn increment(&mut self) {
+    pub const fn increment(&mut self) {
         self.0 = self.0.wrapping_add(1);
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Registers {
     pub accumulator: u8,
     pub index_x: u8,
@@ -183,9 +270,23 @@ impl Default for Registers {
 }
 
 impl Registers {
+    /// The registers as a 6502 leaves its power-on reset: the stack pointer
+    /// at `$FD`, three below where the reset sequence found it, and
+    /// interrupts disabled. A, X, Y and the other flags hold whatever they
+    /// powered up with on a real chip, and are zero here.
     #[must_use]
     pub fn new() -> Registers {
-        // TODO akeeton: Revisit these defaults.
+        Registers {
+            stack_pointer: StackPointer(0xfd),
+            ..Registers::zeroed()
+        }
+    }
+
+    /// Every register zero apart from the I flag and unused bit 5, for
+    /// emulations that want to start from a fixed state unrelated to any
+    /// real chip.
+    #[must_use]
+    pub fn zeroed() -> Registers {
         Registers {
             accumulator: 0,
             index_x: 0,
@@ -195,4 +296,251 @@ impl Registers {
             status: Status::default(),
         }
     }
+
+    // The accessors below stay the same if the fields change type or
+    // representation, so tooling and FFI layers should prefer them.
+
+    #[must_use]
+    pub const fn a(&self) -> u8 {
+        self.accumulator
+    }
+
+    #[must_use]
+    pub const fn x(&self) -> u8 {
+        self.index_x
+    }
+
+    #[must_use]
+    pub const fn y(&self) -> u8 {
+        self.index_y
+    }
+
+    /// The low byte of the stack pointer; the stack is always in page 1.
+    #[must_use]
+    pub const fn sp(&self) -> u8 {
+        self.stack_pointer.0
+    }
+
+    #[must_use]
+    pub const fn pc(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// The status as a byte, as pushed by `PHP`. See [`Status::to_byte`].
+    #[must_use]
+    pub const fn p(&self) -> u8 {
+        self.status.to_byte()
+    }
+
+    #[must_use]
+    pub const fn flag(&self, flag: Flag) -> bool {
+        self.status.contains(flag.status())
+    }
+
+    pub const fn set_a(&mut self, value: u8) {
+        self.accumulator = value;
+    }
+
+    pub const fn set_x(&mut self, value: u8) {
+        self.index_x = value;
+    }
+
+    pub const fn set_y(&mut self, value: u8) {
+        self.index_y = value;
+    }
+
+    pub const fn set_sp(&mut self, value: u8) {
+        self.stack_pointer = StackPointer(value);
+    }
+
+    pub const fn set_pc(&mut self, value: u16) {
+        self.program_counter = value;
+    }
+
+    /// Sets the status from a byte, as pulled by `PLP`. See
+    /// [`Status::from_byte`].
+    pub const fn set_p(&mut self, value: u8) {
+        self.status = Status::from_byte(value);
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        self.status.set(flag.status(), value);
+    }
+}
+
+/// The canonical one-line form used by monitors and test fixtures:
+/// `PC=C000 A=00 X=00 Y=00 SP=FD P=nv-bdizc`. Set flags are upper case.
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC={:04X} A={:02X} X={:02X} Y={:02X} SP={:02X} P=",
+            self.program_counter,
+            self.accumulator,
+            self.index_x,
+            self.index_y,
+            self.stack_pointer.0
+        )?;
+        for (flag, name) in FLAG_NAMES {
+            let c = if flag == Status::PS_UNUSED {
+                '-'
+            } else if self.status.contains(flag) {
+                name
+            } else {
+                name.to_ascii_lowercase()
+            };
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error raised parsing [`Registers`] from text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseRegistersError {
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseRegistersError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseRegistersError {}
+
+/// Parses the form written by [`Registers`]' `Display`. The fields may come
+/// in any order but must all be present, and `P` may also be given in hex.
+impl FromStr for Registers {
+    type Err = ParseRegistersError;
+
+    fn from_str(s: &str) -> Result<Registers, ParseRegistersError> {
+        let error = |message| ParseRegistersError { message };
+        let mut registers = Registers::new();
+        let mut seen = [false; 6];
+        for field in s.split_whitespace() {
+            let (name, value) = field.split_once('=').ok_or(error("expected NAME=value"))?;
+            let byte = || u8::from_str_radix(value, 16).map_err(|_| error("invalid value"));
+            let index = ["PC", "A", "X", "Y", "SP", "P"]
+                .iter()
+                .position(|register| register.eq_ignore_ascii_case(name))
+                .ok_or(error("unknown register"))?;
+            match index {
+                0 => {
+                    registers.program_counter =
+                        u16::from_str_radix(value, 16).map_err(|_| error("invalid value"))?;
+                }
+                1 => registers.accumulator = byte()?,
+                2 => registers.index_x = byte()?,
+                3 => registers.index_y = byte()?,
+                4 => registers.stack_pointer = StackPointer(byte()?),
+                _ if value.len() == 8 => {
+                    registers.status = parse_flags(value).ok_or(error("invalid flags"))?;
+                }
+                _ => registers.status = Status::from_byte(byte()?),
+            }
+            if seen[index] {
+                return Err(error("register given twice"));
+            }
+            seen[index] = true;
+        }
+        if seen.contains(&false) {
+            return Err(error("missing register"));
+        }
+        Ok(registers)
+    }
+}
+
+/// Parses flags written as `NV-BDIZC`, upper case for set and lower case or
+/// `.` for clear.
+fn parse_flags(text: &str) -> Option<Status> {
+    let mut status = Status::PS_UNUSED;
+    for ((flag, name), c) in FLAG_NAMES.into_iter().zip(text.chars()) {
+        if flag == Status::PS_UNUSED {
+            if c != '-' && c != '.' {
+                return None;
+            }
+        } else if c == name {
+            status |= flag;
+        } else if c != name.to_ascii_lowercase() && c != '.' {
+            return None;
+        }
+    }
+    Some(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_5_always_reads_as_set() {
+        assert_eq!(Status::empty().to_byte(), 0x20);
+        assert_eq!(Status::all().to_byte(), 0xFF);
+
+        let mut status = Status::default();
+        status.and(!Status::PS_UNUSED);
+        assert_eq!(status.to_byte() & 0x20, 0x20);
+    }
+
+    #[test]
+    fn accessors_read_and_write_the_fields() {
+        let mut registers = Registers::new();
+        registers.set_a(0x12);
+        registers.set_x(0x34);
+        registers.set_y(0x56);
+        registers.set_sp(0xfd);
+        registers.set_pc(0xc000);
+        registers.set_p(0x00);
+        registers.set_flag(Flag::Carry, true);
+        registers.set_flag(Flag::Negative, true);
+        registers.set_flag(Flag::Negative, false);
+
+        assert_eq!(registers.accumulator, 0x12);
+        assert_eq!(registers.stack_pointer, StackPointer(0xfd));
+        assert_eq!(
+            (registers.a(), registers.x(), registers.y(), registers.sp()),
+            (0x12, 0x34, 0x56, 0xfd)
+        );
+        assert_eq!(registers.pc(), 0xc000);
+        assert_eq!(registers.p(), 0x21);
+        assert!(registers.flag(Flag::Carry));
+        assert!(!registers.flag(Flag::Negative));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn registers_round_trip_through_text() {
+        use alloc::string::ToString;
+
+        let mut registers = Registers::new();
+        registers.program_counter = 0xc000;
+        registers.stack_pointer = StackPointer(0xfd);
+        registers.status = Status::from_byte(0x81);
+        let text = registers.to_string();
+        assert_eq!(text, "PC=C000 A=00 X=00 Y=00 SP=FD P=Nv-bdizC");
+        assert_eq!(text.parse(), Ok(registers));
+
+        // Any order, any case, and P in hex.
+        let parsed: Registers = "sp=fd p=81 y=00 x=00 a=00 pc=c000".parse().unwrap();
+        assert_eq!(parsed, registers);
+    }
+
+    #[test]
+    fn malformed_registers_are_rejected() {
+        let error = |text: &str| text.parse::<Registers>().unwrap_err().message;
+        assert_eq!(error("PC=C000 A=00 X=00 Y=00 SP=FD"), "missing register");
+        assert_eq!(error("PC=C000 A=00 A=01"), "register given twice");
+        assert_eq!(error("PC=C000 Q=00"), "unknown register");
+        assert_eq!(error("PC=C000 A=100"), "invalid value");
+        assert_eq!(error("PC=C000 P=nv?bdizc"), "invalid flags");
+        assert_eq!(error("PC"), "expected NAME=value");
+    }
+
+    #[test]
+    fn loading_a_byte_cannot_clear_bit_5() {
+        assert_eq!(Status::from_byte(0x00), Status::PS_UNUSED);
+        assert_eq!(Status::from_byte(0xC3).to_byte(), 0xE3);
+    }
 }
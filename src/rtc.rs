@@ -0,0 +1,340 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A real-time clock.
+//!
+//! [`RealTimeClock`] maps a block of registers holding the date and time in
+//! UTC. The time comes from the host, or from a fixed value so that tests of
+//! firmware with clock support give the same results on every run.
+//!
+//! | Offset | Register  | Value                          |
+//! |--------|-----------|--------------------------------|
+//! | 0      | `SECONDS` | 0-59                           |
+//! | 1      | `MINUTES` | 0-59                           |
+//! | 2      | `HOURS`   | 0-23                           |
+//! | 3      | `DAY`     | day of the month, 1-31         |
+//! | 4      | `MONTH`   | 1-12                           |
+//! | 5      | `WEEKDAY` | 0-6, from Sunday               |
+//! | 6-7    | `YEAR`    | the full year, LE              |
+//!
+//! The registers hold a snapshot, so a program never sees the minutes roll
+//! over between reading the seconds and the hours; storing any value to
+//! `SECONDS` takes a new one. With [`RealTimeClock::set_bcd`] the values up
+//! to `WEEKDAY` read as binary-coded decimal, as on many clock chips.
+
+use core::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::memory::{Bus, BusError};
+
+pub const SECONDS: u16 = 0;
+pub const MINUTES: u16 = 1;
+pub const HOURS: u16 = 2;
+pub const DAY: u16 = 3;
+pub const MONTH: u16 = 4;
+pub const WEEKDAY: u16 = 5;
+pub const YEAR: u16 = 6;
+
+/// Number of addresses the registers take up.
+pub const SIZE: u16 = 8;
+
+/// Where the clock gets the time from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimeSource {
+    /// The host's clock.
+    Host,
+    /// Always the given time, in seconds since the Unix epoch.
+    Fixed(u64),
+}
+
+impl TimeSource {
+    /// The current time in seconds since the Unix epoch.
+    #[must_use]
+    pub fn now(self) -> u64 {
+        match self {
+            TimeSource::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            TimeSource::Fixed(seconds) => seconds,
+        }
+    }
+}
+
+/// A date and time broken into calendar fields.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// The UTC date and time `seconds` after the Unix epoch.
+    ///
+    /// Years after 65535 are clamped.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_unix(seconds: u64) -> DateTime {
+        // Every narrowing cast below is of a value already reduced to its
+        // field's range.
+        let days = seconds / 86_400;
+        let time = seconds % 86_400;
+
+        // Howard Hinnant's days-to-civil algorithm, with eras of 400 years.
+        // Counting from 0000-03-01 keeps every step unsigned.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: if year > u16::MAX as u64 {
+                u16::MAX
+            } else {
+                year as u16
+            },
+            month: month as u8,
+            day: day as u8,
+            // The epoch was a Thursday.
+            weekday: ((days + 4) % 7) as u8,
+            hours: (time / 3600) as u8,
+            minutes: (time / 60 % 60) as u8,
+            seconds: (time % 60) as u8,
+        }
+    }
+}
+
+const fn bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// A bus with the clock registers at `base`..`base + SIZE`.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::rtc::{RealTimeClock, TimeSource, HOURS, YEAR};
+///
+/// // 2000-01-01 12:00:00 UTC
+/// let clock = RealTimeClock::new(Memory::new(), 0xFE10, TimeSource::Fixed(946_728_000));
+/// assert_eq!(clock.get_byte(0xFE10 + HOURS), 12);
+/// assert_eq!(clock.get_byte(0xFE10 + YEAR), 0xD0);
+/// assert_eq!(clock.get_byte(0xFE10 + YEAR + 1), 0x07);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RealTimeClock<B: Bus> {
+    inner: B,
+    base: u16,
+    source: TimeSource,
+    latched: DateTime,
+    bcd: bool,
+}
+
+impl<B: Bus> RealTimeClock<B> {
+    /// Maps the registers at `base` over `inner`, holding the time from
+    /// `source` at the moment of the call.
+    pub fn new(inner: B, base: u16, source: TimeSource) -> Self {
+        RealTimeClock {
+            inner,
+            base,
+            source,
+            latched: DateTime::from_unix(source.now()),
+            bcd: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    #[must_use]
+    pub const fn source(&self) -> TimeSource {
+        self.source
+    }
+
+    /// Changes where the time comes from. The registers keep their value
+    /// until the next snapshot.
+    pub const fn set_source(&mut self, source: TimeSource) {
+        self.source = source;
+    }
+
+    /// Takes a new snapshot of the time, as a store to `SECONDS` does.
+    pub fn latch(&mut self) {
+        self.latched = DateTime::from_unix(self.source.now());
+    }
+
+    /// The time the registers hold.
+    #[must_use]
+    pub const fn latched(&self) -> DateTime {
+        self.latched
+    }
+
+    /// Makes the registers below `YEAR` read as binary-coded decimal.
+    pub const fn set_bcd(&mut self, enabled: bool) {
+        self.bcd = enabled;
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset < SIZE).then_some(offset)
+    }
+}
+
+impl<B: Bus> Bus for RealTimeClock<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        let time = &self.latched;
+        let value = match self.register(address) {
+            Some(SECONDS) => time.seconds,
+            Some(MINUTES) => time.minutes,
+            Some(HOURS) => time.hours,
+            Some(DAY) => time.day,
+            Some(MONTH) => time.month,
+            Some(WEEKDAY) => time.weekday,
+            Some(offset) => return time.year.to_le_bytes()[usize::from(offset - YEAR)],
+            None => return self.inner.get_byte(address),
+        };
+        if self.bcd {
+            bcd(value)
+        } else {
+            value
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(SECONDS) => self.latch(),
+            Some(_) => {}
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    #[test]
+    fn calendar_fields() {
+        assert_eq!(
+            DateTime::from_unix(0),
+            DateTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                weekday: 4,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            }
+        );
+        // 2024-02-29 23:59:58, a Thursday in a leap year.
+        assert_eq!(
+            DateTime::from_unix(1_709_251_198),
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                weekday: 4,
+                hours: 23,
+                minutes: 59,
+                seconds: 58,
+            }
+        );
+        // 2100-03-01, after a skipped leap day.
+        let date = DateTime::from_unix(4_107_542_400);
+        assert_eq!((date.year, date.month, date.day), (2100, 3, 1));
+    }
+
+    #[test]
+    fn registers_hold_a_snapshot() {
+        const BASE: u16 = 0xFE10;
+        let mut clock = RealTimeClock::new(Memory::new(), BASE, TimeSource::Fixed(1_709_251_198));
+        clock.set_source(TimeSource::Fixed(1_709_251_200));
+        assert_eq!(clock.get_byte(BASE + DAY), 29);
+        clock.set_byte(BASE + SECONDS, 0);
+        assert_eq!(clock.get_byte(BASE + DAY), 1);
+        assert_eq!(clock.get_byte(BASE + MONTH), 3);
+
+        clock.set_bcd(true);
+        clock.set_source(TimeSource::Fixed(1_709_251_198));
+        clock.latch();
+        assert_eq!(clock.get_byte(BASE + MINUTES), 0x59);
+        assert_eq!(clock.get_byte(BASE + HOURS), 0x23);
+        // The year is always binary.
+        assert_eq!(clock.get_byte(BASE + YEAR), 0xE8);
+    }
+}
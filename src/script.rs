@@ -0,0 +1,262 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Debugging with scripts written at runtime.
+//!
+//! With the `scripting` feature, breakpoint conditions, tracepoint actions
+//! and watch expressions can be written in [Rhai](https://rhai.rs) instead
+//! of as Rust closures, so monitors and debugger front-ends can take them
+//! from the user without recompiling.
+//!
+//! A script sees the registers as the constants `pc`, `a`, `x`, `y`, `sp`
+//! and `p`, the flags as the booleans `negative`, `overflow`, `decimal`,
+//! `interrupt`, `zero` and `carry`, and the cycle counter as `cycles`.
+//! `peek(address)` reads a byte of memory.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::cpu::CPU;
+use crate::memory::Bus;
+use crate::registers::Status;
+use crate::Variant;
+
+/// Runaway scripts are stopped after this many operations.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A script that failed to compile or to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+struct Shared {
+    engine: Engine,
+    /// The memory of the CPU a script is being run on. Only filled in for
+    /// scripts that call `peek`.
+    memory: Rc<RefCell<Vec<u8>>>,
+}
+
+/// Compiles scripts. Scripts compiled by one engine share its state, so
+/// creating a single engine for a debugging session is cheapest.
+#[derive(Clone)]
+pub struct ScriptEngine {
+    shared: Rc<Shared>,
+}
+
+impl ScriptEngine {
+    #[must_use]
+    pub fn new() -> ScriptEngine {
+        let memory = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let peek_memory = Rc::clone(&memory);
+        engine.register_fn("peek", move |address: i64| -> i64 {
+            // Addresses wrap around like the 6502's do.
+            let index = usize::try_from(address & 0xffff).unwrap_or_default();
+            peek_memory
+                .borrow()
+                .get(index)
+                .map_or(0, |&byte| i64::from(byte))
+        });
+        ScriptEngine {
+            shared: Rc::new(Shared { engine, memory }),
+        }
+    }
+
+    /// Compiles `source`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the syntax error if `source` doesn't parse.
+    pub fn compile(&self, source: &str) -> Result<Script, ScriptError> {
+        let ast = self
+            .shared
+            .engine
+            .compile(source)
+            .map_err(|error| ScriptError(error.to_string()))?;
+        Ok(Script {
+            source: source.into(),
+            reads_memory: source.contains("peek"),
+            ast: Rc::new(ast),
+            shared: Rc::clone(&self.shared),
+        })
+    }
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        ScriptEngine::new()
+    }
+}
+
+impl fmt::Debug for ScriptEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScriptEngine").finish_non_exhaustive()
+    }
+}
+
+/// A compiled script.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::script::ScriptEngine;
+///
+/// let mut cpu = CPU::new(Memory::new(), Nmos6502);
+/// cpu.registers.index_x = 3;
+/// cpu.memory.set_byte(0x0013, 0x80);
+///
+/// let engine = ScriptEngine::new();
+/// let condition = engine.compile("x > 2 && peek(0x10 + x) >= 0x80").unwrap();
+/// assert_eq!(condition.test(&cpu), Ok(true));
+/// let watch = engine.compile("`X=${x}`").unwrap();
+/// assert_eq!(watch.eval(&cpu).unwrap(), "X=3");
+/// ```
+#[derive(Clone)]
+pub struct Script {
+    source: String,
+    reads_memory: bool,
+    ast: Rc<AST>,
+    shared: Rc<Shared>,
+}
+
+impl Script {
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Runs the script against `cpu` and returns the value of its last
+    /// expression as text, or an empty string if it has none.
+    ///
+    /// Scripts that call `peek` read all of memory with [`Bus::get_byte`]
+    /// first, which is slow and triggers the side effects of reading
+    /// device registers.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the script raised, including running for too
+    /// long.
+    pub fn eval<M: Bus, V: Variant>(&self, cpu: &CPU<M, V>) -> Result<String, ScriptError> {
+        let value = self.run(cpu)?;
+        Ok(if value.is_unit() {
+            String::new()
+        } else {
+            value.to_string()
+        })
+    }
+
+    /// Runs the script against `cpu` as a condition.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the script raised, or an error if its value is
+    /// not a boolean.
+    pub fn test<M: Bus, V: Variant>(&self, cpu: &CPU<M, V>) -> Result<bool, ScriptError> {
+        let value = self.run(cpu)?;
+        value.as_bool().map_err(|type_name| {
+            ScriptError(std::format!("condition must be a boolean, not {type_name}"))
+        })
+    }
+
+    fn run<M: Bus, V: Variant>(&self, cpu: &CPU<M, V>) -> Result<Dynamic, ScriptError> {
+        if self.reads_memory {
+            let mut memory = self.shared.memory.borrow_mut();
+            memory.clear();
+            memory.extend((0..=u16::MAX).map(|address| cpu.memory.get_byte(address)));
+        }
+
+        let r = &cpu.registers;
+        let flag = |flag| r.status.contains(flag);
+        let mut scope = Scope::new();
+        scope
+            .push_constant("pc", i64::from(r.program_counter))
+            .push_constant("a", i64::from(r.accumulator))
+            .push_constant("x", i64::from(r.index_x))
+            .push_constant("y", i64::from(r.index_y))
+            .push_constant("sp", i64::from(r.stack_pointer.0))
+            .push_constant("p", i64::from(r.status.to_byte()))
+            .push_constant("negative", flag(Status::PS_NEGATIVE))
+            .push_constant("overflow", flag(Status::PS_OVERFLOW))
+            .push_constant("decimal", flag(Status::PS_DECIMAL_MODE))
+            .push_constant("interrupt", flag(Status::PS_DISABLE_INTERRUPTS))
+            .push_constant("zero", flag(Status::PS_ZERO))
+            .push_constant("carry", flag(Status::PS_CARRY))
+            // Cycle counts beyond i64 are not reachable in practice.
+            .push_constant("cycles", i64::try_from(cpu.cycles).unwrap_or(i64::MAX));
+        self.shared
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|error| ScriptError(error.to_string()))
+    }
+}
+
+/// Scripts are equal if their source is.
+impl PartialEq for Script {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Script {}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Script").field(&self.source).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let cpu = CPU::new(Memory::new(), Nmos6502);
+        let engine = ScriptEngine::new();
+        assert!(engine.compile("loop {}").unwrap().eval(&cpu).is_err());
+        assert!(engine.compile("a +").is_err());
+        assert!(engine.compile("a + 1").unwrap().test(&cpu).is_err());
+    }
+}
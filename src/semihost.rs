@@ -0,0 +1,369 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Host services for programs under test.
+//!
+//! Cross-compiled test programs need to print results and report how they
+//! went, without any model of real hardware. [`Semihost`] maps a block of
+//! registers where a store asks the host for a service:
+//!
+//! | Offset | Register | Store                                                  |
+//! |--------|----------|--------------------------------------------------------|
+//! | 0      | `PUTC`   | writes the byte to the output                          |
+//! | 1      | `GETC`   | reads a byte from the input into `RESULT`              |
+//! | 2      | `EXIT`   | ends the program, with the byte as exit code           |
+//! | 3      | `TIME`   | puts the host time, in Unix seconds, into `RESULT`     |
+//! | 4      | `FILE`   | runs a file command, see below                         |
+//! | 5      | `ARGS`   | sets the zero-page address of the file command's block |
+//! | 8-11   | `RESULT` | read only: the result of the last service, LE          |
+//!
+//! `GETC` gives the byte, or `$FFFF` at the end of the input. The file
+//! commands take their arguments from a parameter block in zero page:
+//!
+//! | Command   | Block                                    | `RESULT`                 |
+//! |-----------|------------------------------------------|--------------------------|
+//! | 1 `OPEN`  | name pointer (NUL-terminated), mode      | handle, or `$FF`         |
+//! | 2 `READ`  | handle, buffer pointer, length (16 bits) | bytes read, or `$FFFF`   |
+//! | 3 `WRITE` | handle, buffer pointer, length (16 bits) | bytes written, or `$FFFF`|
+//! | 4 `CLOSE` | handle                                   | 0, or `$FF`              |
+//!
+//! Mode 0 opens for reading, 1 creates or truncates for writing and 2
+//! appends. File access is off until [`Semihost::allow_files`] names the
+//! directory that file names are resolved in.
+
+use core::ops::Range;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::vec::Vec;
+
+use crate::memory::{Bus, BusError};
+
+pub const PUTC: u16 = 0;
+pub const GETC: u16 = 1;
+pub const EXIT: u16 = 2;
+pub const TIME: u16 = 3;
+pub const FILE: u16 = 4;
+pub const ARGS: u16 = 5;
+pub const RESULT: u16 = 8;
+
+/// Number of addresses the registers take up.
+pub const SIZE: u16 = 12;
+
+/// File commands, stored to [`FILE`].
+pub const OPEN: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITE: u8 = 3;
+pub const CLOSE: u8 = 4;
+
+/// The longest file name read from memory.
+const MAX_NAME: u16 = 256;
+
+/// A bus with the semihosting registers at `base`..`base + SIZE`.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::semihost::Semihost;
+///
+/// let bus = Semihost::new(Memory::new(), 0xFF00, &b""[..], Vec::new());
+/// let mut cpu = CPU::new(bus, Nmos6502);
+/// // LDA #'!'; STA PUTC; LDA #3; STA EXIT
+/// cpu.memory
+///     .set_bytes(0x0000, &[0xa9, b'!', 0x8d, 0x00, 0xff, 0xa9, 3, 0x8d, 0x02, 0xff]);
+/// while cpu.memory.exit_code().is_none() {
+///     cpu.single_step();
+/// }
+/// assert_eq!(cpu.memory.exit_code(), Some(3));
+/// assert_eq!(cpu.memory.output(), b"!");
+/// ```
+#[derive(Debug)]
+pub struct Semihost<B: Bus, R: Read, W: Write> {
+    inner: B,
+    base: u16,
+    input: R,
+    output: W,
+    args: u8,
+    result: u32,
+    exit: Option<u8>,
+    root: Option<PathBuf>,
+    files: BTreeMap<u8, File>,
+}
+
+impl<B: Bus, R: Read, W: Write> Semihost<B, R, W> {
+    /// Maps the registers at `base` over `inner`, reading characters from
+    /// `input` and writing them to `output`.
+    pub const fn new(inner: B, base: u16, input: R, output: W) -> Self {
+        Semihost {
+            inner,
+            base,
+            input,
+            output,
+            args: 0,
+            result: 0,
+            exit: None,
+            root: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Lets the program open files in `root` and below it. Names that are
+    /// absolute or contain `..` are refused.
+    pub fn allow_files(&mut self, root: impl Into<PathBuf>) {
+        self.root = Some(root.into());
+    }
+
+    /// The exit code, once the program has stored one to [`EXIT`].
+    #[must_use]
+    pub const fn exit_code(&self) -> Option<u8> {
+        self.exit
+    }
+
+    #[must_use]
+    pub const fn output(&self) -> &W {
+        &self.output
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the device, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset < SIZE).then_some(offset)
+    }
+
+    /// The byte at `offset` in the parameter block.
+    fn arg(&self, offset: u8) -> u8 {
+        self.inner
+            .get_byte(u16::from(self.args.wrapping_add(offset)))
+    }
+
+    fn arg_word(&self, offset: u8) -> u16 {
+        u16::from_le_bytes([self.arg(offset), self.arg(offset + 1)])
+    }
+
+    fn file_command(&mut self, command: u8) -> u32 {
+        match command {
+            OPEN => self.open().map_or(0xFF, u32::from),
+            READ | WRITE => {
+                let handle = self.arg(0);
+                let buffer = self.arg_word(1);
+                let length = self.arg_word(3);
+                let Some(file) = self.files.get_mut(&handle) else {
+                    return 0xFFFF;
+                };
+                if command == READ {
+                    let mut data = std::vec![0; usize::from(length)];
+                    let Ok(count) = file.read(&mut data) else {
+                        return 0xFFFF;
+                    };
+                    for (address, &byte) in (buffer..).zip(&data[..count]) {
+                        self.inner.set_byte(address, byte);
+                    }
+                    u32::try_from(count).unwrap_or(0xFFFF)
+                } else {
+                    let data: Vec<u8> = (0..length)
+                        .map(|i| self.inner.get_byte(buffer.wrapping_add(i)))
+                        .collect();
+                    match file.write_all(&data) {
+                        Ok(()) => u32::from(length),
+                        Err(_) => 0xFFFF,
+                    }
+                }
+            }
+            CLOSE => match self.files.remove(&self.arg(0)) {
+                Some(_) => 0,
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn open(&mut self) -> Option<u8> {
+        let root = self.root.as_ref()?;
+        let pointer = self.arg_word(0);
+        let name: Vec<u8> = (0..MAX_NAME)
+            .map(|i| self.inner.get_byte(pointer.wrapping_add(i)))
+            .take_while(|&byte| byte != 0)
+            .collect();
+        let name = Path::new(core::str::from_utf8(&name).ok()?);
+        if !name
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let mut options = OpenOptions::new();
+        match self.arg(2) {
+            0 => options.read(true),
+            1 => options.write(true).create(true).truncate(true),
+            2 => options.append(true).create(true),
+            _ => return None,
+        };
+        let file = options.open(root.join(name)).ok()?;
+        let handle = (1..=0xFE).find(|handle| !self.files.contains_key(handle))?;
+        self.files.insert(handle, file);
+        Some(handle)
+    }
+}
+
+impl<B: Bus, R: Read, W: Write> Bus for Semihost<B, R, W> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        match self.register(address) {
+            Some(ARGS) => self.args,
+            Some(offset @ RESULT..) => self.result.to_le_bytes()[usize::from(offset - RESULT)],
+            Some(_) => 0,
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(PUTC) => {
+                // A closed or broken output must not stop the emulation.
+                let _ = self
+                    .output
+                    .write_all(&[value])
+                    .and_then(|()| self.output.flush());
+            }
+            Some(GETC) => {
+                let mut byte = [0];
+                self.result = match self.input.read(&mut byte) {
+                    Ok(1) => u32::from(byte[0]),
+                    _ => 0xFFFF,
+                };
+            }
+            Some(EXIT) => self.exit = Some(value),
+            Some(TIME) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+                self.result = u32::try_from(now).unwrap_or(u32::MAX);
+            }
+            Some(FILE) => self.result = self.file_command(value),
+            Some(ARGS) => self.args = value,
+            Some(_) => {}
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    const BASE: u16 = 0xFF00;
+
+    fn semihost(input: &[u8]) -> Semihost<Memory, &[u8], Vec<u8>> {
+        Semihost::new(Memory::new(), BASE, input, Vec::new())
+    }
+
+    #[test]
+    fn character_io() {
+        let mut host = semihost(b"x");
+        host.set_byte(BASE + PUTC, b'o');
+        host.set_byte(BASE + GETC, 0);
+        assert_eq!(host.get_byte(BASE + RESULT), b'x');
+        host.set_byte(BASE + GETC, 0);
+        assert_eq!(host.get_byte(BASE + RESULT), 0xFF);
+        assert_eq!(host.get_byte(BASE + RESULT + 1), 0xFF);
+        assert_eq!(host.output(), b"o");
+        assert_eq!(host.exit_code(), None);
+        host.set_byte(BASE + EXIT, 7);
+        assert_eq!(host.exit_code(), Some(7));
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let dir = std::env::temp_dir().join(std::format!("semihost-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut host = semihost(b"");
+        host.allow_files(&dir);
+
+        let memory = host.inner_mut();
+        memory.set_bytes(0x0200, b"out.txt\0");
+        memory.set_bytes(0x0300, b"hello");
+        // Block at $10: name pointer, mode.
+        memory.set_bytes(0x0010, &[0x00, 0x02, 1]);
+        host.set_byte(BASE + ARGS, 0x10);
+        host.set_byte(BASE + FILE, OPEN);
+        let handle = host.get_byte(BASE + RESULT);
+        assert_ne!(handle, 0xFF);
+
+        // Block at $20: handle, buffer, length.
+        host.inner_mut()
+            .set_bytes(0x0020, &[handle, 0x00, 0x03, 5, 0]);
+        host.set_byte(BASE + ARGS, 0x20);
+        host.set_byte(BASE + FILE, WRITE);
+        assert_eq!(host.get_byte(BASE + RESULT), 5);
+        host.set_byte(BASE + FILE, CLOSE);
+        assert_eq!(host.get_byte(BASE + RESULT), 0);
+        assert_eq!(std::fs::read(dir.join("out.txt")).unwrap(), b"hello");
+
+        // Names may not leave the root.
+        host.inner_mut().set_bytes(0x0200, b"../x\0");
+        host.set_byte(BASE + ARGS, 0x10);
+        host.set_byte(BASE + FILE, OPEN);
+        assert_eq!(host.get_byte(BASE + RESULT), 0xFF);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_are_off_by_default() {
+        let mut host = semihost(b"");
+        host.inner_mut().set_bytes(0x0200, b"out.txt\0");
+        host.inner_mut().set_bytes(0x0010, &[0x00, 0x02, 1]);
+        host.set_byte(BASE + ARGS, 0x10);
+        host.set_byte(BASE + FILE, OPEN);
+        assert_eq!(host.get_byte(BASE + RESULT), 0xFF);
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Detecting self-modifying code.
+//!
+//! Plenty of 6502 code patches its own operands: an unrolled copy loop that
+//! bumps the address inside an `LDA $xxxx,X`, a jump table built by storing
+//! into a `JMP`. Anything that decodes an instruction once and reuses the
+//! result — a decode cache, a recompiler, an annotated disassembly — goes
+//! stale when that happens. [`SmcDetect`] wraps a bus and remembers which
+//! bytes have been fetched as part of an instruction; a later write to one of
+//! them is recorded as a [`SelfModification`]. [`step`] executes one
+//! instruction and reports the writes it made to code.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::{Bus, BusError};
+use crate::Variant;
+
+/// A write to a byte that had previously been executed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SelfModification {
+    /// Address of the instruction that made the write, if known.
+    pub pc: Option<u16>,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl fmt::Display for SelfModification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write of ${:02X} to executed code at ${:04X}",
+            self.value, self.address
+        )?;
+        if let Some(pc) = self.pc {
+            write!(f, " by the instruction at ${pc:04X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A bus that tracks which bytes have been executed and records writes to
+/// them.
+///
+/// Bytes are marked by [`step`], which knows where each instruction starts
+/// and how long it is; stepping the CPU any other way leaves the map as it
+/// was. Loading a program with [`Bus::set_bytes`] is not a modification: the
+/// loaded range is forgotten, as if it had never run.
+#[derive(Debug)]
+pub struct SmcDetect<B: Bus> {
+    inner: B,
+    executed: [u64; 1024],
+    writes: Vec<SelfModification>,
+}
+
+impl<B: Bus> SmcDetect<B> {
+    /// Wraps `inner` with no byte executed yet.
+    pub const fn new(inner: B) -> SmcDetect<B> {
+        SmcDetect {
+            inner,
+            executed: [0; 1024],
+            writes: Vec::new(),
+        }
+    }
+
+    /// Whether `address` has been fetched as part of an instruction.
+    #[must_use]
+    pub const fn is_executed(&self, address: u16) -> bool {
+        self.executed[address as usize / 64] & (1 << (address % 64)) != 0
+    }
+
+    /// Forgets every executed byte, e.g. after a bank switch that maps in
+    /// different code.
+    pub const fn clear(&mut self) {
+        self.executed = [0; 1024];
+    }
+
+    /// Returns and forgets the writes to code recorded so far.
+    pub fn take_writes(&mut self) -> Vec<SelfModification> {
+        core::mem::take(&mut self.writes)
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus. Writes made through it
+    /// are not checked.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    const fn mark(&mut self, address: u16) {
+        self.executed[address as usize / 64] |= 1 << (address % 64);
+    }
+
+    const fn unmark(&mut self, address: u16) {
+        self.executed[address as usize / 64] &= !(1 << (address % 64));
+    }
+}
+
+impl<B: Bus> Bus for SmcDetect<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if self.is_executed(address) {
+            self.writes.push(SelfModification {
+                pc: None,
+                address,
+                value,
+            });
+        }
+        self.inner.set_byte(address, value);
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        for (address, _) in (start..=u16::MAX).zip(values) {
+            self.unmark(address);
+        }
+        self.inner.set_bytes(start, values);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+/// Executes one instruction on `cpu`, first marking its bytes as executed.
+///
+/// An instruction that stores into its own operand is caught on its first
+/// run.
+///
+/// # Errors
+///
+/// Returns every write the instruction made to executed code, in order. The
+/// instruction has been executed regardless.
+pub fn step<B: Bus, V: Variant>(
+    cpu: &mut CPU<SmcDetect<B>, V>,
+) -> Result<Option<DecodedInstr>, Vec<SelfModification>> {
+    let pc = cpu.registers.program_counter;
+    let opcode = cpu.memory.inner.get_byte(pc);
+    let length = V::decode(opcode).map_or(1, |(_, mode)| 1 + mode.extra_bytes());
+    for offset in 0..length {
+        cpu.memory.mark(pc.wrapping_add(offset));
+    }
+    cpu.memory.writes.clear();
+    let decoded = cpu.single_step();
+    let writes = cpu.memory.take_writes();
+    if writes.is_empty() {
+        Ok(decoded)
+    } else {
+        Err(writes
+            .into_iter()
+            .map(|write| SelfModification {
+                pc: Some(pc),
+                ..write
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use alloc::string::ToString;
+
+    #[test]
+    fn flags_writes_to_executed_bytes() {
+        let mut cpu = CPU::new(SmcDetect::new(Memory::new()), Nmos6502);
+        // loop: LDA $0300; INC $0201; STA $10; JMP loop
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0xad, 0x00, 0x03, 0xee, 0x01, 0x02, 0x85, 0x10, 0x4c, 0x00, 0x02,
+            ],
+        );
+        cpu.registers.program_counter = 0x0200;
+
+        assert!(step(&mut cpu).is_ok());
+        assert!(cpu.memory.is_executed(0x0202));
+        assert!(!cpu.memory.is_executed(0x0203));
+        assert_eq!(
+            step(&mut cpu).unwrap_err(),
+            [SelfModification {
+                pc: Some(0x0203),
+                address: 0x0201,
+                value: 0x01
+            }]
+        );
+        // Data stores are not code.
+        assert!(step(&mut cpu).is_ok());
+        assert!(step(&mut cpu).is_ok());
+        assert_eq!(cpu.registers.program_counter, 0x0200);
+
+        // Reloading the code forgets it was executed.
+        cpu.memory.set_bytes(0x0200, &[0xea]);
+        assert!(!cpu.memory.is_executed(0x0200));
+    }
+
+    #[test]
+    fn catches_an_instruction_patching_its_own_operand() {
+        let mut cpu = CPU::new(SmcDetect::new(Memory::new()), Nmos6502);
+        // STA $0201
+        cpu.memory.set_bytes(0x0200, &[0x8d, 0x01, 0x02]);
+        cpu.registers.program_counter = 0x0200;
+        cpu.registers.accumulator = 0x42;
+
+        let writes = step(&mut cpu).unwrap_err();
+        assert_eq!(
+            writes[0].to_string(),
+            "write of $42 to executed code at $0201 by the instruction at $0200"
+        );
+    }
+}
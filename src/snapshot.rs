@@ -0,0 +1,674 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Saving and restoring machine state.
+//!
+//! A [`Snapshot`] holds the registers, cycle counter, interrupt state and
+//! all 64K of memory of a CPU. It can be restored into the same or another
+//! CPU, after which execution carries on exactly as it did from the
+//! captured state, and encoded as bytes to be stored in a file:
+//!
+//! | Offset | Size  | Field                                          |
+//! |--------|-------|------------------------------------------------|
+//! | 0      | 4     | magic bytes `M65S`                             |
+//! | 4      | 1     | version (2)                                    |
+//! | 5      | 1     | flags: memory encoding, see below              |
+//! | 6      | 2     | PC (LE)                                        |
+//! | 8      | 5     | A, X, Y, SP, P                                 |
+//! | 13     | 1     | interrupts: IRQ line, NMI line, NMI latched    |
+//! | 14     | 8     | cycle counter (LE)                             |
+//! | 22     |       | memory                                         |
+//!
+//! [`Snapshot::to_bytes`] stores memory sparsely (flag bit 1): as a series of
+//! runs, each an address and a length (both LE `u16`) followed by that many
+//! bytes, in ascending address order. Memory not covered by a run is zero,
+//! so a small program in otherwise untouched memory takes up little more
+//! than the program itself. Without any flags set, memory is stored as a
+//! plain 65536 bytes.
+//!
+//! The interrupt byte holds bits 0 to 2 of
+//! [`InterruptController::to_bits`]. Version 1 only stored the IRQ line
+//! there, and is still read.
+//!
+//! With the `serde` feature, snapshots can also be serialized with serde,
+//! for frontends that keep save states in a format of their own.
+//!
+//! With the `compression` feature, `Snapshot::compress` encodes memory as
+//! runs, which shrinks the mostly-empty images of small programs to a few
+//! hundred bytes. A run is a tag byte followed by its length as an unsigned
+//! LEB128 varint: tag 0 repeats the one byte that follows, tag 1 copies the
+//! bytes that follow.
+//!
+//! A [`SnapshotRing`] keeps the most recent of a series of snapshots taken
+//! at regular cycle intervals, which is what
+//! [`Machine::seek_to_cycle`](crate::machine::Machine::seek_to_cycle) uses
+//! to travel back in time.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::interrupt::InterruptController;
+use crate::memory::Bus;
+use crate::registers::{Registers, StackPointer, Status};
+use crate::Variant;
+
+const MAGIC: &[u8; 4] = b"M65S";
+const VERSION: u8 = 2;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_SPARSE: u8 = 2;
+/// Size of the address and length of a sparse run. Shorter gaps of zeros
+/// are cheaper to store inside a run.
+const RUN_HEADER_LEN: usize = 4;
+const HEADER_LEN: usize = 22;
+const MEMORY_SIZE: usize = 0x10000;
+
+/// Why a snapshot could not be decoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data does not start with the snapshot magic bytes.
+    BadMagic,
+    /// The snapshot was written by an unknown version of the format.
+    UnsupportedVersion(u8),
+    /// The memory is compressed, but the `compression` feature is not
+    /// enabled.
+    Compressed,
+    /// The data ends early.
+    Truncated,
+    /// The data is malformed.
+    Corrupt,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => f.write_str("not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::Compressed => {
+                f.write_str("compressed snapshots need the `compression` feature")
+            }
+            SnapshotError::Truncated => f.write_str("snapshot is truncated"),
+            SnapshotError::Corrupt => f.write_str("snapshot is corrupt"),
+        }
+    }
+}
+
+/// The state of a CPU and its memory at one point in time, between two
+/// instructions.
+///
+/// Pending cycle steals are not captured: they come from devices on the
+/// bus, which save their own state.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::snapshot::Snapshot;
+///
+/// let mut cpu = CPU::new(Memory::new(), Nmos6502);
+/// cpu.memory.set_byte(0x0200, 0x42);
+/// let snapshot = Snapshot::capture(&cpu);
+///
+/// cpu.memory.set_byte(0x0200, 0x00);
+/// cpu.registers.accumulator = 1;
+/// snapshot.restore(&mut cpu);
+/// assert_eq!(cpu.memory.get_byte(0x0200), 0x42);
+/// assert_eq!(cpu.registers.accumulator, 0);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub registers: Registers,
+    pub cycles: u64,
+    /// The interrupt lines and any NMI latched but not yet taken.
+    pub interrupts: InterruptController,
+    memory: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Captures the state of `cpu`. Memory is read with [`Bus::get_byte`].
+    #[must_use]
+    pub fn capture<M: Bus, V: Variant>(cpu: &CPU<M, V>) -> Snapshot {
+        Snapshot {
+            registers: cpu.registers,
+            cycles: cpu.cycles,
+            interrupts: *cpu.interrupts(),
+            memory: (0..=u16::MAX)
+                .map(|address| cpu.memory.get_byte(address))
+                .collect(),
+        }
+    }
+
+    /// Puts `cpu` back into the captured state. Memory is written with
+    /// [`Bus::set_byte`], so writes to ROM are ignored as usual.
+    pub fn restore<M: Bus, V: Variant>(&self, cpu: &mut CPU<M, V>) {
+        cpu.restore_state(self.registers, self.cycles, self.interrupts);
+        for (address, &value) in (0..=u16::MAX).zip(&self.memory) {
+            cpu.memory.set_byte(address, value);
+        }
+    }
+
+    /// Assembles a snapshot decoded from another format. `memory` must be
+    /// 64K bytes long.
+    pub(crate) const fn from_parts(
+        registers: Registers,
+        cycles: u64,
+        interrupts: InterruptController,
+        memory: Vec<u8>,
+    ) -> Snapshot {
+        Snapshot {
+            registers,
+            cycles,
+            interrupts,
+            memory,
+        }
+    }
+
+    /// The captured memory, 64K bytes long.
+    #[must_use]
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Encodes the snapshot, storing only the runs of memory that are not
+    /// zero.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header(FLAG_SPARSE);
+        let memory = &self.memory;
+        let mut i = 0;
+        while i < memory.len() {
+            if memory[i] == 0 {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let mut end = i;
+            while i < memory.len() && i - start < usize::from(u16::MAX) {
+                if memory[i] != 0 {
+                    end = i + 1;
+                } else if i - end >= RUN_HEADER_LEN {
+                    break;
+                }
+                i += 1;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                out.extend_from_slice(&(start as u16).to_le_bytes());
+                out.extend_from_slice(&((end - start) as u16).to_le_bytes());
+            }
+            out.extend_from_slice(&memory[start..end]);
+            i = end;
+        }
+        out
+    }
+
+    /// Encodes the snapshot with compressed memory.
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn compress(&self) -> Vec<u8> {
+        let mut out = self.header(FLAG_COMPRESSED);
+        compression::compress(&self.memory, &mut out);
+        out
+    }
+
+    /// Decodes a snapshot produced by [`Snapshot::to_bytes`] or
+    /// `Snapshot::compress`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a valid snapshot, or if its memory
+    /// is compressed and the `compression` feature is disabled.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let header = bytes.get(..HEADER_LEN).ok_or(SnapshotError::Truncated)?;
+        let version = header[4];
+        if !(1..=VERSION).contains(&version) {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let body = &bytes[HEADER_LEN..];
+        let memory = match header[5] {
+            0 => match body.len() {
+                MEMORY_SIZE => body.to_vec(),
+                len if len < MEMORY_SIZE => return Err(SnapshotError::Truncated),
+                _ => return Err(SnapshotError::Corrupt),
+            },
+            FLAG_SPARSE => from_runs(body)?,
+            FLAG_COMPRESSED => decompress(body)?,
+            _ => return Err(SnapshotError::Corrupt),
+        };
+
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&header[14..22]);
+        Ok(Snapshot {
+            registers: Registers {
+                program_counter: u16::from_le_bytes([header[6], header[7]]),
+                accumulator: header[8],
+                index_x: header[9],
+                index_y: header[10],
+                stack_pointer: StackPointer(header[11]),
+                status: Status::from_byte(header[12]),
+            },
+            interrupts: if version == 1 {
+                InterruptController::from_bits(u8::from(header[13] != 0))
+            } else {
+                InterruptController::from_bits(header[13])
+            },
+            cycles: u64::from_le_bytes(cycles),
+            memory,
+        })
+    }
+
+    fn header(&self, flags: u8) -> Vec<u8> {
+        let r = &self.registers;
+        let mut out = Vec::with_capacity(HEADER_LEN + MEMORY_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&[VERSION, flags]);
+        out.extend_from_slice(&r.program_counter.to_le_bytes());
+        out.extend_from_slice(&[
+            r.accumulator,
+            r.index_x,
+            r.index_y,
+            r.stack_pointer.0,
+            r.status.to_byte(),
+            self.interrupts.to_bits(),
+        ]);
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+        out
+    }
+}
+
+/// A bounded series of snapshots taken every `interval` cycles. When the
+/// ring is full, the oldest snapshot is dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotRing {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotRing {
+    /// Keeps up to `capacity` snapshots, taken `interval` cycles apart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` or `capacity` is zero.
+    #[must_use]
+    pub fn new(interval: u64, capacity: usize) -> SnapshotRing {
+        assert!(interval > 0, "snapshot interval must not be zero");
+        assert!(capacity > 0, "snapshot ring capacity must not be zero");
+        SnapshotRing {
+            interval,
+            capacity,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    #[must_use]
+    pub const fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Captures `cpu` if at least `interval` cycles have passed since the
+    /// newest snapshot, or if there is none. Returns whether it did.
+    pub fn record<M: Bus, V: Variant>(&mut self, cpu: &CPU<M, V>) -> bool {
+        let due = self
+            .snapshots
+            .back()
+            .is_none_or(|last| cpu.cycles >= last.cycles + self.interval);
+        if due {
+            self.push(Snapshot::capture(cpu));
+        }
+        due
+    }
+
+    /// Adds a snapshot taken elsewhere, dropping the oldest if the ring is
+    /// full.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// The newest snapshot taken at or before `cycle`.
+    #[must_use]
+    pub fn at_or_before(&self, cycle: u64) -> Option<&Snapshot> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.cycles <= cycle)
+    }
+
+    /// Drops the snapshots taken after `cycle`, e.g. because the machine
+    /// was rewound and they describe a future that may not happen again.
+    pub fn truncate_after(&mut self, cycle: u64) {
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.cycles > cycle)
+        {
+            self.snapshots.pop_back();
+        }
+    }
+
+    /// The snapshots from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &Snapshot> + '_ {
+        self.snapshots.iter()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+/// Decodes sparse memory.
+fn from_runs(mut body: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    let mut memory = alloc::vec![0; MEMORY_SIZE];
+    // Runs must not overlap or go backwards.
+    let mut next = 0;
+    while !body.is_empty() {
+        let header = body.get(..RUN_HEADER_LEN).ok_or(SnapshotError::Truncated)?;
+        let start = usize::from(u16::from_le_bytes([header[0], header[1]]));
+        let len = usize::from(u16::from_le_bytes([header[2], header[3]]));
+        if len == 0 || start < next || start + len > MEMORY_SIZE {
+            return Err(SnapshotError::Corrupt);
+        }
+        let bytes = body[RUN_HEADER_LEN..]
+            .get(..len)
+            .ok_or(SnapshotError::Truncated)?;
+        memory[start..start + len].copy_from_slice(bytes);
+        next = start + len;
+        body = &body[RUN_HEADER_LEN + len..];
+    }
+    Ok(memory)
+}
+
+#[cfg(feature = "compression")]
+fn decompress(body: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    compression::decompress(body)
+}
+
+#[cfg(not(feature = "compression"))]
+const fn decompress(_body: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    Err(SnapshotError::Compressed)
+}
+
+#[cfg(feature = "compression")]
+mod compression {
+    use super::{SnapshotError, MEMORY_SIZE};
+    use alloc::vec::Vec;
+
+    const TAG_REPEAT: u8 = 0;
+    const TAG_LITERAL: u8 = 1;
+    /// Shorter runs of one byte are cheaper to store as literals.
+    const MIN_REPEAT: usize = 4;
+
+    pub(super) fn compress(memory: &[u8], out: &mut Vec<u8>) {
+        let mut literal_start = 0;
+        let mut i = 0;
+        while i < memory.len() {
+            let run = memory[i..].iter().take_while(|&&b| b == memory[i]).count();
+            if run < MIN_REPEAT {
+                i += run;
+                continue;
+            }
+            literal(&memory[literal_start..i], out);
+            out.push(TAG_REPEAT);
+            varint(run, out);
+            out.push(memory[i]);
+            i += run;
+            literal_start = i;
+        }
+        literal(&memory[literal_start..], out);
+    }
+
+    fn literal(bytes: &[u8], out: &mut Vec<u8>) {
+        if !bytes.is_empty() {
+            out.push(TAG_LITERAL);
+            varint(bytes.len(), out);
+            out.extend_from_slice(bytes);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn varint(mut value: usize, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    pub(super) fn decompress(mut body: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+        let mut memory = Vec::with_capacity(MEMORY_SIZE);
+        while let Some((&tag, rest)) = body.split_first() {
+            let (len, rest) = read_varint(rest)?;
+            if memory.len() + len > MEMORY_SIZE {
+                return Err(SnapshotError::Corrupt);
+            }
+            body = match tag {
+                TAG_REPEAT => {
+                    let (&value, rest) = rest.split_first().ok_or(SnapshotError::Truncated)?;
+                    memory.resize(memory.len() + len, value);
+                    rest
+                }
+                TAG_LITERAL => {
+                    let bytes = rest.get(..len).ok_or(SnapshotError::Truncated)?;
+                    memory.extend_from_slice(bytes);
+                    &rest[len..]
+                }
+                _ => return Err(SnapshotError::Corrupt),
+            };
+        }
+        if memory.len() < MEMORY_SIZE {
+            return Err(SnapshotError::Truncated);
+        }
+        Ok(memory)
+    }
+
+    fn read_varint(bytes: &[u8]) -> Result<(usize, &[u8]), SnapshotError> {
+        let mut value = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            if i >= 3 {
+                // Lengths never exceed 64K, which fits in three bytes.
+                return Err(SnapshotError::Corrupt);
+            }
+            value |= usize::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok((value, &bytes[i + 1..]));
+            }
+        }
+        Err(SnapshotError::Truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    fn cpu() -> CPU<Memory, Nmos6502> {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory
+            .set_bytes(0x0200, &[0xa9, 0x42, 0x85, 0x10, 0x00]);
+        cpu.memory.set_bytes(0xfffc, &[0x00, 0x02]);
+        cpu.registers.program_counter = 0x0200;
+        cpu.single_step();
+        cpu.set_irq(true);
+        cpu
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let cpu = cpu();
+        let snapshot = Snapshot::capture(&cpu);
+        let bytes = snapshot.to_bytes();
+        // Runs of the program at $0200, without the BRK, and of the high
+        // byte of the reset vector.
+        assert_eq!(bytes.len(), HEADER_LEN + (4 + 4) + (4 + 1));
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot.clone()));
+
+        let mut restored = CPU::new(Memory::new(), Nmos6502);
+        snapshot.restore(&mut restored);
+        assert_eq!(restored.state_hash(), cpu.state_hash());
+        assert_eq!(restored.cycles, 2);
+        assert!(restored.irq_asserted());
+        assert_eq!(restored.interrupts(), cpu.interrupts());
+
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..28]),
+            Err(SnapshotError::Truncated)
+        );
+        assert_eq!(Snapshot::from_bytes(b"nope"), Err(SnapshotError::BadMagic));
+    }
+
+    #[test]
+    fn restored_cpu_replays_identically() {
+        // A loop that counts in $10 with interrupts enabled, and handlers
+        // that count NMIs in $11 and IRQs, taken again and again while the
+        // line stays asserted, in $12.
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory
+            .set_bytes(0x0200, &[0x58, 0xe6, 0x10, 0x4c, 0x01, 0x02]);
+        cpu.memory.set_bytes(0x0300, &[0xe6, 0x11, 0x40]);
+        cpu.memory.set_bytes(0x0310, &[0xe6, 0x12, 0x40]);
+        cpu.memory
+            .set_bytes(0xfffa, &[0x00, 0x03, 0x00, 0x02, 0x10, 0x03]);
+        cpu.registers.program_counter = 0x0200;
+        cpu.single_step();
+        cpu.set_irq(true);
+        cpu.set_nmi(true);
+
+        let bytes = Snapshot::capture(&cpu).to_bytes();
+        let mut restored = CPU::new(Memory::new(), Nmos6502);
+        Snapshot::from_bytes(&bytes).unwrap().restore(&mut restored);
+        for _ in 0..100 {
+            cpu.single_step();
+            restored.single_step();
+            assert_eq!(restored.state_hash(), cpu.state_hash());
+            assert_eq!(restored.cycles, cpu.cycles);
+            assert_eq!(restored.interrupts(), cpu.interrupts());
+        }
+        assert_eq!(cpu.memory.get_byte(0x11), 1);
+        assert!(cpu.memory.get_byte(0x12) > 1);
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let mut bytes = Snapshot::capture(&cpu()).to_bytes();
+        bytes[4] = 1;
+        bytes[13] = 1;
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert!(snapshot.interrupts.irq_asserted());
+        assert!(!snapshot.interrupts.nmi_pending());
+
+        bytes[4] = VERSION + 1;
+        assert_eq!(
+            Snapshot::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut cpu = cpu();
+        cpu.set_nmi(true);
+        let snapshot = Snapshot::capture(&cpu);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_round_trip() {
+        let snapshot = Snapshot::capture(&cpu());
+        let bytes = snapshot.compress();
+        assert!(bytes.len() < 64, "{} bytes", bytes.len());
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        // Incompressible memory still round-trips.
+        let mut cpu = cpu();
+        for address in 0..=u16::MAX {
+            #[allow(clippy::cast_possible_truncation)]
+            cpu.memory
+                .set_byte(address, (address ^ (address >> 8)) as u8);
+        }
+        let snapshot = Snapshot::capture(&cpu);
+        assert_eq!(Snapshot::from_bytes(&snapshot.compress()), Ok(snapshot));
+    }
+
+    #[test]
+    fn sparse_runs_bridge_short_gaps() {
+        let mut cpu = cpu();
+        // Gaps of 4 zeros or fewer stay inside a run; longer ones split it.
+        cpu.memory
+            .set_bytes(0x1000, &[1, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 3]);
+        // Nothing but non-zero bytes from $8000 to the end.
+        for address in 0x8000..=u16::MAX {
+            cpu.memory.set_byte(address, 0xea);
+        }
+        let snapshot = Snapshot::capture(&cpu);
+        let bytes = snapshot.to_bytes();
+        assert_eq!(
+            bytes.len(),
+            HEADER_LEN + (4 + 4) + (4 + 6) + (4 + 1) + (4 + 0x8000)
+        );
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+
+        // The raw layout is still read.
+        let mut raw = bytes[..HEADER_LEN].to_vec();
+        raw[5] = 0;
+        raw.resize(HEADER_LEN + MEMORY_SIZE, 0);
+        assert!(Snapshot::from_bytes(&raw).is_ok());
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Reading the stack the way a debugger shows it.
+//!
+//! A [`StackView`] holds the bytes between the stack pointer and `$01FF`
+//! and splits them into [`StackEntry`]s: return addresses pushed by `JSR`,
+//! the status and PC pushed by an interrupt or `BRK`, and registers pushed
+//! by `PHA`, `PHP`, `PHX` or `PHY`. [`StackView::capture`] can only guess,
+//! taking any two bytes that point just past a `JSR` for a return address.
+//! A [`CallTracker`] registered as an observer of a
+//! [`Machine`](crate::machine::Machine) remembers what pushed each byte, so
+//! [`CallTracker::stack`] knows.
+//!
+//! ```
+//! use std::cell::RefCell;
+//! use std::rc::Rc;
+//!
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::{Instruction, Nmos6502};
+//! use mos6502::machine::Machine;
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::stack::{CallTracker, EntryKind};
+//!
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! // JSR sub; sub: LDA #$12; PHA
+//! cpu.memory.set_bytes(0x0200, &[0x20, 0x03, 0x02, 0xa9, 0x12, 0x48]);
+//! cpu.registers.program_counter = 0x0200;
+//! cpu.registers.stack_pointer.0 = 0xff;
+//! let mut machine = Machine::new(cpu);
+//! let tracker = Rc::new(RefCell::new(CallTracker::new()));
+//! machine.add_observer(Rc::clone(&tracker));
+//! machine.step_instructions(3);
+//!
+//! let stack = tracker.borrow().stack(&machine.cpu);
+//! assert_eq!(stack.bytes, [0x12, 0x02, 0x02]);
+//! assert_eq!(stack.entries[0].kind, EntryKind::Register(Instruction::PHA, 0x12));
+//! assert_eq!(
+//!     stack.entries[1].kind,
+//!     EntryKind::ReturnAddress { caller: 0x0200, returns_to: 0x0203 }
+//! );
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::instruction::Instruction;
+use crate::memory::{Bus, STACK_ADDRESS_LO};
+use crate::observer::{InstructionEvent, Observer};
+use crate::registers::Status;
+use crate::Variant;
+
+/// Opcode of `JSR`, which is the same on every variant.
+const JSR: u8 = 0x20;
+
+/// What a stack entry holds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    /// The two bytes pushed by the `JSR` at `caller`. `RTS` continues at
+    /// `returns_to`.
+    ReturnAddress { caller: u16, returns_to: u16 },
+    /// The three bytes pushed by an interrupt or `BRK`. `RTI` restores
+    /// `status` and continues at `returns_to`.
+    Interrupt { status: Status, returns_to: u16 },
+    /// A byte pushed by the given instruction.
+    Register(Instruction, u8),
+    /// A byte of unknown origin.
+    Byte(u8),
+}
+
+impl EntryKind {
+    /// The number of stack bytes the entry covers.
+    #[must_use]
+    pub const fn size(&self) -> u16 {
+        match self {
+            EntryKind::ReturnAddress { .. } => 2,
+            EntryKind::Interrupt { .. } => 3,
+            EntryKind::Register(..) | EntryKind::Byte(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntryKind::ReturnAddress { caller, returns_to } => {
+                write!(f, "JSR ${caller:04X} -> ${returns_to:04X}")
+            }
+            EntryKind::Interrupt { status, returns_to } => {
+                write!(f, "P=${:02X}, RTI -> ${returns_to:04X}", status.to_byte())
+            }
+            EntryKind::Register(instruction, value) => write!(f, "{instruction:?} ${value:02X}"),
+            EntryKind::Byte(value) => write!(f, "${value:02X}"),
+        }
+    }
+}
+
+/// One or more stack bytes that belong together.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StackEntry {
+    /// The lowest address of the entry, which was pushed last.
+    pub address: u16,
+    pub kind: EntryKind,
+}
+
+/// The contents of the stack, from the most recently pushed byte up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackView {
+    pub stack_pointer: u8,
+    /// The bytes from the stack pointer + 1 up to `$01FF`.
+    pub bytes: Vec<u8>,
+    /// The bytes split into entries, in the same order.
+    pub entries: Vec<StackEntry>,
+}
+
+impl StackView {
+    /// Reads the stack of `cpu`, guessing which bytes are return addresses.
+    #[must_use]
+    pub fn capture<M: Bus, V: Variant>(cpu: &CPU<M, V>) -> StackView {
+        StackView::decode(cpu, &[Slot::Unknown; 256])
+    }
+
+    fn decode<M: Bus, V: Variant>(cpu: &CPU<M, V>, slots: &[Slot; 256]) -> StackView {
+        let stack_pointer = cpu.registers.stack_pointer.0;
+        let start = u16::from(stack_pointer) + 1;
+        let bytes: Vec<u8> = (start..=0xff)
+            .map(|offset| cpu.memory.get_byte(STACK_ADDRESS_LO | offset))
+            .collect();
+        let slot = |offset: u16| slots.get(usize::from(offset)).copied();
+        let word = |offset: u16| {
+            let byte = |offset: u16| bytes.get(usize::from(offset - start)).copied();
+            Some(u16::from_le_bytes([byte(offset)?, byte(offset + 1)?]))
+        };
+
+        let mut entries = Vec::new();
+        let mut offset = start;
+        while offset <= 0xff {
+            let value = bytes[usize::from(offset - start)];
+            let kind = match (slot(offset), word(offset)) {
+                (Some(Slot::Interrupt), _)
+                    if slot(offset + 1) == Some(Slot::Interrupt)
+                        && slot(offset + 2) == Some(Slot::Interrupt) =>
+                {
+                    word(offset + 1).map_or(EntryKind::Byte(value), |returns_to| {
+                        EntryKind::Interrupt {
+                            status: Status::from_byte(value),
+                            returns_to,
+                        }
+                    })
+                }
+                (Some(Slot::Call(caller)), Some(pushed))
+                    if slot(offset + 1) == Some(Slot::Call(caller)) =>
+                {
+                    EntryKind::ReturnAddress {
+                        caller,
+                        returns_to: pushed.wrapping_add(1),
+                    }
+                }
+                (Some(Slot::Pushed(instruction)), _) => EntryKind::Register(instruction, value),
+                (Some(Slot::Unknown), Some(pushed))
+                    if slot(offset + 1) == Some(Slot::Unknown)
+                        && cpu.memory.get_byte(pushed.wrapping_sub(2)) == JSR =>
+                {
+                    EntryKind::ReturnAddress {
+                        caller: pushed.wrapping_sub(2),
+                        returns_to: pushed.wrapping_add(1),
+                    }
+                }
+                _ => EntryKind::Byte(value),
+            };
+            entries.push(StackEntry {
+                address: STACK_ADDRESS_LO | offset,
+                kind,
+            });
+            offset += kind.size();
+        }
+        StackView {
+            stack_pointer,
+            bytes,
+            entries,
+        }
+    }
+}
+
+/// What pushed a stack byte, as far as a [`CallTracker`] knows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Slot {
+    Unknown,
+    /// Part of the return address pushed by the `JSR` at this address.
+    Call(u16),
+    Interrupt,
+    Pushed(Instruction),
+}
+
+/// Remembers which instruction pushed each stack byte, for decoding the
+/// stack exactly.
+///
+/// Register it with
+/// [`Machine::add_observer`](crate::machine::Machine::add_observer),
+/// wrapped in an `Rc<RefCell<_>>` to keep access to it.
+#[derive(Clone, Debug)]
+pub struct CallTracker {
+    slots: [Slot; 256],
+    /// Stack offsets written since the last instruction began.
+    written: Vec<u8>,
+    /// Whether `written` belongs to an instruction already reported.
+    reported: bool,
+}
+
+impl Default for CallTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CallTracker {
+    #[must_use]
+    pub const fn new() -> CallTracker {
+        CallTracker {
+            slots: [Slot::Unknown; 256],
+            written: Vec::new(),
+            reported: false,
+        }
+    }
+
+    /// Reads the stack of `cpu`, using what the tracker saw pushed to tell
+    /// the entries apart. Bytes it didn't see pushed are guessed at as by
+    /// [`StackView::capture`].
+    #[must_use]
+    pub fn stack<M: Bus, V: Variant>(&self, cpu: &CPU<M, V>) -> StackView {
+        StackView::decode(cpu, &self.slots)
+    }
+}
+
+impl Observer for CallTracker {
+    fn on_instruction(&mut self, event: &InstructionEvent) {
+        let slot = match event.instruction.0 {
+            Instruction::JSR => Slot::Call(event.pc),
+            Instruction::BRK | Instruction::BRKcld => Slot::Interrupt,
+            instruction @ (Instruction::PHA
+            | Instruction::PHP
+            | Instruction::PHX
+            | Instruction::PHY) => Slot::Pushed(instruction),
+            _ => Slot::Unknown,
+        };
+        for &offset in &self.written {
+            self.slots[usize::from(offset)] = slot;
+        }
+        self.reported = true;
+    }
+
+    fn on_write(&mut self, address: u16, _value: u8) {
+        if self.reported {
+            self.written.clear();
+            self.reported = false;
+        }
+        if let [0x01, offset] = address.to_be_bytes() {
+            self.written.push(offset);
+        }
+    }
+
+    fn on_irq(&mut self, _handler: u16) {
+        // The interrupt's pushes come after those of the instruction.
+        for &offset in self.written.iter().rev().take(3) {
+            self.slots[usize::from(offset)] = Slot::Interrupt;
+        }
+    }
+
+    fn on_reset(&mut self) {
+        *self = CallTracker::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn guesses_return_addresses_without_a_tracker() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0300, &[JSR, 0x00, 0x04]);
+        // A return address to $0303, then a byte that doesn't follow a JSR.
+        cpu.memory.set_bytes(0x01fc, &[0x02, 0x03, 0x55]);
+        cpu.registers.stack_pointer.0 = 0xfb;
+
+        let stack = StackView::capture(&cpu);
+        assert_eq!(stack.bytes, [0x02, 0x03, 0x55, 0x00]);
+        assert_eq!(
+            stack.entries,
+            [
+                StackEntry {
+                    address: 0x01fc,
+                    kind: EntryKind::ReturnAddress {
+                        caller: 0x0300,
+                        returns_to: 0x0303
+                    }
+                },
+                StackEntry {
+                    address: 0x01fe,
+                    kind: EntryKind::Byte(0x55)
+                },
+                StackEntry {
+                    address: 0x01ff,
+                    kind: EntryKind::Byte(0x00)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_interrupt_frames() {
+        let mut tracker = CallTracker::new();
+        let event = |instruction| InstructionEvent {
+            pc: 0x0200,
+            opcode: 0x00,
+            instruction: (instruction, crate::instruction::OpInput::UseImplied),
+            cycles: 7,
+            next_pc: 0x0400,
+        };
+        // PHP, then NOP followed by an IRQ.
+        tracker.on_write(0x01ff, 0x30);
+        tracker.on_instruction(&event(Instruction::PHP));
+        for (address, value) in [(0x01fe, 0x02), (0x01fd, 0x01), (0x01fc, 0xb1)] {
+            tracker.on_write(address, value);
+        }
+        tracker.on_instruction(&event(Instruction::NOP));
+        tracker.on_irq(0x0400);
+
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x01fc, &[0xb1, 0x01, 0x02, 0x30]);
+        cpu.registers.stack_pointer.0 = 0xfb;
+        let kinds: Vec<_> = tracker
+            .stack(&cpu)
+            .entries
+            .iter()
+            .map(|entry| entry.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                EntryKind::Interrupt {
+                    status: Status::from_byte(0xb1),
+                    returns_to: 0x0201
+                },
+                EntryKind::Register(Instruction::PHP, 0x30),
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Inspecting machine state.
+//!
+//! A [`CpuState`] is the registers and cycle counter of a CPU at one
+//! moment, without the 64K of memory a [`Snapshot`](crate::snapshot::Snapshot)
+//! carries, so it is cheap to take after every instruction, to compare in
+//! test assertions and to show in debugger views. [`HexDump`] formats a
+//! range of memory as a classic hex dump.
+//!
+//! ```
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::registers::Flag;
+//! use mos6502::state::{CpuState, HexDump};
+//!
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! cpu.memory.set_bytes(0x0200, b"6502");
+//! let state = CpuState::capture(&cpu);
+//! assert!(state.flag(Flag::InterruptDisable));
+//! assert_eq!(
+//!     state.to_string(),
+//!     "PC=0000 A=00 X=00 Y=00 SP=FD P=nv-bdIzc CYC=0"
+//! );
+//! assert_eq!(
+//!     HexDump::new(&cpu.memory, 0x0200, 4).to_string(),
+//!     "0200  36 35 30 32                                       |6502|\n"
+//! );
+//! ```
+
+use core::fmt;
+
+use crate::cpu::CPU;
+use crate::memory::Bus;
+use crate::registers::{Flag, Registers};
+use crate::Variant;
+
+/// The registers and cycle counter of a CPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    pub registers: Registers,
+    /// Cycles since power-on.
+    pub cycles: u64,
+}
+
+impl CpuState {
+    #[must_use]
+    pub const fn capture<M: Bus, V: Variant>(cpu: &CPU<M, V>) -> CpuState {
+        CpuState {
+            registers: cpu.registers,
+            cycles: cpu.cycles,
+        }
+    }
+
+    #[must_use]
+    pub const fn flag(&self, flag: Flag) -> bool {
+        self.registers.flag(flag)
+    }
+
+    /// Every flag and whether it is set, from N down to C.
+    pub fn flags(&self) -> impl Iterator<Item = (Flag, bool)> + '_ {
+        Flag::ALL.into_iter().map(|flag| (flag, self.flag(flag)))
+    }
+}
+
+/// The registers in their canonical one-line form, see [`Registers`],
+/// followed by the cycle count: `PC=C000 A=00 X=00 Y=00 SP=FD
+/// P=nv-bdizc CYC=7`.
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} CYC={}", self.registers, self.cycles)
+    }
+}
+
+/// Bytes per line of a [`HexDump`].
+const WIDTH: usize = 16;
+
+/// A range of memory shown sixteen bytes to a line, with the address, the
+/// bytes in hex and their printable ASCII characters. Addresses wrap
+/// around at `$FFFF`.
+pub struct HexDump<'a, B: Bus + ?Sized> {
+    bus: &'a B,
+    start: u16,
+    len: usize,
+}
+
+impl<'a, B: Bus + ?Sized> HexDump<'a, B> {
+    /// Dumps `len` bytes of `bus` from `start`.
+    pub const fn new(bus: &'a B, start: u16, len: usize) -> HexDump<'a, B> {
+        HexDump { bus, start, len }
+    }
+}
+
+impl<B: Bus + ?Sized> fmt::Display for HexDump<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut address = self.start;
+        let mut left = self.len;
+        while left > 0 {
+            let count = left.min(WIDTH);
+            let mut bytes = [0; WIDTH];
+            for (offset, byte) in (0..=u16::MAX).zip(&mut bytes[..count]) {
+                *byte = self.bus.get_byte(address.wrapping_add(offset));
+            }
+            write!(f, "{address:04X} ")?;
+            for (column, byte) in bytes.iter().enumerate() {
+                if column == WIDTH / 2 {
+                    f.write_str(" ")?;
+                }
+                if column < count {
+                    write!(f, " {byte:02X}")?;
+                } else {
+                    f.write_str("   ")?;
+                }
+            }
+            f.write_str("  |")?;
+            for &byte in &bytes[..count] {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                };
+                fmt::Write::write_char(f, c)?;
+            }
+            f.write_str("|\n")?;
+            // Lines are 16 bytes, so this only truncates past the last.
+            #[allow(clippy::cast_possible_truncation)]
+            let advance = count as u16;
+            address = address.wrapping_add(advance);
+            left -= count;
+        }
+        Ok(())
+    }
+}
+
+impl<B: Bus + ?Sized> fmt::Debug for HexDump<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HexDump")
+            .field("start", &self.start)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+// The tests format into strings, which needs an allocator.
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use crate::registers::Status;
+    use alloc::{format, vec::Vec};
+
+    #[test]
+    fn flags_are_decoded_from_n_to_c() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.registers.status = Status::PS_NEGATIVE | Status::PS_CARRY;
+        let set: Vec<_> = CpuState::capture(&cpu)
+            .flags()
+            .filter_map(|(flag, set)| set.then_some(flag))
+            .collect();
+        assert_eq!(set, [Flag::Negative, Flag::Carry]);
+    }
+
+    #[test]
+    fn hex_dumps_wrap_around_and_pad_the_last_line() {
+        let mut memory = Memory::new();
+        memory.set_bytes(0xfff0, b"0123456789abcdef");
+        memory.set_bytes(0x0000, &[0x00, 0x7f, 0x41]);
+        assert_eq!(
+            format!("{}", HexDump::new(&memory, 0xfff0, 19)),
+            "FFF0  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             0000  00 7F 41                                          |..A|\n"
+        );
+        assert_eq!(format!("{}", HexDump::new(&memory, 0, 0)), "");
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Catching reads of uninitialized memory.
+//!
+//! RAM powers up holding garbage, so firmware that reads a variable before
+//! storing to it works or fails depending on the machine it runs on. Most
+//! emulators zero their memory and hide the bug. [`Strict`] wraps a bus and
+//! remembers which addresses have been written; while strict mode is on, a
+//! read of RAM that was never written is recorded as an
+//! [`UninitializedRead`]. [`step`] executes one instruction and reports the
+//! first such read along with the address of the instruction that made it.
+
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::ops::{Range, RangeInclusive};
+
+use crate::cpu::CPU;
+use crate::instruction::DecodedInstr;
+use crate::memory::{Bus, BusError};
+use crate::Variant;
+
+/// A read of an address that had never been written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UninitializedRead {
+    /// Address of the instruction that made the read, if known.
+    pub pc: Option<u16>,
+    pub address: u16,
+}
+
+impl fmt::Display for UninitializedRead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "read of uninitialized memory at ${:04X}", self.address)?;
+        if let Some(pc) = self.pc {
+            write!(f, " by the instruction at ${pc:04X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A bus that tracks which addresses have been written and flags reads of
+/// those that have not.
+///
+/// Only addresses inside the ranges added with [`Strict::add_ram`] are
+/// checked, so reads of ROM and I/O registers are never flagged. With no
+/// ranges added, every address is treated as RAM. Loading a program with
+/// [`Bus::set_bytes`] counts as writing it.
+///
+/// Reads made by tools such as a disassembler or memory viewer go through
+/// [`Bus::get_byte`] too; turn checking off with [`Strict::set_enabled`]
+/// around them.
+#[derive(Debug)]
+pub struct Strict<B: Bus> {
+    inner: B,
+    written: [u64; 1024],
+    ram: Vec<RangeInclusive<u16>>,
+    enabled: Cell<bool>,
+    reads: RefCell<Vec<UninitializedRead>>,
+}
+
+impl<B: Bus> Strict<B> {
+    /// Wraps `inner` with strict mode on and no address written yet.
+    pub const fn new(inner: B) -> Strict<B> {
+        Strict {
+            inner,
+            written: [0; 1024],
+            ram: Vec::new(),
+            enabled: Cell::new(true),
+            reads: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Checks reads in `range`. Without any ranges, all of memory is checked.
+    pub fn add_ram(&mut self, range: RangeInclusive<u16>) {
+        self.ram.push(range);
+    }
+
+    /// Turns checking on or off. Writes are tracked either way.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Whether `address` has been written.
+    #[must_use]
+    pub const fn is_written(&self, address: u16) -> bool {
+        self.written[address as usize / 64] & (1 << (address % 64)) != 0
+    }
+
+    /// Marks `range` as initialized without writing to it, e.g. for memory
+    /// the hardware clears at power-on.
+    pub fn mark_written(&mut self, range: RangeInclusive<u16>) {
+        for address in range {
+            self.mark(address);
+        }
+    }
+
+    /// Returns and forgets the uninitialized reads recorded so far.
+    pub fn take_reads(&self) -> Vec<UninitializedRead> {
+        self.reads.take()
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus. Writes made through it
+    /// are not tracked.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    const fn mark(&mut self, address: u16) {
+        self.written[address as usize / 64] |= 1 << (address % 64);
+    }
+
+    fn is_ram(&self, address: u16) -> bool {
+        self.ram.is_empty() || self.ram.iter().any(|range| range.contains(&address))
+    }
+}
+
+impl<B: Bus> Bus for Strict<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        if self.enabled.get() && !self.is_written(address) && self.is_ram(address) {
+            self.reads
+                .borrow_mut()
+                .push(UninitializedRead { pc: None, address });
+        }
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self.mark(address);
+        self.inner.set_byte(address, value);
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        for (address, _) in (start..=u16::MAX).zip(values) {
+            self.mark(address);
+        }
+        self.inner.set_bytes(start, values);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+/// Executes one instruction on `cpu`.
+///
+/// # Errors
+///
+/// Returns the first read of uninitialized memory the instruction made,
+/// including the fetch of the instruction itself. The instruction has been
+/// executed regardless.
+pub fn step<B: Bus, V: Variant>(
+    cpu: &mut CPU<Strict<B>, V>,
+) -> Result<Option<DecodedInstr>, UninitializedRead> {
+    let pc = cpu.registers.program_counter;
+    cpu.memory.reads.borrow_mut().clear();
+    let decoded = cpu.single_step();
+    match cpu.memory.take_reads().first() {
+        Some(read) => Err(UninitializedRead {
+            pc: Some(pc),
+            ..*read
+        }),
+        None => Ok(decoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn flags_reads_of_unwritten_ram() {
+        let mut bus = Strict::new(Memory::new());
+        bus.add_ram(0x0000..=0x07FF);
+        let mut cpu = CPU::new(bus, Nmos6502);
+        // LDA #$01; STA $10; LDA $10; LDA $11; LDA $C000
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0xa9, 0x01, 0x85, 0x10, 0xa5, 0x10, 0xa5, 0x11, 0xad, 0x00, 0xc0,
+            ],
+        );
+        cpu.registers.program_counter = 0x0200;
+
+        assert!(step(&mut cpu).is_ok());
+        assert!(step(&mut cpu).is_ok());
+        assert!(step(&mut cpu).is_ok());
+        assert_eq!(
+            step(&mut cpu).unwrap_err(),
+            UninitializedRead {
+                pc: Some(0x0206),
+                address: 0x0011
+            }
+        );
+        // $C000 is outside the RAM ranges.
+        assert!(step(&mut cpu).is_ok());
+
+        cpu.memory.set_enabled(false);
+        cpu.registers.program_counter = 0x0206;
+        assert!(step(&mut cpu).is_ok());
+    }
+}
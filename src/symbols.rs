@@ -0,0 +1,265 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Symbol tables naming addresses in a program.
+//!
+//! Symbols are read from plain text, one per line, in either of two forms:
+//!
+//! - VICE monitor labels, as written by `ld65 -Ln`: `al C:C000 .reset` or
+//!   `al 00C000 .reset`;
+//! - assignments: `reset = $C000`, `reset := 0xC000` or `reset = 49152`.
+//!
+//! Blank lines and lines starting with `;` or `#` are ignored.
+
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::disasm::Labels;
+
+/// Error raised for a malformed line in a symbol file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// One-based line number.
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// A bidirectional map between symbol names and addresses.
+///
+/// Several names may refer to one address; the first one added is the one
+/// used to label that address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+    addresses: BTreeMap<String, u16>,
+}
+
+impl SymbolTable {
+    #[must_use]
+    pub const fn new() -> SymbolTable {
+        SymbolTable {
+            names: BTreeMap::new(),
+            addresses: BTreeMap::new(),
+        }
+    }
+
+    /// Parses a symbol file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that is in neither of the supported forms.
+    pub fn parse(text: &str) -> Result<SymbolTable, ParseError> {
+        let mut table = SymbolTable::new();
+        table.load(text)?;
+        Ok(table)
+    }
+
+    /// Adds the symbols of a symbol file to the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that is in neither of the supported forms.
+    /// Symbols on the lines before it have already been added.
+    pub fn load(&mut self, text: &str) -> Result<(), ParseError> {
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            let (name, address) = parse_line(line).map_err(|message| ParseError {
+                line: index + 1,
+                message,
+            })?;
+            self.insert(name, address);
+        }
+        Ok(())
+    }
+
+    /// Adds a symbol. Redefining a name moves it to the new address.
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(old) = self.addresses.insert(name.to_owned(), address) {
+            if self.names.get(&old).is_some_and(|n| n == name) {
+                self.names.remove(&old);
+                // Fall back to another name for the old address, if any.
+                if let Some((other, _)) = self.addresses.iter().find(|(_, a)| **a == old) {
+                    self.names.insert(old, other.clone());
+                }
+            }
+        }
+        self.names.entry(address).or_insert_with(|| name.to_owned());
+    }
+
+    /// The address of the symbol called `name`.
+    #[must_use]
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// The name labelling `address`.
+    #[must_use]
+    pub fn name_of(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// Iterates over all symbols in order of name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.addresses
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Writes the table as VICE `al` lines in address order, which
+    /// [`SymbolTable::parse`] reads back to the same table.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by `out`.
+    pub fn write(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let mut symbols: Vec<_> = self.iter().collect();
+        // The name labelling an address goes first so it is the first added
+        // when the file is loaded again.
+        symbols.sort_by_key(|(name, address)| (*address, self.name_of(*address) != Some(*name)));
+        for (name, address) in symbols {
+            writeln!(out, "al C:{address:04X} .{name}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Labels for SymbolTable {
+    fn label(&self, address: u16) -> Option<&str> {
+        self.name_of(address)
+    }
+}
+
+fn parse_line(line: &str) -> Result<(&str, u16), &'static str> {
+    let mut words = line.split_whitespace();
+    if let Some("al") = words.next() {
+        let address = words.next().ok_or("missing address")?;
+        let address = address.strip_prefix("C:").unwrap_or(address);
+        let name = words.next().ok_or("missing label name")?;
+        let name = name.strip_prefix('.').unwrap_or(name);
+        let address = u32::from_str_radix(address, 16).map_err(|_| "malformed address")?;
+        return Ok((
+            name,
+            u16::try_from(address).map_err(|_| "address out of range")?,
+        ));
+    }
+
+    let (name, value) = line
+        .split_once(":=")
+        .or_else(|| line.split_once('='))
+        .ok_or("expected `name = address` or `al address .name`")?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("malformed symbol name");
+    }
+    Ok((name, parse_number(value.trim()).ok_or("malformed address")?))
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_forms() {
+        let table = SymbolTable::parse(
+            "; reset handler\nal C:c000 .reset\nal 00C010 .nmi\n\nirq = $C020\ncount := 16\n",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.address_of("reset"), Some(0xc000));
+        assert_eq!(table.address_of("nmi"), Some(0xc010));
+        assert_eq!(table.name_of(0xc020), Some("irq"));
+        assert_eq!(table.name_of(0x0010), Some("count"));
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let err = SymbolTable::parse("reset = $C000\nbogus\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(SymbolTable::parse("x = $10000").is_err());
+    }
+
+    #[test]
+    fn first_name_labels_an_address() {
+        let mut table = SymbolTable::new();
+        table.insert("start", 0x0200);
+        table.insert("main", 0x0200);
+        assert_eq!(table.name_of(0x0200), Some("start"));
+        table.insert("start", 0x0300);
+        assert_eq!(table.name_of(0x0200), Some("main"));
+        assert_eq!(table.name_of(0x0300), Some("start"));
+        assert_eq!(table.address_of("main"), Some(0x0200));
+    }
+
+    #[test]
+    fn written_tables_parse_back() {
+        let mut table = SymbolTable::new();
+        table.insert("start", 0x0200);
+        table.insert("main", 0x0200);
+        table.insert("start.loop", 0x0203);
+        table.insert(".local", 0x0010);
+        let mut text = String::new();
+        table.write(&mut text).unwrap();
+        assert_eq!(
+            text,
+            "al C:0010 ..local\nal C:0200 .start\nal C:0200 .main\nal C:0203 .start.loop\n"
+        );
+        assert_eq!(SymbolTable::parse(&text).unwrap(), table);
+    }
+}
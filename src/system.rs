@@ -0,0 +1,617 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Keeping devices in step with the CPU clock.
+//!
+//! Every device is driven from the CPU's cycle counter, the master clock. A
+//! [`ClockDomain`] converts master cycles into ticks of a device's own clock
+//! using a [`ClockDivider`], so a peripheral running at a quarter of the CPU
+//! speed or a video chip running at three times the CPU speed stays exactly
+//! in sync without accumulating rounding drift.
+//!
+//! A [`Scheduler`] also owns the IRQ line its devices share: after each
+//! instruction, it drives the CPU's IRQ input from its [`IrqLine`]. Events
+//! such as a timer running out or a raster line being reached can be
+//! scheduled for a future cycle; they run once the devices have been
+//! advanced to exactly that cycle, in the order they are due.
+
+#[cfg(feature = "alloc")]
+use crate::{
+    cpu::Cpu,
+    interrupt::{IrqLine, IrqSource},
+};
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, collections::VecDeque, rc::Rc, string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::cell::RefCell;
+
+/// A device that is driven by a clock.
+pub trait Tickable {
+    /// Advances the device by `ticks` cycles of its own clock.
+    fn tick(&mut self, ticks: u64);
+}
+
+impl<T: Tickable + ?Sized> Tickable for &mut T {
+    fn tick(&mut self, ticks: u64) {
+        (**self).tick(ticks);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Tickable + ?Sized> Tickable for Box<T> {
+    fn tick(&mut self, ticks: u64) {
+        (**self).tick(ticks);
+    }
+}
+
+/// Lets a device be shared between the scheduler and a bus that maps its
+/// registers.
+#[cfg(feature = "alloc")]
+impl<T: Tickable + ?Sized> Tickable for Rc<RefCell<T>> {
+    fn tick(&mut self, ticks: u64) {
+        self.borrow_mut().tick(ticks);
+    }
+}
+
+/// A device that returns to its power-on state when the machine is reset,
+/// such as a timer chip kept outside the bus and shared with it through an
+/// `Rc<RefCell<_>>`. Devices that are part of the bus are reset through
+/// [`Bus::reset`](crate::memory::Bus::reset) instead.
+pub trait Resettable {
+    fn reset(&mut self);
+}
+
+impl<T: Resettable + ?Sized> Resettable for &mut T {
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Resettable + ?Sized> Resettable for Box<T> {
+    fn reset(&mut self) {
+        (**self).reset();
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Resettable + ?Sized> Resettable for Rc<RefCell<T>> {
+    fn reset(&mut self) {
+        self.borrow_mut().reset();
+    }
+}
+
+/// The ratio between a device clock and the CPU clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClockDivider {
+    multiplier: u32,
+    divisor: u32,
+}
+
+impl ClockDivider {
+    /// A device running at the CPU clock.
+    pub const CPU: ClockDivider = ClockDivider::new(1, 1);
+
+    /// A device clocked at `multiplier / divisor` times the CPU clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `divisor` is zero.
+    #[must_use]
+    pub const fn new(multiplier: u32, divisor: u32) -> ClockDivider {
+        assert!(divisor != 0, "clock divisor must not be zero");
+        ClockDivider {
+            multiplier,
+            divisor,
+        }
+    }
+
+    /// A device clocked at the CPU clock divided by `divisor`.
+    #[must_use]
+    pub const fn divide(divisor: u32) -> ClockDivider {
+        ClockDivider::new(1, divisor)
+    }
+
+    /// A device clocked at `multiplier` times the CPU clock.
+    #[must_use]
+    pub const fn multiply(multiplier: u32) -> ClockDivider {
+        ClockDivider::new(multiplier, 1)
+    }
+
+    /// Number of device ticks that have elapsed after `master_cycles` CPU
+    /// cycles.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn ticks_at(self, master_cycles: u64) -> u64 {
+        // Widen so that large cycle counts don't overflow the multiplication.
+        (master_cycles as u128 * self.multiplier as u128 / self.divisor as u128) as u64
+    }
+
+    /// The first CPU cycle at which the device has ticked `ticks` times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the multiplier is zero and `ticks` isn't.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn master_cycles_at(self, ticks: u64) -> u64 {
+        if ticks == 0 {
+            return 0;
+        }
+        (ticks as u128 * self.divisor as u128).div_ceil(self.multiplier as u128) as u64
+    }
+}
+
+impl Default for ClockDivider {
+    fn default() -> Self {
+        ClockDivider::CPU
+    }
+}
+
+/// Tracks how far a device clock has been advanced relative to the master
+/// clock.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClockDomain {
+    divider: ClockDivider,
+    ticks: u64,
+}
+
+impl ClockDomain {
+    #[must_use]
+    pub const fn new(divider: ClockDivider) -> ClockDomain {
+        ClockDomain { divider, ticks: 0 }
+    }
+
+    #[must_use]
+    pub const fn divider(&self) -> ClockDivider {
+        self.divider
+    }
+
+    /// Total number of device ticks issued so far.
+    #[must_use]
+    pub const fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the number of device ticks owed to catch up with
+    /// `master_cycles`, and records them as issued.
+    pub const fn advance_to(&mut self, master_cycles: u64) -> u64 {
+        let target = self.divider.ticks_at(master_cycles);
+        let owed = target.saturating_sub(self.ticks);
+        self.ticks += owed;
+        owed
+    }
+
+    /// Ticks `device` forward so that it is in step with `master_cycles`.
+    pub fn catch_up<T: Tickable + ?Sized>(&mut self, master_cycles: u64, device: &mut T) {
+        let owed = self.advance_to(master_cycles);
+        if owed > 0 {
+            device.tick(owed);
+        }
+    }
+}
+
+/// Identifies a device registered with a [`Scheduler`].
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId(usize);
+
+/// Identifies an event scheduled with a [`Scheduler`].
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventId(u64);
+
+/// What an event does when it is due. It gets the scheduler, advanced to
+/// the cycle it was due at, so it can schedule the next one.
+#[cfg(feature = "alloc")]
+type Action<'a> = Box<dyn FnOnce(&mut Scheduler<'a>) + 'a>;
+
+#[cfg(feature = "alloc")]
+struct Event<'a> {
+    due: u64,
+    id: EventId,
+    action: Action<'a>,
+}
+
+/// Drives a set of devices, each in its own clock domain, from the CPU's
+/// cycle counter.
+///
+/// Devices that are also memory-mapped can be shared with the bus by
+/// registering an `Rc<RefCell<_>>` handle.
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+///
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::system::Scheduler;
+///
+/// let fired = Rc::new(Cell::new(None));
+/// let mut scheduler = Scheduler::new();
+/// let at = Rc::clone(&fired);
+/// scheduler.schedule(5, move |scheduler| at.set(Some(scheduler.master_cycles())));
+///
+/// let mut cpu = CPU::new(Memory::new(), Nmos6502);
+/// cpu.memory.set_bytes(0x0000, &[0xea, 0xea, 0xea]);
+/// for _ in 0..3 {
+///     scheduler.step(&mut cpu);
+/// }
+/// assert_eq!(fired.get(), Some(5));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Default)]
+pub struct Scheduler<'a> {
+    devices: Vec<(ClockDomain, Box<dyn Tickable + 'a>)>,
+    master_cycles: u64,
+    irq: IrqLine<'a>,
+    /// Pending events, ordered by when they are due and then by when they
+    /// were scheduled.
+    events: VecDeque<Event<'a>>,
+    next_event_id: u64,
+    granularity: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Scheduler<'a> {
+    #[must_use]
+    pub fn new() -> Scheduler<'a> {
+        Scheduler::default()
+    }
+
+    /// Registers a device clocked at `divider` times the CPU clock.
+    ///
+    /// The device's clock starts at the scheduler's current master cycle.
+    pub fn add<T: Tickable + 'a>(&mut self, divider: ClockDivider, device: T) -> DeviceId {
+        let mut domain = ClockDomain::new(divider);
+        domain.advance_to(self.master_cycles);
+        self.devices.push((domain, Box::new(device)));
+        DeviceId(self.devices.len() - 1)
+    }
+
+    /// Connects a device's IRQ output to the CPU's IRQ input, under `name`.
+    ///
+    /// Once a device is connected, [`Scheduler::step`] drives the CPU's
+    /// IRQ input, overriding [`CPU::set_irq`](crate::cpu::CPU::set_irq).
+    pub fn add_irq_source(&mut self, name: impl Into<String>, source: impl IrqSource + 'a) {
+        self.irq.add(name, source);
+    }
+
+    /// The IRQ line of the devices, for asking which are asserting it.
+    #[must_use]
+    pub const fn irq_line(&self) -> &IrqLine<'a> {
+        &self.irq
+    }
+
+    /// The clock domain of a registered device.
+    #[must_use]
+    pub fn domain(&self, id: DeviceId) -> Option<&ClockDomain> {
+        self.devices.get(id.0).map(|(domain, _)| domain)
+    }
+
+    /// The master cycle the devices have been advanced to.
+    #[must_use]
+    pub const fn master_cycles(&self) -> u64 {
+        self.master_cycles
+    }
+
+    /// Makes [`Scheduler::step`] only advance the devices once they lag the
+    /// CPU by `cycles` or more, or when an event is due, which saves work
+    /// for devices that are cheap to tick in bulk. Zero, the default, and
+    /// one advance them after every instruction. Call
+    /// [`Scheduler::advance_to`] to bring them up to date before looking
+    /// at them.
+    pub const fn set_granularity(&mut self, cycles: u64) {
+        self.granularity = cycles;
+    }
+
+    #[must_use]
+    pub const fn granularity(&self) -> u64 {
+        self.granularity
+    }
+
+    /// Schedules `action` to run once the devices reach `delay` cycles
+    /// after [`Scheduler::master_cycles`].
+    pub fn schedule(
+        &mut self,
+        delay: u64,
+        action: impl FnOnce(&mut Scheduler<'a>) + 'a,
+    ) -> EventId {
+        self.schedule_at(self.master_cycles.saturating_add(delay), action)
+    }
+
+    /// Schedules `action` to run once the devices reach master cycle `due`.
+    /// Events due at the same cycle run in the order they were scheduled,
+    /// and one already in the past runs on the next advance.
+    pub fn schedule_at(
+        &mut self,
+        due: u64,
+        action: impl FnOnce(&mut Scheduler<'a>) + 'a,
+    ) -> EventId {
+        let id = EventId(self.next_event_id);
+        self.next_event_id += 1;
+        let index = self.events.partition_point(|event| event.due <= due);
+        self.events.insert(
+            index,
+            Event {
+                due,
+                id,
+                action: Box::new(action),
+            },
+        );
+        id
+    }
+
+    /// Drops a pending event. Returns `false` if it has already run or been
+    /// cancelled.
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        let index = self.events.iter().position(|event| event.id == id);
+        index.and_then(|index| self.events.remove(index)).is_some()
+    }
+
+    /// The master cycle the next event is due at.
+    #[must_use]
+    pub fn next_event(&self) -> Option<u64> {
+        self.events.front().map(|event| event.due)
+    }
+
+    /// Advances every device to `master_cycles`, stopping at each event due
+    /// on the way to run it.
+    pub fn advance_to(&mut self, master_cycles: u64) {
+        let target = self.master_cycles.max(master_cycles);
+        while let Some(event) = self.events.pop_front() {
+            if event.due > target {
+                self.events.push_front(event);
+                break;
+            }
+            self.catch_up(event.due);
+            (event.action)(self);
+        }
+        self.catch_up(target);
+    }
+
+    fn catch_up(&mut self, master_cycles: u64) {
+        self.master_cycles = self.master_cycles.max(master_cycles);
+        for (domain, device) in &mut self.devices {
+            domain.catch_up(self.master_cycles, device);
+        }
+    }
+
+    /// Executes one instruction on `cpu` and then brings every device up to
+    /// the CPU's cycle counter, as far as the granularity asks for, and
+    /// updates the CPU's IRQ input from them.
+    pub fn step<C: Cpu>(&mut self, cpu: &mut C) -> Option<C::Step> {
+        let decoded = cpu.step();
+        let cycles = cpu.cycles();
+        if cycles >= self.master_cycles.saturating_add(self.granularity)
+            || self.next_event().is_some_and(|due| due <= cycles)
+        {
+            self.advance_to(cycles);
+        }
+        if !self.irq.is_empty() {
+            cpu.set_irq(self.irq.asserted());
+        }
+        decoded
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for Scheduler<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("devices", &self.devices.len())
+            .field("master_cycles", &self.master_cycles)
+            .field("irq", &self.irq)
+            .field("events", &self.events.len())
+            .field("granularity", &self.granularity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use crate::{cpu::CPU, memory::Bus};
+
+    #[derive(Default)]
+    struct Counter(u64);
+
+    impl Tickable for Counter {
+        fn tick(&mut self, ticks: u64) {
+            self.0 += ticks;
+        }
+    }
+
+    #[test]
+    fn dividers_stay_in_sync_without_drift() {
+        let mut quarter = ClockDomain::new(ClockDivider::divide(4));
+        let mut triple = ClockDomain::new(ClockDivider::multiply(3));
+        let mut slow = Counter::default();
+        let mut fast = Counter::default();
+
+        // Advance in uneven steps, as instructions of varying length would.
+        let mut master = 0;
+        for step in [2, 3, 7, 2, 5, 4, 6, 1] {
+            master += step;
+            quarter.catch_up(master, &mut slow);
+            triple.catch_up(master, &mut fast);
+            assert_eq!(slow.0, master / 4);
+            assert_eq!(fast.0, master * 3);
+        }
+    }
+
+    #[test]
+    fn fractional_ratio() {
+        // A 5:3 ratio needs the remainder carried between calls.
+        let mut domain = ClockDomain::new(ClockDivider::new(5, 3));
+        assert_eq!(domain.advance_to(1), 1);
+        assert_eq!(domain.advance_to(2), 2);
+        assert_eq!(domain.advance_to(3), 2);
+        assert_eq!(domain.ticks(), 5);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn scheduler_ticks_shared_devices() {
+        use crate::instruction::Nmos6502;
+        use crate::memory::Memory;
+
+        let ppu = Rc::new(RefCell::new(Counter::default()));
+        let timer = Rc::new(RefCell::new(Counter::default()));
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ClockDivider::multiply(3), Rc::clone(&ppu));
+        scheduler.add(ClockDivider::divide(4), Rc::clone(&timer));
+
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        // NOP; NOP; NOP; NOP
+        cpu.memory.set_bytes(0x0000, &[0xea, 0xea, 0xea, 0xea]);
+        for _ in 0..4 {
+            scheduler.step(&mut cpu);
+        }
+
+        assert_eq!(cpu.cycles, 8);
+        assert_eq!(ppu.borrow().0, 24);
+        assert_eq!(timer.borrow().0, 2);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn scheduler_drives_the_irq_line() {
+        use crate::instruction::Nmos6502;
+        use crate::memory::Memory;
+
+        /// Asserts IRQ once it has ticked 5 times.
+        #[derive(Default)]
+        struct Timer(u64);
+
+        impl Tickable for Timer {
+            fn tick(&mut self, ticks: u64) {
+                self.0 += ticks;
+            }
+        }
+
+        impl IrqSource for Timer {
+            fn irq_pending(&self) -> bool {
+                self.0 >= 5
+            }
+        }
+
+        let timer = Rc::new(RefCell::new(Timer::default()));
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ClockDivider::CPU, Rc::clone(&timer));
+        scheduler.add_irq_source("timer", Rc::clone(&timer));
+        scheduler.add_irq_source("idle", Timer::default());
+
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0000, &[0xea, 0xea, 0xea]);
+        scheduler.step(&mut cpu);
+        scheduler.step(&mut cpu);
+        assert!(!cpu.irq_asserted());
+        scheduler.step(&mut cpu);
+        assert!(cpu.irq_asserted());
+        assert_eq!(
+            scheduler.irq_line().asserting().collect::<Vec<_>>(),
+            ["timer"]
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn events_run_in_order_at_their_cycle() {
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ClockDivider::CPU, Rc::clone(&counter));
+        for (due, name) in [(7, "b"), (3, "a"), (7, "c"), (20, "late")] {
+            let (counter, log) = (Rc::clone(&counter), Rc::clone(&log));
+            scheduler.schedule_at(due, move |_| {
+                log.borrow_mut().push((name, counter.borrow().0));
+            });
+        }
+        let cancelled = scheduler.schedule(5, |_| panic!("cancelled"));
+        assert!(scheduler.cancel(cancelled));
+        assert!(!scheduler.cancel(cancelled));
+
+        scheduler.advance_to(10);
+        assert_eq!(*log.borrow(), [("a", 3), ("b", 7), ("c", 7)]);
+        assert_eq!(counter.borrow().0, 10);
+        assert_eq!(scheduler.next_event(), Some(20));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn events_can_reschedule_themselves() {
+        fn raster(lines: Rc<RefCell<Vec<u64>>>) -> impl FnOnce(&mut Scheduler) {
+            move |scheduler| {
+                lines.borrow_mut().push(scheduler.master_cycles());
+                scheduler.schedule(63, raster(lines));
+            }
+        }
+
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(63, raster(Rc::clone(&lines)));
+        scheduler.advance_to(200);
+        assert_eq!(*lines.borrow(), [63, 126, 189]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn granularity_batches_ticks_but_not_events() {
+        use crate::instruction::Nmos6502;
+        use crate::memory::Memory;
+
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        let fired = Rc::new(RefCell::new(None));
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ClockDivider::CPU, Rc::clone(&counter));
+        scheduler.set_granularity(8);
+        let at = Rc::clone(&fired);
+        scheduler.schedule_at(3, move |scheduler| {
+            *at.borrow_mut() = Some(scheduler.master_cycles());
+        });
+
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0000, &[0xea; 8]);
+        scheduler.step(&mut cpu);
+        assert_eq!(counter.borrow().0, 0);
+        scheduler.step(&mut cpu);
+        // The event was due, so the devices caught up to the CPU.
+        assert_eq!(*fired.borrow(), Some(3));
+        assert_eq!(counter.borrow().0, 4);
+        for _ in 0..3 {
+            scheduler.step(&mut cpu);
+        }
+        assert_eq!(counter.borrow().0, 4);
+        scheduler.step(&mut cpu);
+        assert_eq!(counter.borrow().0, 12);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Following data through a program.
+//!
+//! [`Taint`] keeps a shadow bit for every byte of memory and for the A, X
+//! and Y registers. Bytes can be tainted directly, or an address range can
+//! be declared a *source* so that every value loaded from it is tainted,
+//! which suits I/O registers such as a keyboard port. As the program runs,
+//! taint follows values through loads, stores, register transfers, the
+//! stack and ALU operations, and stores of tainted values into a *sink*
+//! range are reported. That answers questions such as "does user input ever
+//! reach this I/O register?".
+//!
+//! Taint follows data only: a value computed from a tainted pointer or
+//! selected by a branch on a tainted flag is not tainted.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use crate::cpu::CPU;
+use crate::instruction::{DecodedInstr, Instruction, OpInput};
+use crate::memory::Bus;
+use crate::Variant;
+
+/// A tainted value stored into a sink.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaintedWrite {
+    /// Address of the storing instruction.
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl fmt::Display for TaintedWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tainted ${:02X} written to ${:04X} by the instruction at ${:04X}",
+            self.value, self.address, self.pc
+        )
+    }
+}
+
+/// Shadow state recording which bytes and registers hold tainted data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Taint {
+    memory: [u64; 1024],
+    a: bool,
+    x: bool,
+    y: bool,
+    sources: Vec<RangeInclusive<u16>>,
+    sinks: Vec<RangeInclusive<u16>>,
+    writes: Vec<TaintedWrite>,
+}
+
+impl Default for Taint {
+    fn default() -> Self {
+        Taint::new()
+    }
+}
+
+impl Taint {
+    #[must_use]
+    pub const fn new() -> Taint {
+        Taint {
+            memory: [0; 1024],
+            a: false,
+            x: false,
+            y: false,
+            sources: Vec::new(),
+            sinks: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Taints the bytes in `range`.
+    pub fn taint(&mut self, range: RangeInclusive<u16>) {
+        for address in range {
+            self.set(address, true);
+        }
+    }
+
+    /// Clears the taint of the bytes in `range`.
+    pub fn clean(&mut self, range: RangeInclusive<u16>) {
+        for address in range {
+            self.set(address, false);
+        }
+    }
+
+    /// Taints every value loaded from `range`, whatever was stored there.
+    pub fn add_source(&mut self, range: RangeInclusive<u16>) {
+        self.sources.push(range);
+    }
+
+    /// Reports stores of tainted values into `range`.
+    pub fn add_sink(&mut self, range: RangeInclusive<u16>) {
+        self.sinks.push(range);
+    }
+
+    /// Whether the byte at `address` is tainted.
+    #[must_use]
+    pub fn is_tainted(&self, address: u16) -> bool {
+        self.memory[usize::from(address) / 64] & (1 << (address % 64)) != 0
+            || self.sources.iter().any(|range| range.contains(&address))
+    }
+
+    /// Taint of the A, X and Y registers.
+    #[must_use]
+    pub const fn registers(&self) -> (bool, bool, bool) {
+        (self.a, self.x, self.y)
+    }
+
+    /// The tainted bytes of memory, in address order. Source ranges are not
+    /// included.
+    pub fn tainted(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=u16::MAX)
+            .filter(|&address| self.memory[usize::from(address) / 64] & (1 << (address % 64)) != 0)
+    }
+
+    /// Returns and forgets the tainted stores into sinks seen so far.
+    pub fn take_writes(&mut self) -> Vec<TaintedWrite> {
+        core::mem::take(&mut self.writes)
+    }
+
+    /// Executes one instruction on `cpu`, propagating taint through it.
+    ///
+    /// The instruction is decoded once more beforehand to find its operand,
+    /// so operand and pointer bytes are read from the bus twice.
+    pub fn step<M: Bus, V: Variant>(&mut self, cpu: &mut CPU<M, V>) -> Option<DecodedInstr> {
+        let pc = cpu.registers.program_counter;
+        let decoded = cpu.fetch_next_and_decode();
+        cpu.registers.program_counter = pc;
+        let stack = cpu.registers.stack_pointer.to_u16();
+
+        let executed = cpu.single_step();
+        if let Some(decoded) = decoded {
+            self.propagate(cpu, pc, stack, decoded);
+        }
+        executed
+    }
+
+    /// Updates the shadow state after `instruction` executed at `pc` with
+    /// the stack pointer at `stack` beforehand.
+    fn propagate<M: Bus, V: Variant>(
+        &mut self,
+        cpu: &CPU<M, V>,
+        pc: u16,
+        stack: u16,
+        (instruction, input): DecodedInstr,
+    ) {
+        let operand = match input {
+            OpInput::UseAddress(address) => self.is_tainted(address),
+            _ => false,
+        };
+        let pushed = stack;
+        let pulled = stack.wrapping_add(1) & 0x01FF | 0x0100;
+
+        match instruction {
+            Instruction::LDA => self.a = operand,
+            Instruction::LDX => self.x = operand,
+            Instruction::LDY => self.y = operand,
+            Instruction::ADC
+            | Instruction::ADCnd
+            | Instruction::SBC
+            | Instruction::SBCnd
+            | Instruction::AND
+            | Instruction::ORA
+            | Instruction::EOR => self.a |= operand,
+            Instruction::TAX => self.x = self.a,
+            Instruction::TAY => self.y = self.a,
+            Instruction::TXA => self.a = self.x,
+            Instruction::TYA => self.a = self.y,
+            Instruction::TSX => self.x = false,
+            Instruction::PHA => self.set(pushed, self.a),
+            Instruction::PHX => self.set(pushed, self.x),
+            Instruction::PHY => self.set(pushed, self.y),
+            Instruction::PLA => self.a = self.is_tainted(pulled),
+            Instruction::PLX => self.x = self.is_tainted(pulled),
+            Instruction::PLY => self.y = self.is_tainted(pulled),
+            Instruction::STA => self.store(cpu, pc, input, self.a),
+            Instruction::STX => self.store(cpu, pc, input, self.x),
+            Instruction::STY => self.store(cpu, pc, input, self.y),
+            Instruction::STZ => self.store(cpu, pc, input, false),
+            Instruction::TSB | Instruction::TRB => {
+                self.store(cpu, pc, input, operand || self.a);
+            }
+            _ => {}
+        }
+    }
+
+    fn store<M: Bus, V: Variant>(
+        &mut self,
+        cpu: &CPU<M, V>,
+        pc: u16,
+        input: OpInput,
+        tainted: bool,
+    ) {
+        let OpInput::UseAddress(address) = input else {
+            return;
+        };
+        self.set(address, tainted);
+        if tainted && self.sinks.iter().any(|range| range.contains(&address)) {
+            self.writes.push(TaintedWrite {
+                pc,
+                address,
+                value: cpu.memory.get_byte(address),
+            });
+        }
+    }
+
+    const fn set(&mut self, address: u16, tainted: bool) {
+        let bit = 1 << (address % 64);
+        let word = &mut self.memory[address as usize / 64];
+        if tainted {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn input_reaching_an_output_register() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        // LDA $D010   ; keyboard
+        // PHA
+        // LDA #$00
+        // PLA
+        // TAX
+        // STX $20
+        // LDA $20
+        // EOR #$80
+        // STA $D012   ; display
+        // LDA #$41
+        // STA $D012
+        cpu.memory.set_bytes(
+            0x0200,
+            &[
+                0xad, 0x10, 0xd0, 0x48, 0xa9, 0x00, 0x68, 0xaa, 0x86, 0x20, 0xa5, 0x20, 0x49, 0x80,
+                0x8d, 0x12, 0xd0, 0xa9, 0x41, 0x8d, 0x12, 0xd0,
+            ],
+        );
+        cpu.registers.program_counter = 0x0200;
+        cpu.registers.stack_pointer.0 = 0xff;
+        cpu.memory.set_byte(0xd010, 0xc1);
+
+        let mut taint = Taint::new();
+        taint.add_source(0xd010..=0xd010);
+        taint.add_sink(0xd012..=0xd012);
+        while cpu.registers.program_counter != 0x0216 {
+            taint.step(&mut cpu);
+            if cpu.registers.program_counter == 0x0206 {
+                // LDA #$00 cleaned A; the pushed copy stays tainted.
+                assert_eq!(taint.registers(), (false, false, false));
+                assert!(taint.is_tainted(0x01ff));
+            }
+        }
+
+        assert_eq!(
+            taint.take_writes(),
+            [TaintedWrite {
+                pc: 0x020E,
+                address: 0xd012,
+                value: 0x41,
+            }]
+        );
+        // The final clean store overwrote the tainted byte at $D012.
+        assert_eq!(taint.tainted().collect::<Vec<_>>(), [0x0020, 0x01ff]);
+    }
+}
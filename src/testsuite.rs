@@ -0,0 +1,520 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Harnesses for the well-known 6502 conformance test programs.
+//!
+//! The test images are not distributed with this crate; each harness takes
+//! the image as bytes and reports whether the core passed it:
+//!
+//! - [`FunctionalTest`]: Klaus Dormann's `6502_functional_test`;
+//! - [`DecimalTest`]: Klaus Dormann's `6502_decimal_test`, a port of Bruce
+//!   Clark's decimal mode test;
+//! - [`Nestest`]: Kevin Horton's `nestest.nes`, in automation mode.
+//!
+//! The defaults match the images as distributed. Images assembled with a
+//! different configuration can be run by adjusting the public fields.
+//!
+//! [`DecimalConformance`] needs no image: it runs the checks of Bruce
+//! Clark's decimal mode test directly, and lists each operand and carry
+//! combination that fails rather than stopping at the first.
+
+use core::fmt;
+
+use crate::alu::{self, Mismatch};
+use crate::cpu::CPU;
+use crate::memory::{Bus, Memory};
+use crate::registers::{StackPointer, Status};
+use crate::Variant;
+
+/// Why a test program stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program jumped or branched to itself at this address.
+    Trapped(u16),
+    /// The program reached the address it was expected to finish at.
+    Finished(u16),
+    /// The opcode at this address is not valid for the variant.
+    IllegalOpcode(u16, u8),
+    /// The cycle budget ran out.
+    CycleLimit,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Trapped(pc) => write!(f, "trapped at ${pc:04X}"),
+            Outcome::Finished(pc) => write!(f, "finished at ${pc:04X}"),
+            Outcome::IllegalOpcode(pc, opcode) => {
+                write!(f, "illegal opcode ${opcode:02X} at ${pc:04X}")
+            }
+            Outcome::CycleLimit => f.write_str("cycle limit reached"),
+        }
+    }
+}
+
+/// The result of running a test program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub suite: &'static str,
+    pub passed: bool,
+    pub outcome: Outcome,
+    pub instructions: u64,
+    pub cycles: u64,
+    /// Result bytes the program left in memory, as `(address, value)`.
+    pub results: [Option<(u16, u8)>; 2],
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "suite: {}", self.suite)?;
+        writeln!(f, "result: {}", if self.passed { "pass" } else { "FAIL" })?;
+        writeln!(f, "stopped: {}", self.outcome)?;
+        writeln!(f, "instructions: {}", self.instructions)?;
+        writeln!(f, "cycles: {}", self.cycles)?;
+        for (address, value) in self.results.iter().flatten() {
+            writeln!(f, "${address:04X}: ${value:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error raised for an image that cannot be loaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// The image runs past the end of memory.
+    TooLarge,
+    /// The image is not in the expected format.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::TooLarge => f.write_str("image does not fit in memory"),
+            ImageError::Malformed(reason) => write!(f, "malformed image: {reason}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ImageError {}
+
+/// Executes `cpu` until it traps in a loop, reaches `end`, hits an opcode
+/// the variant can't decode or has run for `max_cycles`.
+///
+/// Returns the outcome and the number of instructions executed.
+pub fn run_until_trap<M: Bus, V: Variant>(
+    cpu: &mut CPU<M, V>,
+    end: Option<u16>,
+    max_cycles: u64,
+) -> (Outcome, u64) {
+    let mut instructions = 0;
+    loop {
+        let pc = cpu.registers.program_counter;
+        if end == Some(pc) {
+            return (Outcome::Finished(pc), instructions);
+        }
+        if cpu.cycles >= max_cycles {
+            return (Outcome::CycleLimit, instructions);
+        }
+        match cpu.single_step() {
+            None => {
+                return (
+                    Outcome::IllegalOpcode(pc, cpu.memory.get_byte(pc)),
+                    instructions,
+                )
+            }
+            Some(_) if cpu.registers.program_counter == pc => {
+                return (Outcome::Trapped(pc), instructions + 1)
+            }
+            Some(_) => instructions += 1,
+        }
+    }
+}
+
+fn load(memory: &mut Memory, address: u16, image: &[u8]) -> Result<(), ImageError> {
+    if usize::from(address) + image.len() > 0x10000 {
+        return Err(ImageError::TooLarge);
+    }
+    memory.set_bytes(address, image);
+    Ok(())
+}
+
+/// Klaus Dormann's functional test. The program traps in a loop at the
+/// failing test, or at [`FunctionalTest::success`] once every test passed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FunctionalTest {
+    pub load: u16,
+    pub start: u16,
+    pub success: u16,
+    pub max_cycles: u64,
+}
+
+impl Default for FunctionalTest {
+    fn default() -> Self {
+        FunctionalTest {
+            load: 0x0000,
+            start: 0x0400,
+            success: 0x3469,
+            max_cycles: 200_000_000,
+        }
+    }
+}
+
+impl FunctionalTest {
+    /// Runs the test image on the given variant.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ImageError::TooLarge`] if the image does not fit at
+    /// [`FunctionalTest::load`].
+    pub fn run<V: Variant>(&self, image: &[u8], variant: V) -> Result<Report, ImageError> {
+        let mut cpu = CPU::new(Memory::new(), variant);
+        load(&mut cpu.memory, self.load, image)?;
+        cpu.registers.program_counter = self.start;
+
+        let (outcome, instructions) = run_until_trap(&mut cpu, None, self.max_cycles);
+        Ok(Report {
+            suite: "klaus",
+            passed: outcome == Outcome::Trapped(self.success),
+            outcome,
+            instructions,
+            cycles: cpu.cycles,
+            results: [None, None],
+        })
+    }
+}
+
+/// Klaus Dormann's decimal mode test. The program stores 0 in
+/// [`DecimalTest::error`] if every combination passed and 1 otherwise, and
+/// then stops.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecimalTest {
+    pub load: u16,
+    pub start: u16,
+    pub error: u16,
+    pub max_cycles: u64,
+}
+
+impl Default for DecimalTest {
+    fn default() -> Self {
+        DecimalTest {
+            load: 0x0200,
+            start: 0x0200,
+            error: 0x000b,
+            max_cycles: 200_000_000,
+        }
+    }
+}
+
+impl DecimalTest {
+    /// Runs the test image on the given variant.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ImageError::TooLarge`] if the image does not fit at
+    /// [`DecimalTest::load`].
+    pub fn run<V: Variant>(&self, image: &[u8], variant: V) -> Result<Report, ImageError> {
+        let mut cpu = CPU::new(Memory::new(), variant);
+        load(&mut cpu.memory, self.load, image)?;
+        cpu.registers.program_counter = self.start;
+
+        let (outcome, instructions) = run_until_trap(&mut cpu, None, self.max_cycles);
+        let error = cpu.memory.get_byte(self.error);
+        Ok(Report {
+            suite: "decimal",
+            // The test ends with a `STP` or a jump to itself, either of which
+            // means it ran to completion.
+            passed: outcome != Outcome::CycleLimit && error == 0,
+            outcome,
+            instructions,
+            cycles: cpu.cycles,
+            results: [Some((self.error, error)), None],
+        })
+    }
+}
+
+/// Which chip's decimal mode [`DecimalConformance`] expects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecimalFlavor {
+    /// The NMOS 6502, whose N, V and Z flags come from intermediate results.
+    Nmos,
+    /// The 65C02, whose N and Z flags follow the result.
+    Cmos,
+}
+
+/// Bruce Clark's decimal mode test, run on the CPU. Every `ADC #` and
+/// `SBC #` of two operands, with the carry clear and set, is executed in
+/// decimal mode and compared against [`alu::reference_adc`] and
+/// [`alu::reference_sbc`], or their 65C02 counterparts.
+///
+/// Like the test program, the accumulator is always checked and the flags
+/// in [`DecimalConformance::flags`] are; the default checks only the
+/// carry, the one flag documented in decimal mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecimalConformance {
+    pub flavor: DecimalFlavor,
+    pub flags: Status,
+    /// Restrict the operands to valid BCD, `$00` to `$99`.
+    pub valid_bcd_only: bool,
+}
+
+impl Default for DecimalConformance {
+    fn default() -> Self {
+        DecimalConformance {
+            flavor: DecimalFlavor::Nmos,
+            flags: Status::PS_CARRY,
+            valid_bcd_only: false,
+        }
+    }
+}
+
+impl DecimalConformance {
+    /// Runs every combination on the given variant and returns those that
+    /// fail, in the order the test program tries them.
+    pub fn run<V: Variant>(&self, variant: V) -> impl Iterator<Item = Mismatch> {
+        const fn is_bcd(value: u8) -> bool {
+            value & 0x0f < 0x0a && value < 0xa0
+        }
+
+        type Operation = fn(u8, u8, bool, bool) -> (u8, Status);
+
+        let (adc, sbc): (Operation, Operation) = match self.flavor {
+            DecimalFlavor::Nmos => (alu::reference_adc, alu::reference_sbc),
+            DecimalFlavor::Cmos => (alu::reference_adc_cmos, alu::reference_sbc_cmos),
+        };
+        let checked = self.flags & alu::NVZC;
+        let valid_bcd_only = self.valid_bcd_only;
+        let mut cpu = CPU::new(Memory::new(), variant);
+
+        (0..0x4_0000_u32).filter_map(move |index| {
+            let [b, a, mode, _] = index.to_le_bytes();
+            let carry = mode & 1 != 0;
+            let subtract = mode & 2 != 0;
+            if valid_bcd_only && !(is_bcd(a) && is_bcd(b)) {
+                return None;
+            }
+
+            // ADC #b or SBC #b
+            cpu.memory
+                .set_bytes(0x0200, &[if subtract { 0xe9 } else { 0x69 }, b]);
+            cpu.registers.program_counter = 0x0200;
+            cpu.registers.accumulator = a;
+            cpu.registers.status = Status::PS_DECIMAL_MODE | Status::PS_UNUSED;
+            cpu.registers.status.set(Status::PS_CARRY, carry);
+            cpu.single_step();
+
+            let actual = (cpu.registers.accumulator, cpu.registers.status & alu::NVZC);
+            let expected = if subtract {
+                sbc(a, b, carry, true)
+            } else {
+                adc(a, b, carry, true)
+            };
+            (actual.0 != expected.0 || actual.1 & checked != expected.1 & checked).then_some(
+                Mismatch {
+                    subtract,
+                    a,
+                    b,
+                    carry,
+                    decimal: true,
+                    actual,
+                    expected,
+                },
+            )
+        })
+    }
+}
+
+/// Kevin Horton's `nestest`, run from `$C000` without a PPU. The program
+/// stores a nonzero error code at `$02` when an official instruction fails
+/// and at `$03` when an undocumented one does.
+///
+/// Undocumented opcodes that the variant doesn't decode stop the run; the
+/// test then passes if every official instruction did.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Nestest {
+    pub start: u16,
+    pub end: u16,
+    pub max_cycles: u64,
+}
+
+impl Default for Nestest {
+    fn default() -> Self {
+        Nestest {
+            start: 0xc000,
+            end: 0xc66e,
+            max_cycles: 1_000_000,
+        }
+    }
+}
+
+const INES_MAGIC: &[u8] = b"NES\x1a";
+const INES_HEADER_LEN: usize = 16;
+const PRG_BANK_LEN: usize = 0x4000;
+
+impl Nestest {
+    /// Runs `nestest.nes` on the given variant, normally
+    /// [`Ricoh2a03`](crate::instruction::Ricoh2a03). A bare 16K PRG ROM is
+    /// accepted too.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ImageError::Malformed`] if the image holds no 16K PRG bank.
+    pub fn run<V: Variant>(&self, image: &[u8], variant: V) -> Result<Report, ImageError> {
+        let prg = if image.starts_with(INES_MAGIC) {
+            image.get(INES_HEADER_LEN..INES_HEADER_LEN + PRG_BANK_LEN)
+        } else {
+            image.get(..PRG_BANK_LEN)
+        }
+        .ok_or(ImageError::Malformed("expected a 16K PRG bank"))?;
+
+        let mut cpu = CPU::new(Memory::new(), variant);
+        // NROM-128 mirrors its single bank at $8000 and $C000.
+        load(&mut cpu.memory, 0x8000, prg)?;
+        load(&mut cpu.memory, 0xc000, prg)?;
+        cpu.registers.program_counter = self.start;
+        cpu.registers.stack_pointer = StackPointer(0xfd);
+
+        let (outcome, instructions) = run_until_trap(&mut cpu, Some(self.end), self.max_cycles);
+        let official = cpu.memory.get_byte(0x02);
+        let unofficial = cpu.memory.get_byte(0x03);
+        let completed = match outcome {
+            Outcome::Finished(_) => unofficial == 0,
+            Outcome::IllegalOpcode(..) => true,
+            Outcome::Trapped(_) | Outcome::CycleLimit => false,
+        };
+        Ok(Report {
+            suite: "nestest",
+            passed: completed && official == 0,
+            outcome,
+            instructions,
+            cycles: cpu.cycles,
+            results: [Some((0x02, official)), Some((0x03, unofficial))],
+        })
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::instruction::{Nmos6502, Ricoh2a03};
+    use alloc::string::ToString;
+
+    fn image(source: &str) -> alloc::vec::Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    #[test]
+    fn functional_test_passes_at_success_trap() {
+        let program = image(".org $0400\nLDX #3\nloop: DEX\nBNE loop\ndone: JMP done");
+        let test = FunctionalTest {
+            load: 0x0400,
+            success: 0x0405,
+            ..FunctionalTest::default()
+        };
+        let report = test.run(&program, Nmos6502).unwrap();
+        assert!(report.passed);
+        assert_eq!(report.outcome, Outcome::Trapped(0x0405));
+        assert_eq!(report.instructions, 8);
+
+        let failing = FunctionalTest {
+            success: 0x3469,
+            ..test
+        };
+        assert!(!failing.run(&program, Nmos6502).unwrap().passed);
+    }
+
+    #[test]
+    fn decimal_conformance_lists_failures() {
+        let bcd = DecimalConformance {
+            valid_bcd_only: true,
+            ..DecimalConformance::default()
+        };
+        // The 2A03 has no decimal mode, so it fails wherever a digit carries.
+        let mut failures = bcd.run(Ricoh2a03);
+        assert_eq!(
+            failures.next().unwrap().to_string(),
+            "ADC $01, $09 with C=0 D=1: got $0A Status(0x0), expected $10 Status(0x0)"
+        );
+        let failure = failures.find(|failure| failure.subtract).unwrap();
+        assert_eq!((failure.a, failure.b, failure.carry), (0x00, 0x00, false));
+        assert_eq!(failure.expected.0, 0x99);
+    }
+
+    #[cfg(feature = "decimal_mode")]
+    #[test]
+    fn decimal_mode_passes_with_every_flag() {
+        let nmos = DecimalConformance {
+            flags: alu::NVZC,
+            ..DecimalConformance::default()
+        };
+        assert_eq!(nmos.run(Nmos6502).next(), None);
+        let cmos = DecimalConformance {
+            flavor: DecimalFlavor::Cmos,
+            ..nmos
+        };
+        assert_eq!(cmos.run(crate::instruction::Cmos6502).next(), None);
+    }
+
+    #[test]
+    fn decimal_test_reads_error_byte() {
+        let test = DecimalTest::default();
+        let pass = image(".org $0200\nLDA #0\nSTA $0B\nJMP *");
+        assert!(test.run(&pass, Nmos6502).unwrap().passed);
+        let fail = image(".org $0200\nLDA #1\nSTA $0B\nJMP *");
+        let report = test.run(&fail, Nmos6502).unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.results[0], Some((0x000b, 1)));
+    }
+
+    #[test]
+    fn nestest_stops_at_end_address() {
+        let mut prg = image(".org $C000\nLDA #0\nSTA $02\nSTA $03\nJMP $C66E");
+        prg.resize(PRG_BANK_LEN, 0);
+        let mut nes = INES_MAGIC.to_vec();
+        nes.resize(INES_HEADER_LEN, 0);
+        nes.extend(&prg);
+
+        let report = Nestest::default().run(&nes, Ricoh2a03).unwrap();
+        assert_eq!(report.outcome, Outcome::Finished(0xc66e));
+        assert!(report.passed);
+        assert_eq!(
+            Nestest::default().run(&nes[..100], Ricoh2a03),
+            Err(ImageError::Malformed("expected a 16K PRG bank"))
+        );
+    }
+
+    #[test]
+    fn image_must_fit() {
+        let test = FunctionalTest {
+            load: 0xffff,
+            ..FunctionalTest::default()
+        };
+        assert_eq!(test.run(&[0xea, 0xea], Nmos6502), Err(ImageError::TooLarge));
+    }
+}
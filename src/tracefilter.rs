@@ -0,0 +1,166 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Choosing which instructions a trace records.
+//!
+//! Tracing a whole ROM buries the interesting part under the BIOS and
+//! interrupt handlers. A [`TraceFilter`] narrows a trace down to address
+//! ranges, minus excluded ranges, and optionally to code running from
+//! specific PRG ROM banks. The address rules are folded into a 64K bitmap
+//! when the filter is built, so checking an instruction costs one lookup.
+
+use core::ops::RangeInclusive;
+
+use crate::mapper::Mapper;
+
+/// Which instruction addresses, and which banks, to trace.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::tracefilter::TraceFilter;
+///
+/// let mut filter = TraceFilter::new();
+/// filter.include(0xC000..=0xCFFF);
+/// filter.exclude(0xC800..=0xC8FF);
+/// assert!(filter.matches(0xC000));
+/// assert!(!filter.matches(0xC800));
+/// assert!(!filter.matches(0xE000));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    addresses: [u64; 1024],
+    /// Whether any range has been included yet; until then everything is.
+    restricted: bool,
+    /// Bit `n` set if bank `n` is traced; `None` traces every bank.
+    banks: Option<u64>,
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        TraceFilter::new()
+    }
+}
+
+impl TraceFilter {
+    /// A filter that traces everything.
+    #[must_use]
+    pub const fn new() -> TraceFilter {
+        TraceFilter {
+            addresses: [u64::MAX; 1024],
+            restricted: false,
+            banks: None,
+        }
+    }
+
+    /// Traces instructions in `range`. The first call narrows the trace
+    /// from everything down to `range`; later calls widen it again.
+    pub fn include(&mut self, range: RangeInclusive<u16>) {
+        if !self.restricted {
+            self.addresses = [0; 1024];
+            self.restricted = true;
+        }
+        self.set(range, true);
+    }
+
+    /// Stops tracing instructions in `range`, which takes precedence over
+    /// earlier calls to [`TraceFilter::include`].
+    pub fn exclude(&mut self, range: RangeInclusive<u16>) {
+        self.set(range, false);
+    }
+
+    /// Only traces code in PRG ROM bank `bank` among the banked addresses.
+    /// Addresses not backed by PRG ROM, such as RAM, are unaffected. Banks
+    /// 64 and above can't be selected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bank` is 64 or more.
+    pub fn include_bank(&mut self, bank: usize) {
+        assert!(bank < 64, "only banks 0 to 63 can be filtered");
+        self.banks = Some(self.banks.unwrap_or(0) | 1 << bank);
+    }
+
+    /// Whether an instruction at `address` is traced, ignoring banks.
+    #[must_use]
+    pub const fn matches(&self, address: u16) -> bool {
+        self.addresses[address as usize / 64] & (1 << (address % 64)) != 0
+    }
+
+    /// Whether an instruction at `address`, fetched from PRG ROM bank
+    /// `bank` if it is banked, is traced.
+    #[must_use]
+    pub const fn matches_bank(&self, address: u16, bank: Option<usize>) -> bool {
+        if !self.matches(address) {
+            return false;
+        }
+        match (self.banks, bank) {
+            (Some(banks), Some(bank)) => bank < 64 && banks & (1 << bank) != 0,
+            _ => true,
+        }
+    }
+
+    /// Whether an instruction at `address` is traced, looking its bank up
+    /// in `mapper`.
+    #[must_use]
+    pub fn matches_mapper<M: Mapper>(&self, address: u16, mapper: &M) -> bool {
+        self.matches(address)
+            && (self.banks.is_none() || self.matches_bank(address, mapper.prg_bank(address)))
+    }
+
+    fn set(&mut self, range: RangeInclusive<u16>, traced: bool) {
+        for address in range {
+            let bit = 1 << (address % 64);
+            let word = &mut self.addresses[usize::from(address) / 64];
+            if traced {
+                *word |= bit;
+            } else {
+                *word &= !bit;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::{Uxrom, PRG_BANK_SIZE};
+
+    #[test]
+    fn banks_only_filter_banked_addresses() {
+        static PRG: [u8; 4 * PRG_BANK_SIZE] = [0; 4 * PRG_BANK_SIZE];
+        let mut mapper = Uxrom::new(&PRG).unwrap();
+        let mut filter = TraceFilter::new();
+        filter.include_bank(2);
+
+        assert!(filter.matches_mapper(0x0200, &mapper));
+        assert!(!filter.matches_mapper(0x8000, &mapper));
+        mapper.cpu_write(0x8000, 2);
+        assert!(filter.matches_mapper(0x8000, &mapper));
+        assert!(!filter.matches_bank(0x8000, Some(70)));
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Text traces of execution, one line per instruction, for diffing against
+//! the logs of other emulators.
+//!
+//! [`TraceFormat::Nintendulator`] writes the columns of Nintendulator's
+//! logs, the format of the reference `nestest.log`: the PC, the raw bytes,
+//! the disassembly with the memory it touches, the registers and the cycle
+//! count. Undocumented opcodes are marked with a `*`. There is no PPU, so
+//! the `PPU:` column is left out; strip it from the reference log before
+//! diffing:
+//!
+//! ```text
+//! C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+//! C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD CYC:10
+//! C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:12
+//! ```
+//!
+//! A [`TraceLogger`] writes the lines to any [`io::Write`] sink as it steps
+//! the CPU. Nothing is formatted, and the CPU runs at full speed, unless
+//! it is used:
+//!
+//! ```
+//! use mos6502::cpu::CPU;
+//! use mos6502::instruction::Nmos6502;
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::tracelog::{TraceFormat, TraceLogger};
+//!
+//! let mut cpu = CPU::new(Memory::new(), Nmos6502);
+//! cpu.memory.set_bytes(0x0200, &[0xa2, 0x00, 0x86, 0x10, 0x02]);
+//! cpu.registers.program_counter = 0x0200;
+//!
+//! let mut trace = TraceLogger::new(Vec::new(), TraceFormat::Nintendulator);
+//! while trace.step(&mut cpu)?.is_some() {}
+//! let text = String::from_utf8(trace.into_inner()).unwrap();
+//! assert!(text.starts_with("0200  A2 00     LDX #$00 "));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt;
+use std::format;
+use std::io::{self, Write};
+use std::string::ToString;
+use std::vec::Vec;
+
+use crate::cpu::CPU;
+use crate::disasm::Disassembler;
+use crate::instruction::{AddressingMode, DecodedInstr, Instruction};
+use crate::memory::Bus;
+use crate::Variant;
+
+/// The layout of trace lines.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Nintendulator's columns, with the memory each instruction reads or
+    /// writes, as in `nestest.log`.
+    #[default]
+    Nintendulator,
+    /// The same columns without the memory contents, for buses where
+    /// reading has side effects.
+    Compact,
+}
+
+/// The trace line of the instruction a CPU is about to execute.
+///
+/// Memory is read with [`Bus::get_byte`], without clocking the bus.
+#[derive(Debug)]
+pub struct TraceLine<'a, M: Bus, V: Variant> {
+    cpu: &'a CPU<M, V>,
+    format: TraceFormat,
+}
+
+impl<'a, M: Bus, V: Variant> TraceLine<'a, M, V> {
+    #[must_use]
+    pub const fn new(cpu: &'a CPU<M, V>, format: TraceFormat) -> TraceLine<'a, M, V> {
+        TraceLine { cpu, format }
+    }
+
+    /// The memory an instruction touches, in Nintendulator's notation:
+    /// `@` introduces an address computed by indexing and `=` what is
+    /// stored at an address.
+    fn annotate(
+        &self,
+        f: &mut impl fmt::Write,
+        instruction: Instruction,
+        mode: AddressingMode,
+        operand: u16,
+    ) -> fmt::Result {
+        let memory = &self.cpu.memory;
+        let registers = &self.cpu.registers;
+        let word = |address: u16| {
+            u16::from_le_bytes([
+                memory.get_byte(address),
+                memory.get_byte(address.wrapping_add(1)),
+            ])
+        };
+        // Pointers in the zero page wrap around within it.
+        let zp_word = |address: u8| {
+            u16::from_le_bytes([
+                memory.get_byte(address.into()),
+                memory.get_byte(address.wrapping_add(1).into()),
+            ])
+        };
+        let [lo, hi] = operand.to_le_bytes();
+        match mode {
+            AddressingMode::ZeroPage | AddressingMode::Absolute
+                if !matches!(instruction, Instruction::JMP | Instruction::JSR) =>
+            {
+                write!(f, " = {:02X}", memory.get_byte(operand))
+            }
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+                let index = if mode == AddressingMode::ZeroPageX {
+                    registers.index_x
+                } else {
+                    registers.index_y
+                };
+                let address = lo.wrapping_add(index);
+                write!(
+                    f,
+                    " @ {address:02X} = {:02X}",
+                    memory.get_byte(address.into())
+                )
+            }
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+                let index = if mode == AddressingMode::AbsoluteX {
+                    registers.index_x
+                } else {
+                    registers.index_y
+                };
+                let address = operand.wrapping_add(index.into());
+                write!(f, " @ {address:04X} = {:02X}", memory.get_byte(address))
+            }
+            AddressingMode::IndexedIndirectX => {
+                let pointer = lo.wrapping_add(registers.index_x);
+                let address = zp_word(pointer);
+                write!(
+                    f,
+                    " @ {pointer:02X} = {address:04X} = {:02X}",
+                    memory.get_byte(address)
+                )
+            }
+            AddressingMode::IndirectIndexedY => {
+                let base = zp_word(lo);
+                let address = base.wrapping_add(registers.index_y.into());
+                write!(
+                    f,
+                    " = {base:04X} @ {address:04X} = {:02X}",
+                    memory.get_byte(address)
+                )
+            }
+            AddressingMode::ZeroPageIndirect => {
+                let address = zp_word(lo);
+                write!(f, " = {address:04X} = {:02X}", memory.get_byte(address))
+            }
+            AddressingMode::Indirect => write!(f, " = {:04X}", word(operand)),
+            // The NMOS JMP doesn't carry into the high byte of the pointer.
+            AddressingMode::BuggyIndirect => write!(
+                f,
+                " = {:04X}",
+                u16::from_le_bytes([
+                    memory.get_byte(operand),
+                    memory.get_byte(u16::from_le_bytes([lo.wrapping_add(1), hi])),
+                ])
+            ),
+            AddressingMode::AbsoluteIndexedIndirect => {
+                let pointer = operand.wrapping_add(registers.index_x.into());
+                write!(f, " @ {pointer:04X} = {:04X}", word(pointer))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<M: Bus, V: Variant> fmt::Display for TraceLine<'_, M, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cpu = self.cpu;
+        let pc = cpu.registers.program_counter;
+        // Enough bytes for the longest instruction; the disassembler only
+        // consumes what the opcode needs.
+        let bytes = [0, 1, 2].map(|i| cpu.memory.get_byte(pc.wrapping_add(i)));
+        let line = Disassembler::<V>::for_variant(&bytes, pc)
+            .next()
+            .expect("three bytes always hold an instruction");
+
+        let hex = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut text = line.to_string();
+        let mut mark = ' ';
+        if let (TraceFormat::Nintendulator, Some((instruction, mode))) = (self.format, line.decoded)
+        {
+            if V::is_undocumented(bytes[0]) {
+                mark = '*';
+            }
+            // Nintendulator's name for ISC.
+            if matches!(instruction, Instruction::ISC | Instruction::ISCnd) {
+                text.replace_range(..3, "ISB");
+            }
+            let operand = line.operand().unwrap_or_default();
+            self.annotate(&mut text, instruction, mode, operand)?;
+        }
+
+        let registers = &cpu.registers;
+        write!(
+            f,
+            "{pc:04X}  {hex:<8} {mark}{text:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            registers.accumulator,
+            registers.index_x,
+            registers.index_y,
+            registers.status.to_byte(),
+            registers.stack_pointer.0,
+            cpu.cycles,
+        )
+    }
+}
+
+/// Writes a trace line for every instruction it steps a CPU through.
+///
+/// The writer is not buffered; wrap files in a [`std::io::BufWriter`].
+#[derive(Debug)]
+pub struct TraceLogger<W: Write> {
+    out: W,
+    format: TraceFormat,
+}
+
+impl<W: Write> TraceLogger<W> {
+    #[must_use]
+    pub const fn new(out: W, format: TraceFormat) -> TraceLogger<W> {
+        TraceLogger { out, format }
+    }
+
+    /// Writes the line of the instruction `cpu` is about to execute.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn write<M: Bus, V: Variant>(&mut self, cpu: &CPU<M, V>) -> io::Result<()> {
+        writeln!(self.out, "{}", TraceLine::new(cpu, self.format))
+    }
+
+    /// Traces the next instruction of `cpu` and then executes it. Like
+    /// [`CPU::single_step`], returns `None` if the opcode cannot be decoded,
+    /// after tracing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn step<M: Bus, V: Variant>(
+        &mut self,
+        cpu: &mut CPU<M, V>,
+    ) -> io::Result<Option<DecodedInstr>> {
+        self.write(cpu)?;
+        Ok(cpu.single_step())
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+    use crate::registers::{StackPointer, Status};
+    use std::string::String;
+
+    fn nestest_cpu() -> CPU<Memory, Nmos6502> {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.registers.status = Status::from_byte(0x24);
+        cpu.registers.stack_pointer = StackPointer(0xfd);
+        cpu.cycles = 7;
+        cpu
+    }
+
+    /// The disassembly column of the instruction at `pc`, with its marker.
+    fn disassembly(cpu: &CPU<Memory, Nmos6502>, pc: u16) -> String {
+        let mut cpu = cpu.clone();
+        cpu.registers.program_counter = pc;
+        let line = TraceLine::new(&cpu, TraceFormat::Nintendulator).to_string();
+        line[15..48].trim_end().to_string()
+    }
+
+    #[test]
+    fn matches_nestest_log() {
+        let mut cpu = nestest_cpu();
+        cpu.memory.set_bytes(0xc000, &[0x4c, 0xf5, 0xc5]);
+        cpu.memory.set_bytes(0xc5f5, &[0xa2, 0x00, 0x86, 0x00]);
+        cpu.registers.program_counter = 0xc000;
+
+        let mut trace = TraceLogger::new(Vec::new(), TraceFormat::Nintendulator);
+        for _ in 0..3 {
+            trace.step(&mut cpu).unwrap();
+        }
+        let text = String::from_utf8(trace.into_inner()).unwrap();
+        assert_eq!(
+            text,
+            "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:12
+"
+        );
+    }
+
+    #[test]
+    fn annotates_memory_operands() {
+        let mut cpu = nestest_cpu();
+        cpu.registers.index_x = 0x02;
+        cpu.registers.index_y = 0x01;
+        cpu.memory.set_bytes(0x0080, &[0x00, 0x00, 0x00, 0x02]);
+        cpu.memory.set_bytes(0x0200, &[0x5a, 0x89]);
+        cpu.memory.set_bytes(0x02ff, &[0x00, 0x03]);
+        cpu.memory.set_byte(0x0300, 0xa9);
+        cpu.memory.set_bytes(
+            0x0400,
+            &[
+                0xa1, 0x80, // LDA ($80,X)
+                0xb1, 0x82, // LDA ($82),Y
+                0xb5, 0xff, // LDA $FF,X
+                0xbd, 0xff, 0x01, // LDA $01FF,X
+                0x6c, 0xff, 0x02, // JMP ($02FF)
+                0x20, 0x00, 0x02, // JSR $0200
+                0x04, 0xa9, // NOP $A9
+                0xfb, 0xff, 0x01, // ISC $01FF,Y
+                0x0a, // ASL A
+            ],
+        );
+
+        let columns = [
+            (0x0400, " LDA ($80,X) @ 82 = 0200 = 5A"),
+            (0x0402, " LDA ($82),Y = 0200 @ 0201 = 89"),
+            (0x0404, " LDA $FF,X @ 01 = 00"),
+            (0x0406, " LDA $01FF,X @ 0201 = 89"),
+            // The pointer's high byte comes from $0200, not $0300.
+            (0x0409, " JMP ($02FF) = 5A00"),
+            (0x040c, " JSR $0200"),
+            (0x040f, "*NOP $A9 = 00"),
+            (0x0411, "*ISB $01FF,Y @ 0200 = 5A"),
+            (0x0414, " ASL A"),
+        ];
+        for (pc, column) in columns {
+            assert_eq!(disassembly(&cpu, pc), column);
+        }
+    }
+
+    #[test]
+    fn compact_format_leaves_memory_alone() {
+        let mut cpu = nestest_cpu();
+        cpu.memory.set_bytes(0x0400, &[0x04, 0xa9]);
+        cpu.registers.program_counter = 0x0400;
+        assert_eq!(
+            TraceLine::new(&cpu, TraceFormat::Compact).to_string(),
+            "0400  04 A9     NOP $A9                         A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Instrumenting emulated code without stopping it.
+//!
+//! A [`Tracepoint`] is like a breakpoint that doesn't break: whenever the
+//! instruction at its address is about to execute, it counts the hit and
+//! performs its [`TraceAction`], and execution carries on. Tracepoints are
+//! set with [`Machine::add_tracepoint`], and the messages they produce are
+//! collected with [`Machine::take_trace_output`].
+//!
+//! [`Machine::add_tracepoint`]: crate::machine::Machine::add_tracepoint
+//! [`Machine::take_trace_output`]: crate::machine::Machine::take_trace_output
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::cpu::CPU;
+use crate::memory::Bus;
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::Variant;
+
+/// What a tracepoint does when it is hit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceAction {
+    /// Outputs a message, with placeholders replaced by the current state:
+    /// `{pc}`, `{a}`, `{x}`, `{y}`, `{sp}` and `{p}` by the registers in
+    /// hex, `{cycles}` by the cycle counter and `{$XXXX}` by the byte at
+    /// address `XXXX` in hex. Anything else is output as-is.
+    Log(String),
+    /// Outputs the registers.
+    Registers,
+    /// Only counts the hit.
+    Count,
+    /// Runs a script and outputs its value, if it has one, or the error it
+    /// raised.
+    #[cfg(feature = "scripting")]
+    Script(Script),
+}
+
+/// Identifies a tracepoint set on a [`Machine`](crate::machine::Machine).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TracepointId(pub(crate) u32);
+
+/// An address that triggers an action every time it is executed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tracepoint {
+    pub address: u16,
+    pub action: TraceAction,
+    /// How many times the tracepoint was hit.
+    pub hits: u64,
+}
+
+impl Tracepoint {
+    #[must_use]
+    pub const fn new(address: u16, action: TraceAction) -> Tracepoint {
+        Tracepoint {
+            address,
+            action,
+            hits: 0,
+        }
+    }
+
+    /// Counts a hit on `cpu`, which is at the tracepoint, and returns the
+    /// message to output, if any.
+    pub fn hit<M: Bus, V: Variant>(&mut self, cpu: &CPU<M, V>) -> Option<String> {
+        self.hits += 1;
+        match &self.action {
+            TraceAction::Log(template) => Some(expand(template, cpu)),
+            TraceAction::Registers => {
+                let r = &cpu.registers;
+                Some(format!(
+                    "{:04X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                    r.program_counter,
+                    r.accumulator,
+                    r.index_x,
+                    r.index_y,
+                    r.status.to_byte(),
+                    r.stack_pointer.0,
+                    cpu.cycles
+                ))
+            }
+            TraceAction::Count => None,
+            #[cfg(feature = "scripting")]
+            TraceAction::Script(script) => match script.eval(cpu) {
+                Ok(value) => Some(value).filter(|value| !value.is_empty()),
+                Err(error) => Some(format!("tracepoint script failed: {error}")),
+            },
+        }
+    }
+}
+
+/// Replaces the placeholders in `template` with the state of `cpu`.
+fn expand<M: Bus, V: Variant>(template: &str, cpu: &CPU<M, V>) -> String {
+    let r = &cpu.registers;
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some(close) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[1..close];
+        // Writing to a String can't fail.
+        let _ = match name {
+            "pc" => write!(out, "{:04X}", r.program_counter),
+            "a" => write!(out, "{:02X}", r.accumulator),
+            "x" => write!(out, "{:02X}", r.index_x),
+            "y" => write!(out, "{:02X}", r.index_y),
+            "sp" => write!(out, "{:02X}", r.stack_pointer.0),
+            "p" => write!(out, "{:02X}", r.status.to_byte()),
+            "cycles" => write!(out, "{}", cpu.cycles),
+            _ => match name
+                .strip_prefix('$')
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            {
+                Some(address) => write!(out, "{:02X}", cpu.memory.get_byte(address)),
+                None => out.write_str(&rest[..=close]),
+            },
+        };
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn expands_placeholders() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.registers.accumulator = 0x42;
+        cpu.registers.program_counter = 0x0200;
+        cpu.memory.set_byte(0x1234, 0xab);
+        let mut tracepoint = Tracepoint::new(
+            0x0200,
+            TraceAction::Log("at {pc}: a={a} mem={$1234} {unknown} {".into()),
+        );
+        assert_eq!(
+            tracepoint.hit(&cpu).as_deref(),
+            Some("at 0200: a=42 mem=AB {unknown} {")
+        );
+        assert_eq!(tracepoint.hits, 1);
+    }
+}
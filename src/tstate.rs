@@ -0,0 +1,690 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! The clock cycles of each instruction.
+//!
+//! The 6502 uses the bus on every cycle. Besides the accesses an instruction
+//! needs, it makes dummy ones: it reads the byte after a one-byte opcode,
+//! reads from the wrong page before fixing up an indexed address, and
+//! writes the unmodified value back during a read-modify-write. Hardware
+//! registers with side effects on read or write see all of these.
+//!
+//! [`sequence`] gives the T-states of an instruction in order, with where
+//! each one takes its address from. The CPU follows it to make the dummy
+//! accesses when [`CPU::cycle_accurate`](crate::cpu::CPU::cycle_accurate)
+//! is set, to report the bus activity of every cycle, to find the write
+//! cycles a stall cannot interrupt and to know when interrupts are polled.
+//! The table describes the NMOS 6502.
+
+use crate::instruction::{AddressingMode, Instruction};
+use crate::memory::Access;
+
+/// The most T-states in an instruction or the interrupt sequence.
+const MAX_STEPS: usize = 8;
+
+/// Where a T-state takes its address from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// The address of the instruction.
+    Opcode,
+    /// The byte after the opcode.
+    Operand,
+    /// The second byte after the opcode.
+    OperandHigh,
+    /// The operand as a zero-page address, before indexing.
+    ZeroPage,
+    /// The low byte of an indirect address.
+    Pointer,
+    /// The high byte of an indirect address.
+    PointerHigh,
+    /// The indexed address before the carry into its high byte: the base
+    /// address's page with the indexed low byte. For a branch, the target's
+    /// low byte on the page of the next instruction.
+    Unfixed,
+    /// The address the instruction operates on.
+    Effective,
+    /// `$0100` plus the stack pointer. Dummy reads use the stack pointer as
+    /// it was at the start of the instruction.
+    Stack,
+    /// The low byte of an interrupt vector.
+    Vector,
+    /// The high byte of an interrupt vector.
+    VectorHigh,
+    /// The address of the next instruction.
+    Next,
+    /// The address `RTS` pulled from the stack, before it is incremented.
+    Return,
+}
+
+/// When a T-state takes place.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    /// Only if indexing carried into the high byte of the address.
+    PageCrossed,
+    /// Only if the branch is taken.
+    BranchTaken,
+    /// Only if the branch is taken to another page.
+    BranchCrossed,
+}
+
+/// One clock cycle of an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TState {
+    pub access: Access,
+    pub address: Address,
+    /// Whether the value is thrown away (for a read) or rewritten on the
+    /// next cycle (for a write).
+    pub dummy: bool,
+    pub condition: Condition,
+}
+
+impl TState {
+    const fn new(access: Access, address: Address, dummy: bool) -> TState {
+        TState {
+            access,
+            address,
+            dummy,
+            condition: Condition::Always,
+        }
+    }
+
+    const fn read(address: Address) -> TState {
+        TState::new(Access::Read, address, false)
+    }
+
+    const fn write(address: Address) -> TState {
+        TState::new(Access::Write, address, false)
+    }
+
+    const fn dummy_read(address: Address) -> TState {
+        TState::new(Access::Read, address, true)
+    }
+
+    const fn dummy_write(address: Address) -> TState {
+        TState::new(Access::Write, address, true)
+    }
+
+    const fn when(self, condition: Condition) -> TState {
+        TState { condition, ..self }
+    }
+}
+
+/// The T-states of an instruction, T0 first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    steps: [TState; MAX_STEPS],
+    len: u8,
+    late_status: bool,
+}
+
+impl Sequence {
+    const EMPTY: Sequence = Sequence {
+        steps: [TState::read(Address::Opcode); MAX_STEPS],
+        len: 0,
+        late_status: false,
+    };
+
+    // The length never exceeds `MAX_STEPS`.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn new(steps: &[TState]) -> Sequence {
+        let mut sequence = Sequence::EMPTY;
+        let mut i = 0;
+        while i < steps.len() {
+            sequence.steps[i] = steps[i];
+            i += 1;
+        }
+        sequence.len = steps.len() as u8;
+        sequence
+    }
+
+    const fn then(mut self, step: TState) -> Sequence {
+        self.steps[self.len as usize] = step;
+        self.len += 1;
+        self
+    }
+
+    const fn with_late_status(self) -> Sequence {
+        Sequence {
+            late_status: true,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn steps(&self) -> &[TState] {
+        &self.steps[..usize::from(self.len)]
+    }
+
+    /// The T-state at `index`.
+    #[must_use]
+    pub const fn step(&self, index: usize) -> Option<TState> {
+        if index < self.len as usize {
+            Some(self.steps[index])
+        } else {
+            None
+        }
+    }
+
+    /// Number of cycles, not counting conditional ones.
+    #[must_use]
+    pub const fn base_cycles(&self) -> u8 {
+        let mut count = 0;
+        let mut i = 0;
+        while i < self.len as usize {
+            if matches!(self.steps[i].condition, Condition::Always) {
+                count += 1;
+            }
+            i += 1;
+        }
+        count
+    }
+
+    /// Number of write cycles at the end, during which the CPU cannot be
+    /// halted by a device stealing cycles.
+    #[must_use]
+    pub const fn trailing_writes(&self) -> u8 {
+        let mut count = 0;
+        let mut i = self.len as usize;
+        while i > 0 && matches!(self.steps[i - 1].access, Access::Write) {
+            count += 1;
+            i -= 1;
+        }
+        count
+    }
+
+    /// Whether the status register changes on the last cycle, after the
+    /// interrupt lines have been polled, so that a change to the I flag
+    /// only takes effect after the next instruction.
+    #[must_use]
+    pub const fn late_status(&self) -> bool {
+        self.late_status
+    }
+}
+
+/// The hardware interrupt sequence: the opcode fetch is thrown away, the
+/// program counter and status are pushed and the handler's address is read
+/// from the vector.
+pub const INTERRUPT: Sequence = Sequence::new(&[
+    TState::dummy_read(Address::Opcode),
+    TState::dummy_read(Address::Opcode),
+    TState::write(Address::Stack),
+    TState::write(Address::Stack),
+    TState::write(Address::Stack),
+    TState::read(Address::Vector),
+    TState::read(Address::VectorHigh),
+]);
+
+/// How an instruction uses the address its operand resolves to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operation {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+const fn operation(instruction: Instruction) -> Operation {
+    match instruction {
+        Instruction::STA
+        | Instruction::STX
+        | Instruction::STY
+        | Instruction::STZ
+        | Instruction::SAX => Operation::Write,
+        Instruction::ASL
+        | Instruction::LSR
+        | Instruction::ROL
+        | Instruction::ROR
+        | Instruction::INC
+        | Instruction::DEC
+        | Instruction::TSB
+        | Instruction::TRB
+        | Instruction::SLO
+        | Instruction::RLA
+        | Instruction::SRE
+        | Instruction::RRA
+        | Instruction::RRAnd
+        | Instruction::DCP
+        | Instruction::ISC
+        | Instruction::ISCnd => Operation::ReadModifyWrite,
+        _ => Operation::Read,
+    }
+}
+
+/// The T-states of `instruction` with operands in `mode`.
+#[must_use]
+pub const fn sequence(instruction: Instruction, mode: AddressingMode) -> Sequence {
+    use Address::{Effective, Next, Opcode, Operand, OperandHigh, Pointer, PointerHigh, Stack};
+
+    let fetch = Sequence::new(&[TState::read(Opcode)]);
+    let implied = fetch.then(TState::dummy_read(Operand));
+    match (instruction, mode) {
+        (Instruction::BRK | Instruction::BRKcld, _) => implied
+            .then(TState::write(Stack))
+            .then(TState::write(Stack))
+            .then(TState::write(Stack))
+            .then(TState::read(Address::Vector))
+            .then(TState::read(Address::VectorHigh)),
+        // The high byte of the target is read last, after the return address
+        // has been pushed.
+        (Instruction::JSR, _) => fetch
+            .then(TState::read(Operand))
+            .then(TState::dummy_read(Stack))
+            .then(TState::write(Stack))
+            .then(TState::write(Stack))
+            .then(TState::read(OperandHigh)),
+        (Instruction::RTS, _) => implied
+            .then(TState::dummy_read(Stack))
+            .then(TState::read(Stack))
+            .then(TState::read(Stack))
+            .then(TState::dummy_read(Address::Return)),
+        (Instruction::RTI, _) => implied
+            .then(TState::dummy_read(Stack))
+            .then(TState::read(Stack))
+            .then(TState::read(Stack))
+            .then(TState::read(Stack)),
+        (Instruction::PHA | Instruction::PHP | Instruction::PHX | Instruction::PHY, _) => {
+            implied.then(TState::write(Stack))
+        }
+        (Instruction::PLA | Instruction::PLX | Instruction::PLY, _) => implied
+            .then(TState::dummy_read(Stack))
+            .then(TState::read(Stack)),
+        (Instruction::PLP, _) => implied
+            .then(TState::dummy_read(Stack))
+            .then(TState::read(Stack))
+            .with_late_status(),
+        (Instruction::CLI | Instruction::SEI, _) => implied.with_late_status(),
+        (_, AddressingMode::Implied | AddressingMode::Accumulator) => implied,
+        (_, AddressingMode::Immediate) => fetch.then(TState::read(Operand)),
+        (_, AddressingMode::Relative) => fetch
+            .then(TState::read(Operand))
+            .then(TState::dummy_read(Next).when(Condition::BranchTaken))
+            .then(TState::dummy_read(Address::Unfixed).when(Condition::BranchCrossed)),
+        (Instruction::JMP, AddressingMode::Absolute) => fetch
+            .then(TState::read(Operand))
+            .then(TState::read(OperandHigh)),
+        // The 65C02 adds X to the pointer while rereading its high byte.
+        (Instruction::JMP, AddressingMode::AbsoluteIndexedIndirect) => fetch
+            .then(TState::read(Operand))
+            .then(TState::read(OperandHigh))
+            .then(TState::dummy_read(OperandHigh))
+            .then(TState::read(Pointer))
+            .then(TState::read(PointerHigh)),
+        (Instruction::JMP, _) => fetch
+            .then(TState::read(Operand))
+            .then(TState::read(OperandHigh))
+            .then(TState::read(Pointer))
+            .then(TState::read(PointerHigh)),
+        (_, mode) => {
+            let operation = operation(instruction);
+            // Reads only pay for the wrong page when indexing crosses one;
+            // writes always take the extra cycle.
+            let fixup = TState::dummy_read(Address::Unfixed).when(
+                if matches!(operation, Operation::Read) {
+                    Condition::PageCrossed
+                } else {
+                    Condition::Always
+                },
+            );
+            let addressed = match mode {
+                AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::dummy_read(Address::ZeroPage)),
+                AddressingMode::Absolute => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::read(OperandHigh)),
+                AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::read(OperandHigh))
+                    .then(fixup),
+                AddressingMode::IndexedIndirectX => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::dummy_read(Address::ZeroPage))
+                    .then(TState::read(Pointer))
+                    .then(TState::read(PointerHigh)),
+                AddressingMode::IndirectIndexedY => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::read(Pointer))
+                    .then(TState::read(PointerHigh))
+                    .then(fixup),
+                AddressingMode::ZeroPageIndirect => fetch
+                    .then(TState::read(Operand))
+                    .then(TState::read(Pointer))
+                    .then(TState::read(PointerHigh)),
+                _ => fetch.then(TState::read(Operand)),
+            };
+            match operation {
+                Operation::Read => addressed.then(TState::read(Effective)),
+                Operation::Write => addressed.then(TState::write(Effective)),
+                Operation::ReadModifyWrite => addressed
+                    .then(TState::read(Effective))
+                    .then(TState::dummy_write(Effective))
+                    .then(TState::write(Effective)),
+            }
+        }
+    }
+}
+
+/// The bus activity of one clock cycle: the state of the address bus, data
+/// bus, R/W and SYNC pins once the cycle has completed.
+///
+/// The CPU, [`CPU::step_cycle`] and the transistor-level simulation all
+/// describe cycles this way, so anything driven by pin state sees the same
+/// values whichever produced them.
+///
+/// [`CPU::step_cycle`]: crate::cpu::CPU::step_cycle
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusCycle {
+    pub address: u16,
+    pub data: u8,
+    pub access: Access,
+    /// Whether this is an opcode fetch.
+    pub sync: bool,
+}
+
+impl BusCycle {
+    /// Level of the R/W pin: high for a read, low for a write.
+    #[must_use]
+    pub const fn read_write(self) -> bool {
+        matches!(self.access, Access::Read)
+    }
+}
+
+/// The most cycles logged for one step: an instruction followed by the
+/// interrupt sequence.
+const LOG_SIZE: usize = 2 * MAX_STEPS;
+
+/// Follows an instruction through its T-states as the CPU executes it,
+/// supplying the dummy accesses between the real ones and logging every
+/// cycle.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Sequencer {
+    sequence: Sequence,
+    mode: AddressingMode,
+    step: usize,
+    pc: u16,
+    stack: u8,
+    operand: [u8; 2],
+    pointer: [u8; 2],
+    /// The last real access, which a read-modify-write rewrites.
+    last: (u16, u8),
+    /// Set when the instruction made an access the table doesn't expect,
+    /// after which no more dummy accesses are made.
+    lost: bool,
+    log: [BusCycle; LOG_SIZE],
+    logged: usize,
+}
+
+/// The registers a dummy access's address may depend on.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Context {
+    pub(crate) index_x: u8,
+    pub(crate) index_y: u8,
+    pub(crate) program_counter: u16,
+    pub(crate) branch_taken: bool,
+}
+
+impl Sequencer {
+    pub(crate) const fn new() -> Sequencer {
+        Sequencer {
+            sequence: Sequence::EMPTY,
+            mode: AddressingMode::Implied,
+            step: 0,
+            pc: 0,
+            stack: 0,
+            operand: [0; 2],
+            pointer: [0; 2],
+            last: (0, 0),
+            lost: false,
+            log: [BusCycle {
+                address: 0,
+                data: 0,
+                access: Access::Read,
+                sync: false,
+            }; LOG_SIZE],
+            logged: 0,
+        }
+    }
+
+    /// Starts following `sequence` for an instruction at `pc`. The log is
+    /// kept when `append` is set, as for an interrupt taken after the
+    /// instruction.
+    pub(crate) const fn begin(
+        &mut self,
+        sequence: Sequence,
+        mode: AddressingMode,
+        pc: u16,
+        stack: u8,
+        append: bool,
+    ) {
+        self.sequence = sequence;
+        self.mode = mode;
+        self.step = 0;
+        self.pc = pc;
+        self.stack = stack;
+        self.lost = false;
+        if !append {
+            self.logged = 0;
+        }
+    }
+
+    /// Switches to the T-states of the instruction decoded from the opcode
+    /// fetched since [`Sequencer::begin`], the first T-state of every
+    /// sequence.
+    pub(crate) const fn decoded(&mut self, sequence: Sequence, mode: AddressingMode) {
+        self.sequence = sequence;
+        self.mode = mode;
+    }
+
+    pub(crate) const fn sequence(&self) -> Sequence {
+        self.sequence
+    }
+
+    /// The cycles logged since the last instruction began.
+    pub(crate) fn log(&self) -> &[BusCycle] {
+        &self.log[..self.logged]
+    }
+
+    const fn push_log(&mut self, access: Access, address: u16, data: u8, sync: bool) {
+        if self.logged < LOG_SIZE {
+            self.log[self.logged] = BusCycle {
+                address,
+                data,
+                access,
+                sync,
+            };
+            self.logged += 1;
+        }
+    }
+
+    /// Records a real access made by the instruction.
+    pub(crate) fn real(&mut self, access: Access, address: u16, data: u8) {
+        // Dummy T-states are skipped when the CPU doesn't make them.
+        while self.sequence.step(self.step).is_some_and(|step| step.dummy) {
+            self.step += 1;
+        }
+        let step = self.sequence.step(self.step);
+        let sync = match step {
+            Some(step) if step.access == access && !self.lost => {
+                match step.address {
+                    Address::Operand => self.operand[0] = data,
+                    Address::OperandHigh => self.operand[1] = data,
+                    Address::Pointer => self.pointer[0] = data,
+                    Address::PointerHigh => self.pointer[1] = data,
+                    _ => {}
+                }
+                self.step += 1;
+                step.address == Address::Opcode
+            }
+            _ => {
+                self.lost = true;
+                false
+            }
+        };
+        self.last = (address, data);
+        self.push_log(access, address, data, sync);
+    }
+
+    /// Records a dummy access made on the CPU's behalf.
+    pub(crate) const fn dummy(&mut self, access: Access, address: u16, data: u8, sync: bool) {
+        self.push_log(access, address, data, sync);
+    }
+
+    /// The next dummy access due before the next real one, as its
+    /// direction, address, value to write and whether it is an opcode
+    /// fetch. Dummy T-states whose condition doesn't hold are skipped.
+    ///
+    /// With `finish` set, the instruction is over and every T-state left is
+    /// due, including real ones the CPU has no use for, such as the read of
+    /// the high byte of a `JSR` target.
+    pub(crate) fn next_dummy(
+        &mut self,
+        context: Context,
+        finish: bool,
+    ) -> Option<(Access, u16, u8, bool)> {
+        if self.lost {
+            return None;
+        }
+        while let Some(step) = self.sequence.step(self.step) {
+            if !step.dummy && !finish {
+                return None;
+            }
+            self.step += 1;
+            if self.holds(step.condition, context) {
+                let address = self.address(step.address, context);
+                return Some((
+                    step.access,
+                    address,
+                    self.last.1,
+                    step.address == Address::Opcode,
+                ));
+            }
+        }
+        None
+    }
+
+    /// The base address of an indexed mode and the index added to it.
+    const fn indexed(&self, context: Context) -> (u16, u8) {
+        match self.mode {
+            AddressingMode::AbsoluteX => (u16::from_le_bytes(self.operand), context.index_x),
+            AddressingMode::IndirectIndexedY => (u16::from_le_bytes(self.pointer), context.index_y),
+            _ => (u16::from_le_bytes(self.operand), context.index_y),
+        }
+    }
+
+    const fn holds(&self, condition: Condition, context: Context) -> bool {
+        match condition {
+            Condition::Always => true,
+            Condition::PageCrossed => {
+                let (base, index) = self.indexed(context);
+                (base & 0xff) + index as u16 > 0xff
+            }
+            Condition::BranchTaken => context.branch_taken,
+            Condition::BranchCrossed => {
+                let next = self.pc.wrapping_add(2);
+                context.branch_taken && (next ^ context.program_counter) & 0xff00 != 0
+            }
+        }
+    }
+
+    const fn address(&self, address: Address, context: Context) -> u16 {
+        match address {
+            Address::Opcode => self.pc,
+            Address::Operand => self.pc.wrapping_add(1),
+            Address::OperandHigh => self.pc.wrapping_add(2),
+            Address::ZeroPage => self.operand[0] as u16,
+            Address::Pointer | Address::PointerHigh => {
+                let high = matches!(address, Address::PointerHigh) as u8;
+                match self.mode {
+                    AddressingMode::Indirect => {
+                        u16::from_le_bytes(self.operand).wrapping_add(high as u16)
+                    }
+                    // The NMOS chip doesn't carry into the pointer's high byte.
+                    AddressingMode::BuggyIndirect => {
+                        u16::from_le_bytes([self.operand[0].wrapping_add(high), self.operand[1]])
+                    }
+                    AddressingMode::AbsoluteIndexedIndirect => u16::from_le_bytes(self.operand)
+                        .wrapping_add(context.index_x as u16)
+                        .wrapping_add(high as u16),
+                    AddressingMode::IndexedIndirectX => self.operand[0]
+                        .wrapping_add(context.index_x)
+                        .wrapping_add(high)
+                        as u16,
+                    _ => self.operand[0].wrapping_add(high) as u16,
+                }
+            }
+            Address::Unfixed => {
+                if matches!(self.mode, AddressingMode::Relative) {
+                    let next = self.pc.wrapping_add(2);
+                    (next & 0xff00) | (context.program_counter & 0xff)
+                } else {
+                    let (base, index) = self.indexed(context);
+                    (base & 0xff00) | base.to_le_bytes()[0].wrapping_add(index) as u16
+                }
+            }
+            Address::Effective => self.last.0,
+            Address::Stack => 0x0100 | self.stack as u16,
+            Address::Vector => 0xfffe,
+            Address::VectorHigh => 0xffff,
+            Address::Next => self.pc.wrapping_add(2),
+            Address::Return => context.program_counter.wrapping_sub(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::NMOS6502_CYCLES;
+    use crate::Variant;
+
+    #[test]
+    fn base_cycles_match_the_timing_table() {
+        for opcode in 0..=u8::MAX {
+            if let Some((instruction, mode)) = crate::instruction::Nmos6502::decode(opcode) {
+                assert_eq!(
+                    sequence(instruction, mode).base_cycles(),
+                    NMOS6502_CYCLES[usize::from(opcode)],
+                    "opcode {opcode:02x}"
+                );
+            }
+        }
+        assert_eq!(INTERRUPT.base_cycles(), 7);
+    }
+
+    #[test]
+    fn trailing_writes() {
+        use AddressingMode::{AbsoluteX, Accumulator, Implied, ZeroPage};
+        assert_eq!(sequence(Instruction::STA, AbsoluteX).trailing_writes(), 1);
+        assert_eq!(sequence(Instruction::PHA, Implied).trailing_writes(), 1);
+        assert_eq!(sequence(Instruction::INC, ZeroPage).trailing_writes(), 2);
+        assert_eq!(sequence(Instruction::ASL, Accumulator).trailing_writes(), 0);
+        assert_eq!(
+            sequence(Instruction::JSR, AddressingMode::Absolute).trailing_writes(),
+            0
+        );
+    }
+}
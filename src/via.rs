@@ -0,0 +1,876 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! A 6522 Versatile Interface Adapter.
+//!
+//! [`Via`] maps the sixteen registers of a 6522 (or W65C22) over another
+//! bus:
+//!
+//! | Offset | Register | Read                                  | Write                          |
+//! |--------|----------|---------------------------------------|--------------------------------|
+//! | 0      | `ORB`    | port B                                | port B outputs                 |
+//! | 1      | `ORA`    | port A, with handshake                | port A outputs, with handshake |
+//! | 2      | `DDRB`   | port B direction, 1 for output        | the same                       |
+//! | 3      | `DDRA`   | port A direction                      | the same                       |
+//! | 4      | `T1C_L`  | timer 1 counter low, clears its flag  | timer 1 latch low              |
+//! | 5      | `T1C_H`  | timer 1 counter high                  | latch high, starts timer 1     |
+//! | 6      | `T1L_L`  | timer 1 latch low                     | timer 1 latch low              |
+//! | 7      | `T1L_H`  | timer 1 latch high                    | latch high, clears its flag    |
+//! | 8      | `T2C_L`  | timer 2 counter low, clears its flag  | timer 2 latch low              |
+//! | 9      | `T2C_H`  | timer 2 counter high                  | counter high, starts timer 2   |
+//! | A      | `SR`     | shift register, starts a shift        | the same                       |
+//! | B      | `ACR`    | auxiliary control                     | the same                       |
+//! | C      | `PCR`    | peripheral control                    | the same                       |
+//! | D      | `IFR`    | interrupt flags, bit 7 if any enabled | clears the flags written as 1  |
+//! | E      | `IER`    | interrupt enables, bit 7 set          | sets or clears by bit 7        |
+//! | F      | `ORA_NH` | port A, no handshake                  | port A outputs, no handshake   |
+//!
+//! The timers and the shift register are clocked from [`Bus::phi2`]. The
+//! host drives the input pins with [`Via::set_port_a`] and
+//! [`Via::set_port_b`] and the control lines with [`Via::set_ca1`] and
+//! friends, and reads back what the VIA drives with [`Via::port_a`],
+//! [`Via::ca2`] and so on. [`Via::irq_pending`] follows the IRQ output,
+//! for wiring to the CPU:
+//!
+//! ```
+//! use mos6502::memory::{Bus, Memory};
+//! use mos6502::via::{Via, ACR, IER, IFR, INT_T1, T1C_H, T1C_L};
+//!
+//! let mut via = Via::new(Memory::new(), 0x6000);
+//! // Free-running timer 1 with a period of 1000 cycles.
+//! via.set_byte(0x6000 + ACR, 0x40);
+//! via.set_byte(0x6000 + IER, 0x80 | INT_T1);
+//! via.set_byte(0x6000 + T1C_L, 0xe6);
+//! via.set_byte(0x6000 + T1C_H, 0x03);
+//! for cycle in 0..1000 {
+//!     via.phi2(cycle);
+//! }
+//! assert!(via.irq_pending());
+//! assert_eq!(via.get_byte(0x6000 + IFR), 0x80 | INT_T1);
+//! // Reading the counter acknowledges the interrupt.
+//! via.get_byte(0x6000 + T1C_L);
+//! assert!(!via.irq_pending());
+//! ```
+//!
+//! Timing follows whole cycles: a one-shot started with a count of `N`
+//! flags its interrupt `N + 1` cycles later, and a free-running timer
+//! repeats every `N + 2` cycles. Timer 2 can count falling edges on PB6
+//! instead of cycles. The shift register supports all eight modes of
+//! `ACR` bits 4-2; in the timer 2 modes it shifts a bit every
+//! `2 * (N + 2)` cycles for the low latch `N` of timer 2, and under
+//! external control it shifts out on falling edges of CB1 and in on rising
+//! ones. A reset clears every register except the timers, their latches
+//! and the shift register, as on the real chip.
+
+use core::cell::Cell;
+use core::ops::Range;
+
+use crate::interrupt::IrqSource;
+use crate::memory::{Bus, BusError};
+
+pub const ORB: u16 = 0x0;
+pub const ORA: u16 = 0x1;
+pub const DDRB: u16 = 0x2;
+pub const DDRA: u16 = 0x3;
+pub const T1C_L: u16 = 0x4;
+pub const T1C_H: u16 = 0x5;
+pub const T1L_L: u16 = 0x6;
+pub const T1L_H: u16 = 0x7;
+pub const T2C_L: u16 = 0x8;
+pub const T2C_H: u16 = 0x9;
+pub const SR: u16 = 0xA;
+pub const ACR: u16 = 0xB;
+pub const PCR: u16 = 0xC;
+pub const IFR: u16 = 0xD;
+pub const IER: u16 = 0xE;
+pub const ORA_NH: u16 = 0xF;
+
+/// The number of registers, and of addresses the VIA occupies.
+pub const SIZE: u16 = 16;
+
+/// An active edge on CA2, in `IFR` and `IER`.
+pub const INT_CA2: u8 = 0x01;
+/// An active edge on CA1.
+pub const INT_CA1: u8 = 0x02;
+/// Eight bits shifted.
+pub const INT_SR: u8 = 0x04;
+/// An active edge on CB2.
+pub const INT_CB2: u8 = 0x08;
+/// An active edge on CB1.
+pub const INT_CB1: u8 = 0x10;
+/// Timer 2 ran out.
+pub const INT_T2: u8 = 0x20;
+/// Timer 1 ran out.
+pub const INT_T1: u8 = 0x40;
+
+/// The shift register modes of `ACR` bits 4-2.
+const SR_DISABLED: u8 = 0;
+const SR_IN_T2: u8 = 1;
+const SR_IN_PHI2: u8 = 2;
+const SR_IN_CB1: u8 = 3;
+const SR_OUT_T2_FREE: u8 = 4;
+const SR_OUT_T2: u8 = 5;
+const SR_OUT_PHI2: u8 = 6;
+const SR_OUT_CB1: u8 = 7;
+
+/// The CA2 and CB2 modes of `PCR`. Modes 0 to 3 are inputs.
+const C2_INDEPENDENT: u8 = 0x01;
+const C2_POSITIVE: u8 = 0x02;
+const C2_HANDSHAKE: u8 = 4;
+const C2_PULSE: u8 = 5;
+const C2_LOW: u8 = 6;
+const C2_HIGH: u8 = 7;
+
+/// A bus with the registers of a 6522 VIA at `base` to `base + 15`.
+#[derive(Clone, Debug)]
+// The chip's state is mostly single lines and latches.
+#[allow(clippy::struct_excessive_bools)]
+pub struct Via<B: Bus> {
+    inner: B,
+    base: u16,
+    orb: u8,
+    ora: u8,
+    ddrb: u8,
+    ddra: u8,
+    /// Levels driven onto the port pins by the host.
+    pins_a: u8,
+    pins_b: u8,
+    /// Pin levels latched on the active edge of CA1 and CB1.
+    latch_a: u8,
+    latch_b: u8,
+    t1_counter: u16,
+    t1_latch: u16,
+    /// Whether timer 1 still has a one-shot interrupt to flag.
+    t1_armed: bool,
+    /// Whether a free-running timer 1 reloads on the next cycle.
+    t1_reload: bool,
+    /// The level timer 1 drives onto PB7 when enabled by `ACR` bit 7.
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch_low: u8,
+    t2_armed: bool,
+    sr: u8,
+    /// Bits left to shift, or zero when the shift register is idle.
+    sr_bits: Cell<u8>,
+    /// Cycles until the next bit is shifted.
+    sr_timer: Cell<u16>,
+    /// The level the shift register drives onto CB2 when shifting out.
+    sr_out: bool,
+    acr: u8,
+    pcr: u8,
+    ifr: Cell<u8>,
+    ier: u8,
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    /// The level of CA2 in handshake and pulse output modes.
+    ca2_out: Cell<bool>,
+    /// The level of CB2 in handshake and pulse output modes.
+    cb2_out: bool,
+}
+
+impl<B: Bus> Via<B> {
+    /// Maps the VIA registers at `base` over `inner`. All lines start high
+    /// and every pin of both ports is an input.
+    pub const fn new(inner: B, base: u16) -> Self {
+        Via {
+            inner,
+            base,
+            orb: 0,
+            ora: 0,
+            ddrb: 0,
+            ddra: 0,
+            pins_a: 0xff,
+            pins_b: 0xff,
+            latch_a: 0,
+            latch_b: 0,
+            t1_counter: 0xffff,
+            t1_latch: 0xffff,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0xffff,
+            t2_latch_low: 0xff,
+            t2_armed: false,
+            sr: 0,
+            sr_bits: Cell::new(0),
+            sr_timer: Cell::new(0),
+            sr_out: true,
+            acr: 0,
+            pcr: 0,
+            ifr: Cell::new(0),
+            ier: 0,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: Cell::new(true),
+            cb2_out: true,
+        }
+    }
+
+    #[must_use]
+    pub const fn base(&self) -> u16 {
+        self.base
+    }
+
+    /// Returns `true` while an enabled interrupt flag is set.
+    #[must_use]
+    pub const fn irq_pending(&self) -> bool {
+        self.ifr.get() & self.ier & 0x7f != 0
+    }
+
+    /// The levels on the port A pins: the outputs where `DDRA` selects
+    /// them and what the host drives elsewhere.
+    #[must_use]
+    pub const fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    /// The levels on the port B pins, with PB7 driven by timer 1 when
+    /// `ACR` bit 7 is set.
+    #[must_use]
+    pub const fn port_b(&self) -> u8 {
+        let pins = (self.orb & self.ddrb) | (self.pins_b & !self.ddrb);
+        if self.acr & 0x80 == 0 {
+            pins
+        } else if self.pb7 {
+            pins | 0x80
+        } else {
+            pins & 0x7f
+        }
+    }
+
+    /// Drives the port A pins. Only the pins set as inputs are read.
+    pub const fn set_port_a(&mut self, levels: u8) {
+        self.pins_a = levels;
+    }
+
+    /// Drives the port B pins. A falling edge on PB6 counts timer 2 down
+    /// when `ACR` bit 5 is set.
+    pub fn set_port_b(&mut self, levels: u8) {
+        let falling = self.pins_b & !levels & 0x40 != 0;
+        self.pins_b = levels;
+        if falling && self.acr & 0x20 != 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.flag(INT_T2);
+            }
+        }
+    }
+
+    /// Drives CA1. An active edge, as selected by `PCR` bit 0, sets its
+    /// flag, latches port A if `ACR` bit 0 is set and ends a CA2
+    /// handshake.
+    pub fn set_ca1(&mut self, level: bool) {
+        let edge = level != self.ca1;
+        self.ca1 = level;
+        if edge && level == (self.pcr & 0x01 != 0) {
+            self.flag(INT_CA1);
+            self.latch_a = self.port_a();
+            if self.ca2_control() == C2_HANDSHAKE {
+                self.ca2_out.set(true);
+            }
+        }
+    }
+
+    /// Drives CA2, which sets its flag on an active edge when `PCR`
+    /// makes it an input.
+    pub fn set_ca2(&mut self, level: bool) {
+        let edge = level != self.ca2;
+        self.ca2 = level;
+        let control = self.ca2_control();
+        if edge && control < C2_HANDSHAKE && level == (control & C2_POSITIVE != 0) {
+            self.flag(INT_CA2);
+        }
+    }
+
+    /// Drives CB1. An active edge, as selected by `PCR` bit 4, sets its
+    /// flag, latches port B if `ACR` bit 1 is set and ends a CB2
+    /// handshake. CB1 also clocks the shift register in the external
+    /// modes.
+    pub fn set_cb1(&mut self, level: bool) {
+        let edge = level != self.cb1;
+        self.cb1 = level;
+        if !edge {
+            return;
+        }
+        if level == (self.pcr & 0x10 != 0) {
+            self.flag(INT_CB1);
+            self.latch_b = self.port_b();
+            if self.cb2_control() == C2_HANDSHAKE {
+                self.cb2_out = true;
+            }
+        }
+        if self.sr_bits.get() > 0 {
+            match self.sr_mode() {
+                SR_IN_CB1 if level => self.shift(),
+                SR_OUT_CB1 if !level => self.shift(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Drives CB2, which sets its flag on an active edge when `PCR`
+    /// makes it an input, and is where the shift register shifts in from.
+    pub fn set_cb2(&mut self, level: bool) {
+        let edge = level != self.cb2;
+        self.cb2 = level;
+        let control = self.cb2_control();
+        if edge
+            && control < C2_HANDSHAKE
+            && !self.shifting_out()
+            && level == (control & C2_POSITIVE != 0)
+        {
+            self.flag(INT_CB2);
+        }
+    }
+
+    /// The level on CA2: what the VIA drives in the output modes, and the
+    /// host's level otherwise.
+    #[must_use]
+    pub const fn ca2(&self) -> bool {
+        match self.ca2_control() {
+            C2_HANDSHAKE | C2_PULSE => self.ca2_out.get(),
+            C2_LOW => false,
+            C2_HIGH => true,
+            _ => self.ca2,
+        }
+    }
+
+    /// The level on CB2: the shift register output while shifting out,
+    /// what the VIA drives in the output modes, and the host's level
+    /// otherwise.
+    #[must_use]
+    pub const fn cb2(&self) -> bool {
+        if self.shifting_out() {
+            return self.sr_out;
+        }
+        match self.cb2_control() {
+            C2_HANDSHAKE | C2_PULSE => self.cb2_out,
+            C2_LOW => false,
+            C2_HIGH => true,
+            _ => self.cb2,
+        }
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the VIA, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn register(&self, address: u16) -> Option<u16> {
+        let offset = address.wrapping_sub(self.base);
+        (offset < SIZE).then_some(offset)
+    }
+
+    fn flag(&self, bits: u8) {
+        self.ifr.set(self.ifr.get() | bits);
+    }
+
+    fn clear(&self, bits: u8) {
+        self.ifr.set(self.ifr.get() & !bits);
+    }
+
+    const fn ca2_control(&self) -> u8 {
+        (self.pcr >> 1) & 0x07
+    }
+
+    const fn cb2_control(&self) -> u8 {
+        (self.pcr >> 5) & 0x07
+    }
+
+    const fn sr_mode(&self) -> u8 {
+        (self.acr >> 2) & 0x07
+    }
+
+    const fn shifting_out(&self) -> bool {
+        self.sr_mode() >= SR_OUT_T2_FREE
+    }
+
+    /// Cycles between shifts in the modes clocked by the CPU.
+    fn sr_period(&self) -> u16 {
+        match self.sr_mode() {
+            SR_IN_PHI2 | SR_OUT_PHI2 => 2,
+            _ => 2 * (u16::from(self.t2_latch_low) + 2),
+        }
+    }
+
+    fn start_shift(&self) {
+        self.clear(INT_SR);
+        if self.sr_mode() != SR_DISABLED {
+            self.sr_bits.set(8);
+            self.sr_timer.set(self.sr_period());
+        }
+    }
+
+    fn shift(&mut self) {
+        if self.shifting_out() {
+            self.sr_out = self.sr & 0x80 != 0;
+            self.sr = self.sr.rotate_left(1);
+        } else {
+            self.sr = self.sr << 1 | u8::from(self.cb2);
+        }
+        // Free-running output keeps going and never interrupts.
+        if self.sr_mode() != SR_OUT_T2_FREE {
+            let bits = self.sr_bits.get() - 1;
+            self.sr_bits.set(bits);
+            if bits == 0 {
+                self.flag(INT_SR);
+            }
+        }
+    }
+
+    /// Clears the CA1 and CA2 flags on an access to `ORA`, except a CA2
+    /// input in independent mode, and starts a handshake.
+    fn access_port_a(&self) {
+        let control = self.ca2_control();
+        if control < C2_HANDSHAKE && control & C2_INDEPENDENT != 0 {
+            self.clear(INT_CA1);
+        } else {
+            self.clear(INT_CA1 | INT_CA2);
+        }
+        if control == C2_HANDSHAKE || control == C2_PULSE {
+            self.ca2_out.set(false);
+        }
+    }
+
+    /// The same for `ORB`. CB2 only handshakes on writes.
+    fn access_port_b(&self) {
+        let control = self.cb2_control();
+        if control < C2_HANDSHAKE && control & C2_INDEPENDENT != 0 {
+            self.clear(INT_CB1);
+        } else {
+            self.clear(INT_CB1 | INT_CB2);
+        }
+    }
+
+    const fn read_port_a(&self) -> u8 {
+        if self.acr & 0x01 == 0 {
+            self.port_a()
+        } else {
+            self.latch_a
+        }
+    }
+
+    /// Port B reads the output register for output pins, even if the pin
+    /// is loaded, and the pins or the latch for inputs.
+    const fn read_port_b(&self) -> u8 {
+        let inputs = if self.acr & 0x02 == 0 {
+            self.port_b()
+        } else {
+            self.latch_b
+        };
+        (self.orb & self.ddrb) | (inputs & !self.ddrb)
+    }
+
+    fn tick_timer1(&mut self) {
+        if self.t1_reload {
+            self.t1_reload = false;
+            self.t1_counter = self.t1_latch;
+        } else if self.t1_counter == 0 {
+            self.t1_counter = 0xffff;
+            if self.acr & 0x40 != 0 {
+                self.flag(INT_T1);
+                self.t1_reload = true;
+                self.pb7 = !self.pb7;
+            } else if self.t1_armed {
+                self.flag(INT_T1);
+                self.t1_armed = false;
+                self.pb7 = true;
+            }
+        } else {
+            self.t1_counter -= 1;
+        }
+    }
+
+    fn tick_timer2(&mut self) {
+        if self.acr & 0x20 != 0 {
+            return;
+        }
+        if self.t2_counter == 0 && self.t2_armed {
+            self.t2_armed = false;
+            self.flag(INT_T2);
+        }
+        self.t2_counter = self.t2_counter.wrapping_sub(1);
+    }
+
+    fn tick_shift_register(&mut self) {
+        let mode = self.sr_mode();
+        let clocked = matches!(
+            mode,
+            SR_IN_T2 | SR_IN_PHI2 | SR_OUT_T2_FREE | SR_OUT_T2 | SR_OUT_PHI2
+        );
+        if !clocked || (self.sr_bits.get() == 0 && mode != SR_OUT_T2_FREE) {
+            return;
+        }
+        let timer = self.sr_timer.get().saturating_sub(1);
+        if timer == 0 {
+            self.shift();
+            self.sr_timer.set(self.sr_period());
+        } else {
+            self.sr_timer.set(timer);
+        }
+    }
+}
+
+impl<B: Bus> IrqSource for Via<B> {
+    fn irq_pending(&self) -> bool {
+        Via::irq_pending(self)
+    }
+}
+
+impl<B: Bus> Bus for Via<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        let [t1_low, t1_high] = self.t1_counter.to_le_bytes();
+        let [t2_low, t2_high] = self.t2_counter.to_le_bytes();
+        let [l1_low, l1_high] = self.t1_latch.to_le_bytes();
+        match self.register(address) {
+            Some(ORB) => {
+                self.access_port_b();
+                self.read_port_b()
+            }
+            Some(ORA) => {
+                self.access_port_a();
+                self.read_port_a()
+            }
+            Some(DDRB) => self.ddrb,
+            Some(DDRA) => self.ddra,
+            Some(T1C_L) => {
+                self.clear(INT_T1);
+                t1_low
+            }
+            Some(T1C_H) => t1_high,
+            Some(T1L_L) => l1_low,
+            Some(T1L_H) => l1_high,
+            Some(T2C_L) => {
+                self.clear(INT_T2);
+                t2_low
+            }
+            Some(T2C_H) => t2_high,
+            Some(SR) => {
+                self.start_shift();
+                self.sr
+            }
+            Some(ACR) => self.acr,
+            Some(PCR) => self.pcr,
+            Some(IFR) => {
+                let flags = self.ifr.get();
+                if self.irq_pending() {
+                    flags | 0x80
+                } else {
+                    flags
+                }
+            }
+            Some(IER) => self.ier | 0x80,
+            Some(_) => self.read_port_a(),
+            None => self.inner.get_byte(address),
+        }
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        match self.register(address) {
+            Some(ORB) => {
+                self.orb = value;
+                self.access_port_b();
+                if matches!(self.cb2_control(), C2_HANDSHAKE | C2_PULSE) {
+                    self.cb2_out = false;
+                }
+            }
+            Some(ORA) => {
+                self.ora = value;
+                self.access_port_a();
+            }
+            Some(DDRB) => self.ddrb = value,
+            Some(DDRA) => self.ddra = value,
+            Some(T1C_L | T1L_L) => {
+                self.t1_latch = self.t1_latch & 0xff00 | u16::from(value);
+            }
+            Some(T1C_H) => {
+                self.t1_latch = self.t1_latch & 0x00ff | u16::from(value) << 8;
+                self.t1_counter = self.t1_latch;
+                self.t1_armed = true;
+                self.t1_reload = false;
+                self.pb7 = false;
+                self.clear(INT_T1);
+            }
+            Some(T1L_H) => {
+                self.t1_latch = self.t1_latch & 0x00ff | u16::from(value) << 8;
+                self.clear(INT_T1);
+            }
+            Some(T2C_L) => self.t2_latch_low = value,
+            Some(T2C_H) => {
+                self.t2_counter = u16::from_le_bytes([self.t2_latch_low, value]);
+                self.t2_armed = true;
+                self.clear(INT_T2);
+            }
+            Some(SR) => {
+                self.sr = value;
+                self.start_shift();
+            }
+            Some(ACR) => self.acr = value,
+            Some(PCR) => self.pcr = value,
+            Some(IFR) => self.clear(value & 0x7f),
+            Some(IER) => {
+                if value & 0x80 == 0 {
+                    self.ier &= !value;
+                } else {
+                    self.ier |= value & 0x7f;
+                }
+            }
+            Some(_) => self.ora = value,
+            None => self.inner.set_byte(address, value),
+        }
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+        // A pulse output goes back high after one cycle.
+        if self.ca2_control() == C2_PULSE {
+            self.ca2_out.set(true);
+        }
+        if self.cb2_control() == C2_PULSE {
+            self.cb2_out = true;
+        }
+        self.tick_timer1();
+        self.tick_timer2();
+        self.tick_shift_register();
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.inner.wait_states(address)
+    }
+
+    /// Clears the port, control and interrupt registers, which makes every
+    /// pin an input and disables the interrupts. The timers, their latches
+    /// and the shift register keep their contents, but stop interrupting.
+    fn reset(&mut self) {
+        self.orb = 0;
+        self.ora = 0;
+        self.ddrb = 0;
+        self.ddra = 0;
+        self.acr = 0;
+        self.pcr = 0;
+        self.ifr.set(0);
+        self.ier = 0;
+        self.t1_armed = false;
+        self.t1_reload = false;
+        self.t2_armed = false;
+        self.sr_bits.set(0);
+        self.ca2_out.set(true);
+        self.cb2_out = true;
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn via() -> Via<Memory> {
+        Via::new(Memory::new(), 0x6000)
+    }
+
+    fn run(via: &mut Via<Memory>, cycles: u64) {
+        for cycle in 0..cycles {
+            via.phi2(cycle);
+        }
+    }
+
+    #[test]
+    fn one_shot_timer_interrupts_once() {
+        let mut via = via();
+        via.set_byte(0x6000 + IER, 0x80 | INT_T1);
+        via.set_byte(0x6000 + T1C_L, 10);
+        via.set_byte(0x6000 + T1C_H, 0);
+        run(&mut via, 10);
+        assert!(!via.irq_pending());
+        run(&mut via, 1);
+        assert!(via.irq_pending());
+        via.set_byte(0x6000 + IFR, INT_T1);
+        // The counter keeps running but doesn't interrupt again.
+        run(&mut via, 0x1_0000);
+        assert!(!via.irq_pending());
+    }
+
+    #[test]
+    fn free_running_timer_repeats_and_toggles_pb7() {
+        let mut via = via();
+        via.set_byte(0x6000 + ACR, 0xc0);
+        via.set_byte(0x6000 + T1C_L, 8);
+        via.set_byte(0x6000 + T1C_H, 0);
+        assert_eq!(via.port_b() & 0x80, 0);
+        run(&mut via, 9);
+        assert_eq!(via.get_byte(0x6000 + IFR), INT_T1);
+        assert_eq!(via.port_b() & 0x80, 0x80);
+        via.get_byte(0x6000 + T1C_L);
+        run(&mut via, 9);
+        assert_eq!(via.get_byte(0x6000 + IFR), 0);
+        run(&mut via, 1);
+        assert_eq!(via.get_byte(0x6000 + IFR), INT_T1);
+        assert_eq!(via.port_b() & 0x80, 0);
+    }
+
+    #[test]
+    fn timer_2_counts_pulses_on_pb6() {
+        let mut via = via();
+        via.set_byte(0x6000 + ACR, 0x20);
+        via.set_byte(0x6000 + T2C_L, 2);
+        via.set_byte(0x6000 + T2C_H, 0);
+        run(&mut via, 100);
+        assert_eq!(via.get_byte(0x6000 + T2C_L), 2);
+        via.set_port_b(0xbf);
+        via.set_port_b(0xff);
+        via.set_port_b(0xbf);
+        assert_eq!(via.get_byte(0x6000 + IFR), INT_T2);
+    }
+
+    #[test]
+    fn interrupt_enable_sets_and_clears_by_bit_7() {
+        let mut via = via();
+        via.set_byte(0x6000 + IER, 0x80 | INT_CA1 | INT_T2);
+        assert_eq!(via.get_byte(0x6000 + IER), 0x80 | INT_CA1 | INT_T2);
+        via.set_byte(0x6000 + IER, INT_T2);
+        assert_eq!(via.get_byte(0x6000 + IER), 0x80 | INT_CA1);
+        // A disabled flag is set but doesn't raise IRQ.
+        via.set_byte(0x6000 + T2C_L, 0);
+        via.set_byte(0x6000 + T2C_H, 0);
+        run(&mut via, 1);
+        assert_eq!(via.get_byte(0x6000 + IFR), INT_T2);
+        assert!(!via.irq_pending());
+    }
+
+    #[test]
+    fn ports_mix_outputs_and_inputs() {
+        let mut via = via();
+        via.set_port_a(0x5a);
+        via.set_byte(0x6000 + DDRA, 0xf0);
+        via.set_byte(0x6000 + ORA, 0xc3);
+        assert_eq!(via.port_a(), 0xca);
+        assert_eq!(via.get_byte(0x6000 + ORA_NH), 0xca);
+        // Port B reads its output register even when the pin is loaded.
+        via.set_port_b(0x00);
+        via.set_byte(0x6000 + DDRB, 0x0f);
+        via.set_byte(0x6000 + ORB, 0xff);
+        assert_eq!(via.get_byte(0x6000 + ORB), 0x0f);
+    }
+
+    #[test]
+    fn ca1_edge_latches_port_a() {
+        let mut via = via();
+        // Positive edge on CA1, latching enabled.
+        via.set_byte(0x6000 + PCR, 0x01);
+        via.set_byte(0x6000 + ACR, 0x01);
+        via.set_byte(0x6000 + IER, 0x80 | INT_CA1);
+        via.set_ca1(false);
+        via.set_port_a(0x42);
+        assert!(!via.irq_pending());
+        via.set_ca1(true);
+        via.set_port_a(0x99);
+        assert!(via.irq_pending());
+        assert_eq!(via.get_byte(0x6000 + ORA), 0x42);
+        assert!(!via.irq_pending());
+    }
+
+    #[test]
+    fn ca2_handshakes_on_reads() {
+        let mut via = via();
+        // Handshake output on CA2, negative edge on CA1.
+        via.set_byte(0x6000 + PCR, 0x08);
+        assert!(via.ca2());
+        via.get_byte(0x6000 + ORA);
+        assert!(!via.ca2());
+        via.set_ca1(false);
+        assert!(via.ca2());
+    }
+
+    #[test]
+    fn shift_register_shifts_out_under_phi2() {
+        let mut via = via();
+        via.set_byte(0x6000 + ACR, 0x18);
+        via.set_byte(0x6000 + SR, 0xa5);
+        let mut out = 0;
+        for cycle in 0..16 {
+            via.phi2(cycle);
+            if cycle % 2 == 1 {
+                out = out << 1 | u8::from(via.cb2());
+            }
+        }
+        assert_eq!(out, 0xa5);
+        assert_eq!(via.get_byte(0x6000 + IFR), INT_SR);
+    }
+
+    #[test]
+    fn shift_register_shifts_in_on_cb1() {
+        let mut via = via();
+        via.set_byte(0x6000 + ACR, 0x0c);
+        via.get_byte(0x6000 + SR);
+        for bit in [1, 0, 0, 1, 1, 1, 0, 1] {
+            via.set_cb2(bit == 1);
+            via.set_cb1(false);
+            via.set_cb1(true);
+        }
+        assert_eq!(via.get_byte(0x6000 + IFR) & INT_SR, INT_SR);
+        assert_eq!(via.get_byte(0x6000 + SR), 0x9d);
+    }
+
+    #[test]
+    fn reset_keeps_the_timers() {
+        let mut via = via();
+        via.set_byte(0x6000 + DDRA, 0xff);
+        via.set_byte(0x6000 + IER, 0xff);
+        via.set_byte(0x6000 + T1L_L, 0x34);
+        via.set_byte(0x6000 + T1L_H, 0x12);
+        via.reset();
+        assert_eq!(via.get_byte(0x6000 + DDRA), 0);
+        assert_eq!(via.get_byte(0x6000 + IER), 0x80);
+        assert_eq!(via.get_byte(0x6000 + T1L_L), 0x34);
+        assert_eq!(via.get_byte(0x6000 + T1L_H), 0x12);
+    }
+}
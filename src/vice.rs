@@ -0,0 +1,257 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Moving state to and from VICE.
+//!
+//! VICE saves its state as a `.vsf` file: a header naming the machine,
+//! followed by one module per emulated chip. [`import`] reads the CPU and
+//! RAM modules of a C64 snapshot into a [`Snapshot`], and [`export`] writes
+//! a snapshot back out with just those two modules, so a state can be
+//! loaded into VICE and stepped in both emulators side by side.
+//!
+//! The file header is the magic `VICE Snapshot File` and `$1A`, a format
+//! version, and the machine name padded to 16 bytes; version 2 files add
+//! `VICE Version` and `$1A` with the version of VICE that wrote them. Each
+//! module starts with its name padded to 16 bytes, a version, and its total
+//! length including this 22-byte header (LE `u32`). The modules used are:
+//!
+//! | Module    | Offset | Size  | Field                                  |
+//! |-----------|--------|-------|----------------------------------------|
+//! | `MAINCPU` | 0      | 4     | cycle counter (LE)                     |
+//! |           | 4      | 4     | A, X, Y, SP                            |
+//! |           | 8      | 2     | PC (LE)                                |
+//! |           | 10     | 1     | P                                      |
+//! | `C64MEM`  | 0      | 2     | 6510 port data and direction registers |
+//! |           | 2      | 2     | EXROM and GAME lines                   |
+//! |           | 4      | 65536 | RAM                                    |
+//!
+//! Fields after these are skipped on import. The 6510 port registers answer
+//! at `$00` and `$01` on the real chip, so they are placed there in the
+//! snapshot's memory, over the RAM underneath.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::interrupt::InterruptController;
+use crate::registers::{Registers, StackPointer, Status};
+use crate::snapshot::Snapshot;
+
+const MAGIC: &[u8; 19] = b"VICE Snapshot File\x1a";
+const VERSION_MAGIC: &[u8; 13] = b"VICE Version\x1a";
+const NAME_LEN: usize = 16;
+const MODULE_HEADER_LEN: usize = NAME_LEN + 2 + 4;
+const CPU_MODULE: &str = "MAINCPU";
+const MEMORY_MODULE: &str = "C64MEM";
+const RAM_SIZE: usize = 0x10000;
+
+/// Why a VICE snapshot could not be imported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VsfError {
+    /// The data does not start with the VICE snapshot magic bytes.
+    BadMagic,
+    /// The data ends early.
+    Truncated,
+    /// The snapshot has no module of the given name.
+    MissingModule(&'static str),
+}
+
+impl fmt::Display for VsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VsfError::BadMagic => f.write_str("not a VICE snapshot"),
+            VsfError::Truncated => f.write_str("VICE snapshot is truncated"),
+            VsfError::MissingModule(name) => write!(f, "VICE snapshot has no {name} module"),
+        }
+    }
+}
+
+/// Reads the registers, cycle counter and RAM from a C64 `.vsf` snapshot.
+/// The IRQ line is taken to be released.
+///
+/// # Errors
+///
+/// Returns an error if `bytes` is not a VICE snapshot, or lacks the CPU or
+/// RAM module.
+pub fn import(bytes: &[u8]) -> Result<Snapshot, VsfError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(VsfError::BadMagic);
+    }
+    let mut rest = bytes
+        .get(MAGIC.len() + 2 + NAME_LEN..)
+        .ok_or(VsfError::Truncated)?;
+    if rest.starts_with(VERSION_MAGIC) {
+        rest = rest
+            .get(VERSION_MAGIC.len() + 8..)
+            .ok_or(VsfError::Truncated)?;
+    }
+
+    let (mut cpu, mut memory) = (None, None);
+    while !rest.is_empty() {
+        let header = rest.get(..MODULE_HEADER_LEN).ok_or(VsfError::Truncated)?;
+        let name = &header[..NAME_LEN];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN)];
+        let len = u32::from_le_bytes([header[18], header[19], header[20], header[21]]);
+        let len = usize::try_from(len).map_err(|_| VsfError::Truncated)?;
+        let module = rest
+            .get(MODULE_HEADER_LEN..len.max(MODULE_HEADER_LEN))
+            .ok_or(VsfError::Truncated)?;
+        match name {
+            b"MAINCPU" => cpu = Some(module),
+            b"C64MEM" => memory = Some(module),
+            _ => {}
+        }
+        rest = &rest[len.max(MODULE_HEADER_LEN)..];
+    }
+
+    let cpu = cpu.ok_or(VsfError::MissingModule(CPU_MODULE))?;
+    let memory = memory.ok_or(VsfError::MissingModule(MEMORY_MODULE))?;
+    let cpu = cpu.get(..11).ok_or(VsfError::Truncated)?;
+    let ram = memory.get(4..4 + RAM_SIZE).ok_or(VsfError::Truncated)?;
+
+    let mut ram = ram.to_vec();
+    ram[0] = memory[1];
+    ram[1] = memory[0];
+    let registers = Registers {
+        accumulator: cpu[4],
+        index_x: cpu[5],
+        index_y: cpu[6],
+        stack_pointer: StackPointer(cpu[7]),
+        program_counter: u16::from_le_bytes([cpu[8], cpu[9]]),
+        status: Status::from_byte(cpu[10]),
+    };
+    let cycles = u32::from_le_bytes([cpu[0], cpu[1], cpu[2], cpu[3]]);
+    Ok(Snapshot::from_parts(
+        registers,
+        cycles.into(),
+        InterruptController::new(),
+        ram,
+    ))
+}
+
+/// Writes `snapshot` as a VICE snapshot of machine `machine`, such as `C64`
+/// or `C64SC`, with `MAINCPU` and `C64MEM` modules. The cycle counter is
+/// truncated to 32 bits, and the EXROM and GAME lines are written inactive.
+#[must_use]
+pub fn export(snapshot: &Snapshot, machine: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(128 + RAM_SIZE);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[2, 0]);
+    push_name(&mut out, machine);
+    out.extend_from_slice(VERSION_MAGIC);
+    out.extend_from_slice(&[0; 8]);
+
+    let r = &snapshot.registers;
+    #[allow(clippy::cast_possible_truncation)]
+    let cycles = snapshot.cycles as u32;
+    let mut cpu = Vec::new();
+    cpu.extend_from_slice(&cycles.to_le_bytes());
+    cpu.extend_from_slice(&[r.accumulator, r.index_x, r.index_y, r.stack_pointer.0]);
+    cpu.extend_from_slice(&r.program_counter.to_le_bytes());
+    cpu.push(r.status.to_byte());
+    // The last opcode, and when the pending interrupts were raised.
+    cpu.extend_from_slice(&[0; 4 * 5]);
+    push_module(&mut out, CPU_MODULE, (1, 1), &cpu);
+
+    let ram = snapshot.memory();
+    let mut memory = Vec::with_capacity(4 + RAM_SIZE);
+    memory.extend_from_slice(&[ram[1], ram[0], 1, 1]);
+    memory.extend_from_slice(ram);
+    push_module(&mut out, MEMORY_MODULE, (0, 0), &memory);
+    out
+}
+
+fn push_name(out: &mut Vec<u8>, name: &str) {
+    let name = &name.as_bytes()[..name.len().min(NAME_LEN)];
+    out.extend_from_slice(name);
+    out.resize(out.len() + NAME_LEN - name.len(), 0);
+}
+
+fn push_module(out: &mut Vec<u8>, name: &str, (major, minor): (u8, u8), body: &[u8]) {
+    push_name(out, name);
+    out.extend_from_slice(&[major, minor]);
+    #[allow(clippy::cast_possible_truncation)]
+    let len = (MODULE_HEADER_LEN + body.len()) as u32;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::{Bus, Memory};
+
+    #[test]
+    fn round_trips_registers_and_ram() {
+        let mut cpu = CPU::new(Memory::new(), Nmos6502);
+        cpu.memory.set_bytes(0x0000, &[0x2f, 0x37]);
+        cpu.memory.set_bytes(0xc000, &[0xa9, 0x42]);
+        cpu.registers.program_counter = 0xc000;
+        cpu.single_step();
+        let snapshot = Snapshot::capture(&cpu);
+
+        let vsf = export(&snapshot, "C64");
+        assert!(vsf.starts_with(b"VICE Snapshot File\x1a\x02\x00C64\0"));
+        assert_eq!(import(&vsf), Ok(snapshot));
+    }
+
+    #[test]
+    fn skips_unknown_modules_and_trailing_fields() {
+        let mut vsf = Vec::new();
+        vsf.extend_from_slice(MAGIC);
+        vsf.extend_from_slice(&[1, 1]);
+        push_name(&mut vsf, "C64");
+        push_module(&mut vsf, "CIA1", (2, 2), &[0xff; 40]);
+        push_module(
+            &mut vsf,
+            "MAINCPU",
+            (1, 1),
+            &[0x10, 0, 0, 0, 1, 2, 3, 0xf0, 0x34, 0x12, 0x24, 0xaa, 0xbb],
+        );
+        let mut memory = alloc::vec![0; 4 + RAM_SIZE + 8];
+        memory[..2].copy_from_slice(&[0x37, 0x2f]);
+        memory[4 + 0x0400] = 0x20;
+        push_module(&mut vsf, "C64MEM", (0, 1), &memory);
+
+        let snapshot = import(&vsf).unwrap();
+        let r = snapshot.registers;
+        assert_eq!(
+            (r.accumulator, r.index_x, r.index_y, r.stack_pointer.0),
+            (1, 2, 3, 0xf0)
+        );
+        assert_eq!(r.program_counter, 0x1234);
+        assert_eq!(r.status.to_byte(), 0x24);
+        assert_eq!(snapshot.cycles, 0x10);
+        assert_eq!(snapshot.memory()[..2], [0x2f, 0x37]);
+        assert_eq!(snapshot.memory()[0x0400], 0x20);
+
+        vsf.truncate(vsf.len() - RAM_SIZE);
+        assert_eq!(import(&vsf), Err(VsfError::Truncated));
+        assert_eq!(import(b"C64 snapshot"), Err(VsfError::BadMagic));
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Slow memory.
+//!
+//! Boards often pair a fast CPU with memory that can't keep up: an EPROM
+//! with a 250 ns access time, RAM on the far side of a backplane, a UART
+//! that needs a long chip select. Their glue logic pulls RDY low to stretch
+//! each access by a few cycles. [`WaitStates`] declares those regions over
+//! any bus, and the CPU adds their wait states to its cycle count, so a
+//! design can be timed before it is built.
+
+use alloc::vec::Vec;
+use core::ops::{Range, RangeInclusive};
+
+use crate::memory::{Bus, BusError};
+
+/// A bus whose accesses take extra cycles in the declared regions.
+///
+/// # Examples
+///
+/// ```
+/// use mos6502::cpu::CPU;
+/// use mos6502::instruction::Nmos6502;
+/// use mos6502::memory::{Bus, Memory};
+/// use mos6502::waitstate::WaitStates;
+///
+/// let mut bus = WaitStates::new(Memory::new());
+/// // A slow EPROM holding the program.
+/// bus.add_region(0xE000..=0xFFFF, 1);
+/// let mut cpu = CPU::new(bus, Nmos6502);
+/// // LDA #$01: two fetches from ROM, so two wait states.
+/// cpu.memory.set_bytes(0xE000, &[0xa9, 0x01]);
+/// cpu.registers.program_counter = 0xE000;
+/// cpu.single_step();
+/// assert_eq!(cpu.cycles, 2 + 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WaitStates<B: Bus> {
+    inner: B,
+    regions: Vec<(RangeInclusive<u16>, u8)>,
+}
+
+impl<B: Bus> WaitStates<B> {
+    /// Wraps `inner` with every access taking its usual time.
+    pub const fn new(inner: B) -> WaitStates<B> {
+        WaitStates {
+            inner,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Makes every access to `range` take `cycles` extra cycles. Where
+    /// regions overlap, the one added last wins.
+    pub fn add_region(&mut self, range: RangeInclusive<u16>, cycles: u8) {
+        self.regions.push((range, cycles));
+    }
+
+    /// Removes every region.
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Returns a reference to the wrapped bus.
+    #[must_use]
+    pub const fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped bus.
+    pub const fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped bus.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: Bus> Bus for WaitStates<B> {
+    fn get_bytes(&self, range: Range<usize>) -> &[u8] {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte(&self, address: u16) -> u8 {
+        self.inner.get_byte(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        self.inner.set_byte(address, value);
+    }
+
+    fn set_bytes(&mut self, start: u16, values: &[u8]) {
+        self.inner.set_bytes(start, values);
+    }
+
+    fn phi2(&mut self, cycle: u64) {
+        self.inner.phi2(cycle);
+    }
+
+    fn wait_states(&self, address: u16) -> u8 {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map_or_else(|| self.inner.wait_states(address), |&(_, cycles)| cycles)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    fn take_error(&mut self) -> Option<BusError> {
+        self.inner.take_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::instruction::Nmos6502;
+    use crate::memory::Memory;
+
+    #[test]
+    fn counts_wait_states_of_every_access() {
+        let mut bus = WaitStates::new(Memory::new());
+        bus.add_region(0x8000..=0xFFFF, 2);
+        bus.add_region(0x9000..=0x90FF, 3);
+        let mut cpu = CPU::new(bus, Nmos6502);
+        // LDA $9000 from slow RAM: three fetches at 2, one read at 3.
+        cpu.memory.set_bytes(0x8000, &[0xad, 0x00, 0x90]);
+        cpu.registers.program_counter = 0x8000;
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 4 + 3 * 2 + 3);
+
+        // The same instruction with dummy accesses: STA $9000,X also reads
+        // the target before writing it.
+        let mut bus = WaitStates::new(Memory::new());
+        bus.add_region(0x9000..=0x90FF, 1);
+        let mut cpu = CPU::new(bus, Nmos6502);
+        cpu.cycle_accurate = true;
+        cpu.memory.set_bytes(0x0200, &[0x9d, 0x00, 0x90]);
+        cpu.registers.program_counter = 0x0200;
+        cpu.single_step();
+        assert_eq!(cpu.cycles, 5 + 2);
+    }
+}
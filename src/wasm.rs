@@ -0,0 +1,231 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! An [`Emulator`] for JavaScript, exported through wasm-bindgen.
+//!
+//! Everything that crosses into JavaScript is a number, a `Uint8Array` or
+//! a string. Cycle counts are `f64`s rather than `u64`s, which would turn
+//! into `BigInt`s; they stay exact for longer than any emulator runs.
+//! Build it without `std`, so that nothing reaches for threads, the clock
+//! or the filesystem, none of which `wasm32-unknown-unknown` has:
+//!
+//! ```sh
+//! cargo build --lib --target wasm32-unknown-unknown --no-default-features --features decimal_mode,wasm
+//! ```
+//!
+//! From Rust the facade is an ordinary type:
+//!
+//! ```
+//! use mos6502::wasm::Emulator;
+//!
+//! let mut emulator = Emulator::new();
+//! // LDA #$2A; loop: JMP loop
+//! emulator.load_program(&[0xa9, 0x2a, 0x4c, 0x02, 0x02], 0x0200).unwrap();
+//! emulator.step_frame(100);
+//! assert_eq!(emulator.registers_snapshot().a, 0x2a);
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use wasm_bindgen::prelude::*;
+
+use crate::cpu::CPU;
+use crate::instruction::Nmos6502;
+use crate::machine::{Machine, StopReason};
+use crate::memory::{Bus, Memory};
+
+/// The registers at one moment, as [`Emulator::registers_snapshot`] takes
+/// them.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    /// The status register, with the unused bit and B as `PHP` pushes
+    /// them.
+    pub p: u8,
+    /// The low byte of the stack pointer; the stack is on page 1.
+    pub sp: u8,
+    pub pc: u16,
+    /// Cycles since power-on.
+    pub cycles: f64,
+}
+
+/// An NMOS 6502 with 64 KiB of RAM, for JavaScript front-ends.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Emulator {
+    machine: Machine<Memory, Nmos6502>,
+    /// Why the last frame stopped before its budget ran out.
+    stop: Option<StopReason>,
+}
+
+// wasm-bindgen can't export `const fn`s.
+#[allow(clippy::missing_const_for_fn)]
+#[wasm_bindgen]
+impl Emulator {
+    /// A powered-on CPU with cleared memory.
+    #[wasm_bindgen(constructor)]
+    #[must_use]
+    pub fn new() -> Emulator {
+        Emulator {
+            machine: Machine::new(CPU::new(Memory::new(), Nmos6502)),
+            stop: None,
+        }
+    }
+
+    /// Copies `bytes` into memory at `address` and starts executing there.
+    ///
+    /// # Errors
+    ///
+    /// Fails, loading nothing, if the bytes run past `$FFFF`.
+    #[wasm_bindgen(js_name = loadProgram)]
+    pub fn load_program(&mut self, bytes: &[u8], address: u16) -> Result<(), JsError> {
+        self.machine
+            .load_bin(bytes, address)
+            .map_err(|error| JsError::new(&error.to_string()))?;
+        self.machine.cpu.registers.program_counter = address;
+        self.stop = None;
+        Ok(())
+    }
+
+    /// Resets the CPU, which starts at the reset vector. Memory keeps its
+    /// contents.
+    pub fn reset(&mut self) {
+        self.machine.reset();
+        self.stop = None;
+    }
+
+    /// Runs whole instructions for a budget of `cycles`, as
+    /// [`Machine::run_frame`] does, and returns the cycles that ran. Call
+    /// it once per animation frame.
+    #[wasm_bindgen(js_name = stepFrame)]
+    pub fn step_frame(&mut self, cycles: u32) -> u32 {
+        let report = self.machine.run_frame(u64::from(cycles));
+        self.stop =
+            Some(report.stop).filter(|stop| !matches!(stop, StopReason::LimitReached { .. }));
+        // A frame only overshoots its budget by the last instruction.
+        u32::try_from(report.cycles).unwrap_or(u32::MAX)
+    }
+
+    /// Why the last frame stopped early, such as at an illegal opcode, or
+    /// `undefined` if it used up its budget.
+    #[wasm_bindgen(js_name = stopReason)]
+    #[must_use]
+    pub fn stop_reason(&self) -> Option<String> {
+        self.stop.map(|stop| stop.to_string())
+    }
+
+    /// `len` bytes of memory from `start`, wrapping around at `$FFFF`.
+    #[wasm_bindgen(js_name = readMemoryRange)]
+    #[must_use]
+    pub fn read_memory_range(&self, start: u16, len: u32) -> Vec<u8> {
+        (0..=u16::MAX)
+            .cycle()
+            .take(len as usize)
+            .map(|offset| self.machine.cpu.memory.get_byte(start.wrapping_add(offset)))
+            .collect()
+    }
+
+    /// Stores `bytes` at `start`, wrapping around at `$FFFF`.
+    #[wasm_bindgen(js_name = writeMemory)]
+    pub fn write_memory(&mut self, start: u16, bytes: &[u8]) {
+        let addresses = (0..=u16::MAX).map(|offset| start.wrapping_add(offset));
+        for (address, &value) in addresses.zip(bytes) {
+            self.machine.cpu.memory.set_byte(address, value);
+        }
+    }
+
+    #[wasm_bindgen(js_name = registersSnapshot)]
+    #[must_use]
+    // Counts stay exact up to 2^53 cycles, months of emulated time.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn registers_snapshot(&self) -> Registers {
+        let registers = &self.machine.cpu.registers;
+        Registers {
+            a: registers.accumulator,
+            x: registers.index_x,
+            y: registers.index_y,
+            p: registers.status.bits(),
+            sp: registers.stack_pointer.0,
+            pc: registers.program_counter,
+            cycles: self.machine.cpu.cycles as f64,
+        }
+    }
+
+    /// Drives the IRQ input; `true` asserts it.
+    pub fn irq(&mut self, asserted: bool) {
+        self.machine.irq(asserted);
+    }
+
+    /// Pulses the NMI input.
+    pub fn nmi(&mut self) {
+        self.machine.nmi();
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Emulator {
+        Emulator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn frames_run_the_program() {
+        let mut emulator = Emulator::new();
+        // loop: INX; JMP loop
+        emulator
+            .load_program(&[0xe8, 0x4c, 0x00, 0x03], 0x0300)
+            .unwrap();
+        assert_eq!(emulator.step_frame(50), 50);
+        assert_eq!(emulator.stop_reason(), None);
+        let registers = emulator.registers_snapshot();
+        assert_eq!((registers.x, registers.cycles), (10, 50.0));
+
+        // An illegal opcode ends the frame early.
+        emulator.write_memory(0x0301, &[0x02]);
+        emulator.step_frame(50);
+        assert!(emulator.stop_reason().is_some());
+    }
+
+    #[test]
+    fn memory_ranges_wrap_around() {
+        let mut emulator = Emulator::new();
+        emulator.write_memory(0xfffe, &[1, 2, 3]);
+        assert_eq!(emulator.read_memory_range(0xfffe, 4), vec![1, 2, 3, 0]);
+        assert_eq!(emulator.read_memory_range(0, 0), Vec::<u8>::new());
+    }
+}
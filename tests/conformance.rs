@@ -0,0 +1,79 @@
+// Copyright (C) 2014 The 6502-rs Developers
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions
+// are met:
+// 1. Redistributions of source code must retain the above copyright
+//    notice, this list of conditions and the following disclaimer.
+// 2. Redistributions in binary form must reproduce the above copyright
+//    notice, this list of conditions and the following disclaimer in the
+//    documentation and/or other materials provided with the distribution.
+// 3. Neither the names of the copyright holders nor the names of any
+//    contributors may be used to endorse or promote products derived from this
+//    software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+// AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+// IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+// ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+// LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+// CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+// SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+// INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+// CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+// ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+// POSSIBILITY OF SUCH DAMAGE.
+
+//! Runs Klaus Dormann's conformance tests against the core.
+//!
+//! The images aren't distributed with the crate. Put
+//! `6502_functional_test.bin` and `6502_decimal_test.bin`, assembled with
+//! their default settings, in `tests/roms/`, or point `MOS6502_TEST_ROMS`
+//! at the directory holding them. A missing image skips its test.
+
+use std::path::PathBuf;
+
+use mos6502::instruction::Nmos6502;
+use mos6502::testsuite::{FunctionalTest, Report};
+
+fn image(name: &str) -> Option<Vec<u8>> {
+    let dir = std::env::var_os("MOS6502_TEST_ROMS").map_or_else(
+        || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"),
+        PathBuf::from,
+    );
+    let path = dir.join(name);
+    match std::fs::read(&path) {
+        Ok(image) => Some(image),
+        Err(err) => {
+            eprintln!("skipping: {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+fn assert_passed(report: &Report) {
+    assert!(report.passed, "{report}");
+}
+
+#[test]
+fn functional_test() {
+    let Some(image) = image("6502_functional_test.bin") else {
+        return;
+    };
+    let report = FunctionalTest::default().run(&image, Nmos6502).unwrap();
+    assert_passed(&report);
+}
+
+// The decimal test can only pass with BCD arithmetic built in.
+#[cfg(feature = "decimal_mode")]
+#[test]
+fn decimal_test() {
+    let Some(image) = image("6502_decimal_test.bin") else {
+        return;
+    };
+    let report = mos6502::testsuite::DecimalTest::default()
+        .run(&image, Nmos6502)
+        .unwrap();
+    assert_passed(&report);
+}